@@ -180,6 +180,7 @@ fn config(name: &str, description: &str) -> CollectionConfig {
     CollectionConfig {
         name: name.to_string(),
         description: description.to_string(),
+        ..Default::default()
     }
 }
 
@@ -121,6 +121,7 @@ pub(crate) async fn prepare(db: &Arc<AndaDB>) -> Result<(), KipError> {
             CollectionConfig {
                 name: LEGACY_STAGING.to_string(),
                 description: "KIP 1.x rows, kept verbatim across the 2.0 migration".to_string(),
+                ..Default::default()
             },
             init_staging,
         )
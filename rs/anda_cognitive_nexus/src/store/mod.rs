@@ -53,7 +53,7 @@ use anda_db::{
     query::{Filter, RangeQuery},
 };
 use anda_db_schema::Fv;
-use anda_db_tfs::jieba_tokenizer;
+use anda_db_tfs::{TokenizerConfig, jieba_tokenizer};
 use anda_kip::{ElementKind, KipError, KipErrorCode};
 use std::collections::BTreeMap;
 use std::sync::Arc;
@@ -142,8 +142,8 @@ pub struct Store {
 ///
 /// The per-kind setups below are named functions rather than closures inlined
 /// at the open site because every *re*-open must run exactly the same setup:
-/// `create_*_nx` is a no-op once the index exists, but a freshly loaded handle
-/// starts with the default tokenizer and needs the jieba chain reinstalled.
+/// `create_*_nx` is a no-op once the index exists, and a collection created
+/// before tokenizers were recorded needs the jieba chain reinstalled.
 async fn init_envelope(c: &mut Collection) -> Result<(), DBError> {
     c.create_btree_index_nx(&["space"]).await?;
     c.create_btree_index_nx(&["state"]).await?;
@@ -152,8 +152,20 @@ async fn init_envelope(c: &mut Collection) -> Result<(), DBError> {
     Ok(())
 }
 
+/// Installs the jieba chain on a collection that records no tokenizer.
+///
+/// Collections record jieba in their config (see [`collection_config`]) and
+/// rebuild it on open; only stores created before that need the chain
+/// installed by hand, and their BM25 indexes are rebuilt with it only when
+/// they are created.
+fn use_jieba(c: &mut Collection) {
+    if c.metadata().config.tokenizer.is_none() {
+        c.set_tokenizer(jieba_tokenizer());
+    }
+}
+
 async fn init_concepts(c: &mut Collection) -> Result<(), DBError> {
-    c.set_tokenizer(jieba_tokenizer());
+    use_jieba(c);
     init_envelope(c).await?;
     // `key` is the logical identity `UPSERT ... MATCH {key: ...}` resolves. It
     // is Space-local, so the lookup intersects this with `space`; it cannot be
@@ -173,7 +185,7 @@ async fn init_concepts(c: &mut Collection) -> Result<(), DBError> {
 }
 
 async fn init_propositions(c: &mut Collection) -> Result<(), DBError> {
-    c.set_tokenizer(jieba_tokenizer());
+    use_jieba(c);
     init_envelope(c).await?;
     // Declared `#[unique]` by the schema: this is the constraint that keeps
     // one canonical Proposition per semantic tuple in a Space (§93.6).
@@ -190,7 +202,7 @@ async fn init_propositions(c: &mut Collection) -> Result<(), DBError> {
 }
 
 async fn init_assertions(c: &mut Collection) -> Result<(), DBError> {
-    c.set_tokenizer(jieba_tokenizer());
+    use_jieba(c);
     init_envelope(c).await?;
     // Projection's first move is always "every Assertion about this
     // Proposition", so this index is the one that has to be fast.
@@ -210,7 +222,7 @@ async fn init_assertions(c: &mut Collection) -> Result<(), DBError> {
 }
 
 async fn init_evidence(c: &mut Collection) -> Result<(), DBError> {
-    c.set_tokenizer(jieba_tokenizer());
+    use_jieba(c);
     init_envelope(c).await?;
     c.create_btree_index_nx(&["client_key"]).await?;
     c.create_btree_index_nx(&["evidence_class"]).await?;
@@ -225,7 +237,7 @@ async fn init_evidence(c: &mut Collection) -> Result<(), DBError> {
 }
 
 async fn init_activities(c: &mut Collection) -> Result<(), DBError> {
-    c.set_tokenizer(jieba_tokenizer());
+    use_jieba(c);
     init_envelope(c).await?;
     c.create_btree_index_nx(&["client_key"]).await?;
     c.create_btree_index_nx(&["activity_class"]).await?;
@@ -466,8 +478,8 @@ impl Store {
     ///
     /// Idempotent, and safe to call when nothing is poisoned: reopening a
     /// healthy handle costs a reload and changes no state. Each setup closure
-    /// runs again, which is what reinstalls the jieba tokenizer a freshly
-    /// loaded handle does not carry.
+    /// runs again, which reinstalls the jieba tokenizer on collections that
+    /// do not record it (see [`use_jieba`]).
     pub async fn reopen(&self) -> Result<(), KipError> {
         self.concepts
             .set(self.reload(CONCEPTS, init_concepts).await?);
@@ -639,6 +651,9 @@ fn collection_config(name: &str, description: &str) -> CollectionConfig {
     CollectionConfig {
         name: name.to_string(),
         description: description.to_string(),
+        // Names, aliases and payloads mix Chinese and English.
+        tokenizer: Some(TokenizerConfig::jieba()),
+        ..Default::default()
     }
 }
 
@@ -75,6 +75,7 @@ async fn write_v1(name: &str) -> Arc<InMemory> {
             CollectionConfig {
                 name: "concepts".to_string(),
                 description: "Concept nodes".to_string(),
+                ..Default::default()
             },
             async |c| {
                 c.create_btree_index_nx(&["type"]).await?;
@@ -120,6 +121,7 @@ async fn write_v1(name: &str) -> Arc<InMemory> {
             CollectionConfig {
                 name: "propositions".to_string(),
                 description: "Proposition links".to_string(),
+                ..Default::default()
             },
             async |c| {
                 c.create_btree_index_nx(&["subject"]).await?;
@@ -355,6 +357,7 @@ async fn an_interrupted_extract_is_redone_rather_than_resumed_half_way() {
                 CollectionConfig {
                     name: LEGACY_STAGING.to_string(),
                     description: "half-written".to_string(),
+                    ..Default::default()
                 },
                 async |c| {
                     c.create_btree_index_nx(&["kind"]).await?;
@@ -484,6 +487,7 @@ async fn a_dry_run_names_what_would_block_it() {
             CollectionConfig {
                 name: "concepts".to_string(),
                 description: "Concept nodes".to_string(),
+                ..Default::default()
             },
             async |c| {
                 c.create_btree_index_nx(&["type"]).await?;
@@ -509,6 +513,7 @@ async fn a_dry_run_names_what_would_block_it() {
             CollectionConfig {
                 name: "propositions".to_string(),
                 description: "Proposition links".to_string(),
+                ..Default::default()
             },
             async |c| {
                 c.create_btree_index_nx(&["subject"]).await?;
@@ -581,6 +586,7 @@ async fn an_unambiguous_legacy_author_becomes_the_speaker() {
             CollectionConfig {
                 name: "concepts".to_string(),
                 description: "Concept nodes".to_string(),
+                ..Default::default()
             },
             async |c| {
                 c.create_btree_index_nx(&["name"]).await?;
@@ -607,6 +613,7 @@ async fn an_unambiguous_legacy_author_becomes_the_speaker() {
             CollectionConfig {
                 name: "propositions".to_string(),
                 description: "Proposition links".to_string(),
+                ..Default::default()
             },
             async |c| {
                 c.create_btree_index_nx(&["subject"]).await?;
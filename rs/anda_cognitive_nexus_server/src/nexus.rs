@@ -245,6 +245,7 @@ impl Nexus {
                 CollectionConfig {
                     name: "kip_logs".to_string(),
                     description: "KIP logs collection".to_string(),
+                    ..Default::default()
                 },
                 async |collection| {
                     // create BTree indexes if not exists
@@ -342,6 +343,7 @@ impl Nexus {
                 filter,
                 limit: Some(limit),
                 search: None,
+                ..Default::default()
             })
             .await
             .map_err(|err| ListLogsError::Internal(err.into()))?;
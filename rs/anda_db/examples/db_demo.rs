@@ -78,6 +78,7 @@ async fn main() -> Result<(), DBError> {
     let collection_config = CollectionConfig {
         name: "knowledges".to_string(),
         description: "My knowledges".to_string(),
+        ..Default::default()
     };
 
     let collection = db
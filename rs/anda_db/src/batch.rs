@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::schema::{Document, DocumentId, DocumentOwned, Fv};
+
+/// A document mutation staged in a [`WriteBatch`].
+#[derive(Debug, Clone)]
+pub enum BatchOp {
+    /// Adds a new document; its id is assigned at commit time.
+    Add(Document),
+    /// Applies field values to an existing document.
+    Update(DocumentId, BTreeMap<String, Fv>),
+    /// Removes an existing document.
+    Remove(DocumentId),
+}
+
+/// A set of document mutations across one or more collections that
+/// [`AndaDB::write_batch`](crate::database::AndaDB::write_batch) commits
+/// all-or-nothing.
+///
+/// Operations are applied in the order they were staged. A batch may touch
+/// each existing document at most once, and every referenced collection must
+/// be open on the database that commits it.
+///
+/// ```rust,ignore
+/// let mut batch = WriteBatch::new();
+/// batch
+///     .add("messages", message_doc)
+///     .update("threads", thread_id, BTreeMap::from([("summary".into(), summary)]));
+/// let ids = db.write_batch(batch).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    ops: Vec<(String, BatchOp)>,
+}
+
+impl WriteBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stages adding `doc` to `collection`.
+    pub fn add(&mut self, collection: &str, doc: Document) -> &mut Self {
+        self.ops.push((collection.to_string(), BatchOp::Add(doc)));
+        self
+    }
+
+    /// Stages updating document `id` of `collection` with `fields`.
+    pub fn update(
+        &mut self,
+        collection: &str,
+        id: DocumentId,
+        fields: BTreeMap<String, Fv>,
+    ) -> &mut Self {
+        self.ops
+            .push((collection.to_string(), BatchOp::Update(id, fields)));
+        self
+    }
+
+    /// Stages removing document `id` from `collection`.
+    pub fn remove(&mut self, collection: &str, id: DocumentId) -> &mut Self {
+        self.ops.push((collection.to_string(), BatchOp::Remove(id)));
+        self
+    }
+
+    /// Returns the staged operations in commit order.
+    pub fn ops(&self) -> &[(String, BatchOp)] {
+        &self.ops
+    }
+
+    /// Returns the number of staged operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns whether no operation has been staged.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub(crate) fn into_ops(self) -> Vec<(String, BatchOp)> {
+        self.ops
+    }
+}
+
+/// The durable commit record of a [`WriteBatch`].
+///
+/// The manifest is written under the database prefix once every collection
+/// has validated its part of the batch and recorded its mutation intents.
+/// Its successful creation is the single commit point: a manifest found on
+/// [`AndaDB::open`](crate::database::AndaDB::open) is rolled forward by
+/// writing every final document image, after which the collections'
+/// mutation-intent replay brings their indexes and id bitmaps in line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BatchManifest {
+    pub sequence: u64,
+    pub entries: Vec<BatchEntry>,
+}
+
+/// The final state of one document in a committed batch. `None` means the
+/// document is removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BatchEntry {
+    pub collection: String,
+    pub document_id: DocumentId,
+    pub proposed: Option<DocumentOwned>,
+}
@@ -0,0 +1,111 @@
+//! Bulk loading with deferred index building.
+//!
+//! [`Collection::add`](crate::collection::Collection::add) updates every
+//! B-tree, BM25 and HNSW index per document. A [`BulkLoader`], obtained from
+//! [`Collection::bulk_loader`](crate::collection::Collection::bulk_loader),
+//! only validates and stores documents; [`BulkLoader::finish`] then builds
+//! every index in one pass over the loaded documents, through the same
+//! backfill path `create_*_index` uses:
+//!
+//! - B-tree keys are collected, sorted and inserted in key order, so adjacent
+//!   keys land in the same buckets.
+//! - BM25 texts are tokenized and inserted in parallel batches.
+//! - HNSW vectors are inserted with `HnswIndex::insert_batch`, which searches
+//!   neighbors in parallel.
+//!
+//! Loaded documents become visible (to `get`, filters and search) only when
+//! `finish` returns.
+use serde::Serialize;
+use tokio::sync::OwnedRwLockReadGuard;
+
+use crate::{
+    collection::Collection,
+    error::DBError,
+    schema::{Document, DocumentId},
+};
+
+/// Appends documents to a collection with index maintenance deferred to
+/// [`BulkLoader::finish`].
+///
+/// The loader holds a mutation lease on the collection for its whole
+/// lifetime, so a flush cannot checkpoint past documents that are stored but
+/// not indexed yet: a flush requested meanwhile waits for `finish`, and
+/// other writers queue behind that flush. A crash while loading is recovered
+/// on reopen like a crashed `add`.
+///
+/// Dropping a loader that stored documents without calling `finish` poisons
+/// the collection handle; reopening the collection indexes the stored
+/// documents.
+pub struct BulkLoader<'a> {
+    collection: &'a Collection,
+    ids: Vec<DocumentId>,
+    finished: bool,
+    _operation_lease: OwnedRwLockReadGuard<()>,
+}
+
+impl<'a> BulkLoader<'a> {
+    pub(crate) fn new(collection: &'a Collection, lease: OwnedRwLockReadGuard<()>) -> Self {
+        Self {
+            collection,
+            ids: Vec::new(),
+            finished: false,
+            _operation_lease: lease,
+        }
+    }
+
+    /// Validates and stores a document without indexing it.
+    ///
+    /// Returns the id assigned to the document. A failed add skips the id
+    /// and leaves the loader usable.
+    pub async fn add(&mut self, doc: Document) -> Result<DocumentId, DBError> {
+        let id = self.collection.bulk_add(doc).await?;
+        self.ids.push(id);
+        Ok(id)
+    }
+
+    /// Converts a serializable value into a document and stores it like
+    /// [`BulkLoader::add`].
+    pub async fn add_from<T>(&mut self, val: &T) -> Result<DocumentId, DBError>
+    where
+        T: Serialize,
+    {
+        let doc = Document::try_from(self.collection.schema(), val)?;
+        self.add(doc).await
+    }
+
+    /// Returns the number of documents stored so far.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns `true` if no document was stored yet.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Builds every index over the loaded documents and publishes them.
+    ///
+    /// Returns the number of loaded documents. If an index rejects a document
+    /// (e.g. a unique B-tree key conflict), every loaded document is removed
+    /// from the indexes and from storage, and the error is returned.
+    pub async fn finish(mut self) -> Result<usize, DBError> {
+        self.finished = true;
+        let ids = std::mem::take(&mut self.ids);
+        self.collection.finish_bulk_load(&ids).await?;
+        Ok(ids.len())
+    }
+}
+
+impl Drop for BulkLoader<'_> {
+    fn drop(&mut self) {
+        if !self.finished && !self.ids.is_empty() {
+            log::warn!(
+                action = "BulkLoader::drop",
+                collection = self.collection.name(),
+                documents = self.ids.len();
+                "Bulk loader dropped without finish, the stored documents are indexed on reopen",
+            );
+            self.collection.poison("BulkLoader::drop");
+        }
+    }
+}
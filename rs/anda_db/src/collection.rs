@@ -1,6 +1,7 @@
 use anda_db_utils::UniqueVec;
+use bytes::Bytes;
 use croaring::{Portable, Treemap};
-use futures::{StreamExt, future::try_join_all, try_join as try_join_await};
+use futures::{StreamExt, future::try_join_all, stream::BoxStream, try_join as try_join_await};
 use object_store::path::Path;
 use parking_lot::RwLock;
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -17,12 +18,20 @@ use std::{
 };
 
 use crate::{
+    batch::{BatchEntry, BatchOp},
+    block_in_place,
+    bulk::BulkLoader,
     database::AndaDB,
     error::{CollectionState, CollectionStateError, DBError},
     index::*,
     query::*,
     schema::*,
+    segment::{DocumentStorage, DocumentStore, RecordKey, RecordKind, SegmentStats},
     storage::{ObjectVersion, Storage, StorageStats},
+    transfer::{
+        EXPORT_FORMAT_VERSION, ExportFormat, ExportHeader, FrameReader, ImportIds, ImportOptions,
+        ImportReport, encode_frame,
+    },
     unix_ms,
 };
 
@@ -41,12 +50,19 @@ pub struct Collection {
     schema: Arc<Schema>,
     /// Storage backend for persisting collection data
     storage: Storage,
+    /// Document and mutation-intent records, per the configured
+    /// [`DocumentStorage`] mode
+    documents: DocumentStore,
     /// BTree indexes for efficient exact-match queries
     btree_indexes: Vec<BTree>,
+    /// Declarative options of the BTree indexes that have any, by index name
+    btree_index_options: BTreeMap<String, BTreeIndexOptions>,
     /// BM25 (text search) indexes
     bm25_indexes: Vec<BM25>,
     /// HNSW (vector search) indexes
     hnsw_indexes: Vec<Hnsw>,
+    /// Sparse vector (dot-product search) indexes
+    sparse_indexes: Vec<Sparse>,
     /// Collection metadata including statistics and configuration
     metadata: RwLock<CollectionMetadata>,
     /// Highest document ID assigned so far
@@ -57,6 +73,11 @@ pub struct Collection {
     get_count: AtomicU64,
     /// Text tokenization chain for text analysis
     tokenizer: TokenizerChain,
+    /// Definition of `tokenizer`, the built-in default definition for a
+    /// collection that records none, `None` after [`Collection::set_tokenizer`]
+    tokenizer_config: Option<TokenizerConfig>,
+    /// Tokenizer definitions of the database, snapshotted on open
+    tokenizers: TokenizerRegistry,
     /// BTree index for document IDs
     doc_ids_index: RwLock<BTreeSet<DocumentId>>,
     /// Bitmap of document IDs for efficient membership tests
@@ -76,12 +97,19 @@ pub struct Collection {
     /// `lifecycle`, then waits for this gate to drain operations that already
     /// passed admission.
     operation_gate: Arc<tokio::sync::RwLock<()>>,
+    /// Taken exclusively by a database write batch from the moment it changes
+    /// the in-memory indexes until it is applied or rolled back, and shared
+    /// by the queries that read the indexes, so they never observe a batch
+    /// whose manifest commit may still fail.
+    batch_gate: tokio::sync::RwLock<()>,
     /// Last saved version of the collection
     last_saved_version: AtomicU64,
 
     metadata_version: RwLock<ObjectVersion>,
     ids_version: RwLock<ObjectVersion>,
     index_hooks: Arc<dyn IndexHooks>,
+    /// Second-stage rerankers selectable by name through `Search::rerank`.
+    rerankers: FxHashMap<String, Arc<dyn DocumentReranker>>,
 
     /// Striped async locks serializing `update` / `remove` per document id
     /// (stripe = `id % DOC_LOCK_STRIPES`).
@@ -100,7 +128,7 @@ pub struct Collection {
     /// Unusable retained intent objects discovered during reopen. Their
     /// contents cannot drive recovery, but their paths are kept so the next
     /// successful checkpoint can retire them instead of logging them forever.
-    stale_mutation_intents: parking_lot::Mutex<BTreeSet<String>>,
+    stale_mutation_intents: parking_lot::Mutex<BTreeSet<RecordKey>>,
     /// Serializes concurrent extension writers' unclaimed metadata PUTs.
     /// They hold shared `operation_gate` leases, so without this two of them
     /// could race the same expected object version and one would fail with a
@@ -137,14 +165,14 @@ const LIFECYCLE_POISONED: u8 = 5;
 /// Poisons a collection handle when a mutating future is dropped before its
 /// wrapped operation returned. Callers `disarm` the guard after the operation
 /// completes (with either result); only cancellation leaves it armed.
-struct CancelGuard<'a> {
+pub(crate) struct CancelGuard<'a> {
     collection: &'a Collection,
     action: &'static str,
     armed: bool,
 }
 
 impl CancelGuard<'_> {
-    fn disarm(mut self) {
+    pub(crate) fn disarm(mut self) {
         self.armed = false;
     }
 }
@@ -180,6 +208,150 @@ struct MutationIntent {
     proposed: Option<DocumentOwned>,
 }
 
+/// One collection's share of a [`WriteBatch`](crate::batch::WriteBatch)
+/// that has been validated, journaled as mutation intents and applied to the
+/// in-memory indexes, but whose document objects are not written yet.
+///
+/// The stripe locks of every updated or removed document are held until the
+/// batch is applied ([`PreparedBatch::apply`]) or abandoned
+/// ([`PreparedBatch::rollback`]).
+pub(crate) struct PreparedBatch<'a> {
+    collection: &'a Collection,
+    entries: Vec<PreparedEntry>,
+    _doc_guards: Vec<tokio::sync::MutexGuard<'a, ()>>,
+    _batch_guard: tokio::sync::RwLockWriteGuard<'a, ()>,
+}
+
+/// Before/after images of one document in a [`PreparedBatch`]. `None`
+/// means absent: an add has no `previous`, a remove no `proposed`.
+struct PreparedEntry {
+    id: DocumentId,
+    previous: Option<Document>,
+    proposed: Option<Document>,
+}
+
+impl PreparedBatch<'_> {
+    /// Returns the ids of the prepared documents, in staging order.
+    pub(crate) fn ids(&self) -> Vec<DocumentId> {
+        self.entries.iter().map(|entry| entry.id).collect()
+    }
+
+    /// Returns the final document images recorded in the batch manifest.
+    pub(crate) fn manifest_entries(&self) -> Vec<BatchEntry> {
+        self.entries
+            .iter()
+            .map(|entry| BatchEntry {
+                collection: self.collection.name.clone(),
+                document_id: entry.id,
+                proposed: entry.proposed.clone().map(Into::into),
+            })
+            .collect()
+    }
+
+    /// Restores the in-memory indexes to the pre-batch state. Nothing durable
+    /// was written besides mutation intents, which the next flush retires.
+    pub(crate) fn rollback(self) {
+        self.collection
+            .rollback_batch_indexes(&self.entries, unix_ms());
+    }
+
+    /// Writes the final document objects and publishes the id bitmap and
+    /// statistics. Runs after the batch manifest is durable, so an error
+    /// here leaves a committed batch that recovery must roll forward; the
+    /// caller poisons the handle.
+    pub(crate) async fn apply(self) -> Result<(), DBError> {
+        let collection = self.collection;
+        let now_ms = unix_ms();
+        for entry in &self.entries {
+            let key = RecordKey::Document(entry.id);
+            match &entry.proposed {
+                Some(doc) => {
+                    collection.documents.put(key, doc, None).await?;
+                }
+                None => match collection.documents.delete(key).await {
+                    Ok(()) | Err(DBError::NotFound { .. }) => {}
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+
+        let (mut inserted, mut updated, mut deleted) = (0u64, 0u64, 0u64);
+        {
+            let mut doc_ids = collection.doc_ids.write();
+            let mut doc_ids_index = collection.doc_ids_index.write();
+            for entry in &self.entries {
+                match (&entry.previous, &entry.proposed) {
+                    (None, Some(_)) => {
+                        doc_ids.add(entry.id);
+                        doc_ids_index.insert(entry.id);
+                        inserted += 1;
+                    }
+                    (Some(_), Some(_)) => updated += 1,
+                    (Some(_), None) => {
+                        doc_ids.remove(entry.id);
+                        doc_ids_index.remove(&entry.id);
+                        deleted += 1;
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+
+        collection.update_metadata(|meta| {
+            if inserted > 0 {
+                meta.stats.last_inserted = now_ms;
+                meta.stats.insert_count += inserted;
+            }
+            if updated > 0 {
+                meta.stats.last_updated = now_ms;
+                meta.stats.update_count += updated;
+            }
+            if deleted > 0 {
+                meta.stats.last_deleted = now_ms;
+                meta.stats.delete_count += deleted;
+            }
+            meta.stats.version += 1;
+        });
+        Ok(())
+    }
+}
+
+/// The indexes one [`Collection::backfill_indexes`] pass builds.
+#[derive(Default)]
+struct BackfillTargets<'a> {
+    btree: Vec<&'a BTree>,
+    bm25: Vec<&'a BM25>,
+    hnsw: Vec<&'a Hnsw>,
+    sparse: Vec<&'a Sparse>,
+}
+
+/// BM25 texts, HNSW vectors and sparse vectors of the documents scanned
+/// since the last chunk was inserted, one list per target index.
+struct BackfillChunk {
+    bm25: Vec<Vec<(DocumentId, String)>>,
+    hnsw: Vec<Vec<(DocumentId, Vec<Vector>)>>,
+    sparse: Vec<Vec<(DocumentId, SparseVector)>>,
+    len: usize,
+}
+
+impl BackfillChunk {
+    fn insert_into(&mut self, targets: &BackfillTargets<'_>, now_ms: u64) -> Result<(), DBError> {
+        for (index, texts) in targets.bm25.iter().zip(&mut self.bm25) {
+            index.insert_batch(texts, now_ms)?;
+            texts.clear();
+        }
+        for (index, vectors) in targets.hnsw.iter().zip(&mut self.hnsw) {
+            index.insert_batch(std::mem::take(vectors), now_ms)?;
+        }
+        for (index, vectors) in targets.sparse.iter().zip(&mut self.sparse) {
+            index.insert_batch(vectors, now_ms)?;
+            vectors.clear();
+        }
+        self.len = 0;
+        Ok(())
+    }
+}
+
 /// Which end of the matching set a bounded query keeps.
 ///
 /// This is an **input** chosen by the caller, never inferred from the filter:
@@ -231,6 +403,36 @@ pub struct CollectionConfig {
 
     /// Collection description
     pub description: String,
+
+    /// How documents and mutation intents are persisted. Fixed at creation.
+    #[serde(default)]
+    pub document_storage: DocumentStorage,
+
+    /// Expires documents a fixed time after a timestamp field; see
+    /// [`Collection::expire`]. Can be changed with [`Collection::set_ttl`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<TtlConfig>,
+
+    /// Tokenizer of the collection's BM25 indexes, recorded so that it is
+    /// rebuilt on open (see [`AndaDB::register_tokenizer`]). Fixed at
+    /// creation; `None` uses [`default_tokenizer`] unless
+    /// [`Collection::set_tokenizer`] installs another one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<TokenizerConfig>,
+}
+
+/// Time-to-live of the documents of a collection.
+///
+/// A document expires once `field + ttl_ms <= now`, with `field` read as a
+/// unix-ms timestamp; a `ttl_ms` of 0 makes `field` the expiry time itself.
+/// Documents without a value in `field` never expire.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtlConfig {
+    /// A `U64` or `I64` timestamp field with a single-field BTree index.
+    pub field: String,
+
+    /// How long documents live after their timestamp, in milliseconds.
+    pub ttl_ms: u64,
 }
 
 /// Collection metadata containing configuration, schema, indexes, and statistics.
@@ -245,12 +447,26 @@ pub struct CollectionMetadata {
     /// Map of BTree index names to their field entries
     pub btree_indexes: BTreeMap<String, FieldEntry>,
 
+    /// Map of BTree index names to their declarative options, for the
+    /// indexes that have a predicate or an expression.
+    #[serde(default)]
+    pub btree_index_options: BTreeMap<String, BTreeIndexOptions>,
+
     /// Map of BM25 index names to their field entries
     pub bm25_indexes: BTreeMap<String, FieldEntry>,
 
     /// Map of HNSW index names to their field entries
     pub hnsw_indexes: BTreeMap<String, FieldEntry>,
 
+    /// Map of HNSW index names to the generation of their graph, for the
+    /// indexes rebuilt at least once (see [`Collection::rebuild_hnsw_index`]).
+    #[serde(default)]
+    pub hnsw_index_generations: BTreeMap<String, u64>,
+
+    /// Map of sparse vector index names to their field entries
+    #[serde(default)]
+    pub sparse_indexes: BTreeMap<String, FieldEntry>,
+
     /// Collection statistics.
     pub stats: CollectionStats,
 
@@ -298,6 +514,15 @@ pub struct CollectionStats {
     /// Number of delete operations performed.
     pub delete_count: u64,
 
+    /// Number of documents removed by TTL expiry, also counted in
+    /// `delete_count`.
+    #[serde(default)]
+    pub expired_count: u64,
+
+    /// Last TTL expiry timestamp (unix ms).
+    #[serde(default)]
+    pub last_expired: u64,
+
     /// Whether the collection handle is in read-only mode.
     ///
     /// This is **live handle state, not durable state**: [`Collection::open`]
@@ -449,6 +674,34 @@ impl HnswIndexView<'_> {
         self.inner.metadata()
     }
 
+    pub fn exact_rerank(&self) -> bool {
+        self.inner.exact_rerank()
+    }
+
+    pub fn multi_vector(&self) -> bool {
+        self.inner.multi_vector()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.inner.generation()
+    }
+
+    pub fn ef_search(&self) -> usize {
+        self.inner.ef_search()
+    }
+
+    /// Measures recall@k and latency per `ef_search` value, see
+    /// [`Collection::tune_hnsw_ef_search`].
+    pub fn evaluate_recall(&self, options: &RecallOptions) -> Result<RecallReport, DBError> {
+        self.inner.evaluate_recall(options)
+    }
+
+    /// Measures the connectivity of the graph, see
+    /// [`Collection::rebuild_hnsw_index`].
+    pub fn graph_health(&self, now_ms: u64) -> HnswGraphHealth {
+        self.inner.graph_health(now_ms)
+    }
+
     pub fn try_search(
         &self,
         query: &[f32],
@@ -475,7 +728,8 @@ impl Collection {
     /// Path to the document IDs bitmap file
     const IDS_PATH: &'static str = "ids.cbor";
 
-    /// Prefix for durable update/remove intents.
+    /// Prefix of mutation-intent objects in [`DocumentStorage::Objects`] mode.
+    #[cfg(test)]
     const MUTATION_INTENT_PREFIX: &'static str = "mutation_intents/";
 
     /// Path of the durable allocation watermark object (a single `u64`).
@@ -487,6 +741,9 @@ impl Collection {
     /// probes at most this many ids beyond the last observed allocation.
     const ALLOCATION_WATERMARK_STRIDE: u64 = 64;
 
+    /// Documents per BM25/HNSW insert batch of an index backfill.
+    const BACKFILL_CHUNK_SIZE: usize = 1024;
+
     /// Upper bound for limit-driven speculative pre-allocations, so a huge
     /// caller-supplied limit (e.g. via `query_ids`) cannot allocate excessive
     /// memory up front. Result vectors still grow on demand beyond this hint.
@@ -500,6 +757,32 @@ impl Collection {
     /// (`limit * 10`).
     pub const MAX_SEARCH_LIMIT: usize = 1000;
 
+    /// Relevance hits each search index contributes to a query ordered by
+    /// `Query::order_by`, whatever its `limit`. Every page of the ordering
+    /// draws from the same matches; [`SearchPage::truncated`] reports when an
+    /// index had more.
+    pub const MAX_ORDERED_SEARCH_HITS: usize = 10_000;
+
+    /// Most filter matches a vector search hands to the HNSW traversal. A
+    /// filter matching more documents is applied to the search results
+    /// instead, so a broad filter is never expanded in full per query.
+    pub const MAX_FILTERED_SEARCH_IDS: usize = 10_000;
+
+    /// Hits of a quantized HNSW index re-scored by their exact distance per
+    /// requested result, up to [`Collection::MAX_EXACT_RERANK_CANDIDATES`].
+    const EXACT_RERANK_OVERSAMPLING: usize = 4;
+
+    /// Most hits of a quantized HNSW index a search re-scores by their exact
+    /// distance; each one loads its document.
+    pub const MAX_EXACT_RERANK_CANDIDATES: usize = 1000;
+
+    /// Default candidate pool of a [`Search::rerank`] stage.
+    pub const DEFAULT_RERANK_CANDIDATES: usize = 50;
+
+    /// Maximum number of documents one [`Collection::expire`] batch of
+    /// [`AndaDB::expire_documents`] removes.
+    pub const EXPIRE_BATCH_SIZE: usize = 1000;
+
     /// Returns a safe pre-allocation size for a caller-supplied limit.
     #[inline]
     fn reserve_hint(limit: usize) -> usize {
@@ -517,8 +800,9 @@ impl Collection {
         &self.doc_locks[(id as usize) % Self::DOC_LOCK_STRIPES]
     }
 
+    #[cfg(test)]
     fn mutation_intent_path(sequence: u64) -> String {
-        format!("{}{sequence:020}.cbor", Self::MUTATION_INTENT_PREFIX)
+        RecordKey::Intent(sequence).path()
     }
 
     fn lifecycle_error(&self) -> DBError {
@@ -588,7 +872,7 @@ impl Collection {
     /// future was dropped mid-operation. Delete states are preserved: a
     /// deletion in progress already rejects every operation and its partial
     /// storage removal is not recoverable by reopening anyway.
-    fn poison(&self, action: &'static str) {
+    pub(crate) fn poison(&self, action: &'static str) {
         loop {
             let state = self.lifecycle.load(Ordering::Acquire);
             if !matches!(state, LIFECYCLE_ACTIVE | LIFECYCLE_CLOSING) {
@@ -617,7 +901,7 @@ impl Collection {
     /// Arms a [`CancelGuard`] for `action`. Cancellation of the wrapped
     /// future is treated as a crash: recovery happens on reopen, never
     /// in place.
-    fn cancel_guard(&self, action: &'static str) -> CancelGuard<'_> {
+    pub(crate) fn cancel_guard(&self, action: &'static str) -> CancelGuard<'_> {
         CancelGuard {
             collection: self,
             action,
@@ -635,9 +919,11 @@ impl Collection {
         Ok(guard)
     }
 
-    /// Generates the storage path for a document with the given ID
+    /// Generates the storage path for a document with the given ID in
+    /// [`DocumentStorage::Objects`] mode
+    #[cfg(test)]
     fn doc_path(id: DocumentId) -> String {
-        format!("data/{id}.cbor")
+        RecordKey::Document(id).path()
     }
 
     /// Creates a new collection with the given schema and configuration.
@@ -667,6 +953,8 @@ impl Collection {
             });
         }
 
+        let tokenizers = db.tokenizers();
+        let tokenizer = Self::resolve_tokenizer(&tokenizers, config.tokenizer.as_ref())?;
         let storage = Storage::connect(
             base_path.to_string(),
             db.object_store(),
@@ -681,8 +969,11 @@ impl Collection {
             config: config.clone(),
             schema: schema.clone(),
             btree_indexes: BTreeMap::new(),
+            btree_index_options: BTreeMap::new(),
             bm25_indexes: BTreeMap::new(),
             hnsw_indexes: BTreeMap::new(),
+            hnsw_index_generations: BTreeMap::new(),
+            sparse_indexes: BTreeMap::new(),
             stats,
             extensions: BTreeMap::new(),
         };
@@ -706,18 +997,24 @@ impl Collection {
 
         // created successfully, and store storage metadata
         storage.store_metadata(0, unix_ms()).await?;
+        let documents = DocumentStore::open(storage.clone(), &config.document_storage).await?;
 
         Ok(Self {
             name: config.name.clone(),
             schema: Arc::new(schema),
             storage,
+            documents,
             btree_indexes: Vec::new(),
+            btree_index_options: BTreeMap::new(),
             bm25_indexes: Vec::new(),
             hnsw_indexes: Vec::new(),
+            sparse_indexes: Vec::new(),
             max_document_id: AtomicU64::new(0),
             search_count: AtomicU64::new(0),
             get_count: AtomicU64::new(0),
-            tokenizer: default_tokenizer(),
+            tokenizer,
+            tokenizer_config: Some(config.tokenizer.clone().unwrap_or_default()),
+            tokenizers,
             doc_ids_index: RwLock::new(BTreeSet::new()),
             doc_ids: RwLock::new(Treemap::new()),
             metadata: RwLock::new(metadata),
@@ -725,10 +1022,12 @@ impl Collection {
             database_read_only: db.read_only_flag(),
             lifecycle: AtomicU8::new(LIFECYCLE_ACTIVE),
             operation_gate: Arc::new(tokio::sync::RwLock::new(())),
+            batch_gate: tokio::sync::RwLock::new(()),
             last_saved_version: AtomicU64::new(0),
             metadata_version: RwLock::new(metadata_version),
             ids_version: RwLock::new(ids_version),
             index_hooks: Arc::new(DefaultIndexHooks),
+            rerankers: FxHashMap::default(),
             doc_locks: Self::new_doc_locks(),
             pending_mutations: parking_lot::Mutex::new(BTreeMap::new()),
             stale_mutation_intents: parking_lot::Mutex::new(BTreeSet::new()),
@@ -739,6 +1038,25 @@ impl Collection {
         })
     }
 
+    /// Opens the document storage of collection `name` without loading the
+    /// collection itself, for recovery work that must land before it opens.
+    pub(crate) async fn connect_documents(
+        db: &AndaDB,
+        name: &str,
+    ) -> Result<DocumentStore, DBError> {
+        let base_path = Path::from(db.name()).join(name);
+        let storage = Storage::connect(
+            base_path.to_string(),
+            db.object_store(),
+            db.metadata().config.storage.clone(),
+        )
+        .await?;
+        let (metadata, _) = storage
+            .fetch::<CollectionMetadata>(Self::METADATA_PATH)
+            .await?;
+        DocumentStore::open(storage, &metadata.config.document_storage).await
+    }
+
     /// Opens an existing collection.
     ///
     /// # Arguments
@@ -770,6 +1088,9 @@ impl Collection {
         let (metadata, metadata_version) = storage
             .fetch::<CollectionMetadata>(Self::METADATA_PATH)
             .await?;
+        let tokenizers = db.tokenizers();
+        let tokenizer_config = metadata.config.tokenizer.clone();
+        let tokenizer = Self::resolve_tokenizer(&tokenizers, tokenizer_config.as_ref())?;
 
         let (ids, ids_version) = storage.fetch::<Vec<u8>>(Self::IDS_PATH).await?;
         let doc_ids =
@@ -788,19 +1109,26 @@ impl Collection {
             Err(err) => return Err(err),
         };
         let metadata_max_document_id = metadata.stats.max_document_id;
+        let documents =
+            DocumentStore::open(storage.clone(), &metadata.config.document_storage).await?;
 
         let mut collection = Self {
             name,
             schema: Arc::new(metadata.schema.clone()),
             storage,
+            documents,
             btree_indexes: Vec::new(),
+            btree_index_options: BTreeMap::new(),
             bm25_indexes: Vec::new(),
             hnsw_indexes: Vec::new(),
+            sparse_indexes: Vec::new(),
             max_document_id: AtomicU64::new(metadata.stats.max_document_id),
             search_count: AtomicU64::new(metadata.stats.search_count),
             get_count: AtomicU64::new(metadata.stats.get_count),
             last_saved_version: AtomicU64::new(metadata.stats.version),
-            tokenizer: default_tokenizer(),
+            tokenizer,
+            tokenizer_config: Some(tokenizer_config.unwrap_or_default()),
+            tokenizers,
             doc_ids_index: RwLock::new(doc_ids_index),
             doc_ids: RwLock::new(doc_ids),
             metadata: RwLock::new(metadata),
@@ -808,9 +1136,11 @@ impl Collection {
             database_read_only: db.read_only_flag(),
             lifecycle: AtomicU8::new(LIFECYCLE_ACTIVE),
             operation_gate: Arc::new(tokio::sync::RwLock::new(())),
+            batch_gate: tokio::sync::RwLock::new(()),
             metadata_version: RwLock::new(metadata_version),
             ids_version: RwLock::new(ids_version),
             index_hooks: Arc::new(DefaultIndexHooks),
+            rerankers: FxHashMap::default(),
             doc_locks: Self::new_doc_locks(),
             pending_mutations: parking_lot::Mutex::new(BTreeMap::new()),
             stale_mutation_intents: parking_lot::Mutex::new(BTreeSet::new()),
@@ -852,13 +1182,25 @@ impl Collection {
         Ok(collection)
     }
 
+    /// Builds the recorded tokenizer of a collection, or the default tokenizer
+    /// when none is recorded. Fails if the registered version differs.
+    fn resolve_tokenizer(
+        tokenizers: &TokenizerRegistry,
+        config: Option<&TokenizerConfig>,
+    ) -> Result<TokenizerChain, DBError> {
+        match config {
+            Some(config) => Ok(tokenizers.resolve(config)?),
+            None => Ok(default_tokenizer()),
+        }
+    }
+
     /// Loads all indexes from storage.
     ///
     /// # Returns
     /// Ok(()) if successful, or an error if loading fails
     async fn load_indexes(&mut self) -> Result<(), DBError> {
         let meta = { self.metadata.read().clone() };
-        let (btree_indexes, bm25_indexes, hnsw_indexes) = try_join_await!(
+        let (btree_indexes, bm25_indexes, hnsw_indexes, sparse_indexes) = try_join_await!(
             async {
                 let mut btree_indexes = Vec::new();
                 for (name, field) in meta.btree_indexes.iter() {
@@ -876,9 +1218,13 @@ impl Collection {
             async {
                 let mut bm25_indexes = Vec::new();
                 for (name, _) in meta.bm25_indexes.iter() {
-                    let index =
-                        BM25::bootstrap(name.clone(), self.tokenizer.clone(), self.storage.clone())
-                            .await?;
+                    let index = BM25::bootstrap_with_registry(
+                        name.clone(),
+                        &self.tokenizers,
+                        self.tokenizer.clone(),
+                        self.storage.clone(),
+                    )
+                    .await?;
 
                     bm25_indexes.push(index);
                 }
@@ -886,24 +1232,40 @@ impl Collection {
             },
             async {
                 let mut hnsw_indexes = Vec::new();
-                for (name, _) in meta.hnsw_indexes.iter() {
-                    let index = Hnsw::bootstrap(name.clone(), self.storage.clone()).await?;
+                for (name, field) in meta.hnsw_indexes.iter() {
+                    let generation = meta
+                        .hnsw_index_generations
+                        .get(name)
+                        .copied()
+                        .unwrap_or_default();
+                    let index =
+                        Hnsw::bootstrap_field(field, self.storage.clone(), generation).await?;
 
                     hnsw_indexes.push(index);
                 }
                 Ok::<Vec<Hnsw>, DBError>(hnsw_indexes)
             },
+            async {
+                let mut sparse_indexes = Vec::new();
+                for name in meta.sparse_indexes.keys() {
+                    sparse_indexes
+                        .push(Sparse::bootstrap(name.clone(), self.storage.clone()).await?);
+                }
+                Ok::<Vec<Sparse>, DBError>(sparse_indexes)
+            },
         )?;
 
         self.btree_indexes = btree_indexes;
+        self.btree_index_options = meta.btree_index_options;
         self.bm25_indexes = bm25_indexes;
         self.hnsw_indexes = hnsw_indexes;
+        self.sparse_indexes = sparse_indexes;
         Ok(())
     }
 
     fn remove_document_from_indexes(&self, id: DocumentId, doc: &Document, now_ms: u64) {
         for index in &self.btree_indexes {
-            if let Some(value) = self.index_hooks.btree_index_value(index, doc)
+            if let Some(value) = self.btree_index_value(index, doc)
                 && value.as_ref() != &FieldValue::Null
             {
                 index.remove(id, &value, now_ms);
@@ -920,6 +1282,11 @@ impl Collection {
         for index in &self.hnsw_indexes {
             index.remove(id, now_ms);
         }
+        for index in &self.sparse_indexes {
+            if let Some(vector) = self.index_hooks.sparse_index_value(index, doc) {
+                index.remove(id, &vector, now_ms);
+            }
+        }
     }
 
     fn insert_document_into_indexes(
@@ -929,7 +1296,7 @@ impl Collection {
         now_ms: u64,
     ) -> Result<(), DBError> {
         for index in &self.btree_indexes {
-            if let Some(value) = self.index_hooks.btree_index_value(index, doc)
+            if let Some(value) = self.btree_index_value(index, doc)
                 && value.as_ref() != &FieldValue::Null
             {
                 index.insert(id, &value, now_ms)?;
@@ -941,8 +1308,11 @@ impl Collection {
             }
         }
         for index in &self.hnsw_indexes {
-            if let Some(vector) = self.index_hooks.hnsw_index_value(index, doc) {
-                index.insert(id, vector.into_owned(), now_ms)?;
+            index.insert_vectors(id, self.hnsw_index_vectors(index, doc), now_ms)?;
+        }
+        for index in &self.sparse_indexes {
+            if let Some(vector) = self.index_hooks.sparse_index_value(index, doc) {
+                index.insert(id, &vector, now_ms)?;
             }
         }
         Ok(())
@@ -964,8 +1334,11 @@ impl Collection {
                 previous: previous.map(|document| document.clone().into()),
                 proposed: proposed.map(|document| document.clone().into()),
             };
-            let path = Self::mutation_intent_path(sequence);
-            match self.storage.create(&path, &intent).await {
+            match self
+                .documents
+                .create(RecordKey::Intent(sequence), &intent)
+                .await
+            {
                 Ok(_) => {
                     self.pending_mutations.lock().insert(sequence, intent);
                     return Ok(());
@@ -990,26 +1363,12 @@ impl Collection {
     /// [`Self::repair_document`] gives a document that no longer matches the
     /// schema); the skipped records are retired by the next flush.
     async fn replay_mutation_intents(&self) -> Result<usize, DBError> {
-        let mut stream = self
-            .storage
-            .list_meta(Some(Self::MUTATION_INTENT_PREFIX), None);
         let mut intents = BTreeMap::<u64, MutationIntent>::new();
-        let mut stale_paths = BTreeSet::new();
-        while let Some(meta) = stream.next().await {
-            let meta = meta?;
-            let Some(relative) = meta.location.prefix_match(self.storage.base_path()) else {
-                log::warn!(
-                    action = "Collection::replay_mutation_intents",
-                    collection = self.name,
-                    path = meta.location.as_ref();
-                    "Skipping mutation intent outside the collection storage prefix",
-                );
-                continue;
-            };
-            let path = relative.collect::<Path>().to_string();
-            let intent = match self.storage.fetch::<MutationIntent>(&path).await {
+        let mut stale_keys = BTreeSet::new();
+        for key in self.documents.list(RecordKind::Intent).await? {
+            let intent = match self.documents.fetch::<MutationIntent>(key).await {
                 Ok((intent, _)) => intent,
-                // An intent object that no longer decodes carries no usable
+                // An intent record that no longer decodes carries no usable
                 // recovery information. Storage-level failures still
                 // propagate: those are transient and retrying the open is the
                 // right answer.
@@ -1019,7 +1378,7 @@ impl Collection {
                         collection = self.name;
                         "Skipping undecodable mutation intent: {err:?}",
                     );
-                    stale_paths.insert(path);
+                    stale_keys.insert(key);
                     continue;
                 }
                 Err(err) => return Err(err),
@@ -1031,24 +1390,23 @@ impl Collection {
                     sequence = intent.sequence;
                     "Skipping mutation intent with the reserved document id 0",
                 );
-                stale_paths.insert(path);
+                stale_keys.insert(key);
                 continue;
             }
-            let expected_path = Self::mutation_intent_path(intent.sequence);
-            if path != expected_path {
+            if key != RecordKey::Intent(intent.sequence) {
                 log::warn!(
                     action = "Collection::replay_mutation_intents",
                     collection = self.name,
                     sequence = intent.sequence,
-                    path;
+                    path = key.path();
                     "Skipping mutation intent whose path does not match its sequence",
                 );
-                stale_paths.insert(path);
+                stale_keys.insert(key);
                 continue;
             }
             intents.insert(intent.sequence, intent);
         }
-        *self.stale_mutation_intents.lock() = stale_paths;
+        *self.stale_mutation_intents.lock() = stale_keys;
         if intents.is_empty() {
             return Ok(0);
         }
@@ -1109,8 +1467,8 @@ impl Collection {
 
         for id in affected_ids {
             match self
-                .storage
-                .fetch::<DocumentOwned>(&Self::doc_path(id))
+                .documents
+                .fetch::<DocumentOwned>(RecordKey::Document(id))
                 .await
             {
                 Ok((current, _)) => {
@@ -1169,23 +1527,24 @@ impl Collection {
 
     async fn clear_mutation_intents(&self) -> Result<(), DBError> {
         let sequences: Vec<u64> = self.pending_mutations.lock().keys().copied().collect();
-        for sequence in sequences {
-            let path = Self::mutation_intent_path(sequence);
-            match self.storage.delete(&path).await {
-                Ok(()) | Err(DBError::NotFound { .. }) => {
-                    self.pending_mutations.lock().remove(&sequence);
-                }
-                Err(err) => return Err(err),
+        let stale_keys: Vec<RecordKey> =
+            self.stale_mutation_intents.lock().iter().copied().collect();
+        let keys: Vec<RecordKey> = sequences
+            .iter()
+            .map(|sequence| RecordKey::Intent(*sequence))
+            .chain(stale_keys.iter().copied())
+            .collect();
+        // Segment storage retires the whole set with a single group commit.
+        self.documents.delete_many(&keys).await?;
+        {
+            let mut pending = self.pending_mutations.lock();
+            for sequence in &sequences {
+                pending.remove(sequence);
             }
         }
-        let stale_paths: Vec<String> = self.stale_mutation_intents.lock().iter().cloned().collect();
-        for path in stale_paths {
-            match self.storage.delete(&path).await {
-                Ok(()) | Err(DBError::NotFound { .. }) => {
-                    self.stale_mutation_intents.lock().remove(&path);
-                }
-                Err(err) => return Err(err),
-            }
+        let mut stale = self.stale_mutation_intents.lock();
+        for key in &stale_keys {
+            stale.remove(key);
         }
         Ok(())
     }
@@ -1228,22 +1587,17 @@ impl Collection {
         // whose objects may not have been visible to the listing below.
         let scan_max_id = self.max_document_id.load(Ordering::Acquire);
 
-        // Enumerate the ids of every document object under `data/`.
-        let mut stored_ids: BTreeSet<DocumentId> = BTreeSet::new();
-        {
-            let mut stream = self.storage.list_meta(Some("data/"), None);
-            while let Some(meta) = stream.next().await {
-                let meta = meta?;
-                if let Some(id) = meta
-                    .location
-                    .filename()
-                    .and_then(|name| name.strip_suffix(".cbor"))
-                    .and_then(|raw| raw.parse::<DocumentId>().ok())
-                {
-                    stored_ids.insert(id);
-                }
-            }
-        }
+        // Enumerate the ids of every stored document.
+        let stored_ids: BTreeSet<DocumentId> = self
+            .documents
+            .list(RecordKind::Document)
+            .await?
+            .into_iter()
+            .filter_map(|key| match key {
+                RecordKey::Document(id) => Some(id),
+                RecordKey::Intent(_) => None,
+            })
+            .collect();
 
         // Direction 1: recover documents that exist on disk but are missing
         // from the bitmap.
@@ -1258,8 +1612,8 @@ impl Collection {
         let mut recovered = 0usize;
         for id in missing_in_bitmap {
             match self
-                .storage
-                .fetch::<DocumentOwned>(&Self::doc_path(id))
+                .documents
+                .fetch::<DocumentOwned>(RecordKey::Document(id))
                 .await
             {
                 Ok((doc, _)) => {
@@ -1321,8 +1675,8 @@ impl Collection {
         let mut fixed = 0;
         for id in (check_point + 1)..=scan_max {
             match self
-                .storage
-                .fetch::<DocumentOwned>(&Self::doc_path(id))
+                .documents
+                .fetch::<DocumentOwned>(RecordKey::Document(id))
                 .await
             {
                 Err(DBError::NotFound { .. }) => {}
@@ -1403,7 +1757,7 @@ impl Collection {
 
         // try to repair indexes
         for index in &self.btree_indexes {
-            if let Some(fv) = self.index_hooks.btree_index_value(index, &doc) {
+            if let Some(fv) = self.btree_index_value(index, &doc) {
                 if fv.as_ref() == &FieldValue::Null {
                     continue;
                 }
@@ -1434,8 +1788,7 @@ impl Collection {
         }
 
         for index in &self.hnsw_indexes {
-            if let Some(vector) = self.index_hooks.hnsw_index_value(index, &doc)
-                && let Err(err) = index.insert(id, vector.into_owned(), now_ms)
+            if let Err(err) = index.insert_vectors(id, self.hnsw_index_vectors(index, &doc), now_ms)
             {
                 log::warn!(
                     action = "Collection::repair_document",
@@ -1447,6 +1800,20 @@ impl Collection {
             }
         }
 
+        for index in &self.sparse_indexes {
+            if let Some(vector) = self.index_hooks.sparse_index_value(index, &doc)
+                && let Err(err) = index.insert(id, &vector, now_ms)
+            {
+                log::warn!(
+                    action = "Collection::repair_document",
+                    collection = self.name,
+                    doc_id = id,
+                    index = index.name();
+                    "Failed to repair sparse index: {err:?}",
+                );
+            }
+        }
+
         if is_new {
             self.update_metadata(|meta| {
                 meta.stats.version += 1;
@@ -1456,25 +1823,30 @@ impl Collection {
         Ok(is_new)
     }
 
-    /// Streams every existing document through `f`, one at a time.
+    /// Streams the stored documents of `ids` through `f`, one at a time.
     ///
     /// Documents are fetched with `Storage::fetch` (bypassing the cache) so a
     /// full backfill scan does not evict the hot working set, and are never
     /// collected into memory as a whole — large collections would otherwise
     /// risk OOM during index creation.
-    async fn for_each_existing_document<F>(&self, mut f: F) -> Result<(), DBError>
+    async fn for_each_existing_document<F>(
+        &self,
+        ids: Vec<DocumentId>,
+        mut f: F,
+    ) -> Result<(), DBError>
     where
         F: FnMut(DocumentId, Document) -> Result<(), DBError>,
     {
-        let ids = self.ids();
         let schema = self.schema();
         let mut stream = futures::stream::iter(ids)
             .map(|id| {
-                let storage = self.storage.clone();
+                let documents = self.documents.clone();
                 async move {
                     (
                         id,
-                        storage.fetch::<DocumentOwned>(&Self::doc_path(id)).await,
+                        documents
+                            .fetch::<DocumentOwned>(RecordKey::Document(id))
+                            .await,
                     )
                 }
             })
@@ -1493,49 +1865,103 @@ impl Collection {
         Ok(())
     }
 
-    async fn backfill_btree_index(&self, index: &BTree, now_ms: u64) -> Result<(), DBError> {
-        if self.is_empty() {
+    /// Indexes the stored documents of `ids` into `targets` in one scan.
+    ///
+    /// This is the shared build path of `create_*_index` and
+    /// [`BulkLoader::finish`]. B-tree keys of every document are collected
+    /// first and inserted in key order at the end, so buckets are filled
+    /// with adjacent keys. BM25 texts and HNSW vectors are inserted in
+    /// chunks of [`Self::BACKFILL_CHUNK_SIZE`] documents, tokenized and
+    /// linked in parallel. On error the documents indexed so far stay in the
+    /// targets; callers drop a new index or roll back the documents.
+    async fn backfill_indexes(
+        &self,
+        ids: Vec<DocumentId>,
+        targets: BackfillTargets<'_>,
+        now_ms: u64,
+    ) -> Result<(), DBError> {
+        if ids.is_empty() {
             return Ok(());
         }
 
-        self.for_each_existing_document(|id, doc| {
-            if let Some(fv) = self.index_hooks.btree_index_value(index, &doc) {
-                if fv.as_ref() == &FieldValue::Null {
-                    return Ok(());
+        let mut btree_entries: Vec<Vec<(DocumentId, FieldValue)>> =
+            vec![Vec::new(); targets.btree.len()];
+        let mut chunk = BackfillChunk {
+            bm25: vec![Vec::new(); targets.bm25.len()],
+            hnsw: vec![Vec::new(); targets.hnsw.len()],
+            sparse: vec![Vec::new(); targets.sparse.len()],
+            len: 0,
+        };
+        self.for_each_existing_document(ids, |id, doc| {
+            for (entries, index) in btree_entries.iter_mut().zip(&targets.btree) {
+                if let Some(fv) = self.btree_index_value(index, &doc)
+                    && fv.as_ref() != &FieldValue::Null
+                {
+                    entries.push((id, fv.into_owned()));
+                }
+            }
+            for (texts, index) in chunk.bm25.iter_mut().zip(&targets.bm25) {
+                if let Some(text) = self.index_hooks.bm25_index_value(index, &doc) {
+                    texts.push((id, text.into_owned()));
+                }
+            }
+            for (vectors, index) in chunk.hnsw.iter_mut().zip(&targets.hnsw) {
+                let document_vectors = self.hnsw_index_vectors(index, &doc);
+                if !document_vectors.is_empty() {
+                    vectors.push((id, document_vectors));
+                }
+            }
+            for (vectors, index) in chunk.sparse.iter_mut().zip(&targets.sparse) {
+                if let Some(vector) = self.index_hooks.sparse_index_value(index, &doc) {
+                    vectors.push((id, vector));
                 }
-                index.insert(id, &fv, now_ms)?;
+            }
+            chunk.len += 1;
+            if chunk.len >= Self::BACKFILL_CHUNK_SIZE {
+                block_in_place(|| chunk.insert_into(&targets, now_ms))?;
             }
             Ok(())
         })
-        .await
-    }
-
-    async fn backfill_bm25_index(&self, index: &BM25, now_ms: u64) -> Result<(), DBError> {
-        if self.is_empty() {
-            return Ok(());
-        }
-
-        self.for_each_existing_document(|id, doc| {
-            if let Some(text) = self.index_hooks.bm25_index_value(index, &doc) {
-                index.insert(id, &text, now_ms)?;
+        .await?;
+        block_in_place(|| {
+            chunk.insert_into(&targets, now_ms)?;
+            for (index, entries) in targets.btree.iter().zip(btree_entries) {
+                index.insert_batch(entries, now_ms)?;
             }
             Ok(())
         })
-        .await
+    }
+
+    async fn backfill_btree_index(&self, index: &BTree, now_ms: u64) -> Result<(), DBError> {
+        let targets = BackfillTargets {
+            btree: vec![index],
+            ..Default::default()
+        };
+        self.backfill_indexes(self.ids(), targets, now_ms).await
+    }
+
+    async fn backfill_bm25_index(&self, index: &BM25, now_ms: u64) -> Result<(), DBError> {
+        let targets = BackfillTargets {
+            bm25: vec![index],
+            ..Default::default()
+        };
+        self.backfill_indexes(self.ids(), targets, now_ms).await
     }
 
     async fn backfill_hnsw_index(&self, index: &Hnsw, now_ms: u64) -> Result<(), DBError> {
-        if self.is_empty() {
-            return Ok(());
-        }
+        let targets = BackfillTargets {
+            hnsw: vec![index],
+            ..Default::default()
+        };
+        self.backfill_indexes(self.ids(), targets, now_ms).await
+    }
 
-        self.for_each_existing_document(|id, doc| {
-            if let Some(vector) = self.index_hooks.hnsw_index_value(index, &doc) {
-                index.insert(id, vector.into_owned(), now_ms)?;
-            }
-            Ok(())
-        })
-        .await
+    async fn backfill_sparse_index(&self, index: &Sparse, now_ms: u64) -> Result<(), DBError> {
+        let targets = BackfillTargets {
+            sparse: vec![index],
+            ..Default::default()
+        };
+        self.backfill_indexes(self.ids(), targets, now_ms).await
     }
 
     async fn try_upgrade_schema(&mut self, mut new_schema: Schema) -> Result<(), DBError> {
@@ -1680,6 +2106,29 @@ impl Collection {
         rt
     }
 
+    /// Checkpoints the collection and returns the exclusive operation gate,
+    /// keeping its storage frozen until the guard is dropped.
+    ///
+    /// Used by [`AndaDB::snapshot`]. A handle that no longer admits
+    /// mutations (read-only, poisoned, closed) is frozen as-is: whatever it
+    /// left in storage is already a state that reopening recovers from.
+    pub(crate) async fn freeze(
+        &self,
+        now_ms: u64,
+    ) -> Result<tokio::sync::OwnedRwLockWriteGuard<()>, DBError> {
+        let operation_guard = self.operation_gate.clone().write_owned().await;
+        if self.ensure_mutable().is_ok() {
+            let guard = self.cancel_guard("Collection::freeze");
+            let rt = self.flush_inner(now_ms).await;
+            guard.disarm();
+            if let Err(err) = rt {
+                self.poison("Collection::freeze");
+                return Err(err);
+            }
+        }
+        Ok(operation_guard)
+    }
+
     /// A checkpoint is a sequence of dependent writes (collection metadata,
     /// indexes, ids bitmap, storage checkpoint, WAL retirement). Any error
     /// after the first write leaves memory and storage diverged in a way this
@@ -1745,6 +2194,10 @@ impl Collection {
             self.clear_mutation_intents().await?;
         }
 
+        // Segment storage persists its record map last, covering every
+        // record written above, and then reclaims sparse segments.
+        self.documents.checkpoint().await?;
+
         Ok(stored_check_point.is_some() || indexes_saved || has_pending_mutations)
     }
 
@@ -1929,29 +2382,39 @@ impl Collection {
     /// # Returns
     /// Ok(()) if successful, or an error if storing fails
     async fn store_indexes(&self, now_ms: u64) -> Result<bool, DBError> {
-        let (btree_saved, bm25_saved, hnsw_saved) = try_join_await!(
+        let (btree_saved, bm25_saved, hnsw_saved, sparse_saved) = try_join_await!(
             try_join_all(self.btree_indexes.iter().map(|index| index.flush(now_ms))),
             try_join_all(self.bm25_indexes.iter().map(|index| index.flush(now_ms))),
             try_join_all(self.hnsw_indexes.iter().map(|index| index.flush(now_ms))),
+            try_join_all(self.sparse_indexes.iter().map(|index| index.flush(now_ms))),
         )?;
 
         Ok(btree_saved.into_iter().any(|saved| saved)
             || bm25_saved.into_iter().any(|saved| saved)
-            || hnsw_saved.into_iter().any(|saved| saved))
+            || hnsw_saved.into_iter().any(|saved| saved)
+            || sparse_saved.into_iter().any(|saved| saved))
     }
 
     fn has_pending_index_flush(&self) -> bool {
         self.btree_indexes.iter().any(BTree::has_pending_flush)
             || self.bm25_indexes.iter().any(BM25::has_pending_flush)
             || self.hnsw_indexes.iter().any(Hnsw::has_pending_flush)
+            || self.sparse_indexes.iter().any(Sparse::has_pending_flush)
     }
 
     /// Sets the tokenizer for text analysis.
     ///
+    /// The chain is not persisted: BM25 indexes created afterwards record no
+    /// tokenizer (a warning is logged) and must be reopened with the same
+    /// chain installed, which happens only after the indexes have loaded.
+    /// Prefer recording a definition in [`CollectionConfig::tokenizer`] or
+    /// [`BM25Config::tokenizer`].
+    ///
     /// # Arguments
     /// * `tokenizer` - The tokenizer chain to use
     pub fn set_tokenizer(&mut self, tokenizer: TokenizerChain) {
         self.tokenizer = tokenizer;
+        self.tokenizer_config = None;
     }
 
     /// Replaces the strategy used to derive indexable values from documents.
@@ -1973,6 +2436,86 @@ impl Collection {
         self.index_hooks = hooks;
     }
 
+    /// Registers a second-stage reranker under `name`, replacing any
+    /// reranker registered under the same name.
+    ///
+    /// Queries select it through [`Search::rerank`]. Like index hooks,
+    /// rerankers are not persisted and must be registered whenever the
+    /// collection is opened.
+    pub fn set_reranker(&mut self, name: impl Into<String>, reranker: Arc<dyn DocumentReranker>) {
+        self.rerankers.insert(name.into(), reranker);
+    }
+
+    /// Returns whether a reranker is registered under `name`.
+    pub fn has_reranker(&self, name: &str) -> bool {
+        self.rerankers.contains_key(name)
+    }
+
+    /// Checks [`Search::field_boosts`] against this collection: the boosts
+    /// must be finite and non-negative, the search must have a text query,
+    /// and every field must belong to a BM25 index with field boosts.
+    pub fn validate_field_boosts(&self, search: &Search) -> Result<(), String> {
+        search.validate_field_boosts()?;
+        let Some(field_boosts) = &search.field_boosts else {
+            return Ok(());
+        };
+        if search.text.is_none() {
+            return Err("field_boosts requires a text query".to_string());
+        }
+        if let Some(field) = field_boosts.keys().find(|field| {
+            !self.bm25_indexes.iter().any(|index| {
+                !index.field_boosts().is_empty() && index.virtual_field().contains(field)
+            })
+        }) {
+            return Err(format!(
+                "field boost of {field:?} matches no BM25 index with field boosts"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks [`Search::highlight`] against this collection: the search
+    /// must have a text query, the fragment options must be in range, and
+    /// every field must belong to a BM25 index.
+    pub fn validate_highlight(&self, search: &Search) -> Result<(), String> {
+        let Some(highlight) = &search.highlight else {
+            return Ok(());
+        };
+        if search.text.is_none() {
+            return Err("highlight requires a text query".to_string());
+        }
+        highlight.options.validate()?;
+        if let Some(field) = highlight.fields.iter().find(|field| {
+            !self
+                .bm25_indexes
+                .iter()
+                .any(|index| index.virtual_field().contains(field))
+        }) {
+            return Err(format!("highlight field {field:?} matches no BM25 index"));
+        }
+        Ok(())
+    }
+
+    /// Extracts the value `index` stores for `doc`: the hook value, with the
+    /// index's declarative options applied.
+    fn btree_index_value<'a>(&self, index: &BTree, doc: &'a Document) -> Option<Cow<'a, Fv>> {
+        let value = self.index_hooks.btree_index_value(index, doc)?;
+        match self.btree_index_options.get(index.name()) {
+            Some(options) => options.apply(doc, value),
+            None => Some(value),
+        }
+    }
+
+    /// Extracts the vectors `index` stores for `doc`: one for a single-vector
+    /// index, any number for a multi-vector index.
+    fn hnsw_index_vectors(&self, index: &Hnsw, doc: &Document) -> Vec<Vector> {
+        self.index_hooks
+            .hnsw_index_values(index, doc)
+            .into_iter()
+            .map(Cow::into_owned)
+            .collect()
+    }
+
     /// Returns the collection name.
     pub fn name(&self) -> &str {
         &self.name
@@ -2014,6 +2557,25 @@ impl Collection {
         self.storage.stats()
     }
 
+    /// Returns the segment space accounting, or `None` unless the collection
+    /// uses [`DocumentStorage::Segments`].
+    pub fn segment_stats(&self) -> Option<SegmentStats> {
+        self.documents.segment_stats()
+    }
+
+    /// Runs one compaction pass over the collection's sparse segments and
+    /// returns the number of segments reclaimed; 0 unless the collection
+    /// uses [`DocumentStorage::Segments`].
+    ///
+    /// A pass copies at most one segment's worth of live records, so it can
+    /// be repeated until it returns 0. [`AndaDB::auto_flush`] does so after
+    /// each flush. An interrupted pass only leaves a segment to be compacted
+    /// again.
+    pub async fn compact_segments(&self) -> Result<usize, DBError> {
+        let _operation_lease = self.mutation_lease().await?;
+        self.documents.compact().await
+    }
+
     /// Returns the maximum document ID in the collection.
     pub fn max_document_id(&self) -> DocumentId {
         self.max_document_id.load(Ordering::Relaxed)
@@ -2223,18 +2785,147 @@ impl Collection {
         rt
     }
 
-    /// Sets a user-defined extension key-value pair with a serializable value and immediately persists the change.
-    pub async fn save_extension_from<T>(&self, key: String, value: &T) -> Result<(), DBError>
-    where
-        T: Serialize,
-    {
-        let field_value = FieldValue::serialized(value, None)?;
-        self.save_extension(key, field_value).await
+    /// Returns the collection's TTL configuration, if any.
+    pub fn ttl(&self) -> Option<TtlConfig> {
+        self.metadata.read().config.ttl.clone()
     }
 
-    /// Removes a user-defined extension key and immediately persists the change.
-    /// Returns the previous value if the key existed.
-    pub async fn remove_extension(&self, key: &str) -> Result<Option<FieldValue>, DBError> {
+    /// Sets or clears the collection's TTL and immediately persists the
+    /// change.
+    ///
+    /// # Arguments
+    /// * `ttl` - The new TTL; its field must have a single-field BTree index
+    ///   of type `U64` or `I64` without a filter or expression
+    ///
+    /// # Returns
+    /// Ok(()) if successful, or an error if the TTL is invalid or persisting
+    /// fails
+    pub async fn set_ttl(&self, ttl: Option<TtlConfig>) -> Result<(), DBError> {
+        let _operation_lease = self.mutation_lease().await?;
+        if let Some(ttl) = &ttl {
+            self.validate_ttl(ttl)?;
+        }
+
+        let guard = self.cancel_guard("Collection::set_ttl");
+        self.update_metadata(|meta| {
+            meta.config.ttl = ttl;
+            meta.stats.version += 1;
+        });
+        let rt = self.store_metadata_unclaimed().await;
+        guard.disarm();
+        rt
+    }
+
+    /// Checks that the TTL field has a single-field integer BTree index that
+    /// indexes every document's raw value: a partial index would never expire
+    /// the documents outside its filter, and an expression index would
+    /// expire them early.
+    pub(crate) fn validate_ttl(&self, ttl: &TtlConfig) -> Result<(), DBError> {
+        let declarative = self
+            .btree_index_options
+            .get(&virtual_field_name(&[&ttl.field]))
+            .is_some_and(BTreeIndexOptions::is_declarative);
+        match self.find_btree_index(&[&ttl.field]) {
+            Ok(BTree::U64(_) | BTree::I64(_)) if !declarative => Ok(()),
+            _ => Err(DBError::Schema {
+                name: self.name.clone(),
+                source: format!(
+                    "TTL field {:?} requires a single-field BTree index of type U64 or I64 \
+                     without a filter or expression",
+                    ttl.field
+                )
+                .into(),
+            }),
+        }
+    }
+
+    /// Returns `true` if `doc`'s TTL field, read through `index`, has
+    /// elapsed at `now_ms`.
+    fn is_expired(&self, index: &BTree, ttl: &TtlConfig, doc: &Document, now_ms: u64) -> bool {
+        let timestamp = match self.btree_index_value(index, doc).as_deref() {
+            Some(Fv::U64(value)) => *value as i128,
+            Some(Fv::I64(value)) => *value as i128,
+            _ => return false,
+        };
+        timestamp + ttl.ttl_ms as i128 <= now_ms as i128
+    }
+
+    /// Removes up to `limit` documents whose TTL has elapsed at `now_ms`.
+    ///
+    /// Each document is removed like [`Collection::remove`], so every index
+    /// is updated; the documents expired first are those with the oldest
+    /// timestamps. A document whose timestamp was refreshed after the index
+    /// scan is re-checked under its lock and kept. [`AndaDB::auto_flush`] calls this in batches of
+    /// [`Collection::EXPIRE_BATCH_SIZE`] before each flush.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time (unix ms)
+    /// * `limit` - Maximum number of documents to remove
+    ///
+    /// # Returns
+    /// The number of removed documents; 0 if the collection has no TTL
+    pub async fn expire(&self, now_ms: u64, limit: usize) -> Result<usize, DBError> {
+        let Some(ttl) = self.ttl() else {
+            return Ok(0);
+        };
+        self.ensure_mutable()?;
+        self.validate_ttl(&ttl)?;
+        let index = self.find_btree_index(&[&ttl.field])?;
+        let cutoff = now_ms as i128 - ttl.ttl_ms as i128;
+        let query = match index {
+            BTree::U64(_) if cutoff < 0 => return Ok(0),
+            BTree::U64(_) => RangeQuery::Le(Fv::U64(cutoff as u64)),
+            _ => RangeQuery::Le(Fv::I64(cutoff.max(i64::MIN as i128) as i64)),
+        };
+
+        // Skip ids the index still maps but the collection no longer holds,
+        // so stale postings cannot take up every batch.
+        let mut ids: UniqueVec<DocumentId> = UniqueVec::with_capacity(Self::reserve_hint(limit));
+        {
+            let doc_ids = self.doc_ids.read();
+            index.try_range_query_ids(query, false, |matched| {
+                for id in matched {
+                    if doc_ids.contains(*id) {
+                        ids.push(*id);
+                        if ids.len() >= limit {
+                            return false;
+                        }
+                    }
+                }
+                true
+            })?;
+        }
+
+        let mut expired = 0;
+        for id in Vec::from(ids) {
+            let removed = self
+                .remove_if(id, |doc| self.is_expired(index, &ttl, doc, now_ms))
+                .await?;
+            if removed.is_some() {
+                expired += 1;
+            }
+        }
+        if expired > 0 {
+            self.update_metadata(|meta| {
+                meta.stats.expired_count += expired as u64;
+                meta.stats.last_expired = now_ms;
+            });
+        }
+        Ok(expired)
+    }
+
+    /// Sets a user-defined extension key-value pair with a serializable value and immediately persists the change.
+    pub async fn save_extension_from<T>(&self, key: String, value: &T) -> Result<(), DBError>
+    where
+        T: Serialize,
+    {
+        let field_value = FieldValue::serialized(value, None)?;
+        self.save_extension(key, field_value).await
+    }
+
+    /// Removes a user-defined extension key and immediately persists the change.
+    /// Returns the previous value if the key existed.
+    pub async fn remove_extension(&self, key: &str) -> Result<Option<FieldValue>, DBError> {
         let _operation_lease = self.mutation_lease().await?;
 
         let guard = self.cancel_guard("Collection::remove_extension");
@@ -2283,12 +2974,73 @@ impl Collection {
     ///   are backfilled at creation time and can make creation fail if they
     ///   already violate the constraint.
     ///
+    /// # Nested field paths
+    ///
+    /// A field may be a dotted path into a `Map` or `Json` field, such as
+    /// `metadata.user_id`; see [`field_path_type`] for how it resolves. The
+    /// path is the index name persisted in [`CollectionMetadata`], so every
+    /// opener indexes the same nested value, and it is the name
+    /// `Filter::Field` and `OrderBy` refer to. A path index is not unique
+    /// unless created with [`Collection::create_unique_btree_index`].
+    ///
+    /// Values inside a `Json` field are indexed as typed JSON scalars (see
+    /// [`JsonKey`]): numbers compare numerically, and a range filter only
+    /// matches values of its bound's kind.
+    ///
+    /// Partial and expression indexes are created with
+    /// [`Collection::create_btree_index_with_options`].
+    ///
     /// # Arguments
-    /// * `fields` - Fields to index
+    /// * `fields` - Fields or field paths to index
     ///
     /// # Returns
     /// Ok(()) if successful, or an error if creation fails
     pub async fn create_btree_index(&mut self, fields: &[&str]) -> Result<(), DBError> {
+        self.create_btree_index_with_options(fields, BTreeIndexOptions::default())
+            .await
+    }
+
+    /// Creates a BTree index that rejects duplicate keys, on a single field
+    /// or field path regardless of its schema `unique` flag.
+    ///
+    /// Multi-field indexes are always unique; see
+    /// [`Collection::create_btree_index`].
+    ///
+    /// # Arguments
+    /// * `fields` - Fields or field paths to index
+    ///
+    /// # Returns
+    /// Ok(()) if successful, or an error if creation fails
+    pub async fn create_unique_btree_index(&mut self, fields: &[&str]) -> Result<(), DBError> {
+        self.create_btree_index_with_options(
+            fields,
+            BTreeIndexOptions {
+                unique: true,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Creates a BTree index with declarative options: a predicate that
+    /// restricts the indexed documents (a partial index) and an expression
+    /// that transforms the indexed value. See [`BTreeIndexOptions`].
+    ///
+    /// The options are validated against the schema here and persisted in
+    /// [`CollectionMetadata::btree_index_options`], so every opener of the
+    /// collection applies them, with or without the same [`IndexHooks`].
+    ///
+    /// # Arguments
+    /// * `fields` - Fields or field paths to index
+    /// * `options` - Uniqueness, predicate and expression of the index
+    ///
+    /// # Returns
+    /// Ok(()) if successful, or an error if creation fails
+    pub async fn create_btree_index_with_options(
+        &mut self,
+        fields: &[&str],
+        options: BTreeIndexOptions,
+    ) -> Result<(), DBError> {
         self.ensure_mutable()?;
         if fields.is_empty() {
             return Err(DBError::Schema {
@@ -2331,25 +3083,35 @@ impl Collection {
             }
         }
 
-        if fields.len() == 1 {
-            let field = self.schema.get_field_or_err(fields[0])?;
+        options
+            .validate(&self.schema, fields)
+            .map_err(|source| DBError::Schema {
+                name: self.name.clone(),
+                source: source.into(),
+            })?;
 
-            let index = BTree::new(field.clone(), self.storage.clone(), now_ms).await?;
-            if let Err(err) = self.backfill_btree_index(&index, now_ms).await {
-                index.drop_data().await;
-                return Err(err);
+        let (index, field) = if fields.len() == 1 && fields[0].contains(FIELD_PATH_SEPARATOR) {
+            let mut field = FieldEntry::new(
+                "_path_field_".to_string(),
+                field_path_type(&self.schema, fields[0])?,
+            )?
+            .with_description(name.clone());
+            if options.unique {
+                field = field.with_unique();
             }
-            if field.unique() {
-                self.btree_indexes.insert(0, index);
-            } else {
-                self.btree_indexes.push(index);
+            let index =
+                BTree::with_field_path(name.clone(), &field, self.storage.clone(), now_ms).await?;
+            (index, field)
+        } else if fields.len() == 1 {
+            let mut field = self.schema.get_field_or_err(fields[0])?.clone();
+            if options.unique {
+                field = field.with_unique();
             }
-            let mut meta = self.metadata.write();
-            meta.btree_indexes.insert(name.to_string(), field.clone());
-            meta.stats.version += 1;
+            let index = BTree::new(field.clone(), self.storage.clone(), now_ms).await?;
+            (index, field)
         } else {
             for field in fields {
-                self.schema.get_field_or_err(field)?;
+                field_path_type(&self.schema, field)?;
             }
             let field = FieldEntry::new("_virtual_field_".to_string(), FieldType::Bytes)?
                 .with_unique()
@@ -2360,16 +3122,31 @@ impl Collection {
                 now_ms,
             )
             .await?;
+            (index, field)
+        };
 
-            if let Err(err) = self.backfill_btree_index(&index, now_ms).await {
-                index.drop_data().await;
-                return Err(err);
-            }
+        // Backfill reads the options through `btree_index_value`.
+        let options = options.is_declarative().then_some(options);
+        if let Some(options) = &options {
+            self.btree_index_options
+                .insert(name.clone(), options.clone());
+        }
+        if let Err(err) = self.backfill_btree_index(&index, now_ms).await {
+            self.btree_index_options.remove(&name);
+            index.drop_data().await;
+            return Err(err);
+        }
+        if field.unique() {
             self.btree_indexes.insert(0, index);
-            let mut meta = self.metadata.write();
-            meta.btree_indexes.insert(name, field);
-            meta.stats.version += 1;
+        } else {
+            self.btree_indexes.push(index);
         }
+        let mut meta = self.metadata.write();
+        meta.btree_indexes.insert(name.clone(), field);
+        if let Some(options) = options {
+            meta.btree_index_options.insert(name, options);
+        }
+        meta.stats.version += 1;
 
         Ok(())
     }
@@ -2405,6 +3182,31 @@ impl Collection {
     /// # Returns
     /// Ok(()) if successful, or an error if creation fails
     pub async fn create_bm25_index(&mut self, fields: &[&str]) -> Result<(), DBError> {
+        self.create_bm25_index_with(fields, BM25Config::default())
+            .await
+    }
+
+    /// Creates a BM25 text search index with the given configuration.
+    ///
+    /// Set [`BM25Config::positions`] to enable phrase (`"machine learning"`)
+    /// and proximity (`NEAR/n`) matching, and [`BM25Config::field_boosts`],
+    /// one boost per field, to score the fields with BM25F instead of as one
+    /// text. `bucket_overload_size` is always taken from the storage
+    /// configuration. [`BM25Config::tokenizer`] defaults to the collection's
+    /// [`CollectionConfig::tokenizer`]; a recorded definition is resolved
+    /// against the database's tokenizer registry, here and on every open.
+    ///
+    /// # Arguments
+    /// * `fields` - Fields to index
+    /// * `config` - BM25 index configuration
+    ///
+    /// # Returns
+    /// Ok(()) if successful, or an error if creation fails
+    pub async fn create_bm25_index_with(
+        &mut self,
+        fields: &[&str],
+        config: BM25Config,
+    ) -> Result<(), DBError> {
         self.ensure_mutable()?;
         if fields.is_empty() {
             return Err(DBError::Schema {
@@ -2412,6 +3214,27 @@ impl Collection {
                 source: "BM25 index requires at least one field".into(),
             });
         }
+        if !config.field_boosts.is_empty() && config.field_boosts.len() != fields.len() {
+            return Err(DBError::Schema {
+                name: self.name.clone(),
+                source: format!(
+                    "BM25 index has {} fields but {} field boosts",
+                    fields.len(),
+                    config.field_boosts.len()
+                )
+                .into(),
+            });
+        }
+        if config
+            .field_boosts
+            .iter()
+            .any(|boost| !boost.is_finite() || *boost < 0.0)
+        {
+            return Err(DBError::Schema {
+                name: self.name.clone(),
+                source: "BM25 field boosts must be finite and non-negative".into(),
+            });
+        }
 
         let now_ms = unix_ms();
         let name = virtual_field_name(fields);
@@ -2431,9 +3254,33 @@ impl Collection {
             self.schema.get_field_or_err(field)?;
         }
 
-        let index = BM25::new(
+        // The index records its tokenizer: the one given in its config, or
+        // the collection's, which is the built-in default definition unless
+        // `set_tokenizer` installed an unnamed chain.
+        let tokenizer_config = config
+            .tokenizer
+            .clone()
+            .or_else(|| self.tokenizer_config.clone());
+        if tokenizer_config.is_none() {
+            log::warn!(
+                action = "Collection::create_bm25_index_with",
+                collection = self.name,
+                index = name;
+                "Tokenizer installed by set_tokenizer cannot be recorded; \
+                 reinstall it before the index is next used",
+            );
+        }
+        let tokenizer = match &tokenizer_config {
+            Some(tokenizer) => self.tokenizers.resolve(tokenizer)?,
+            None => self.tokenizer.clone(),
+        };
+        let index = BM25::new_with(
             fields.iter().map(|s| s.to_string()).collect(),
-            self.tokenizer.clone(),
+            tokenizer,
+            BM25Config {
+                tokenizer: tokenizer_config,
+                ..config
+            },
             self.storage.clone(),
             now_ms,
         )
@@ -2458,9 +3305,8 @@ impl Collection {
 
     /// Creates a BM25 index if it doesn't already exist.
     ///
-    /// Like [`Collection::create_btree_index_nx`], a BM25 index takes no
-    /// configuration beyond its field list, so an existing index cannot
-    /// disagree with the request.
+    /// Like [`Collection::create_btree_index_nx`], only the field list is
+    /// compared: an existing index is kept with its persisted configuration.
     ///
     /// # Arguments
     /// * `fields` - Fields to index
@@ -2468,7 +3314,28 @@ impl Collection {
     /// # Returns
     /// Ok(()) if successful, or an error if creation fails
     pub async fn create_bm25_index_nx(&mut self, fields: &[&str]) -> Result<(), DBError> {
-        match self.create_bm25_index(fields).await {
+        self.create_bm25_index_with_nx(fields, BM25Config::default())
+            .await
+    }
+
+    /// Creates a BM25 index with the given configuration if it doesn't
+    /// already exist.
+    ///
+    /// See [`Collection::create_bm25_index_nx`]: an existing index keeps its
+    /// persisted configuration, including whether it records positions.
+    ///
+    /// # Arguments
+    /// * `fields` - Fields to index
+    /// * `config` - BM25 index configuration
+    ///
+    /// # Returns
+    /// Ok(()) if successful, or an error if creation fails
+    pub async fn create_bm25_index_with_nx(
+        &mut self,
+        fields: &[&str],
+        config: BM25Config,
+    ) -> Result<(), DBError> {
+        match self.create_bm25_index_with(fields, config).await {
             Ok(_) => Ok(()),
             Err(DBError::AlreadyExists { .. }) => {
                 // Ignore the error if the index already exists
@@ -2480,6 +3347,11 @@ impl Collection {
 
     /// Creates a HNSW (vector) search index.
     ///
+    /// The field must be a `Vector`, or an `Array` of vectors for a
+    /// multi-vector index: every vector of a document is indexed and searches
+    /// rank the document by its nearest vector (see [`Search::vectors`] for
+    /// multi-vector queries).
+    ///
     /// # Arguments
     /// * `field` - Name of the field to index
     /// * `config` - HNSW index configuration
@@ -2517,10 +3389,10 @@ impl Collection {
                 source: "field not found".into(),
                 _id: 0,
             })?;
-        if field.r#type() != &FieldType::Vector {
+        if field.r#type() != &FieldType::Vector && !is_multi_vector_field(field.r#type()) {
             return Err(DBError::Schema {
                 name: self.name.clone(),
-                source: "The type of field for HNSW index should be FieldType::Vector".into(),
+                source: "The type of field for HNSW index should be FieldType::Vector or an Array of FieldType::Vector".into(),
             });
         }
 
@@ -2599,6 +3471,92 @@ impl Collection {
         }
     }
 
+    /// Creates a sparse vector (dot-product) search index.
+    ///
+    /// The field must be a `SparseVector` (or an `Option` of one). Documents
+    /// are ranked by the dot product of their weights with
+    /// [`Search::sparse_vector`], and the ranking is fused with BM25 and HNSW
+    /// results like any other ranked list.
+    ///
+    /// # Arguments
+    /// * `field` - Name of the field to index
+    ///
+    /// # Returns
+    /// Ok(()) if successful, or an error if creation fails
+    pub async fn create_sparse_index(&mut self, field: &str) -> Result<(), DBError> {
+        self.ensure_mutable()?;
+        validate_field_name(field)?;
+
+        let name = field.to_string();
+        let now_ms = unix_ms();
+
+        {
+            if self.metadata.read().sparse_indexes.contains_key(&name) {
+                return Err(DBError::AlreadyExists {
+                    name: name.clone(),
+                    path: self.name.clone(),
+                    source: "sparse index already exists".into(),
+                    _id: 0,
+                });
+            }
+        }
+
+        let field = self
+            .schema
+            .get_field(field)
+            .ok_or_else(|| DBError::NotFound {
+                name: field.to_string(),
+                path: self.name.clone(),
+                source: "field not found".into(),
+                _id: 0,
+            })?;
+        let is_sparse = match field.r#type() {
+            FieldType::SparseVector => true,
+            FieldType::Option(ft) => ft.as_ref() == &FieldType::SparseVector,
+            _ => false,
+        };
+        if !is_sparse {
+            return Err(DBError::Schema {
+                name: self.name.clone(),
+                source: "The type of field for sparse index should be FieldType::SparseVector"
+                    .into(),
+            });
+        }
+
+        let index = Sparse::new(field, self.storage.clone(), now_ms).await?;
+        if let Err(err) = self.backfill_sparse_index(&index, now_ms).await {
+            index.drop_data().await;
+            return Err(err);
+        }
+
+        {
+            let mut meta = self.metadata.write();
+            meta.stats.version += 1;
+            meta.sparse_indexes.insert(name, field.clone());
+        }
+
+        self.sparse_indexes.push(index);
+        Ok(())
+    }
+
+    /// Creates a sparse vector index if it doesn't already exist.
+    ///
+    /// A sparse index takes no configuration beyond its field, so an existing
+    /// index cannot disagree with the request.
+    ///
+    /// # Arguments
+    /// * `field` - Name of the field to index
+    ///
+    /// # Returns
+    /// Ok(()) if successful, or an error if creation fails
+    pub async fn create_sparse_index_nx(&mut self, field: &str) -> Result<(), DBError> {
+        match self.create_sparse_index(field).await {
+            Ok(_) => Ok(()),
+            Err(DBError::AlreadyExists { .. }) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
     /// Removes a B-tree index and its persisted files.
     ///
     /// Returns `true` when either metadata or an in-memory index entry was
@@ -2613,15 +3571,28 @@ impl Collection {
         }
 
         let name = virtual_field_name(fields);
+        if let Some(ttl) = self.ttl()
+            && name == virtual_field_name(&[&ttl.field])
+        {
+            return Err(DBError::Schema {
+                name: self.name.clone(),
+                source: format!(
+                    "BTree index {name:?} backs the collection TTL; clear the TTL first"
+                )
+                .into(),
+            });
+        }
         let removed_index = self
             .btree_indexes
             .iter()
             .position(|index| index.name() == name)
             .map(|position| self.btree_indexes.remove(position))
             .is_some();
+        self.btree_index_options.remove(&name);
 
         let removed_metadata = {
             let mut meta = self.metadata.write();
+            meta.btree_index_options.remove(&name);
             let removed = meta.btree_indexes.remove(&name).is_some();
             if removed {
                 meta.stats.version += 1;
@@ -2725,12 +3696,50 @@ impl Collection {
             .hnsw_indexes
             .iter()
             .position(|index| index.field_name() == field)
-            .map(|position| self.hnsw_indexes.remove(position))
+            .map(|position| self.hnsw_indexes.remove(position));
+
+        let (removed_metadata, generation) = {
+            let mut meta = self.metadata.write();
+            let removed = meta.hnsw_indexes.remove(field).is_some();
+            let generation = meta.hnsw_index_generations.remove(field);
+            if removed || generation.is_some() {
+                meta.stats.version += 1;
+            }
+            (removed, generation)
+        };
+
+        let generation = removed_index
+            .as_ref()
+            .map(Hnsw::generation)
+            .or(generation)
+            .unwrap_or_default();
+        let removed = removed_index.is_some() || removed_metadata;
+        if removed {
+            self.cleanup_removed_index(&Hnsw::dir_path(field, generation))
+                .await?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Removes a sparse vector index and its persisted files.
+    ///
+    /// Returns `true` when either metadata or an in-memory index entry was
+    /// removed. Returns `false` if the requested field has no sparse index.
+    pub async fn remove_sparse_index(&mut self, field: &str) -> Result<bool, DBError> {
+        self.ensure_mutable()?;
+        validate_field_name(field)?;
+
+        let removed_index = self
+            .sparse_indexes
+            .iter()
+            .position(|index| index.field_name() == field)
+            .map(|position| self.sparse_indexes.remove(position))
             .is_some();
 
         let removed_metadata = {
             let mut meta = self.metadata.write();
-            let removed = meta.hnsw_indexes.remove(field).is_some();
+            let removed = meta.sparse_indexes.remove(field).is_some();
             if removed {
                 meta.stats.version += 1;
             }
@@ -2739,7 +3748,7 @@ impl Collection {
 
         let removed = removed_index || removed_metadata;
         if removed {
-            self.cleanup_removed_index(&Hnsw::dir_path(field)).await?;
+            self.cleanup_removed_index(&Sparse::dir_path(field)).await?;
         }
 
         Ok(removed)
@@ -2836,16 +3845,141 @@ impl Collection {
         rt
     }
 
-    /// Adds a new document to the collection.
+    /// Measures the recall of the HNSW index of `field` and sets the smallest
+    /// measured `ef_search` that reaches `target_recall` as the beam width of
+    /// its searches. Returns the chosen value with the measurements.
     ///
-    /// This method:
-    /// 1. Validates the document against the collection schema
-    /// 2. Assigns a unique ID to the document
-    /// 3. Updates all relevant indexes
-    /// 4. Persists the document to storage
-    ///
-    /// # Arguments
-    /// * `doc` - The document to add to the collection
+    /// Recall is measured against an exact scan of the stored vectors, see
+    /// [`HnswIndexView::evaluate_recall`]. The value is kept apart from the
+    /// index configuration, so [`Collection::create_hnsw_index_nx`] still
+    /// accepts the original one, and is persisted by the next flush.
+    ///
+    /// The measurements run on a blocking thread without holding the
+    /// operation gate, so writes and flushes proceed meanwhile; only setting
+    /// the chosen value takes a mutation lease.
+    pub async fn tune_hnsw_ef_search(
+        &self,
+        field: &str,
+        target_recall: f32,
+        options: &RecallOptions,
+    ) -> Result<(usize, RecallReport), DBError> {
+        self.ensure_mutable()?;
+        let index = self.find_hnsw_index(field)?;
+        if !(target_recall > 0.0 && target_recall <= 1.0) {
+            return Err(DBError::Index {
+                name: field.to_string(),
+                source: format!("target recall must be within (0, 1], got {target_recall}").into(),
+            });
+        }
+
+        let report = index.evaluate_recall_blocking(options.clone()).await?;
+        let chosen = report
+            .ef_search_for(target_recall)
+            .unwrap_or_else(|| index.ef_search());
+        let _operation_lease = self.mutation_lease().await?;
+        index.set_ef_search(Some(chosen))?;
+        Ok((chosen, report))
+    }
+
+    /// Rebuilds the HNSW index of `field` from the live documents, online.
+    ///
+    /// Removals without [`HnswConfig::reconnect_on_delete`] leave a graph
+    /// with orphaned and unreachable nodes that searches miss (see
+    /// [`HnswIndexView::graph_health`]). The rebuild writes a fresh graph of
+    /// the next generation under its own directory while the current one
+    /// keeps serving searches and mutations. Documents mutated meanwhile are
+    /// then replayed into the new graph under the **exclusive** operation
+    /// gate, the graphs are swapped, and the checkpoint that records the new
+    /// generation in the collection metadata commits the rebuild. The objects
+    /// of the old graph are purged afterwards.
+    ///
+    /// A failure before the swap leaves the index untouched. A failure of the
+    /// committing checkpoint poisons the handle like [`Collection::flush`];
+    /// reopening loads whichever generation the metadata recorded.
+    pub async fn rebuild_hnsw_index(&self, field: &str) -> Result<(), DBError> {
+        self.ensure_mutable()?;
+        let index = self.find_hnsw_index(field)?;
+        let entry = self
+            .schema
+            .get_field(field)
+            .ok_or_else(|| DBError::NotFound {
+                name: field.to_string(),
+                path: self.name.clone(),
+                source: "field not found".into(),
+                _id: 0,
+            })?;
+
+        let rebuild = index.begin_rebuild(entry, unix_ms()).await?;
+        let rt = async {
+            self.backfill_hnsw_index(rebuild.rebuilt(), unix_ms())
+                .await?;
+            rebuild.rebuilt().flush(unix_ms()).await?;
+            Ok::<_, DBError>(())
+        }
+        .await;
+        if let Err(err) = rt {
+            rebuild.abort().await;
+            return Err(err);
+        }
+
+        let _operation_guard = self.operation_gate.clone().write_owned().await;
+        let rt = async {
+            self.ensure_mutable()?;
+            let now_ms = unix_ms();
+            let changes: Vec<DocumentId> = rebuild.take_changes().into_iter().collect();
+            let rebuilt = rebuild.rebuilt();
+            for id in &changes {
+                rebuilt.remove(*id, now_ms);
+            }
+            let mut items = Vec::new();
+            self.for_each_existing_document(changes, |id, doc| {
+                let vectors = self.hnsw_index_vectors(rebuilt, &doc);
+                if !vectors.is_empty() {
+                    items.push((id, vectors));
+                }
+                Ok(())
+            })
+            .await?;
+            block_in_place(|| rebuilt.insert_batch(items, now_ms))?;
+            rebuilt.flush(now_ms).await?;
+            Ok::<_, DBError>(())
+        }
+        .await;
+        if let Err(err) = rt {
+            rebuild.abort().await;
+            return Err(err);
+        }
+
+        let guard = self.cancel_guard("Collection::rebuild_hnsw_index");
+        let generation = rebuild.generation();
+        let old_generation = rebuild.commit();
+        self.update_metadata(|meta| {
+            meta.hnsw_index_generations
+                .insert(field.to_string(), generation);
+            meta.stats.version += 1;
+        });
+        let rt = self.flush_inner(unix_ms()).await;
+        guard.disarm();
+        if let Err(err) = rt {
+            self.poison("Collection::rebuild_hnsw_index");
+            return Err(err);
+        }
+
+        index.drop_generation(old_generation).await;
+        index.graph_health(unix_ms());
+        Ok(())
+    }
+
+    /// Adds a new document to the collection.
+    ///
+    /// This method:
+    /// 1. Validates the document against the collection schema
+    /// 2. Assigns a unique ID to the document
+    /// 3. Updates all relevant indexes
+    /// 4. Persists the document to storage
+    ///
+    /// # Arguments
+    /// * `doc` - The document to add to the collection
     ///
     /// # Returns
     /// The ID of the newly added document, or an error if addition fails
@@ -2917,10 +4051,12 @@ impl Collection {
         let mut bm25_inserted: FxHashMap<&BM25, (u64, Cow<str>)> = FxHashMap::default();
         #[allow(clippy::mutable_key_type)]
         let mut hnsw_inserted: FxHashMap<&Hnsw, u64> = FxHashMap::default();
+        #[allow(clippy::mutable_key_type)]
+        let mut sparse_inserted: FxHashMap<&Sparse, SparseVector> = FxHashMap::default();
 
         let rt: Result<(), DBError> = (|| {
             for index in &self.btree_indexes {
-                if let Some(fv) = self.index_hooks.btree_index_value(index, &doc) {
+                if let Some(fv) = self.btree_index_value(index, &doc) {
                     if fv.as_ref() == &FieldValue::Null {
                         continue;
                     }
@@ -2938,9 +4074,17 @@ impl Collection {
             }
 
             for index in &self.hnsw_indexes {
-                if let Some(vector) = self.index_hooks.hnsw_index_value(index, &doc) {
+                let vectors = self.hnsw_index_vectors(index, &doc);
+                if !vectors.is_empty() {
                     hnsw_inserted.insert(index, id);
-                    index.insert(id, vector.into_owned(), now_ms)?;
+                    index.insert_vectors(id, vectors, now_ms)?;
+                }
+            }
+
+            for index in &self.sparse_indexes {
+                if let Some(vector) = self.index_hooks.sparse_index_value(index, &doc) {
+                    index.insert(id, &vector, now_ms)?;
+                    sparse_inserted.insert(index, vector);
                 }
             }
 
@@ -2957,6 +4101,9 @@ impl Collection {
             for (k, v) in hnsw_inserted {
                 k.remove(v, now_ms);
             }
+            for (k, v) in sparse_inserted {
+                k.remove(id, &v, now_ms);
+            }
         };
 
         if let Err(err) = rt {
@@ -2964,8 +4111,8 @@ impl Collection {
             return Err(err);
         }
 
-        let path = Self::doc_path(id);
-        if let Err(err) = self.storage.create(&path, &doc).await {
+        let key = RecordKey::Document(id);
+        if let Err(err) = self.documents.create(key, &doc).await {
             rollback_indexes();
             // `AlreadyExists` is the one *known* outcome of `PutMode::Create`:
             // this add wrote nothing, the object at `path` is someone else's
@@ -2979,7 +4126,7 @@ impl Collection {
                 // outcome is unknown, treat it like a crash — the reopen
                 // repair scan (whose checkpoint has not advanced past this
                 // id) decides whether the document exists.
-                match self.storage.delete(&path).await {
+                match self.documents.delete(key).await {
                     Ok(()) | Err(DBError::NotFound { .. }) => {}
                     Err(delete_err) => {
                         log::error!(
@@ -3028,6 +4175,359 @@ impl Collection {
         self.add(doc).await
     }
 
+    /// Starts a bulk load: documents are stored without index maintenance
+    /// and every index is built in one pass by [`BulkLoader::finish`].
+    ///
+    /// The loader holds a mutation lease until it is finished or dropped, so
+    /// flushes wait for it; see [`BulkLoader`].
+    pub async fn bulk_loader(&self) -> Result<BulkLoader<'_>, DBError> {
+        let lease = self.mutation_lease().await?;
+        Ok(BulkLoader::new(self, lease))
+    }
+
+    /// Stores one bulk-loaded document. Same id allocation and failure
+    /// cleanup as [`Collection::add`], without the index inserts and the id
+    /// registration, which [`Collection::finish_bulk_load`] performs.
+    pub(crate) async fn bulk_add(&self, mut doc: Document) -> Result<DocumentId, DBError> {
+        self.ensure_mutable()?;
+        self.schema.validate(doc.fields())?;
+        let guard = self.cancel_guard("BulkLoader::add");
+        let id = self.max_document_id.fetch_add(1, Ordering::Acquire) + 1;
+        doc.set_id(id);
+        if let Err(err) = self.ensure_allocation_watermark(id).await {
+            guard.disarm();
+            return Err(err);
+        }
+
+        let key = RecordKey::Document(id);
+        let rt = self.documents.create(key, &doc).await;
+        if let Err(err) = &rt
+            && !matches!(err, DBError::AlreadyExists { .. })
+        {
+            match self.documents.delete(key).await {
+                Ok(()) | Err(DBError::NotFound { .. }) => {}
+                Err(delete_err) => {
+                    log::error!(
+                        action = "BulkLoader::add",
+                        collection = self.name,
+                        doc_id = id;
+                        "Failed to clean up document after failed add: {delete_err:?}",
+                    );
+                    self.poison("BulkLoader::add");
+                }
+            }
+        }
+        guard.disarm();
+        rt.map(|_| id)
+    }
+
+    /// Indexes the bulk-loaded documents of `ids` and registers them.
+    ///
+    /// On error the documents are removed from every index and from
+    /// storage, so the collection is left as before the bulk load.
+    pub(crate) async fn finish_bulk_load(&self, ids: &[DocumentId]) -> Result<(), DBError> {
+        let guard = self.cancel_guard("BulkLoader::finish");
+        let now_ms = unix_ms();
+        let targets = BackfillTargets {
+            btree: self.btree_indexes.iter().collect(),
+            bm25: self.bm25_indexes.iter().collect(),
+            hnsw: self.hnsw_indexes.iter().collect(),
+            sparse: self.sparse_indexes.iter().collect(),
+        };
+        if let Err(err) = self.backfill_indexes(ids.to_vec(), targets, now_ms).await {
+            self.abort_bulk_load(ids, now_ms).await;
+            guard.disarm();
+            return Err(err);
+        }
+
+        {
+            let mut doc_ids = self.doc_ids.write();
+            let mut doc_ids_index = self.doc_ids_index.write();
+            for id in ids {
+                doc_ids.add(*id);
+                doc_ids_index.insert(*id);
+            }
+        }
+        self.update_metadata(|meta| {
+            meta.stats.last_inserted = now_ms;
+            meta.stats.version += 1;
+            meta.stats.insert_count += ids.len() as u64;
+        });
+        guard.disarm();
+        Ok(())
+    }
+
+    /// Removes failed bulk-loaded documents from the indexes and storage.
+    /// Poisons the handle when a document cannot be read back or deleted:
+    /// the reopen repair scan then decides its fate.
+    async fn abort_bulk_load(&self, ids: &[DocumentId], now_ms: u64) {
+        for &id in ids {
+            let key = RecordKey::Document(id);
+            let rt = match self.documents.fetch::<DocumentOwned>(key).await {
+                Ok((doc, _)) => Document::try_from_doc(self.schema(), doc)
+                    .map(Some)
+                    .map_err(DBError::from),
+                Err(DBError::NotFound { .. }) => Ok(None),
+                Err(err) => Err(err),
+            };
+            let rt = match rt {
+                Ok(Some(doc)) => {
+                    self.remove_document_from_indexes(id, &doc, now_ms);
+                    self.documents.delete(key).await
+                }
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = rt
+                && !matches!(err, DBError::NotFound { .. })
+            {
+                log::error!(
+                    action = "BulkLoader::finish",
+                    collection = self.name,
+                    doc_id = id;
+                    "Failed to roll back bulk-loaded document: {err:?}",
+                );
+                self.poison("BulkLoader::finish");
+            }
+        }
+    }
+
+    /// Streams the collection as export frames: an [`ExportHeader`] with the
+    /// schema, then every document in ascending id order.
+    ///
+    /// The stream is not a point-in-time view: documents removed while it
+    /// runs are skipped and later additions may be missed. Use
+    /// [`AndaDB::snapshot`] for a consistent copy of a live database.
+    /// Documents are read without populating the object cache.
+    pub fn export_stream(&self, format: ExportFormat) -> BoxStream<'_, Result<Bytes, DBError>> {
+        let ids = self.ids();
+        let header = ExportHeader {
+            version: EXPORT_FORMAT_VERSION,
+            collection: self.name.clone(),
+            schema: self.schema.as_ref().clone(),
+            count: ids.len() as u64,
+        };
+        let documents = futures::stream::iter(ids)
+            .map(|id| {
+                let documents = self.documents.clone();
+                async move {
+                    documents
+                        .fetch::<DocumentOwned>(RecordKey::Document(id))
+                        .await
+                }
+            })
+            .buffered(8)
+            .filter_map(move |rt| async move {
+                match rt {
+                    Ok((doc, _)) => Some(encode_frame(format, &doc)),
+                    Err(DBError::NotFound { .. }) => None,
+                    Err(err) => Some(Err(err)),
+                }
+            });
+        futures::stream::once(async move { encode_frame(format, &header) })
+            .chain(documents)
+            .boxed()
+    }
+
+    /// Imports an export stream written by [`Collection::export_stream`].
+    ///
+    /// Field values are mapped to this collection's schema by field name and
+    /// every document is validated. Documents are imported in batches of
+    /// [`ImportOptions::batch_size`]: each batch is inserted into the indexes
+    /// index by index, then written to storage concurrently. A failed batch
+    /// is rolled back and aborts the import; earlier batches stay imported.
+    pub async fn import_stream<R>(
+        &self,
+        reader: R,
+        format: ExportFormat,
+        options: ImportOptions,
+    ) -> Result<ImportReport, DBError>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        let mut frames = FrameReader::new(reader, format);
+        let header: ExportHeader = frames.next().await?.ok_or_else(|| DBError::Serialization {
+            name: self.name.clone(),
+            source: "import stream has no header".into(),
+        })?;
+        if header.version != EXPORT_FORMAT_VERSION {
+            return Err(DBError::Serialization {
+                name: self.name.clone(),
+                source: format!("unsupported export format version {}", header.version).into(),
+            });
+        }
+        // Source field index -> target field index; `None` for fields the
+        // target schema does not declare.
+        let field_map: BTreeMap<usize, Option<usize>> = header
+            .schema
+            .iter()
+            .map(|field| {
+                (
+                    field.idx(),
+                    self.schema
+                        .get_field(field.name())
+                        .map(|target| target.idx()),
+                )
+            })
+            .collect();
+
+        let batch_size = options.batch_size.max(1);
+        let mut report = ImportReport::default();
+        let mut batch = Vec::with_capacity(batch_size);
+        loop {
+            let next: Option<DocumentOwned> = frames.next().await?;
+            let done = next.is_none();
+            if let Some(doc) = next {
+                let source_id = doc.fields.get(&0).and_then(|id| match id {
+                    FieldValue::U64(id) => Some(*id),
+                    _ => None,
+                });
+                let mut fields = IndexedFieldValues::new();
+                for (idx, value) in doc.fields {
+                    match field_map.get(&idx) {
+                        Some(Some(target)) => {
+                            fields.insert(*target, value);
+                        }
+                        _ if options.skip_unknown_fields => {}
+                        _ => {
+                            return Err(DBError::Schema {
+                                name: self.name.clone(),
+                                source: format!(
+                                    "exported field index {idx} is not declared by the target schema"
+                                )
+                                .into(),
+                            });
+                        }
+                    }
+                }
+                let doc = Document::try_from_doc(self.schema.clone(), DocumentOwned { fields })?;
+                batch.push((source_id.unwrap_or_default(), doc));
+            }
+
+            if batch.len() >= batch_size || (done && !batch.is_empty()) {
+                let docs = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+                let ids = self.import_batch(docs, options.ids).await?;
+                report.imported += ids.len() as u64;
+                report.ids.extend(ids);
+            }
+            if done {
+                return Ok(report);
+            }
+        }
+    }
+
+    async fn import_batch(
+        &self,
+        docs: Vec<(DocumentId, Document)>,
+        ids: ImportIds,
+    ) -> Result<Vec<(DocumentId, DocumentId)>, DBError> {
+        let _operation_lease = self.mutation_lease().await?;
+        let guard = self.cancel_guard("Collection::import_stream");
+        let rt = self.import_batch_impl(docs, ids).await;
+        guard.disarm();
+        rt
+    }
+
+    async fn import_batch_impl(
+        &self,
+        docs: Vec<(DocumentId, Document)>,
+        ids: ImportIds,
+    ) -> Result<Vec<(DocumentId, DocumentId)>, DBError> {
+        // Ids are allocated exactly like `add_impl`; an id taken by a batch
+        // that fails later is skipped forever.
+        let mut id_map = Vec::with_capacity(docs.len());
+        let mut batch = Vec::with_capacity(docs.len());
+        for (source_id, mut doc) in docs {
+            let id = match ids {
+                ImportIds::Remap => self.max_document_id.fetch_add(1, Ordering::Acquire) + 1,
+                ImportIds::Preserve => {
+                    if self
+                        .max_document_id
+                        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |max| {
+                            (source_id > max).then_some(source_id)
+                        })
+                        .is_err()
+                    {
+                        return Err(DBError::AlreadyExists {
+                            name: self.name.clone(),
+                            path: RecordKey::Document(source_id).path(),
+                            source: "preserved id is not above the collection's allocated ids"
+                                .into(),
+                            _id: source_id,
+                        });
+                    }
+                    source_id
+                }
+            };
+            doc.set_id(id);
+            id_map.push((source_id, id));
+            batch.push((id, doc));
+        }
+        let Some(max_id) = batch.iter().map(|(id, _)| *id).max() else {
+            return Ok(id_map);
+        };
+        self.ensure_allocation_watermark(max_id).await?;
+
+        let now_ms = unix_ms();
+        let rollback_indexes = || {
+            for (id, doc) in &batch {
+                self.remove_document_from_indexes(*id, doc, now_ms);
+            }
+        };
+        let rt = batch
+            .iter()
+            .try_for_each(|(id, doc)| self.insert_document_into_indexes(*id, doc, now_ms));
+        if let Err(err) = rt {
+            rollback_indexes();
+            return Err(err);
+        }
+
+        let results: Vec<Result<ObjectVersion, DBError>> = futures::stream::iter(&batch)
+            .map(|(id, doc)| self.documents.create(RecordKey::Document(*id), doc))
+            .buffered(8)
+            .collect()
+            .await;
+        if let Some(pos) = results.iter().position(|rt| rt.is_err()) {
+            rollback_indexes();
+            // Same cleanup as a failed `add`: delete every object whose PUT
+            // may have committed, but never one another writer owns.
+            for ((id, _), rt) in batch.iter().zip(&results) {
+                if matches!(rt, Err(DBError::AlreadyExists { .. })) {
+                    continue;
+                }
+                match self.documents.delete(RecordKey::Document(*id)).await {
+                    Ok(()) | Err(DBError::NotFound { .. }) => {}
+                    Err(delete_err) => {
+                        log::error!(
+                            action = "Collection::import_stream",
+                            collection = self.name,
+                            doc_id = id;
+                            "Failed to clean up document after failed import: {delete_err:?}",
+                        );
+                        self.poison("Collection::import_stream");
+                    }
+                }
+            }
+            let mut results = results;
+            return Err(results.swap_remove(pos).unwrap_err());
+        }
+
+        {
+            let mut doc_ids = self.doc_ids.write();
+            let mut doc_ids_index = self.doc_ids_index.write();
+            for (id, _) in &batch {
+                doc_ids.add(*id);
+                doc_ids_index.insert(*id);
+            }
+        }
+        self.update_metadata(|meta| {
+            meta.stats.last_inserted = now_ms;
+            meta.stats.version += 1;
+            meta.stats.insert_count += batch.len() as u64;
+        });
+        Ok(id_map)
+    }
+
     /// Updates an existing document with new field values.
     ///
     /// Concurrent `update` / `remove` calls for the same document id are
@@ -3100,8 +4600,8 @@ impl Collection {
         let _doc_guard = self.doc_lock(id).lock().await;
 
         let (doc, ver) = self
-            .storage
-            .get::<DocumentOwned>(&Self::doc_path(id))
+            .documents
+            .get::<DocumentOwned>(RecordKey::Document(id))
             .await?;
         let mut doc = Document::try_from_doc(self.schema(), doc)?;
         let old_doc = doc.clone();
@@ -3135,19 +4635,32 @@ impl Collection {
         #[allow(clippy::mutable_key_type)]
         let mut bm25_removed: FxHashMap<&BM25, (u64, Cow<str>)> = FxHashMap::default();
         #[allow(clippy::mutable_key_type)]
-        let mut hnsw_removed: FxHashMap<&Hnsw, (u64, Cow<Vector>)> = FxHashMap::default();
+        let mut hnsw_removed: FxHashMap<&Hnsw, (u64, Vec<Vector>)> = FxHashMap::default();
+        #[allow(clippy::mutable_key_type)]
+        let mut sparse_inserted: FxHashMap<&Sparse, SparseVector> = FxHashMap::default();
+        #[allow(clippy::mutable_key_type)]
+        let mut sparse_removed: FxHashMap<&Sparse, SparseVector> = FxHashMap::default();
 
         // update the indexes
         let rt: Result<(), DBError> = (|| {
             for index in &self.btree_indexes {
                 let fields = index.virtual_field();
-                if fields_keys.iter().any(|v| fields.contains(v)) {
+                // A predicate may read fields outside the index's own.
+                let filter_fields = self
+                    .btree_index_options
+                    .get(index.name())
+                    .map(|options| options.filter_fields())
+                    .unwrap_or_default();
+                if fields
+                    .iter()
+                    .map(|f| root_field(f))
+                    .chain(filter_fields)
+                    .any(|f| fields_keys.contains(f))
+                {
                     let old_value = self
-                        .index_hooks
                         .btree_index_value(index, &old_doc)
                         .unwrap_or(Cow::Owned(FieldValue::Null));
                     let new_value = self
-                        .index_hooks
                         .btree_index_value(index, &doc)
                         .unwrap_or(Cow::Owned(FieldValue::Null));
 
@@ -3174,14 +4687,30 @@ impl Collection {
             for index in &self.hnsw_indexes {
                 let field_name = index.field_name();
                 if fields_keys.contains(field_name) {
-                    if let Some(vector) = self.index_hooks.hnsw_index_value(index, &old_doc) {
+                    let old_vectors = self.hnsw_index_vectors(index, &old_doc);
+                    if !old_vectors.is_empty() {
                         index.remove(id, now_ms);
-                        hnsw_removed.insert(index, (id, vector));
+                        hnsw_removed.insert(index, (id, old_vectors));
                     }
 
-                    if let Some(vector) = self.index_hooks.hnsw_index_value(index, &doc) {
+                    let vectors = self.hnsw_index_vectors(index, &doc);
+                    if !vectors.is_empty() {
                         hnsw_inserted.insert(index, id);
-                        index.insert(id, vector.into_owned(), now_ms)?;
+                        index.insert_vectors(id, vectors, now_ms)?;
+                    }
+                }
+            }
+
+            for index in &self.sparse_indexes {
+                if fields_keys.contains(index.field_name()) {
+                    if let Some(vector) = self.index_hooks.sparse_index_value(index, &old_doc) {
+                        index.remove(id, &vector, now_ms);
+                        sparse_removed.insert(index, vector);
+                    }
+
+                    if let Some(vector) = self.index_hooks.sparse_index_value(index, &doc) {
+                        index.insert(id, &vector, now_ms)?;
+                        sparse_inserted.insert(index, vector);
                     }
                 }
             }
@@ -3202,6 +4731,9 @@ impl Collection {
             for (k, v) in hnsw_inserted {
                 k.remove(v, now_ms);
             }
+            for (k, v) in sparse_inserted {
+                k.remove(id, &v, now_ms);
+            }
 
             for (k, v) in btree_updated {
                 if let Err(err) = k.update(id, &v.1, &v.0, now_ms) {
@@ -3229,7 +4761,7 @@ impl Collection {
                 }
             }
             for (k, v) in hnsw_removed {
-                if let Err(err) = k.insert(v.0, v.1.to_vec(), now_ms) {
+                if let Err(err) = k.insert_vectors(v.0, v.1, now_ms) {
                     restored = false;
                     log::error!(
                         action = "Collection::update",
@@ -3240,6 +4772,18 @@ impl Collection {
                     );
                 }
             }
+            for (k, v) in sparse_removed {
+                if let Err(err) = k.insert(id, &v, now_ms) {
+                    restored = false;
+                    log::error!(
+                        action = "Collection::update",
+                        collection = self.name,
+                        doc_id = id,
+                        index = k.name();
+                        "Failed to restore sparse index during update rollback: {err:?}",
+                    );
+                }
+            }
             restored
         };
 
@@ -3255,8 +4799,11 @@ impl Collection {
         }
 
         // persist the updated document with update version
-        let path = Self::doc_path(id);
-        if let Err(err) = self.storage.put(&path, &doc, Some(ver)).await {
+        if let Err(err) = self
+            .documents
+            .put(RecordKey::Document(id), &doc, Some(ver))
+            .await
+        {
             let _ = rollback_indexes();
             // The PUT outcome is unknown: the new document may be durable
             // while memory was just rolled back. The retained intent plus a
@@ -3300,14 +4847,30 @@ impl Collection {
     /// - Any index update fails
     /// - Storage operations fail
     pub async fn remove(&self, id: DocumentId) -> Result<Option<Document>, DBError> {
+        self.remove_if(id, |_| true).await
+    }
+
+    /// Removes a document like [`Collection::remove`] if `predicate` holds
+    /// for its current contents, evaluated under the document's lock so a
+    /// concurrent update cannot slip in between. Returns `Ok(None)` if the
+    /// document does not exist or `predicate` rejects it.
+    async fn remove_if(
+        &self,
+        id: DocumentId,
+        predicate: impl FnOnce(&Document) -> bool,
+    ) -> Result<Option<Document>, DBError> {
         let _operation_lease = self.mutation_lease().await?;
         let guard = self.cancel_guard("Collection::remove");
-        let rt = self.remove_impl(id).await;
+        let rt = self.remove_impl(id, predicate).await;
         guard.disarm();
         rt
     }
 
-    async fn remove_impl(&self, id: DocumentId) -> Result<Option<Document>, DBError> {
+    async fn remove_impl(
+        &self,
+        id: DocumentId,
+        predicate: impl FnOnce(&Document) -> bool,
+    ) -> Result<Option<Document>, DBError> {
         // Membership check is non-authoritative; the bitmap mutation below
         // serializes concurrent removes and is the source of truth.
         if !self.doc_ids.read().contains(id) {
@@ -3318,7 +4881,7 @@ impl Collection {
         let _doc_guard = self.doc_lock(id).lock().await;
 
         let now_ms = unix_ms();
-        let path = Self::doc_path(id);
+        let key = RecordKey::Document(id);
 
         // Best-effort fetch to drive index cleanup. If the document has already
         // been deleted from storage we still want to clear the in-memory state
@@ -3328,7 +4891,7 @@ impl Collection {
         // it is the only in-band way out of that state — so it is deleted
         // with an id sweep instead of value-keyed index cleanup.
         let mut undecodable = false;
-        let doc = match self.storage.get::<DocumentOwned>(&path).await {
+        let doc = match self.documents.get::<DocumentOwned>(key).await {
             Ok((doc, _)) => match Document::try_from_doc(self.schema(), doc) {
                 Ok(doc) => Some(doc),
                 Err(err) => {
@@ -3354,6 +4917,11 @@ impl Collection {
                 return Err(err);
             }
         };
+        if let Some(doc) = &doc
+            && !predicate(doc)
+        {
+            return Ok(None);
+        }
 
         if let Some(doc) = &doc {
             self.record_mutation_intent(id, Some(doc), None).await?;
@@ -3372,14 +4940,16 @@ impl Collection {
         #[allow(clippy::mutable_key_type)]
         let mut bm25_removed: FxHashMap<&BM25, (u64, Cow<str>)> = FxHashMap::default();
         #[allow(clippy::mutable_key_type)]
-        let mut hnsw_removed: FxHashMap<&Hnsw, (u64, Cow<Vector>)> = FxHashMap::default();
+        let mut hnsw_removed: FxHashMap<&Hnsw, (u64, Vec<Vector>)> = FxHashMap::default();
+        #[allow(clippy::mutable_key_type)]
+        let mut sparse_removed: FxHashMap<&Sparse, SparseVector> = FxHashMap::default();
 
         // Phase 1: remove index entries while we still hold the original
         // contents. Record actual removals so a storage delete failure can
         // restore the in-memory indexes before returning.
         if let Some(doc) = &doc {
             for index in &self.btree_indexes {
-                if let Some(fv) = self.index_hooks.btree_index_value(index, doc)
+                if let Some(fv) = self.btree_index_value(index, doc)
                     && fv.as_ref() != &FieldValue::Null
                     && index.remove(id, &fv, now_ms)
                 {
@@ -3396,13 +4966,20 @@ impl Collection {
             }
 
             for index in &self.hnsw_indexes {
-                if let Some(vector) = self.index_hooks.hnsw_index_value(index, doc)
-                    && index.remove(id, now_ms)
-                {
-                    hnsw_removed.insert(index, (id, vector));
+                let vectors = self.hnsw_index_vectors(index, doc);
+                if !vectors.is_empty() && index.remove(id, now_ms) {
+                    hnsw_removed.insert(index, (id, vectors));
                 }
             }
-        }
+
+            for index in &self.sparse_indexes {
+                if let Some(vector) = self.index_hooks.sparse_index_value(index, doc)
+                    && index.remove(id, &vector, now_ms)
+                {
+                    sparse_removed.insert(index, vector);
+                }
+            }
+        }
 
         let rollback_indexes = || {
             for (index, value) in btree_removed {
@@ -3411,8 +4988,11 @@ impl Collection {
             for (index, (id, text)) in bm25_removed {
                 let _ = index.insert(id, &text, now_ms);
             }
-            for (index, (id, vector)) in hnsw_removed {
-                let _ = index.insert(id, vector.to_vec(), now_ms);
+            for (index, (id, vectors)) in hnsw_removed {
+                let _ = index.insert_vectors(id, vectors, now_ms);
+            }
+            for (index, vector) in sparse_removed {
+                let _ = index.insert(id, &vector, now_ms);
             }
         };
 
@@ -3420,7 +5000,7 @@ impl Collection {
         // failure here keeps the document visible (and recoverable) rather
         // than producing an orphan beyond the auto-repair scan window.
         if (doc.is_some() || undecodable)
-            && let Err(err) = self.storage.delete(&path).await
+            && let Err(err) = self.documents.delete(key).await
         {
             rollback_indexes();
             log::error!(
@@ -3459,6 +5039,169 @@ impl Collection {
         Ok(doc)
     }
 
+    /// Acquires the mutation lease a database write batch holds on this
+    /// collection from preparation until the batch is applied.
+    pub(crate) async fn batch_lease(
+        &self,
+    ) -> Result<tokio::sync::OwnedRwLockReadGuard<()>, DBError> {
+        self.mutation_lease().await
+    }
+
+    /// Validates this collection's operations of a write batch, journals
+    /// them as mutation intents and applies them to the in-memory indexes.
+    ///
+    /// Nothing is visible to readers of the document objects until the batch
+    /// manifest is committed and [`PreparedBatch::apply`] runs, and the
+    /// index queries wait on the exclusive `batch_gate` the prepared batch
+    /// holds until then (or until it is rolled back). On error the
+    /// indexes are already restored; the intents written so far describe
+    /// unchanged documents and are retired by the next flush.
+    pub(crate) async fn prepare_batch(
+        &self,
+        ops: Vec<BatchOp>,
+    ) -> Result<PreparedBatch<'_>, DBError> {
+        let mut touched = BTreeSet::new();
+        for op in &ops {
+            if let BatchOp::Update(id, _) | BatchOp::Remove(id) = op
+                && !touched.insert(*id)
+            {
+                return Err(DBError::Generic {
+                    name: self.name.clone(),
+                    source: format!("Document with ID {id} is staged more than once in the batch")
+                        .into(),
+                });
+            }
+        }
+
+        // Stripes are locked in ascending order, so two batches (or a batch
+        // and a single-document update) can never wait on each other in a
+        // cycle.
+        let stripes: BTreeSet<usize> = touched
+            .iter()
+            .map(|id| (*id as usize) % Self::DOC_LOCK_STRIPES)
+            .collect();
+        let mut doc_guards = Vec::with_capacity(stripes.len());
+        for stripe in stripes {
+            doc_guards.push(self.doc_locks[stripe].lock().await);
+        }
+
+        let mut entries = Vec::with_capacity(ops.len());
+        for op in ops {
+            match op {
+                BatchOp::Add(mut doc) => {
+                    self.schema.validate(doc.fields())?;
+                    let id = self.max_document_id.fetch_add(1, Ordering::Acquire) + 1;
+                    doc.set_id(id);
+                    self.ensure_allocation_watermark(id).await?;
+                    entries.push(PreparedEntry {
+                        id,
+                        previous: None,
+                        proposed: Some(doc),
+                    });
+                }
+                BatchOp::Update(id, fields) => {
+                    if fields.is_empty() {
+                        return Err(DBError::Generic {
+                            name: self.name.clone(),
+                            source: "No fields to update".into(),
+                        });
+                    }
+                    let previous = self.fetch_batch_target(id).await?;
+                    let mut doc = previous.clone();
+                    for (field_name, fv) in fields {
+                        doc.set_field(&field_name, fv)?;
+                    }
+                    self.schema.validate(doc.fields())?;
+                    entries.push(PreparedEntry {
+                        id,
+                        previous: Some(previous),
+                        proposed: Some(doc),
+                    });
+                }
+                BatchOp::Remove(id) => {
+                    let previous = self.fetch_batch_target(id).await?;
+                    entries.push(PreparedEntry {
+                        id,
+                        previous: Some(previous),
+                        proposed: None,
+                    });
+                }
+            }
+        }
+
+        // The intents make every entry recoverable once the manifest commits:
+        // roll-forward rewrites the document objects and the reopen replay
+        // re-derives indexes and the id bitmap from them.
+        for entry in &entries {
+            self.record_mutation_intent(entry.id, entry.previous.as_ref(), entry.proposed.as_ref())
+                .await?;
+        }
+
+        let batch_guard = self.batch_gate.write().await;
+        let now_ms = unix_ms();
+        for (i, entry) in entries.iter().enumerate() {
+            if let Some(previous) = &entry.previous {
+                self.remove_document_from_indexes(entry.id, previous, now_ms);
+            }
+            if let Some(proposed) = &entry.proposed
+                && let Err(err) = self.insert_document_into_indexes(entry.id, proposed, now_ms)
+            {
+                self.rollback_batch_indexes(&entries[..=i], now_ms);
+                return Err(err);
+            }
+        }
+
+        Ok(PreparedBatch {
+            collection: self,
+            entries,
+            _doc_guards: doc_guards,
+            _batch_guard: batch_guard,
+        })
+    }
+
+    /// Loads the stored document an update or remove of a batch applies to.
+    async fn fetch_batch_target(&self, id: DocumentId) -> Result<Document, DBError> {
+        if !self.doc_ids.read().contains(id) {
+            return Err(DBError::NotFound {
+                name: "document".to_string(),
+                path: self.name.clone(),
+                source: format!("Document with ID {id} not found").into(),
+                _id: id,
+            });
+        }
+        let (doc, _) = self
+            .documents
+            .get::<DocumentOwned>(RecordKey::Document(id))
+            .await?;
+        Ok(Document::try_from_doc(self.schema(), doc)?)
+    }
+
+    /// Undoes the index changes of `entries`, newest first. A failed restore
+    /// leaves the indexes diverged from storage, so the handle is poisoned
+    /// and the reopen replay of the retained intents repairs them.
+    fn rollback_batch_indexes(&self, entries: &[PreparedEntry], now_ms: u64) {
+        let mut restored = true;
+        for entry in entries.iter().rev() {
+            if let Some(proposed) = &entry.proposed {
+                self.remove_document_from_indexes(entry.id, proposed, now_ms);
+            }
+            if let Some(previous) = &entry.previous
+                && let Err(err) = self.insert_document_into_indexes(entry.id, previous, now_ms)
+            {
+                restored = false;
+                log::error!(
+                    action = "AndaDB::write_batch",
+                    collection = self.name,
+                    doc_id = entry.id;
+                    "Failed to restore indexes during batch rollback: {err:?}",
+                );
+            }
+        }
+        if !restored {
+            self.poison("AndaDB::write_batch");
+        }
+    }
+
     /// Searches for documents matching the given query and returns them.
     ///
     /// # Arguments
@@ -3467,13 +5210,177 @@ impl Collection {
     /// # Returns
     /// A vector of matching documents, or an error if the search fails
     pub async fn search(&self, query: Query) -> Result<Vec<Document>, DBError> {
-        let ids = self.search_ids(query).await?;
-        let schema = self.schema();
+        Ok(self.search_page(query).await?.items)
+    }
+
+    /// Searches for documents matching the given query and returns one page
+    /// of them.
+    ///
+    /// Honors every [`Query`] setting like [`Collection::search`]. When
+    /// `Query::order_by` is set and more results follow, the page also
+    /// carries the cursor to pass as `Query::cursor` for the next page.
+    ///
+    /// # Arguments
+    /// * `query` - The search query parameters
+    ///
+    /// # Returns
+    /// A page of matching documents, or an error if the search fails
+    pub async fn search_page(&self, query: Query) -> Result<SearchPage<Document>, DBError> {
+        if query.search.as_ref().is_some_and(|s| s.rerank.is_some()) {
+            let items = self.search_reranked(query).await?;
+            return Ok(SearchPage {
+                items: items.into_iter().map(|(doc, _)| doc).collect(),
+                cursor: None,
+                truncated: false,
+            });
+        }
+
+        let schema = match &query.fields {
+            Some(fields) => Arc::new(self.schema().project(fields)?),
+            None => self.schema(),
+        };
+        let SearchPage {
+            items: ids,
+            cursor,
+            truncated,
+        } = self.search_ids_page(query).await?;
+        Ok(SearchPage {
+            items: self.load_search_documents(schema, ids).await?,
+            cursor,
+            truncated,
+        })
+    }
+
+    /// Searches for documents matching the given query and returns them with
+    /// their fused relevance score.
+    ///
+    /// Honors every [`Query`] setting like [`Collection::search`]. The score
+    /// is the one [`Search::reranker`] assigned: an RRF score by default, or
+    /// the weighted sum of normalized scores of a score-based fusion, or the
+    /// reranker's score with [`Search::rerank`]. Without [`Query::search`]
+    /// every score is 0.
+    ///
+    /// # Arguments
+    /// * `query` - The search query parameters
+    ///
+    /// # Returns
+    /// A vector of `(document, score)` pairs, or an error if the search fails
+    pub async fn search_with_scores(&self, query: Query) -> Result<Vec<(Document, f32)>, DBError> {
+        if query.search.as_ref().is_some_and(|s| s.rerank.is_some()) {
+            return self.search_reranked(query).await;
+        }
+
+        let schema = match &query.fields {
+            Some(fields) => Arc::new(self.schema().project(fields)?),
+            None => self.schema(),
+        };
+        let mut scores = FxHashMap::default();
+        let ids = self.search_ids_page_with(query, &mut scores).await?.items;
+        let docs = self.load_search_documents(schema, ids).await?;
+        Ok(docs
+            .into_iter()
+            .map(|doc| {
+                let score = scores.get(&doc.id()).copied().unwrap_or_default();
+                (doc, score)
+            })
+            .collect())
+    }
+
+    /// Searches for documents matching the given query and returns them with
+    /// their fused relevance score and, when [`Search::highlight`] is set,
+    /// the highlighted snippets of their text fields.
+    ///
+    /// Documents and scores are the ones of
+    /// [`Collection::search_with_scores`]. Snippets are extracted from the
+    /// stored field values, tokenized with the tokenizer of the BM25 index
+    /// the field belongs to, so they match exactly the tokens that index
+    /// matches. A field spanning several text values (an array or a map)
+    /// is highlighted on its values joined with newlines, the text the
+    /// index was given; byte ranges refer to that text. Fields left out by
+    /// `Query::fields` have no snippets.
+    ///
+    /// # Arguments
+    /// * `query` - The search query parameters
+    ///
+    /// # Returns
+    /// A vector of search hits, or an error if the search fails
+    pub async fn search_hits(&self, query: Query) -> Result<Vec<SearchHit<Document>>, DBError> {
+        let highlighters = match &query.search {
+            Some(search) => self.highlighters(search),
+            None => Vec::new(),
+        };
+        let highlight = query.search.as_ref().is_some_and(|s| s.highlight.is_some());
+        let docs = self.search_with_scores(query).await?;
+        Ok(docs
+            .into_iter()
+            .map(|(doc, score)| {
+                let highlight = highlight.then(|| {
+                    let mut highlights = Highlights::new();
+                    for (field, highlighter) in &highlighters {
+                        let snippets = virtual_searchable_text(&[doc.get_field(field)])
+                            .map(|text| highlighter.highlight(&text))
+                            .unwrap_or_default();
+                        if !snippets.is_empty() {
+                            highlights.insert(field.clone(), snippets);
+                        }
+                    }
+                    highlights
+                });
+                SearchHit {
+                    doc,
+                    score,
+                    highlight,
+                }
+            })
+            .collect())
+    }
+
+    /// Returns the highlighter of every field [`Search::highlight`] selects,
+    /// created by the first BM25 index the field belongs to.
+    fn highlighters(&self, search: &Search) -> Vec<(String, Highlighter<TokenizerChain>)> {
+        let (Some(highlight), Some(text)) = (&search.highlight, &search.text) else {
+            return Vec::new();
+        };
+
+        let mut highlighters: Vec<(String, Highlighter<TokenizerChain>)> = Vec::new();
+        for index in &self.bm25_indexes {
+            let fields: Vec<&String> = index
+                .virtual_field()
+                .iter()
+                .filter(|field| highlight.fields.is_empty() || highlight.fields.contains(field))
+                .filter(|field| !highlighters.iter().any(|(name, _)| name == *field))
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            let highlighter = index.highlighter(text, search.logical_search, highlight.options);
+            for field in fields {
+                highlighters.push((field.clone(), highlighter.clone()));
+            }
+        }
+        highlighters
+    }
+
+    /// Loads the documents of search results in order, skipping the ones
+    /// that no longer decode or have no backing object.
+    async fn load_search_documents(
+        &self,
+        schema: Arc<Schema>,
+        ids: Vec<DocumentId>,
+    ) -> Result<Vec<Document>, DBError> {
         let mut docs = Vec::with_capacity(ids.len());
         let mut stream = futures::stream::iter(ids)
             .map(|id| {
-                let storage = self.storage.clone();
-                async move { (id, storage.get::<DocumentOwned>(&Self::doc_path(id)).await) }
+                let documents = self.documents.clone();
+                async move {
+                    (
+                        id,
+                        documents
+                            .get::<DocumentOwned>(RecordKey::Document(id))
+                            .await,
+                    )
+                }
             })
             .buffered(8);
         while let Some((id, result)) = stream.next().await {
@@ -3625,6 +5532,10 @@ impl Collection {
         for index in &self.bm25_indexes {
             index.purge_ids(dead_ids, now_ms);
         }
+
+        for index in &self.sparse_indexes {
+            index.purge_ids(dead_ids, now_ms);
+        }
     }
 
     /// Searches for documents matching the given query and deserializes them into the specified type.
@@ -3641,12 +5552,34 @@ impl Collection {
     where
         T: DeserializeOwned,
     {
-        let docs = self.search(query).await?;
-        let mut rt = Vec::with_capacity(docs.len());
-        for doc in docs {
+        Ok(self.search_page_as(query).await?.items)
+    }
+
+    /// Searches for one page of documents like [`Collection::search_page`]
+    /// and deserializes them into the specified type.
+    ///
+    /// # Type Parameters
+    /// * `T` - The type to deserialize documents into
+    ///
+    /// # Arguments
+    /// * `query` - The search query parameters
+    ///
+    /// # Returns
+    /// A page of deserialized objects of type T, or an error if the search or deserialization fails
+    pub async fn search_page_as<T>(&self, query: Query) -> Result<SearchPage<T>, DBError>
+    where
+        T: DeserializeOwned,
+    {
+        let page = self.search_page(query).await?;
+        let mut rt = Vec::with_capacity(page.items.len());
+        for doc in page.items {
             rt.push(doc.try_into()?);
         }
-        Ok(rt)
+        Ok(SearchPage {
+            items: rt,
+            cursor: page.cursor,
+            truncated: page.truncated,
+        })
     }
 
     /// Searches for documents matching the given query and returns only their IDs.
@@ -3660,7 +5593,24 @@ impl Collection {
     /// an empty result (consistent with the underlying indexes' `top_k = 0`
     /// behavior). Each search index is asked for up to `limit * 10`
     /// candidates before reranking and filtering, capped at 4096 to bound
-    /// the per-query search breadth.
+    /// the per-query search breadth. With `Query::order_by`, it is asked for
+    /// [`Collection::MAX_ORDERED_SEARCH_HITS`] candidates instead, so the
+    /// ordered results do not depend on the page size.
+    ///
+    /// With a vector search, `Query::filter` is evaluated first and the HNSW
+    /// traversal only collects matching documents (see
+    /// [`HnswIndex::search_filtered`](anda_db_hnsw::HnswIndex::search_filtered)),
+    /// so a selective filter does not starve the result. The candidates of a
+    /// quantized HNSW index configured with `exact_rerank` are re-scored by
+    /// the exact distance to the document vectors before fusion.
+    ///
+    /// # Ordering
+    ///
+    /// Results are ordered by relevance for a search and by id for a plain
+    /// filter. With `Query::order_by` they are ordered by the values of that
+    /// B-Tree index instead, ties broken by id in the same direction, and
+    /// `Query::cursor` resumes after the last result of a previous page (see
+    /// [`Collection::search_page`]).
     ///
     /// # Arguments
     /// * `query` - The search query parameters
@@ -3668,28 +5618,295 @@ impl Collection {
     /// # Returns
     /// A vector of matching document IDs, or an error if the search fails
     pub async fn search_ids(&self, query: Query) -> Result<Vec<DocumentId>, DBError> {
+        if query.search.as_ref().is_some_and(|s| s.rerank.is_some()) {
+            let items = self.search_reranked(query).await?;
+            return Ok(items.into_iter().map(|(doc, _)| doc.id()).collect());
+        }
+        Ok(self.search_ids_page(query).await?.items)
+    }
+
+    /// Runs a search with a [`Search::rerank`] stage: retrieves and fuses
+    /// the candidate pool, scores the candidates with the registered
+    /// reranker, and returns the best `Query::limit` of them with their
+    /// reranker scores.
+    ///
+    /// Candidates with equal scores keep their retrieval order.
+    async fn search_reranked(&self, mut query: Query) -> Result<Vec<(Document, f32)>, DBError> {
+        let generic = |source: String| DBError::Generic {
+            name: self.name.clone(),
+            source: source.into(),
+        };
+        let Some(search) = &query.search else {
+            return Ok(Vec::new());
+        };
+        let Some(options) = search.rerank.clone() else {
+            return Ok(Vec::new());
+        };
+        if query.order_by.is_some() || query.cursor.is_some() {
+            return Err(generic(
+                "search rerank cannot be combined with order_by".to_string(),
+            ));
+        }
+        let reranker = self
+            .rerankers
+            .get(&options.name)
+            .cloned()
+            .ok_or_else(|| generic(format!("reranker {:?} is not registered", options.name)))?;
+        let text = options
+            .query
+            .or_else(|| search.text.clone())
+            .ok_or_else(|| generic("search rerank requires a query text".to_string()))?;
+        let projection = match query.fields.take() {
+            Some(fields) => Some(self.schema().project(&fields)?),
+            None => None,
+        };
+
+        let limit = query.limit.unwrap_or(10).min(Self::MAX_SEARCH_LIMIT);
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let candidates = options
+            .candidates
+            .unwrap_or(Self::DEFAULT_RERANK_CANDIDATES)
+            .clamp(limit, Self::MAX_SEARCH_LIMIT);
+        query.limit = Some(candidates);
+        let ids = self.search_ids_page(query).await?.items;
+        let docs = self.load_search_documents(self.schema(), ids).await?;
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let scores = reranker
+            .rerank(&text, &docs)
+            .await
+            .map_err(|err| generic(format!("reranker {:?} failed: {err}", options.name)))?;
+        if scores.len() != docs.len() || scores.iter().any(|score| !score.is_finite()) {
+            return Err(generic(format!(
+                "reranker {:?} must return one finite score per document, got {} scores for {} documents",
+                options.name,
+                scores.len(),
+                docs.len()
+            )));
+        }
+
+        let mut reranked: Vec<(Document, f32)> = docs.into_iter().zip(scores).collect();
+        // Stable sort: ties keep the retrieval order.
+        reranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        reranked.truncate(limit);
+        if let Some(projection) = projection {
+            let dropped: Vec<String> = self
+                .schema()
+                .iter()
+                .filter(|field| projection.get_field(field.name()).is_none())
+                .map(|field| field.name().to_string())
+                .collect();
+            for (doc, _) in &mut reranked {
+                for name in &dropped {
+                    doc.remove_field(name);
+                }
+            }
+        }
+        Ok(reranked)
+    }
+
+    /// Re-scores the nearest `pool` hits of a quantized HNSW index by the
+    /// exact distance to the vectors stored in the documents, and sorts them
+    /// by it. The hits past `pool` follow with their approximate distances.
+    ///
+    /// Only the indexed field of each document is kept for
+    /// [`IndexHooks::hnsw_index_values`]. A hit whose vector cannot be
+    /// decoded keeps its approximate distance; a dead id is dropped.
+    async fn exact_rerank(
+        &self,
+        index: &Hnsw,
+        queries: &[&[f32]],
+        mut hits: Vec<(DocumentId, f32)>,
+        pool: usize,
+    ) -> Result<Vec<(DocumentId, f32)>, DBError> {
+        let rest = hits.split_off(pool.min(hits.len()));
+        let schema = self.schema();
+        let field = index.field_name();
+        let idx = schema.get_field(field).map(|f| f.idx());
+        let mut reranked = Vec::with_capacity(hits.len() + rest.len());
+        let mut stream = futures::stream::iter(hits)
+            .map(|(id, approx)| {
+                let documents = self.documents.clone();
+                async move {
+                    (
+                        id,
+                        approx,
+                        documents
+                            .get::<DocumentOwned>(RecordKey::Document(id))
+                            .await,
+                    )
+                }
+            })
+            .buffered(8);
+        while let Some((id, approx, result)) = stream.next().await {
+            let mut doc = match result {
+                Ok((doc, _)) => doc,
+                Err(DBError::NotFound { .. }) => continue,
+                Err(err) => return Err(err),
+            };
+            let mut projected = Document::new(schema.clone());
+            let value = idx.and_then(|idx| doc.fields.remove(&idx));
+            let distance = match value.map(|value| projected.set_field(field, value)) {
+                Some(Ok(_)) => index
+                    .exact_score(
+                        queries,
+                        &self.index_hooks.hnsw_index_values(index, &projected),
+                    )?
+                    .unwrap_or(approx),
+                _ => approx,
+            };
+            reranked.push((id, distance));
+        }
+        reranked.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        reranked.extend(rest);
+        Ok(reranked)
+    }
+
+    /// Returns the query vectors of a search: the `vector`, or the vectors of
+    /// a multi-vector `vectors` query.
+    fn vector_queries<'a>(&self, params: &'a Search) -> Result<Vec<&'a [f32]>, DBError> {
+        let queries: Vec<&[f32]> = match (&params.vector, &params.vectors) {
+            (None, None) => return Ok(Vec::new()),
+            (Some(vector), None) => vec![vector],
+            (None, Some(vectors)) => vectors.iter().map(Vec::as_slice).collect(),
+            (Some(_), Some(_)) => {
+                return Err(DBError::Generic {
+                    name: self.name.clone(),
+                    source: "search vector and vectors are mutually exclusive".into(),
+                });
+            }
+        };
+        match queries.first() {
+            None => Err(DBError::Generic {
+                name: self.name.clone(),
+                source: "search vectors must not be empty".into(),
+            }),
+            Some(first) if queries.iter().any(|query| query.len() != first.len()) => {
+                Err(DBError::Generic {
+                    name: self.name.clone(),
+                    source: "search vectors must have the same dimension".into(),
+                })
+            }
+            Some(_) => Ok(queries),
+        }
+    }
+
+    async fn search_ids_page(&self, query: Query) -> Result<SearchPage<DocumentId>, DBError> {
+        self.search_ids_page_with(query, &mut FxHashMap::default())
+            .await
+    }
+
+    /// Body of [`Self::search_ids_page`]; also records the fused score of
+    /// every search candidate into `scores`.
+    async fn search_ids_page_with(
+        &self,
+        query: Query,
+        scores: &mut FxHashMap<DocumentId, f32>,
+    ) -> Result<SearchPage<DocumentId>, DBError> {
         query
             .validate_complexity()
             .map_err(|source| DBError::Generic {
                 name: self.name.clone(),
                 source: source.into(),
             })?;
+        let order_by = match query.order_by {
+            Some(order_by) => {
+                let index = self.order_index(&order_by.field)?;
+                let after = self.decode_order_cursor(&order_by, &query.cursor)?;
+                Some((index, order_by.descending, after))
+            }
+            None if query.cursor.is_some() => {
+                return Err(DBError::Generic {
+                    name: self.name.clone(),
+                    source: "query cursor requires order_by".into(),
+                });
+            }
+            None => None,
+        };
 
+        let _batch_lease = self.batch_gate.read().await;
         self.search_count.fetch_add(1, Ordering::Relaxed);
         let limit = query.limit.unwrap_or(10).min(Self::MAX_SEARCH_LIMIT);
         if limit == 0 {
             // A zero limit previously behaved inconsistently: empty for
             // search queries (top_k = 0) but unlimited for filter-only
             // queries. Normalize to "no results" for both.
-            return Ok(Vec::new());
+            return Ok(SearchPage::default());
         }
 
-        let top_k = (limit * 10).min(4096);
+        let top_k = if order_by.is_some() {
+            Self::MAX_ORDERED_SEARCH_HITS
+        } else {
+            (limit * 10).min(4096)
+        };
         let mut candidates = Vec::new();
-        let mut result = Vec::new();
+        let mut truncated = false;
+        let mut filter = query.filter;
 
         if let Some(params) = query.search {
-            let mut results: Vec<Vec<u64>> = Vec::new();
+            let mut results: Vec<RankedList> = Vec::new();
+            let fusion = params
+                .resolve_fusion()
+                .and_then(|fusion| {
+                    params
+                        .score_cutoffs
+                        .as_ref()
+                        .map_or(Ok(()), ScoreCutoffs::validate)?;
+                    params.mmr.as_ref().map_or(Ok(()), MmrOptions::validate)?;
+                    Ok(fusion)
+                })
+                .map_err(|source| DBError::Generic {
+                    name: self.name.clone(),
+                    source: source.into(),
+                })?;
+            self.validate_field_boosts(&params)
+                .and_then(|_| self.validate_highlight(&params))
+                .map_err(|source| DBError::Generic {
+                    name: self.name.clone(),
+                    source: source.into(),
+                })?;
+            let queries = self.vector_queries(&params)?;
+            if params.mmr.is_some() && queries.is_empty() {
+                return Err(DBError::Generic {
+                    name: self.name.clone(),
+                    source: "mmr requires a vector query".into(),
+                });
+            }
+            // MMR selects `limit` documents, which would make the ordered
+            // results depend on the page size.
+            if params.mmr.is_some() && order_by.is_some() {
+                return Err(DBError::Generic {
+                    name: self.name.clone(),
+                    source: "mmr cannot be combined with order_by".into(),
+                });
+            }
+            // MMR re-selects `limit` documents from a larger pool of nearest
+            // neighbors.
+            let vector_top_k = match &params.mmr {
+                Some(mmr) => mmr
+                    .candidates
+                    .unwrap_or(top_k)
+                    .clamp(limit, Self::MAX_SEARCH_LIMIT),
+                None => top_k,
+            };
+            // A vector search evaluates a selective filter up front and hands
+            // the matching ids to the HNSW traversal, so it still yields
+            // `top_k` nearest matches instead of the few that survive
+            // post-filtering an unfiltered beam. A broad filter stays a
+            // post-filter: most of any beam matches it anyway.
+            let allowed = match &filter {
+                Some(f) if !queries.is_empty() => {
+                    self.selective_filter_ids(f, Self::MAX_FILTERED_SEARCH_IDS)?
+                }
+                _ => None,
+            };
+            if allowed.is_some() {
+                filter = None;
+            }
 
             if let Some(ref text) = params.text {
                 if self.bm25_indexes.is_empty() {
@@ -3699,28 +5916,79 @@ impl Collection {
                     });
                 }
                 for index in self.bm25_indexes.iter() {
+                    let bm25_params = index
+                        .query_params(params.bm25_params.as_ref(), params.field_boosts.as_ref());
                     let rt = if params.logical_search {
-                        index.try_search_advanced(text, top_k, params.bm25_params.clone())?
+                        index.try_search_advanced(text, top_k, bm25_params)?
                     } else {
-                        index.search(text, top_k, params.bm25_params.clone())
+                        index.search(text, top_k, bm25_params)
                     };
-                    results.push(rt.into_iter().map(|r| r.0).collect());
+                    results.push(RankedList {
+                        source: RankSource::Text,
+                        hits: rt,
+                    });
+                }
+            }
+
+            if let Some(ref sparse_vector) = params.sparse_vector {
+                if self.sparse_indexes.is_empty() {
+                    return Err(DBError::Index {
+                        name: self.name.clone(),
+                        source: "sparse vector search requires a sparse index, but none exists"
+                            .into(),
+                    });
+                }
+                for index in self.sparse_indexes.iter() {
+                    results.push(RankedList {
+                        source: RankSource::Sparse,
+                        hits: index.search(sparse_vector, top_k),
+                    });
                 }
             }
 
-            if let Some(ref vector) = params.vector {
+            if let Some(dimension) = queries.first().map(|query| query.len()) {
                 // Only query the HNSW indexes whose dimension matches the
                 // query vector: with multiple vector indexes of different
                 // dimensions, failing the whole query on the first mismatch
                 // would make vector search permanently unusable.
                 let mut searched = false;
                 for index in self.hnsw_indexes.iter() {
-                    if index.dimension() != vector.len() {
+                    if index.dimension() != dimension {
                         continue;
                     }
                     searched = true;
-                    let rt = index.try_search(vector, top_k)?;
-                    results.push(rt.into_iter().map(|r| r.0).collect());
+                    let mut rt = match (queries.as_slice(), &allowed) {
+                        ([query], Some(allowed)) => {
+                            index.try_search_filtered(query, vector_top_k, allowed)?
+                        }
+                        ([query], None) => index.try_search(query, vector_top_k)?,
+                        (queries, allowed) => {
+                            index.try_search_max_sim(queries, vector_top_k, allowed.as_ref())?
+                        }
+                    };
+                    if index.exact_rerank() {
+                        // MMR re-selects from its whole candidate pool.
+                        let pool = match params.mmr {
+                            Some(_) => vector_top_k,
+                            None => limit.saturating_mul(Self::EXACT_RERANK_OVERSAMPLING),
+                        }
+                        .min(Self::MAX_EXACT_RERANK_CANDIDATES);
+                        rt = self.exact_rerank(index, &queries, rt, pool).await?;
+                    }
+                    let mut list = RankedList {
+                        source: RankSource::Vector,
+                        hits: rt,
+                    };
+                    if let Some(mmr) = &params.mmr {
+                        // Cut the pool first, so MMR does not pick documents
+                        // that the distance cutoff drops afterwards.
+                        if let Some(cutoffs) = &params.score_cutoffs {
+                            cutoffs.retain(&mut list);
+                        }
+                        list.hits =
+                            index.select_mmr(list.hits, queries.len(), mmr.lambda, limit)?;
+                    }
+                    results.push(list);
                 }
                 if !searched {
                     // A pure vector query with no usable HNSW index is a
@@ -3729,12 +5997,11 @@ impl Collection {
                     // to text-only results instead (best effort), so one
                     // mismatched vector dimension does not fail an otherwise
                     // valid full-text search.
-                    if params.text.is_none() {
+                    if params.text.is_none() && params.sparse_vector.is_none() {
                         return Err(DBError::Index {
                             name: self.name.clone(),
                             source: format!(
-                                "no HNSW index matches the query vector dimension {}",
-                                vector.len()
+                                "no HNSW index matches the query vector dimension {dimension}"
                             )
                             .into(),
                         });
@@ -3742,41 +6009,210 @@ impl Collection {
                     log::warn!(
                         action = "Collection::search_ids",
                         collection = self.name,
-                        dimension = vector.len();
+                        dimension = dimension;
                         "no HNSW index matches the query vector dimension; degrading to text-only results",
                     );
                 }
             }
 
-            let reranker = params.reranker.unwrap_or_default();
-            let reranked = reranker.rerank(&results);
+            truncated = order_by.is_some() && results.iter().any(|list| list.hits.len() >= top_k);
+            if let Some(cutoffs) = &params.score_cutoffs {
+                for list in &mut results {
+                    cutoffs.retain(list);
+                }
+            }
+            let reranked = fusion.fuse(&results);
             let mut uniq_candidates = UniqueVec::with_capacity(top_k);
-            uniq_candidates.extend(reranked.into_iter().map(|(id, _)| id));
+            uniq_candidates.extend(reranked.iter().map(|(id, _)| *id));
+            scores.extend(reranked);
             candidates = uniq_candidates.into();
+            if let Some(allowed) = &allowed {
+                // Text hits are not filtered by their index.
+                candidates.retain(|id| allowed.contains(*id));
+            }
 
             if candidates.is_empty() {
-                return Ok(result);
+                return Ok(SearchPage::default());
             }
         }
 
+        if let Some((index, descending, after)) = order_by {
+            // The search and the filter only decide which documents match;
+            // their order comes from the `order_by` index. The filter is
+            // evaluated without a bound, each search up to
+            // `MAX_ORDERED_SEARCH_HITS` hits whatever the page size.
+            // An empty candidate list here means there was no search, and
+            // `filter_by_field` then scans the whole collection.
+            let allowed = match filter {
+                Some(filter) => {
+                    Some(self.filter_by_field(filter, &candidates, 0, ScanOrder::Ascending)?)
+                }
+                None if !candidates.is_empty() => Some(candidates),
+                None => None,
+            };
+            let allowed: Option<FxHashSet<DocumentId>> =
+                allowed.map(|ids| ids.into_iter().collect());
+            let mut page = self.order_page(index, descending, after, allowed.as_ref(), limit)?;
+            page.truncated = truncated;
+            return Ok(page);
+        }
+
         // 过滤路径按 id 升序保留最小的 `limit` 个（与 `query_ids` 一致）；
         // 混合搜索的结果按相关性降序排列，同样保留头部，否则会丢弃最相关的
         // 命中。要按 id 取最新的一页，用 `query_last_ids`。
         let order = ScanOrder::Ascending;
-        match query.filter {
-            Some(filter) => {
-                result = self.filter_by_field(filter, &candidates, top_k, order)?;
-            }
-            None => result = candidates,
+        let mut result = match filter {
+            Some(filter) => self.filter_by_field(filter, &candidates, top_k, order)?,
+            None => candidates,
         };
         order.truncate(&mut result, limit);
 
-        Ok(result)
+        Ok(SearchPage {
+            items: result,
+            cursor: None,
+            truncated: false,
+        })
     }
 
-    /// Queries the **smallest** matching document IDs.
-    ///
-    /// # Page semantics
+    /// Returns the B-tree index a query can be ordered by: a single-field
+    /// index over a scalar field, so every document sits under one key.
+    fn order_index(&self, field: &str) -> Result<&BTree, DBError> {
+        let index = self
+            .btree_indexes
+            .iter()
+            .find(|i| i.name() == field && i.virtual_field().len() == 1)
+            .ok_or_else(|| DBError::Index {
+                name: self.name.clone(),
+                source: format!("order_by requires a single-field BTree index on {field:?}").into(),
+            })?;
+        let ft = field_path_type(&self.schema(), field)?;
+        let ft = match &ft {
+            Ft::Option(inner) => inner.as_ref(),
+            other => other,
+        };
+        if matches!(ft, Ft::Array(_) | Ft::Map(_)) {
+            return Err(DBError::Index {
+                name: self.name.clone(),
+                source: format!("order_by field {field:?} must be a scalar field, got {ft:?}")
+                    .into(),
+            });
+        }
+        Ok(index)
+    }
+
+    /// Decodes `Query::cursor` and checks it was issued for `order_by`.
+    fn decode_order_cursor(
+        &self,
+        order_by: &OrderBy,
+        cursor: &Option<String>,
+    ) -> Result<Option<(Fv, DocumentId)>, DBError> {
+        let Some(cursor) = cursor else {
+            return Ok(None);
+        };
+        let cursor =
+            OrderCursor::decode_for(cursor, order_by).map_err(|source| DBError::Generic {
+                name: self.name.clone(),
+                source: source.into(),
+            })?;
+        Ok(Some((cursor.key, cursor.id)))
+    }
+
+    /// Walks the `order_by` index from the cursor position and collects up to
+    /// `limit` ids that are in `allowed` (every id when `None`).
+    ///
+    /// Ids under one key are visited in id order, so `(key, id)` is a total
+    /// order and the cursor of the last result resumes exactly after it. One
+    /// extra id is collected to tell whether a next page exists.
+    fn order_page(
+        &self,
+        index: &BTree,
+        descending: bool,
+        after: Option<(Fv, DocumentId)>,
+        allowed: Option<&FxHashSet<DocumentId>>,
+        limit: usize,
+    ) -> Result<SearchPage<DocumentId>, DBError> {
+        let mut hits: Vec<(Fv, DocumentId)> = Vec::with_capacity(limit + 1);
+        let mut visit = |key: Fv, ids: &[DocumentId], after: Option<DocumentId>| {
+            let mut ids: Vec<DocumentId> = ids
+                .iter()
+                .copied()
+                .filter(|id| allowed.is_none_or(|s| s.contains(id)))
+                .filter(|id| match after {
+                    Some(after) if descending => *id < after,
+                    Some(after) => *id > after,
+                    None => true,
+                })
+                .collect();
+            if descending {
+                ids.sort_unstable_by(|a, b| b.cmp(a));
+            } else {
+                ids.sort_unstable();
+            }
+            for id in ids {
+                hits.push((key.clone(), id));
+                if hits.len() > limit {
+                    return false;
+                }
+            }
+            true
+        };
+
+        match after {
+            Some((key, id)) => {
+                // The rest of the cursor key first, then the keys past it.
+                let mut more = true;
+                index.try_range_query_entries(
+                    RangeQuery::Eq(key.clone()),
+                    descending,
+                    |k, ids| {
+                        more = visit(k, ids, Some(id));
+                        more
+                    },
+                )?;
+                if more {
+                    let rest = if descending {
+                        RangeQuery::Lt(key)
+                    } else {
+                        RangeQuery::Gt(key)
+                    };
+                    index
+                        .try_range_query_entries(rest, descending, |k, ids| visit(k, ids, None))?;
+                }
+            }
+            None => {
+                // `Not(Include([]))` excludes nothing, i.e. it walks every key.
+                index.try_range_query_entries(
+                    RangeQuery::Not(Box::new(RangeQuery::Include(Vec::new()))),
+                    descending,
+                    |k, ids| visit(k, ids, None),
+                )?;
+            }
+        }
+
+        let cursor = if hits.len() > limit {
+            hits.truncate(limit);
+            hits.last().and_then(|(key, id)| {
+                OrderCursor {
+                    field: index.name().to_string(),
+                    descending,
+                    key: key.clone(),
+                    id: *id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+        Ok(SearchPage {
+            items: hits.into_iter().map(|(_, id)| id).collect(),
+            cursor,
+            truncated: false,
+        })
+    }
+
+    /// Queries the **smallest** matching document IDs.
+    ///
+    /// # Page semantics
     ///
     /// The result is the first `limit` matching ids in ascending id order, for
     /// every filter shape. Which end you get is a property of the method you
@@ -3860,6 +6296,7 @@ impl Collection {
             .unwrap_or(Self::MAX_SEARCH_LIMIT)
             .min(Self::MAX_SEARCH_LIMIT);
 
+        let _batch_lease = self.batch_gate.read().await;
         let mut rt = self.filter_by_field(filter, &[], limit, order)?;
         order.truncate(&mut rt, limit);
         Ok(rt)
@@ -3892,10 +6329,113 @@ impl Collection {
 
         self.search_count.fetch_add(1, Ordering::Relaxed);
         // The internal scan uses `0` for "unbounded".
+        let _batch_lease = self.batch_gate.read().await;
         let rt = self.filter_by_field(filter, &[], 0, ScanOrder::Ascending)?;
         Ok(rt)
     }
 
+    /// Aggregates the keys of a B-Tree index.
+    ///
+    /// The index is walked once in ascending key order and every key
+    /// contributes the size of its posting list, so no document is loaded.
+    /// With `Aggregate::filter`, the filter is evaluated first (unbounded,
+    /// like [`Collection::query_all_ids`]) and each posting list only counts
+    /// its matching ids.
+    ///
+    /// `Aggregate::limit` bounds the returned buckets only; it defaults to
+    /// and is clamped to [`Collection::MAX_SEARCH_LIMIT`].
+    ///
+    /// # Arguments
+    /// * `query` - The aggregation to compute
+    ///
+    /// # Returns
+    /// The aggregation result, or an error if the index does not exist, a
+    /// histogram is requested over a non-integer index, or filtering fails.
+    pub async fn aggregate(&self, query: Aggregate) -> Result<AggregateResult, DBError> {
+        query
+            .validate_complexity()
+            .map_err(|source| DBError::Generic {
+                name: self.name.clone(),
+                source: source.into(),
+            })?;
+        let index = self
+            .btree_indexes
+            .iter()
+            .find(|i| i.name() == query.field)
+            .ok_or_else(|| DBError::Index {
+                name: self.name.clone(),
+                source: format!("BTree index {:?} not found", query.field).into(),
+            })?;
+        if let Aggregation::Histogram { interval } = query.aggregation
+            && (interval == 0 || !matches!(index, BTree::I64(_) | BTree::U64(_)))
+        {
+            return Err(DBError::Index {
+                name: self.name.clone(),
+                source: format!(
+                    "histogram requires a positive interval over an integer BTree index, got {interval} over {:?}",
+                    query.field
+                )
+                .into(),
+            });
+        }
+
+        let _batch_lease = self.batch_gate.read().await;
+        self.search_count.fetch_add(1, Ordering::Relaxed);
+        let limit = query
+            .limit
+            .unwrap_or(Self::MAX_SEARCH_LIMIT)
+            .min(Self::MAX_SEARCH_LIMIT);
+        let allowed: Option<FxHashSet<DocumentId>> = match query.filter {
+            Some(filter) => Some(
+                self.filter_by_field(filter, &[], 0, ScanOrder::Ascending)?
+                    .into_iter()
+                    .collect(),
+            ),
+            None => None,
+        };
+
+        let mut rt = AggregateResult::default();
+        // `Not(Include([]))` excludes nothing, i.e. it walks every key.
+        index.try_range_query_entries(
+            RangeQuery::Not(Box::new(RangeQuery::Include(Vec::new()))),
+            false,
+            |key, ids| {
+                let n = match &allowed {
+                    Some(allowed) => ids.iter().filter(|id| allowed.contains(id)).count(),
+                    None => ids.len(),
+                } as u64;
+                if n == 0 {
+                    return true;
+                }
+                rt.count += n;
+                if rt.min.is_none() {
+                    rt.min = Some(key.clone());
+                }
+                let bucket = match query.aggregation {
+                    Aggregation::Stats => None,
+                    Aggregation::Terms => Some(key.clone()),
+                    Aggregation::Histogram { interval } => Some(truncate_key(&key, interval)),
+                };
+                if let Some(bucket) = bucket {
+                    // Keys arrive in ascending order, so a histogram bucket
+                    // only ever continues the last one.
+                    let len = rt.buckets.len();
+                    match rt.buckets.last_mut() {
+                        Some(last) if last.key == bucket => last.count += n,
+                        _ if len >= limit => rt.truncated = true,
+                        _ => rt.buckets.push(AggregateBucket {
+                            key: bucket,
+                            count: n,
+                        }),
+                    }
+                }
+                rt.max = Some(key);
+                true
+            },
+        )?;
+        Ok(rt)
+    }
+
     /// Gets a document by its ID.
     ///
     /// # Arguments
@@ -3907,8 +6447,11 @@ impl Collection {
         if self.doc_ids.read().contains(id) {
             self.get_count.fetch_add(1, Ordering::Relaxed);
 
-            let path = Self::doc_path(id);
-            match self.storage.get::<DocumentOwned>(&path).await {
+            match self
+                .documents
+                .get::<DocumentOwned>(RecordKey::Document(id))
+                .await
+            {
                 Ok((doc, _)) => {
                     let doc = Document::try_from_doc(self.schema(), doc)?;
                     return Ok(doc);
@@ -3996,6 +6539,29 @@ impl Collection {
         }
     }
 
+    /// Evaluates `filter` for a filtered vector traversal, returning `None`
+    /// when it matches more than `max_ids` documents.
+    ///
+    /// A single-field filter stops scanning after `max_ids + 1` matches;
+    /// composite filters evaluate their operands in full (see
+    /// [`Collection::filter_by_field_with`]).
+    fn selective_filter_ids(
+        &self,
+        filter: &Filter,
+        max_ids: usize,
+    ) -> Result<Option<Treemap>, DBError> {
+        let ids = self.filter_by_field(
+            filter.clone(),
+            &[],
+            max_ids.saturating_add(1),
+            ScanOrder::Ascending,
+        )?;
+        if ids.len() > max_ids {
+            return Ok(None);
+        }
+        Ok(Some(ids.into_iter().collect()))
+    }
+
     /// Inner implementation of `filter_by_field` using a `FxHashSet` for O(1) candidate lookups.
     ///
     /// `order` is the end the caller wants; scans walk that way so either end
@@ -4325,7 +6891,7 @@ mod tests {
         error::{CollectionState, DBError},
         index::HnswConfig,
         query::{Filter, Query, RangeQuery, Search},
-        schema::{AndaDBSchema, Document, Fv, Json, Schema, Vector},
+        schema::{AndaDBSchema, Document, FieldKey, Fv, Json, Schema, Vector},
         storage::{PutMode, StorageConfig},
     };
     use async_trait::async_trait;
@@ -4390,6 +6956,7 @@ mod tests {
         let collection_config = CollectionConfig {
             name: "test_collection".to_string(),
             description: "Test collection".to_string(),
+            ..Default::default()
         };
 
         let collection = db
@@ -6595,6 +9162,7 @@ mod tests {
                     RangeQuery::Lt(Fv::U64(100)),
                 ))),
                 limit: Some(2),
+                ..Default::default()
             })
             .await?;
         assert_eq!(ids, vec![1, 2]);
@@ -6863,115 +9431,96 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_search_with_multiple_hnsw_dimensions_and_missing_indexes() -> Result<(), DBError>
-    {
+    async fn test_vector_search_applies_filter_during_traversal() -> Result<(), DBError> {
         let db = setup_test_db().await?;
-        let collection = db
-            .open_or_create_collection(
-                DualVectorDoc::schema()?,
-                CollectionConfig {
-                    name: "dual_vec".to_string(),
-                    description: "two vector fields".to_string(),
-                },
-                async |c| {
-                    c.create_hnsw_index_nx(
-                        "vec_a",
-                        HnswConfig {
-                            dimension: 4,
-                            ..Default::default()
-                        },
-                    )
-                    .await?;
-                    c.create_hnsw_index_nx(
-                        "vec_b",
-                        HnswConfig {
-                            dimension: 8,
-                            ..Default::default()
-                        },
-                    )
-                    .await?;
-                    Ok(())
-                },
-            )
-            .await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_btree_index_nx(&["age"]).await?;
+            collection.create_bm25_index_nx(&["name"]).await?;
+            collection
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            Ok(())
+        })
+        .await?;
 
-        let id = collection
-            .add_from(&DualVectorDoc {
-                _id: 0,
-                name: "a".into(),
-                vec_a: std::iter::repeat_n(bf16::from_f32(0.1), 4).collect(),
-                vec_b: std::iter::repeat_n(bf16::from_f32(0.2), 8).collect(),
-            })
-            .await?;
+        // 60 documents at increasing distance from the query vector; only
+        // the 5 farthest match the filter.
+        for i in 0..60u32 {
+            let mut doc = create_test_doc(0, &format!("doc{i}"), i, vec!["x"]);
+            doc.vector = std::iter::repeat_n(bf16::from_f32(0.1 * i as f32), 10).collect();
+            collection.add_from(&doc).await?;
+        }
 
-        // A 4-dim query searches only the matching index and succeeds even
-        // though another index has a different dimension.
+        // `limit * 10` unfiltered candidates are the 20 nearest documents,
+        // none of which matches: post-filtering them returned nothing.
         let ids = collection
             .search_ids(Query {
                 search: Some(Search {
-                    vector: Some(vec![0.1; 4]),
+                    vector: Some(vec![0.0; 10]),
                     ..Default::default()
                 }),
+                filter: Some(Filter::Field((
+                    "age".to_string(),
+                    RangeQuery::Ge(Fv::U64(55)),
+                ))),
+                limit: Some(2),
                 ..Default::default()
             })
             .await?;
-        assert_eq!(ids, vec![id]);
+        assert_eq!(ids, vec![56, 57]);
 
-        // An 8-dim query hits the other index.
+        // Hybrid text hits are filtered by the same evaluated match set.
         let ids = collection
             .search_ids(Query {
                 search: Some(Search {
-                    vector: Some(vec![0.2; 8]),
+                    text: Some("doc1".to_string()),
+                    vector: Some(vec![0.0; 10]),
                     ..Default::default()
                 }),
+                filter: Some(Filter::Field((
+                    "age".to_string(),
+                    RangeQuery::Ge(Fv::U64(58)),
+                ))),
+                limit: Some(5),
                 ..Default::default()
             })
             .await?;
-        assert_eq!(ids, vec![id]);
-
-        // A query dimension matching no index is an error, not silence.
-        let err = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    vector: Some(vec![0.3; 5]),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .await
-            .unwrap_err();
-        assert!(matches!(err, DBError::Index { .. }));
+        assert_eq!(ids, vec![59, 60]);
 
-        // Text search without any BM25 index is an error, not silence.
-        let err = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    text: Some("a".to_string()),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .await
-            .unwrap_err();
-        assert!(matches!(err, DBError::Index { .. }));
+        // Only a selective filter is expanded for the traversal; a broad one
+        // stops scanning past the bound and stays a post-filter.
+        let broad = Filter::Field(("age".to_string(), RangeQuery::Ge(Fv::U64(0))));
+        assert!(collection.selective_filter_ids(&broad, 10)?.is_none());
+        let selective = Filter::Field(("age".to_string(), RangeQuery::Ge(Fv::U64(55))));
+        assert_eq!(
+            collection
+                .selective_filter_ids(&selective, 10)?
+                .map(|ids| ids.iter().collect::<Vec<_>>()),
+            Some(vec![56, 57, 58, 59, 60])
+        );
 
         db.close().await?;
         Ok(())
     }
 
-    /// Regression (#20): a hybrid text+vector query whose vector dimension
-    /// matches no HNSW index degrades to text-only results instead of
-    /// failing the whole query; a pure vector query still errors.
     #[tokio::test]
-    async fn test_hybrid_search_degrades_when_vector_matches_no_index() -> Result<(), DBError> {
+    async fn test_quantized_vector_search_reranks_by_exact_distance() -> Result<(), DBError> {
         let db = setup_test_db().await?;
         let collection = create_test_collection(&db, async |collection| {
-            collection.create_bm25_index_nx(&["name"]).await?;
             collection
                 .create_hnsw_index_nx(
                     "vector",
                     HnswConfig {
                         dimension: 10,
+                        quantization: Quantization::Product { subspaces: 1 },
+                        quantization_training_size: 2,
+                        exact_rerank: true,
                         ..Default::default()
                     },
                 )
@@ -6979,516 +9528,2485 @@ mod tests {
             Ok(())
         })
         .await?;
-        let id = collection
-            .add_from(&create_test_doc(0, "alpha beta", 30, vec!["x"]))
-            .await?;
+        let add = async |name: &str, value: f32| {
+            let mut doc = create_test_doc(0, name, 1, vec![]);
+            doc.vector = vec![bf16::from_f32(value); 10];
+            collection.add_from(&doc).await
+        };
 
-        // Hybrid: the BM25 hit is kept, the mismatched vector part is
-        // dropped with a warning.
+        // The codebook is trained on the first two vectors, and quantization
+        // takes effect once a flush persisted it.
+        add("zero", 0.0).await?;
+        add("ten", 10.0).await?;
+        collection.flush(unix_ms()).await?;
+        let hnsw = collection.get_hnsw_index("vector")?;
+        assert!(hnsw.exact_rerank());
+
+        // Both encode to the `0.0` centroid: only their stored vectors tell
+        // them apart.
+        let four = add("four", 4.0).await?;
+        let near = add("four_and_a_half", 4.5).await?;
         let ids = collection
             .search_ids(Query {
                 search: Some(Search {
-                    text: Some("alpha".to_string()),
-                    vector: Some(vec![0.1; 5]),
+                    vector: Some(vec![4.6; 10]),
                     ..Default::default()
                 }),
+                limit: Some(2),
                 ..Default::default()
             })
             .await?;
-        assert_eq!(ids, vec![id]);
+        assert_eq!(ids, vec![near, four]);
 
-        // Pure vector misuse still surfaces as an error.
-        let err = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    vector: Some(vec![0.1; 5]),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .await
-            .unwrap_err();
-        assert!(matches!(err, DBError::Index { .. }));
+        // Only the head of the hits is re-scored; the rest keep their
+        // approximate distances, after it.
+        let query = [4.6; 10];
+        let hits = collection
+            .exact_rerank(
+                collection.find_hnsw_index("vector")?,
+                &[&query],
+                vec![(four, 0.5), (near, 0.25)],
+                1,
+            )
+            .await?;
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].0, four);
+        assert_ne!(hits[0].1, 0.5);
+        assert_eq!(hits[1], (near, 0.25));
 
         db.close().await?;
         Ok(())
     }
 
-    // Document with a signed counter, for the I64/U64 read-back regressions.
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, AndaDBSchema)]
-    struct CounterDoc {
+    #[derive(Debug, Clone, Serialize, Deserialize, AndaDBSchema)]
+    struct ChunkedDoc {
         pub _id: u64,
         pub name: String,
-        pub count: i64,
-    }
-
-    fn counter_config() -> CollectionConfig {
-        CollectionConfig {
-            name: "counters".to_string(),
-            description: "signed counters".to_string(),
-        }
+        pub chunks: Vec<Vector>,
     }
 
-    /// Regression (#1/#9, cases a+b): a non-negative i64 field reads back
-    /// from storage as U64. `update` must retire the old B-tree entry (no
-    /// phantom match on the old value) and `remove` must not leak entries.
     #[tokio::test]
-    async fn test_i64_btree_index_update_and_remove_after_read_back() -> Result<(), DBError> {
-        let db = setup_test_db().await?;
-        let collection = db
-            .open_or_create_collection(CounterDoc::schema()?, counter_config(), async |c| {
-                c.create_btree_index_nx(&["count"]).await?;
-                Ok(())
-            })
+    async fn test_multi_vector_index_and_max_sim_search() -> Result<(), DBError> {
+        let object_store = Arc::new(InMemory::new());
+        let db_config = DBConfig {
+            name: "test_db".to_string(),
+            description: "Test database".to_string(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let collection_config = CollectionConfig {
+            name: "chunked".to_string(),
+            description: "multi-vector documents".to_string(),
+            ..Default::default()
+        };
+        let chunks = |values: &[[f32; 2]]| -> Vec<Vector> {
+            values
+                .iter()
+                .map(|v| v.iter().map(|x| bf16::from_f32(*x)).collect())
+                .collect()
+        };
+        let search = async |collection: &Collection, search: Search| {
+            collection
+                .search_ids(Query {
+                    search: Some(search),
+                    limit: Some(3),
+                    ..Default::default()
+                })
+                .await
+        };
+        let max_sim = || Search {
+            vectors: Some(vec![vec![1.0, 0.0], vec![0.0, 1.0]]),
+            ..Default::default()
+        };
+
+        let db = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
+        let collection = db
+            .open_or_create_collection(
+                ChunkedDoc::schema()?,
+                collection_config.clone(),
+                async |c| {
+                    c.create_hnsw_index_nx(
+                        "chunks",
+                        HnswConfig {
+                            dimension: 2,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                    Ok(())
+                },
+            )
             .await?;
+        assert!(collection.get_hnsw_index("chunks")?.multi_vector());
 
-        let id = collection
-            .add_from(&CounterDoc {
+        let one = collection
+            .add_from(&ChunkedDoc {
                 _id: 0,
-                name: "a".to_string(),
-                count: 5,
+                name: "one".into(),
+                chunks: chunks(&[[1.0, 0.0]]),
             })
             .await?;
-
-        // (a) update: the old document is materialized from storage where
-        // `count: 5` reads back as U64(5); the old index entry must still be
-        // retired.
-        collection
-            .update(id, BTreeMap::from([("count".to_string(), Fv::I64(7))]))
+        let both = collection
+            .add_from(&ChunkedDoc {
+                _id: 0,
+                name: "both".into(),
+                chunks: chunks(&[[0.9, 0.1], [0.1, 0.9]]),
+            })
             .await?;
-        let stale = collection
-            .query_ids(
-                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(5)))),
-                Some(10),
-            )
+        let far = collection
+            .add_from(&ChunkedDoc {
+                _id: 0,
+                name: "far".into(),
+                chunks: chunks(&[[-1.0, -1.0]]),
+            })
             .await?;
-        assert!(
-            stale.is_empty(),
-            "stale index entry for the old value must be removed, got {stale:?}",
+        assert_eq!(collection.get_hnsw_index("chunks")?.stats().num_elements, 4);
+
+        // A document ranks by its nearest vector and appears once.
+        let single = Search {
+            vector: Some(vec![1.0, 0.0]),
+            ..Default::default()
+        };
+        assert_eq!(
+            search(&collection, single.clone()).await?,
+            vec![one, both, far]
         );
-        let current = collection
-            .query_ids(
-                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(7)))),
-                Some(10),
-            )
-            .await?;
-        assert_eq!(current, vec![id]);
+        // MaxSim rewards the document covering every query vector.
+        assert_eq!(search(&collection, max_sim()).await?, vec![both, one, far]);
 
-        // (b) remove: the index entry must be dropped, not leaked.
-        assert!(collection.remove(id).await?.is_some());
-        let leaked = collection
-            .query_ids(
-                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(7)))),
-                Some(10),
-            )
-            .await?;
-        assert!(leaked.is_empty(), "removed document must leave no entries");
+        let conflicting = Search {
+            vector: Some(vec![1.0, 0.0]),
+            ..max_sim()
+        };
+        assert!(search(&collection, conflicting).await.is_err());
+        let mixed_dimensions = Search {
+            vectors: Some(vec![vec![1.0, 0.0], vec![1.0]]),
+            ..Default::default()
+        };
+        assert!(search(&collection, mixed_dimensions).await.is_err());
+
+        // Updating replaces every vector of the document.
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "chunks".to_string(),
+            Fv::Array(chunks(&[[0.0, 1.0]]).into_iter().map(Fv::Vector).collect()),
+        );
+        collection.update(both, fields).await?;
+        assert_eq!(collection.get_hnsw_index("chunks")?.stats().num_elements, 3);
         assert_eq!(
-            collection.get_btree_index(&["count"])?.stats().num_elements,
-            0,
+            search(&collection, single.clone()).await?,
+            vec![one, both, far]
         );
 
+        collection.remove(one).await?;
+        assert_eq!(search(&collection, max_sim()).await?, vec![both, far]);
         db.close().await?;
-        Ok(())
-    }
-
-    /// Regression (#1/#9, case c): creating a B-tree index over existing
-    /// non-negative i64 data backfills from storage, where the values read
-    /// back as U64 — index creation must succeed and the index must be
-    /// queryable.
-    #[tokio::test]
-    async fn test_i64_btree_index_backfill_over_existing_data() -> Result<(), DBError> {
-        let db = setup_test_db().await?;
-        let collection = db
-            .open_or_create_collection(CounterDoc::schema()?, counter_config(), async |_| Ok(()))
-            .await?;
-        let mut ids = Vec::new();
-        for (i, count) in [5i64, -2, 0].into_iter().enumerate() {
-            ids.push(
-                collection
-                    .add_from(&CounterDoc {
-                        _id: 0,
-                        name: format!("doc{i}"),
-                        count,
-                    })
-                    .await?,
-            );
-        }
-        collection.flush(unix_ms()).await?;
-        drop(collection);
-        db.close_collection("counters").await?;
 
-        // Reopen from storage and build the index over the existing data.
+        // The multi-vector mode comes back from the field type on reopen.
+        let db = AndaDB::connect(object_store, db_config).await?;
         let collection = db
-            .open_collection("counters".to_string(), async |c| {
-                c.create_btree_index(&["count"]).await?;
-                Ok(())
-            })
-            .await?;
-        let found = collection
-            .query_ids(
-                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(5)))),
-                Some(10),
-            )
+            .open_collection(collection_config.name.clone(), async |_| Ok(()))
             .await?;
-        assert_eq!(found, vec![ids[0]]);
-        let found = collection
-            .query_ids(
-                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(-2)))),
-                Some(10),
-            )
-            .await?;
-        assert_eq!(found, vec![ids[1]]);
+        assert!(collection.get_hnsw_index("chunks")?.multi_vector());
+        assert_eq!(search(&collection, single).await?, vec![both, far]);
 
         db.close().await?;
         Ok(())
     }
 
-    /// Regression (#18): `save_extension` / `remove_extension` persist with
-    /// a single metadata put ("Ok means persisted") and must not advance the
-    /// flush watermark — the next full flush still runs its complete path.
+    #[derive(Debug, Clone, Serialize, Deserialize, AndaDBSchema)]
+    struct SparseDoc {
+        pub _id: u64,
+        pub text: String,
+        pub terms: SparseVector,
+    }
+
     #[tokio::test]
-    async fn test_save_extension_single_put_without_claiming_flush() -> Result<(), DBError> {
+    async fn test_sparse_index_dot_product_and_hybrid_search() -> Result<(), DBError> {
         let object_store = Arc::new(InMemory::new());
         let db_config = DBConfig {
-            name: "ext_db".to_string(),
-            description: "extension persistence".to_string(),
+            name: "test_db".to_string(),
+            description: "Test database".to_string(),
             storage: StorageConfig {
                 compress_level: 0,
                 ..Default::default()
             },
             lock: None,
         };
+        let collection_config = CollectionConfig {
+            name: "splade".to_string(),
+            description: "sparse vector documents".to_string(),
+            ..Default::default()
+        };
+        let terms = |values: &[(&str, f32)]| -> SparseVector {
+            values.iter().map(|(t, w)| (t.to_string(), *w)).collect()
+        };
+        let search = async |collection: &Collection, search: Search| {
+            collection
+                .search_ids(Query {
+                    search: Some(search),
+                    limit: Some(3),
+                    ..Default::default()
+                })
+                .await
+        };
+        let sparse = |values: &[(&str, f32)]| Search {
+            sparse_vector: Some(terms(values)),
+            ..Default::default()
+        };
+
         let db = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
         let collection = db
-            .open_or_create_collection(CounterDoc::schema()?, counter_config(), async |_| Ok(()))
+            .open_or_create_collection(SparseDoc::schema()?, collection_config.clone(), async |c| {
+                assert!(c.create_sparse_index("text").await.is_err());
+                c.create_bm25_index_nx(&["text"]).await?;
+                c.create_sparse_index_nx("terms").await?;
+                c.create_sparse_index_nx("terms").await?;
+                Ok(())
+            })
             .await?;
 
-        let puts_before = collection.storage_stats().total_put_count;
-        collection
-            .save_extension("k".to_string(), Fv::Text("v".to_string()))
+        let rust = collection
+            .add_from(&SparseDoc {
+                _id: 0,
+                text: "rust systems language".into(),
+                terms: terms(&[("rust", 2.0), ("language", 0.5)]),
+            })
             .await?;
+        let db_doc = collection
+            .add_from(&SparseDoc {
+                _id: 0,
+                text: "embedded database".into(),
+                terms: terms(&[("database", 1.5), ("rust", 0.5)]),
+            })
+            .await?;
+        let empty = collection
+            .add_from(&SparseDoc {
+                _id: 0,
+                text: "nothing to see".into(),
+                terms: SparseVector::new(),
+            })
+            .await?;
+
         assert_eq!(
-            collection.storage_stats().total_put_count,
-            puts_before + 1,
-            "save_extension must perform exactly one metadata put",
+            search(&collection, sparse(&[("rust", 1.0)])).await?,
+            vec![rust, db_doc]
+        );
+        assert_eq!(
+            search(&collection, sparse(&[("rust", 0.1), ("database", 1.0)])).await?,
+            vec![db_doc, rust]
         );
+        // Text and sparse rankings are fused; each list contributes.
+        let hybrid = Search {
+            text: Some("nothing".to_string()),
+            ..sparse(&[("database", 1.0)])
+        };
+        let ids = search(&collection, hybrid).await?;
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&db_doc) && ids.contains(&empty));
 
-        // Persisted immediately: a second database instance reads it back
-        // without any flush on the first one.
-        {
-            let db2 = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
-            let c2 = db2
-                .open_collection("counters".to_string(), async |_| Ok(()))
-                .await?;
-            assert_eq!(c2.get_extension("k"), Some(Fv::Text("v".to_string())));
-        }
+        // Updating replaces the indexed weights.
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "terms".to_string(),
+            Fv::Map(BTreeMap::from([("database".into(), Fv::F32(3.0))])),
+        );
+        collection.update(rust, fields).await?;
+        assert_eq!(
+            search(&collection, sparse(&[("database", 1.0)])).await?,
+            vec![rust, db_doc]
+        );
+        assert_eq!(
+            search(&collection, sparse(&[("rust", 1.0)])).await?,
+            vec![db_doc]
+        );
 
-        // The unclaimed write did not advance `last_saved_version`: the next
-        // full flush still persists metadata + ids, and only then does the
-        // fast path apply.
-        assert!(collection.flush(unix_ms()).await?);
-        assert!(!collection.flush(unix_ms()).await?);
+        collection.remove(db_doc).await?;
+        assert_eq!(
+            search(&collection, sparse(&[("database", 1.0)])).await?,
+            vec![rust]
+        );
+        db.close().await?;
 
-        // remove_extension mirrors the single-put behaviour.
-        let puts_before = collection.storage_stats().total_put_count;
-        let old = collection.remove_extension("k").await?;
-        assert_eq!(old, Some(Fv::Text("v".to_string())));
+        let db = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
+        let collection = db
+            .open_collection(collection_config.name.clone(), async |_| Ok(()))
+            .await?;
         assert_eq!(
-            collection.storage_stats().total_put_count,
-            puts_before + 1,
-            "remove_extension must perform exactly one metadata put",
+            search(&collection, sparse(&[("database", 1.0)])).await?,
+            vec![rust]
+        );
+        db.close().await?;
+
+        // Removing the index deletes its storage objects.
+        let db = AndaDB::connect(object_store.clone(), db_config).await?;
+        let collection = db
+            .open_collection(collection_config.name.clone(), async |collection| {
+                assert!(collection.remove_sparse_index("terms").await?);
+                assert!(!collection.remove_sparse_index("terms").await?);
+                Ok(())
+            })
+            .await?;
+        assert!(
+            search(&collection, sparse(&[("database", 1.0)]))
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            count_objects(&db.object_store(), "test_db/splade/sparse_indexes").await,
+            0
         );
-        assert!(collection.get_extension("k").is_none());
-        // Removing a missing key writes nothing.
-        let puts_before = collection.storage_stats().total_put_count;
-        assert!(collection.remove_extension("missing").await?.is_none());
-        assert_eq!(collection.storage_stats().total_put_count, puts_before);
 
         db.close().await?;
         Ok(())
     }
 
+    #[derive(Debug, Clone, Serialize, Deserialize, AndaDBSchema)]
+    struct PointDoc {
+        pub _id: u64,
+        pub vector: Vector,
+    }
+
     #[tokio::test]
-    async fn test_open_repair_scan_skips_unreadable_and_mismatched_documents() -> Result<(), DBError>
+    async fn test_rebuild_hnsw_index_swaps_generation_and_purges_old_graph() -> Result<(), DBError>
     {
         let db = setup_test_db().await?;
-        let collection = create_test_collection(&db, async |_| Ok(())).await?;
-        let id1 = collection
-            .add_from(&create_test_doc(0, "Alice", 30, vec!["a"]))
-            .await?;
-        collection.flush(unix_ms()).await?; // persisted checkpoint = 1
-
-        // id 2: corrupt CBOR object inside the repair scan window.
-        collection
-            .storage
-            .put_bytes(
-                &Collection::doc_path(2),
-                Bytes::from_static(b"not valid cbor"),
-                PutMode::Overwrite,
-            )
-            .await?;
-        // id 3: valid CBOR that does not match the collection schema.
-        let bad_doc = DocumentOwned {
-            fields: BTreeMap::from([(0usize, Fv::Text("not an id".to_string()))]),
+        let object_store = db.object_store();
+        let db_config = DBConfig {
+            name: "test_db".to_string(),
+            description: "Test database".to_string(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
         };
-        collection
-            .storage
-            .create(&Collection::doc_path(3), &bad_doc)
+        let collection_config = CollectionConfig {
+            name: "points".to_string(),
+            description: "rebuilt vectors".to_string(),
+            ..Default::default()
+        };
+        let point = |x: f32| -> Vector { vec![bf16::from_f32(x), bf16::from_f32(1.0)] };
+        let nearest = async |collection: &Collection, x: f32| {
+            collection
+                .search_ids(Query {
+                    search: Some(Search {
+                        vector: Some(vec![x, 1.0]),
+                        ..Default::default()
+                    }),
+                    limit: Some(1),
+                    ..Default::default()
+                })
+                .await
+        };
+
+        let collection = db
+            .open_or_create_collection(PointDoc::schema()?, collection_config.clone(), async |c| {
+                c.create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 2,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+                Ok(())
+            })
             .await?;
-        // id 4: a valid orphan document, recoverable.
-        let orphan = Document::try_from(
-            collection.schema(),
-            &create_test_doc(4, "Carol", 40, vec!["c"]),
-        )?;
-        collection
-            .storage
-            .create(&Collection::doc_path(4), &orphan)
+
+        let mut ids = Vec::new();
+        for i in 0..60 {
+            ids.push(
+                collection
+                    .add_from(&PointDoc {
+                        _id: 0,
+                        vector: point(i as f32),
+                    })
+                    .await?,
+            );
+        }
+        for id in ids.iter().step_by(3) {
+            collection.remove(*id).await?;
+        }
+        collection.flush(unix_ms()).await?;
+        let prefix = "test_db/points/hnsw_indexes/vector/";
+        assert!(count_objects(&object_store, prefix).await > 0);
+
+        collection.rebuild_hnsw_index("vector").await?;
+        let index = collection.get_hnsw_index("vector")?;
+        assert_eq!(index.generation(), 1);
+        assert_eq!(index.stats().num_elements, 40);
+        let health = index.stats().graph_health.unwrap();
+        assert_eq!(health.num_nodes, 40);
+        assert_eq!(health.unreachable, 0);
+        assert_eq!(count_objects(&object_store, prefix).await, 0);
+        assert!(count_objects(&object_store, "test_db/points/hnsw_indexes/vector.1/").await > 0);
+        assert_eq!(nearest(&collection, 1.0).await?, vec![ids[1]]);
+        assert!(collection.rebuild_hnsw_index("missing").await.is_err());
+
+        // The rebuilt graph keeps taking mutations and survives a reopen.
+        collection.remove(ids[1]).await?;
+        let added = collection
+            .add_from(&PointDoc {
+                _id: 0,
+                vector: point(100.0),
+            })
             .await?;
+        db.close().await?;
 
-        // Reopening must not fail on the corrupt or mismatched objects
-        // (previously a schema mismatch bricked the whole collection open),
-        // and the valid orphan behind them must still be recovered.
-        drop(collection);
-        let db = reconnect_test_db(db).await?;
+        let db = AndaDB::connect(object_store.clone(), db_config).await?;
         let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .open_collection(collection_config.name.clone(), async |_| Ok(()))
             .await?;
-        assert!(collection.contains(id1));
-        assert!(!collection.contains(2));
-        assert!(!collection.contains(3));
-        assert!(collection.contains(4));
-        let recovered: TestDoc = collection.get_as(4).await?;
-        assert_eq!(recovered.name, "Carol");
+        let index = collection.get_hnsw_index("vector")?;
+        assert_eq!(index.generation(), 1);
+        assert_eq!(index.stats().num_elements, 40);
+        assert_eq!(nearest(&collection, 1.0).await?, vec![ids[2]]);
+        assert_eq!(nearest(&collection, 100.0).await?, vec![added]);
 
+        collection.rebuild_hnsw_index("vector").await?;
+        assert_eq!(collection.get_hnsw_index("vector")?.generation(), 2);
+        assert_eq!(
+            count_objects(&object_store, "test_db/points/hnsw_indexes/vector.1/").await,
+            0
+        );
         db.close().await?;
         Ok(())
     }
 
-    /// An object store that blocks `put` for paths ending in `gate_suffix`
-    /// until the watch gate is opened, to deterministically hold an `add`
-    /// in flight while a flush runs.
-    #[derive(Debug)]
-    struct GatedPutStore {
-        inner: Arc<InMemory>,
-        gate_suffix: String,
-        gate: tokio::sync::watch::Receiver<bool>,
-        blocked: Arc<TestAtomicBool>,
-    }
-
-    impl fmt::Display for GatedPutStore {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            f.write_str("GatedPutStore")
-        }
-    }
-
-    #[async_trait]
-    impl ObjectStore for GatedPutStore {
-        async fn put_opts(
-            &self,
-            location: &Path,
-            payload: PutPayload,
-            opts: PutOptions,
-        ) -> ObjectStoreResult<PutResult> {
-            if location.to_string().ends_with(&self.gate_suffix) {
-                let mut rx = self.gate.clone();
-                while !*rx.borrow() {
-                    self.blocked.store(true, TestOrdering::Release);
-                    rx.changed()
-                        .await
-                        .map_err(|_| object_store::Error::Generic {
-                            store: "gated_put",
-                            source: "gate sender dropped".into(),
-                        })?;
-                }
-            }
-            self.inner.put_opts(location, payload, opts).await
+    #[tokio::test]
+    async fn test_tune_hnsw_ef_search_persists_across_reopen_and_rebuild() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let object_store = db.object_store();
+        let db_config = DBConfig {
+            name: "test_db".to_string(),
+            description: "Test database".to_string(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let collection_config = CollectionConfig {
+            name: "tuned".to_string(),
+            description: "tuned vectors".to_string(),
+            ..Default::default()
+        };
+        let config = HnswConfig {
+            dimension: 2,
+            ef_search: 300,
+            ..Default::default()
+        };
+        let create = async |c: &mut Collection| {
+            c.create_hnsw_index_nx("vector", config.clone()).await?;
+            Ok(())
+        };
+        let collection = db
+            .open_or_create_collection(PointDoc::schema()?, collection_config.clone(), create)
+            .await?;
+        for i in 0..50 {
+            collection
+                .add_from(&PointDoc {
+                    _id: 0,
+                    vector: vec![bf16::from_f32(i as f32), bf16::from_f32((i % 5) as f32)],
+                })
+                .await?;
         }
 
-        async fn put_multipart_opts(
-            &self,
-            location: &Path,
-            opts: PutMultipartOptions,
-        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
-            self.inner.put_multipart_opts(location, opts).await
-        }
+        let options = RecallOptions {
+            sample_size: 10,
+            top_k: 3,
+            ef_search: vec![3, 64],
+            ..Default::default()
+        };
+        let report = collection
+            .get_hnsw_index("vector")?
+            .evaluate_recall(&options)?;
+        assert_eq!(report.num_queries, 10);
+        assert_eq!(report.measurements.len(), 2);
+        let (ef_search, _) = collection
+            .tune_hnsw_ef_search("vector", 1.0, &options)
+            .await?;
+        assert!(ef_search <= 64);
+        // The measurements are not counted as searches.
+        assert_eq!(collection.get_hnsw_index("vector")?.stats().search_count, 0);
+        assert!(
+            collection
+                .tune_hnsw_ef_search("missing", 1.0, &options)
+                .await
+                .is_err()
+        );
+        assert!(
+            collection
+                .tune_hnsw_ef_search("vector", 1.5, &options)
+                .await
+                .is_err()
+        );
+        collection.rebuild_hnsw_index("vector").await?;
+        assert_eq!(collection.get_hnsw_index("vector")?.ef_search(), ef_search);
+        db.close().await?;
 
-        async fn get_opts(
-            &self,
-            location: &Path,
-            options: GetOptions,
-        ) -> ObjectStoreResult<GetResult> {
-            self.inner.get_opts(location, options).await
-        }
+        // The original configuration is still accepted on reopen.
+        let db = AndaDB::connect(object_store, db_config).await?;
+        let collection = db
+            .open_or_create_collection(PointDoc::schema()?, collection_config, create)
+            .await?;
+        let index = collection.get_hnsw_index("vector")?;
+        assert_eq!(index.ef_search(), ef_search);
+        assert_eq!(index.metadata().config.ef_search, 300);
+        db.close().await?;
+        Ok(())
+    }
 
-        fn delete_stream(
-            &self,
-            locations: BoxStream<'static, ObjectStoreResult<Path>>,
-        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
-            self.inner.delete_stream(locations)
-        }
+    #[tokio::test]
+    async fn test_search_with_multiple_hnsw_dimensions_and_missing_indexes() -> Result<(), DBError>
+    {
+        let db = setup_test_db().await?;
+        let collection = db
+            .open_or_create_collection(
+                DualVectorDoc::schema()?,
+                CollectionConfig {
+                    name: "dual_vec".to_string(),
+                    description: "two vector fields".to_string(),
+                    ..Default::default()
+                },
+                async |c| {
+                    c.create_hnsw_index_nx(
+                        "vec_a",
+                        HnswConfig {
+                            dimension: 4,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                    c.create_hnsw_index_nx(
+                        "vec_b",
+                        HnswConfig {
+                            dimension: 8,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                    Ok(())
+                },
+            )
+            .await?;
 
-        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
-            self.inner.list(prefix)
-        }
+        let id = collection
+            .add_from(&DualVectorDoc {
+                _id: 0,
+                name: "a".into(),
+                vec_a: std::iter::repeat_n(bf16::from_f32(0.1), 4).collect(),
+                vec_b: std::iter::repeat_n(bf16::from_f32(0.2), 8).collect(),
+            })
+            .await?;
 
-        fn list_with_offset(
-            &self,
-            prefix: Option<&Path>,
-            offset: &Path,
-        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
-            self.inner.list_with_offset(prefix, offset)
-        }
+        // A 4-dim query searches only the matching index and succeeds even
+        // though another index has a different dimension.
+        let ids = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    vector: Some(vec![0.1; 4]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(ids, vec![id]);
 
-        async fn list_with_delimiter(
-            &self,
-            prefix: Option<&Path>,
-        ) -> ObjectStoreResult<ListResult> {
-            self.inner.list_with_delimiter(prefix).await
-        }
+        // An 8-dim query hits the other index.
+        let ids = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    vector: Some(vec![0.2; 8]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(ids, vec![id]);
 
-        async fn copy_opts(
-            &self,
-            from: &Path,
+        // A query dimension matching no index is an error, not silence.
+        let err = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    vector: Some(vec![0.3; 5]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::Index { .. }));
+
+        // Text search without any BM25 index is an error, not silence.
+        let err = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("a".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::Index { .. }));
+
+        db.close().await?;
+        Ok(())
+    }
+
+    /// Regression (#20): a hybrid text+vector query whose vector dimension
+    /// matches no HNSW index degrades to text-only results instead of
+    /// failing the whole query; a pure vector query still errors.
+    #[tokio::test]
+    async fn test_hybrid_search_degrades_when_vector_matches_no_index() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_bm25_index_nx(&["name"]).await?;
+            collection
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            Ok(())
+        })
+        .await?;
+        let id = collection
+            .add_from(&create_test_doc(0, "alpha beta", 30, vec!["x"]))
+            .await?;
+
+        // Hybrid: the BM25 hit is kept, the mismatched vector part is
+        // dropped with a warning.
+        let ids = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("alpha".to_string()),
+                    vector: Some(vec![0.1; 5]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(ids, vec![id]);
+
+        // Pure vector misuse still surfaces as an error.
+        let err = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    vector: Some(vec![0.1; 5]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::Index { .. }));
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_score_fusion_cutoffs_and_search_with_scores() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_bm25_index_nx(&["name"]).await?;
+            collection
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            Ok(())
+        })
+        .await?;
+        let unit = |i: &[usize]| -> Vector {
+            (0..10)
+                .map(|d| bf16::from_f32(if i.contains(&d) { 1.0 } else { 0.0 }))
+                .collect()
+        };
+        let add = async |name: &str, vector: Vector| {
+            let mut doc = create_test_doc(0, name, 30, vec!["x"]);
+            doc.vector = vector;
+            collection.add_from(&doc).await
+        };
+        // Text ranks `short` first; the vector ranks `long` first.
+        let long = add("alpha beta gamma delta", unit(&[0])).await?;
+        let short = add("alpha", unit(&[1])).await?;
+        let other = add("omega", unit(&[0, 1])).await?;
+
+        let query = |fusion: Option<Fusion>, score_cutoffs: Option<ScoreCutoffs>| Query {
+            search: Some(Search {
+                text: Some("alpha".to_string()),
+                vector: Some(unit(&[0]).iter().map(|v| v.to_f32()).collect()),
+                fusion,
+                score_cutoffs,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let linear = |text: f32, vector: f32| {
+            Some(Fusion::Linear(LinearFusion {
+                weights: FusionWeights {
+                    text,
+                    vector,
+                    sparse: 1.0,
+                },
+                ..Default::default()
+            }))
+        };
+
+        let ids = collection.search_ids(query(linear(1.0, 0.0), None)).await?;
+        assert_eq!(ids[..2], [short, long]);
+        let ids = collection.search_ids(query(linear(0.0, 1.0), None)).await?;
+        assert_eq!(ids, vec![long, other, short]);
+
+        let scored = collection
+            .search_with_scores(query(linear(1.0, 0.0), None))
+            .await?;
+        assert_eq!(scored[0].0.id(), short);
+        assert_eq!(scored[0].1, 1.0);
+        assert!(scored.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        // A text threshold no hit reaches leaves the vector ranking alone.
+        let text_cutoff = ScoreCutoffs {
+            min_text_score: Some(1e9),
+            ..Default::default()
+        };
+        let ids = collection
+            .search_ids(query(None, Some(text_cutoff.clone())))
+            .await?;
+        assert_eq!(ids, vec![long, other, short]);
+        let ids = collection
+            .search_ids(query(
+                None,
+                Some(ScoreCutoffs {
+                    max_vector_distance: Some(-1.0),
+                    ..text_cutoff
+                }),
+            ))
+            .await?;
+        assert!(ids.is_empty());
+
+        let err = collection
+            .search_ids(query(Some(RRFReranker { k: 0.0 }.into()), None))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::Generic { .. }));
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_phrase_with_positional_bm25() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection
+                .create_bm25_index_with_nx(
+                    &["name"],
+                    BM25Config {
+                        positions: true,
+                        ..Default::default()
+                    },
+                )
+                .await
+        })
+        .await?;
+        let add = async |name: &str| {
+            collection
+                .add_from(&create_test_doc(0, name, 30, vec![]))
+                .await
+        };
+        let phrase = add("machine learning basics").await?;
+        let apart = add("learning about the machine tools").await?;
+        assert!(
+            collection
+                .get_bm25_index(&["name"])?
+                .metadata()
+                .config
+                .positions
+        );
+
+        let search = async |text: &str| {
+            collection
+                .search_ids(Query {
+                    search: Some(Search {
+                        text: Some(text.to_string()),
+                        logical_search: true,
+                        ..Default::default()
+                    }),
+                    limit: Some(10),
+                    ..Default::default()
+                })
+                .await
+        };
+        let mut terms = search("machine learning").await?;
+        terms.sort_unstable();
+        assert_eq!(terms, vec![phrase, apart]);
+        assert_eq!(search("\"machine learning\"").await?, vec![phrase]);
+        assert_eq!(search("learning NEAR/1 machine").await?, vec![phrase]);
+        let mut near = search("learning NEAR/2 machine").await?;
+        near.sort_unstable();
+        assert_eq!(near, vec![phrase, apart]);
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_prefix_wildcard_and_fuzzy_terms() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_bm25_index_nx(&["name"]).await
+        })
+        .await?;
+        let add = async |name: &str| {
+            collection
+                .add_from(&create_test_doc(0, name, 30, vec![]))
+                .await
+        };
+        let memory = add("memory systems").await?;
+        let memoir = add("rust memoir").await?;
+        add("agent workflows").await?;
+
+        let search = async |text: &str, logical_search: bool| {
+            let mut ids = collection
+                .search_ids(Query {
+                    search: Some(Search {
+                        text: Some(text.to_string()),
+                        logical_search,
+                        ..Default::default()
+                    }),
+                    limit: Some(10),
+                    ..Default::default()
+                })
+                .await?;
+            ids.sort_unstable();
+            Ok::<_, DBError>(ids)
+        };
+        assert_eq!(search("memo*", true).await?, vec![memory, memoir]);
+        assert_eq!(search("mem?ir", true).await?, vec![memoir]);
+        assert_eq!(search("sytems~1", true).await?, vec![memory]);
+        // Patterns need logical search; otherwise `*` is dropped by the
+        // tokenizer and the word must match exactly.
+        assert!(search("memo*", false).await?.is_empty());
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_bm25f_field_boosts() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            let err = collection
+                .create_bm25_index_with(
+                    &["name", "tags"],
+                    BM25Config {
+                        field_boosts: vec![1.0],
+                        ..Default::default()
+                    },
+                )
+                .await;
+            assert!(matches!(err, Err(DBError::Schema { .. })));
+
+            collection
+                .create_bm25_index_with_nx(
+                    &["name", "tags"],
+                    BM25Config {
+                        field_boosts: vec![3.0, 1.0],
+                        ..Default::default()
+                    },
+                )
+                .await
+        })
+        .await?;
+        let in_name = collection
+            .add_from(&create_test_doc(0, "rust", 30, vec!["storage", "engines"]))
+            .await?;
+        let in_tags = collection
+            .add_from(&create_test_doc(0, "storage", 30, vec!["rust", "engines"]))
+            .await?;
+        assert_eq!(
+            collection
+                .get_bm25_index(&["name", "tags"])?
+                .metadata()
+                .config
+                .field_boosts,
+            vec![3.0, 1.0]
+        );
+
+        let search = async |field_boosts: Option<BTreeMap<String, f32>>| {
+            collection
+                .search_ids(Query {
+                    search: Some(Search {
+                        text: Some("rust".to_string()),
+                        field_boosts,
+                        ..Default::default()
+                    }),
+                    limit: Some(10),
+                    ..Default::default()
+                })
+                .await
+        };
+        assert_eq!(search(None).await?, vec![in_name, in_tags]);
+        let boosts = BTreeMap::from([("name".to_string(), 0.1), ("tags".to_string(), 5.0)]);
+        assert_eq!(search(Some(boosts)).await?, vec![in_tags, in_name]);
+
+        let unknown = BTreeMap::from([("age".to_string(), 1.0)]);
+        assert!(search(Some(unknown)).await.is_err());
+        let negative = BTreeMap::from([("name".to_string(), -1.0)]);
+        assert!(search(Some(negative)).await.is_err());
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_hits_with_highlight() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_bm25_index_nx(&["name", "tags"]).await
+        })
+        .await?;
+        let rust = collection
+            .add_from(&create_test_doc(
+                0,
+                "Rust agents remember",
+                30,
+                vec!["memory", "rust"],
+            ))
+            .await?;
+        collection
+            .add_from(&create_test_doc(0, "Python scripts", 30, vec!["tooling"]))
+            .await?;
+
+        let query = |highlight: Option<Highlight>| Query {
+            search: Some(Search {
+                text: Some("rust memory".to_string()),
+                highlight,
+                ..Default::default()
+            }),
+            limit: Some(10),
+            ..Default::default()
+        };
+        let hits = collection.search_hits(query(None)).await?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].doc.id(), rust);
+        assert!(hits[0].score > 0.0);
+        assert!(hits[0].highlight.is_none());
+
+        let hits = collection
+            .search_hits(query(Some(Highlight::default())))
+            .await?;
+        let highlight = hits[0].highlight.as_ref().unwrap();
+        assert_eq!(highlight.len(), 2);
+        let name = &highlight["name"][0];
+        assert_eq!(name.text, "Rust agents remember");
+        assert_eq!(name.highlights, vec![0..4]);
+        // Array values are highlighted on their newline-joined text.
+        let tags = &highlight["tags"][0];
+        assert_eq!(tags.text, "memory\nrust");
+        assert_eq!(tags.highlights, vec![0..6, 7..11]);
+
+        let hits = collection
+            .search_hits(query(Some(Highlight {
+                fields: vec!["tags".to_string()],
+                ..Default::default()
+            })))
+            .await?;
+        let highlight = hits[0].highlight.as_ref().unwrap();
+        assert_eq!(highlight.keys().collect::<Vec<_>>(), vec!["tags"]);
+
+        let unknown = Highlight {
+            fields: vec!["age".to_string()],
+            ..Default::default()
+        };
+        assert!(collection.search_hits(query(Some(unknown))).await.is_err());
+        let no_text = Query {
+            search: Some(Search {
+                highlight: Some(Highlight::default()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(collection.search_hits(no_text).await.is_err());
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_mmr_diversifies_vector_results() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            Ok(())
+        })
+        .await?;
+        let vector = |values: &[(usize, f32)]| -> Vector {
+            let mut vector = vec![bf16::ZERO; 10];
+            for (d, v) in values {
+                vector[*d] = bf16::from_f32(*v);
+            }
+            vector
+        };
+        let add = async |name: &str, vector: Vector| {
+            let mut doc = create_test_doc(0, name, 30, vec!["x"]);
+            doc.vector = vector;
+            collection.add_from(&doc).await
+        };
+        // `twin` nearly duplicates `exact`; `apart` is farther from the
+        // query but unlike both.
+        let exact = add("exact", vector(&[(0, 1.0)])).await?;
+        let twin = add("twin", vector(&[(0, 1.0), (1, 0.125)])).await?;
+        let apart = add("apart", vector(&[(1, 1.0)])).await?;
+
+        let query = |mmr: Option<MmrOptions>| Query {
+            search: Some(Search {
+                vector: Some(vector(&[(0, 1.0)]).iter().map(|v| v.to_f32()).collect()),
+                mmr,
+                ..Default::default()
+            }),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mmr = |lambda: f32| {
+            Some(MmrOptions {
+                lambda,
+                candidates: Some(10),
+            })
+        };
+
+        assert_eq!(collection.search_ids(query(None)).await?, vec![exact, twin]);
+        assert_eq!(
+            collection.search_ids(query(mmr(1.0))).await?,
+            vec![exact, twin]
+        );
+        assert_eq!(
+            collection.search_ids(query(mmr(0.3))).await?,
+            vec![exact, apart]
+        );
+
+        let err = collection.search_ids(query(mmr(1.5))).await.unwrap_err();
+        assert!(matches!(err, DBError::Generic { .. }));
+        let err = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("exact".to_string()),
+                    mmr: mmr(0.5),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::Generic { .. }));
+
+        db.close().await?;
+        Ok(())
+    }
+
+    /// Deterministic stand-in for a cross-encoder: the longer the name, the
+    /// higher the score. Records the size of the last candidate pool.
+    #[derive(Default)]
+    struct NameLengthReranker {
+        last_pool: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl DocumentReranker for NameLengthReranker {
+        async fn rerank(&self, query: &str, docs: &[Document]) -> Result<Vec<f32>, BoxError> {
+            assert_eq!(query, "alpha");
+            self.last_pool.store(docs.len(), Ordering::SeqCst);
+            docs.iter()
+                .map(|doc| Ok(doc.get_field_as::<String>("name")?.len() as f32))
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_rerank_stage() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let reranker = Arc::new(NameLengthReranker::default());
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_bm25_index_nx(&["name"]).await?;
+            collection.set_reranker("length", reranker.clone());
+            Ok(())
+        })
+        .await?;
+        let mut ids = Vec::new();
+        for name in ["alpha", "alpha b", "alpha c c", "alpha d d d", "omega"] {
+            ids.push(
+                collection
+                    .add_from(&create_test_doc(0, name, 30, vec!["x"]))
+                    .await?,
+            );
+        }
+        assert!(collection.has_reranker("length"));
+        let query = |rerank: RerankOptions, limit: usize| Query {
+            search: Some(Search {
+                text: Some("alpha".to_string()),
+                rerank: Some(rerank),
+                ..Default::default()
+            }),
+            limit: Some(limit),
+            ..Default::default()
+        };
+        let length = || RerankOptions {
+            name: "length".to_string(),
+            ..Default::default()
+        };
+
+        // BM25 prefers the shortest match; the reranker the longest.
+        let plain = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("alpha".to_string()),
+                    ..Default::default()
+                }),
+                limit: Some(2),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(plain, vec![ids[0], ids[1]]);
+        assert_eq!(
+            collection.search_ids(query(length(), 2)).await?,
+            vec![ids[3], ids[2]]
+        );
+        assert_eq!(reranker.last_pool.load(Ordering::SeqCst), 4);
+
+        // The pool is cut before reranking and never below the limit.
+        let pooled = RerankOptions {
+            candidates: Some(1),
+            ..length()
+        };
+        assert_eq!(
+            collection.search_ids(query(pooled, 2)).await?,
+            vec![ids[1], ids[0]]
+        );
+        assert_eq!(reranker.last_pool.load(Ordering::SeqCst), 2);
+
+        let scored = collection.search_with_scores(query(length(), 1)).await?;
+        assert_eq!(scored.len(), 1);
+        assert_eq!(scored[0].0.id(), ids[3]);
+        assert_eq!(scored[0].1, "alpha d d d".len() as f32);
+
+        let projected = collection
+            .search(Query {
+                fields: Some(vec!["name".to_string()]),
+                ..query(length(), 1)
+            })
+            .await?;
+        assert_eq!(
+            projected[0].get_field("name"),
+            Some(&Fv::Text("alpha d d d".into()))
+        );
+        assert!(projected[0].get_field("age").is_none());
+
+        let page = collection.search_page(query(length(), 2)).await?;
+        let page_ids: Vec<_> = page.items.iter().map(|doc| doc.id()).collect();
+        assert_eq!(page_ids, vec![ids[3], ids[2]]);
+        assert!(page.cursor.is_none());
+        assert!(!page.truncated);
+
+        let unknown = RerankOptions {
+            name: "missing".to_string(),
+            ..Default::default()
+        };
+        assert!(collection.search_ids(query(unknown, 2)).await.is_err());
+        let ordered = Query {
+            order_by: Some(OrderBy {
+                field: "name".to_string(),
+                descending: false,
+            }),
+            ..query(length(), 2)
+        };
+        assert!(collection.search_ids(ordered).await.is_err());
+
+        db.close().await?;
+        Ok(())
+    }
+
+    // Document with a signed counter, for the I64/U64 read-back regressions.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, AndaDBSchema)]
+    struct CounterDoc {
+        pub _id: u64,
+        pub name: String,
+        pub count: i64,
+    }
+
+    fn counter_config() -> CollectionConfig {
+        CollectionConfig {
+            name: "counters".to_string(),
+            description: "signed counters".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Regression (#1/#9, cases a+b): a non-negative i64 field reads back
+    /// from storage as U64. `update` must retire the old B-tree entry (no
+    /// phantom match on the old value) and `remove` must not leak entries.
+    #[tokio::test]
+    async fn test_i64_btree_index_update_and_remove_after_read_back() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = db
+            .open_or_create_collection(CounterDoc::schema()?, counter_config(), async |c| {
+                c.create_btree_index_nx(&["count"]).await?;
+                Ok(())
+            })
+            .await?;
+
+        let id = collection
+            .add_from(&CounterDoc {
+                _id: 0,
+                name: "a".to_string(),
+                count: 5,
+            })
+            .await?;
+
+        // (a) update: the old document is materialized from storage where
+        // `count: 5` reads back as U64(5); the old index entry must still be
+        // retired.
+        collection
+            .update(id, BTreeMap::from([("count".to_string(), Fv::I64(7))]))
+            .await?;
+        let stale = collection
+            .query_ids(
+                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(5)))),
+                Some(10),
+            )
+            .await?;
+        assert!(
+            stale.is_empty(),
+            "stale index entry for the old value must be removed, got {stale:?}",
+        );
+        let current = collection
+            .query_ids(
+                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(7)))),
+                Some(10),
+            )
+            .await?;
+        assert_eq!(current, vec![id]);
+
+        // (b) remove: the index entry must be dropped, not leaked.
+        assert!(collection.remove(id).await?.is_some());
+        let leaked = collection
+            .query_ids(
+                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(7)))),
+                Some(10),
+            )
+            .await?;
+        assert!(leaked.is_empty(), "removed document must leave no entries");
+        assert_eq!(
+            collection.get_btree_index(&["count"])?.stats().num_elements,
+            0,
+        );
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recorded_tokenizer_is_rebuilt_on_open() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let config = CollectionConfig {
+            name: "notes".to_string(),
+            description: "Notes".to_string(),
+            tokenizer: Some(TokenizerConfig::jieba()),
+            ..Default::default()
+        };
+        let collection = db
+            .create_collection(TestDoc::schema()?, config, async |c| {
+                c.create_bm25_index(&["name"]).await?;
+                // An index can record its own tokenizer.
+                c.create_bm25_index_with(
+                    &["tags"],
+                    BM25Config {
+                        tokenizer: Some(TokenizerConfig::default()),
+                        ..Default::default()
+                    },
+                )
+                .await
+            })
+            .await?;
+        let id = collection
+            .add_from(&create_test_doc(0, "长期记忆", 30, vec!["长期记忆"]))
+            .await?;
+        let recorded = |collection: &Collection, field: &str| {
+            collection
+                .get_bm25_index(&[field])
+                .unwrap()
+                .metadata()
+                .config
+                .tokenizer
+        };
+        assert_eq!(
+            recorded(&collection, "name"),
+            Some(TokenizerConfig::jieba())
+        );
+        assert_eq!(
+            recorded(&collection, "tags"),
+            Some(TokenizerConfig::default())
+        );
+        collection.flush(unix_ms()).await?;
+        drop(collection);
+        db.close_collection("notes").await?;
+
+        let search = |collection: &Collection, field: &str| {
+            collection
+                .get_bm25_index(&[field])
+                .unwrap()
+                .search("记忆", 10, None)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>()
+        };
+        // Reopened without any setup, each index rebuilds its recorded
+        // tokenizer: jieba segments "长期记忆", the default tokenizer does not.
+        let collection = db
+            .open_collection("notes".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(search(&collection, "name"), vec![id]);
+        assert!(search(&collection, "tags").is_empty());
+        drop(collection);
+        db.close_collection("notes").await?;
+
+        // A new registered version refuses indexes built by the old one.
+        db.register_tokenizer(TokenizerConfig {
+            version: 2,
+            ..TokenizerConfig::jieba()
+        })?;
+        let err = db
+            .open_collection("notes".to_string(), async |_| Ok(()))
+            .await;
+        assert!(matches!(err, Err(DBError::Index { .. })));
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_tokenizer_is_recorded() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |c| {
+            c.create_bm25_index(&["name"]).await?;
+            // An unnamed chain cannot be recorded.
+            c.set_tokenizer(jieba_tokenizer());
+            c.create_bm25_index(&["tags"]).await
+        })
+        .await?;
+        let recorded = |field: &str| {
+            collection
+                .get_bm25_index(&[field])
+                .unwrap()
+                .metadata()
+                .config
+                .tokenizer
+        };
+        assert_eq!(recorded("name"), Some(TokenizerConfig::default()));
+        assert_eq!(recorded("tags"), None);
+        collection.flush(unix_ms()).await?;
+        drop(collection);
+        db.close_collection("test_collection").await?;
+
+        // Changing the default definition is detected on open.
+        db.register_tokenizer(TokenizerConfig {
+            version: 2,
+            ..TokenizerConfig::default()
+        })?;
+        let err = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await;
+        assert!(matches!(err, Err(DBError::Index { .. })));
+
+        db.close().await?;
+        Ok(())
+    }
+
+    /// Regression (#1/#9, case c): creating a B-tree index over existing
+    /// non-negative i64 data backfills from storage, where the values read
+    /// back as U64 — index creation must succeed and the index must be
+    /// queryable.
+    #[tokio::test]
+    async fn test_i64_btree_index_backfill_over_existing_data() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = db
+            .open_or_create_collection(CounterDoc::schema()?, counter_config(), async |_| Ok(()))
+            .await?;
+        let mut ids = Vec::new();
+        for (i, count) in [5i64, -2, 0].into_iter().enumerate() {
+            ids.push(
+                collection
+                    .add_from(&CounterDoc {
+                        _id: 0,
+                        name: format!("doc{i}"),
+                        count,
+                    })
+                    .await?,
+            );
+        }
+        collection.flush(unix_ms()).await?;
+        drop(collection);
+        db.close_collection("counters").await?;
+
+        // Reopen from storage and build the index over the existing data.
+        let collection = db
+            .open_collection("counters".to_string(), async |c| {
+                c.create_btree_index(&["count"]).await?;
+                Ok(())
+            })
+            .await?;
+        let found = collection
+            .query_ids(
+                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(5)))),
+                Some(10),
+            )
+            .await?;
+        assert_eq!(found, vec![ids[0]]);
+        let found = collection
+            .query_ids(
+                Filter::Field(("count".to_string(), RangeQuery::Eq(Fv::I64(-2)))),
+                Some(10),
+            )
+            .await?;
+        assert_eq!(found, vec![ids[1]]);
+
+        db.close().await?;
+        Ok(())
+    }
+
+    /// Regression (#18): `save_extension` / `remove_extension` persist with
+    /// a single metadata put ("Ok means persisted") and must not advance the
+    /// flush watermark — the next full flush still runs its complete path.
+    #[tokio::test]
+    async fn test_save_extension_single_put_without_claiming_flush() -> Result<(), DBError> {
+        let object_store = Arc::new(InMemory::new());
+        let db_config = DBConfig {
+            name: "ext_db".to_string(),
+            description: "extension persistence".to_string(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let db = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
+        let collection = db
+            .open_or_create_collection(CounterDoc::schema()?, counter_config(), async |_| Ok(()))
+            .await?;
+
+        let puts_before = collection.storage_stats().total_put_count;
+        collection
+            .save_extension("k".to_string(), Fv::Text("v".to_string()))
+            .await?;
+        assert_eq!(
+            collection.storage_stats().total_put_count,
+            puts_before + 1,
+            "save_extension must perform exactly one metadata put",
+        );
+
+        // Persisted immediately: a second database instance reads it back
+        // without any flush on the first one.
+        {
+            let db2 = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
+            let c2 = db2
+                .open_collection("counters".to_string(), async |_| Ok(()))
+                .await?;
+            assert_eq!(c2.get_extension("k"), Some(Fv::Text("v".to_string())));
+        }
+
+        // The unclaimed write did not advance `last_saved_version`: the next
+        // full flush still persists metadata + ids, and only then does the
+        // fast path apply.
+        assert!(collection.flush(unix_ms()).await?);
+        assert!(!collection.flush(unix_ms()).await?);
+
+        // remove_extension mirrors the single-put behaviour.
+        let puts_before = collection.storage_stats().total_put_count;
+        let old = collection.remove_extension("k").await?;
+        assert_eq!(old, Some(Fv::Text("v".to_string())));
+        assert_eq!(
+            collection.storage_stats().total_put_count,
+            puts_before + 1,
+            "remove_extension must perform exactly one metadata put",
+        );
+        assert!(collection.get_extension("k").is_none());
+        // Removing a missing key writes nothing.
+        let puts_before = collection.storage_stats().total_put_count;
+        assert!(collection.remove_extension("missing").await?.is_none());
+        assert_eq!(collection.storage_stats().total_put_count, puts_before);
+
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_open_repair_scan_skips_unreadable_and_mismatched_documents() -> Result<(), DBError>
+    {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |_| Ok(())).await?;
+        let id1 = collection
+            .add_from(&create_test_doc(0, "Alice", 30, vec!["a"]))
+            .await?;
+        collection.flush(unix_ms()).await?; // persisted checkpoint = 1
+
+        // id 2: corrupt CBOR object inside the repair scan window.
+        collection
+            .storage
+            .put_bytes(
+                &Collection::doc_path(2),
+                Bytes::from_static(b"not valid cbor"),
+                PutMode::Overwrite,
+            )
+            .await?;
+        // id 3: valid CBOR that does not match the collection schema.
+        let bad_doc = DocumentOwned {
+            fields: BTreeMap::from([(0usize, Fv::Text("not an id".to_string()))]),
+        };
+        collection
+            .storage
+            .create(&Collection::doc_path(3), &bad_doc)
+            .await?;
+        // id 4: a valid orphan document, recoverable.
+        let orphan = Document::try_from(
+            collection.schema(),
+            &create_test_doc(4, "Carol", 40, vec!["c"]),
+        )?;
+        collection
+            .storage
+            .create(&Collection::doc_path(4), &orphan)
+            .await?;
+
+        // Reopening must not fail on the corrupt or mismatched objects
+        // (previously a schema mismatch bricked the whole collection open),
+        // and the valid orphan behind them must still be recovered.
+        drop(collection);
+        let db = reconnect_test_db(db).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert!(collection.contains(id1));
+        assert!(!collection.contains(2));
+        assert!(!collection.contains(3));
+        assert!(collection.contains(4));
+        let recovered: TestDoc = collection.get_as(4).await?;
+        assert_eq!(recovered.name, "Carol");
+
+        db.close().await?;
+        Ok(())
+    }
+
+    /// An object store that blocks `put` for paths ending in `gate_suffix`
+    /// until the watch gate is opened, to deterministically hold an `add`
+    /// in flight while a flush runs.
+    #[derive(Debug)]
+    struct GatedPutStore {
+        inner: Arc<InMemory>,
+        gate_suffix: String,
+        gate: tokio::sync::watch::Receiver<bool>,
+        blocked: Arc<TestAtomicBool>,
+    }
+
+    impl fmt::Display for GatedPutStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("GatedPutStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for GatedPutStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> ObjectStoreResult<PutResult> {
+            if location.to_string().ends_with(&self.gate_suffix) {
+                let mut rx = self.gate.clone();
+                while !*rx.borrow() {
+                    self.blocked.store(true, TestOrdering::Release);
+                    rx.changed()
+                        .await
+                        .map_err(|_| object_store::Error::Generic {
+                            store: "gated_put",
+                            source: "gate sender dropped".into(),
+                        })?;
+                }
+            }
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> ObjectStoreResult<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        fn delete_stream(
+            &self,
+            locations: BoxStream<'static, ObjectStoreResult<Path>>,
+        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
+            self.inner.delete_stream(locations)
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        fn list_with_offset(
+            &self,
+            prefix: Option<&Path>,
+            offset: &Path,
+        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list_with_offset(prefix, offset)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> ObjectStoreResult<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy_opts(
+            &self,
+            from: &Path,
+            to: &Path,
+            options: CopyOptions,
+        ) -> ObjectStoreResult<()> {
+            self.inner.copy_opts(from, to, options).await
+        }
+    }
+
+    #[derive(Debug)]
+    enum PutFault {
+        FailOnce {
+            armed: Arc<TestAtomicBool>,
+        },
+        BlockAfterCommit {
+            gate: tokio::sync::watch::Receiver<bool>,
+            blocked: Arc<TestAtomicBool>,
+        },
+    }
+
+    /// Injects one path-specific PUT failure, or blocks after the delegated
+    /// PUT is already durable but before success is returned to the caller.
+    #[derive(Debug)]
+    struct FaultPutStore {
+        inner: Arc<InMemory>,
+        suffix: String,
+        fault: PutFault,
+    }
+
+    impl fmt::Display for FaultPutStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("FaultPutStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for FaultPutStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> ObjectStoreResult<PutResult> {
+            let matches = location.to_string().ends_with(&self.suffix);
+            if matches
+                && let PutFault::FailOnce { armed } = &self.fault
+                && armed.swap(false, TestOrdering::AcqRel)
+            {
+                return Err(object_store::Error::Generic {
+                    store: "fault_put",
+                    source: "injected one-shot PUT failure".into(),
+                });
+            }
+
+            let result = self.inner.put_opts(location, payload, opts).await?;
+            if matches && let PutFault::BlockAfterCommit { gate, blocked } = &self.fault {
+                let mut rx = gate.clone();
+                while !*rx.borrow() {
+                    blocked.store(true, TestOrdering::Release);
+                    rx.changed()
+                        .await
+                        .map_err(|_| object_store::Error::Generic {
+                            store: "fault_put",
+                            source: "gate sender dropped".into(),
+                        })?;
+                }
+            }
+            Ok(result)
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> ObjectStoreResult<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        fn delete_stream(
+            &self,
+            locations: BoxStream<'static, ObjectStoreResult<Path>>,
+        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
+            self.inner.delete_stream(locations)
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        fn list_with_offset(
+            &self,
+            prefix: Option<&Path>,
+            offset: &Path,
+        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list_with_offset(prefix, offset)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> ObjectStoreResult<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy_opts(
+            &self,
+            from: &Path,
             to: &Path,
             options: CopyOptions,
         ) -> ObjectStoreResult<()> {
             self.inner.copy_opts(from, to, options).await
         }
-    }
+    }
+
+    /// Regression (P0-04): F1 is held after serializing its old ids snapshot
+    /// but before the conditional ids PUT completes. An add and F2 queue
+    /// behind the collection-wide gate in that order. Once released, F1 must
+    /// publish its version, the add completes, and only then may F2 take the
+    /// new snapshot/version and advance the checkpoint.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_flushes_bind_checkpoint_to_serialized_ids_generation()
+    -> Result<(), DBError> {
+        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
+        let blocked = Arc::new(TestAtomicBool::new(false));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
+            inner: Arc::new(InMemory::new()),
+            gate_suffix: "test_collection/ids.cbor".to_string(),
+            gate: gate_rx,
+            blocked: blocked.clone(),
+        });
+        let db_config = || DBConfig {
+            name: "test_db".to_string(),
+            description: String::new(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
+        let collection = create_test_collection(&db, async |_| Ok(())).await?;
+        assert_eq!(
+            collection
+                .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
+                .await?,
+            1
+        );
+
+        gate_tx.send(false).expect("gate receiver dropped");
+        let first_flush = {
+            let collection = collection.clone();
+            tokio::spawn(async move { collection.flush(unix_ms()).await })
+        };
+        while !blocked.load(TestOrdering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+
+        // Queue the mutation before F2. Tokio's fair RwLock makes F2 observe
+        // the completed mutation instead of overtaking it with a stale ids
+        // snapshot and then treating a CAS conflict as success.
+        let adding = {
+            let collection = collection.clone();
+            tokio::spawn(async move {
+                collection
+                    .add_from(&create_test_doc(0, "two", 21, vec!["two"]))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let second_flush = {
+            let collection = collection.clone();
+            tokio::spawn(async move { collection.flush(unix_ms()).await })
+        };
+        assert!(!adding.is_finished());
+        assert!(!second_flush.is_finished());
+
+        gate_tx.send(true).expect("gate receiver dropped");
+        assert!(first_flush.await.expect("first flush panicked")?);
+        assert_eq!(adding.await.expect("add task panicked")?, 2);
+        assert!(second_flush.await.expect("second flush panicked")?);
+        assert!(collection.storage.stats().check_point >= 2);
+
+        drop(collection);
+        drop(db);
+        let db = AndaDB::connect(object_store, db_config()).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.ids(), vec![1, 2]);
+        db.close().await?;
+        Ok(())
+    }
+
+    /// Once collection metadata is durable, a failure in ids.cbor poisons the
+    /// handle: the checkpoint is a sequence of dependent writes and the
+    /// in-memory watermarks no longer describe what is durable. Reopening
+    /// converges from the WAL and the repair scan; no document is lost.
+    #[tokio::test]
+    async fn test_failed_ids_phase_poisons_handle_and_reopen_converges() -> Result<(), DBError> {
+        let armed = Arc::new(TestAtomicBool::new(false));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
+            inner: Arc::new(InMemory::new()),
+            suffix: "test_collection/ids.cbor".to_string(),
+            fault: PutFault::FailOnce {
+                armed: armed.clone(),
+            },
+        });
+        let config = DBConfig {
+            name: "test_db".to_string(),
+            description: String::new(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
+        let collection = create_test_collection(&db, async |_| Ok(())).await?;
+        collection
+            .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
+            .await?;
+        collection.flush(unix_ms()).await?;
+        collection
+            .add_from(&create_test_doc(0, "two", 21, vec!["two"]))
+            .await?;
+
+        let same_ms = unix_ms();
+        armed.store(true, TestOrdering::Release);
+        assert!(collection.flush(same_ms).await.is_err());
+        assert!(collection.is_poisoned());
+        // Every further operation on the poisoned handle is rejected.
+        let err = collection
+            .flush(same_ms)
+            .await
+            .expect_err("poisoned handle must reject flush");
+        assert!(err.is_poisoned(), "{err:?}");
+        assert_eq!(err.collection_state(), Some(CollectionState::Poisoned));
+        assert!(
+            collection
+                .add_from(&create_test_doc(0, "three", 22, vec!["three"]))
+                .await
+                .is_err()
+        );
+
+        // Reopening through the same database discards the poisoned handle
+        // (without flushing it) and loads a fresh generation. The WAL replay
+        // recovers document 2 and the flush inside `open_collection` already
+        // persists the converged checkpoint and retires the WAL.
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.ids(), vec![1, 2]);
+        assert!(collection.pending_mutations.lock().is_empty());
+        let mut intents = collection
+            .storage
+            .list_meta(Some(Collection::MUTATION_INTENT_PREFIX), None);
+        assert!(intents.next().await.is_none(), "WAL retired after reopen");
+        assert!(collection.storage.stats().check_point >= 2);
+
+        drop(collection);
+        db.close().await?;
+        let db = AndaDB::connect(object_store, config).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.ids(), vec![1, 2]);
+        db.close().await?;
+        Ok(())
+    }
+
+    /// A conditional metadata PUT can commit before its future reports
+    /// success. Aborting at that boundary poisons the handle (cancellation is
+    /// treated as a crash); reopening loads the durable state and the
+    /// retained WAL converges ids and indexes without losing the document.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_cancelled_metadata_put_poisons_handle_and_reopen_converges() -> Result<(), DBError>
+    {
+        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
+        let blocked = Arc::new(TestAtomicBool::new(false));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
+            inner: Arc::new(InMemory::new()),
+            suffix: "test_collection/meta.cbor".to_string(),
+            fault: PutFault::BlockAfterCommit {
+                gate: gate_rx,
+                blocked: blocked.clone(),
+            },
+        });
+        let config = DBConfig {
+            name: "test_db".to_string(),
+            description: String::new(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_btree_index_nx(&["name"]).await
+        })
+        .await?;
+        let id = collection
+            .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
+            .await?;
+        assert_eq!(id, 1);
+
+        gate_tx.send(false).expect("gate receiver dropped");
+        let first_now = unix_ms();
+        let flushing = {
+            let collection = collection.clone();
+            tokio::spawn(async move { collection.flush(first_now).await })
+        };
+        while !blocked.load(TestOrdering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+
+        flushing.abort();
+        assert!(
+            flushing
+                .await
+                .expect_err("flush should be cancelled")
+                .is_cancelled()
+        );
+        gate_tx.send(true).expect("gate receiver dropped");
+        assert!(collection.is_poisoned());
+
+        // Every further operation on the poisoned handle is rejected.
+        let err = collection
+            .save_extension("after_cancel".to_string(), Fv::Text("durable".to_string()))
+            .await
+            .expect_err("poisoned handle must reject writes");
+        assert!(err.is_poisoned(), "{err:?}");
+        assert_eq!(err.collection_state(), Some(CollectionState::Poisoned));
+
+        // Reopening through the same database discards the poisoned handle
+        // without flushing it. The watermark-bounded repair scan recovers the
+        // committed document; a full flush then completes a checkpoint.
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.ids(), vec![id]);
+        collection.flush(unix_ms()).await?;
+        let mut intents = collection
+            .storage
+            .list_meta(Some(Collection::MUTATION_INTENT_PREFIX), None);
+        assert!(intents.next().await.is_none(), "WAL retired after reopen");
+        assert!(collection.storage.stats().check_point >= id);
+
+        drop(collection);
+        db.close().await?;
+        let db = AndaDB::connect(object_store, config).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.ids(), vec![id]);
+        let reopened: TestDoc = collection.get_as(id).await?;
+        assert_eq!(reopened.name, "one");
+        assert!(collection.storage.stats().check_point >= id);
+        assert!(collection.pending_mutations.lock().is_empty());
+        db.close().await?;
+        Ok(())
+    }
+
+    /// An immediate metadata-only write has the same post-commit cancellation
+    /// window as a full flush: aborting it poisons the handle. The committed
+    /// extension write is durable and visible after a reopen; the unclaimed
+    /// write never publishes the full-flush watermark.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_cancelled_unclaimed_metadata_put_poisons_handle() -> Result<(), DBError> {
+        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
+        let blocked = Arc::new(TestAtomicBool::new(false));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
+            inner: Arc::new(InMemory::new()),
+            suffix: "test_collection/meta.cbor".to_string(),
+            fault: PutFault::BlockAfterCommit {
+                gate: gate_rx,
+                blocked: blocked.clone(),
+            },
+        });
+        let config = DBConfig {
+            name: "test_db".to_string(),
+            description: String::new(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
+        let collection = create_test_collection(&db, async |_| Ok(())).await?;
+        let saved_before = collection.last_saved_version.load(Ordering::Acquire);
+
+        gate_tx.send(false).expect("gate receiver dropped");
+        let saving = {
+            let collection = collection.clone();
+            tokio::spawn(async move {
+                collection
+                    .save_extension("before_cancel".to_string(), Fv::Text("durable".to_string()))
+                    .await
+            })
+        };
+        while !blocked.load(TestOrdering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+        saving.abort();
+        assert!(
+            saving
+                .await
+                .expect_err("metadata-only writer should be cancelled after commit")
+                .is_cancelled()
+        );
+        gate_tx.send(true).expect("gate receiver dropped");
+        assert!(collection.is_poisoned());
+        assert_eq!(
+            collection.last_saved_version.load(Ordering::Acquire),
+            saved_before,
+            "an unclaimed generation must not publish the full-flush watermark",
+        );
+        assert!(
+            collection
+                .add_from(&create_test_doc(0, "after", 21, vec!["after"]))
+                .await
+                .is_err(),
+            "poisoned handle must reject mutations",
+        );
 
-    #[derive(Debug)]
-    enum PutFault {
-        FailOnce {
-            armed: Arc<TestAtomicBool>,
-        },
-        BlockAfterCommit {
-            gate: tokio::sync::watch::Receiver<bool>,
-            blocked: Arc<TestAtomicBool>,
-        },
-    }
+        // Reopen through the same database: the committed extension write is
+        // durable and visible in the fresh generation, which stays writable.
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(
+            collection.get_extension("before_cancel"),
+            Some(Fv::Text("durable".to_string()))
+        );
+        let id = collection
+            .add_from(&create_test_doc(0, "after", 21, vec!["after"]))
+            .await?;
+        assert!(collection.flush(unix_ms()).await?);
+        assert!(collection.pending_mutations.lock().is_empty());
+        assert!(collection.storage.stats().check_point >= id);
 
-    /// Injects one path-specific PUT failure, or blocks after the delegated
-    /// PUT is already durable but before success is returned to the caller.
-    #[derive(Debug)]
-    struct FaultPutStore {
-        inner: Arc<InMemory>,
-        suffix: String,
-        fault: PutFault,
+        drop(collection);
+        db.close().await?;
+        let db = AndaDB::connect(object_store, config).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.ids(), vec![id]);
+        assert_eq!(
+            collection.get_extension("before_cancel"),
+            Some(Fv::Text("durable".to_string()))
+        );
+        let reopened: TestDoc = collection.get_as(id).await?;
+        assert_eq!(reopened.name, "after");
+        assert!(collection.storage.stats().check_point >= id);
+        db.close().await?;
+        Ok(())
     }
 
-    impl fmt::Display for FaultPutStore {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            f.write_str("FaultPutStore")
-        }
+    /// A second writer replacing the metadata object makes the next flush
+    /// fail with `Precondition` and poisons the handle: single-writer
+    /// violations are never reconciled in place.
+    #[tokio::test]
+    async fn test_foreign_metadata_writer_poisons_handle() -> Result<(), DBError> {
+        let db = AndaDB::connect(
+            Arc::new(InMemory::new()),
+            DBConfig {
+                name: "conflict_db".to_string(),
+                description: String::new(),
+                storage: StorageConfig {
+                    compress_level: 0,
+                    ..Default::default()
+                },
+                lock: None,
+            },
+        )
+        .await?;
+        let collection = create_test_collection(&db, async |_| Ok(())).await?;
+        collection
+            .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
+            .await?;
+        collection.flush(unix_ms()).await?;
+
+        // Simulate a second writer bumping the durable metadata object.
+        let (mut foreign, _) = collection
+            .storage
+            .fetch::<CollectionMetadata>(Collection::METADATA_PATH)
+            .await?;
+        foreign.config.description = "foreign writer".to_string();
+        collection
+            .storage
+            .put(Collection::METADATA_PATH, &foreign, None)
+            .await?;
+
+        collection
+            .add_from(&create_test_doc(0, "two", 21, vec!["two"]))
+            .await?;
+        let err = collection
+            .flush(unix_ms())
+            .await
+            .expect_err("stale CAS token must remain a conflict");
+        assert!(matches!(err, DBError::Precondition { .. }));
+        assert!(collection.is_poisoned());
+        Ok(())
     }
 
-    #[async_trait]
-    impl ObjectStore for FaultPutStore {
-        async fn put_opts(
-            &self,
-            location: &Path,
-            payload: PutPayload,
-            opts: PutOptions,
-        ) -> ObjectStoreResult<PutResult> {
-            let matches = location.to_string().ends_with(&self.suffix);
-            if matches
-                && let PutFault::FailOnce { armed } = &self.fault
-                && armed.swap(false, TestOrdering::AcqRel)
-            {
-                return Err(object_store::Error::Generic {
-                    store: "fault_put",
-                    source: "injected one-shot PUT failure".into(),
-                });
-            }
+    /// An object-store PUT may commit before the caller observes its result.
+    /// Cancelling `add` in that interval poisons the handle; the durable WAL
+    /// intent makes the reopened generation recover the committed document
+    /// instead of skipping it.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_cancelled_add_poisons_handle_and_reopen_recovers_document() -> Result<(), DBError>
+    {
+        let (gate_tx, gate_rx) = tokio::sync::watch::channel(false);
+        let blocked = Arc::new(TestAtomicBool::new(false));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
+            inner: Arc::new(InMemory::new()),
+            suffix: "data/1.cbor".to_string(),
+            fault: PutFault::BlockAfterCommit {
+                gate: gate_rx,
+                blocked: blocked.clone(),
+            },
+        });
+        let config = DBConfig {
+            name: "test_db".to_string(),
+            description: String::new(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_btree_index_nx(&["name"]).await
+        })
+        .await?;
 
-            let result = self.inner.put_opts(location, payload, opts).await?;
-            if matches && let PutFault::BlockAfterCommit { gate, blocked } = &self.fault {
-                let mut rx = gate.clone();
-                while !*rx.borrow() {
-                    blocked.store(true, TestOrdering::Release);
-                    rx.changed()
-                        .await
-                        .map_err(|_| object_store::Error::Generic {
-                            store: "fault_put",
-                            source: "gate sender dropped".into(),
-                        })?;
-                }
-            }
-            Ok(result)
+        let adding = {
+            let collection = collection.clone();
+            tokio::spawn(async move {
+                collection
+                    .add_from(&create_test_doc(0, "committed", 20, vec!["x"]))
+                    .await
+            })
+        };
+        while !blocked.load(TestOrdering::Acquire) {
+            tokio::task::yield_now().await;
         }
+        adding.abort();
+        assert!(
+            adding
+                .await
+                .expect_err("add should be cancelled")
+                .is_cancelled()
+        );
+        gate_tx.send(true).expect("gate receiver dropped");
+        assert!(collection.is_poisoned());
+        assert!(
+            collection
+                .add_from(&create_test_doc(0, "second", 21, vec!["y"]))
+                .await
+                .is_err(),
+            "poisoned handle must reject mutations",
+        );
 
-        async fn put_multipart_opts(
-            &self,
-            location: &Path,
-            opts: PutMultipartOptions,
-        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
-            self.inner.put_multipart_opts(location, opts).await
-        }
+        // Reopen through the same database: WAL replay recovers the committed
+        // document and the id allocator, so the next add gets a fresh id.
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(
+            collection
+                .add_from(&create_test_doc(0, "second", 21, vec!["y"]))
+                .await?,
+            2
+        );
+        collection.flush(unix_ms()).await?;
+        assert_eq!(collection.ids(), vec![1, 2]);
+        assert_eq!(
+            collection
+                .query_ids(
+                    Filter::Field((
+                        "name".to_string(),
+                        RangeQuery::Eq(Fv::Text("committed".to_string())),
+                    )),
+                    Some(10),
+                )
+                .await?,
+            vec![1]
+        );
+
+        drop(collection);
+        drop(db);
+        let db = AndaDB::connect(object_store, config).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.ids(), vec![1, 2]);
+        assert_eq!(collection.get_as::<TestDoc>(1).await?.name, "committed");
+        db.close().await?;
+        Ok(())
+    }
 
-        async fn get_opts(
-            &self,
-            location: &Path,
-            options: GetOptions,
-        ) -> ObjectStoreResult<GetResult> {
-            self.inner.get_opts(location, options).await
-        }
+    /// A cancelled update can leave its proposed in-memory index value
+    /// applied while the document PUT is still blocked. The handle is
+    /// poisoned; the WAL stores both before/after values so the reopened
+    /// generation removes that phantom.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_cancelled_update_poisons_handle_and_reopen_removes_phantom() -> Result<(), DBError>
+    {
+        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
+        let blocked = Arc::new(TestAtomicBool::new(false));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
+            inner: Arc::new(InMemory::new()),
+            gate_suffix: "data/1.cbor".to_string(),
+            gate: gate_rx,
+            blocked: blocked.clone(),
+        });
+        let db = AndaDB::connect(
+            object_store,
+            DBConfig {
+                name: "test_db".to_string(),
+                description: String::new(),
+                storage: StorageConfig {
+                    compress_level: 0,
+                    ..Default::default()
+                },
+                lock: None,
+            },
+        )
+        .await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_btree_index_nx(&["name"]).await
+        })
+        .await?;
+        let id = collection
+            .add_from(&create_test_doc(0, "before", 20, vec!["x"]))
+            .await?;
+        collection.flush(unix_ms()).await?;
 
-        fn delete_stream(
-            &self,
-            locations: BoxStream<'static, ObjectStoreResult<Path>>,
-        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
-            self.inner.delete_stream(locations)
+        gate_tx.send(false).expect("gate receiver dropped");
+        let updating = {
+            let collection = collection.clone();
+            tokio::spawn(async move {
+                collection
+                    .update(
+                        id,
+                        BTreeMap::from([("name".to_string(), Fv::Text("cancelled".to_string()))]),
+                    )
+                    .await
+            })
+        };
+        while !blocked.load(TestOrdering::Acquire) {
+            tokio::task::yield_now().await;
         }
+        updating.abort();
+        assert!(
+            updating
+                .await
+                .expect_err("update should be cancelled")
+                .is_cancelled()
+        );
+        gate_tx.send(true).expect("gate receiver dropped");
+        assert!(collection.is_poisoned());
+        assert!(
+            collection.flush(unix_ms()).await.is_err(),
+            "poisoned handle must reject flush",
+        );
 
-        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
-            self.inner.list(prefix)
-        }
+        // The reopened generation replays the WAL: both historical values are
+        // removed and the document still present in storage is re-indexed.
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        collection.flush(unix_ms()).await?;
+        let before = collection
+            .query_ids(
+                Filter::Field((
+                    "name".to_string(),
+                    RangeQuery::Eq(Fv::Text("before".to_string())),
+                )),
+                Some(10),
+            )
+            .await?;
+        let cancelled = collection
+            .query_ids(
+                Filter::Field((
+                    "name".to_string(),
+                    RangeQuery::Eq(Fv::Text("cancelled".to_string())),
+                )),
+                Some(10),
+            )
+            .await?;
+        assert_eq!(before, vec![id]);
+        assert!(cancelled.is_empty());
+        db.close().await?;
+        Ok(())
+    }
 
-        fn list_with_offset(
-            &self,
-            prefix: Option<&Path>,
-            offset: &Path,
-        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
-            self.inner.list_with_offset(prefix, offset)
-        }
+    /// Recovery must wait until the open callback installs custom hooks.
+    /// Replaying once with default derivation would persist raw `After`
+    /// B-tree/BM25 entries that the custom hook cannot subsequently identify.
+    #[tokio::test]
+    async fn test_mutation_replay_uses_custom_hooks_before_clearing_intent() -> Result<(), DBError>
+    {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let config = DBConfig {
+            name: "custom_replay_db".to_string(),
+            description: String::new(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
+        let collection = db
+            .create_collection(
+                TestDoc::schema()?,
+                CollectionConfig {
+                    name: "documents".to_string(),
+                    description: String::new(),
+                    ..Default::default()
+                },
+                async |collection| {
+                    collection.set_index_hooks(Arc::new(RecoveryCustomHooks));
+                    collection.create_btree_index_nx(&["name"]).await?;
+                    collection.create_bm25_index_nx(&["name"]).await?;
+                    Ok(())
+                },
+            )
+            .await?;
+        let id = collection
+            .add_from(&create_test_doc(0, "Before", 20, vec!["x"]))
+            .await?;
+        collection.flush(unix_ms()).await?;
+        collection
+            .update(
+                id,
+                BTreeMap::from([("name".to_string(), Fv::Text("After".to_string()))]),
+            )
+            .await?;
+        drop(collection);
+        drop(db);
 
-        async fn list_with_delimiter(
-            &self,
-            prefix: Option<&Path>,
-        ) -> ObjectStoreResult<ListResult> {
-            self.inner.list_with_delimiter(prefix).await
-        }
+        let db = AndaDB::connect(object_store, config).await?;
+        let collection = db
+            .open_collection("documents".to_string(), async |collection| {
+                collection.set_index_hooks(Arc::new(RecoveryCustomHooks));
+                Ok(())
+            })
+            .await?;
+        let raw = collection
+            .query_ids(
+                Filter::Field((
+                    "name".to_string(),
+                    RangeQuery::Eq(Fv::Text("After".to_string())),
+                )),
+                Some(10),
+            )
+            .await?;
+        let hooked = collection
+            .query_ids(
+                Filter::Field((
+                    "name".to_string(),
+                    RangeQuery::Eq(Fv::Text("hook:after".to_string())),
+                )),
+                Some(10),
+            )
+            .await?;
+        assert!(raw.is_empty(), "default-hook B-tree value must not survive");
+        assert_eq!(hooked, vec![id]);
 
-        async fn copy_opts(
-            &self,
-            from: &Path,
-            to: &Path,
-            options: CopyOptions,
-        ) -> ObjectStoreResult<()> {
-            self.inner.copy_opts(from, to, options).await
-        }
+        let raw_text = collection
+            .get_bm25_index(&["name"])?
+            .search("after", 10, None);
+        let hooked_text = collection
+            .get_bm25_index(&["name"])?
+            .search("hooktoken", 10, None);
+        assert!(raw_text.iter().all(|(found, _)| *found != id));
+        assert!(hooked_text.iter().any(|(found, _)| *found == id));
+        db.close().await?;
+        Ok(())
     }
 
-    /// Regression (P0-04): F1 is held after serializing its old ids snapshot
-    /// but before the conditional ids PUT completes. An add and F2 queue
-    /// behind the collection-wide gate in that order. Once released, F1 must
-    /// publish its version, the add completes, and only then may F2 take the
-    /// new snapshot/version and advance the checkpoint.
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_concurrent_flushes_bind_checkpoint_to_serialized_ids_generation()
-    -> Result<(), DBError> {
-        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
-        let blocked = Arc::new(TestAtomicBool::new(false));
+    async fn test_flush_drains_in_flight_add_before_checkpoint() -> Result<(), DBError> {
+        let (gate_tx, gate_rx) = tokio::sync::watch::channel(false);
         let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
             inner: Arc::new(InMemory::new()),
-            gate_suffix: "test_collection/ids.cbor".to_string(),
+            gate_suffix: "data/2.cbor".to_string(),
             gate: gate_rx,
-            blocked: blocked.clone(),
+            blocked: Arc::new(TestAtomicBool::new(false)),
         });
         let db_config = || DBConfig {
             name: "test_db".to_string(),
-            description: String::new(),
+            description: "Test database".to_string(),
             storage: StorageConfig {
                 compress_level: 0,
                 ..Default::default()
@@ -7497,74 +12015,75 @@ mod tests {
         };
         let db = AndaDB::connect(object_store.clone(), db_config()).await?;
         let collection = create_test_collection(&db, async |_| Ok(())).await?;
-        assert_eq!(
-            collection
-                .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
-                .await?,
-            1
-        );
 
-        gate_tx.send(false).expect("gate receiver dropped");
-        let first_flush = {
-            let collection = collection.clone();
-            tokio::spawn(async move { collection.flush(unix_ms()).await })
-        };
-        while !blocked.load(TestOrdering::Acquire) {
-            tokio::task::yield_now().await;
-        }
-
-        // Queue the mutation before F2. Tokio's fair RwLock makes F2 observe
-        // the completed mutation instead of overtaking it with a stale ids
-        // snapshot and then treating a CAS conflict as success.
-        let adding = {
+        let id1 = collection
+            .add_from(&create_test_doc(0, "a", 20, vec!["x"]))
+            .await?;
+        assert_eq!(id1, 1);
+        collection.flush(unix_ms()).await?;
+
+        // Start an add whose document write (data/2.cbor) is blocked,
+        // simulating an add still in flight while a flush runs.
+        let blocked = {
             let collection = collection.clone();
             tokio::spawn(async move {
                 collection
-                    .add_from(&create_test_doc(0, "two", 21, vec!["two"]))
+                    .add_from(&create_test_doc(0, "b", 21, vec!["y"]))
                     .await
             })
         };
-        tokio::time::sleep(Duration::from_millis(50)).await;
-        let second_flush = {
+        // Wait until the blocked add has allocated its id.
+        while collection.max_document_id() < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        // A third add completes fully and bumps the metadata version so the
+        // flush below has something to persist.
+        let id3 = collection
+            .add_from(&create_test_doc(0, "c", 22, vec!["z"]))
+            .await?;
+        assert_eq!(id3, 3);
+
+        // A complete flush now owns the collection operation gate
+        // exclusively. It must wait for add(2), rather than checkpointing a
+        // bitmap/index snapshot while that mutation is only half complete.
+        let flushing = {
             let collection = collection.clone();
             tokio::spawn(async move { collection.flush(unix_ms()).await })
         };
-        assert!(!adding.is_finished());
-        assert!(!second_flush.is_finished());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!flushing.is_finished(), "flush must drain the active add");
 
+        // Unblock the in-flight add, let the serialized flush checkpoint the
+        // complete state, then simulate a crash and reopen.
         gate_tx.send(true).expect("gate receiver dropped");
-        assert!(first_flush.await.expect("first flush panicked")?);
-        assert_eq!(adding.await.expect("add task panicked")?, 2);
-        assert!(second_flush.await.expect("second flush panicked")?);
-        assert!(collection.storage.stats().check_point >= 2);
-
+        let id2 = blocked.await.expect("add task panicked")?;
+        assert_eq!(id2, 2);
+        assert!(flushing.await.expect("flush task panicked")?);
+        assert!(collection.storage.stats().check_point >= 3);
         drop(collection);
         drop(db);
+
         let db = AndaDB::connect(object_store, db_config()).await?;
         let collection = db
             .open_collection("test_collection".to_string(), async |_| Ok(()))
             .await?;
-        assert_eq!(collection.ids(), vec![1, 2]);
+        assert!(collection.contains(1));
+        assert!(
+            collection.contains(2),
+            "document written by the in-flight add must be recovered by the repair scan"
+        );
+        assert!(collection.contains(3));
+
         db.close().await?;
         Ok(())
     }
 
-    /// Once collection metadata is durable, a failure in ids.cbor poisons the
-    /// handle: the checkpoint is a sequence of dependent writes and the
-    /// in-memory watermarks no longer describe what is durable. Reopening
-    /// converges from the WAL and the repair scan; no document is lost.
     #[tokio::test]
-    async fn test_failed_ids_phase_poisons_handle_and_reopen_converges() -> Result<(), DBError> {
-        let armed = Arc::new(TestAtomicBool::new(false));
-        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
-            inner: Arc::new(InMemory::new()),
-            suffix: "test_collection/ids.cbor".to_string(),
-            fault: PutFault::FailOnce {
-                armed: armed.clone(),
-            },
-        });
-        let config = DBConfig {
-            name: "test_db".to_string(),
+    async fn test_mutation_intents_replay_update_and_remove_after_crash() -> Result<(), DBError> {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let db_config = || DBConfig {
+            name: "mutation_replay_db".to_string(),
             description: String::new(),
             storage: StorageConfig {
                 compress_level: 0,
@@ -7572,171 +12091,164 @@ mod tests {
             },
             lock: None,
         };
-        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
-        let collection = create_test_collection(&db, async |_| Ok(())).await?;
-        collection
-            .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
+
+        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
+        let collection = db
+            .create_collection(
+                TestDoc::schema()?,
+                CollectionConfig {
+                    name: "documents".to_string(),
+                    description: String::new(),
+                    ..Default::default()
+                },
+                async |c| {
+                    c.create_btree_index_nx(&["name"]).await?;
+                    c.create_bm25_index_nx(&["name"]).await?;
+                    c.create_hnsw_index_nx(
+                        "vector",
+                        HnswConfig {
+                            dimension: 10,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                    Ok(())
+                },
+            )
             .await?;
+
+        let mut first = create_test_doc(0, "before", 20, vec!["old"]);
+        first.vector = vec![bf16::from_f32(0.0); 10];
+        let first_id = collection.add_from(&first).await?;
+        let mut second = create_test_doc(0, "other", 21, vec!["other"]);
+        second.vector = vec![bf16::from_f32(10.0); 10];
+        let second_id = collection.add_from(&second).await?;
         collection.flush(unix_ms()).await?;
+
+        // The document PUT succeeds, but no index flush follows before the
+        // process disappears. The retained intent must make reopen converge
+        // every index to the new document rather than the old checkpoint.
         collection
-            .add_from(&create_test_doc(0, "two", 21, vec!["two"]))
+            .update(
+                first_id,
+                BTreeMap::from([
+                    ("name".to_string(), Fv::Text("middle".to_string())),
+                    (
+                        "vector".to_string(),
+                        Fv::Vector(vec![bf16::from_f32(15.0); 10]),
+                    ),
+                ]),
+            )
             .await?;
-
-        let same_ms = unix_ms();
-        armed.store(true, TestOrdering::Release);
-        assert!(collection.flush(same_ms).await.is_err());
-        assert!(collection.is_poisoned());
-        // Every further operation on the poisoned handle is rejected.
-        let err = collection
-            .flush(same_ms)
-            .await
-            .expect_err("poisoned handle must reject flush");
-        assert!(err.is_poisoned(), "{err:?}");
-        assert_eq!(err.collection_state(), Some(CollectionState::Poisoned));
-        assert!(
-            collection
-                .add_from(&create_test_doc(0, "three", 22, vec!["three"]))
-                .await
-                .is_err()
-        );
-
-        // Reopening through the same database discards the poisoned handle
-        // (without flushing it) and loads a fresh generation. The WAL replay
-        // recovers document 2 and the flush inside `open_collection` already
-        // persists the converged checkpoint and retires the WAL.
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
+        collection
+            .update(
+                first_id,
+                BTreeMap::from([
+                    ("name".to_string(), Fv::Text("after".to_string())),
+                    (
+                        "vector".to_string(),
+                        Fv::Vector(vec![bf16::from_f32(20.0); 10]),
+                    ),
+                ]),
+            )
             .await?;
-        assert_eq!(collection.ids(), vec![1, 2]);
-        assert!(collection.pending_mutations.lock().is_empty());
-        let mut intents = collection
-            .storage
-            .list_meta(Some(Collection::MUTATION_INTENT_PREFIX), None);
-        assert!(intents.next().await.is_none(), "WAL retired after reopen");
-        assert!(collection.storage.stats().check_point >= 2);
-
+        assert!(!collection.pending_mutations.lock().is_empty());
         drop(collection);
-        db.close().await?;
-        let db = AndaDB::connect(object_store, config).await?;
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
-            .await?;
-        assert_eq!(collection.ids(), vec![1, 2]);
-        db.close().await?;
-        Ok(())
-    }
+        drop(db);
 
-    /// A conditional metadata PUT can commit before its future reports
-    /// success. Aborting at that boundary poisons the handle (cancellation is
-    /// treated as a crash); reopening loads the durable state and the
-    /// retained WAL converges ids and indexes without losing the document.
-    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_cancelled_metadata_put_poisons_handle_and_reopen_converges() -> Result<(), DBError>
-    {
-        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
-        let blocked = Arc::new(TestAtomicBool::new(false));
-        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
-            inner: Arc::new(InMemory::new()),
-            suffix: "test_collection/meta.cbor".to_string(),
-            fault: PutFault::BlockAfterCommit {
-                gate: gate_rx,
-                blocked: blocked.clone(),
-            },
-        });
-        let config = DBConfig {
-            name: "test_db".to_string(),
-            description: String::new(),
-            storage: StorageConfig {
-                compress_level: 0,
-                ..Default::default()
-            },
-            lock: None,
-        };
-        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
-        let collection = create_test_collection(&db, async |collection| {
-            collection.create_btree_index_nx(&["name"]).await
-        })
-        .await?;
-        let id = collection
-            .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
+        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
+        let collection = db
+            .open_collection("documents".to_string(), async |_| Ok(()))
             .await?;
-        assert_eq!(id, 1);
 
-        gate_tx.send(false).expect("gate receiver dropped");
-        let first_now = unix_ms();
-        let flushing = {
-            let collection = collection.clone();
-            tokio::spawn(async move { collection.flush(first_now).await })
+        let by_name = |name: &str| Query {
+            filter: Some(Filter::Field((
+                "name".to_string(),
+                RangeQuery::Eq(Fv::Text(name.to_string())),
+            ))),
+            ..Default::default()
         };
-        while !blocked.load(TestOrdering::Acquire) {
-            tokio::task::yield_now().await;
-        }
-
-        flushing.abort();
-        assert!(
-            flushing
-                .await
-                .expect_err("flush should be cancelled")
-                .is_cancelled()
+        assert!(collection.search_ids(by_name("before")).await?.is_empty());
+        assert!(collection.search_ids(by_name("middle")).await?.is_empty());
+        assert_eq!(
+            collection.search_ids(by_name("after")).await?,
+            vec![first_id]
         );
-        gate_tx.send(true).expect("gate receiver dropped");
-        assert!(collection.is_poisoned());
-
-        // Every further operation on the poisoned handle is rejected.
-        let err = collection
-            .save_extension("after_cancel".to_string(), Fv::Text("durable".to_string()))
-            .await
-            .expect_err("poisoned handle must reject writes");
-        assert!(err.is_poisoned(), "{err:?}");
-        assert_eq!(err.collection_state(), Some(CollectionState::Poisoned));
 
-        // Reopening through the same database discards the poisoned handle
-        // without flushing it. The watermark-bounded repair scan recovers the
-        // committed document; a full flush then completes a checkpoint.
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
+        let old_text = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("before".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
             .await?;
-        assert_eq!(collection.ids(), vec![id]);
-        collection.flush(unix_ms()).await?;
-        let mut intents = collection
-            .storage
-            .list_meta(Some(Collection::MUTATION_INTENT_PREFIX), None);
-        assert!(intents.next().await.is_none(), "WAL retired after reopen");
-        assert!(collection.storage.stats().check_point >= id);
+        assert!(!old_text.contains(&first_id));
+        let new_text = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("after".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await?;
+        assert!(new_text.contains(&first_id));
+
+        let hnsw = collection.get_hnsw_index("vector")?;
+        assert_eq!(hnsw.try_search(&[0.0; 10], 1)?[0].0, second_id);
+        assert_eq!(hnsw.try_search(&[20.0; 10], 1)?[0].0, first_id);
+        assert!(collection.pending_mutations.lock().is_empty());
 
+        // Exercise the other terminal state: a remove whose object delete is
+        // durable while the derived indexes are not yet flushed.
+        collection.remove(first_id).await?;
+        assert!(!collection.pending_mutations.lock().is_empty());
         drop(collection);
-        db.close().await?;
-        let db = AndaDB::connect(object_store, config).await?;
+        drop(db);
+
+        let db = AndaDB::connect(object_store, db_config()).await?;
         let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .open_collection("documents".to_string(), async |_| Ok(()))
             .await?;
-        assert_eq!(collection.ids(), vec![id]);
-        let reopened: TestDoc = collection.get_as(id).await?;
-        assert_eq!(reopened.name, "one");
-        assert!(collection.storage.stats().check_point >= id);
+        assert!(!collection.contains(first_id));
+        assert!(collection.search_ids(by_name("after")).await?.is_empty());
+        let removed_text = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("after".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await?;
+        assert!(!removed_text.contains(&first_id));
+        assert!(
+            !collection
+                .get_hnsw_index("vector")?
+                .try_search(&[20.0; 10], 2)?
+                .iter()
+                .any(|(id, _)| *id == first_id)
+        );
         assert!(collection.pending_mutations.lock().is_empty());
+
         db.close().await?;
         Ok(())
     }
 
-    /// An immediate metadata-only write has the same post-commit cancellation
-    /// window as a full flush: aborting it poisons the handle. The committed
-    /// extension write is durable and visible after a reopen; the unclaimed
-    /// write never publishes the full-flush watermark.
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_cancelled_unclaimed_metadata_put_poisons_handle() -> Result<(), DBError> {
+    async fn test_close_drains_update_and_old_handle_cannot_reenable() -> Result<(), DBError> {
         let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
         let blocked = Arc::new(TestAtomicBool::new(false));
-        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
             inner: Arc::new(InMemory::new()),
-            suffix: "test_collection/meta.cbor".to_string(),
-            fault: PutFault::BlockAfterCommit {
-                gate: gate_rx,
-                blocked: blocked.clone(),
-            },
+            gate_suffix: "data/1.cbor".to_string(),
+            gate: gate_rx,
+            blocked: blocked.clone(),
         });
         let config = DBConfig {
-            name: "test_db".to_string(),
+            name: "close_drain_db".to_string(),
             description: String::new(),
             storage: StorageConfig {
                 compress_level: 0,
@@ -7744,1736 +12256,2234 @@ mod tests {
             },
             lock: None,
         };
-        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
-        let collection = create_test_collection(&db, async |_| Ok(())).await?;
-        let saved_before = collection.last_saved_version.load(Ordering::Acquire);
+        let db = AndaDB::connect(object_store, config).await?;
+        let old = db
+            .create_collection(
+                TestDoc::schema()?,
+                CollectionConfig {
+                    name: "documents".to_string(),
+                    description: String::new(),
+                    ..Default::default()
+                },
+                async |_| Ok(()),
+            )
+            .await?;
 
+        let id = old
+            .add_from(&create_test_doc(0, "before", 20, vec!["x"]))
+            .await?;
         gate_tx.send(false).expect("gate receiver dropped");
-        let saving = {
-            let collection = collection.clone();
+        let updating = {
+            let collection = old.clone();
             tokio::spawn(async move {
                 collection
-                    .save_extension("before_cancel".to_string(), Fv::Text("durable".to_string()))
+                    .update(
+                        id,
+                        BTreeMap::from([("name".to_string(), Fv::Text("after".to_string()))]),
+                    )
                     .await
             })
         };
         while !blocked.load(TestOrdering::Acquire) {
             tokio::task::yield_now().await;
         }
-        saving.abort();
+
+        let closing = {
+            let db = db.clone();
+            tokio::spawn(async move { db.close_collection("documents").await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
         assert!(
-            saving
+            !closing.is_finished(),
+            "close must wait for admitted update"
+        );
+
+        // Cancelling close must not create an empty registry slot. Opening the
+        // same name takes over the retiring handle and remains blocked until
+        // its admitted update is drained and flushed.
+        closing.abort();
+        assert!(
+            closing
                 .await
-                .expect_err("metadata-only writer should be cancelled after commit")
+                .expect_err("close should be cancelled")
                 .is_cancelled()
         );
-        gate_tx.send(true).expect("gate receiver dropped");
-        assert!(collection.is_poisoned());
-        assert_eq!(
-            collection.last_saved_version.load(Ordering::Acquire),
-            saved_before,
-            "an unclaimed generation must not publish the full-flush watermark",
-        );
-        assert!(
-            collection
-                .add_from(&create_test_doc(0, "after", 21, vec!["after"]))
+        let opening = {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.open_or_create_collection(
+                    TestDoc::schema()?,
+                    CollectionConfig {
+                        name: "documents".to_string(),
+                        description: String::new(),
+                        ..Default::default()
+                    },
+                    async |_| Ok(()),
+                )
                 .await
-                .is_err(),
-            "poisoned handle must reject mutations",
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(
+            !opening.is_finished(),
+            "open must finish the cancelled close before loading a fresh handle"
         );
 
-        // Reopen through the same database: the committed extension write is
-        // durable and visible in the fresh generation, which stays writable.
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
-            .await?;
-        assert_eq!(
-            collection.get_extension("before_cancel"),
-            Some(Fv::Text("durable".to_string()))
-        );
-        let id = collection
-            .add_from(&create_test_doc(0, "after", 21, vec!["after"]))
-            .await?;
-        assert!(collection.flush(unix_ms()).await?);
-        assert!(collection.pending_mutations.lock().is_empty());
-        assert!(collection.storage.stats().check_point >= id);
+        gate_tx.send(true).expect("gate receiver dropped");
+        let updated = updating.await.expect("update task panicked")?;
+        assert_eq!(updated.get_field("name"), Some(&Fv::Text("after".into())));
+        let fresh = opening.await.expect("open task panicked")?;
+        assert_eq!(fresh.len(), 1);
+        let persisted: TestDoc = fresh.get_as(id).await?;
+        assert_eq!(persisted.name, "after");
+        assert!(!Arc::ptr_eq(&old, &fresh));
 
-        drop(collection);
-        db.close().await?;
-        let db = AndaDB::connect(object_store, config).await?;
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
-            .await?;
-        assert_eq!(collection.ids(), vec![id]);
-        assert_eq!(
-            collection.get_extension("before_cancel"),
-            Some(Fv::Text("durable".to_string()))
+        // The user-controlled read-only flag is reversible only while the
+        // handle's lifecycle lease is active. The retired Arc must never
+        // become a second writer over the same prefix.
+        old.set_read_only(false);
+        assert!(
+            old.add_from(&create_test_doc(0, "zombie", 21, vec!["z"]))
+                .await
+                .is_err()
         );
-        let reopened: TestDoc = collection.get_as(id).await?;
-        assert_eq!(reopened.name, "after");
-        assert!(collection.storage.stats().check_point >= id);
-        db.close().await?;
-        Ok(())
-    }
-
-    /// A second writer replacing the metadata object makes the next flush
-    /// fail with `Precondition` and poisons the handle: single-writer
-    /// violations are never reconciled in place.
-    #[tokio::test]
-    async fn test_foreign_metadata_writer_poisons_handle() -> Result<(), DBError> {
-        let db = AndaDB::connect(
-            Arc::new(InMemory::new()),
-            DBConfig {
-                name: "conflict_db".to_string(),
-                description: String::new(),
-                storage: StorageConfig {
-                    compress_level: 0,
-                    ..Default::default()
-                },
-                lock: None,
-            },
-        )
-        .await?;
-        let collection = create_test_collection(&db, async |_| Ok(())).await?;
-        collection
-            .add_from(&create_test_doc(0, "one", 20, vec!["one"]))
-            .await?;
-        collection.flush(unix_ms()).await?;
-
-        // Simulate a second writer bumping the durable metadata object.
-        let (mut foreign, _) = collection
-            .storage
-            .fetch::<CollectionMetadata>(Collection::METADATA_PATH)
-            .await?;
-        foreign.config.description = "foreign writer".to_string();
-        collection
-            .storage
-            .put(Collection::METADATA_PATH, &foreign, None)
-            .await?;
-
-        collection
-            .add_from(&create_test_doc(0, "two", 21, vec!["two"]))
-            .await?;
-        let err = collection
-            .flush(unix_ms())
-            .await
-            .expect_err("stale CAS token must remain a conflict");
-        assert!(matches!(err, DBError::Precondition { .. }));
-        assert!(collection.is_poisoned());
+        assert!(
+            old.save_extension("zombie".to_string(), Fv::Bool(true))
+                .await
+                .is_err()
+        );
+        old.set_extension("zombie".to_string(), Fv::Bool(true));
+        assert!(old.get_extension("zombie").is_none());
+        assert!(old.flush(unix_ms()).await.is_err());
+
+        db.close().await?;
         Ok(())
     }
 
-    /// An object-store PUT may commit before the caller observes its result.
-    /// Cancelling `add` in that interval poisons the handle; the durable WAL
-    /// intent makes the reopened generation recover the committed document
-    /// instead of skipping it.
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_cancelled_add_poisons_handle_and_reopen_recovers_document() -> Result<(), DBError>
-    {
+    async fn test_delete_drains_add_before_prefix_removal() -> Result<(), DBError> {
         let (gate_tx, gate_rx) = tokio::sync::watch::channel(false);
-        let blocked = Arc::new(TestAtomicBool::new(false));
-        let object_store: Arc<dyn ObjectStore> = Arc::new(FaultPutStore {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
             inner: Arc::new(InMemory::new()),
-            suffix: "data/1.cbor".to_string(),
-            fault: PutFault::BlockAfterCommit {
-                gate: gate_rx,
-                blocked: blocked.clone(),
-            },
+            gate_suffix: "data/1.cbor".to_string(),
+            gate: gate_rx,
+            blocked: Arc::new(TestAtomicBool::new(false)),
         });
-        let config = DBConfig {
-            name: "test_db".to_string(),
-            description: String::new(),
-            storage: StorageConfig {
-                compress_level: 0,
-                ..Default::default()
+        let db = AndaDB::connect(
+            object_store,
+            DBConfig {
+                name: "delete_drain_db".to_string(),
+                description: String::new(),
+                storage: StorageConfig {
+                    compress_level: 0,
+                    ..Default::default()
+                },
+                lock: None,
             },
-            lock: None,
-        };
-        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
-        let collection = create_test_collection(&db, async |collection| {
-            collection.create_btree_index_nx(&["name"]).await
-        })
+        )
         .await?;
-
+        let old = db
+            .create_collection(
+                TestDoc::schema()?,
+                CollectionConfig {
+                    name: "documents".to_string(),
+                    description: String::new(),
+                    ..Default::default()
+                },
+                async |_| Ok(()),
+            )
+            .await?;
         let adding = {
-            let collection = collection.clone();
+            let collection = old.clone();
             tokio::spawn(async move {
                 collection
-                    .add_from(&create_test_doc(0, "committed", 20, vec!["x"]))
+                    .add_from(&create_test_doc(0, "deleted", 20, vec!["x"]))
                     .await
             })
         };
-        while !blocked.load(TestOrdering::Acquire) {
+        while old.max_document_id() == 0 {
             tokio::task::yield_now().await;
         }
-        adding.abort();
+
+        let deleting = {
+            let db = db.clone();
+            tokio::spawn(async move { db.delete_collection("documents").await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
         assert!(
-            adding
+            !deleting.is_finished(),
+            "delete must drain the active add before listing the prefix"
+        );
+        deleting.abort();
+        assert!(
+            deleting
                 .await
-                .expect_err("add should be cancelled")
+                .expect_err("delete should be cancelled")
                 .is_cancelled()
         );
-        gate_tx.send(true).expect("gate receiver dropped");
-        assert!(collection.is_poisoned());
         assert!(
-            collection
-                .add_from(&create_test_doc(0, "second", 21, vec!["y"]))
+            db.open_collection("documents".to_string(), async |_| Ok(()))
                 .await
                 .is_err(),
-            "poisoned handle must reject mutations",
+            "the deletion tombstone must block open after cancellation"
+        );
+        let deleting = {
+            let db = db.clone();
+            tokio::spawn(async move { db.delete_collection("documents").await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(
+            !deleting.is_finished(),
+            "retry must take over and continue draining the retained handle"
         );
+        gate_tx.send(true).expect("gate receiver dropped");
+        adding.await.expect("add task panicked")?;
+        deleting.await.expect("delete task panicked")?;
 
-        // Reopen through the same database: WAL replay recovers the committed
-        // document and the id allocator, so the next add gets a fresh id.
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
+        let fresh = db
+            .create_collection(
+                TestDoc::schema()?,
+                CollectionConfig {
+                    name: "documents".to_string(),
+                    description: String::new(),
+                    ..Default::default()
+                },
+                async |_| Ok(()),
+            )
             .await?;
-        assert_eq!(
-            collection
-                .add_from(&create_test_doc(0, "second", 21, vec!["y"]))
-                .await?,
-            2
+        assert!(
+            fresh.is_empty(),
+            "deleted add must leave no residual object"
         );
-        collection.flush(unix_ms()).await?;
-        assert_eq!(collection.ids(), vec![1, 2]);
-        assert_eq!(
-            collection
-                .query_ids(
-                    Filter::Field((
-                        "name".to_string(),
-                        RangeQuery::Eq(Fv::Text("committed".to_string())),
-                    )),
-                    Some(10),
-                )
-                .await?,
-            vec![1]
+        old.set_read_only(false);
+        assert!(
+            old.add_from(&create_test_doc(0, "zombie", 21, vec!["z"]))
+                .await
+                .is_err()
         );
 
-        drop(collection);
-        drop(db);
-        let db = AndaDB::connect(object_store, config).await?;
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
-            .await?;
-        assert_eq!(collection.ids(), vec![1, 2]);
-        assert_eq!(collection.get_as::<TestDoc>(1).await?.name, "committed");
         db.close().await?;
         Ok(())
     }
 
-    /// A cancelled update can leave its proposed in-memory index value
-    /// applied while the document PUT is still blocked. The handle is
-    /// poisoned; the WAL stores both before/after values so the reopened
-    /// generation removes that phantom.
-    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_cancelled_update_poisons_handle_and_reopen_removes_phantom() -> Result<(), DBError>
-    {
-        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
-        let blocked = Arc::new(TestAtomicBool::new(false));
-        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
-            inner: Arc::new(InMemory::new()),
-            gate_suffix: "data/1.cbor".to_string(),
-            gate: gate_rx,
-            blocked: blocked.clone(),
-        });
-        let db = AndaDB::connect(
-            object_store,
-            DBConfig {
-                name: "test_db".to_string(),
-                description: String::new(),
-                storage: StorageConfig {
-                    compress_level: 0,
-                    ..Default::default()
-                },
-                lock: None,
+    // ---------------------------------------------------------------------
+    // Regression tests for audited defects
+    // ---------------------------------------------------------------------
+
+    fn test_db_config() -> DBConfig {
+        DBConfig {
+            name: "test_db".to_string(),
+            description: "Test database".to_string(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
             },
-        )
-        .await?;
-        let collection = create_test_collection(&db, async |collection| {
-            collection.create_btree_index_nx(&["name"]).await
-        })
-        .await?;
-        let id = collection
-            .add_from(&create_test_doc(0, "before", 20, vec!["x"]))
-            .await?;
-        collection.flush(unix_ms()).await?;
+            lock: None,
+        }
+    }
+
+    async fn connect_test_db(object_store: Arc<dyn ObjectStore>) -> Result<AndaDB, DBError> {
+        AndaDB::connect(object_store, test_db_config()).await
+    }
+
+    /// An `InMemory` store that rejects `put` for every location containing a
+    /// configured substring while armed.
+    #[derive(Debug)]
+    struct FailPutStore {
+        inner: Arc<InMemory>,
+        fail_put_substr: String,
+        armed: Arc<TestAtomicBool>,
+    }
+
+    impl FailPutStore {
+        fn new(fail_put_substr: impl Into<String>) -> Self {
+            Self {
+                inner: Arc::new(InMemory::new()),
+                fail_put_substr: fail_put_substr.into(),
+                armed: Arc::new(TestAtomicBool::new(false)),
+            }
+        }
+
+        fn arm(&self, armed: bool) {
+            self.armed.store(armed, TestOrdering::Release);
+        }
+    }
+
+    impl fmt::Display for FailPutStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("FailPutStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for FailPutStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> ObjectStoreResult<PutResult> {
+            if self.armed.load(TestOrdering::Acquire)
+                && location.as_ref().contains(&self.fail_put_substr)
+            {
+                return Err(object_store::Error::Generic {
+                    store: "fail_put",
+                    source: "injected put failure".into(),
+                });
+            }
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> ObjectStoreResult<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
 
-        gate_tx.send(false).expect("gate receiver dropped");
-        let updating = {
-            let collection = collection.clone();
-            tokio::spawn(async move {
-                collection
-                    .update(
-                        id,
-                        BTreeMap::from([("name".to_string(), Fv::Text("cancelled".to_string()))]),
-                    )
-                    .await
-            })
-        };
-        while !blocked.load(TestOrdering::Acquire) {
-            tokio::task::yield_now().await;
+        fn delete_stream(
+            &self,
+            locations: BoxStream<'static, ObjectStoreResult<Path>>,
+        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
+            self.inner.delete_stream(locations)
         }
-        updating.abort();
-        assert!(
-            updating
-                .await
-                .expect_err("update should be cancelled")
-                .is_cancelled()
-        );
-        gate_tx.send(true).expect("gate receiver dropped");
-        assert!(collection.is_poisoned());
-        assert!(
-            collection.flush(unix_ms()).await.is_err(),
-            "poisoned handle must reject flush",
-        );
 
-        // The reopened generation replays the WAL: both historical values are
-        // removed and the document still present in storage is re-indexed.
-        let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
-            .await?;
-        collection.flush(unix_ms()).await?;
-        let before = collection
-            .query_ids(
-                Filter::Field((
-                    "name".to_string(),
-                    RangeQuery::Eq(Fv::Text("before".to_string())),
-                )),
-                Some(10),
-            )
-            .await?;
-        let cancelled = collection
-            .query_ids(
-                Filter::Field((
-                    "name".to_string(),
-                    RangeQuery::Eq(Fv::Text("cancelled".to_string())),
-                )),
-                Some(10),
-            )
-            .await?;
-        assert_eq!(before, vec![id]);
-        assert!(cancelled.is_empty());
-        db.close().await?;
-        Ok(())
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        fn list_with_offset(
+            &self,
+            prefix: Option<&Path>,
+            offset: &Path,
+        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list_with_offset(prefix, offset)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> ObjectStoreResult<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy_opts(
+            &self,
+            from: &Path,
+            to: &Path,
+            options: CopyOptions,
+        ) -> ObjectStoreResult<()> {
+            self.inner.copy_opts(from, to, options).await
+        }
     }
 
-    /// Recovery must wait until the open callback installs custom hooks.
-    /// Replaying once with default derivation would persist raw `After`
-    /// B-tree/BM25 entries that the custom hook cannot subsequently identify.
+    /// A newly created index must be durable **before** the collection
+    /// metadata that registers it. Publishing the registration first left the
+    /// index permanently empty on the next start: bootstrap loaded the empty
+    /// durable index, `create_bm25_index_nx` swallowed `AlreadyExists`, and the
+    /// repair scan only covers ids above the storage checkpoint.
     #[tokio::test]
-    async fn test_mutation_replay_uses_custom_hooks_before_clearing_intent() -> Result<(), DBError>
-    {
-        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
-        let config = DBConfig {
-            name: "custom_replay_db".to_string(),
-            description: String::new(),
-            storage: StorageConfig {
-                compress_level: 0,
-                ..Default::default()
-            },
-            lock: None,
-        };
-        let db = AndaDB::connect(object_store.clone(), config.clone()).await?;
-        let collection = db
-            .create_collection(
-                TestDoc::schema()?,
-                CollectionConfig {
-                    name: "documents".to_string(),
-                    description: String::new(),
-                },
-                async |collection| {
-                    collection.set_index_hooks(Arc::new(RecoveryCustomHooks));
-                    collection.create_btree_index_nx(&["name"]).await?;
-                    collection.create_bm25_index_nx(&["name"]).await?;
-                    Ok(())
-                },
-            )
-            .await?;
-        let id = collection
-            .add_from(&create_test_doc(0, "Before", 20, vec!["x"]))
-            .await?;
-        collection.flush(unix_ms()).await?;
-        collection
-            .update(
-                id,
-                BTreeMap::from([("name".to_string(), Fv::Text("After".to_string()))]),
-            )
-            .await?;
-        drop(collection);
+    async fn test_flush_persists_index_before_registering_it() -> Result<(), DBError> {
+        let store = Arc::new(FailPutStore::new("bm25_indexes/name/b_"));
+        let object_store: Arc<dyn ObjectStore> = store.clone();
+
+        let db = connect_test_db(object_store.clone()).await?;
+        let collection = create_test_collection(&db, async |_| Ok(())).await?;
+        for i in 0..20u32 {
+            collection
+                .add_from(&create_test_doc(0, &format!("doc-{i}"), 20 + i, vec!["t"]))
+                .await?;
+        }
+        assert_eq!(collection.len(), 20);
+        db.close().await?;
         drop(db);
 
-        let db = AndaDB::connect(object_store, config).await?;
+        // Reopen and create the BM25 index: the backfill only exists in
+        // memory until a flush persists it. The index bucket write fails.
+        store.arm(true);
+        let db = connect_test_db(object_store.clone()).await?;
+        let opened = db
+            .open_collection("test_collection".to_string(), async |c| {
+                c.create_bm25_index_nx(&["name"]).await?;
+                Ok(())
+            })
+            .await;
+        assert!(
+            opened.is_err(),
+            "the failing index flush must fail the open that created it"
+        );
+        drop(db);
+
+        // Next start: whatever is durable must not claim to be a usable index.
+        store.arm(false);
+        let db = connect_test_db(object_store.clone()).await?;
         let collection = db
-            .open_collection("documents".to_string(), async |collection| {
-                collection.set_index_hooks(Arc::new(RecoveryCustomHooks));
+            .open_collection("test_collection".to_string(), async |c| {
+                c.create_bm25_index_nx(&["name"]).await?;
                 Ok(())
             })
             .await?;
-        let raw = collection
-            .query_ids(
-                Filter::Field((
-                    "name".to_string(),
-                    RangeQuery::Eq(Fv::Text("After".to_string())),
-                )),
-                Some(10),
-            )
-            .await?;
-        let hooked = collection
-            .query_ids(
-                Filter::Field((
-                    "name".to_string(),
-                    RangeQuery::Eq(Fv::Text("hook:after".to_string())),
-                )),
-                Some(10),
-            )
+        assert_eq!(collection.len(), 20);
+        let hits = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("doc-3".to_string()),
+                    ..Default::default()
+                }),
+                limit: Some(20),
+                ..Default::default()
+            })
             .await?;
-        assert!(raw.is_empty(), "default-hook B-tree value must not survive");
-        assert_eq!(hooked, vec![id]);
+        assert!(
+            !hits.is_empty(),
+            "the BM25 index must be backfilled instead of silently staying empty"
+        );
 
-        let raw_text = collection
-            .get_bm25_index(&["name"])?
-            .search("after", 10, None);
-        let hooked_text = collection
-            .get_bm25_index(&["name"])?
-            .search("hooktoken", 10, None);
-        assert!(raw_text.iter().all(|(found, _)| *found != id));
-        assert!(hooked_text.iter().any(|(found, _)| *found == id));
         db.close().await?;
         Ok(())
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_flush_drains_in_flight_add_before_checkpoint() -> Result<(), DBError> {
-        let (gate_tx, gate_rx) = tokio::sync::watch::channel(false);
-        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
-            inner: Arc::new(InMemory::new()),
-            gate_suffix: "data/2.cbor".to_string(),
-            gate: gate_rx,
-            blocked: Arc::new(TestAtomicBool::new(false)),
-        });
-        let db_config = || DBConfig {
-            name: "test_db".to_string(),
-            description: "Test database".to_string(),
-            storage: StorageConfig {
-                compress_level: 0,
-                ..Default::default()
-            },
-            lock: None,
-        };
-        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
-        let collection = create_test_collection(&db, async |_| Ok(())).await?;
-
-        let id1 = collection
-            .add_from(&create_test_doc(0, "a", 20, vec!["x"]))
+    /// A single unusable retained intent must not make the collection
+    /// permanently unopenable: nothing clears it, so every reopen would fail
+    /// identically with no operator escape hatch.
+    #[tokio::test]
+    async fn test_open_tolerates_unusable_mutation_intents() -> Result<(), DBError> {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let db = connect_test_db(object_store.clone()).await?;
+        let collection = create_test_collection(&db, async |c| {
+            c.create_btree_index_nx(&["age"]).await?;
+            Ok(())
+        })
+        .await?;
+        let id = collection
+            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
             .await?;
-        assert_eq!(id1, 1);
         collection.flush(unix_ms()).await?;
 
-        // Start an add whose document write (data/2.cbor) is blocked,
-        // simulating an add still in flight while a flush runs.
-        let blocked = {
-            let collection = collection.clone();
-            tokio::spawn(async move {
-                collection
-                    .add_from(&create_test_doc(0, "b", 21, vec!["y"]))
-                    .await
-            })
-        };
-        // Wait until the blocked add has allocated its id.
-        while collection.max_document_id() < 2 {
-            tokio::task::yield_now().await;
-        }
+        // (a) An intent object that no longer decodes at all.
+        collection
+            .storage
+            .put_bytes(
+                &Collection::mutation_intent_path(1),
+                Bytes::from_static(b"not a mutation intent"),
+                PutMode::Overwrite,
+            )
+            .await?;
 
-        // A third add completes fully and bumps the metadata version so the
-        // flush below has something to persist.
-        let id3 = collection
-            .add_from(&create_test_doc(0, "c", 22, vec!["z"]))
+        // (b) A decodable intent whose recorded pre-image no longer satisfies
+        // the schema — what a crash plus a later schema change produces.
+        let name_idx = collection
+            .schema()
+            .get_field("name")
+            .expect("name field")
+            .idx();
+        let mut previous: DocumentOwned = Document::try_from(
+            collection.schema(),
+            &create_test_doc(id, "alice", 30, vec!["a"]),
+        )?
+        .into();
+        previous.fields.insert(name_idx, Fv::U64(7));
+        assert!(
+            Document::try_from_doc(collection.schema(), previous.clone()).is_err(),
+            "the pre-image must be undecodable for this test to mean anything"
+        );
+        let intent = MutationIntent {
+            sequence: 2,
+            document_id: id,
+            previous: Some(previous),
+            proposed: None,
+        };
+        collection
+            .storage
+            .create(&Collection::mutation_intent_path(2), &intent)
             .await?;
-        assert_eq!(id3, 3);
 
-        // A complete flush now owns the collection operation gate
-        // exclusively. It must wait for add(2), rather than checkpointing a
-        // bitmap/index snapshot while that mutation is only half complete.
-        let flushing = {
-            let collection = collection.clone();
-            tokio::spawn(async move { collection.flush(unix_ms()).await })
+        // (c) A decodable intent that targets the reserved document id.
+        let reserved = MutationIntent {
+            sequence: 3,
+            document_id: 0,
+            previous: None,
+            proposed: None,
         };
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        assert!(!flushing.is_finished(), "flush must drain the active add");
+        collection
+            .storage
+            .create(&Collection::mutation_intent_path(3), &reserved)
+            .await?;
 
-        // Unblock the in-flight add, let the serialized flush checkpoint the
-        // complete state, then simulate a crash and reopen.
-        gate_tx.send(true).expect("gate receiver dropped");
-        let id2 = blocked.await.expect("add task panicked")?;
-        assert_eq!(id2, 2);
-        assert!(flushing.await.expect("flush task panicked")?);
-        assert!(collection.storage.stats().check_point >= 3);
-        drop(collection);
+        // (d) A phantom posting a partial flush left behind under a key only
+        // the (undecodable) pre-image knew about. Value-keyed removal cannot
+        // reach it; the replay must sweep it out by id.
+        for index in &collection.btree_indexes {
+            if index.name() == "age" {
+                assert!(index.insert(id, &Fv::U64(99), unix_ms())?);
+            }
+        }
+
+        db.close().await?;
         drop(db);
 
-        let db = AndaDB::connect(object_store, db_config()).await?;
+        let db = connect_test_db(object_store.clone()).await?;
         let collection = db
             .open_collection("test_collection".to_string(), async |_| Ok(()))
             .await?;
-        assert!(collection.contains(1));
         assert!(
-            collection.contains(2),
-            "document written by the in-flight add must be recovered by the repair scan"
+            collection.stale_mutation_intents.lock().is_empty(),
+            "the successful open checkpoint must retire unusable records"
+        );
+        assert_eq!(collection.len(), 1);
+        let doc: TestDoc = collection.get_as(id).await?;
+        assert_eq!(doc.name, "alice");
+        assert_eq!(
+            collection
+                .query_ids(
+                    Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(30)))),
+                    None,
+                )
+                .await?,
+            vec![id]
+        );
+        // The phantom posting from (d) must be gone: the replay swept the id
+        // out of every index before re-indexing the stored document.
+        assert_eq!(
+            collection
+                .query_ids(
+                    Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(99)))),
+                    None,
+                )
+                .await?,
+            Vec::<DocumentId>::new()
         );
-        assert!(collection.contains(3));
 
         db.close().await?;
+        assert!(collection.stale_mutation_intents.lock().is_empty());
+        let mut objects = object_store.list(None);
+        while let Some(meta) = objects.next().await {
+            let meta = meta.map_err(DBError::from)?;
+            assert!(
+                !meta
+                    .location
+                    .as_ref()
+                    .contains(Collection::MUTATION_INTENT_PREFIX),
+                "stale mutation intent survived a successful checkpoint: {}",
+                meta.location
+            );
+        }
         Ok(())
     }
 
+    /// Which end of the match set a page keeps must depend only on the method
+    /// called, never on the filter's shape. 0.11.0 derived it from the scan
+    /// direction, so `_id Lt cursor` returned the newest ids while the same
+    /// predicate inside an `And` returned the oldest — cursor pagination built
+    /// on the bare form silently walked backwards off the first page as soon
+    /// as a second condition was added.
     #[tokio::test]
-    async fn test_mutation_intents_replay_update_and_remove_after_crash() -> Result<(), DBError> {
-        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
-        let db_config = || DBConfig {
-            name: "mutation_replay_db".to_string(),
-            description: String::new(),
-            storage: StorageConfig {
-                compress_level: 0,
-                ..Default::default()
-            },
-            lock: None,
-        };
+    async fn test_page_end_is_the_methods_contract_not_the_filters_shape() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |c| {
+            c.create_btree_index_nx(&["name"]).await?;
+            c.create_btree_index_nx(&["age"]).await?;
+            Ok(())
+        })
+        .await?;
 
-        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
-        let collection = db
-            .create_collection(
-                TestDoc::schema()?,
-                CollectionConfig {
-                    name: "documents".to_string(),
-                    description: String::new(),
-                },
-                async |c| {
-                    c.create_btree_index_nx(&["name"]).await?;
-                    c.create_bm25_index_nx(&["name"]).await?;
-                    c.create_hnsw_index_nx(
-                        "vector",
-                        HnswConfig {
-                            dimension: 10,
-                            ..Default::default()
-                        },
-                    )
-                    .await?;
-                    Ok(())
-                },
-            )
-            .await?;
+        // ids 1..=10, all sharing one `name` so the composite filter matches
+        // exactly what the bare `_id` filter matches.
+        for i in 1..=10u64 {
+            let doc = create_test_doc(0, "alice", 20 + i as u32, vec!["x"]);
+            assert_eq!(collection.add_from(&doc).await?, i);
+        }
 
-        let mut first = create_test_doc(0, "before", 20, vec!["old"]);
-        first.vector = vec![bf16::from_f32(0.0); 10];
-        let first_id = collection.add_from(&first).await?;
-        let mut second = create_test_doc(0, "other", 21, vec!["other"]);
-        second.vector = vec![bf16::from_f32(10.0); 10];
-        let second_id = collection.add_from(&second).await?;
-        collection.flush(unix_ms()).await?;
+        let bare = Filter::Field((Schema::ID_KEY.to_string(), RangeQuery::Lt(Fv::U64(100))));
+        let composite = Filter::And(vec![
+            Box::new(Filter::Field((
+                "name".to_string(),
+                RangeQuery::Eq(Fv::Text("alice".to_string())),
+            ))),
+            Box::new(bare.clone()),
+        ]);
 
-        // The document PUT succeeds, but no index flush follows before the
-        // process disappears. The retained intent must make reopen converge
-        // every index to the new document rather than the old checkpoint.
-        collection
-            .update(
-                first_id,
-                BTreeMap::from([
-                    ("name".to_string(), Fv::Text("middle".to_string())),
-                    (
-                        "vector".to_string(),
-                        Fv::Vector(vec![bf16::from_f32(15.0); 10]),
-                    ),
-                ]),
-            )
-            .await?;
-        collection
-            .update(
-                first_id,
-                BTreeMap::from([
-                    ("name".to_string(), Fv::Text("after".to_string())),
-                    (
-                        "vector".to_string(),
-                        Fv::Vector(vec![bf16::from_f32(20.0); 10]),
-                    ),
-                ]),
-            )
-            .await?;
-        assert!(!collection.pending_mutations.lock().is_empty());
-        drop(collection);
-        drop(db);
+        // Same predicate, two shapes, same page — in both directions.
+        for filter in [bare.clone(), composite.clone()] {
+            assert_eq!(
+                collection.query_ids(filter.clone(), Some(3)).await?,
+                vec![1, 2, 3],
+                "query_ids must keep the smallest ids for {filter:?}"
+            );
+            assert_eq!(
+                collection.query_last_ids(filter.clone(), Some(3)).await?,
+                vec![8, 9, 10],
+                "query_last_ids must keep the largest ids for {filter:?}"
+            );
+        }
 
-        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
-        let collection = db
-            .open_collection("documents".to_string(), async |_| Ok(()))
-            .await?;
+        // Newest-first cursor pagination walks the whole set without gaps or
+        // repeats, which is what the shape-dependent end broke downstream.
+        let mut seen = Vec::new();
+        let mut cursor = collection.max_document_id() + 1;
+        loop {
+            let page = collection
+                .query_last_ids(
+                    Filter::And(vec![
+                        Box::new(Filter::Field((
+                            "name".to_string(),
+                            RangeQuery::Eq(Fv::Text("alice".to_string())),
+                        ))),
+                        Box::new(Filter::Field((
+                            Schema::ID_KEY.to_string(),
+                            RangeQuery::Lt(Fv::U64(cursor)),
+                        ))),
+                    ]),
+                    Some(4),
+                )
+                .await?;
+            if page.is_empty() {
+                break;
+            }
+            cursor = page[0];
+            seen.extend(page.iter().rev().copied());
+        }
+        assert_eq!(seen, vec![10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
 
-        let by_name = |name: &str| Query {
-            filter: Some(Filter::Field((
-                "name".to_string(),
-                RangeQuery::Eq(Fv::Text(name.to_string())),
-            ))),
-            ..Default::default()
-        };
-        assert!(collection.search_ids(by_name("before")).await?.is_empty());
-        assert!(collection.search_ids(by_name("middle")).await?.is_empty());
+        // A bounded `Not` also honors the requested end.
+        let complement = Filter::Not(Box::new(Filter::Field((
+            "age".to_string(),
+            RangeQuery::Between(Fv::U64(21), Fv::U64(28)),
+        ))));
         assert_eq!(
-            collection.search_ids(by_name("after")).await?,
-            vec![first_id]
+            collection.query_ids(complement.clone(), Some(1)).await?,
+            vec![9]
+        );
+        assert_eq!(
+            collection.query_last_ids(complement, Some(1)).await?,
+            vec![10]
         );
 
-        let old_text = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    text: Some("before".to_string()),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .await?;
-        assert!(!old_text.contains(&first_id));
-        let new_text = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    text: Some("after".to_string()),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .await?;
-        assert!(new_text.contains(&first_id));
+        db.close().await?;
+        Ok(())
+    }
 
-        let hnsw = collection.get_hnsw_index("vector")?;
-        assert_eq!(hnsw.try_search(&[0.0; 10], 1)?[0].0, second_id);
-        assert_eq!(hnsw.try_search(&[20.0; 10], 1)?[0].0, first_id);
-        assert!(collection.pending_mutations.lock().is_empty());
+    /// `query_all_ids` must return every match: in-process callers (cascade
+    /// deletion, link re-pointing, `NOT` evaluation) corrupt data when a
+    /// result is silently truncated, which is exactly what `query_ids`'s
+    /// `MAX_SEARCH_LIMIT` clamp does to them.
+    #[tokio::test]
+    async fn test_query_all_ids_is_unbounded() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |c| {
+            c.create_btree_index_nx(&["age"]).await?;
+            Ok(())
+        })
+        .await?;
 
-        // Exercise the other terminal state: a remove whose object delete is
-        // durable while the derived indexes are not yet flushed.
-        collection.remove(first_id).await?;
-        assert!(!collection.pending_mutations.lock().is_empty());
-        drop(collection);
-        drop(db);
+        let n = Collection::MAX_SEARCH_LIMIT + 100;
+        for i in 0..n {
+            collection
+                .add_from(&create_test_doc(0, &format!("u{i}"), 1, vec![]))
+                .await?;
+        }
 
-        let db = AndaDB::connect(object_store, db_config()).await?;
-        let collection = db
-            .open_collection("documents".to_string(), async |_| Ok(()))
-            .await?;
-        assert!(!collection.contains(first_id));
-        assert!(collection.search_ids(by_name("after")).await?.is_empty());
-        let removed_text = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    text: Some("after".to_string()),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .await?;
-        assert!(!removed_text.contains(&first_id));
-        assert!(
-            !collection
-                .get_hnsw_index("vector")?
-                .try_search(&[20.0; 10], 2)?
-                .iter()
-                .any(|(id, _)| *id == first_id)
+        let filter = Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(1))));
+        assert_eq!(
+            collection.query_ids(filter.clone(), None).await?.len(),
+            Collection::MAX_SEARCH_LIMIT
         );
-        assert!(collection.pending_mutations.lock().is_empty());
+        assert_eq!(collection.query_all_ids(filter).await?.len(), n);
 
         db.close().await?;
         Ok(())
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_close_drains_update_and_old_handle_cannot_reenable() -> Result<(), DBError> {
-        let (gate_tx, gate_rx) = tokio::sync::watch::channel(true);
-        let blocked = Arc::new(TestAtomicBool::new(false));
-        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
-            inner: Arc::new(InMemory::new()),
-            gate_suffix: "data/1.cbor".to_string(),
-            gate: gate_rx,
-            blocked: blocked.clone(),
-        });
-        let config = DBConfig {
-            name: "close_drain_db".to_string(),
-            description: String::new(),
-            storage: StorageConfig {
-                compress_level: 0,
-                ..Default::default()
-            },
-            lock: None,
-        };
-        let db = AndaDB::connect(object_store, config).await?;
-        let old = db
-            .create_collection(
-                TestDoc::schema()?,
-                CollectionConfig {
-                    name: "documents".to_string(),
-                    description: String::new(),
-                },
-                async |_| Ok(()),
-            )
-            .await?;
+    /// Legacy data a later validation tightening rejects must stay
+    /// manageable in-band: `search` skips such a document instead of failing
+    /// the whole batch, and `remove` deletes it via an id sweep even though
+    /// its values cannot drive value-keyed index cleanup. `get` stays strict.
+    #[tokio::test]
+    async fn test_schema_invalid_stored_document_is_skippable_and_removable() -> Result<(), DBError>
+    {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |c| {
+            c.create_btree_index_nx(&["age"]).await?;
+            Ok(())
+        })
+        .await?;
 
-        let id = old
-            .add_from(&create_test_doc(0, "before", 20, vec!["x"]))
+        let id_ok = collection
+            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
+            .await?;
+        let id_bad = collection
+            .add_from(&create_test_doc(0, "bob", 40, vec!["b"]))
             .await?;
-        gate_tx.send(false).expect("gate receiver dropped");
-        let updating = {
-            let collection = old.clone();
-            tokio::spawn(async move {
-                collection
-                    .update(
-                        id,
-                        BTreeMap::from([("name".to_string(), Fv::Text("after".to_string()))]),
-                    )
-                    .await
-            })
-        };
-        while !blocked.load(TestOrdering::Acquire) {
-            tokio::task::yield_now().await;
-        }
 
-        let closing = {
-            let db = db.clone();
-            tokio::spawn(async move { db.close_collection("documents").await })
-        };
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        assert!(
-            !closing.is_finished(),
-            "close must wait for admitted update"
-        );
+        // Overwrite the stored object with a raw image whose `name` is not
+        // Text — the shape 0.10 write paths could persist before validation
+        // covered it.
+        let name_idx = collection
+            .schema()
+            .get_field("name")
+            .expect("name field")
+            .idx();
+        let mut raw: DocumentOwned = Document::try_from(
+            collection.schema(),
+            &create_test_doc(id_bad, "bob", 40, vec!["b"]),
+        )?
+        .into();
+        raw.fields.insert(name_idx, Fv::U64(7));
+        collection
+            .storage
+            .put(&Collection::doc_path(id_bad), &raw, None)
+            .await?;
 
-        // Cancelling close must not create an empty registry slot. Opening the
-        // same name takes over the retiring handle and remains blocked until
-        // its admitted update is drained and flushed.
-        closing.abort();
-        assert!(
-            closing
-                .await
-                .expect_err("close should be cancelled")
-                .is_cancelled()
-        );
-        let opening = {
-            let db = db.clone();
-            tokio::spawn(async move {
-                db.open_or_create_collection(
-                    TestDoc::schema()?,
-                    CollectionConfig {
-                        name: "documents".to_string(),
-                        description: String::new(),
-                    },
-                    async |_| Ok(()),
-                )
-                .await
-            })
-        };
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        assert!(
-            !opening.is_finished(),
-            "open must finish the cancelled close before loading a fresh handle"
-        );
+        // Reads of the poisoned id stay strict.
+        assert!(collection.get_as::<TestDoc>(id_bad).await.is_err());
 
-        gate_tx.send(true).expect("gate receiver dropped");
-        let updated = updating.await.expect("update task panicked")?;
-        assert_eq!(updated.get_field("name"), Some(&Fv::Text("after".into())));
-        let fresh = opening.await.expect("open task panicked")?;
-        assert_eq!(fresh.len(), 1);
-        let persisted: TestDoc = fresh.get_as(id).await?;
-        assert_eq!(persisted.name, "after");
-        assert!(!Arc::ptr_eq(&old, &fresh));
+        // A search matching both documents returns the healthy one instead
+        // of failing the whole batch.
+        let docs = collection
+            .search(Query {
+                filter: Some(Filter::Field((
+                    "age".to_string(),
+                    RangeQuery::Ge(Fv::U64(0)),
+                ))),
+                limit: Some(10),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].id(), id_ok);
 
-        // The user-controlled read-only flag is reversible only while the
-        // handle's lifecycle lease is active. The retired Arc must never
-        // become a second writer over the same prefix.
-        old.set_read_only(false);
-        assert!(
-            old.add_from(&create_test_doc(0, "zombie", 21, vec!["z"]))
-                .await
-                .is_err()
-        );
-        assert!(
-            old.save_extension("zombie".to_string(), Fv::Bool(true))
-                .await
-                .is_err()
+        // `remove` deletes it (no recoverable pre-image, so it reports None)
+        // and sweeps its postings, so the id stops matching queries.
+        assert!(collection.remove(id_bad).await?.is_none());
+        assert_eq!(collection.len(), 1);
+        assert_eq!(
+            collection
+                .query_ids(
+                    Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(40)))),
+                    None,
+                )
+                .await?,
+            Vec::<DocumentId>::new()
         );
-        old.set_extension("zombie".to_string(), Fv::Bool(true));
-        assert!(old.get_extension("zombie").is_none());
-        assert!(old.flush(unix_ms()).await.is_err());
 
         db.close().await?;
         Ok(())
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn test_delete_drains_add_before_prefix_removal() -> Result<(), DBError> {
-        let (gate_tx, gate_rx) = tokio::sync::watch::channel(false);
-        let object_store: Arc<dyn ObjectStore> = Arc::new(GatedPutStore {
-            inner: Arc::new(InMemory::new()),
-            gate_suffix: "data/1.cbor".to_string(),
-            gate: gate_rx,
-            blocked: Arc::new(TestAtomicBool::new(false)),
-        });
-        let db = AndaDB::connect(
-            object_store,
-            DBConfig {
-                name: "delete_drain_db".to_string(),
-                description: String::new(),
-                storage: StorageConfig {
-                    compress_level: 0,
-                    ..Default::default()
-                },
-                lock: None,
-            },
-        )
+    /// Neither a multi-key posting scan nor a caller-supplied `Include` list
+    /// may emit the same document id twice: duplicates make `search` return
+    /// the same document repeatedly and consume the caller's `limit`.
+    #[tokio::test]
+    async fn test_filter_results_are_deduplicated() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |c| {
+            c.create_btree_index_nx(&["tags"]).await?;
+            Ok(())
+        })
         .await?;
-        let old = db
-            .create_collection(
-                TestDoc::schema()?,
-                CollectionConfig {
-                    name: "documents".to_string(),
-                    description: String::new(),
-                },
-                async |_| Ok(()),
-            )
+
+        let id = collection
+            .add_from(&create_test_doc(0, "alice", 30, vec!["x", "y", "z"]))
             .await?;
-        let adding = {
-            let collection = old.clone();
-            tokio::spawn(async move {
-                collection
-                    .add_from(&create_test_doc(0, "deleted", 20, vec!["x"]))
-                    .await
-            })
-        };
-        while old.max_document_id() == 0 {
-            tokio::task::yield_now().await;
-        }
 
-        let deleting = {
-            let db = db.clone();
-            tokio::spawn(async move { db.delete_collection("documents").await })
-        };
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        assert!(
-            !deleting.is_finished(),
-            "delete must drain the active add before listing the prefix"
-        );
-        deleting.abort();
-        assert!(
-            deleting
-                .await
-                .expect_err("delete should be cancelled")
-                .is_cancelled()
-        );
-        assert!(
-            db.open_collection("documents".to_string(), async |_| Ok(()))
-                .await
-                .is_err(),
-            "the deletion tombstone must block open after cancellation"
-        );
-        let deleting = {
-            let db = db.clone();
-            tokio::spawn(async move { db.delete_collection("documents").await })
-        };
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        assert!(
-            !deleting.is_finished(),
-            "retry must take over and continue draining the retained handle"
+        // The document is indexed under three keys; a range scan visits all
+        // three postings.
+        let tags_filter = Filter::Field((
+            "tags".to_string(),
+            RangeQuery::Between(Fv::Text("a".to_string()), Fv::Text("zz".to_string())),
+        ));
+        assert_eq!(
+            collection.query_ids(tags_filter.clone(), None).await?,
+            vec![id]
         );
-        gate_tx.send(true).expect("gate receiver dropped");
-        adding.await.expect("add task panicked")?;
-        deleting.await.expect("delete task panicked")?;
+        let docs = collection
+            .search(Query {
+                filter: Some(tags_filter.clone()),
+                limit: Some(10),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(docs.len(), 1);
 
-        let fresh = db
-            .create_collection(
-                TestDoc::schema()?,
-                CollectionConfig {
-                    name: "documents".to_string(),
-                    description: String::new(),
-                },
-                async |_| Ok(()),
-            )
+        // A `limit` must count distinct documents, not postings.
+        let second = collection
+            .add_from(&create_test_doc(0, "bob", 31, vec!["x", "y", "z"]))
             .await?;
-        assert!(
-            fresh.is_empty(),
-            "deleted add must leave no residual object"
+        assert_eq!(
+            collection.query_ids(tags_filter, Some(2)).await?,
+            vec![id, second]
         );
-        old.set_read_only(false);
-        assert!(
-            old.add_from(&create_test_doc(0, "zombie", 21, vec!["z"]))
-                .await
-                .is_err()
+
+        // Duplicated keys in an `Include` list yield one hit each, matching
+        // `anda_db_btree`'s own `Include` semantics.
+        assert_eq!(
+            collection
+                .query_ids(
+                    Filter::Field((
+                        "_id".to_string(),
+                        RangeQuery::Include(vec![
+                            Fv::U64(id),
+                            Fv::U64(id),
+                            Fv::U64(second),
+                            Fv::U64(id),
+                        ]),
+                    )),
+                    None,
+                )
+                .await?,
+            vec![id, second]
         );
 
         db.close().await?;
         Ok(())
     }
 
-    // ---------------------------------------------------------------------
-    // Regression tests for audited defects
-    // ---------------------------------------------------------------------
-
-    fn test_db_config() -> DBConfig {
-        DBConfig {
-            name: "test_db".to_string(),
-            description: "Test database".to_string(),
-            storage: StorageConfig {
-                compress_level: 0,
-                ..Default::default()
-            },
-            lock: None,
-        }
-    }
+    /// `create_hnsw_index_nx` must not silently keep an index whose persisted
+    /// configuration differs from the requested one.
+    #[tokio::test]
+    async fn test_create_hnsw_index_nx_rejects_changed_config() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |c| {
+            c.create_hnsw_index_nx(
+                "vector",
+                HnswConfig {
+                    dimension: 10,
+                    ..Default::default()
+                },
+            )
+            .await?;
+            // Same configuration: still idempotent.
+            c.create_hnsw_index_nx(
+                "vector",
+                HnswConfig {
+                    dimension: 10,
+                    ..Default::default()
+                },
+            )
+            .await?;
 
-    async fn connect_test_db(object_store: Arc<dyn ObjectStore>) -> Result<AndaDB, DBError> {
-        AndaDB::connect(object_store, test_db_config()).await
-    }
+            let err = c
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 4,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .expect_err("a changed configuration must not be discarded");
+            assert!(matches!(err, DBError::Index { .. }), "{err:?}");
 
-    /// An `InMemory` store that rejects `put` for every location containing a
-    /// configured substring while armed.
-    #[derive(Debug)]
-    struct FailPutStore {
-        inner: Arc<InMemory>,
-        fail_put_substr: String,
-        armed: Arc<TestAtomicBool>,
-    }
+            // A non-dimension change must be reported too.
+            let err = c
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ef_search: 999,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .expect_err("a changed configuration must not be discarded");
+            assert!(matches!(err, DBError::Index { .. }), "{err:?}");
+            Ok(())
+        })
+        .await?;
 
-    impl FailPutStore {
-        fn new(fail_put_substr: impl Into<String>) -> Self {
-            Self {
-                inner: Arc::new(InMemory::new()),
-                fail_put_substr: fail_put_substr.into(),
-                armed: Arc::new(TestAtomicBool::new(false)),
-            }
-        }
+        // The existing index is untouched and still accepts its own vectors.
+        assert_eq!(collection.get_hnsw_index("vector")?.dimension(), 10);
+        collection
+            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
+            .await?;
 
-        fn arm(&self, armed: bool) {
-            self.armed.store(armed, TestOrdering::Release);
-        }
+        db.close().await?;
+        Ok(())
     }
 
-    impl fmt::Display for FailPutStore {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            f.write_str("FailPutStore")
-        }
-    }
+    /// `PutMode::Create` reporting `AlreadyExists` means this add wrote
+    /// nothing and the object belongs to someone else. The compensating
+    /// delete must not run, or it destroys a committed document.
+    #[tokio::test]
+    async fn test_add_conflict_does_not_delete_the_existing_document() -> Result<(), DBError> {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
 
-    #[async_trait]
-    impl ObjectStore for FailPutStore {
-        async fn put_opts(
-            &self,
-            location: &Path,
-            payload: PutPayload,
-            opts: PutOptions,
-        ) -> ObjectStoreResult<PutResult> {
-            if self.armed.load(TestOrdering::Acquire)
-                && location.as_ref().contains(&self.fail_put_substr)
-            {
-                return Err(object_store::Error::Generic {
-                    store: "fail_put",
-                    source: "injected put failure".into(),
-                });
-            }
-            self.inner.put_opts(location, payload, opts).await
-        }
+        let db_a = connect_test_db(object_store.clone()).await?;
+        let collection_a = create_test_collection(&db_a, async |_| Ok(())).await?;
+        // Publish the collection so the second writer can open it while its
+        // own `max_document_id` is still 0.
+        db_a.flush().await?;
 
-        async fn put_multipart_opts(
-            &self,
-            location: &Path,
-            opts: PutMultipartOptions,
-        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
-            self.inner.put_multipart_opts(location, opts).await
-        }
+        let db_b = connect_test_db(object_store.clone()).await?;
+        let collection_b = create_test_collection(&db_b, async |_| Ok(())).await?;
+        assert_eq!(collection_b.max_document_id(), 0);
 
-        async fn get_opts(
-            &self,
-            location: &Path,
-            options: GetOptions,
-        ) -> ObjectStoreResult<GetResult> {
-            self.inner.get_opts(location, options).await
-        }
+        let id = collection_a
+            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
+            .await?;
+        assert_eq!(id, 1);
+        collection_a.flush(unix_ms()).await?;
 
-        fn delete_stream(
-            &self,
-            locations: BoxStream<'static, ObjectStoreResult<Path>>,
-        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
-            self.inner.delete_stream(locations)
-        }
+        // The second writer allocates the same id and loses the create race.
+        let err = collection_b
+            .add_from(&create_test_doc(0, "bob", 31, vec!["b"]))
+            .await
+            .expect_err("the conflicting add must fail");
+        assert!(matches!(err, DBError::AlreadyExists { .. }), "{err:?}");
 
-        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
-            self.inner.list(prefix)
-        }
+        // Writer A's document must still be there.
+        let stored = object_store
+            .head(&Path::from(format!(
+                "test_db/test_collection/{}",
+                Collection::doc_path(id)
+            )))
+            .await;
+        assert!(
+            stored.is_ok(),
+            "the losing add must not delete the winner's document: {stored:?}"
+        );
+        let doc: TestDoc = collection_a.get_as(id).await?;
+        assert_eq!(doc.name, "alice");
 
-        fn list_with_offset(
-            &self,
-            prefix: Option<&Path>,
-            offset: &Path,
-        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
-            self.inner.list_with_offset(prefix, offset)
-        }
+        db_a.close().await?;
+        Ok(())
+    }
 
-        async fn list_with_delimiter(
-            &self,
-            prefix: Option<&Path>,
-        ) -> ObjectStoreResult<ListResult> {
-            self.inner.list_with_delimiter(prefix).await
-        }
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, AndaDBSchema)]
+    struct UniqueNameDoc {
+        pub _id: u64,
+        #[unique]
+        pub name: String,
+        pub body: String,
+        pub vector: Vector,
+    }
 
-        async fn copy_opts(
-            &self,
-            from: &Path,
-            to: &Path,
-            options: CopyOptions,
-        ) -> ObjectStoreResult<()> {
-            self.inner.copy_opts(from, to, options).await
+    fn unique_name_doc(name: &str, vector: [f32; 4]) -> UniqueNameDoc {
+        UniqueNameDoc {
+            _id: 0,
+            name: name.to_string(),
+            body: format!("{name} keeps a journal"),
+            vector: vector.into_iter().map(bf16::from_f32).collect(),
         }
     }
 
-    /// A newly created index must be durable **before** the collection
-    /// metadata that registers it. Publishing the registration first left the
-    /// index permanently empty on the next start: bootstrap loaded the empty
-    /// durable index, `create_bm25_index_nx` swallowed `AlreadyExists`, and the
-    /// repair scan only covers ids above the storage checkpoint.
+    /// Dropping a dead id from the bitmap is only half the repair: the index
+    /// postings survive, so a unique key stays occupied forever and queries
+    /// keep returning the dead id.
     #[tokio::test]
-    async fn test_flush_persists_index_before_registering_it() -> Result<(), DBError> {
-        let store = Arc::new(FailPutStore::new("bm25_indexes/name/b_"));
-        let object_store: Arc<dyn ObjectStore> = store.clone();
+    async fn test_reconcile_storage_purges_index_postings_of_dead_ids() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = db
+            .open_or_create_collection(
+                UniqueNameDoc::schema()?,
+                CollectionConfig {
+                    name: "unique_docs".to_string(),
+                    description: String::new(),
+                    ..Default::default()
+                },
+                async |c| {
+                    c.create_btree_index_nx(&["name"]).await?;
+                    c.create_bm25_index_nx(&["body"]).await?;
+                    c.create_hnsw_index_nx(
+                        "vector",
+                        HnswConfig {
+                            dimension: 4,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                    Ok(())
+                },
+            )
+            .await?;
 
-        let db = connect_test_db(object_store.clone()).await?;
-        let collection = create_test_collection(&db, async |_| Ok(())).await?;
-        for i in 0..20u32 {
-            collection
-                .add_from(&create_test_doc(0, &format!("doc-{i}"), 20 + i, vec!["t"]))
-                .await?;
-        }
-        assert_eq!(collection.len(), 20);
-        db.close().await?;
-        drop(db);
+        let alice = collection
+            .add_from(&unique_name_doc("alice", [1.0, 0.0, 0.0, 0.0]))
+            .await?;
+        collection
+            .add_from(&unique_name_doc("bob", [0.0, 1.0, 0.0, 0.0]))
+            .await?;
+        collection.flush(unix_ms()).await?;
 
-        // Reopen and create the BM25 index: the backfill only exists in
-        // memory until a flush persists it. The index bucket write fails.
-        store.arm(true);
-        let db = connect_test_db(object_store.clone()).await?;
-        let opened = db
-            .open_collection("test_collection".to_string(), async |c| {
-                c.create_bm25_index_nx(&["name"]).await?;
-                Ok(())
-            })
-            .await;
+        // Simulate a crash that lost the document object.
+        collection
+            .storage
+            .delete(&Collection::doc_path(alice))
+            .await?;
+
+        let (recovered, dropped) = collection.reconcile_storage().await?;
+        assert_eq!((recovered, dropped), (0, 1));
+        assert!(!collection.contains(alice));
+
+        // The B-tree posting must be gone: queries no longer return the dead
+        // id, and the unique key is free again.
         assert!(
-            opened.is_err(),
-            "the failing index flush must fail the open that created it"
+            collection
+                .query_ids(
+                    Filter::Field((
+                        "name".to_string(),
+                        RangeQuery::Eq(Fv::Text("alice".to_string())),
+                    )),
+                    None,
+                )
+                .await?
+                .is_empty()
         );
-        drop(db);
 
-        // Next start: whatever is durable must not claim to be a usable index.
-        store.arm(false);
-        let db = connect_test_db(object_store.clone()).await?;
-        let collection = db
-            .open_collection("test_collection".to_string(), async |c| {
-                c.create_bm25_index_nx(&["name"]).await?;
-                Ok(())
+        // The BM25 postings must be gone too. They are keyed by the document's
+        // tokens, which the lost body no longer supplies, so the index has to
+        // be swept by id — otherwise a full-text search keeps resurrecting the
+        // dead id (`search_ids` returns BM25 hits unfiltered).
+        let text_hits = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("alice".to_string()),
+                    ..Default::default()
+                }),
+                limit: Some(10),
+                ..Default::default()
+            })
+            .await?;
+        assert!(text_hits.is_empty(), "{text_hits:?}");
+
+        let reborn = collection
+            .add_from(&unique_name_doc("alice", [1.0, 0.0, 0.0, 0.0]))
+            .await?;
+        assert_ne!(reborn, alice);
+
+        // The reborn document is the only text hit; the dead id stays gone.
+        let text_hits = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("alice".to_string()),
+                    ..Default::default()
+                }),
+                limit: Some(10),
+                ..Default::default()
             })
             .await?;
-        assert_eq!(collection.len(), 20);
+        assert_eq!(text_hits, vec![reborn]);
+
+        // The HNSW posting must be gone as well.
         let hits = collection
             .search_ids(Query {
                 search: Some(Search {
-                    text: Some("doc-3".to_string()),
+                    vector: Some(vec![1.0, 0.0, 0.0, 0.0]),
                     ..Default::default()
                 }),
-                limit: Some(20),
+                limit: Some(10),
                 ..Default::default()
             })
             .await?;
-        assert!(
-            !hits.is_empty(),
-            "the BM25 index must be backfilled instead of silently staying empty"
+        assert!(!hits.contains(&alice), "{hits:?}");
+
+        // Surviving documents keep their postings.
+        assert_eq!(
+            collection
+                .query_ids(
+                    Filter::Field((
+                        "name".to_string(),
+                        RangeQuery::Eq(Fv::Text("bob".to_string())),
+                    )),
+                    None,
+                )
+                .await?
+                .len(),
+            1
         );
+        let bob_hits = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("bob".to_string()),
+                    ..Default::default()
+                }),
+                limit: Some(10),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(bob_hits.len(), 1, "{bob_hits:?}");
 
         db.close().await?;
         Ok(())
     }
 
-    /// A single unusable retained intent must not make the collection
-    /// permanently unopenable: nothing clears it, so every reopen would fail
-    /// identically with no operator escape hatch.
+    /// A B-tree index on the primary key can never answer a query: the filter
+    /// dispatcher serves `_id` from the collection's own id index.
     #[tokio::test]
-    async fn test_open_tolerates_unusable_mutation_intents() -> Result<(), DBError> {
-        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
-        let db = connect_test_db(object_store.clone()).await?;
-        let collection = create_test_collection(&db, async |c| {
-            c.create_btree_index_nx(&["age"]).await?;
+    async fn test_btree_index_on_primary_key_is_rejected() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        create_test_collection(&db, async |c| {
+            let err = c
+                .create_btree_index(&["_id"])
+                .await
+                .expect_err("an unreachable index must be rejected");
+            assert!(matches!(err, DBError::Schema { .. }), "{err:?}");
+            // `_nx` must not swallow it either.
+            assert!(c.create_btree_index_nx(&["_id"]).await.is_err());
             Ok(())
         })
         .await?;
-        let id = collection
-            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
-            .await?;
-        collection.flush(unix_ms()).await?;
 
-        // (a) An intent object that no longer decodes at all.
-        collection
-            .storage
-            .put_bytes(
-                &Collection::mutation_intent_path(1),
-                Bytes::from_static(b"not a mutation intent"),
-                PutMode::Overwrite,
+        db.close().await?;
+        Ok(())
+    }
+
+    /// Segment storage keeps every document and intent inside segment
+    /// objects, and a crash before any flush loses nothing: the segments
+    /// written after the last manifest are replayed on open.
+    #[tokio::test]
+    async fn test_segment_storage_recovers_unflushed_mutations() -> Result<(), DBError> {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let db_config = || DBConfig {
+            name: "segment_db".to_string(),
+            description: String::new(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+
+        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
+        let collection = db
+            .create_collection(
+                TestDoc::schema()?,
+                CollectionConfig {
+                    name: "documents".to_string(),
+                    description: String::new(),
+                    document_storage: DocumentStorage::Segments(Default::default()),
+                    ..Default::default()
+                },
+                async |c| {
+                    c.create_btree_index_nx(&["name"]).await?;
+                    Ok(())
+                },
             )
             .await?;
 
-        // (b) A decodable intent whose recorded pre-image no longer satisfies
-        // the schema — what a crash plus a later schema change produces.
-        let name_idx = collection
-            .schema()
-            .get_field("name")
-            .expect("name field")
-            .idx();
-        let mut previous: DocumentOwned = Document::try_from(
-            collection.schema(),
-            &create_test_doc(id, "alice", 30, vec!["a"]),
-        )?
-        .into();
-        previous.fields.insert(name_idx, Fv::U64(7));
-        assert!(
-            Document::try_from_doc(collection.schema(), previous.clone()).is_err(),
-            "the pre-image must be undecodable for this test to mean anything"
-        );
-        let intent = MutationIntent {
-            sequence: 2,
-            document_id: id,
-            previous: Some(previous),
-            proposed: None,
-        };
-        collection
-            .storage
-            .create(&Collection::mutation_intent_path(2), &intent)
+        let alice = collection
+            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
+            .await?;
+        let bob = collection
+            .add_from(&create_test_doc(0, "bob", 31, vec!["b"]))
             .await?;
+        collection.flush(unix_ms()).await?;
 
-        // (c) A decodable intent that targets the reserved document id.
-        let reserved = MutationIntent {
-            sequence: 3,
-            document_id: 0,
-            previous: None,
-            proposed: None,
-        };
         collection
-            .storage
-            .create(&Collection::mutation_intent_path(3), &reserved)
+            .update(
+                alice,
+                BTreeMap::from([("name".to_string(), Fv::Text("carol".to_string()))]),
+            )
             .await?;
-
-        // (d) A phantom posting a partial flush left behind under a key only
-        // the (undecodable) pre-image knew about. Value-keyed removal cannot
-        // reach it; the replay must sweep it out by id.
-        for index in &collection.btree_indexes {
-            if index.name() == "age" {
-                assert!(index.insert(id, &Fv::U64(99), unix_ms())?);
-            }
-        }
-
-        db.close().await?;
+        collection.remove(bob).await?;
+        let dave = collection
+            .add_from(&create_test_doc(0, "dave", 32, vec!["d"]))
+            .await?;
+        drop(collection);
         drop(db);
 
-        let db = connect_test_db(object_store.clone()).await?;
+        let db = AndaDB::connect(object_store.clone(), db_config()).await?;
         let collection = db
-            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .open_collection("documents".to_string(), async |_| Ok(()))
             .await?;
-        assert!(
-            collection.stale_mutation_intents.lock().is_empty(),
-            "the successful open checkpoint must retire unusable records"
-        );
-        assert_eq!(collection.len(), 1);
-        let doc: TestDoc = collection.get_as(id).await?;
-        assert_eq!(doc.name, "alice");
-        assert_eq!(
-            collection
-                .query_ids(
-                    Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(30)))),
-                    None,
-                )
-                .await?,
-            vec![id]
-        );
-        // The phantom posting from (d) must be gone: the replay swept the id
-        // out of every index before re-indexing the stored document.
-        assert_eq!(
-            collection
-                .query_ids(
-                    Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(99)))),
-                    None,
-                )
-                .await?,
-            Vec::<DocumentId>::new()
-        );
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection.get_as::<TestDoc>(alice).await?.name, "carol");
+        assert_eq!(collection.get_as::<TestDoc>(dave).await?.name, "dave");
+        assert!(matches!(
+            collection.get(bob).await,
+            Err(DBError::NotFound { .. })
+        ));
+        let hits = collection
+            .query_ids(
+                Filter::Field(("name".to_string(), RangeQuery::Eq(Fv::Text("carol".into())))),
+                None,
+            )
+            .await?;
+        assert_eq!(hits, vec![alice]);
 
-        db.close().await?;
-        assert!(collection.stale_mutation_intents.lock().is_empty());
-        let mut objects = object_store.list(None);
-        while let Some(meta) = objects.next().await {
-            let meta = meta.map_err(DBError::from)?;
-            assert!(
-                !meta
-                    .location
-                    .as_ref()
-                    .contains(Collection::MUTATION_INTENT_PREFIX),
-                "stale mutation intent survived a successful checkpoint: {}",
-                meta.location
-            );
+        collection.flush(unix_ms()).await?;
+        let stats = collection.segment_stats().expect("segment storage");
+        assert!(stats.segments > 0 && stats.live_bytes > 0, "{stats:?}");
+
+        // Compaction runs outside the flush and keeps every record readable.
+        assert!(db.compact_segments().await? > 0);
+        let compacted = collection.segment_stats().expect("segment storage");
+        assert!(compacted.segments < stats.segments, "{compacted:?}");
+        assert_eq!(compacted.live_bytes, stats.live_bytes);
+        assert_eq!(collection.get_as::<TestDoc>(alice).await?.name, "carol");
+        assert_eq!(collection.get_as::<TestDoc>(dave).await?.name, "dave");
+        for prefix in ["data/", "mutation_intents/"] {
+            let path = Path::from(format!("segment_db/documents/{prefix}"));
+            let objects = object_store.list(Some(&path)).count().await;
+            assert_eq!(objects, 0, "{prefix}");
         }
+
+        db.close().await?;
         Ok(())
     }
 
-    /// Which end of the match set a page keeps must depend only on the method
-    /// called, never on the filter's shape. 0.11.0 derived it from the scan
-    /// direction, so `_id Lt cursor` returned the newest ids while the same
-    /// predicate inside an `And` returned the oldest — cursor pagination built
-    /// on the bare form silently walked backwards off the first page as soon
-    /// as a second condition was added.
     #[tokio::test]
-    async fn test_page_end_is_the_methods_contract_not_the_filters_shape() -> Result<(), DBError> {
-        let db = setup_test_db().await?;
-        let collection = create_test_collection(&db, async |c| {
-            c.create_btree_index_nx(&["name"]).await?;
-            c.create_btree_index_nx(&["age"]).await?;
-            Ok(())
-        })
-        .await?;
-
-        // ids 1..=10, all sharing one `name` so the composite filter matches
-        // exactly what the bare `_id` filter matches.
-        for i in 1..=10u64 {
-            let doc = create_test_doc(0, "alice", 20 + i as u32, vec!["x"]);
-            assert_eq!(collection.add_from(&doc).await?, i);
-        }
+    async fn test_export_import_stream_round_trip() -> Result<(), DBError> {
+        use crate::transfer::{ExportFormat, ImportIds, ImportOptions};
 
-        let bare = Filter::Field((Schema::ID_KEY.to_string(), RangeQuery::Lt(Fv::U64(100))));
-        let composite = Filter::And(vec![
-            Box::new(Filter::Field((
-                "name".to_string(),
-                RangeQuery::Eq(Fv::Text("alice".to_string())),
-            ))),
-            Box::new(bare.clone()),
-        ]);
+        async fn with_indexes(collection: &mut Collection) -> Result<(), DBError> {
+            collection.create_btree_index_nx(&["name"]).await?;
+            collection.create_bm25_index_nx(&["name", "tags"]).await?;
+            collection
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ..Default::default()
+                    },
+                )
+                .await
+        }
+        async fn export(collection: &Collection, format: ExportFormat) -> Vec<u8> {
+            let mut out = Vec::new();
+            let mut frames = collection.export_stream(format);
+            while let Some(frame) = frames.next().await {
+                out.extend_from_slice(&frame.unwrap());
+            }
+            out
+        }
 
-        // Same predicate, two shapes, same page — in both directions.
-        for filter in [bare.clone(), composite.clone()] {
+        let db = setup_test_db().await?;
+        let source =
+            create_test_collection(&db, async |collection| with_indexes(collection).await).await?;
+        let mut expected = Vec::new();
+        for (name, age, tags) in [
+            ("Alice", 30, vec!["smart"]),
+            ("Bob", 42, vec!["careful", "focused"]),
+            ("Carol", 27, vec![]),
+        ] {
+            let mut doc = create_test_doc(0, name, age, tags);
+            doc.metadata
+                .insert("nested".to_string(), serde_json::json!({"a": [1, 2]}));
+            doc.data.insert(ByteArrayB64([1, 2, 3, 4]), age as u64);
+            doc._id = source.add_from(&doc).await?;
+            expected.push(doc);
+        }
+        source.remove(expected[1]._id).await?;
+        expected.remove(1);
+
+        for (format, ids) in [
+            (ExportFormat::Ndjson, ImportIds::Preserve),
+            (ExportFormat::Cbor, ImportIds::Remap),
+        ] {
+            let data = export(&source, format).await;
+            let target = db
+                .create_collection(
+                    TestDoc::schema()?,
+                    CollectionConfig {
+                        name: format!("import_{format:?}").to_lowercase(),
+                        description: "".to_string(),
+                        ..Default::default()
+                    },
+                    async |collection| with_indexes(collection).await,
+                )
+                .await?;
+            if ids == ImportIds::Remap {
+                // Occupies id 1, so remapped ids differ from the exported ones.
+                target
+                    .add_from(&create_test_doc(0, "Zed", 1, vec![]))
+                    .await?;
+            }
+            let report = target
+                .import_stream(
+                    &data[..],
+                    format,
+                    ImportOptions {
+                        ids,
+                        batch_size: 1,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            assert_eq!(report.imported, 2);
+            if ids == ImportIds::Remap {
+                assert_eq!(report.ids, vec![(expected[0]._id, 2), (expected[1]._id, 3)]);
+            }
+
+            for (doc, (exported, imported)) in expected.iter().zip(&report.ids) {
+                assert_eq!(*exported, doc._id);
+                if ids == ImportIds::Preserve {
+                    assert_eq!(imported, exported);
+                }
+                let got: TestDoc = target.get_as(*imported).await?;
+                assert_eq!(
+                    got,
+                    TestDoc {
+                        _id: *imported,
+                        ..doc.clone()
+                    }
+                );
+                assert_eq!(
+                    target
+                        .query_ids(
+                            Filter::Field((
+                                "name".to_string(),
+                                RangeQuery::Eq(Fv::Text(doc.name.clone()))
+                            )),
+                            None,
+                        )
+                        .await?,
+                    vec![*imported]
+                );
+            }
             assert_eq!(
-                collection.query_ids(filter.clone(), Some(3)).await?,
-                vec![1, 2, 3],
-                "query_ids must keep the smallest ids for {filter:?}"
+                target
+                    .search_ids(Query {
+                        search: Some(Search {
+                            text: Some("smart".to_string()),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })
+                    .await?,
+                vec![report.ids[0].1]
             );
             assert_eq!(
-                collection.query_last_ids(filter.clone(), Some(3)).await?,
-                vec![8, 9, 10],
-                "query_last_ids must keep the largest ids for {filter:?}"
+                target.get_hnsw_index("vector")?.stats().num_elements,
+                if ids == ImportIds::Remap { 3 } else { 2 }
             );
+
+            // Preserved ids must be fresh in the target.
+            assert!(matches!(
+                target
+                    .import_stream(
+                        &data[..],
+                        format,
+                        ImportOptions {
+                            ids: ImportIds::Preserve,
+                            ..Default::default()
+                        },
+                    )
+                    .await,
+                Err(DBError::AlreadyExists { .. })
+            ));
         }
 
-        // Newest-first cursor pagination walks the whole set without gaps or
-        // repeats, which is what the shape-dependent end broke downstream.
-        let mut seen = Vec::new();
-        let mut cursor = collection.max_document_id() + 1;
-        loop {
-            let page = collection
-                .query_last_ids(
-                    Filter::And(vec![
-                        Box::new(Filter::Field((
-                            "name".to_string(),
-                            RangeQuery::Eq(Fv::Text("alice".to_string())),
-                        ))),
-                        Box::new(Filter::Field((
-                            Schema::ID_KEY.to_string(),
-                            RangeQuery::Lt(Fv::U64(cursor)),
-                        ))),
-                    ]),
-                    Some(4),
-                )
-                .await?;
-            if page.is_empty() {
-                break;
-            }
-            cursor = page[0];
-            seen.extend(page.iter().rev().copied());
+        // Re-seeding into a redesigned schema.
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, AndaDBSchema)]
+        struct SlimDoc {
+            pub _id: u64,
+            pub name: String,
+            pub age: u32,
         }
-        assert_eq!(seen, vec![10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        let data = export(&source, ExportFormat::Cbor).await;
+        let slim = db
+            .create_collection(
+                SlimDoc::schema()?,
+                CollectionConfig {
+                    name: "slim".to_string(),
+                    description: "".to_string(),
+                    ..Default::default()
+                },
+                async |_| Ok(()),
+            )
+            .await?;
+        assert!(matches!(
+            slim.import_stream(&data[..], ExportFormat::Cbor, ImportOptions::default())
+                .await,
+            Err(DBError::Schema { .. })
+        ));
+        assert!(slim.is_empty());
+        let report = slim
+            .import_stream(
+                &data[..],
+                ExportFormat::Cbor,
+                ImportOptions {
+                    skip_unknown_fields: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let got: SlimDoc = slim.get_as(report.ids[1].1).await?;
+        assert_eq!((got.name.as_str(), got.age), ("Carol", 27));
 
-        // A bounded `Not` also honors the requested end.
-        let complement = Filter::Not(Box::new(Filter::Field((
-            "age".to_string(),
-            RangeQuery::Between(Fv::U64(21), Fv::U64(28)),
-        ))));
-        assert_eq!(
-            collection.query_ids(complement.clone(), Some(1)).await?,
-            vec![9]
-        );
-        assert_eq!(
-            collection.query_last_ids(complement, Some(1)).await?,
-            vec![10]
+        assert!(
+            slim.import_stream(
+                &data[..data.len() - 1],
+                ExportFormat::Cbor,
+                ImportOptions::default()
+            )
+            .await
+            .is_err()
         );
 
         db.close().await?;
         Ok(())
     }
 
-    /// `query_all_ids` must return every match: in-process callers (cascade
-    /// deletion, link re-pointing, `NOT` evaluation) corrupt data when a
-    /// result is silently truncated, which is exactly what `query_ids`'s
-    /// `MAX_SEARCH_LIMIT` clamp does to them.
-    #[tokio::test]
-    async fn test_query_all_ids_is_unbounded() -> Result<(), DBError> {
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_bulk_loader_finish_on_a_runtime_worker() -> Result<(), DBError> {
         let db = setup_test_db().await?;
-        let collection = create_test_collection(&db, async |c| {
-            c.create_btree_index_nx(&["age"]).await?;
-            Ok(())
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_bm25_index_nx(&["name", "tags"]).await?;
+            collection
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ..Default::default()
+                    },
+                )
+                .await
         })
         .await?;
 
-        let n = Collection::MAX_SEARCH_LIMIT + 100;
-        for i in 0..n {
-            collection
-                .add_from(&create_test_doc(0, &format!("u{i}"), 1, vec![]))
-                .await?;
-        }
-
-        let filter = Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(1))));
+        // `finish` runs as a task on the runtime's only worker, which its
+        // batch inserts hand off through `block_in_place`.
+        let loaded = tokio::spawn({
+            let collection = collection.clone();
+            async move {
+                let mut loader = collection.bulk_loader().await?;
+                for i in 0..300u32 {
+                    let mut doc = create_test_doc(0, &format!("user{i:03}"), i, vec!["bulk"]);
+                    doc.vector = (0..10)
+                        .map(|d| bf16::from_f32(((i * 10 + d) % 97) as f32 / 97.0))
+                        .collect();
+                    loader.add_from(&doc).await?;
+                }
+                loader.finish().await
+            }
+        })
+        .await
+        .unwrap()?;
+        assert_eq!(loaded, 300);
         assert_eq!(
-            collection.query_ids(filter.clone(), None).await?.len(),
-            Collection::MAX_SEARCH_LIMIT
+            collection.get_hnsw_index("vector")?.stats().num_elements,
+            300
         );
-        assert_eq!(collection.query_all_ids(filter).await?.len(), n);
 
         db.close().await?;
         Ok(())
     }
 
-    /// Legacy data a later validation tightening rejects must stay
-    /// manageable in-band: `search` skips such a document instead of failing
-    /// the whole batch, and `remove` deletes it via an id sweep even though
-    /// its values cannot drive value-keyed index cleanup. `get` stays strict.
     #[tokio::test]
-    async fn test_schema_invalid_stored_document_is_skippable_and_removable() -> Result<(), DBError>
-    {
+    async fn test_bulk_loader_builds_indexes_at_finish() -> Result<(), DBError> {
         let db = setup_test_db().await?;
-        let collection = create_test_collection(&db, async |c| {
-            c.create_btree_index_nx(&["age"]).await?;
-            Ok(())
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_btree_index_nx(&["name"]).await?;
+            collection.create_btree_index_nx(&["name", "age"]).await?;
+            collection.create_bm25_index_nx(&["name", "tags"]).await?;
+            collection
+                .create_hnsw_index_nx(
+                    "vector",
+                    HnswConfig {
+                        dimension: 10,
+                        ..Default::default()
+                    },
+                )
+                .await
         })
         .await?;
-
-        let id_ok = collection
-            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
-            .await?;
-        let id_bad = collection
-            .add_from(&create_test_doc(0, "bob", 40, vec!["b"]))
-            .await?;
-
-        // Overwrite the stored object with a raw image whose `name` is not
-        // Text — the shape 0.10 write paths could persist before validation
-        // covered it.
-        let name_idx = collection
-            .schema()
-            .get_field("name")
-            .expect("name field")
-            .idx();
-        let mut raw: DocumentOwned = Document::try_from(
-            collection.schema(),
-            &create_test_doc(id_bad, "bob", 40, vec!["b"]),
-        )?
-        .into();
-        raw.fields.insert(name_idx, Fv::U64(7));
         collection
-            .storage
-            .put(&Collection::doc_path(id_bad), &raw, None)
+            .add_from(&create_test_doc(0, "seed", 1, vec!["seed"]))
             .await?;
 
-        // Reads of the poisoned id stay strict.
-        assert!(collection.get_as::<TestDoc>(id_bad).await.is_err());
-
-        // A search matching both documents returns the healthy one instead
-        // of failing the whole batch.
-        let docs = collection
-            .search(Query {
-                filter: Some(Filter::Field((
-                    "age".to_string(),
-                    RangeQuery::Ge(Fv::U64(0)),
-                ))),
-                limit: Some(10),
-                ..Default::default()
-            })
-            .await?;
-        assert_eq!(docs.len(), 1);
-        assert_eq!(docs[0].id(), id_ok);
+        let mut loader = collection.bulk_loader().await?;
+        let mut ids = Vec::new();
+        for i in 0..300u32 {
+            let mut doc = create_test_doc(0, &format!("user{i:03}"), i, vec!["bulk"]);
+            doc.vector = (0..10)
+                .map(|d| bf16::from_f32(((i * 10 + d) % 97) as f32 / 97.0))
+                .collect();
+            ids.push(loader.add_from(&doc).await?);
+        }
+        assert_eq!(loader.len(), 300);
+        // Nothing is visible before `finish`.
+        assert!(matches!(
+            collection.get(ids[0]).await,
+            Err(DBError::NotFound { .. })
+        ));
+        assert_eq!(loader.finish().await?, 300);
 
-        // `remove` deletes it (no recoverable pre-image, so it reports None)
-        // and sweeps its postings, so the id stops matching queries.
-        assert!(collection.remove(id_bad).await?.is_none());
-        assert_eq!(collection.len(), 1);
+        assert_eq!(collection.len(), 301);
+        let got: TestDoc = collection.get_as(ids[42]).await?;
+        assert_eq!(got.name, "user042");
         assert_eq!(
             collection
                 .query_ids(
-                    Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(40)))),
+                    Filter::Field((
+                        "name".to_string(),
+                        RangeQuery::Between(
+                            Fv::Text("user010".to_string()),
+                            Fv::Text("user019".to_string())
+                        )
+                    )),
                     None,
                 )
                 .await?,
-            Vec::<DocumentId>::new()
+            ids[10..20].to_vec()
         );
-
-        db.close().await?;
-        Ok(())
-    }
-
-    /// Neither a multi-key posting scan nor a caller-supplied `Include` list
-    /// may emit the same document id twice: duplicates make `search` return
-    /// the same document repeatedly and consume the caller's `limit`.
-    #[tokio::test]
-    async fn test_filter_results_are_deduplicated() -> Result<(), DBError> {
-        let db = setup_test_db().await?;
-        let collection = create_test_collection(&db, async |c| {
-            c.create_btree_index_nx(&["tags"]).await?;
-            Ok(())
-        })
-        .await?;
-
-        let id = collection
-            .add_from(&create_test_doc(0, "alice", 30, vec!["x", "y", "z"]))
+        let hits = collection
+            .search_ids(Query {
+                search: Some(Search {
+                    text: Some("bulk".to_string()),
+                    ..Default::default()
+                }),
+                limit: Some(1000),
+                ..Default::default()
+            })
             .await?;
+        assert_eq!(hits.len(), 300);
+        assert_eq!(
+            collection.get_hnsw_index("vector")?.stats().num_elements,
+            301
+        );
+        assert_eq!(collection.stats().insert_count, 301);
 
-        // The document is indexed under three keys; a range scan visits all
-        // three postings.
-        let tags_filter = Filter::Field((
-            "tags".to_string(),
-            RangeQuery::Between(Fv::Text("a".to_string()), Fv::Text("zz".to_string())),
+        // A unique conflict rolls the whole load back.
+        let mut loader = collection.bulk_loader().await?;
+        let conflicting = loader
+            .add_from(&create_test_doc(0, "fresh", 7, vec!["fresh"]))
+            .await?;
+        loader
+            .add_from(&create_test_doc(0, "user005", 5, vec!["dup"]))
+            .await?;
+        assert!(matches!(
+            loader.finish().await,
+            Err(DBError::AlreadyExists { .. })
         ));
+        assert!(!collection.is_poisoned());
+        assert_eq!(collection.len(), 301);
+        assert!(matches!(
+            collection
+                .documents
+                .fetch::<DocumentOwned>(RecordKey::Document(conflicting))
+                .await,
+            Err(DBError::NotFound { .. })
+        ));
+        assert!(
+            collection
+                .query_ids(
+                    Filter::Field((
+                        "name".to_string(),
+                        RangeQuery::Eq(Fv::Text("fresh".to_string()))
+                    )),
+                    None,
+                )
+                .await?
+                .is_empty()
+        );
         assert_eq!(
-            collection.query_ids(tags_filter.clone(), None).await?,
-            vec![id]
+            collection.get_hnsw_index("vector")?.stats().num_elements,
+            301
         );
-        let docs = collection
-            .search(Query {
-                filter: Some(tags_filter.clone()),
-                limit: Some(10),
-                ..Default::default()
-            })
-            .await?;
-        assert_eq!(docs.len(), 1);
 
-        // A `limit` must count distinct documents, not postings.
-        let second = collection
-            .add_from(&create_test_doc(0, "bob", 31, vec!["x", "y", "z"]))
+        // Dropping an unfinished loader poisons the handle; reopening indexes
+        // the stored documents.
+        let mut loader = collection.bulk_loader().await?;
+        let orphan = loader
+            .add_from(&create_test_doc(0, "orphan", 99, vec!["orphan"]))
             .await?;
-        assert_eq!(
-            collection.query_ids(tags_filter, Some(2)).await?,
-            vec![id, second]
-        );
+        drop(loader);
+        assert!(collection.is_poisoned());
+        drop(collection);
 
-        // Duplicated keys in an `Include` list yield one hit each, matching
-        // `anda_db_btree`'s own `Include` semantics.
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
+            .await?;
+        assert_eq!(collection.len(), 302);
         assert_eq!(
             collection
                 .query_ids(
                     Filter::Field((
-                        "_id".to_string(),
-                        RangeQuery::Include(vec![
-                            Fv::U64(id),
-                            Fv::U64(id),
-                            Fv::U64(second),
-                            Fv::U64(id),
-                        ]),
+                        "name".to_string(),
+                        RangeQuery::Eq(Fv::Text("orphan".to_string()))
                     )),
                     None,
                 )
                 .await?,
-            vec![id, second]
+            vec![orphan]
         );
 
         db.close().await?;
         Ok(())
     }
 
-    /// `create_hnsw_index_nx` must not silently keep an index whose persisted
-    /// configuration differs from the requested one.
     #[tokio::test]
-    async fn test_create_hnsw_index_nx_rejects_changed_config() -> Result<(), DBError> {
+    async fn test_search_order_by_projection_and_cursor() -> Result<(), DBError> {
         let db = setup_test_db().await?;
-        let collection = create_test_collection(&db, async |c| {
-            c.create_hnsw_index_nx(
-                "vector",
-                HnswConfig {
-                    dimension: 10,
-                    ..Default::default()
-                },
-            )
-            .await?;
-            // Same configuration: still idempotent.
-            c.create_hnsw_index_nx(
-                "vector",
-                HnswConfig {
-                    dimension: 10,
-                    ..Default::default()
-                },
-            )
-            .await?;
-
-            let err = c
-                .create_hnsw_index_nx(
-                    "vector",
-                    HnswConfig {
-                        dimension: 4,
-                        ..Default::default()
-                    },
-                )
-                .await
-                .expect_err("a changed configuration must not be discarded");
-            assert!(matches!(err, DBError::Index { .. }), "{err:?}");
-
-            // A non-dimension change must be reported too.
-            let err = c
-                .create_hnsw_index_nx(
-                    "vector",
-                    HnswConfig {
-                        dimension: 10,
-                        ef_search: 999,
-                        ..Default::default()
-                    },
-                )
-                .await
-                .expect_err("a changed configuration must not be discarded");
-            assert!(matches!(err, DBError::Index { .. }), "{err:?}");
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_btree_index_nx(&["age"]).await?;
+            collection.create_btree_index_nx(&["name"]).await?;
+            collection.create_btree_index_nx(&["tags"]).await?;
+            collection.create_bm25_index_nx(&["tags"]).await?;
             Ok(())
         })
         .await?;
 
-        // The existing index is untouched and still accepts its own vectors.
-        assert_eq!(collection.get_hnsw_index("vector")?.dimension(), 10);
-        collection
-            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
-            .await?;
+        let mut docs = Vec::new();
+        for i in 0..23u32 {
+            let tag = if i % 2 == 0 { "even" } else { "odd" };
+            let doc = create_test_doc(0, &format!("user{i:02}"), i % 4, vec![tag]);
+            let id = collection.add_from(&doc).await?;
+            docs.push((i % 4, id, format!("user{i:02}"), tag));
+        }
+
+        // Pages an ordered query to the end and returns every id in order.
+        async fn collect_pages(collection: &Collection, query: Query) -> Result<Vec<u64>, DBError> {
+            let mut ids = Vec::new();
+            let mut query = query;
+            loop {
+                let page = collection.search_page(query.clone()).await?;
+                assert!(page.items.len() <= query.limit.unwrap());
+                ids.extend(page.items.iter().map(|doc| doc.id()));
+                match page.cursor {
+                    Some(cursor) => query.cursor = Some(cursor),
+                    None => return Ok(ids),
+                }
+            }
+        }
+
+        let age_order = |descending| Query {
+            order_by: Some(OrderBy {
+                field: "age".to_string(),
+                descending,
+            }),
+            limit: Some(5),
+            ..Default::default()
+        };
+
+        let mut expected: Vec<(u32, u64)> = docs.iter().map(|d| (d.0, d.1)).collect();
+        expected.sort();
+        let asc: Vec<u64> = expected.iter().map(|d| d.1).collect();
+        assert_eq!(collect_pages(&collection, age_order(false)).await?, asc);
+        let desc: Vec<u64> = asc.iter().rev().copied().collect();
+        assert_eq!(collect_pages(&collection, age_order(true)).await?, desc);
+
+        // `search_ids` returns the first page of the same order.
+        assert_eq!(collection.search_ids(age_order(true)).await?, desc[..5]);
+
+        // A filter narrows the ordered results.
+        let mut query = age_order(true);
+        query.filter = Some(Filter::Field((
+            "name".to_string(),
+            RangeQuery::Ge(Fv::Text("user10".to_string())),
+        )));
+        let filtered: Vec<u64> = desc
+            .iter()
+            .copied()
+            .filter(|id| docs.iter().any(|d| d.1 == *id && d.2.as_str() >= "user10"))
+            .collect();
+        assert_eq!(collect_pages(&collection, query).await?, filtered);
+
+        // So does a text search, whose relevance order is replaced.
+        let mut query = age_order(false);
+        query.search = Some(Search {
+            text: Some("odd".to_string()),
+            ..Default::default()
+        });
+        let odd: Vec<u64> = asc
+            .iter()
+            .copied()
+            .filter(|id| docs.iter().any(|d| d.1 == *id && d.3 == "odd"))
+            .collect();
+        assert_eq!(collect_pages(&collection, query).await?, odd);
+
+        // Projection keeps `_id` and the named fields only.
+        let mut query = age_order(false);
+        query.fields = Some(vec!["name".to_string()]);
+        let page = collection.search_page(query).await?;
+        assert_eq!(page.items.len(), 5);
+        for doc in &page.items {
+            assert!(doc.get_field("name").is_some());
+            assert!(doc.get_field("age").is_none());
+            assert!(doc.get_field("vector").is_none());
+        }
+        let mut query = age_order(false);
+        query.fields = Some(vec!["name".to_string()]);
+        let names: Vec<BTreeMap<String, Fv>> = collection.search_as(query).await?;
+        assert_eq!(names[0].len(), 2);
+        assert_eq!(names[0].get("_id"), Some(&Fv::U64(asc[0])));
+
+        // Invalid orderings, cursors and projections are rejected.
+        for field in ["tags", "metadata", "missing"] {
+            let query = Query {
+                order_by: Some(OrderBy {
+                    field: field.to_string(),
+                    descending: false,
+                }),
+                ..Default::default()
+            };
+            assert!(collection.search_ids(query).await.is_err(), "{field}");
+        }
+        let cursor = collection
+            .search_page(age_order(false))
+            .await?
+            .cursor
+            .unwrap();
+        let mut query = age_order(true);
+        query.cursor = Some(cursor.clone());
+        assert!(collection.search_ids(query).await.is_err());
+        let query = Query {
+            cursor: Some(cursor),
+            ..Default::default()
+        };
+        assert!(collection.search_ids(query).await.is_err());
+        let query = Query {
+            fields: Some(vec!["missing".to_string()]),
+            ..Default::default()
+        };
+        assert!(collection.search(query).await.is_err());
 
-        db.close().await?;
         Ok(())
     }
 
-    /// `PutMode::Create` reporting `AlreadyExists` means this add wrote
-    /// nothing and the object belongs to someone else. The compensating
-    /// delete must not run, or it destroys a committed document.
     #[tokio::test]
-    async fn test_add_conflict_does_not_delete_the_existing_document() -> Result<(), DBError> {
-        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    async fn test_aggregate_over_btree_index() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let collection = create_test_collection(&db, async |collection| {
+            collection.create_btree_index_nx(&["age"]).await?;
+            collection.create_btree_index_nx(&["name"]).await?;
+            collection.create_btree_index_nx(&["tags"]).await?;
+            Ok(())
+        })
+        .await?;
+        for i in 0..10u32 {
+            let tags = if i % 2 == 0 {
+                vec!["even", "all"]
+            } else {
+                vec!["all"]
+            };
+            collection
+                .add_from(&create_test_doc(0, &format!("user{i}"), i % 4 * 10, tags))
+                .await?;
+        }
 
-        let db_a = connect_test_db(object_store.clone()).await?;
-        let collection_a = create_test_collection(&db_a, async |_| Ok(())).await?;
-        // Publish the collection so the second writer can open it while its
-        // own `max_document_id` is still 0.
-        db_a.flush().await?;
+        let aggregate = |field: &str, aggregation| Aggregate {
+            field: field.to_string(),
+            aggregation,
+            filter: None,
+            limit: None,
+        };
+        let bucket = |key: Fv, count| AggregateBucket { key, count };
 
-        let db_b = connect_test_db(object_store.clone()).await?;
-        let collection_b = create_test_collection(&db_b, async |_| Ok(())).await?;
-        assert_eq!(collection_b.max_document_id(), 0);
+        let rt = collection
+            .aggregate(aggregate("age", Aggregation::Stats))
+            .await?;
+        assert_eq!(rt.count, 10);
+        assert_eq!(rt.min, Some(Fv::U64(0)));
+        assert_eq!(rt.max, Some(Fv::U64(30)));
+        assert!(rt.buckets.is_empty());
 
-        let id = collection_a
-            .add_from(&create_test_doc(0, "alice", 30, vec!["a"]))
+        let rt = collection
+            .aggregate(aggregate("age", Aggregation::Terms))
             .await?;
-        assert_eq!(id, 1);
-        collection_a.flush(unix_ms()).await?;
+        assert_eq!(
+            rt.buckets,
+            vec![
+                bucket(Fv::U64(0), 3),
+                bucket(Fv::U64(10), 3),
+                bucket(Fv::U64(20), 2),
+                bucket(Fv::U64(30), 2),
+            ]
+        );
+        assert!(!rt.truncated);
 
-        // The second writer allocates the same id and loses the create race.
-        let err = collection_b
-            .add_from(&create_test_doc(0, "bob", 31, vec!["b"]))
-            .await
-            .expect_err("the conflicting add must fail");
-        assert!(matches!(err, DBError::AlreadyExists { .. }), "{err:?}");
+        let rt = collection
+            .aggregate(aggregate("age", Aggregation::Histogram { interval: 20 }))
+            .await?;
+        assert_eq!(
+            rt.buckets,
+            vec![bucket(Fv::U64(0), 6), bucket(Fv::U64(20), 4)]
+        );
 
-        // Writer A's document must still be there.
-        let stored = object_store
-            .head(&Path::from(format!(
-                "test_db/test_collection/{}",
-                Collection::doc_path(id)
-            )))
-            .await;
-        assert!(
-            stored.is_ok(),
-            "the losing add must not delete the winner's document: {stored:?}"
+        // Array fields count a document once per element.
+        let rt = collection
+            .aggregate(aggregate("tags", Aggregation::Terms))
+            .await?;
+        assert_eq!(rt.count, 15);
+        assert_eq!(
+            rt.buckets,
+            vec![
+                bucket(Fv::Text("all".to_string()), 10),
+                bucket(Fv::Text("even".to_string()), 5),
+            ]
         );
-        let doc: TestDoc = collection_a.get_as(id).await?;
-        assert_eq!(doc.name, "alice");
 
-        db_a.close().await?;
+        // A filter restricts the counted documents; `limit` only the buckets.
+        let mut query = aggregate("age", Aggregation::Terms);
+        query.filter = Some(Filter::Field((
+            "tags".to_string(),
+            RangeQuery::Eq(Fv::Text("even".to_string())),
+        )));
+        query.limit = Some(1);
+        let rt = collection.aggregate(query).await?;
+        assert_eq!(rt.count, 5);
+        assert_eq!(rt.min, Some(Fv::U64(0)));
+        assert_eq!(rt.max, Some(Fv::U64(20)));
+        assert_eq!(rt.buckets, vec![bucket(Fv::U64(0), 3)]);
+        assert!(rt.truncated);
+
+        for query in [
+            aggregate("missing", Aggregation::Stats),
+            aggregate("name", Aggregation::Histogram { interval: 10 }),
+            aggregate("age", Aggregation::Histogram { interval: 0 }),
+        ] {
+            assert!(matches!(
+                collection.aggregate(query).await,
+                Err(DBError::Index { .. })
+            ));
+        }
+
         Ok(())
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, AndaDBSchema)]
-    struct UniqueNameDoc {
-        pub _id: u64,
-        #[unique]
-        pub name: String,
-        pub body: String,
-        pub vector: Vector,
-    }
+    #[tokio::test]
+    async fn test_btree_index_on_nested_field_paths() -> Result<(), DBError> {
+        let object_store = Arc::new(InMemory::new());
+        let db_config = DBConfig {
+            name: "test_db".to_string(),
+            description: "Test database".to_string(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let doc = |name: &str, source: &str, rank: Json| {
+            let mut doc = create_test_doc(0, name, 30, vec![]);
+            doc.metadata
+                .insert("source".to_string(), Json::from(source));
+            doc.metadata
+                .insert("extra".to_string(), serde_json::json!({"rank": rank}));
+            doc
+        };
+        let eq = |path: &str, value: &str| {
+            Filter::Field((path.to_string(), RangeQuery::Eq(Fv::Text(value.into()))))
+        };
 
-    fn unique_name_doc(name: &str, vector: [f32; 4]) -> UniqueNameDoc {
-        UniqueNameDoc {
-            _id: 0,
-            name: name.to_string(),
-            body: format!("{name} keeps a journal"),
-            vector: vector.into_iter().map(bf16::from_f32).collect(),
+        let db = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
+        let collection = create_test_collection(&db, async |_| Ok(())).await?;
+        let alice = collection.add_from(&doc("Alice", "web", 1.into())).await?;
+        db.close().await?;
+
+        // Path indexes backfill existing documents like plain ones.
+        let db = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |collection| {
+                collection
+                    .create_unique_btree_index(&["metadata.source"])
+                    .await?;
+                collection
+                    .create_btree_index_nx(&["metadata.extra.rank"])
+                    .await?;
+                assert!(matches!(
+                    collection.create_btree_index(&["name.first"]).await,
+                    Err(DBError::Schema { .. })
+                ));
+                Ok(())
+            })
+            .await?;
+        let bob = collection.add_from(&doc("Bob", "api", 1.into())).await?;
+        assert!(matches!(
+            collection.add_from(&doc("Carol", "web", 2.into())).await,
+            Err(DBError::AlreadyExists { .. })
+        ));
+
+        assert_eq!(
+            collection
+                .query_ids(eq("metadata.source", "web"), None)
+                .await?,
+            vec![alice]
+        );
+        // JSON numbers are indexed as numbers, whatever their JSON form.
+        let rank =
+            |query: RangeQuery<Fv>| Filter::Field(("metadata.extra.rank".to_string(), query));
+        let mut ids = collection
+            .query_ids(rank(RangeQuery::Eq(Fv::U64(1))), None)
+            .await?;
+        ids.sort();
+        assert_eq!(ids, vec![alice, bob]);
+
+        // Ranges compare numerically ("10" would sort before "9" as text)
+        // and only match values of their bound's kind.
+        let nine = collection.add_from(&doc("Nina", "rss", 9.into())).await?;
+        let ten = collection.add_from(&doc("Ted", "cli", 10.0.into())).await?;
+        let half = collection.add_from(&doc("Hal", "irc", 9.5.into())).await?;
+        let text = collection.add_from(&doc("Tex", "ftp", "10".into())).await?;
+        let flag = collection
+            .add_from(&doc("Flo", "nntp", true.into()))
+            .await?;
+        assert_eq!(
+            collection
+                .query_ids(rank(RangeQuery::Eq(Fv::I64(10))), None)
+                .await?,
+            vec![ten]
+        );
+        let mut ids = collection
+            .query_ids(rank(RangeQuery::Gt(Fv::F64(2.5))), None)
+            .await?;
+        ids.sort();
+        assert_eq!(ids, vec![nine, ten, half]);
+        assert_eq!(
+            collection
+                .query_ids(rank(RangeQuery::Ge(Fv::Text(String::new()))), None)
+                .await?,
+            vec![text]
+        );
+        assert_eq!(
+            collection
+                .query_ids(rank(RangeQuery::Le(Fv::Bool(true))), None)
+                .await?,
+            vec![flag]
+        );
+        let ordered = Query {
+            order_by: Some(OrderBy {
+                field: "metadata.extra.rank".to_string(),
+                descending: true,
+            }),
+            ..Default::default()
+        };
+        let page = collection.search_ids(ordered).await?;
+        assert_eq!(page[..4], [text, ten, half, nine]);
+        assert_eq!(page.last(), Some(&flag));
+        for id in [nine, ten, half, text, flag] {
+            collection.remove(id).await?;
         }
-    }
 
-    /// Dropping a dead id from the bitmap is only half the repair: the index
-    /// postings survive, so a unique key stays occupied forever and queries
-    /// keep returning the dead id.
-    #[tokio::test]
-    async fn test_reconcile_storage_purges_index_postings_of_dead_ids() -> Result<(), DBError> {
-        let db = setup_test_db().await?;
+        // Updating the root field refreshes the path indexes.
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "metadata".to_string(),
+            Fv::Map(BTreeMap::from([(
+                FieldKey::Text("source".to_string()),
+                Fv::Json(Json::from("mail")),
+            )])),
+        );
+        collection.update(alice, fields).await?;
+        assert!(
+            collection
+                .query_ids(eq("metadata.source", "web"), None)
+                .await?
+                .is_empty()
+        );
+        assert_eq!(
+            collection
+                .query_ids(rank(RangeQuery::Eq(Fv::I64(1))), None)
+                .await?,
+            vec![bob]
+        );
+        db.close().await?;
+
+        let db = AndaDB::connect(object_store, db_config).await?;
         let collection = db
-            .open_or_create_collection(
-                UniqueNameDoc::schema()?,
-                CollectionConfig {
-                    name: "unique_docs".to_string(),
-                    description: String::new(),
-                },
-                async |c| {
-                    c.create_btree_index_nx(&["name"]).await?;
-                    c.create_bm25_index_nx(&["body"]).await?;
-                    c.create_hnsw_index_nx(
-                        "vector",
-                        HnswConfig {
-                            dimension: 4,
-                            ..Default::default()
-                        },
-                    )
-                    .await?;
-                    Ok(())
-                },
-            )
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
             .await?;
+        assert_eq!(
+            collection
+                .query_ids(eq("metadata.source", "mail"), None)
+                .await?,
+            vec![alice]
+        );
+        assert!(matches!(
+            collection.add_from(&doc("Dave", "api", 3.into())).await,
+            Err(DBError::AlreadyExists { .. })
+        ));
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partial_and_expression_btree_indexes() -> Result<(), DBError> {
+        let object_store = Arc::new(InMemory::new());
+        let db_config = DBConfig {
+            name: "test_db".to_string(),
+            description: "Test database".to_string(),
+            storage: StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+            lock: None,
+        };
+        let active = Filter::Field((
+            "tags".to_string(),
+            RangeQuery::Eq(Fv::Text("active".to_string())),
+        ));
+        let name_eq =
+            |name: &str| Filter::Field(("name".to_string(), RangeQuery::Eq(Fv::Text(name.into()))));
+
+        let db = AndaDB::connect(object_store.clone(), db_config.clone()).await?;
+        let collection = create_test_collection(&db, async |collection| {
+            for (fields, options) in [
+                (
+                    &["age"][..],
+                    BTreeIndexOptions {
+                        expression: Some(IndexExpression::Lowercase),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    &["name"][..],
+                    BTreeIndexOptions {
+                        expression: Some(IndexExpression::Truncate { interval: 10 }),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    &["name", "age"][..],
+                    BTreeIndexOptions {
+                        expression: Some(IndexExpression::Lowercase),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    &["name"][..],
+                    BTreeIndexOptions {
+                        filter: Some(Filter::Field((
+                            "age".to_string(),
+                            RangeQuery::Eq(Fv::Text("30".to_string())),
+                        ))),
+                        ..Default::default()
+                    },
+                ),
+            ] {
+                assert!(matches!(
+                    collection
+                        .create_btree_index_with_options(fields, options)
+                        .await,
+                    Err(DBError::Schema { .. })
+                ));
+            }
+
+            collection
+                .create_btree_index_with_options(
+                    &["name"],
+                    BTreeIndexOptions {
+                        filter: Some(active.clone()),
+                        expression: Some(IndexExpression::Lowercase),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            collection
+                .create_btree_index_with_options(
+                    &["age"],
+                    BTreeIndexOptions {
+                        expression: Some(IndexExpression::Truncate { interval: 10 }),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            Ok(())
+        })
+        .await?;
 
         let alice = collection
-            .add_from(&unique_name_doc("alice", [1.0, 0.0, 0.0, 0.0]))
+            .add_from(&create_test_doc(0, "Alice", 31, vec!["active"]))
             .await?;
-        collection
-            .add_from(&unique_name_doc("bob", [0.0, 1.0, 0.0, 0.0]))
+        let bob = collection
+            .add_from(&create_test_doc(0, "Bob", 35, vec!["inactive"]))
             .await?;
-        collection.flush(unix_ms()).await?;
-
-        // Simulate a crash that lost the document object.
-        collection
-            .storage
-            .delete(&Collection::doc_path(alice))
+        let carol = collection
+            .add_from(&create_test_doc(0, "CAROL", 47, vec!["x", "active"]))
             .await?;
 
-        let (recovered, dropped) = collection.reconcile_storage().await?;
-        assert_eq!((recovered, dropped), (0, 1));
-        assert!(!collection.contains(alice));
-
-        // The B-tree posting must be gone: queries no longer return the dead
-        // id, and the unique key is free again.
+        // Only active documents are indexed, under their lowercased name.
+        assert_eq!(
+            collection.query_ids(name_eq("alice"), None).await?,
+            vec![alice]
+        );
+        assert_eq!(
+            collection.query_ids(name_eq("carol"), None).await?,
+            vec![carol]
+        );
         assert!(
             collection
-                .query_ids(
-                    Filter::Field((
-                        "name".to_string(),
-                        RangeQuery::Eq(Fv::Text("alice".to_string())),
-                    )),
-                    None,
-                )
+                .query_ids(name_eq("Alice"), None)
                 .await?
                 .is_empty()
         );
+        assert!(collection.query_ids(name_eq("bob"), None).await?.is_empty());
 
-        // The BM25 postings must be gone too. They are keyed by the document's
-        // tokens, which the lost body no longer supplies, so the index has to
-        // be swept by id — otherwise a full-text search keeps resurrecting the
-        // dead id (`search_ids` returns BM25 hits unfiltered).
-        let text_hits = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    text: Some("alice".to_string()),
-                    ..Default::default()
-                }),
-                limit: Some(10),
-                ..Default::default()
-            })
-            .await?;
-        assert!(text_hits.is_empty(), "{text_hits:?}");
+        let age_eq = |age: u64| Filter::Field(("age".to_string(), RangeQuery::Eq(Fv::U64(age))));
+        let mut ids = collection.query_ids(age_eq(30), None).await?;
+        ids.sort();
+        assert_eq!(ids, vec![alice, bob]);
+        assert!(collection.query_ids(age_eq(31), None).await?.is_empty());
 
-        let reborn = collection
-            .add_from(&unique_name_doc("alice", [1.0, 0.0, 0.0, 0.0]))
-            .await?;
-        assert_ne!(reborn, alice);
+        // Updating only a field the predicate reads re-evaluates it.
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "tags".to_string(),
+            Fv::Array(vec![Fv::Text("active".to_string())]),
+        );
+        collection.update(bob, fields).await?;
+        assert_eq!(collection.query_ids(name_eq("bob"), None).await?, vec![bob]);
+        db.close().await?;
 
-        // The reborn document is the only text hit; the dead id stays gone.
-        let text_hits = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    text: Some("alice".to_string()),
-                    ..Default::default()
-                }),
-                limit: Some(10),
-                ..Default::default()
-            })
+        // A plain reopen, without hooks, applies the persisted options.
+        let db = AndaDB::connect(object_store, db_config).await?;
+        let collection = db
+            .open_collection("test_collection".to_string(), async |_| Ok(()))
             .await?;
-        assert_eq!(text_hits, vec![reborn]);
-
-        // The HNSW posting must be gone as well.
-        let hits = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    vector: Some(vec![1.0, 0.0, 0.0, 0.0]),
-                    ..Default::default()
-                }),
-                limit: Some(10),
-                ..Default::default()
-            })
+        assert!(
+            collection
+                .metadata()
+                .btree_index_options
+                .contains_key("name")
+        );
+        let dave = collection
+            .add_from(&create_test_doc(0, "DAVE", 52, vec!["active"]))
             .await?;
-        assert!(!hits.contains(&alice), "{hits:?}");
-
-        // Surviving documents keep their postings.
         assert_eq!(
-            collection
-                .query_ids(
-                    Filter::Field((
-                        "name".to_string(),
-                        RangeQuery::Eq(Fv::Text("bob".to_string())),
-                    )),
-                    None,
-                )
-                .await?
-                .len(),
-            1
+            collection.query_ids(name_eq("dave"), None).await?,
+            vec![dave]
         );
-        let bob_hits = collection
-            .search_ids(Query {
-                search: Some(Search {
-                    text: Some("bob".to_string()),
-                    ..Default::default()
-                }),
-                limit: Some(10),
-                ..Default::default()
+        assert_eq!(collection.query_ids(age_eq(50), None).await?, vec![dave]);
+        db.close().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ttl_expiry() -> Result<(), DBError> {
+        let db = setup_test_db().await?;
+        let config = CollectionConfig {
+            name: "test_collection".to_string(),
+            description: "Test collection".to_string(),
+            ttl: Some(TtlConfig {
+                field: "age".to_string(),
+                ttl_ms: 100,
+            }),
+            ..Default::default()
+        };
+
+        // The TTL field needs a BTree index.
+        assert!(matches!(
+            db.create_collection(TestDoc::schema()?, config.clone(), async |_| Ok(()))
+                .await,
+            Err(DBError::Schema { .. })
+        ));
+        let collection = db
+            .create_collection(TestDoc::schema()?, config, async |collection| {
+                collection.create_btree_index_nx(&["age"]).await?;
+                Ok(())
             })
             .await?;
-        assert_eq!(bob_hits.len(), 1, "{bob_hits:?}");
 
-        db.close().await?;
+        let mut ids = Vec::new();
+        for age in [60, 10, 200, 50, 40] {
+            let id = collection
+                .add_from(&create_test_doc(0, &format!("user{age}"), age, vec![]))
+                .await?;
+            ids.push(id);
+        }
+
+        // The oldest documents expire first, in bounded batches.
+        assert_eq!(collection.expire(150, 2).await?, 2);
+        assert!(collection.get(ids[1]).await.is_err());
+        assert!(collection.get(ids[4]).await.is_err());
+        assert_eq!(collection.expire(150, 10).await?, 1);
+        assert_eq!(collection.expire(150, 10).await?, 0);
+        let stats = collection.stats();
+        assert_eq!(stats.expired_count, 3);
+        assert_eq!(stats.last_expired, 150);
+        assert_eq!(stats.delete_count, 3);
+        assert_eq!(stats.num_documents, 2);
+
+        assert!(matches!(
+            collection
+                .set_ttl(Some(TtlConfig {
+                    field: "name".to_string(),
+                    ttl_ms: 0,
+                }))
+                .await,
+            Err(DBError::Schema { .. })
+        ));
+        collection.set_ttl(None).await?;
+        assert_eq!(collection.expire(1000, 10).await?, 0);
+
+        // With `ttl_ms = 0` the field is the expiry time itself.
+        collection
+            .set_ttl(Some(TtlConfig {
+                field: "age".to_string(),
+                ttl_ms: 0,
+            }))
+            .await?;
+        assert_eq!(db.expire_documents(100).await?, 1);
+        assert_eq!(collection.stats().num_documents, 1);
+        assert!(collection.get(ids[2]).await.is_ok());
+        assert_eq!(collection.stats().expired_count, 4);
+
+        // An update that refreshes the timestamp after the index scan keeps
+        // the document: the removal re-checks it under the document lock.
+        collection
+            .update(ids[2], BTreeMap::from([("age".to_string(), Fv::U64(500))]))
+            .await?;
+        let ttl = collection.ttl().unwrap();
+        let index = collection.find_btree_index(&["age"])?;
+        let removed = collection
+            .remove_if(ids[2], |doc| collection.is_expired(index, &ttl, doc, 300))
+            .await?;
+        assert!(removed.is_none());
+        assert!(collection.get(ids[2]).await.is_ok());
         Ok(())
     }
 
-    /// A B-tree index on the primary key can never answer a query: the filter
-    /// dispatcher serves `_id` from the collection's own id index.
     #[tokio::test]
-    async fn test_btree_index_on_primary_key_is_rejected() -> Result<(), DBError> {
+    async fn test_ttl_requires_plain_index() -> Result<(), DBError> {
         let db = setup_test_db().await?;
-        create_test_collection(&db, async |c| {
-            let err = c
-                .create_btree_index(&["_id"])
-                .await
-                .expect_err("an unreachable index must be rejected");
-            assert!(matches!(err, DBError::Schema { .. }), "{err:?}");
-            // `_nx` must not swallow it either.
-            assert!(c.create_btree_index_nx(&["_id"]).await.is_err());
+        let config = |name: &str| CollectionConfig {
+            name: name.to_string(),
+            description: "Test collection".to_string(),
+            ttl: Some(TtlConfig {
+                field: "age".to_string(),
+                ttl_ms: 100,
+            }),
+            ..Default::default()
+        };
+
+        // Partial and expression indexes do not hold every document's raw
+        // timestamp.
+        for options in [
+            BTreeIndexOptions {
+                filter: Some(Filter::Field((
+                    "name".to_string(),
+                    RangeQuery::Eq(Fv::Text("a".to_string())),
+                ))),
+                ..Default::default()
+            },
+            BTreeIndexOptions {
+                expression: Some(IndexExpression::Truncate { interval: 10 }),
+                ..Default::default()
+            },
+        ] {
+            let rt = db
+                .create_collection(TestDoc::schema()?, config("declarative"), async |c| {
+                    c.create_btree_index_with_options(&["age"], options).await
+                })
+                .await;
+            assert!(matches!(rt, Err(DBError::Schema { .. })));
+        }
+
+        db.create_collection(TestDoc::schema()?, config("plain"), async |c| {
+            c.create_btree_index_nx(&["age"]).await
+        })
+        .await?;
+        db.close_collection("plain").await?;
+
+        // The index backing the TTL cannot be removed while the TTL is set.
+        db.open_collection("plain".to_string(), async |c| {
+            assert!(matches!(
+                c.remove_btree_index(&["age"]).await,
+                Err(DBError::Schema { .. })
+            ));
             Ok(())
         })
         .await?;
-
         db.close().await?;
         Ok(())
     }
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::{
     Arc,
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
 };
 use std::{
     fmt::Debug,
@@ -15,9 +15,13 @@ use std::{
 use tokio_util::sync::CancellationToken;
 
 use crate::{
+    batch::{BatchManifest, BatchOp, WriteBatch},
     collection::{Collection, CollectionConfig},
     error::DBError,
+    index::{TokenizerConfig, TokenizerRegistry},
     schema::*,
+    segment::RecordKey,
+    snapshot::{DBSnapshot, SnapshotManifest, SnapshotObject},
     storage::{Storage, StorageConfig, StorageStats},
     unix_ms,
 };
@@ -63,6 +67,23 @@ struct InnerDB {
     /// the same name observe the winner's registration instead of a storage
     /// conflict. Entries are removed again once no task holds or awaits them.
     collection_locks: parking_lot::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Monotonic path component for write-batch manifests.
+    next_batch_sequence: AtomicU64,
+    /// Write batches that may be committed but are not fully applied: found
+    /// in storage on open, or registered by [`AndaDB::write_batch`] before
+    /// its manifest is created and retired once the manifest is deleted. A
+    /// batch whose call failed or was cancelled in between stays here, and
+    /// every collection it touches is poisoned until the batch is rolled
+    /// forward (see [`AndaDB::finish_write_batches`]).
+    unfinished_batches: parking_lot::Mutex<BTreeMap<u64, BatchManifest>>,
+    /// Sequences of the [`InnerDB::unfinished_batches`] whose
+    /// [`AndaDB::write_batch`] call is still running; roll-forward skips them.
+    active_batches: parking_lot::Mutex<BTreeSet<u64>>,
+    /// Serializes rolling unfinished write batches forward.
+    batch_recovery_lock: tokio::sync::Mutex<()>,
+    /// Named tokenizer definitions collections resolve their recorded
+    /// tokenizers against (see [`AndaDB::register_tokenizer`]).
+    tokenizers: RwLock<TokenizerRegistry>,
 }
 
 /// RAII guard for a per-collection-name lifecycle lock.
@@ -94,6 +115,42 @@ impl Drop for CollectionNameLock {
     }
 }
 
+/// Marks a write batch as running in [`InnerDB::active_batches`].
+///
+/// Dropping the guard, including when the [`AndaDB::write_batch`] future is
+/// cancelled, hands a batch still registered in
+/// [`InnerDB::unfinished_batches`] over to roll-forward.
+struct ActiveBatch<'a> {
+    inner: &'a InnerDB,
+    sequence: u64,
+}
+
+impl<'a> ActiveBatch<'a> {
+    fn new(inner: &'a InnerDB, manifest: &BatchManifest) -> Self {
+        inner.active_batches.lock().insert(manifest.sequence);
+        inner
+            .unfinished_batches
+            .lock()
+            .insert(manifest.sequence, manifest.clone());
+        Self {
+            inner,
+            sequence: manifest.sequence,
+        }
+    }
+
+    /// Unregisters a batch that is definitely not committed, or fully
+    /// applied with its manifest deleted.
+    fn retire(self) {
+        self.inner.unfinished_batches.lock().remove(&self.sequence);
+    }
+}
+
+impl Drop for ActiveBatch<'_> {
+    fn drop(&mut self) {
+        self.inner.active_batches.lock().remove(&self.sequence);
+    }
+}
+
 /// Database configuration parameters.
 ///
 /// Contains settings that define the database's behavior and properties.
@@ -150,6 +207,14 @@ impl AndaDB {
     /// Path where database metadata is stored
     const METADATA_PATH: &'static str = "db_meta.cbor";
 
+    /// Prefix for write-batch manifests. Collection names cannot contain
+    /// `-`, so the prefix never overlaps a collection's storage prefix.
+    const WRITE_BATCH_PREFIX: &'static str = "write-batches/";
+
+    fn write_batch_path(sequence: u64) -> String {
+        format!("{}{sequence:020}.cbor", Self::WRITE_BATCH_PREFIX)
+    }
+
     /// Returns aggregated storage I/O statistics across the database and all
     /// currently open collections.
     pub fn stats(&self) -> StorageStats {
@@ -210,6 +275,11 @@ impl AndaDB {
                 read_only: Arc::new(AtomicBool::new(false)),
                 dropping_collections: RwLock::new(BTreeSet::new()),
                 collection_locks: parking_lot::Mutex::new(HashMap::new()),
+                next_batch_sequence: AtomicU64::new(unix_ms()),
+                unfinished_batches: parking_lot::Mutex::new(BTreeMap::new()),
+                active_batches: parking_lot::Mutex::new(BTreeSet::new()),
+                batch_recovery_lock: tokio::sync::Mutex::new(()),
+                tokenizers: RwLock::new(TokenizerRegistry::default()),
             }),
         })
     }
@@ -278,6 +348,11 @@ impl AndaDB {
                         read_only: Arc::new(AtomicBool::new(false)),
                         dropping_collections: RwLock::new(BTreeSet::new()),
                         collection_locks: parking_lot::Mutex::new(HashMap::new()),
+                        next_batch_sequence: AtomicU64::new(unix_ms()),
+                        unfinished_batches: parking_lot::Mutex::new(BTreeMap::new()),
+                        active_batches: parking_lot::Mutex::new(BTreeSet::new()),
+                        batch_recovery_lock: tokio::sync::Mutex::new(()),
+                        tokenizers: RwLock::new(TokenizerRegistry::default()),
                     }),
                 };
 
@@ -285,6 +360,19 @@ impl AndaDB {
                     this.set_lock(lock).await?;
                 }
 
+                // Committed write batches left by a crash must be rolled
+                // forward before any collection loads, so the collections'
+                // intent replay sees their final document images.
+                this.load_write_batches().await?;
+                let recovered = this.finish_write_batches().await?;
+                if recovered > 0 {
+                    log::warn!(
+                        action = "AndaDB::open",
+                        database = this.inner.name;
+                        "Rolled forward {recovered} committed write batches",
+                    );
+                }
+
                 Ok(this)
             }
             Err(err) => Err(err),
@@ -315,6 +403,35 @@ impl AndaDB {
         self.inner.read_only.clone()
     }
 
+    /// Returns a clone of the tokenizer registry.
+    pub fn tokenizers(&self) -> TokenizerRegistry {
+        self.inner.tokenizers.read().clone()
+    }
+
+    /// Registers a named tokenizer definition, replacing the definition of
+    /// the same name.
+    ///
+    /// Collections and BM25 indexes record the definition they were built
+    /// with ([`CollectionConfig::tokenizer`],
+    /// [`BM25Config::tokenizer`](crate::index::BM25Config::tokenizer)) and
+    /// rebuild it when they open. A recorded definition whose version differs
+    /// from the registered one refuses to open, so register a new version
+    /// whenever a definition's tokens change. The registry starts with the
+    /// built-in `default` and `jieba` definitions.
+    ///
+    /// Registrations are process-local and not persisted: call this again
+    /// after every restart, before opening the collections that use the
+    /// definition. A recorded definition whose name is not registered is
+    /// rebuilt from its recorded filters, unchecked, so a forgotten
+    /// registration cannot detect a changed definition.
+    ///
+    /// # Errors
+    /// Returns an error if the definition cannot be built.
+    pub fn register_tokenizer(&self, config: TokenizerConfig) -> Result<(), DBError> {
+        self.inner.tokenizers.write().register(config)?;
+        Ok(())
+    }
+
     /// Sets the database to read-only mode.
     ///
     /// When in read-only mode, operations that modify the database will fail.
@@ -438,11 +555,124 @@ impl AndaDB {
         Ok(())
     }
 
+    /// Removes the expired documents of every open collection that has a
+    /// TTL (see [`Collection::expire`]).
+    ///
+    /// Each collection is expired in batches of
+    /// [`Collection::EXPIRE_BATCH_SIZE`] until a batch comes back short, so
+    /// other writers interleave between batches. A read-only database expires
+    /// nothing.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time (unix ms)
+    ///
+    /// # Returns
+    /// The number of removed documents, or the first collection error after
+    /// every collection was attempted
+    pub async fn expire_documents(&self, now_ms: u64) -> Result<u64, DBError> {
+        if self.is_read_only() {
+            return Ok(0);
+        }
+        let collections = self
+            .inner
+            .collections
+            .read()
+            .values()
+            .filter(|collection| collection.ttl().is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut total = 0u64;
+        let mut first_err: Option<DBError> = None;
+        for collection in collections {
+            loop {
+                match collection
+                    .expire(now_ms, Collection::EXPIRE_BATCH_SIZE)
+                    .await
+                {
+                    Ok(expired) => {
+                        total += expired as u64;
+                        if expired < Collection::EXPIRE_BATCH_SIZE {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        log::error!(
+                            action = "AndaDB::expire_documents",
+                            database = self.inner.name,
+                            collection = collection.name();
+                            "Collection expiry failed: {err:?}",
+                        );
+                        if first_err.is_none() {
+                            first_err = Some(err);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(err) = first_err {
+            return Err(err);
+        }
+        Ok(total)
+    }
+
+    /// Compacts the sparse segments of every open collection that uses
+    /// segment document storage, see [`Collection::compact_segments`].
+    ///
+    /// # Returns
+    /// The total number of segments reclaimed, or the first error
+    /// encountered (the remaining collections are still compacted)
+    pub async fn compact_segments(&self) -> Result<u64, DBError> {
+        if self.is_read_only() {
+            return Ok(0);
+        }
+        let collections = self
+            .inner
+            .collections
+            .read()
+            .values()
+            .filter(|collection| collection.segment_stats().is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut total = 0u64;
+        let mut first_err: Option<DBError> = None;
+        for collection in collections {
+            loop {
+                match collection.compact_segments().await {
+                    Ok(0) => break,
+                    Ok(compacted) => total += compacted as u64,
+                    Err(err) => {
+                        log::error!(
+                            action = "AndaDB::compact_segments",
+                            database = self.inner.name,
+                            collection = collection.name();
+                            "Segment compaction failed: {err:?}",
+                        );
+                        if first_err.is_none() {
+                            first_err = Some(err);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(err) = first_err {
+            return Err(err);
+        }
+        Ok(total)
+    }
+
     /// Automatically flushes the database at regular intervals.
     ///
     /// This method runs in a loop, waiting for the specified interval
     /// before flushing the database. When the cancellation token is triggered,
-    /// the loop will exit and the database will be closed.
+    /// the loop will exit and the database will be closed. Before each flush,
+    /// expired documents are removed with [`AndaDB::expire_documents`]; after
+    /// it, sparse segments are compacted with [`AndaDB::compact_segments`].
     ///
     /// # Arguments
     /// * `cancel_token` - A cancellation token to stop the loop
@@ -469,6 +699,25 @@ impl AndaDB {
                 _ = tokio::time::sleep(interval) => {}
             };
 
+            match self.expire_documents(unix_ms()).await {
+                Ok(0) => {}
+                Ok(expired) => {
+                    log::warn!(
+                        action = "AndaDB::auto_flush",
+                        database = self.inner.name,
+                        expired = expired;
+                        "Expired {expired} documents",
+                    );
+                }
+                Err(err) => {
+                    log::error!(
+                        action = "AndaDB::auto_flush",
+                        database = self.inner.name;
+                        "Failed to expire documents: {err:?}",
+                    );
+                }
+            }
+
             let start = Instant::now();
             match self.flush().await {
                 Ok(_) => {
@@ -490,9 +739,165 @@ impl AndaDB {
                     );
                 }
             }
+
+            match self.compact_segments().await {
+                Ok(0) => {}
+                Ok(compacted) => {
+                    log::info!(
+                        action = "AndaDB::auto_flush",
+                        database = self.inner.name,
+                        compacted = compacted;
+                        "Compacted {compacted} segments",
+                    );
+                }
+                Err(err) => {
+                    log::error!(
+                        action = "AndaDB::auto_flush",
+                        database = self.inner.name;
+                        "Failed to compact segments: {err:?}",
+                    );
+                }
+            }
+        }
+    }
+
+    /// Takes a consistent point-in-time snapshot of the database.
+    ///
+    /// Every registered collection name is locked against lifecycle changes,
+    /// every open collection is checkpointed and held frozen, and the
+    /// database metadata is flushed; then the [`ObjectVersion`] of every
+    /// object under the database prefix is recorded and its stored bytes are
+    /// copied into the snapshot. Mutations resume as soon as this returns,
+    /// and later flushes cannot invalidate the snapshot, even on backends
+    /// without object versioning. The snapshot holds the whole stored
+    /// database in memory. Materialize it with [`AndaDB::restore_from`] or
+    /// [`DBSnapshot::export`].
+    ///
+    /// [`ObjectVersion`]: crate::storage::ObjectVersion
+    pub async fn snapshot(&self) -> Result<DBSnapshot, DBError> {
+        loop {
+            let names: BTreeSet<String> = self.inner.metadata.read().collections.clone();
+            // Sorted acquisition, so two concurrent snapshots cannot deadlock.
+            let mut name_guards = Vec::with_capacity(names.len());
+            for name in &names {
+                name_guards.push(self.lock_collection_name(name).await);
+            }
+
+            let now_ms = unix_ms();
+            let mut operation_guards = Vec::with_capacity(names.len());
+            for name in &names {
+                let collection = { self.inner.collections.read().get(name).cloned() };
+                if let Some(collection) = collection {
+                    operation_guards.push(collection.freeze(now_ms).await?);
+                }
+            }
+            if !self.is_read_only() {
+                self.flush_metadata(now_ms).await?;
+            }
+
+            let _flush_guard = self.inner.metadata_flush_lock.clone().lock_owned().await;
+            let (persisted, _) = self
+                .inner
+                .storage
+                .fetch::<DBMetadata>(Self::METADATA_PATH)
+                .await?;
+            // A creation that was waiting for its name lock before we read
+            // `names` may have registered and persisted its collection since;
+            // its storage is not frozen, so start over with the new set.
+            if !persisted.collections.is_subset(&names) {
+                continue;
+            }
+
+            let base = format!("{}/", self.inner.storage.base_path());
+            let mut listed = self.inner.storage.list_meta(None, None);
+            let mut objects = Vec::new();
+            while let Some(meta) = listed.next().await {
+                let meta = meta?;
+                let location = meta.location.to_string();
+                let Some(path) = location.strip_prefix(&base) else {
+                    continue;
+                };
+                let keep = match path.split_once('/') {
+                    None => true,
+                    Some((dir, _)) => {
+                        path.starts_with(Self::WRITE_BATCH_PREFIX)
+                            || persisted.collections.contains(dir)
+                    }
+                };
+                if keep {
+                    objects.push(SnapshotObject {
+                        path: path.to_string(),
+                        size: meta.size,
+                        version: crate::storage::ObjectVersion {
+                            e_tag: meta.e_tag,
+                            version: meta.version,
+                        },
+                    });
+                }
+            }
+            objects.sort_by(|a, b| a.path.cmp(&b.path));
+
+            let manifest = SnapshotManifest {
+                database: self.inner.name.clone(),
+                created_at: now_ms,
+                objects,
+            };
+            let mut data = Vec::with_capacity(manifest.objects.len());
+            for object in &manifest.objects {
+                let location = object_store::path::Path::from(format!("{base}{}", object.path));
+                data.push(
+                    crate::snapshot::read_pinned(
+                        self.inner.object_store.as_ref(),
+                        &location,
+                        object,
+                    )
+                    .await?,
+                );
+            }
+
+            log::info!(
+                action = "AndaDB::snapshot",
+                database = self.inner.name,
+                objects = manifest.objects.len();
+                "Snapshot taken",
+            );
+            return Ok(DBSnapshot::from_captured(
+                self.inner.object_store.clone(),
+                manifest,
+                data,
+            ));
         }
     }
 
+    /// Materializes `snapshot` into `target` under the same database name.
+    ///
+    /// The restored database is opened with [`AndaDB::connect`] on `target`.
+    /// Objects are created, never overwritten, so restoring over an existing
+    /// database fails with [`DBError::AlreadyExists`]; a snapshot rebuilt
+    /// from its manifest whose pinned version is gone fails with
+    /// [`DBError::Precondition`]. A failed restore deletes the objects it
+    /// created.
+    pub async fn restore_from(
+        snapshot: &DBSnapshot,
+        target: Arc<dyn ObjectStore>,
+    ) -> Result<(), DBError> {
+        snapshot.restore(target.as_ref()).await
+    }
+
+    /// Restores an archive written by [`DBSnapshot::export`] into `target`
+    /// and returns its manifest.
+    ///
+    /// Same semantics as [`AndaDB::restore_from`].
+    pub async fn restore_archive<R>(
+        reader: &mut R,
+        target: Arc<dyn ObjectStore>,
+    ) -> Result<SnapshotManifest, DBError>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        crate::snapshot::restore_archive(reader, target.as_ref()).await
+    }
+
     /// Acquires the lifecycle lock for a collection name.
     ///
     /// Collection creation, the slow (load-from-storage) path of opening,
@@ -623,7 +1028,15 @@ impl AndaDB {
         F: AsyncFnOnce(&mut Collection) -> Result<(), DBError>,
     {
         let start = Instant::now();
-        if let Err(err) = f(&mut collection).await {
+        // The TTL index can only exist once `f` created it.
+        let rt = match f(&mut collection).await {
+            Ok(()) => match collection.ttl() {
+                Some(ttl) => collection.validate_ttl(&ttl),
+                None => Ok(()),
+            },
+            Err(err) => Err(err),
+        };
+        if let Err(err) = rt {
             // The collection is not registered in the database metadata yet;
             // delete the files written so far so the name can be created again.
             let _ = collection.drop_data().await;
@@ -915,6 +1328,12 @@ impl AndaDB {
             }
         }
 
+        // A collection touched by an unfinished write batch was poisoned;
+        // loading it before the batch is rolled forward would replay its
+        // intents against the pre-batch documents.
+        if !self.inner.read_only.load(Ordering::Acquire) {
+            self.finish_write_batches().await?;
+        }
         let collection = Collection::open(self.clone(), name, schema, f).await?;
         let collection = Arc::new(collection);
         {
@@ -1087,6 +1506,331 @@ impl AndaDB {
         Ok(())
     }
 
+    /// Commits a [`WriteBatch`] of document adds, updates and removes across
+    /// one or more open collections as a single all-or-nothing unit.
+    ///
+    /// Returns the id of every staged operation's document, in staging order
+    /// (the newly assigned id for adds).
+    ///
+    /// # Atomicity
+    ///
+    /// Every collection first validates its operations, records durable
+    /// mutation intents and applies the change to its in-memory indexes.
+    /// Only then is a batch manifest holding every final document image
+    /// written under the database prefix; creating that single object is the
+    /// commit point. Any failure before it leaves all collections unchanged.
+    /// Index queries of the touched collections wait until the batch is
+    /// applied or rolled back, so they never see an uncommitted batch.
+    /// After it, the document objects are written and the manifest is
+    /// deleted.
+    ///
+    /// If the process stops after the commit point, the next
+    /// [`AndaDB::open`] rolls the manifest forward and the collections' intent
+    /// replay re-derives their indexes, so either every document of the batch
+    /// is visible after recovery or none is. If a storage write fails after
+    /// the commit point, the touched collection handles are poisoned and this
+    /// call returns the error; the batch is still committed and is completed
+    /// before any of those collections is reopened.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The database or any touched collection is read-only
+    /// - A referenced collection is not open
+    /// - A document is updated or removed more than once in the batch
+    /// - An updated or removed document doesn't exist
+    /// - Any document fails schema validation or an index update fails
+    /// - Storage operations fail
+    pub async fn write_batch(&self, batch: WriteBatch) -> Result<Vec<DocumentId>, DBError> {
+        if self.inner.read_only.load(Ordering::Acquire) {
+            return Err(DBError::Generic {
+                name: self.inner.name.clone(),
+                source: "database is read-only".into(),
+            });
+        }
+        if batch.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let total = batch.len();
+        let mut groups: BTreeMap<String, Vec<(usize, BatchOp)>> = BTreeMap::new();
+        for (position, (name, op)) in batch.into_ops().into_iter().enumerate() {
+            groups.entry(name).or_default().push((position, op));
+        }
+
+        let mut collections = Vec::with_capacity(groups.len());
+        for name in groups.keys() {
+            let collection = { self.inner.collections.read().get(name).cloned() };
+            match collection {
+                Some(collection) if collection.is_active_handle() => collections.push(collection),
+                _ => {
+                    return Err(DBError::NotFound {
+                        name: name.clone(),
+                        path: self.inner.name.clone(),
+                        source: "collection is not open".into(),
+                        _id: 0,
+                    });
+                }
+            }
+        }
+
+        // Leases are taken in collection-name order (the `BTreeMap` order),
+        // so concurrent batches cannot deadlock against each other or against
+        // a queued flush of one of their collections.
+        let mut leases = Vec::with_capacity(collections.len());
+        for collection in &collections {
+            leases.push(collection.batch_lease().await?);
+        }
+        let guards: Vec<_> = collections
+            .iter()
+            .map(|collection| collection.cancel_guard("AndaDB::write_batch"))
+            .collect();
+        let rt = self.write_batch_inner(&collections, groups, total).await;
+        for guard in guards {
+            guard.disarm();
+        }
+        rt
+    }
+
+    async fn write_batch_inner(
+        &self,
+        collections: &[Arc<Collection>],
+        groups: BTreeMap<String, Vec<(usize, BatchOp)>>,
+        total: usize,
+    ) -> Result<Vec<DocumentId>, DBError> {
+        let mut prepared = Vec::with_capacity(collections.len());
+        let mut positions = Vec::with_capacity(collections.len());
+        for (collection, ops) in collections.iter().zip(groups.into_values()) {
+            let (position, ops): (Vec<usize>, Vec<BatchOp>) = ops.into_iter().unzip();
+            match collection.prepare_batch(ops).await {
+                Ok(batch) => {
+                    prepared.push(batch);
+                    positions.push(position);
+                }
+                Err(err) => {
+                    for batch in prepared {
+                        batch.rollback();
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let mut manifest = BatchManifest {
+            sequence: 0,
+            entries: prepared
+                .iter()
+                .flat_map(|batch| batch.manifest_entries())
+                .collect(),
+        };
+        // The batch is registered as unfinished before its manifest is
+        // created: if this call is cancelled anywhere after that, the cancel
+        // guards poison the collections and reopening one of them rolls the
+        // batch forward (or, if the manifest was never written, writes the
+        // same final images), instead of leaving a retained manifest that a
+        // later process start would replay over newer writes.
+        let (path, active) = loop {
+            manifest.sequence = self
+                .inner
+                .next_batch_sequence
+                .fetch_add(1, Ordering::AcqRel);
+            let path = Self::write_batch_path(manifest.sequence);
+            let active = ActiveBatch::new(&self.inner, &manifest);
+            match self.inner.storage.create(&path, &manifest).await {
+                Ok(_) => break (path, active),
+                // A manifest retained from an earlier process may use the
+                // same wall-clock-derived sequence; never overwrite it.
+                Err(DBError::AlreadyExists { .. }) => {
+                    active.retire();
+                    continue;
+                }
+                Err(err) => {
+                    // The PUT outcome is unknown. Deleting the manifest makes
+                    // the batch definitely uncommitted; if even that fails,
+                    // it may be committed and must be rolled forward.
+                    match self.inner.storage.delete(&path).await {
+                        Ok(()) | Err(DBError::NotFound { .. }) => {
+                            active.retire();
+                            for batch in prepared {
+                                batch.rollback();
+                            }
+                        }
+                        Err(delete_err) => {
+                            log::error!(
+                                action = "AndaDB::write_batch",
+                                database = self.inner.name;
+                                "Failed to clean up batch manifest after failed commit: {delete_err:?}",
+                            );
+                            self.defer_write_batch(collections);
+                        }
+                    }
+                    return Err(err);
+                }
+            }
+        };
+
+        let mut ids = vec![0; total];
+        for (batch, position) in prepared.iter().zip(positions) {
+            for (position, id) in position.into_iter().zip(batch.ids()) {
+                ids[position] = id;
+            }
+        }
+
+        for batch in prepared {
+            if let Err(err) = batch.apply().await {
+                log::error!(
+                    action = "AndaDB::write_batch",
+                    database = self.inner.name,
+                    sequence = manifest.sequence;
+                    "Failed to apply committed batch: {err:?}",
+                );
+                self.defer_write_batch(collections);
+                return Err(err);
+            }
+        }
+
+        // Until the manifest is gone a restart would roll it forward again,
+        // overwriting any later change to its documents, so a failed delete
+        // keeps the collections poisoned until it is retired.
+        if let Err(err) = self.inner.storage.delete(&path).await
+            && !matches!(err, DBError::NotFound { .. })
+        {
+            self.defer_write_batch(collections);
+            return Err(err);
+        }
+        active.retire();
+        Ok(ids)
+    }
+
+    /// Poisons every collection a committed but unfinished batch touches, so
+    /// none of them accepts another write before the batch, still registered
+    /// in [`InnerDB::unfinished_batches`], is rolled forward.
+    fn defer_write_batch(&self, collections: &[Arc<Collection>]) {
+        for collection in collections {
+            collection.poison("AndaDB::write_batch");
+        }
+    }
+
+    /// Loads the batch manifests retained in storage into
+    /// [`InnerDB::unfinished_batches`].
+    ///
+    /// An undecodable manifest cannot be rolled forward; like an unusable
+    /// collection mutation intent it is logged and skipped instead of making
+    /// the database unopenable.
+    async fn load_write_batches(&self) -> Result<(), DBError> {
+        let mut stream = self
+            .inner
+            .storage
+            .list_meta(Some(Self::WRITE_BATCH_PREFIX), None);
+        while let Some(meta) = stream.next().await {
+            let meta = meta?;
+            let Some(relative) = meta.location.prefix_match(self.inner.storage.base_path()) else {
+                continue;
+            };
+            let path = relative.collect::<object_store::path::Path>().to_string();
+            let manifest = match self.inner.storage.fetch::<BatchManifest>(&path).await {
+                Ok((manifest, _)) => manifest,
+                Err(err @ DBError::Serialization { .. }) => {
+                    log::warn!(
+                        action = "AndaDB::load_write_batches",
+                        database = self.inner.name,
+                        path;
+                        "Skipping undecodable batch manifest: {err:?}",
+                    );
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if path != Self::write_batch_path(manifest.sequence) {
+                log::warn!(
+                    action = "AndaDB::load_write_batches",
+                    database = self.inner.name,
+                    path;
+                    "Skipping batch manifest whose path does not match its sequence",
+                );
+                continue;
+            }
+            self.inner
+                .next_batch_sequence
+                .fetch_max(manifest.sequence.saturating_add(1), Ordering::AcqRel);
+            self.inner
+                .unfinished_batches
+                .lock()
+                .insert(manifest.sequence, manifest);
+        }
+        Ok(())
+    }
+
+    /// Rolls every unfinished write batch forward, oldest first, and deletes
+    /// its manifest. Returns the number of batches completed. Batches whose
+    /// [`AndaDB::write_batch`] call is still running are left to it.
+    ///
+    /// Roll-forward only writes document objects: the collection mutation
+    /// intents recorded before the commit point are still pending (their
+    /// collections were never flushed since), and the reopen replay derives
+    /// indexes and id bitmaps from the rewritten documents. Entries of
+    /// collections deleted in the meantime are skipped.
+    async fn finish_write_batches(&self) -> Result<usize, DBError> {
+        if self.inner.unfinished_batches.lock().is_empty() {
+            return Ok(0);
+        }
+        let _recovery_guard = self.inner.batch_recovery_lock.lock().await;
+        let manifests: Vec<BatchManifest> = {
+            let active = self.inner.active_batches.lock();
+            self.inner
+                .unfinished_batches
+                .lock()
+                .values()
+                .filter(|manifest| !active.contains(&manifest.sequence))
+                .cloned()
+                .collect()
+        };
+
+        let mut finished = 0;
+        for manifest in manifests {
+            let mut entries: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+            for entry in &manifest.entries {
+                entries
+                    .entry(entry.collection.as_str())
+                    .or_default()
+                    .push(entry);
+            }
+            for (name, entries) in entries {
+                if !self.inner.metadata.read().collections.contains(name) {
+                    continue;
+                }
+                let documents = Collection::connect_documents(self, name).await?;
+                for entry in entries {
+                    let key = RecordKey::Document(entry.document_id);
+                    match &entry.proposed {
+                        Some(doc) => {
+                            documents.put(key, doc, None).await?;
+                        }
+                        None => match documents.delete(key).await {
+                            Ok(()) | Err(DBError::NotFound { .. }) => {}
+                            Err(err) => return Err(err),
+                        },
+                    }
+                }
+            }
+
+            match self
+                .inner
+                .storage
+                .delete(&Self::write_batch_path(manifest.sequence))
+                .await
+            {
+                Ok(()) | Err(DBError::NotFound { .. }) => {}
+                Err(err) => return Err(err),
+            }
+            self.inner
+                .unfinished_batches
+                .lock()
+                .remove(&manifest.sequence);
+            finished += 1;
+        }
+        Ok(finished)
+    }
+
     async fn set_lock(&self, lock: ByteBufB64) -> Result<(), DBError> {
         {
             self.inner.metadata.write().config.lock = Some(lock);
@@ -1298,13 +2042,14 @@ impl AndaDB {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::query::{Filter, RangeQuery};
     use crate::schema::{ByteBufB64, Fe, FieldValue, Ft, Schema};
     use async_trait::async_trait;
     use futures::stream::BoxStream;
     use object_store::{
         CopyOptions, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta,
-        PutMultipartOptions, PutOptions, PutPayload, PutResult, Result as ObjectStoreResult,
-        memory::InMemory, path::Path,
+        ObjectStoreExt, PutMultipartOptions, PutOptions, PutPayload, PutResult,
+        Result as ObjectStoreResult, memory::InMemory, path::Path,
     };
     use std::{
         fmt,
@@ -1446,6 +2191,89 @@ mod tests {
         }
     }
 
+    /// An object store whose write-batch manifest PUTs reach the backing
+    /// store but never return, so a test can cancel a batch right after its
+    /// commit point.
+    #[derive(Debug)]
+    struct HoldBatchManifestStore {
+        inner: Arc<InMemory>,
+        held: Arc<tokio::sync::Notify>,
+    }
+
+    impl fmt::Display for HoldBatchManifestStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("HoldBatchManifestStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for HoldBatchManifestStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> ObjectStoreResult<PutResult> {
+            let rt = self.inner.put_opts(location, payload, opts).await;
+            if location.as_ref().contains(AndaDB::WRITE_BATCH_PREFIX) {
+                self.held.notify_one();
+                std::future::pending::<()>().await;
+            }
+            rt
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> ObjectStoreResult<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        fn delete_stream(
+            &self,
+            locations: BoxStream<'static, ObjectStoreResult<Path>>,
+        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
+            self.inner.delete_stream(locations)
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        fn list_with_offset(
+            &self,
+            prefix: Option<&Path>,
+            offset: &Path,
+        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list_with_offset(prefix, offset)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> ObjectStoreResult<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy_opts(
+            &self,
+            from: &Path,
+            to: &Path,
+            options: CopyOptions,
+        ) -> ObjectStoreResult<()> {
+            self.inner.copy_opts(from, to, options).await
+        }
+    }
+
     #[tokio::test]
     async fn test_database_creation() {
         let object_store = Arc::new(InMemory::new());
@@ -1579,6 +2407,7 @@ mod tests {
         let collection_config = CollectionConfig {
             name: "test_collection".to_string(),
             description: "Test Collection".to_string(),
+            ..Default::default()
         };
 
         let collection = db
@@ -1605,6 +2434,7 @@ mod tests {
         let collection_config = CollectionConfig {
             name: "test_collection".to_string(),
             description: "Test Collection".to_string(),
+            ..Default::default()
         };
 
         // Create collection first
@@ -1636,6 +2466,7 @@ mod tests {
         let collection_config = CollectionConfig {
             name: "test_collection".to_string(),
             description: "Test Collection".to_string(),
+            ..Default::default()
         };
 
         // First call should create the collection
@@ -1670,6 +2501,7 @@ mod tests {
         let ghost_config = CollectionConfig {
             name: "ghost".to_string(),
             description: "Ghost Collection".to_string(),
+            ..Default::default()
         };
         db.inner
             .metadata
@@ -1685,6 +2517,7 @@ mod tests {
         let dropping_config = CollectionConfig {
             name: "drop_me".to_string(),
             description: "Dropping Collection".to_string(),
+            ..Default::default()
         };
         db.inner
             .dropping_collections
@@ -1715,6 +2548,7 @@ mod tests {
         let live_config = CollectionConfig {
             name: "live_drop".to_string(),
             description: "Live Dropping Collection".to_string(),
+            ..Default::default()
         };
         db.create_collection(schema.clone(), live_config.clone(), async |_| Ok(()))
             .await
@@ -1750,6 +2584,7 @@ mod tests {
         let collection_config = CollectionConfig {
             name: "test_collection".to_string(),
             description: "Test Collection".to_string(),
+            ..Default::default()
         };
         let collection = db
             .create_collection(schema.clone(), collection_config.clone(), async |_| Ok(()))
@@ -1789,6 +2624,7 @@ mod tests {
         let collection_config2 = CollectionConfig {
             name: "test_collection2".to_string(),
             description: "Test Collection 2".to_string(),
+            ..Default::default()
         };
         let result = db
             .create_collection(schema, collection_config2, async |_| Ok(()))
@@ -1816,6 +2652,7 @@ mod tests {
         let collection_config = CollectionConfig {
             name: "test_collection".to_string(),
             description: "Test Collection".to_string(),
+            ..Default::default()
         };
 
         db.create_collection(schema, collection_config, async |_| Ok(()))
@@ -1845,6 +2682,7 @@ mod tests {
         let collection_config = CollectionConfig {
             name: "test_collection".to_string(),
             description: "Test Collection".to_string(),
+            ..Default::default()
         };
 
         // 创建集合
@@ -1889,6 +2727,7 @@ mod tests {
         let config = CollectionConfig {
             name: "broken".to_string(),
             description: "Broken Collection".to_string(),
+            ..Default::default()
         };
 
         let err = db
@@ -2092,6 +2931,7 @@ mod tests {
             CollectionConfig {
                 name: "x".to_string(),
                 description: String::new(),
+                ..Default::default()
             },
         )
         .await
@@ -2103,6 +2943,7 @@ mod tests {
             CollectionConfig {
                 name: "y".to_string(),
                 description: String::new(),
+                ..Default::default()
             },
         )
         .await
@@ -2173,6 +3014,7 @@ mod tests {
         let config = CollectionConfig {
             name: "c1".to_string(),
             description: "".to_string(),
+            ..Default::default()
         };
         let collection = db
             .create_collection(test_schema(), config, async |_| Ok(()))
@@ -2218,6 +3060,7 @@ mod tests {
                     CollectionConfig {
                         name: "racing".to_string(),
                         description: "".to_string(),
+                        ..Default::default()
                     },
                     async |_| Ok(()),
                 )
@@ -2255,6 +3098,7 @@ mod tests {
                     CollectionConfig {
                         name: "outer".to_string(),
                         description: "".to_string(),
+                        ..Default::default()
                     },
                     async move |_| {
                         let inner = db2
@@ -2263,6 +3107,7 @@ mod tests {
                                 CollectionConfig {
                                     name: "inner".to_string(),
                                     description: "".to_string(),
+                                    ..Default::default()
                                 },
                                 async |_| Ok(()),
                             )
@@ -2307,6 +3152,7 @@ mod tests {
                 CollectionConfig {
                     name: "c".to_string(),
                     description: "".to_string(),
+                    ..Default::default()
                 },
                 async |_| Ok(()),
             )
@@ -2374,6 +3220,7 @@ mod tests {
                     CollectionConfig {
                         name: "late_drop".to_string(),
                         description: "".to_string(),
+                        ..Default::default()
                     },
                     async |_| Ok(()),
                 )
@@ -2411,6 +3258,7 @@ mod tests {
         let config = CollectionConfig {
             name: "racy".to_string(),
             description: "".to_string(),
+            ..Default::default()
         };
         db.create_collection(test_schema(), config, async |_| Ok(()))
             .await
@@ -2501,4 +3349,358 @@ mod tests {
 
         db.close().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_write_batch_failure_leaves_every_collection_unchanged() {
+        let object_store = Arc::new(InMemory::new());
+        let db = AndaDB::create(object_store, DBConfig::default())
+            .await
+            .unwrap();
+        let mut collections = Vec::new();
+        for name in ["messages", "threads"] {
+            let collection = db
+                .create_collection(
+                    test_schema(),
+                    CollectionConfig {
+                        name: name.to_string(),
+                        description: "".to_string(),
+                        ..Default::default()
+                    },
+                    async |collection| collection.create_btree_index(&["name"]).await,
+                )
+                .await
+                .unwrap();
+            collections.push(collection);
+        }
+        let (messages, threads) = (&collections[0], &collections[1]);
+        let new_doc = |name: &str| {
+            let mut doc = messages.new_document();
+            doc.set_id(0);
+            doc.set_field("name", FieldValue::Text(name.to_string()))
+                .unwrap();
+            doc
+        };
+        let thread_id = threads.add(new_doc("thread")).await.unwrap();
+
+        // The update of a missing thread fails after the message add was
+        // prepared: neither collection may observe any part of the batch.
+        let mut batch = WriteBatch::new();
+        batch.add("messages", new_doc("hello")).update(
+            "threads",
+            thread_id + 1,
+            BTreeMap::from([("name".to_string(), FieldValue::Text("x".to_string()))]),
+        );
+        assert!(matches!(
+            db.write_batch(batch).await,
+            Err(DBError::NotFound { .. })
+        ));
+        assert!(messages.is_empty());
+        assert!(
+            messages
+                .get_btree_index(&["name"])
+                .unwrap()
+                .query_with(&FieldValue::Text("hello".to_string()), |ids| Some(
+                    ids.len()
+                ))
+                .is_none()
+        );
+
+        let mut batch = WriteBatch::new();
+        batch
+            .remove("threads", thread_id)
+            .remove("threads", thread_id);
+        assert!(db.write_batch(batch).await.is_err());
+        let mut batch = WriteBatch::new();
+        batch.add("missing", new_doc("hello"));
+        assert!(matches!(
+            db.write_batch(batch).await,
+            Err(DBError::NotFound { .. })
+        ));
+        assert_eq!(threads.len(), 1);
+
+        let mut batch = WriteBatch::new();
+        batch.add("messages", new_doc("hello")).update(
+            "threads",
+            thread_id,
+            BTreeMap::from([("name".to_string(), FieldValue::Text("summary".to_string()))]),
+        );
+        let ids = db.write_batch(batch).await.unwrap();
+        assert_eq!(ids[1], thread_id);
+        assert!(messages.contains(ids[0]));
+        assert_eq!(
+            threads.get(thread_id).await.unwrap().get_field("name"),
+            Some(&FieldValue::Text("summary".to_string()))
+        );
+        db.close().await.unwrap();
+    }
+
+    /// A batch cancelled after its commit point is rolled forward when a
+    /// touched collection is reopened, not left for the next process start.
+    #[tokio::test]
+    async fn test_write_batch_cancelled_after_commit_is_rolled_forward() {
+        let inner = Arc::new(InMemory::new());
+        let held = Arc::new(tokio::sync::Notify::new());
+        let store = Arc::new(HoldBatchManifestStore {
+            inner: inner.clone(),
+            held: held.clone(),
+        });
+        let db = AndaDB::create(store, DBConfig::default()).await.unwrap();
+        let messages = db
+            .create_collection(
+                test_schema(),
+                CollectionConfig {
+                    name: "messages".to_string(),
+                    description: "".to_string(),
+                    ..Default::default()
+                },
+                async |_| Ok(()),
+            )
+            .await
+            .unwrap();
+        let mut doc = messages.new_document();
+        doc.set_id(0);
+        doc.set_field("name", FieldValue::Text("hello".to_string()))
+            .unwrap();
+        let mut batch = WriteBatch::new();
+        batch.add("messages", doc);
+
+        let task = {
+            let db = db.clone();
+            tokio::spawn(async move { db.write_batch(batch).await })
+        };
+        held.notified().await;
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert!(messages.is_poisoned());
+        let manifests = |inner: &InMemory| {
+            let prefix = Path::from(format!("{}/{}", db.name(), AndaDB::WRITE_BATCH_PREFIX));
+            let inner = inner.list(Some(&prefix));
+            async move { inner.collect::<Vec<_>>().await.len() }
+        };
+        assert_eq!(manifests(&inner).await, 1);
+
+        let reopened = db
+            .open_collection("messages".to_string(), async |_| Ok(()))
+            .await
+            .unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(manifests(&inner).await, 0);
+        assert!(db.inner.unfinished_batches.lock().is_empty());
+        db.close().await.unwrap();
+    }
+
+    /// Index queries wait for a batch that has changed the in-memory indexes
+    /// until its manifest commit is decided, instead of reading documents a
+    /// failed commit would roll back.
+    #[tokio::test]
+    async fn test_write_batch_is_not_visible_to_queries_before_its_commit() {
+        let held = Arc::new(tokio::sync::Notify::new());
+        let store = Arc::new(HoldBatchManifestStore {
+            inner: Arc::new(InMemory::new()),
+            held: held.clone(),
+        });
+        let db = AndaDB::create(store, DBConfig::default()).await.unwrap();
+        let messages = db
+            .create_collection(
+                test_schema(),
+                CollectionConfig {
+                    name: "messages".to_string(),
+                    description: "".to_string(),
+                    ..Default::default()
+                },
+                async |collection| collection.create_btree_index(&["name"]).await,
+            )
+            .await
+            .unwrap();
+        let mut doc = messages.new_document();
+        doc.set_id(0);
+        doc.set_field("name", FieldValue::Text("hello".to_string()))
+            .unwrap();
+        let mut batch = WriteBatch::new();
+        batch.add("messages", doc);
+
+        let task = {
+            let db = db.clone();
+            tokio::spawn(async move { db.write_batch(batch).await })
+        };
+        held.notified().await;
+        let filter = || {
+            Filter::Field((
+                "name".to_string(),
+                RangeQuery::Eq(FieldValue::Text("hello".to_string())),
+            ))
+        };
+        let pending = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            messages.query_ids(filter(), None),
+        )
+        .await;
+        assert!(pending.is_err());
+
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        // The cancelled batch released the gate; the in-memory indexes of
+        // the poisoned handle still answer.
+        assert!(messages.query_ids(filter(), None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_and_archive_round_trip() {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let db = AndaDB::create(object_store.clone(), DBConfig::default())
+            .await
+            .unwrap();
+        let collection = db
+            .create_collection(
+                test_schema(),
+                CollectionConfig {
+                    name: "messages".to_string(),
+                    description: "".to_string(),
+                    ..Default::default()
+                },
+                async |collection| collection.create_btree_index(&["name"]).await,
+            )
+            .await
+            .unwrap();
+        let new_doc = |name: &str| {
+            let mut doc = collection.new_document();
+            doc.set_id(0);
+            doc.set_field("name", FieldValue::Text(name.to_string()))
+                .unwrap();
+            doc
+        };
+        let first = collection.add(new_doc("first")).await.unwrap();
+        collection.flush(unix_ms()).await.unwrap();
+        // Not flushed: the snapshot must checkpoint it.
+        let second = collection.add(new_doc("second")).await.unwrap();
+        db.set_extension("owner".to_string(), FieldValue::Text("me".to_string()));
+
+        let snapshot = db.snapshot().await.unwrap();
+        assert_eq!(snapshot.manifest().database, db.name());
+        assert!(
+            snapshot
+                .manifest()
+                .objects
+                .iter()
+                .any(|obj| obj.path == AndaDB::METADATA_PATH)
+        );
+        let mut archive = Vec::new();
+        let written = snapshot.export(&mut archive).await.unwrap();
+        assert_eq!(written, snapshot.manifest().total_size());
+
+        let target: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        AndaDB::restore_from(&snapshot, target.clone())
+            .await
+            .unwrap();
+        assert!(matches!(
+            AndaDB::restore_from(&snapshot, target.clone()).await,
+            Err(DBError::AlreadyExists { .. })
+        ));
+        let from_archive: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let manifest = AndaDB::restore_archive(&mut &archive[..], from_archive.clone())
+            .await
+            .unwrap();
+        assert_eq!(&manifest, snapshot.manifest());
+        assert!(
+            AndaDB::restore_archive(
+                &mut &archive[..archive.len() - 1],
+                Arc::new(InMemory::new())
+            )
+            .await
+            .is_err()
+        );
+        // Corrupt lengths fail without allocating what they claim: one above
+        // the entry limit, and one within it that the archive cannot back.
+        for manifest_len in [u64::MAX, 1 << 31] {
+            let mut corrupt = archive[..12].to_vec();
+            corrupt.extend_from_slice(&manifest_len.to_le_bytes());
+            corrupt.extend_from_slice(&[0; 16]);
+            assert!(
+                AndaDB::restore_archive(&mut &corrupt[..], Arc::new(InMemory::new()))
+                    .await
+                    .is_err()
+            );
+        }
+
+        // The live database moves on; the snapshot copies do not.
+        let third = collection.add(new_doc("third")).await.unwrap();
+        db.close().await.unwrap();
+
+        for store in [target, from_archive] {
+            let restored = AndaDB::open(store, DBConfig::default()).await.unwrap();
+            assert_eq!(
+                restored.get_extension("owner"),
+                Some(FieldValue::Text("me".to_string()))
+            );
+            let messages = restored
+                .open_collection("messages".to_string(), async |_| Ok(()))
+                .await
+                .unwrap();
+            assert_eq!(messages.len(), 2);
+            assert!(messages.contains(first) && messages.contains(second));
+            assert!(!messages.contains(third));
+            assert_eq!(
+                messages
+                    .get_btree_index(&["name"])
+                    .unwrap()
+                    .query_with(&FieldValue::Text("second".to_string()), |ids| Some(
+                        ids.to_vec()
+                    )),
+                Some(vec![second])
+            );
+        }
+
+        // The snapshot copied its bytes, so the close that overwrote its
+        // objects in the source does not invalidate it.
+        let late: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        AndaDB::restore_from(&snapshot, late.clone()).await.unwrap();
+        let restored = AndaDB::open(late, DBConfig::default()).await.unwrap();
+        let messages = restored
+            .open_collection("messages".to_string(), async |_| Ok(()))
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(!messages.contains(third));
+
+        // A snapshot rebuilt from its manifest only has the pinned versions,
+        // which the close overwrote: restoring it must fail rather than mix
+        // generations, and leave nothing behind.
+        let pinned = DBSnapshot::from_manifest(object_store, snapshot.manifest().clone());
+        let partial = Arc::new(InMemory::new());
+        assert!(matches!(
+            AndaDB::restore_from(&pinned, partial.clone()).await,
+            Err(DBError::Precondition { .. })
+        ));
+        assert_eq!(partial.list(None).count().await, 0);
+
+        // A restore that fails partway removes what it already created.
+        let mut conflicting = archive.clone();
+        let last = snapshot.manifest().objects.last().unwrap().clone();
+        let occupied = Arc::new(InMemory::new());
+        occupied
+            .put(
+                &Path::from(format!("{}/{}", snapshot.manifest().database, last.path)),
+                PutPayload::from_static(b"x"),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            AndaDB::restore_archive(&mut &conflicting[..], occupied.clone()).await,
+            Err(DBError::AlreadyExists { .. })
+        ));
+        assert_eq!(occupied.list(None).count().await, 1);
+        assert!(matches!(
+            AndaDB::restore_from(&snapshot, occupied.clone()).await,
+            Err(DBError::AlreadyExists { .. })
+        ));
+        assert_eq!(occupied.list(None).count().await, 1);
+        conflicting.truncate(conflicting.len() - 1);
+        let truncated = Arc::new(InMemory::new());
+        assert!(
+            AndaDB::restore_archive(&mut &conflicting[..], truncated.clone())
+                .await
+                .is_err()
+        );
+        assert_eq!(truncated.list(None).count().await, 0);
+    }
 }
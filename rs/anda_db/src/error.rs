@@ -1,7 +1,7 @@
 //! Error types for schema module
 use anda_db_btree::BTreeError;
 use anda_db_hnsw::HnswError;
-use anda_db_tfs::BM25Error;
+use anda_db_tfs::{BM25Error, SparseError};
 use std::fmt;
 use thiserror::Error;
 
@@ -350,6 +350,10 @@ impl From<BM25Error> for DBError {
                 name: name.clone(),
                 source: err.into(),
             },
+            BM25Error::Tokenizer { name, .. } => DBError::Index {
+                name: name.clone(),
+                source: err.into(),
+            },
             BM25Error::NotFound { name, id, .. } => DBError::NotFound {
                 name: name.clone(),
                 path: "unknown".to_string(),
@@ -366,6 +370,31 @@ impl From<BM25Error> for DBError {
     }
 }
 
+impl From<SparseError> for DBError {
+    fn from(err: SparseError) -> Self {
+        match &err {
+            SparseError::Generic { name, .. } => DBError::Index {
+                name: name.clone(),
+                source: err.into(),
+            },
+            SparseError::Serialization { name, .. } => DBError::Index {
+                name: name.clone(),
+                source: err.into(),
+            },
+            SparseError::EmptyVector { name, .. } => DBError::Index {
+                name: name.clone(),
+                source: err.into(),
+            },
+            SparseError::AlreadyExists { name, id, .. } => DBError::AlreadyExists {
+                name: name.clone(),
+                path: "unknown".to_string(),
+                _id: *id,
+                source: err.into(),
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -669,6 +698,13 @@ mod tests {
             }),
             "text",
         );
+        assert_index(
+            DBError::from(BM25Error::Tokenizer {
+                name: "jieba".into(),
+                source: "version".into(),
+            }),
+            "jieba",
+        );
         assert_not_found(
             DBError::from(BM25Error::NotFound {
                 name: "text".into(),
@@ -1,13 +1,19 @@
 use anda_db_tfs::{BM25Index, BucketObject};
 use bytes::Bytes;
 use parking_lot::RwLock;
-use std::{collections::BTreeSet, fmt::Debug, hash::Hash, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    hash::Hash,
+    sync::Arc,
+};
 use tokio::sync::Mutex;
 
 use super::from_virtual_field_name;
 pub use anda_db_tfs::{
-    BM25Config, BM25Error, BM25Metadata, BM25Params, BM25Stats, TokenizerChain, collect_tokens,
-    default_tokenizer, jieba_tokenizer,
+    BM25Config, BM25Error, BM25Metadata, BM25Params, BM25Stats, BaseTokenizer, FIELD_SEPARATOR,
+    HighlightOptions, Highlighter, Snippet, TokenFilterConfig, TokenizerChain, TokenizerConfig,
+    TokenizerRegistry, collect_tokens, default_tokenizer, jieba_tokenizer,
 };
 
 use crate::{
@@ -60,6 +66,9 @@ impl Hash for &BM25 {
 }
 
 impl BM25 {
+    /// Upper bound on the threads [`BM25::insert_batch`] spawns.
+    const MAX_BATCH_THREADS: usize = 8;
+
     pub(crate) fn dir_path(name: &str) -> String {
         format!("bm25_indexes/{name}/")
     }
@@ -99,11 +108,24 @@ impl BM25 {
         tokenizer: TokenizerChain,
         storage: Storage,
         now_ms: u64,
+    ) -> Result<Self, DBError> {
+        Self::new_with(fields, tokenizer, BM25Config::default(), storage, now_ms).await
+    }
+
+    /// Like [`BM25::new`], with the given index configuration.
+    ///
+    /// `config.bucket_overload_size` is always taken from the storage.
+    pub async fn new_with(
+        fields: Vec<String>,
+        tokenizer: TokenizerChain,
+        config: BM25Config,
+        storage: Storage,
+        now_ms: u64,
     ) -> Result<Self, DBError> {
         let name = fields.join("-");
         let config = BM25Config {
             bucket_overload_size: storage.bucket_overload_size(),
-            ..Default::default()
+            ..config
         };
         let index = BM25Index::new(name.clone(), tokenizer, Some(config));
         let mut data = Vec::new();
@@ -138,23 +160,50 @@ impl BM25 {
     }
 
     /// Loads an existing BM25 index from persisted metadata and bucket objects.
+    ///
+    /// An index without a recorded tokenizer is loaded with `tokenizer`; a
+    /// recorded one is resolved against the built-in definitions of
+    /// [`TokenizerRegistry::default`]. Use [`BM25::bootstrap_with_registry`]
+    /// for indexes recorded with custom definitions.
     pub async fn bootstrap(
         name: String,
         tokenizer: TokenizerChain,
         storage: Storage,
+    ) -> Result<Self, DBError> {
+        Self::bootstrap_with_registry(name, &TokenizerRegistry::default(), tokenizer, storage).await
+    }
+
+    /// Loads an existing BM25 index from persisted metadata and bucket objects.
+    ///
+    /// An index that recorded its tokenizer ([`BM25Config::tokenizer`]) is
+    /// loaded with that definition resolved against `tokenizers`, and fails
+    /// to load if the registered version differs; an index without a record
+    /// is loaded with `tokenizer`.
+    pub async fn bootstrap_with_registry(
+        name: String,
+        tokenizers: &TokenizerRegistry,
+        tokenizer: TokenizerChain,
+        storage: Storage,
     ) -> Result<Self, DBError> {
         let fields = from_virtual_field_name(&name);
         let (metadata, ver) = storage.fetch_bytes(&BM25::metadata_path(&name)).await?;
         let n = Arc::new(name.clone());
         let s = Arc::new(storage.clone());
-        let index = BM25Index::load_all(tokenizer, &metadata[..], async move |object| {
-            let path = BM25::bucket_path(n.clone().as_str(), object);
-            match s.clone().fetch_bytes(&path).await {
-                Ok((data, _)) => Ok(Some(data.into())),
-                Err(DBError::NotFound { .. }) => Ok(None),
-                Err(e) => Err(e.into()),
-            }
-        })
+        let index = BM25Index::load_all_with(
+            |config| match &config.tokenizer {
+                Some(recorded) => tokenizers.resolve(recorded),
+                None => Ok(tokenizer),
+            },
+            &metadata[..],
+            async move |object| {
+                let path = BM25::bucket_path(n.clone().as_str(), object);
+                match s.clone().fetch_bytes(&path).await {
+                    Ok((data, _)) => Ok(Some(data.into())),
+                    Err(DBError::NotFound { .. }) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            },
+        )
         .await?;
 
         Ok(Self {
@@ -303,6 +352,44 @@ impl BM25 {
         self.index.metadata()
     }
 
+    /// Returns the boosts of the fields of a BM25F index, in the order of
+    /// [`BM25::virtual_field`]; empty if the index scores its fields as one
+    /// text.
+    pub fn field_boosts(&self) -> &[f32] {
+        &self.index.config().field_boosts
+    }
+
+    /// Returns the BM25 parameters of a query, with the boosts of
+    /// `field_boosts` (by field name) applied to the fields of a BM25F
+    /// index.
+    pub fn query_params(
+        &self,
+        params: Option<&BM25Params>,
+        field_boosts: Option<&BTreeMap<String, f32>>,
+    ) -> Option<BM25Params> {
+        let (Some(field_boosts), false) = (field_boosts, self.field_boosts().is_empty()) else {
+            return params.cloned();
+        };
+
+        let mut params = params
+            .cloned()
+            .unwrap_or_else(|| self.index.config().bm25.clone());
+        let mut boosts = self.field_boosts().to_vec();
+        for (boost, value) in boosts
+            .iter_mut()
+            .zip(params.field_boosts.take().unwrap_or_default())
+        {
+            *boost = value;
+        }
+        for (boost, field) in boosts.iter_mut().zip(&self.fields) {
+            if let Some(value) = field_boosts.get(field) {
+                *boost = *value;
+            }
+        }
+        params.field_boosts = Some(boosts);
+        Some(params)
+    }
+
     /// Inserts or updates the text indexed for `id`.
     ///
     /// Empty-token documents are ignored because they are not searchable.
@@ -314,6 +401,45 @@ impl BM25 {
         }
     }
 
+    /// Inserts the texts of many documents, tokenizing them in parallel.
+    ///
+    /// Tokenization dominates BM25 inserts and postings are sharded maps, so
+    /// the batch is split across scoped threads that insert concurrently.
+    /// Empty-token documents are ignored like [`BM25::insert`] does. On error
+    /// the documents inserted so far stay in the index; callers roll back per
+    /// document.
+    pub fn insert_batch(&self, items: &[(DocumentId, String)], now_ms: u64) -> Result<(), DBError> {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(Self::MAX_BATCH_THREADS);
+        if threads < 2 || items.len() < 2 * threads {
+            for (id, text) in items {
+                self.insert(*id, text, now_ms)?;
+            }
+            return Ok(());
+        }
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = items
+                .chunks(items.len().div_ceil(threads))
+                .map(|part| {
+                    scope.spawn(move || {
+                        for (id, text) in part {
+                            self.insert(*id, text, now_ms)?;
+                        }
+                        Ok::<(), DBError>(())
+                    })
+                })
+                .collect();
+            handles.into_iter().try_for_each(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|err| std::panic::resume_unwind(err))
+            })
+        })
+    }
+
     /// Removes the indexed text for `id`.
     pub fn remove(&self, id: DocumentId, text: &str, now_ms: u64) -> bool {
         self.index.remove(id, text, now_ms)
@@ -355,6 +481,17 @@ impl BM25 {
     ) -> Result<Vec<(u64, f32)>, DBError> {
         Ok(self.index.try_search_advanced(query, top_k, params)?)
     }
+
+    /// Creates a highlighter extracting the fragments of texts that match
+    /// `query`, parsed like `try_search_advanced` when `logical`.
+    pub fn highlighter(
+        &self,
+        query: &str,
+        logical: bool,
+        options: HighlightOptions,
+    ) -> Highlighter<TokenizerChain> {
+        self.index.highlighter(query, logical, options)
+    }
 }
 
 #[cfg(test)]
@@ -3,15 +3,18 @@ use bytes::Bytes;
 use cbor2::{from_reader, to_canonical_vec};
 use ic_auth_types::ByteBufB64;
 use parking_lot::RwLock;
-use serde::{Serialize, de::DeserializeOwned};
-use std::{fmt::Debug, hash::Hash, str::FromStr, sync::Arc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{borrow::Cow, fmt::Debug, hash::Hash, str::FromStr, sync::Arc};
 
 pub use anda_db_btree::{BTreeConfig, BTreeMetadata, BTreeStats, RangeQuery};
 
-use super::from_virtual_field_name;
+use super::{
+    FIELD_PATH_SEPARATOR, field_path_type, field_path_value, from_virtual_field_name, root_field,
+};
 use crate::{
     error::DBError,
-    schema::{BoxError, DocumentId, Fe, Ft, Fv, as_wildcard_map},
+    query::Filter,
+    schema::{BoxError, Document, DocumentId, Fe, Ft, Fv, Schema, as_wildcard_map},
     storage::{ObjectVersion, PutMode, Storage},
     unix_ms,
 };
@@ -19,8 +22,9 @@ use crate::{
 /// Collection-level typed B-tree index wrapper.
 ///
 /// AndaDB supports B-tree indexes over scalar `u64`, `i64`, text, and byte
-/// values. Array and map fields are indexed by their elements or map keys when
-/// the underlying scalar type is supported.
+/// values, and over the scalars at a path into a `Json` field. Array and map
+/// fields are indexed by their elements or map keys when the underlying
+/// scalar type is supported.
 pub enum BTree {
     /// B-tree over unsigned integer keys.
     U64(InnerBTree<u64>),
@@ -30,6 +34,8 @@ pub enum BTree {
     String(InnerBTree<String>),
     /// B-tree over byte-array keys.
     Bytes(InnerBTree<Vec<u8>>),
+    /// B-tree over the JSON scalars at a path into a `Json` field.
+    Json(InnerBTree<JsonKey>),
 }
 
 impl Debug for BTree {
@@ -39,6 +45,7 @@ impl Debug for BTree {
             BTree::U64(btree) => write!(f, "BTreeIndex<U64>({})", btree.name),
             BTree::String(btree) => write!(f, "BTreeIndex<String>({})", btree.name),
             BTree::Bytes(btree) => write!(f, "BTreeIndex<Bytes>({})", btree.name),
+            BTree::Json(btree) => write!(f, "BTreeIndex<Json>({})", btree.name),
         }
     }
 }
@@ -50,6 +57,7 @@ impl PartialEq for &BTree {
             (BTree::U64(a), BTree::U64(b)) => a.name == b.name,
             (BTree::String(a), BTree::String(b)) => a.name == b.name,
             (BTree::Bytes(a), BTree::Bytes(b)) => a.name == b.name,
+            (BTree::Json(a), BTree::Json(b)) => a.name == b.name,
             _ => false,
         }
     }
@@ -63,6 +71,197 @@ impl Hash for &BTree {
             BTree::U64(btree) => btree.name.hash(state),
             BTree::String(btree) => btree.name.hash(state),
             BTree::Bytes(btree) => btree.name.hash(state),
+            BTree::Json(btree) => btree.name.hash(state),
+        }
+    }
+}
+
+/// A B-tree key for a scalar inside a `Json` field: a boolean, a number or a
+/// string.
+///
+/// Booleans order before numbers and numbers before strings. Numbers compare
+/// by value whatever their variant: an integral number in `i64` range is
+/// always `I64`, one above it `U64`, and any other number `F64`, so equal
+/// numbers share one representation. `NaN` cannot be a key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum JsonKey {
+    /// A JSON boolean.
+    Bool(bool),
+    /// A JSON number that is an integer in `i64` range.
+    I64(i64),
+    /// A JSON number that is an integer above `i64::MAX`.
+    U64(u64),
+    /// Any other JSON number.
+    F64(f64),
+    /// A JSON string.
+    Text(String),
+}
+
+impl JsonKey {
+    fn number(value: f64) -> Result<Self, BoxError> {
+        if value.is_nan() {
+            return Err("NaN cannot be a BTree key".into());
+        }
+        // `-0.0` is integral, so it becomes `I64(0)`.
+        Ok(if value.fract() != 0.0 {
+            JsonKey::F64(value)
+        } else if (-(2f64.powi(63))..2f64.powi(63)).contains(&value) {
+            JsonKey::I64(value as i64)
+        } else if (0.0..2f64.powi(64)).contains(&value) {
+            JsonKey::U64(value as u64)
+        } else {
+            JsonKey::F64(value)
+        })
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            JsonKey::Bool(_) => 0,
+            JsonKey::I64(_) | JsonKey::U64(_) | JsonKey::F64(_) => 1,
+            JsonKey::Text(_) => 2,
+        }
+    }
+
+    /// The smallest and, except for strings, the largest key of `value`'s
+    /// kind, if `value` converts to a key.
+    fn kind_bounds(value: &Fv) -> Option<(Fv, Option<Fv>)> {
+        match JsonKey::try_from(value.clone()).ok()? {
+            JsonKey::Bool(_) => Some((Fv::Bool(false), Some(Fv::Bool(true)))),
+            JsonKey::Text(_) => Some((Fv::Text(String::new()), None)),
+            _ => Some((Fv::F64(f64::NEG_INFINITY), Some(Fv::F64(f64::INFINITY)))),
+        }
+    }
+
+    /// Keeps the open-ended ranges of `query` within the kind of their bound,
+    /// so `Gt(I64(5))` matches numbers above 5 but no string.
+    fn bound_query(query: RangeQuery<Fv>) -> RangeQuery<Fv> {
+        let lower = |value: &Fv| JsonKey::kind_bounds(value).map(|(min, _)| RangeQuery::Ge(min));
+        let upper = |value: &Fv| {
+            JsonKey::kind_bounds(value)
+                .and_then(|(_, max)| max)
+                .map(RangeQuery::Le)
+        };
+        let bounded = |query: RangeQuery<Fv>, bound: Option<RangeQuery<Fv>>| match bound {
+            Some(bound) => RangeQuery::And(vec![Box::new(query), Box::new(bound)]),
+            None => query,
+        };
+        match query {
+            RangeQuery::Gt(v) => {
+                let bound = upper(&v);
+                bounded(RangeQuery::Gt(v), bound)
+            }
+            RangeQuery::Ge(v) => {
+                let bound = upper(&v);
+                bounded(RangeQuery::Ge(v), bound)
+            }
+            RangeQuery::Lt(v) => {
+                let bound = lower(&v);
+                bounded(RangeQuery::Lt(v), bound)
+            }
+            RangeQuery::Le(v) => {
+                let bound = lower(&v);
+                bounded(RangeQuery::Le(v), bound)
+            }
+            RangeQuery::And(queries) => RangeQuery::And(
+                queries
+                    .into_iter()
+                    .map(|q| Box::new(JsonKey::bound_query(*q)))
+                    .collect(),
+            ),
+            RangeQuery::Or(queries) => RangeQuery::Or(
+                queries
+                    .into_iter()
+                    .map(|q| Box::new(JsonKey::bound_query(*q)))
+                    .collect(),
+            ),
+            RangeQuery::Not(q) => RangeQuery::Not(Box::new(JsonKey::bound_query(*q))),
+            other => other,
+        }
+    }
+}
+
+impl PartialEq for JsonKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for JsonKey {}
+
+impl PartialOrd for JsonKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JsonKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+
+        // An integer and an `F64` are never equal; on a tie after rounding
+        // the integer to `f64`, the float lies beyond the integer range.
+        fn float_int(f: f64, i: i128) -> Ordering {
+            match f.partial_cmp(&(i as f64)) {
+                Some(Ordering::Equal) | None if f > 0.0 => Ordering::Greater,
+                Some(Ordering::Equal) | None => Ordering::Less,
+                Some(ordering) => ordering,
+            }
+        }
+
+        match (self, other) {
+            (JsonKey::Bool(a), JsonKey::Bool(b)) => a.cmp(b),
+            (JsonKey::Text(a), JsonKey::Text(b)) => a.cmp(b),
+            (JsonKey::I64(a), JsonKey::I64(b)) => a.cmp(b),
+            (JsonKey::U64(a), JsonKey::U64(b)) => a.cmp(b),
+            (JsonKey::I64(_), JsonKey::U64(_)) => Ordering::Less,
+            (JsonKey::U64(_), JsonKey::I64(_)) => Ordering::Greater,
+            (JsonKey::F64(a), JsonKey::F64(b)) => a.total_cmp(b),
+            (JsonKey::F64(f), JsonKey::I64(i)) => float_int(*f, *i as i128),
+            (JsonKey::F64(f), JsonKey::U64(u)) => float_int(*f, *u as i128),
+            (JsonKey::I64(i), JsonKey::F64(f)) => float_int(*f, *i as i128).reverse(),
+            (JsonKey::U64(u), JsonKey::F64(f)) => float_int(*f, *u as i128).reverse(),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+impl Hash for JsonKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            JsonKey::Bool(v) => v.hash(state),
+            JsonKey::I64(v) => v.hash(state),
+            JsonKey::U64(v) => v.hash(state),
+            JsonKey::F64(v) => v.to_bits().hash(state),
+            JsonKey::Text(v) => v.hash(state),
+        }
+    }
+}
+
+impl TryFrom<Fv> for JsonKey {
+    type Error = BoxError;
+
+    fn try_from(value: Fv) -> Result<Self, Self::Error> {
+        match value {
+            Fv::Bool(v) => Ok(JsonKey::Bool(v)),
+            Fv::I64(v) => Ok(JsonKey::I64(v)),
+            Fv::U64(v) => Ok(i64::try_from(v).map_or(JsonKey::U64(v), JsonKey::I64)),
+            Fv::F64(v) => JsonKey::number(v),
+            Fv::F32(v) => JsonKey::number(v as f64),
+            Fv::Text(v) => Ok(JsonKey::Text(v)),
+            _ => Err(format!("expected a JSON scalar, got {value:?}").into()),
+        }
+    }
+}
+
+impl From<JsonKey> for Fv {
+    fn from(key: JsonKey) -> Self {
+        match key {
+            JsonKey::Bool(v) => Fv::Bool(v),
+            JsonKey::I64(v) => Fv::I64(v),
+            JsonKey::U64(v) => Fv::U64(v),
+            JsonKey::F64(v) => Fv::F64(v),
+            JsonKey::Text(v) => Fv::Text(v),
         }
     }
 }
@@ -189,6 +388,32 @@ impl BTree {
         .await
     }
 
+    /// Creates a persisted B-tree index over a nested field path such as
+    /// `metadata.user_id`.
+    ///
+    /// `field` describes the value at the path: its type is the one
+    /// [`field_path_type`](super::field_path_type) resolves, and its `unique`
+    /// flag decides whether duplicates are rejected.
+    pub async fn with_field_path(
+        path: String,
+        field: &Fe,
+        storage: Storage,
+        now_ms: u64,
+    ) -> Result<Self, DBError> {
+        let config = BTreeConfig {
+            bucket_overload_size: storage.bucket_overload_size(),
+            allow_duplicates: !field.unique(),
+        };
+        BTree::inner_new(
+            vec![path],
+            &key_type_of(field.r#type()),
+            config,
+            storage,
+            now_ms,
+        )
+        .await
+    }
+
     /// Creates a persisted multi-field B-tree index.
     ///
     /// Multi-field indexes store a deterministic byte key composed from each
@@ -253,6 +478,10 @@ impl BTree {
             Ft::I64 => BTree::I64(InnerBTree::new(fields, config, storage, now_ms).await?),
             Ft::Text => BTree::String(InnerBTree::new(fields, config, storage, now_ms).await?),
             Ft::Bytes => BTree::Bytes(InnerBTree::new(fields, config, storage, now_ms).await?),
+            // Only a field path resolves to `Json`, see `field_path_type`.
+            Ft::Json if fields.len() == 1 && fields[0].contains(FIELD_PATH_SEPARATOR) => {
+                BTree::Json(InnerBTree::new(fields, config, storage, now_ms).await?)
+            }
             _ => {
                 return Err(DBError::Index {
                     name: fields.join("-"),
@@ -282,6 +511,10 @@ impl BTree {
                 let btree = InnerBTree::<Vec<u8>>::bootstrap(name, storage).await?;
                 Ok(BTree::Bytes(btree))
             }
+            Ft::Json => {
+                let btree = InnerBTree::<JsonKey>::bootstrap(name, storage).await?;
+                Ok(BTree::Json(btree))
+            }
             _ => Err(DBError::Index {
                 name,
                 source: format!("BTree: unsupported field type: {ft:?}").into(),
@@ -296,6 +529,7 @@ impl BTree {
             BTree::U64(btree) => &btree.name,
             BTree::String(btree) => &btree.name,
             BTree::Bytes(btree) => &btree.name,
+            BTree::Json(btree) => &btree.name,
         }
     }
 
@@ -306,6 +540,7 @@ impl BTree {
             BTree::U64(btree) => &btree.fields,
             BTree::String(btree) => &btree.fields,
             BTree::Bytes(btree) => &btree.fields,
+            BTree::Json(btree) => &btree.fields,
         }
     }
 
@@ -316,6 +551,7 @@ impl BTree {
             BTree::U64(btree) => btree.index.allow_duplicates(),
             BTree::String(btree) => btree.index.allow_duplicates(),
             BTree::Bytes(btree) => btree.index.allow_duplicates(),
+            BTree::Json(btree) => btree.index.allow_duplicates(),
         }
     }
 
@@ -326,6 +562,7 @@ impl BTree {
             BTree::U64(btree) => btree.index.stats(),
             BTree::String(btree) => btree.index.stats(),
             BTree::Bytes(btree) => btree.index.stats(),
+            BTree::Json(btree) => btree.index.stats(),
         }
     }
 
@@ -336,6 +573,7 @@ impl BTree {
             BTree::U64(btree) => btree.index.metadata(),
             BTree::String(btree) => btree.index.metadata(),
             BTree::Bytes(btree) => btree.index.metadata(),
+            BTree::Json(btree) => btree.index.metadata(),
         }
     }
 
@@ -410,6 +648,13 @@ impl BTree {
                 .index
                 .insert(doc_id, val.clone(), now_ms)
                 .map_err(DBError::from),
+            (BTree::Json(btree), val) => {
+                let val = self.convert_array_values::<JsonKey, _>([val.clone()])?;
+                btree
+                    .index
+                    .insert(doc_id, val[0].clone(), now_ms)
+                    .map_err(DBError::from)
+            }
             (_, v) => Err(DBError::Index {
                 name: self.name().to_string(),
                 source: format!("{:?}: field value type mismatch: {:?}", self, v).into(),
@@ -452,9 +697,71 @@ impl BTree {
                     .insert_array(doc_id, values, now_ms)
                     .map_err(DBError::from)
             }
+            BTree::Json(btree) => {
+                let values = self.convert_array_values::<JsonKey, _>(field_values)?;
+                btree
+                    .index
+                    .insert_array(doc_id, values, now_ms)
+                    .map_err(DBError::from)
+            }
         }
     }
 
+    /// Inserts the indexed values of many documents in ascending key order.
+    ///
+    /// Array and map values are expanded like [`BTree::insert`] does. New
+    /// keys are assigned to the current bucket until it fills up, so sorting
+    /// first packs adjacent keys into the same buckets, which keeps range
+    /// scans over a bulk-built index on few buckets. On error the entries
+    /// inserted so far stay in the index; callers roll back per document.
+    ///
+    /// Returns the number of inserted keys.
+    pub fn insert_batch(
+        &self,
+        entries: Vec<(DocumentId, Fv)>,
+        now_ms: u64,
+    ) -> Result<usize, DBError> {
+        let mut scalars: Vec<(DocumentId, Fv)> = Vec::with_capacity(entries.len());
+        for (doc_id, fv) in entries {
+            match fv {
+                Fv::Null => {}
+                Fv::Array(vals) => scalars.extend(vals.into_iter().map(|v| (doc_id, v))),
+                Fv::Map(vals) => scalars.extend(vals.into_keys().map(|k| (doc_id, Fv::from(k)))),
+                v => scalars.push((doc_id, v)),
+            }
+        }
+
+        match &self {
+            BTree::I64(btree) => btree.insert_sorted(self.convert_entries(scalars)?, now_ms),
+            BTree::U64(btree) => btree.insert_sorted(self.convert_entries(scalars)?, now_ms),
+            BTree::String(btree) => btree.insert_sorted(self.convert_entries(scalars)?, now_ms),
+            BTree::Bytes(btree) => btree.insert_sorted(self.convert_entries(scalars)?, now_ms),
+            BTree::Json(btree) => btree.insert_sorted(self.convert_entries(scalars)?, now_ms),
+        }
+    }
+
+    fn convert_entries<FV>(
+        &self,
+        entries: Vec<(DocumentId, Fv)>,
+    ) -> Result<Vec<(FV, DocumentId)>, DBError>
+    where
+        FV: TryFrom<Fv, Error = BoxError>,
+    {
+        let name = self.name().to_string();
+        entries
+            .into_iter()
+            .filter(|(_, v)| v != &Fv::Null)
+            .map(|(doc_id, v)| {
+                FV::try_from(v)
+                    .map(|v| (v, doc_id))
+                    .map_err(|source| DBError::Index {
+                        name: name.clone(),
+                        source,
+                    })
+            })
+            .collect()
+    }
+
     /// Removes an indexed value for `doc_id`.
     pub fn remove(&self, doc_id: DocumentId, field_value: &Fv, now_ms: u64) -> bool {
         if field_value == &Fv::Null {
@@ -492,6 +799,8 @@ impl BTree {
             (BTree::Bytes(btree), Fv::Bytes(val)) => {
                 btree.index.remove(doc_id, val.clone(), now_ms)
             }
+            (BTree::Json(btree), val) => JsonKey::try_from(val.clone())
+                .is_ok_and(|val| btree.index.remove(doc_id, val, now_ms)),
             _ => false,
         }
     }
@@ -588,6 +897,10 @@ impl BTree {
                 let values = self.convert_array_values::<Vec<u8>, _>(field_values)?;
                 Ok(btree.index.remove_array(doc_id, values, now_ms))
             }
+            BTree::Json(btree) => {
+                let values = self.convert_array_values::<JsonKey, _>(field_values)?;
+                Ok(btree.index.remove_array(doc_id, values, now_ms))
+            }
         }
     }
 
@@ -636,6 +949,15 @@ impl BTree {
                     .index
                     .batch_update(doc_id, old_field_values, new_field_values, now_ms)?)
             }
+            BTree::Json(btree) => {
+                let old_field_values =
+                    self.convert_array_values::<JsonKey, _>(old_field_values.iter().cloned())?;
+                let new_field_values =
+                    self.convert_array_values::<JsonKey, _>(new_field_values.iter().cloned())?;
+                Ok(btree
+                    .index
+                    .batch_update(doc_id, old_field_values, new_field_values, now_ms)?)
+            }
         }
     }
 
@@ -655,6 +977,9 @@ impl BTree {
             (BTree::U64(btree), Fv::U64(val)) => btree.index.query_with(val, f),
             (BTree::String(btree), Fv::Text(val)) => btree.index.query_with(val, f),
             (BTree::Bytes(btree), Fv::Bytes(val)) => btree.index.query_with(val, f),
+            (BTree::Json(btree), val) => btree
+                .index
+                .query_with(&JsonKey::try_from(val.clone()).ok()?, f),
             _ => None,
         }
     }
@@ -716,6 +1041,65 @@ impl BTree {
                 let q = RangeQuery::<Vec<u8>>::try_convert_from(query).map_err(type_error)?;
                 scan!(btree.index, q);
             }
+            BTree::Json(btree) => {
+                let q = RangeQuery::<JsonKey>::try_convert_from(JsonKey::bound_query(query))
+                    .map_err(type_error)?;
+                scan!(btree.index, q);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a range query and feeds each matching key with its id list to `f`,
+    /// in scan order.
+    ///
+    /// Same contract as [`BTree::try_range_query_ids`], except that `f` sees
+    /// the keys in the order the scan walks them (descending when
+    /// `descending` is set), which is what ordered result pages need.
+    pub fn try_range_query_entries<F>(
+        &self,
+        query: RangeQuery<Fv>,
+        descending: bool,
+        mut f: F,
+    ) -> Result<(), DBError>
+    where
+        F: FnMut(Fv, &[DocumentId]) -> bool,
+    {
+        let type_error = |source: BoxError| DBError::Index {
+            name: self.name().to_string(),
+            source,
+        };
+        macro_rules! scan {
+            ($index:expr, $q:expr, $key:expr) => {{
+                let cb = |k: &_, pks: &Vec<DocumentId>| (f($key(k), pks), Vec::<()>::new());
+                if descending {
+                    $index.range_query_rev_with($q, cb);
+                } else {
+                    $index.range_query_with($q, cb);
+                }
+            }};
+        }
+        match self {
+            BTree::I64(btree) => {
+                let q = RangeQuery::<i64>::try_convert_from(query).map_err(type_error)?;
+                scan!(btree.index, q, |k: &i64| Fv::I64(*k));
+            }
+            BTree::U64(btree) => {
+                let q = RangeQuery::<u64>::try_convert_from(query).map_err(type_error)?;
+                scan!(btree.index, q, |k: &u64| Fv::U64(*k));
+            }
+            BTree::String(btree) => {
+                let q = RangeQuery::<String>::try_convert_from(query).map_err(type_error)?;
+                scan!(btree.index, q, |k: &String| Fv::Text(k.clone()));
+            }
+            BTree::Bytes(btree) => {
+                let q = RangeQuery::<Vec<u8>>::try_convert_from(query).map_err(type_error)?;
+                scan!(btree.index, q, |k: &Vec<u8>| Fv::Bytes(k.clone()));
+            }
+            BTree::Json(btree) => {
+                let q = RangeQuery::<JsonKey>::try_convert_from(query).map_err(type_error)?;
+                scan!(btree.index, q, |k: &JsonKey| Fv::from(k.clone()));
+            }
         }
         Ok(())
     }
@@ -762,6 +1146,14 @@ impl BTree {
                     vec![]
                 }
             },
+            BTree::Json(btree) => match RangeQuery::<JsonKey>::try_convert_from(query) {
+                Ok(q) => btree
+                    .index
+                    .range_query_with(q, |fv, pks| f(Fv::from(fv.clone()), pks)),
+                Err(_) => {
+                    vec![]
+                }
+            },
         }
     }
 
@@ -804,6 +1196,15 @@ impl BTree {
                     .map(Fv::Bytes)
                     .collect(),
             },
+            BTree::Json(btree) => match Self::from_cursor(&cursor) {
+                Err(_) => vec![],
+                Ok(cursor) => btree
+                    .index
+                    .keys(cursor, limit)
+                    .into_iter()
+                    .map(Fv::from)
+                    .collect(),
+            },
         }
     }
 
@@ -820,6 +1221,7 @@ impl BTree {
             BTree::U64(btree) => btree.compact().await,
             BTree::String(btree) => btree.compact().await,
             BTree::Bytes(btree) => btree.compact().await,
+            BTree::Json(btree) => btree.compact().await,
         }
     }
 
@@ -832,6 +1234,7 @@ impl BTree {
             BTree::U64(btree) => btree.flush(now_ms).await,
             BTree::String(btree) => btree.flush(now_ms).await,
             BTree::Bytes(btree) => btree.flush(now_ms).await,
+            BTree::Json(btree) => btree.flush(now_ms).await,
         }
     }
 
@@ -842,6 +1245,7 @@ impl BTree {
             BTree::U64(btree) => btree.has_pending_flush(),
             BTree::String(btree) => btree.has_pending_flush(),
             BTree::Bytes(btree) => btree.has_pending_flush(),
+            BTree::Json(btree) => btree.has_pending_flush(),
         }
     }
 
@@ -851,6 +1255,7 @@ impl BTree {
             BTree::U64(btree) => btree.drop_data().await,
             BTree::String(btree) => btree.drop_data().await,
             BTree::Bytes(btree) => btree.drop_data().await,
+            BTree::Json(btree) => btree.drop_data().await,
         };
 
         if let Err(err) = rt {
@@ -867,6 +1272,21 @@ impl<FV> InnerBTree<FV>
 where
     FV: Eq + Ord + Hash + Debug + Clone + Serialize + DeserializeOwned,
 {
+    fn insert_sorted(
+        &self,
+        mut entries: Vec<(FV, DocumentId)>,
+        now_ms: u64,
+    ) -> Result<usize, DBError> {
+        entries.sort_unstable();
+        let mut inserted = 0;
+        for (val, doc_id) in entries {
+            if self.index.insert(doc_id, val, now_ms)? {
+                inserted += 1;
+            }
+        }
+        Ok(inserted)
+    }
+
     async fn new(
         fields: Vec<String>,
         config: BTreeConfig,
@@ -1037,6 +1457,280 @@ where
     }
 }
 
+/// Declarative options of a B-tree index.
+///
+/// Unlike [`IndexHooks`](super::IndexHooks), which are code, the `filter`
+/// and `expression` of an index are persisted in
+/// `CollectionMetadata::btree_index_options`, so every process that opens
+/// the collection indexes documents identically. They apply to the value the
+/// hooks extract.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BTreeIndexOptions {
+    /// Rejects duplicate keys, regardless of the schema `unique` flag.
+    /// Multi-field indexes are always unique.
+    #[serde(default)]
+    pub unique: bool,
+
+    /// Only documents matching this predicate are indexed (a partial index),
+    /// so queries through the index only return such documents.
+    ///
+    /// Here `Filter::Field` names a field or field path of the document
+    /// rather than an index. A document without a value at the path does
+    /// not match it; an array value matches if any element does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+
+    /// Transforms the value of a single-field index before it is indexed.
+    ///
+    /// Query values are not transformed: a filter on the index compares
+    /// against the transformed keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expression: Option<IndexExpression>,
+}
+
+/// A transformation of the indexed value, see [`BTreeIndexOptions`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexExpression {
+    /// Lowercases text keys.
+    Lowercase,
+
+    /// Rounds integer keys down to a multiple of `interval`; e.g. an interval
+    /// of `86_400_000` truncates a unix-ms timestamp to its UTC day.
+    Truncate {
+        /// The truncation unit. Must be positive.
+        interval: u64,
+    },
+}
+
+impl BTreeIndexOptions {
+    /// Returns `true` if the options change which values are indexed, and
+    /// so must be persisted with the index.
+    pub fn is_declarative(&self) -> bool {
+        self.filter.is_some() || self.expression.is_some()
+    }
+
+    /// Validates the options of an index on `fields` against `schema`.
+    pub fn validate(&self, schema: &Schema, fields: &[&str]) -> Result<(), String> {
+        if let Some(filter) = &self.filter {
+            filter.validate_complexity()?;
+            validate_predicate(schema, filter)?;
+        }
+        match (&self.expression, fields) {
+            (None, _) => Ok(()),
+            (Some(_), [_, _, ..]) => {
+                Err("an index expression requires a single-field index".to_string())
+            }
+            (Some(expression), _) => {
+                let path = fields.first().copied().unwrap_or_default();
+                let ft = field_path_type(schema, path).map_err(|err| err.to_string())?;
+                match (expression, key_type_of(&ft)) {
+                    (IndexExpression::Lowercase, Ft::Text | Ft::Json) => Ok(()),
+                    (IndexExpression::Truncate { interval: 0 }, _) => {
+                        Err("Truncate interval must be positive".to_string())
+                    }
+                    (IndexExpression::Truncate { .. }, Ft::I64 | Ft::U64) => Ok(()),
+                    (expression, ft) => Err(format!(
+                        "expression {expression:?} cannot be applied to field {path:?} of type {ft:?}"
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Returns the top-level fields the filter reads, so that updating one
+    /// of them re-evaluates the predicate.
+    pub fn filter_fields(&self) -> Vec<&str> {
+        let mut fields = Vec::new();
+        if let Some(filter) = &self.filter {
+            collect_filter_fields(filter, &mut fields);
+        }
+        fields
+    }
+
+    /// Applies the options to the value extracted for `doc`: returns `None`
+    /// if `doc` does not match the filter, and the transformed value
+    /// otherwise.
+    pub fn apply<'a>(&self, doc: &Document, value: Cow<'a, Fv>) -> Option<Cow<'a, Fv>> {
+        if let Some(filter) = &self.filter
+            && !predicate_matches(filter, doc)
+        {
+            return None;
+        }
+        match &self.expression {
+            None => Some(value),
+            Some(expression) => Some(Cow::Owned(apply_expression(expression, &value))),
+        }
+    }
+}
+
+/// Rounds an integer key down to a multiple of `interval`. Other keys are
+/// returned unchanged; a bound below `i64::MIN` saturates.
+pub(crate) fn truncate_key(key: &Fv, interval: u64) -> Fv {
+    match key {
+        Fv::U64(v) => Fv::U64(v - v % interval),
+        Fv::I64(v) => {
+            let bound = (*v as i128).div_euclid(interval as i128) * interval as i128;
+            Fv::I64(bound.max(i64::MIN as i128) as i64)
+        }
+        other => other.clone(),
+    }
+}
+
+fn apply_expression(expression: &IndexExpression, value: &Fv) -> Fv {
+    match (expression, value) {
+        (_, Fv::Array(values)) => Fv::Array(
+            values
+                .iter()
+                .map(|v| apply_expression(expression, v))
+                .collect(),
+        ),
+        // A wildcard map indexes its keys.
+        (_, Fv::Map(values)) => Fv::Array(
+            values
+                .keys()
+                .map(|k| apply_expression(expression, &Fv::from(k.clone())))
+                .collect(),
+        ),
+        (IndexExpression::Lowercase, Fv::Text(text)) => Fv::Text(text.to_lowercase()),
+        (IndexExpression::Truncate { interval }, key) => truncate_key(key, *interval),
+        (_, other) => other.clone(),
+    }
+}
+
+fn validate_predicate(schema: &Schema, filter: &Filter) -> Result<(), String> {
+    match filter {
+        Filter::Field((path, query)) => {
+            let ft = field_path_type(schema, path).map_err(|err| err.to_string())?;
+            let ft = key_type_of(&ft);
+            if !matches!(
+                ft,
+                Ft::Bool | Ft::I64 | Ft::U64 | Ft::F64 | Ft::F32 | Ft::Bytes | Ft::Text | Ft::Json
+            ) {
+                return Err(format!(
+                    "field {path:?} of type {ft:?} cannot be used in an index filter"
+                ));
+            }
+            validate_predicate_values(path, &ft, query)
+        }
+        Filter::Or(filters) | Filter::And(filters) => filters
+            .iter()
+            .try_for_each(|filter| validate_predicate(schema, filter)),
+        Filter::Not(filter) => validate_predicate(schema, filter),
+    }
+}
+
+fn validate_predicate_values(path: &str, ft: &Ft, query: &RangeQuery<Fv>) -> Result<(), String> {
+    let check = |value: &Fv| {
+        let ok = matches!(
+            (ft, value),
+            (Ft::Bool, Fv::Bool(_))
+                | (Ft::I64 | Ft::U64, Fv::I64(_) | Fv::U64(_))
+                | (Ft::F64 | Ft::F32, Fv::F64(_) | Fv::F32(_))
+                | (Ft::Bytes, Fv::Bytes(_))
+                | (Ft::Text, Fv::Text(_))
+                | (
+                    Ft::Json,
+                    Fv::Bool(_) | Fv::I64(_) | Fv::U64(_) | Fv::F64(_) | Fv::F32(_) | Fv::Text(_)
+                )
+        );
+        if ok {
+            Ok(())
+        } else {
+            Err(format!(
+                "index filter value {value:?} does not match field {path:?} of type {ft:?}"
+            ))
+        }
+    };
+    match query {
+        RangeQuery::Eq(v)
+        | RangeQuery::Gt(v)
+        | RangeQuery::Ge(v)
+        | RangeQuery::Lt(v)
+        | RangeQuery::Le(v) => check(v),
+        RangeQuery::Between(start, end) => check(start).and(check(end)),
+        RangeQuery::Include(values) => values.iter().try_for_each(check),
+        RangeQuery::Or(queries) | RangeQuery::And(queries) => queries
+            .iter()
+            .try_for_each(|query| validate_predicate_values(path, ft, query)),
+        RangeQuery::Not(query) => validate_predicate_values(path, ft, query),
+    }
+}
+
+fn collect_filter_fields<'a>(filter: &'a Filter, fields: &mut Vec<&'a str>) {
+    match filter {
+        Filter::Field((path, _)) => fields.push(root_field(path)),
+        Filter::Or(filters) | Filter::And(filters) => {
+            for filter in filters {
+                collect_filter_fields(filter, fields);
+            }
+        }
+        Filter::Not(filter) => collect_filter_fields(filter, fields),
+    }
+}
+
+fn predicate_matches(filter: &Filter, doc: &Document) -> bool {
+    match filter {
+        Filter::Field((path, query)) => {
+            field_path_value(doc, path).is_some_and(|value| value_matches(&value, query))
+        }
+        Filter::Or(filters) => filters.iter().any(|filter| predicate_matches(filter, doc)),
+        Filter::And(filters) => filters.iter().all(|filter| predicate_matches(filter, doc)),
+        Filter::Not(filter) => !predicate_matches(filter, doc),
+    }
+}
+
+fn value_matches(value: &Fv, query: &RangeQuery<Fv>) -> bool {
+    match value {
+        Fv::Array(values) => values.iter().any(|value| value_matches(value, query)),
+        value => key_matches(value, query),
+    }
+}
+
+fn key_matches(key: &Fv, query: &RangeQuery<Fv>) -> bool {
+    use std::cmp::Ordering::*;
+
+    let cmp = |other: &Fv| compare_keys(key, other);
+    match query {
+        RangeQuery::Eq(v) => cmp(v) == Some(Equal),
+        RangeQuery::Gt(v) => cmp(v) == Some(Greater),
+        RangeQuery::Ge(v) => matches!(cmp(v), Some(Greater | Equal)),
+        RangeQuery::Lt(v) => cmp(v) == Some(Less),
+        RangeQuery::Le(v) => matches!(cmp(v), Some(Less | Equal)),
+        RangeQuery::Between(start, end) => {
+            matches!(cmp(start), Some(Greater | Equal)) && matches!(cmp(end), Some(Less | Equal))
+        }
+        RangeQuery::Include(values) => values.iter().any(|v| cmp(v) == Some(Equal)),
+        RangeQuery::Or(queries) => queries.iter().any(|query| key_matches(key, query)),
+        RangeQuery::And(queries) => {
+            !queries.is_empty() && queries.iter().all(|query| key_matches(key, query))
+        }
+        RangeQuery::Not(query) => !key_matches(key, query),
+    }
+}
+
+/// Orders two scalar values of compatible types; numbers compare across
+/// `I64`/`U64`/`F64`/`F32`.
+fn compare_keys(a: &Fv, b: &Fv) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Fv::Bool(a), Fv::Bool(b)) => Some(a.cmp(b)),
+        (Fv::I64(a), Fv::I64(b)) => Some(a.cmp(b)),
+        (Fv::U64(a), Fv::U64(b)) => Some(a.cmp(b)),
+        (Fv::I64(a), Fv::U64(b)) => Some((*a as i128).cmp(&(*b as i128))),
+        (Fv::U64(a), Fv::I64(b)) => Some((*a as i128).cmp(&(*b as i128))),
+        (Fv::F64(a), Fv::F64(b)) => a.partial_cmp(b),
+        (Fv::F32(a), Fv::F32(b)) => a.partial_cmp(b),
+        (Fv::F64(a), Fv::F32(b)) => a.partial_cmp(&(*b as f64)),
+        (Fv::F32(a), Fv::F64(b)) => (*a as f64).partial_cmp(b),
+        (Fv::I64(a), Fv::F64(b)) => (*a as f64).partial_cmp(b),
+        (Fv::U64(a), Fv::F64(b)) => (*a as f64).partial_cmp(b),
+        (Fv::F64(a), Fv::I64(b)) => a.partial_cmp(&(*b as f64)),
+        (Fv::F64(a), Fv::U64(b)) => a.partial_cmp(&(*b as f64)),
+        (Fv::Bytes(a), Fv::Bytes(b)) => Some(a.cmp(b)),
+        (Fv::Text(a), Fv::Text(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1662,6 +2356,95 @@ mod tests {
         assert!(!tree.remove(2, &Fv::U64(i64::MAX as u64 + 1), now));
     }
 
+    #[test]
+    fn json_keys_order_by_kind_then_value() {
+        let key = |v: Fv| JsonKey::try_from(v).unwrap();
+        assert_eq!(key(Fv::F64(-0.0)), JsonKey::I64(0));
+        assert_eq!(key(Fv::F64(10.0)), key(Fv::U64(10)));
+        assert!(matches!(key(Fv::F64(2f64.powi(63))), JsonKey::U64(_)));
+        assert!(JsonKey::try_from(Fv::F64(f64::NAN)).is_err());
+        assert!(JsonKey::try_from(Fv::Bytes(vec![1])).is_err());
+
+        let mut keys = vec![
+            key(Fv::Text("10".into())),
+            key(Fv::U64(u64::MAX)),
+            key(Fv::F64(1e20)),
+            key(Fv::I64(10)),
+            key(Fv::F64(9.5)),
+            key(Fv::F64(-1e20)),
+            key(Fv::I64(i64::MIN)),
+            key(Fv::Bool(true)),
+            key(Fv::Text("9".into())),
+            key(Fv::Bool(false)),
+        ];
+        keys.sort();
+        assert_eq!(
+            keys.into_iter().map(Fv::from).collect::<Vec<_>>(),
+            vec![
+                Fv::Bool(false),
+                Fv::Bool(true),
+                Fv::F64(-1e20),
+                Fv::I64(i64::MIN),
+                Fv::F64(9.5),
+                Fv::I64(10),
+                Fv::U64(u64::MAX),
+                Fv::F64(1e20),
+                Fv::Text("10".into()),
+                Fv::Text("9".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn json_path_index_ranges_stay_within_a_kind() {
+        let storage = test_storage().await;
+        let now = unix_ms();
+        let tree = BTree::with_field_path(
+            "meta.rank".to_string(),
+            &field("_path_field_", Ft::Json),
+            storage.clone(),
+            now,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(tree, BTree::Json(_)));
+        // Only a path resolves to `Json`.
+        assert!(
+            BTree::new(field("meta", Ft::Json), storage.clone(), now)
+                .await
+                .is_err()
+        );
+
+        assert!(tree.insert(1, &Fv::I64(9), now).unwrap());
+        assert!(tree.insert(2, &Fv::F64(10.0), now).unwrap());
+        assert!(tree.insert(3, &Fv::Text("8".into()), now).unwrap());
+        assert!(tree.insert(4, &Fv::Bool(true), now).unwrap());
+        assert!(tree.insert(5, &Fv::Bytes(vec![1]), now).is_err());
+        tree.flush(now).await.unwrap();
+
+        let tree = BTree::bootstrap("meta.rank".into(), &Ft::Json, storage)
+            .await
+            .unwrap();
+        let ids = |query| {
+            let mut ids = Vec::new();
+            tree.try_range_query_ids(query, false, |matched| {
+                ids.extend_from_slice(matched);
+                true
+            })
+            .unwrap();
+            ids
+        };
+        assert_eq!(ids(RangeQuery::Eq(Fv::U64(10))), vec![2]);
+        assert_eq!(ids(RangeQuery::Gt(Fv::I64(5))), vec![1, 2]);
+        assert_eq!(ids(RangeQuery::Lt(Fv::Text("9".into()))), vec![3]);
+        assert_eq!(
+            ids(RangeQuery::Not(Box::new(RangeQuery::Gt(Fv::I64(5))))),
+            vec![4, 3]
+        );
+        assert!(tree.remove(2, &Fv::I64(10), now + 1));
+        assert_eq!(tree.stats().num_elements, 3);
+    }
+
     #[tokio::test]
     async fn i64_update_same_numeric_key_across_variants_is_a_noop() {
         let storage = test_storage().await;
@@ -1,14 +1,19 @@
-use anda_db_hnsw::HnswIndex;
+use anda_db_hnsw::{HnswError, HnswIndex};
 use bytes::Bytes;
+use croaring::Treemap;
 use futures::StreamExt;
-use parking_lot::RwLock;
-use std::{fmt::Debug, hash::Hash, sync::Arc};
+use parking_lot::{Mutex, RwLock};
+use rustc_hash::FxHashSet;
+use std::{borrow::Cow, collections::BTreeSet, fmt::Debug, hash::Hash, sync::Arc};
 
-pub use anda_db_hnsw::{HnswConfig, HnswMetadata, HnswStats};
+pub use anda_db_hnsw::{
+    HnswConfig, HnswGraphHealth, HnswMetadata, HnswStats, Quantization, RecallMeasurement,
+    RecallOptions, RecallReport,
+};
 
 use crate::{
     error::DBError,
-    schema::{BoxError, Fe, Vector},
+    schema::{BoxError, Fe, Ft, Vector},
     storage::{ObjectVersion, PutMode, Storage},
 };
 
@@ -18,15 +23,33 @@ use crate::{
 /// id lists, and graph nodes while delegating search behavior to
 /// `anda_db_hnsw::HnswIndex`.
 ///
+/// An index over an `Array` of vectors field is a multi-vector index: every
+/// vector of a document is a graph node whose id packs the document id with
+/// the vector's ordinal (see [`MAX_DOCUMENT_VECTORS`]), and searches
+/// aggregate the nodes back to their documents.
+///
 /// The metadata/ids CAS tokens are the last defense against a second writer,
 /// which the single-writer deployment contract forbids. A `Precondition`
 /// conflict (or a cancelled flush) is never reconciled in place: the error
 /// propagates, the collection poisons its handle and reopening rebuilds this
 /// wrapper from the durable objects.
+///
+/// The graph can be rebuilt online (see [`Hnsw::begin_rebuild`]): every
+/// rebuild writes a new generation of the graph under its own directory and
+/// swaps it in, while searches keep using the graph they started on.
 pub struct Hnsw {
     name: String,
-    index: HnswIndex,
+    multi_vector: bool,
     storage: Storage, // 与 Collection 共享同一个 Storage 实例
+    graph: RwLock<Arc<HnswGraph>>,
+    /// Documents mutated since [`Hnsw::begin_rebuild`], while a rebuild runs.
+    rebuild_log: Mutex<Option<BTreeSet<u64>>>,
+}
+
+/// One generation of the persisted graph.
+struct HnswGraph {
+    generation: u64,
+    index: HnswIndex,
     metadata_version: Arc<RwLock<ObjectVersion>>,
     ids_version: Arc<RwLock<ObjectVersion>>,
 }
@@ -50,21 +73,48 @@ impl Hash for &Hnsw {
     }
 }
 
+/// Number of low node id bits holding the ordinal of a vector within its
+/// document in a multi-vector index.
+const ORDINAL_BITS: u32 = 16;
+
+/// Maximum number of vectors a document can hold in a multi-vector index.
+///
+/// Node ids are `document_id << 16 | ordinal`, so a multi-vector index also
+/// requires document ids below `2^48`.
+pub const MAX_DOCUMENT_VECTORS: usize = 1 << ORDINAL_BITS;
+
+/// How many nodes a single-vector query fetches per requested document from
+/// a multi-vector index, since several nodes may belong to one document.
+const MULTI_VECTOR_OVERSAMPLING: usize = 4;
+
+/// Returns `true` if an HNSW index on a field of type `ft` is a multi-vector
+/// index, i.e. the field holds an `Array` of vectors.
+pub fn is_multi_vector_field(ft: &Ft) -> bool {
+    matches!(ft, Ft::Array(types) if types.as_slice() == [Ft::Vector])
+}
+
 impl Hnsw {
-    pub(crate) fn dir_path(name: &str) -> String {
-        format!("hnsw_indexes/{name}/")
+    /// Returns the directory of graph generation `generation`. Generation 0
+    /// keeps the original layout; field names cannot contain `.`, so later
+    /// generations never collide with another index.
+    pub(crate) fn dir_path(name: &str, generation: u64) -> String {
+        if generation == 0 {
+            format!("hnsw_indexes/{name}/")
+        } else {
+            format!("hnsw_indexes/{name}.{generation}/")
+        }
     }
 
-    fn metadata_path(name: &str) -> String {
-        format!("hnsw_indexes/{name}/meta.cbor")
+    fn metadata_path(name: &str, generation: u64) -> String {
+        format!("{}meta.cbor", Hnsw::dir_path(name, generation))
     }
 
-    fn ids_path(name: &str) -> String {
-        format!("hnsw_indexes/{name}/ids.cbor")
+    fn ids_path(name: &str, generation: u64) -> String {
+        format!("{}ids.cbor", Hnsw::dir_path(name, generation))
     }
 
-    fn node_path(name: &str, node: u64) -> String {
-        format!("hnsw_indexes/{name}/n_{node}.cbor")
+    fn node_path(name: &str, generation: u64, node: u64) -> String {
+        format!("{}n_{node}.cbor", Hnsw::dir_path(name, generation))
     }
 
     /// Creates a new persisted HNSW index for `field`.
@@ -73,6 +123,16 @@ impl Hnsw {
         config: HnswConfig,
         storage: Storage,
         now_ms: u64,
+    ) -> Result<Self, DBError> {
+        Self::create(field, config, storage, 0, now_ms).await
+    }
+
+    async fn create(
+        field: &Fe,
+        config: HnswConfig,
+        storage: Storage,
+        generation: u64,
+        now_ms: u64,
     ) -> Result<Self, DBError> {
         let name = field.name().to_string();
         let index = HnswIndex::try_new(name.clone(), Some(config))?;
@@ -87,43 +147,115 @@ impl Hnsw {
         // Publish the id set before the metadata commit record, matching the
         // steady-state crash contract used by `flush` below.
         let ids_version = storage
-            .put_bytes(&Hnsw::ids_path(&name), ids.into(), PutMode::Overwrite)
+            .put_bytes(
+                &Hnsw::ids_path(&name, generation),
+                ids.into(),
+                PutMode::Overwrite,
+            )
             .await?;
         let metadata_version = storage
             .put_bytes(
-                &Hnsw::metadata_path(&name),
+                &Hnsw::metadata_path(&name, generation),
                 metadata.into(),
                 PutMode::Overwrite,
             )
             .await?;
-        Ok(Self {
-            name,
-            index,
+        Ok(Self::with_graph(
+            field,
             storage,
-            metadata_version: Arc::new(RwLock::new(metadata_version)),
-            ids_version: Arc::new(RwLock::new(ids_version)),
-        })
+            HnswGraph {
+                generation,
+                index,
+                metadata_version: Arc::new(RwLock::new(metadata_version)),
+                ids_version: Arc::new(RwLock::new(ids_version)),
+            },
+        ))
+    }
+
+    fn with_graph(field: &Fe, storage: Storage, graph: HnswGraph) -> Self {
+        Self {
+            name: field.name().to_string(),
+            multi_vector: is_multi_vector_field(field.r#type()),
+            storage,
+            graph: RwLock::new(Arc::new(graph)),
+            rebuild_log: Mutex::new(None),
+        }
+    }
+
+    fn graph(&self) -> Arc<HnswGraph> {
+        self.graph.read().clone()
+    }
+
+    /// Returns the generation of the graph currently serving, bumped by every
+    /// committed rebuild.
+    pub fn generation(&self) -> u64 {
+        self.graph.read().generation
     }
 
     pub(crate) async fn drop_data(&self) {
         // Delete the metadata, ids and all node objects under the index directory.
-        if let Err(err) = self.storage.drop_prefix(&Hnsw::dir_path(&self.name)).await {
+        self.drop_generation(self.generation()).await;
+    }
+
+    /// Best-effort deletion of every object of graph generation `generation`.
+    pub(crate) async fn drop_generation(&self, generation: u64) {
+        let dir = Hnsw::dir_path(&self.name, generation);
+        if let Err(err) = self.storage.drop_prefix(&dir).await {
             log::warn!(
                 action = "Hnsw::drop_data",
-                index = self.name;
+                index = self.name,
+                generation = generation;
                 "Failed to drop HNSW index data: {err:?}",
             );
         }
     }
 
-    /// Loads an existing HNSW index from metadata, id list, and node objects.
+    /// Loads an existing single-vector HNSW index from metadata, id list, and
+    /// node objects.
+    ///
+    /// This reads graph generation 0 and leaves every other generation
+    /// untouched; indexes that were rebuilt or hold multi-vector fields are
+    /// loaded with [`Hnsw::bootstrap_field`].
     pub async fn bootstrap(name: String, storage: Storage) -> Result<Self, DBError> {
-        let (metadata, metadata_version) = storage.fetch_bytes(&Hnsw::metadata_path(&name)).await?;
-        let (ids, ids_version) = storage.fetch_bytes(&Hnsw::ids_path(&name)).await?;
+        let field = Fe::new(name, Ft::Vector)?;
+        Self::load_generation(&field, storage, 0).await
+    }
+
+    /// Loads an existing HNSW index for `field` from graph generation
+    /// `generation`.
+    ///
+    /// The neighbouring generations are dropped: the previous one is left
+    /// behind by a crash after a rebuild was committed, the next one by a
+    /// crash before it was.
+    pub async fn bootstrap_field(
+        field: &Fe,
+        storage: Storage,
+        generation: u64,
+    ) -> Result<Self, DBError> {
+        let this = Self::load_generation(field, storage, generation).await?;
+        if generation > 0 {
+            this.drop_generation(generation - 1).await;
+        }
+        this.drop_generation(generation + 1).await;
+        Ok(this)
+    }
+
+    async fn load_generation(
+        field: &Fe,
+        storage: Storage,
+        generation: u64,
+    ) -> Result<Self, DBError> {
+        let name = field.name().to_string();
+        let (metadata, metadata_version) = storage
+            .fetch_bytes(&Hnsw::metadata_path(&name, generation))
+            .await?;
+        let (ids, ids_version) = storage
+            .fetch_bytes(&Hnsw::ids_path(&name, generation))
+            .await?;
         let n = Arc::new(name.clone());
         let s = Arc::new(storage.clone());
         let index = HnswIndex::load_all(&metadata[..], &ids[..], async move |id: u64| {
-            let path = Hnsw::node_path(n.clone().as_str(), id);
+            let path = Hnsw::node_path(n.clone().as_str(), generation, id);
             match s.clone().fetch_bytes(&path).await {
                 Ok((data, _)) => Ok(Some(data.into())),
                 Err(DBError::NotFound { .. }) => Ok(None),
@@ -132,13 +264,16 @@ impl Hnsw {
         })
         .await?;
 
-        let this = Self {
-            name,
-            index,
+        let this = Self::with_graph(
+            field,
             storage,
-            metadata_version: Arc::new(RwLock::new(metadata_version)),
-            ids_version: Arc::new(RwLock::new(ids_version)),
-        };
+            HnswGraph {
+                generation,
+                index,
+                metadata_version: Arc::new(RwLock::new(metadata_version)),
+                ids_version: Arc::new(RwLock::new(ids_version)),
+            },
+        );
         this.purge_orphan_node_blobs().await;
         Ok(this)
     }
@@ -153,14 +288,15 @@ impl Hnsw {
     /// already pays O(nodes) to fetch every referenced blob, so one listing
     /// of the index directory to sweep unreferenced ones is proportional.
     async fn purge_orphan_node_blobs(&self) {
-        let referenced: std::collections::BTreeSet<u64> = self
+        let graph = self.graph();
+        let referenced: BTreeSet<u64> = graph
             .index
             .node_ids()
             .into_iter()
-            .chain(self.index.removed_node_ids())
+            .chain(graph.index.removed_node_ids())
             .collect();
 
-        let dir = Hnsw::dir_path(&self.name);
+        let dir = Hnsw::dir_path(&self.name, graph.generation);
         let mut stream = self.storage.list_meta(Some(&dir), None);
         let mut orphans: Vec<u64> = Vec::new();
         while let Some(meta) = stream.next().await {
@@ -182,7 +318,7 @@ impl Hnsw {
         }
 
         for id in orphans {
-            let path = Hnsw::node_path(&self.name, id);
+            let path = Hnsw::node_path(&self.name, graph.generation, id);
             match self.storage.delete(&path).await {
                 Ok(()) | Err(DBError::NotFound { .. }) => {
                     log::warn!(
@@ -233,16 +369,18 @@ impl Hnsw {
     ///
     /// Returns `true` when any object was written or deleted.
     pub async fn flush(&self, now_ms: u64) -> Result<bool, DBError> {
-        let had_removed = self.index.has_removed_nodes();
+        let graph = self.graph();
+        let generation = graph.generation;
+        let had_removed = graph.index.has_removed_nodes();
         let node_name = Arc::new(self.name.clone());
         let node_storage = Arc::new(self.storage.clone());
-        let ids_path = Hnsw::ids_path(&self.name);
+        let ids_path = Hnsw::ids_path(&self.name, generation);
         let ids_storage = self.storage.clone();
-        let ids_version = self.ids_version.clone();
-        let metadata_path = Hnsw::metadata_path(&self.name);
+        let ids_version = graph.ids_version.clone();
+        let metadata_path = Hnsw::metadata_path(&self.name, generation);
         let metadata_storage = self.storage.clone();
-        let metadata_version = self.metadata_version.clone();
-        let saved = self
+        let metadata_version = graph.metadata_version.clone();
+        let saved = graph
             .index
             .flush_with(
                 now_ms,
@@ -250,7 +388,7 @@ impl Hnsw {
                     let name = node_name.clone();
                     let storage = node_storage.clone();
                     async move {
-                        let path = Hnsw::node_path(name.as_str(), id);
+                        let path = Hnsw::node_path(name.as_str(), generation, id);
                         storage
                             .put_bytes(&path, Bytes::from(data), PutMode::Overwrite)
                             .await
@@ -269,9 +407,10 @@ impl Hnsw {
         // would leak forever. "Not found" is success (already deleted).
         let n = Arc::new(self.name.clone());
         let s = Arc::new(self.storage.clone());
-        self.index
+        graph
+            .index
             .purge_removed_nodes(async move |id| {
-                let path = Hnsw::node_path(n.clone().as_str(), id);
+                let path = Hnsw::node_path(n.clone().as_str(), generation, id);
                 match s.clone().delete(&path).await {
                     Ok(()) | Err(DBError::NotFound { .. }) => Ok(true),
                     Err(err) => Err(err.into()),
@@ -285,11 +424,12 @@ impl Hnsw {
     /// Returns whether metadata, nodes, or removed-node tombstones have
     /// in-memory changes to flush.
     pub fn has_pending_flush(&self) -> bool {
-        if self.index.has_dirty_nodes() || self.index.has_removed_nodes() {
+        let graph = self.graph();
+        if graph.index.has_dirty_nodes() || graph.index.has_removed_nodes() {
             return true;
         }
 
-        self.index.has_pending_metadata_flush()
+        graph.index.has_pending_metadata_flush()
     }
 
     /// Returns the stable index name.
@@ -304,41 +444,552 @@ impl Hnsw {
 
     /// Returns the vector dimension this index was configured with.
     pub fn dimension(&self) -> usize {
-        self.index.dimension()
+        self.graph().index.dimension()
     }
 
     /// Returns a snapshot of HNSW runtime statistics.
     pub fn stats(&self) -> HnswStats {
-        self.index.stats()
+        self.graph().index.stats()
     }
 
     /// Returns a snapshot of HNSW metadata.
     pub fn metadata(&self) -> HnswMetadata {
-        self.index.metadata()
+        self.graph().index.metadata()
     }
 
-    /// Inserts or updates the vector for `id`.
+    /// Returns `true` if the indexed field holds an `Array` of vectors per
+    /// document.
+    pub fn multi_vector(&self) -> bool {
+        self.multi_vector
+    }
+
+    /// Returns the id of the graph node holding vector `ordinal` of document
+    /// `id`.
+    fn node_id(&self, id: u64, ordinal: usize) -> Result<u64, DBError> {
+        if !self.multi_vector {
+            return if ordinal == 0 {
+                Ok(id)
+            } else {
+                Err(DBError::Index {
+                    name: self.name.clone(),
+                    source: format!(
+                        "document {id} has more than one vector for a single-vector index"
+                    )
+                    .into(),
+                })
+            };
+        }
+        if id >> (u64::BITS - ORDINAL_BITS) != 0 {
+            return Err(DBError::Index {
+                name: self.name.clone(),
+                source: format!("document id {id} is too large for a multi-vector index").into(),
+            });
+        }
+        if ordinal >= MAX_DOCUMENT_VECTORS {
+            return Err(DBError::Index {
+                name: self.name.clone(),
+                source: format!("document {id} has more than {MAX_DOCUMENT_VECTORS} vectors")
+                    .into(),
+            });
+        }
+        Ok((id << ORDINAL_BITS) | ordinal as u64)
+    }
+
+    /// Returns the document owning graph node `node`.
+    fn document_id(&self, node: u64) -> u64 {
+        if self.multi_vector {
+            node >> ORDINAL_BITS
+        } else {
+            node
+        }
+    }
+
+    fn document_nodes(&self, id: u64, vectors: Vec<Vector>) -> Result<Vec<(u64, Vector)>, DBError> {
+        vectors
+            .into_iter()
+            .enumerate()
+            .map(|(ordinal, vector)| Ok((self.node_id(id, ordinal)?, vector)))
+            .collect()
+    }
+
+    /// Inserts the vector for `id`.
     pub fn insert(&self, id: u64, vector: Vector, now_ms: u64) -> Result<(), DBError> {
-        self.index.insert(id, vector, now_ms)?;
+        self.insert_vectors(id, vec![vector], now_ms)
+    }
+
+    /// Inserts the vectors of document `id`.
+    ///
+    /// A single-vector index accepts at most one vector. The vectors are
+    /// validated before anything is inserted.
+    pub fn insert_vectors(
+        &self,
+        id: u64,
+        vectors: Vec<Vector>,
+        now_ms: u64,
+    ) -> Result<(), DBError> {
+        let mut nodes = self.document_nodes(id, vectors)?;
+        self.log_rebuild_changes([id]);
+        let graph = self.graph();
+        match nodes.len() {
+            0 => {}
+            1 => {
+                let (node, vector) = nodes.pop().expect("one node");
+                graph.index.insert(node, vector, now_ms)?;
+            }
+            _ => graph.index.insert_batch(nodes, now_ms)?,
+        }
+        Ok(())
+    }
+
+    /// Inserts the vectors of many new documents at once, planning their
+    /// neighbors in parallel.
+    ///
+    /// The batch is validated before anything is inserted; see
+    /// `HnswIndex::insert_batch`.
+    pub fn insert_batch(&self, items: Vec<(u64, Vec<Vector>)>, now_ms: u64) -> Result<(), DBError> {
+        let mut ids = Vec::with_capacity(items.len());
+        let mut nodes = Vec::with_capacity(items.len());
+        for (id, vectors) in items {
+            ids.push(id);
+            nodes.extend(self.document_nodes(id, vectors)?);
+        }
+        self.log_rebuild_changes(ids);
+        self.graph().index.insert_batch(nodes, now_ms)?;
         Ok(())
     }
 
-    /// Removes the vector for `id` if present.
+    /// Removes the vectors of document `id` if present.
     pub fn remove(&self, id: u64, now_ms: u64) -> bool {
-        self.index.remove(id, now_ms)
+        self.log_rebuild_changes([id]);
+        let graph = self.graph();
+        if !self.multi_vector {
+            return graph.index.remove(id, now_ms);
+        }
+
+        // Ordinals are dense, so the first missing node ends the document.
+        let mut removed = false;
+        for ordinal in 0..MAX_DOCUMENT_VECTORS {
+            match self.node_id(id, ordinal) {
+                Ok(node) if graph.index.remove(node, now_ms) => removed = true,
+                _ => break,
+            }
+        }
+        removed
+    }
+
+    fn log_rebuild_changes(&self, ids: impl IntoIterator<Item = u64>) {
+        if let Some(changes) = self.rebuild_log.lock().as_mut() {
+            changes.extend(ids);
+        }
     }
 
-    /// Searches for the nearest vectors and returns `(document_id, distance)` pairs.
+    /// Starts an online rebuild of the graph.
+    ///
+    /// Returns an empty index of the next generation, persisted under its own
+    /// directory, for the caller to fill with the live documents while this
+    /// index keeps serving. Until the returned [`HnswRebuild`] is committed
+    /// or dropped, the ids of documents inserted into or removed from this
+    /// index are recorded, for the caller to replay into the new graph once
+    /// mutations are blocked.
+    ///
+    /// Fails if a rebuild is already running.
+    pub async fn begin_rebuild(&self, field: &Fe, now_ms: u64) -> Result<HnswRebuild<'_>, DBError> {
+        {
+            let mut log = self.rebuild_log.lock();
+            if log.is_some() {
+                return Err(DBError::Index {
+                    name: self.name.clone(),
+                    source: "HNSW index rebuild already in progress".into(),
+                });
+            }
+            *log = Some(BTreeSet::new());
+        }
+
+        let mut rebuild = HnswRebuild {
+            index: self,
+            rebuilt: None,
+        };
+        let generation = self.generation() + 1;
+        // Leftovers of a rebuild that never committed.
+        self.drop_generation(generation).await;
+        let metadata = self.metadata();
+        let rebuilt = Hnsw::create(
+            field,
+            metadata.config,
+            self.storage.clone(),
+            generation,
+            now_ms,
+        )
+        .await?;
+        // A tuned beam width carries over to the new graph.
+        rebuilt.graph().index.set_ef_search(metadata.ef_search)?;
+        rebuild.rebuilt = Some(rebuilt);
+        Ok(rebuild)
+    }
+
+    /// Measures the connectivity of the graph and records it in
+    /// [`HnswStats::graph_health`]; see [`HnswIndex::graph_health`].
+    pub fn graph_health(&self, now_ms: u64) -> HnswGraphHealth {
+        self.graph().index.graph_health(now_ms)
+    }
+
+    /// Returns the layer-0 beam width of searches, see
+    /// [`HnswIndex::set_ef_search`].
+    pub fn ef_search(&self) -> usize {
+        self.graph().index.ef_search()
+    }
+
+    /// Measures recall@k and latency per `ef_search` value, see
+    /// [`HnswIndex::evaluate_recall`]. Recall is measured over graph nodes,
+    /// i.e. over vectors rather than documents for a multi-vector index.
+    pub fn evaluate_recall(&self, options: &RecallOptions) -> Result<RecallReport, DBError> {
+        Ok(self.graph().index.evaluate_recall(options)?)
+    }
+
+    /// Like [`Hnsw::evaluate_recall`], on a blocking thread: the exact scan
+    /// behind the measurements costs `O(Q·N)` distance computations.
+    pub async fn evaluate_recall_blocking(
+        &self,
+        options: RecallOptions,
+    ) -> Result<RecallReport, DBError> {
+        let graph = self.graph();
+        tokio::task::spawn_blocking(move || graph.index.evaluate_recall(&options))
+            .await
+            .map_err(|err| DBError::Index {
+                name: self.name.clone(),
+                source: err.into(),
+            })?
+            .map_err(DBError::from)
+    }
+
+    /// Sets the layer-0 beam width of searches, see
+    /// [`HnswIndex::set_ef_search`]. The value is persisted by the next
+    /// flush.
+    pub fn set_ef_search(&self, ef_search: Option<usize>) -> Result<(), DBError> {
+        Ok(self.graph().index.set_ef_search(ef_search)?)
+    }
+
+    /// Sets the smallest `ef_search` reaching `target_recall`, see
+    /// [`HnswIndex::tune_ef_search`]. The value is persisted by the next
+    /// flush.
+    pub fn tune_ef_search(
+        &self,
+        target_recall: f32,
+        options: &RecallOptions,
+    ) -> Result<(usize, RecallReport), DBError> {
+        Ok(self.graph().index.tune_ef_search(target_recall, options)?)
+    }
+
+    /// Searches for the nearest documents and returns `(document_id, distance)`
+    /// pairs.
+    ///
+    /// A document of a multi-vector index is as near as its nearest vector.
     pub fn try_search(&self, query: &[f32], top_k: usize) -> Result<Vec<(u64, f32)>, DBError> {
-        self.index.search_f32(query, top_k).map_err(DBError::from)
+        self.search_documents(&self.graph().index, query, top_k, None)
+    }
+
+    /// Searches for the nearest documents among the document ids in
+    /// `allowed`.
+    ///
+    /// See [`HnswIndex::search_filtered`]: the filter is applied during the
+    /// graph traversal, with an exhaustive scan for small allowed sets.
+    pub fn try_search_filtered(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        allowed: &Treemap,
+    ) -> Result<Vec<(u64, f32)>, DBError> {
+        self.search_documents(&self.graph().index, query, top_k, Some(allowed))
+    }
+
+    /// Late-interaction (ColBERT-style MaxSim) search with a multi-vector
+    /// query.
+    ///
+    /// The candidates are the nearest documents of every query vector. Each
+    /// candidate is then scored by summing, over the query vectors, the
+    /// distance to its nearest document vector, and the `top_k` documents
+    /// with the smallest sum are returned in ascending order.
+    pub fn try_search_max_sim(
+        &self,
+        queries: &[&[f32]],
+        top_k: usize,
+        allowed: Option<&Treemap>,
+    ) -> Result<Vec<(u64, f32)>, DBError> {
+        let graph = self.graph();
+        let mut candidates = BTreeSet::new();
+        for query in queries {
+            for (id, _) in self.search_documents(&graph.index, query, top_k, allowed)? {
+                candidates.insert(id);
+            }
+        }
+
+        let mut results = Vec::with_capacity(candidates.len());
+        for id in candidates {
+            if let Some(score) = self.max_sim(&graph.index, queries, id)? {
+                results.push((id, score));
+            }
+        }
+        results.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        results.truncate(top_k);
+        Ok(results)
+    }
+
+    fn search_documents(
+        &self,
+        index: &HnswIndex,
+        query: &[f32],
+        top_k: usize,
+        allowed: Option<&Treemap>,
+    ) -> Result<Vec<(u64, f32)>, DBError> {
+        if !self.multi_vector {
+            return match allowed {
+                Some(allowed) => index.search_filtered(query, top_k, allowed),
+                None => index.search_f32(query, top_k),
+            }
+            .map_err(DBError::from);
+        }
+
+        let limit = top_k.saturating_mul(MULTI_VECTOR_OVERSAMPLING);
+        let hits = match allowed {
+            Some(allowed) => {
+                let mut nodes = Treemap::new();
+                for id in allowed.iter() {
+                    if let Ok(first) = self.node_id(id, 0) {
+                        nodes.add_range(first..first + MAX_DOCUMENT_VECTORS as u64);
+                    }
+                }
+                index.search_filtered(query, limit, &nodes)?
+            }
+            None => index.search_f32(query, limit)?,
+        };
+
+        // Hits come sorted by distance, so the first hit of a document is
+        // its nearest vector.
+        let mut seen = FxHashSet::default();
+        let mut results = Vec::with_capacity(top_k.min(hits.len()));
+        for (node, distance) in hits {
+            let id = self.document_id(node);
+            if seen.insert(id) {
+                results.push((id, distance));
+                if results.len() == top_k {
+                    break;
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Sums, over `queries`, the distance to the nearest indexed vector of
+    /// document `id`. Returns `None` if the document has no vector.
+    fn max_sim(
+        &self,
+        index: &HnswIndex,
+        queries: &[&[f32]],
+        id: u64,
+    ) -> Result<Option<f32>, DBError> {
+        let mut nearest = vec![f32::INFINITY; queries.len()];
+        'nodes: for ordinal in 0..MAX_DOCUMENT_VECTORS {
+            let Ok(node) = self.node_id(id, ordinal) else {
+                break;
+            };
+            for (query, nearest) in queries.iter().zip(nearest.iter_mut()) {
+                match index.distance(query, node) {
+                    Ok(distance) => *nearest = nearest.min(distance),
+                    Err(HnswError::NotFound { .. }) => break 'nodes,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        if nearest.iter().any(|distance| distance.is_infinite()) {
+            return Ok(None);
+        }
+        Ok(Some(nearest.into_iter().sum()))
+    }
+
+    /// Returns `true` if the index stores quantized vectors and is
+    /// configured with `exact_rerank`: its candidates should be re-scored
+    /// against the document vectors with [`Hnsw::exact_score`].
+    pub fn exact_rerank(&self) -> bool {
+        let graph = self.graph();
+        graph.index.config().exact_rerank && graph.index.is_quantized()
+    }
+
+    /// Scores a document by its vectors with the metric of this index, the
+    /// way searches rank it: the exact distance between the query and the
+    /// nearest vector, summed over the query vectors for a multi-vector
+    /// query. Returns `None` if `vectors` is empty.
+    pub fn exact_score(
+        &self,
+        queries: &[&[f32]],
+        vectors: &[Cow<'_, Vector>],
+    ) -> Result<Option<f32>, DBError> {
+        if vectors.is_empty() {
+            return Ok(None);
+        }
+
+        let metric = self.graph().index.config().distance_metric;
+        let mut score = 0.0;
+        for query in queries {
+            let mut nearest = f32::INFINITY;
+            for vector in vectors {
+                nearest = nearest.min(metric.compute_mixed(query, vector)?);
+            }
+            score += nearest;
+        }
+        Ok(Some(score))
+    }
+
+    /// Re-selects up to `limit` documents from `hits` with maximal marginal
+    /// relevance (MMR).
+    ///
+    /// `hits` are `(document_id, distance)` pairs sorted by ascending query
+    /// distance, as returned by the searches of this index; `query_count` is
+    /// the number of query vectors their distances were summed over. Each
+    /// step picks the candidate maximizing
+    /// `lambda * relevance - (1 - lambda) * similarity`, where relevance is
+    /// the negated (per query vector) query distance and similarity the
+    /// negated distance to the nearest document already selected. Document
+    /// distances are computed from the stored vectors with the metric of
+    /// this index; a multi-vector document is as near as its nearest vector.
+    ///
+    /// The selected hits keep their query distances, in selection order.
+    pub fn select_mmr(
+        &self,
+        hits: Vec<(u64, f32)>,
+        query_count: usize,
+        lambda: f32,
+        limit: usize,
+    ) -> Result<Vec<(u64, f32)>, DBError> {
+        if hits.len() <= 1 || limit == 0 {
+            let mut hits = hits;
+            hits.truncate(limit);
+            return Ok(hits);
+        }
+
+        let graph = self.graph();
+        let scale = query_count.max(1) as f32;
+        let mut candidates = Vec::with_capacity(hits.len());
+        for (id, distance) in hits {
+            let nodes = self.indexed_nodes(&graph.index, id);
+            if !nodes.is_empty() {
+                // The distance to the nearest selected document, infinite
+                // while nothing is selected.
+                candidates.push((id, distance, nodes, f32::INFINITY));
+            }
+        }
+
+        let mut selected = Vec::with_capacity(limit.min(candidates.len()));
+        while selected.len() < limit && !candidates.is_empty() {
+            let score = |&(_, distance, _, nearest): &(u64, f32, Vec<u64>, f32)| {
+                let relevance = -lambda * distance / scale;
+                if nearest.is_infinite() {
+                    relevance
+                } else {
+                    relevance + (1.0 - lambda) * nearest
+                }
+            };
+            // Ties go to the earlier, more relevant candidate.
+            let mut best = 0;
+            for (i, candidate) in candidates.iter().enumerate().skip(1) {
+                if score(candidate) > score(&candidates[best]) {
+                    best = i;
+                }
+            }
+
+            let (id, distance, nodes, _) = candidates.remove(best);
+            for (_, _, other, nearest) in candidates.iter_mut() {
+                for a in &nodes {
+                    for b in other.iter() {
+                        *nearest = nearest.min(graph.index.distance_between(*a, *b)?);
+                    }
+                }
+            }
+            selected.push((id, distance));
+        }
+        Ok(selected)
+    }
+
+    /// Returns the graph nodes holding the vectors of document `id`.
+    fn indexed_nodes(&self, index: &HnswIndex, id: u64) -> Vec<u64> {
+        let mut nodes = Vec::new();
+        for ordinal in 0..MAX_DOCUMENT_VECTORS {
+            let Ok(node) = self.node_id(id, ordinal) else {
+                break;
+            };
+            if index.get_node_with(node, |_| ()).is_err() {
+                break;
+            }
+            nodes.push(node);
+        }
+        nodes
     }
 
-    /// Searches for nearest vectors, returning an empty result on search errors.
+    /// Searches for nearest documents, returning an empty result on search
+    /// errors.
     pub fn search(&self, query: &[f32], top_k: usize) -> Vec<(u64, f32)> {
         self.try_search(query, top_k).unwrap_or_default()
     }
 }
 
+/// An online rebuild of an [`Hnsw`] index, see [`Hnsw::begin_rebuild`].
+///
+/// Dropping it without [`HnswRebuild::commit`] stops recording changes; the
+/// objects of the new generation are left for [`HnswRebuild::abort`], the
+/// next rebuild or the next bootstrap to drop.
+pub struct HnswRebuild<'a> {
+    index: &'a Hnsw,
+    rebuilt: Option<Hnsw>,
+}
+
+impl HnswRebuild<'_> {
+    /// Returns the new graph being built.
+    pub fn rebuilt(&self) -> &Hnsw {
+        self.rebuilt.as_ref().expect("rebuilt index")
+    }
+
+    /// Returns the generation of the new graph.
+    pub fn generation(&self) -> u64 {
+        self.rebuilt().generation()
+    }
+
+    /// Returns the ids of the documents mutated in the serving index since
+    /// the rebuild began or the previous call, and restarts the record.
+    pub fn take_changes(&self) -> BTreeSet<u64> {
+        self.index
+            .rebuild_log
+            .lock()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Swaps the new graph in and returns the generation it replaced.
+    ///
+    /// Searches already running finish on the old graph. The caller records
+    /// the new generation in the collection metadata and drops the old one
+    /// with [`Hnsw::drop_generation`] once that is durable.
+    pub fn commit(mut self) -> u64 {
+        let rebuilt = self.rebuilt.take().expect("rebuilt index");
+        let graph = rebuilt.graph.into_inner();
+        let old = std::mem::replace(&mut *self.index.graph.write(), graph);
+        old.generation
+    }
+
+    /// Discards the rebuild and the objects of the new generation.
+    pub async fn abort(mut self) {
+        if let Some(rebuilt) = self.rebuilt.take() {
+            *self.index.rebuild_log.lock() = None;
+            rebuilt.drop_data().await;
+        }
+    }
+}
+
+impl Drop for HnswRebuild<'_> {
+    fn drop(&mut self) {
+        *self.index.rebuild_log.lock() = None;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -353,7 +1004,6 @@ mod tests {
         PutMultipartOptions, PutOptions, PutPayload, PutResult, Result as ObjectStoreResult,
         memory::InMemory, path::Path,
     };
-    use parking_lot::Mutex;
     use std::fmt;
 
     /// In-memory object store with a one-shot path-targeted PUT failure and a
@@ -481,6 +1131,10 @@ mod tests {
         }
     }
 
+    fn embedding_field() -> Fe {
+        Fe::new("embedding".to_string(), Ft::Vector).unwrap()
+    }
+
     async fn fault_index() -> (Hnsw, Storage, Arc<FailPutStore>) {
         let object_store = Arc::new(FailPutStore::default());
         let storage = Storage::connect(
@@ -493,9 +1147,8 @@ mod tests {
         )
         .await
         .unwrap();
-        let field = Fe::new("embedding".to_string(), Ft::Vector).unwrap();
         let index = Hnsw::new(
-            &field,
+            &embedding_field(),
             HnswConfig {
                 dimension: 2,
                 ..Default::default()
@@ -525,25 +1178,27 @@ mod tests {
         // set nor the tombstone set references.
         storage
             .put_bytes(
-                &Hnsw::node_path("embedding", 99),
+                &Hnsw::node_path("embedding", 0, 99),
                 Bytes::from_static(b"orphan"),
                 PutMode::Overwrite,
             )
             .await
             .unwrap();
 
-        let reopened = Hnsw::bootstrap("embedding".to_string(), storage.clone())
+        let reopened = Hnsw::bootstrap_field(&embedding_field(), storage.clone(), 0)
             .await
             .unwrap();
         assert_eq!(reopened.search(&[1.0, 1.0], 1), vec![(1, 0.0)]);
         assert!(matches!(
-            storage.fetch_bytes(&Hnsw::node_path("embedding", 99)).await,
+            storage
+                .fetch_bytes(&Hnsw::node_path("embedding", 0, 99))
+                .await,
             Err(DBError::NotFound { .. })
         ));
         // The referenced blob survives the sweep.
         assert!(
             storage
-                .fetch_bytes(&Hnsw::node_path("embedding", 1))
+                .fetch_bytes(&Hnsw::node_path("embedding", 0, 1))
                 .await
                 .is_ok()
         );
@@ -553,7 +1208,7 @@ mod tests {
         assert!(index.has_pending_flush());
         assert!(index.flush(4).await.unwrap());
         assert!(!index.has_pending_flush());
-        let reloaded = Hnsw::bootstrap("embedding".to_string(), storage.clone())
+        let reloaded = Hnsw::bootstrap_field(&embedding_field(), storage.clone(), 0)
             .await
             .unwrap();
         assert_eq!(reloaded.search(&[1.0, 1.0], 1), vec![(1, 0.0)]);
@@ -580,7 +1235,7 @@ mod tests {
 
         // Drop the writer state conceptually and bootstrap only from the
         // durable object-store image left at the selected crash boundary.
-        let reopened = Hnsw::bootstrap("embedding".to_string(), storage.clone())
+        let reopened = Hnsw::bootstrap_field(&embedding_field(), storage.clone(), 0)
             .await
             .unwrap();
         assert_eq!(!reopened.search(&[1.0, 1.0], 1).is_empty(), visible);
@@ -591,12 +1246,12 @@ mod tests {
     async fn node_put_failure_does_not_publish_ids_or_metadata() {
         let (index, storage, object_store) = fault_index().await;
         let old_ids = storage
-            .fetch_bytes(&Hnsw::ids_path("embedding"))
+            .fetch_bytes(&Hnsw::ids_path("embedding", 0))
             .await
             .unwrap()
             .0;
         let old_metadata = storage
-            .fetch_bytes(&Hnsw::metadata_path("embedding"))
+            .fetch_bytes(&Hnsw::metadata_path("embedding", 0))
             .await
             .unwrap()
             .0;
@@ -607,7 +1262,7 @@ mod tests {
         assert!(object_store.put_suffixes()[0].ends_with("n_1.cbor"));
         assert_eq!(
             storage
-                .fetch_bytes(&Hnsw::ids_path("embedding"))
+                .fetch_bytes(&Hnsw::ids_path("embedding", 0))
                 .await
                 .unwrap()
                 .0,
@@ -615,13 +1270,13 @@ mod tests {
         );
         assert_eq!(
             storage
-                .fetch_bytes(&Hnsw::metadata_path("embedding"))
+                .fetch_bytes(&Hnsw::metadata_path("embedding", 0))
                 .await
                 .unwrap()
                 .0,
             old_metadata
         );
-        let crashed = Hnsw::bootstrap("embedding".to_string(), storage.clone())
+        let crashed = Hnsw::bootstrap_field(&embedding_field(), storage.clone(), 0)
             .await
             .unwrap();
         assert!(crashed.search(&[1.0, 1.0], 1).is_empty());
@@ -633,12 +1288,12 @@ mod tests {
     async fn ids_put_failure_leaves_metadata_at_previous_commit() {
         let (index, storage, object_store) = fault_index().await;
         let old_ids = storage
-            .fetch_bytes(&Hnsw::ids_path("embedding"))
+            .fetch_bytes(&Hnsw::ids_path("embedding", 0))
             .await
             .unwrap()
             .0;
         let old_metadata = storage
-            .fetch_bytes(&Hnsw::metadata_path("embedding"))
+            .fetch_bytes(&Hnsw::metadata_path("embedding", 0))
             .await
             .unwrap()
             .0;
@@ -651,7 +1306,7 @@ mod tests {
         assert!(puts[1].ends_with("ids.cbor"));
         assert_eq!(
             storage
-                .fetch_bytes(&Hnsw::ids_path("embedding"))
+                .fetch_bytes(&Hnsw::ids_path("embedding", 0))
                 .await
                 .unwrap()
                 .0,
@@ -659,13 +1314,13 @@ mod tests {
         );
         assert_eq!(
             storage
-                .fetch_bytes(&Hnsw::metadata_path("embedding"))
+                .fetch_bytes(&Hnsw::metadata_path("embedding", 0))
                 .await
                 .unwrap()
                 .0,
             old_metadata
         );
-        let crashed = Hnsw::bootstrap("embedding".to_string(), storage.clone())
+        let crashed = Hnsw::bootstrap_field(&embedding_field(), storage.clone(), 0)
             .await
             .unwrap();
         assert!(crashed.search(&[1.0, 1.0], 1).is_empty());
@@ -677,7 +1332,7 @@ mod tests {
     async fn metadata_put_failure_is_last_and_retryable() {
         let (index, storage, object_store) = fault_index().await;
         let old_metadata = storage
-            .fetch_bytes(&Hnsw::metadata_path("embedding"))
+            .fetch_bytes(&Hnsw::metadata_path("embedding", 0))
             .await
             .unwrap()
             .0;
@@ -691,7 +1346,7 @@ mod tests {
         assert!(puts[2].ends_with("meta.cbor"));
         assert_eq!(
             storage
-                .fetch_bytes(&Hnsw::metadata_path("embedding"))
+                .fetch_bytes(&Hnsw::metadata_path("embedding", 0))
                 .await
                 .unwrap()
                 .0,
@@ -701,7 +1356,7 @@ mod tests {
         // Nodes and ids are already durable. Loading with the previous empty
         // metadata self-repairs its stale entry point instead of pruning the
         // live node, and the original writer can still retry the metadata CAS.
-        let crashed = Hnsw::bootstrap("embedding".to_string(), storage.clone())
+        let crashed = Hnsw::bootstrap_field(&embedding_field(), storage.clone(), 0)
             .await
             .unwrap();
         assert_eq!(crashed.search(&[1.0, 1.0], 1), vec![(1, 0.0)]);
@@ -718,7 +1373,7 @@ mod tests {
             .insert(2, vec![bf16::from_f32(2.0), bf16::from_f32(2.0)], 4)
             .unwrap();
         assert_retry_recovers(&index, &storage).await;
-        let recovered = Hnsw::bootstrap("embedding".to_string(), storage)
+        let recovered = Hnsw::bootstrap_field(&embedding_field(), storage, 0)
             .await
             .unwrap();
         assert_eq!(recovered.search(&[2.0, 2.0], 1), vec![(2, 0.0)]);
@@ -739,7 +1394,7 @@ mod tests {
             index.flush(4).await.is_err(),
             "stale CAS token must remain a conflict",
         );
-        let reopened = Hnsw::bootstrap("embedding".to_string(), storage)
+        let reopened = Hnsw::bootstrap_field(&embedding_field(), storage, 0)
             .await
             .unwrap();
         assert_eq!(reopened.search(&[1.0, 1.0], 1), vec![(1, 0.0)]);
@@ -758,9 +1413,100 @@ mod tests {
             index.flush(4).await.is_err(),
             "stale CAS token must remain a conflict",
         );
-        let reopened = Hnsw::bootstrap("embedding".to_string(), storage)
+        let reopened = Hnsw::bootstrap_field(&embedding_field(), storage, 0)
             .await
             .unwrap();
         assert_eq!(reopened.search(&[1.0, 1.0], 1), vec![(1, 0.0)]);
     }
+
+    #[tokio::test]
+    async fn multi_vector_nodes_map_back_to_documents() {
+        let storage = Storage::connect(
+            "hnsw_multi_vector_tests".to_string(),
+            Arc::new(InMemory::new()),
+            StorageConfig::default(),
+        )
+        .await
+        .unwrap();
+        let field = Fe::new("chunks".to_string(), Ft::Array(vec![Ft::Vector])).unwrap();
+        let index = Hnsw::new(
+            &field,
+            HnswConfig {
+                dimension: 2,
+                ..Default::default()
+            },
+            storage,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(index.multi_vector());
+
+        let vector = |x: f32, y: f32| vec![bf16::from_f32(x), bf16::from_f32(y)];
+        index
+            .insert_vectors(7, vec![vector(1.0, 0.0), vector(0.0, 1.0)], 2)
+            .unwrap();
+        index.insert(8, vector(1.0, 1.0), 2).unwrap();
+        assert_eq!(index.stats().num_elements, 3);
+        assert_eq!(index.search(&[0.0, 1.0], 2), vec![(7, 0.0), (8, 1.0)]);
+
+        let mut allowed = Treemap::new();
+        allowed.add(8);
+        assert_eq!(
+            index.try_search_filtered(&[0.0, 1.0], 2, &allowed).unwrap(),
+            vec![(8, 1.0)]
+        );
+
+        assert!(index.insert(1 << 48, vector(1.0, 1.0), 2).is_err());
+        assert!(index.remove(7, 3));
+        assert!(!index.remove(7, 3));
+        assert_eq!(index.stats().num_elements, 1);
+    }
+
+    #[tokio::test]
+    async fn rebuild_records_changes_until_committed() {
+        let storage = Storage::connect(
+            "hnsw_rebuild_tests".to_string(),
+            Arc::new(InMemory::new()),
+            StorageConfig::default(),
+        )
+        .await
+        .unwrap();
+        let field = embedding_field();
+        let config = HnswConfig {
+            dimension: 2,
+            ..Default::default()
+        };
+        let index = Hnsw::new(&field, config, storage.clone(), 1).await.unwrap();
+        let vector = |x: f32| vec![bf16::from_f32(x), bf16::from_f32(1.0)];
+        index.insert(1, vector(1.0), 1).unwrap();
+        index.insert(2, vector(2.0), 1).unwrap();
+
+        let rebuild = index.begin_rebuild(&field, 2).await.unwrap();
+        assert!(index.begin_rebuild(&field, 2).await.is_err());
+        assert_eq!(rebuild.generation(), 1);
+        rebuild
+            .rebuilt()
+            .insert_batch(vec![(1, vec![vector(1.0)]), (2, vec![vector(2.0)])], 2)
+            .unwrap();
+        index.remove(2, 3);
+        index.insert(3, vector(3.0), 3).unwrap();
+        assert_eq!(rebuild.take_changes(), BTreeSet::from([2, 3]));
+        assert!(rebuild.take_changes().is_empty());
+
+        // Dropping an uncommitted rebuild stops the record.
+        drop(rebuild);
+        index.remove(3, 4);
+        let rebuild = index.begin_rebuild(&field, 4).await.unwrap();
+        assert!(rebuild.take_changes().is_empty());
+        rebuild.rebuilt().insert(1, vector(1.0), 4).unwrap();
+        rebuild.rebuilt().flush(4).await.unwrap();
+        assert_eq!(rebuild.commit(), 0);
+        assert_eq!(index.generation(), 1);
+        assert_eq!(index.search(&[2.0, 1.0], 2), vec![(1, 1.0)]);
+
+        index.drop_generation(0).await;
+        let reopened = Hnsw::bootstrap_field(&field, storage, 1).await.unwrap();
+        assert_eq!(reopened.search(&[2.0, 1.0], 2), vec![(1, 1.0)]);
+    }
 }
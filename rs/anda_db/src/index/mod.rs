@@ -1,14 +1,19 @@
-use anda_db_schema::{Document, Fv, Json, Vector, bf16};
+use anda_db_schema::{
+    Document, FieldKey, Ft, Fv, Json, Schema, SchemaError, SparseVector, Vector, as_wildcard_map,
+    bf16,
+};
 use cbor2::to_canonical_vec;
 use std::borrow::Cow;
 
 mod bm25;
 mod btree;
 mod hnsw;
+mod sparse;
 
 pub use bm25::*;
 pub use btree::*;
 pub use hnsw::*;
+pub use sparse::*;
 
 const MAX_SEARCHABLE_TEXT_DEPTH: usize = 64;
 const MAX_SEARCHABLE_TEXT_NODES: usize = 16_384;
@@ -23,18 +28,20 @@ const MAX_SEARCHABLE_TEXT_FRAGMENTS: usize = 4_096;
 pub trait IndexHooks: Send + Sync {
     /// Returns the value to insert into a B-tree index for `doc`.
     ///
-    /// The default implementation returns a borrowed single-field value or a
+    /// The default implementation returns a borrowed single-field value (or
+    /// the value at a nested field path, see [`field_path_value`]) or a
     /// deterministic CBOR byte key for multi-field virtual indexes.
     fn btree_index_value<'a>(&self, index: &BTree, doc: &'a Document) -> Option<Cow<'a, Fv>> {
         let fields = index.virtual_field();
         match fields {
             [] => None,
-            [name] => doc.get_field(name).map(Cow::Borrowed),
+            [name] => field_path_value(doc, name),
             _ => {
-                let mut vals: Vec<Option<&Fv>> = Vec::with_capacity(fields.len());
+                let mut vals: Vec<Option<Cow<Fv>>> = Vec::with_capacity(fields.len());
                 for name in fields {
-                    vals.push(doc.get_field(name));
+                    vals.push(field_path_value(doc, name));
                 }
+                let vals: Vec<Option<&Fv>> = vals.iter().map(|v| v.as_deref()).collect();
 
                 virtual_field_value(&vals).map(Cow::Owned)
             }
@@ -44,9 +51,31 @@ pub trait IndexHooks: Send + Sync {
     /// Returns searchable text to insert into a BM25 index for `doc`.
     ///
     /// The default implementation extracts text from all configured fields and
-    /// joins multiple text fragments with newline separators.
+    /// joins multiple text fragments with newline separators. For an index
+    /// with field boosts (BM25F), the text of each field is extracted on its
+    /// own and the field texts are joined with [`FIELD_SEPARATOR`]; text
+    /// without separators is scored as the first field.
     fn bm25_index_value<'a>(&self, index: &BM25, doc: &'a Document) -> Option<Cow<'a, str>> {
         let fields = index.virtual_field();
+        if !index.field_boosts().is_empty() {
+            let texts: Vec<Option<Cow<str>>> = fields
+                .iter()
+                .map(|name| virtual_searchable_text(&[doc.get_field(name)]))
+                .collect();
+            if texts.iter().all(Option::is_none) {
+                return None;
+            }
+
+            let mut text = String::new();
+            for (i, field_text) in texts.iter().enumerate() {
+                if i > 0 {
+                    text.push(FIELD_SEPARATOR);
+                }
+                text.push_str(field_text.as_deref().unwrap_or_default());
+            }
+            return Some(Cow::Owned(text));
+        }
+
         let mut vals: Vec<Option<&Fv>> = Vec::with_capacity(fields.len());
         for name in fields {
             vals.push(doc.get_field(name));
@@ -60,21 +89,51 @@ pub trait IndexHooks: Send + Sync {
     /// The default implementation accepts native vector fields and the compact
     /// array-of-bf16-bits representation used by serialized documents.
     fn hnsw_index_value<'a>(&self, index: &Hnsw, doc: &'a Document) -> Option<Cow<'a, Vector>> {
+        doc.get_field(index.field_name()).and_then(vector_value)
+    }
+
+    /// Returns every vector to insert into an HNSW index for `doc`.
+    ///
+    /// The default implementation returns the elements of the `Array` field
+    /// of a multi-vector index (each in either representation accepted by
+    /// [`IndexHooks::hnsw_index_value`]), and the single value of
+    /// `hnsw_index_value` otherwise.
+    fn hnsw_index_values<'a>(&self, index: &Hnsw, doc: &'a Document) -> Vec<Cow<'a, Vector>> {
+        if !index.multi_vector() {
+            return self.hnsw_index_value(index, doc).into_iter().collect();
+        }
+
         match doc.get_field(index.field_name()) {
-            Some(Fv::Vector(vector)) => Some(Cow::Borrowed(vector)),
-            Some(Fv::Array(values)) => {
-                let vector = values
-                    .iter()
-                    .map(|value| match value {
-                        Fv::U64(bits) => u16::try_from(*bits).ok().map(bf16::from_bits),
-                        _ => None,
-                    })
-                    .collect::<Option<Vec<_>>>()?;
-                Some(Cow::Owned(vector))
-            }
-            _ => None,
+            Some(Fv::Array(values)) => values.iter().filter_map(vector_value).collect(),
+            _ => Vec::new(),
         }
     }
+
+    /// Returns the sparse vector to insert into a sparse index for `doc`.
+    ///
+    /// The default implementation reads the indexed `SparseVector` field.
+    fn sparse_index_value(&self, index: &Sparse, doc: &Document) -> Option<SparseVector> {
+        doc.get_field(index.field_name())
+            .and_then(Fv::sparse_vector)
+    }
+}
+
+/// Decodes a native vector value or its compact array-of-bf16-bits form.
+fn vector_value(value: &Fv) -> Option<Cow<'_, Vector>> {
+    match value {
+        Fv::Vector(vector) => Some(Cow::Borrowed(vector)),
+        Fv::Array(values) => {
+            let vector = values
+                .iter()
+                .map(|value| match value {
+                    Fv::U64(bits) => u16::try_from(*bits).ok().map(bf16::from_bits),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(Cow::Owned(vector))
+        }
+        _ => None,
+    }
 }
 
 /// Builds the stable index name for a single-field or multi-field index.
@@ -87,6 +146,115 @@ pub fn from_virtual_field_name(name: &str) -> Vec<String> {
     name.split('-').map(String::from).collect()
 }
 
+/// Separates the segments of a nested field path such as `metadata.user_id`.
+///
+/// Field names never contain it (see `validate_field_name`), so a B-tree
+/// index field is a path exactly when it contains the separator.
+pub const FIELD_PATH_SEPARATOR: char = '.';
+
+/// Returns the top-level field a field path starts at.
+pub fn root_field(path: &str) -> &str {
+    path.split(FIELD_PATH_SEPARATOR).next().unwrap_or(path)
+}
+
+/// Resolves the type of the value at a field path.
+///
+/// The first segment names a schema field; each following segment selects a
+/// key of a `Map` field (any key of a wildcard map) or, once a `Json` field
+/// is reached, a key of a JSON object. A path into a `Json` field resolves
+/// to `Json`: its values are JSON scalars, indexed as a
+/// [`JsonKey`].
+pub fn field_path_type(schema: &Schema, path: &str) -> Result<Ft, SchemaError> {
+    let mut segments = path.split(FIELD_PATH_SEPARATOR);
+    let root = segments.next().unwrap_or(path);
+    let mut ft = schema.get_field_or_err(root)?.r#type().clone();
+    for segment in segments {
+        let inner = match ft {
+            Ft::Option(inner) => *inner,
+            other => other,
+        };
+        ft = match inner {
+            Ft::Json => Ft::Json,
+            Ft::Map(map) => match as_wildcard_map(&map) {
+                Some((_, value)) => value.clone(),
+                None => map_key(segment)
+                    .find_map(|key| map.get(&key).cloned())
+                    .ok_or_else(|| {
+                        SchemaError::Validation(format!(
+                            "field path {path:?}: key {segment:?} not found in {:?}",
+                            Ft::Map(map.clone())
+                        ))
+                    })?,
+            },
+            other => {
+                return Err(SchemaError::Validation(format!(
+                    "field path {path:?}: cannot select {segment:?} in {other:?}"
+                )));
+            }
+        };
+    }
+    if path.contains(FIELD_PATH_SEPARATOR) && is_json(&ft) {
+        return Ok(Ft::Json);
+    }
+    Ok(ft)
+}
+
+fn is_json(ft: &Ft) -> bool {
+    match ft {
+        Ft::Json => true,
+        Ft::Option(inner) => is_json(inner),
+        _ => false,
+    }
+}
+
+/// Returns the value at a field path of `doc` (see [`field_path_type`]).
+///
+/// A plain field name returns the field value. JSON strings, numbers and
+/// booleans are returned as `Text`, `I64` (`U64` above `i64::MAX`, `F64` if
+/// not an integer) and `Bool`; other JSON values are not indexable and yield
+/// `None`.
+pub fn field_path_value<'a>(doc: &'a Document, path: &str) -> Option<Cow<'a, Fv>> {
+    let mut segments = path.split(FIELD_PATH_SEPARATOR);
+    let mut value = doc.get_field(segments.next()?)?;
+    let mut json = None;
+    for segment in segments {
+        match (json, value) {
+            (Some(parent), _) => json = Some(Json::get(parent, segment)?),
+            (None, Fv::Map(map)) => value = map_key(segment).find_map(|key| map.get(&key))?,
+            (None, Fv::Json(parent)) => json = Some(parent.get(segment)?),
+            _ => return None,
+        }
+    }
+    if json.is_none() {
+        if !path.contains(FIELD_PATH_SEPARATOR) {
+            return Some(Cow::Borrowed(value));
+        }
+        match value {
+            Fv::Json(leaf) => json = Some(leaf),
+            _ => return Some(Cow::Borrowed(value)),
+        }
+    }
+    match json? {
+        Json::String(s) => Some(Cow::Owned(Fv::Text(s.clone()))),
+        Json::Bool(v) => Some(Cow::Owned(Fv::Bool(*v))),
+        Json::Number(n) => {
+            let value = match (n.as_i64(), n.as_u64()) {
+                (Some(v), _) => Fv::I64(v),
+                (None, Some(v)) => Fv::U64(v),
+                (None, None) => Fv::F64(n.as_f64()?),
+            };
+            Some(Cow::Owned(value))
+        }
+        _ => None,
+    }
+}
+
+/// The map keys a path segment can denote: the text key, and the integer
+/// key when the segment parses as one.
+fn map_key(segment: &str) -> impl Iterator<Item = FieldKey> {
+    std::iter::once(FieldKey::from(segment)).chain(segment.parse::<i64>().ok().map(FieldKey::from))
+}
+
 /// Builds a deterministic byte key for a multi-field B-tree index.
 ///
 /// Each field value, including `None`, is encoded as deterministic CBOR and
@@ -0,0 +1,307 @@
+use anda_db_tfs::{BucketObject, SparseIndex};
+use bytes::Bytes;
+use parking_lot::RwLock;
+use std::{collections::BTreeSet, fmt::Debug, hash::Hash, sync::Arc};
+use tokio::sync::Mutex;
+
+pub use anda_db_tfs::{SparseConfig, SparseError, SparseMetadata, SparseStats};
+
+use crate::{
+    error::DBError,
+    schema::{BoxError, DocumentId, FieldEntry, SparseVector},
+    storage::{ObjectVersion, PutMode, Storage},
+};
+
+/// Collection-level wrapper around a sparse vector (dot-product) index.
+///
+/// Mirrors [`BM25`](super::BM25): the wrapper keeps the index name, storage
+/// namespace, and the metadata object version used as the CAS token of the
+/// manifest commit. A `Precondition` conflict propagates and the collection
+/// poisons its handle; reopening rebuilds the wrapper from durable objects.
+pub struct Sparse {
+    name: String,
+    index: SparseIndex,
+    storage: Storage, // 与 Collection 共享同一个 Storage 实例
+    metadata_version: RwLock<ObjectVersion>,
+    /// Serializes complete object-store flushes for this wrapper.
+    flush_gate: Arc<Mutex<()>>,
+}
+
+impl Debug for Sparse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SparseIndex({})", self.name)
+    }
+}
+
+impl PartialEq for &Sparse {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for &Sparse {}
+impl Hash for &Sparse {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl Sparse {
+    pub(crate) fn dir_path(name: &str) -> String {
+        format!("sparse_indexes/{name}/")
+    }
+
+    fn metadata_path(name: &str) -> String {
+        format!("sparse_indexes/{name}/meta.cbor")
+    }
+
+    fn bucket_path(name: &str, object: BucketObject) -> String {
+        format!(
+            "sparse_indexes/{name}/b_{}_{}.cbor",
+            object.bucket_id, object.generation
+        )
+    }
+
+    /// Creates a new persisted sparse index for `field`.
+    ///
+    /// The initial metadata object is written immediately; the caller is
+    /// responsible for backfilling existing documents before publishing the
+    /// index in collection metadata.
+    pub async fn new(field: &FieldEntry, storage: Storage, now_ms: u64) -> Result<Self, DBError> {
+        let name = field.name().to_string();
+        let config = SparseConfig {
+            bucket_overload_size: storage.bucket_overload_size(),
+        };
+        let index = SparseIndex::new(name.clone(), Some(config));
+        let mut data = Vec::new();
+        index
+            .flush(&mut data, now_ms, |_, _| std::future::ready(Ok(())))
+            .await?;
+        // Overwrite any leftover files from a crashed creation or a
+        // previously removed index, like `BM25::new`.
+        let ver = storage
+            .put_bytes(
+                &Sparse::metadata_path(&name),
+                data.into(),
+                PutMode::Overwrite,
+            )
+            .await?;
+        Ok(Self {
+            name,
+            index,
+            storage,
+            metadata_version: RwLock::new(ver),
+            flush_gate: Arc::new(Mutex::new(())),
+        })
+    }
+
+    pub(crate) async fn drop_data(&self) {
+        if let Err(err) = self
+            .storage
+            .drop_prefix(&Sparse::dir_path(&self.name))
+            .await
+        {
+            log::warn!(
+                action = "Sparse::drop_data",
+                index = self.name;
+                "Failed to drop sparse index data: {err:?}",
+            );
+        }
+    }
+
+    /// Loads an existing sparse index from persisted metadata and bucket objects.
+    pub async fn bootstrap(name: String, storage: Storage) -> Result<Self, DBError> {
+        let (metadata, ver) = storage.fetch_bytes(&Sparse::metadata_path(&name)).await?;
+        let n = Arc::new(name.clone());
+        let s = Arc::new(storage.clone());
+        let index = SparseIndex::load_all(&metadata[..], async move |object| {
+            let path = Sparse::bucket_path(n.clone().as_str(), object);
+            match s.clone().fetch_bytes(&path).await {
+                Ok((data, _)) => Ok(Some(data.into())),
+                Err(DBError::NotFound { .. }) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+        .await?;
+
+        Ok(Self {
+            name,
+            index,
+            storage,
+            metadata_version: RwLock::new(ver),
+            flush_gate: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Persists dirty metadata and buckets with the manifest commit protocol
+    /// of [`SparseIndex::flush_with`], then deletes the objects the new
+    /// manifest no longer references.
+    ///
+    /// Returns `true` when any object was written.
+    pub async fn flush(&self, now_ms: u64) -> Result<bool, DBError> {
+        let _flush_guard = self.flush_gate.clone().lock_owned().await;
+        let metadata_path = Sparse::metadata_path(&self.name);
+        let outcome = self
+            .index
+            .flush_with(
+                now_ms,
+                move |data: Vec<u8>| {
+                    let metadata_path = metadata_path.clone();
+                    async move {
+                        let expected = { self.metadata_version.read().clone() };
+                        let version = self
+                            .storage
+                            .put_bytes(
+                                &metadata_path,
+                                Bytes::from(data),
+                                PutMode::Update(expected.into()),
+                            )
+                            .await
+                            .map_err(BoxError::from)?;
+                        *self.metadata_version.write() = version;
+                        Ok(())
+                    }
+                },
+                |object: BucketObject, data: Vec<u8>| async move {
+                    let path = Sparse::bucket_path(&self.name, object);
+                    let _ = self
+                        .storage
+                        .put_bytes(&path, Bytes::from(data), PutMode::Overwrite)
+                        .await?;
+                    Ok(())
+                },
+            )
+            .await?;
+
+        for object in &outcome.obsolete {
+            let path = Sparse::bucket_path(&self.name, *object);
+            match self.storage.delete(&path).await {
+                Ok(()) | Err(DBError::NotFound { .. }) => {}
+                Err(err) => {
+                    log::warn!(
+                        action = "Sparse::flush",
+                        index = self.name,
+                        bucket = object.bucket_id,
+                        generation = object.generation;
+                        "Failed to delete obsolete bucket object: {err:?}",
+                    );
+                }
+            }
+        }
+
+        Ok(outcome.saved)
+    }
+
+    /// Returns whether metadata or buckets have in-memory changes to flush.
+    pub fn has_pending_flush(&self) -> bool {
+        self.index.has_dirty_buckets() || self.index.has_pending_metadata_flush()
+    }
+
+    /// Returns the stable index name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the indexed field name.
+    pub fn field_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a snapshot of sparse index runtime statistics.
+    pub fn stats(&self) -> SparseStats {
+        self.index.stats()
+    }
+
+    /// Returns a snapshot of sparse index metadata.
+    pub fn metadata(&self) -> SparseMetadata {
+        self.index.metadata()
+    }
+
+    /// Inserts the sparse vector indexed for `id`.
+    ///
+    /// Vectors without any finite, non-zero weight are ignored because they
+    /// can never score.
+    pub fn insert(
+        &self,
+        id: DocumentId,
+        vector: &SparseVector,
+        now_ms: u64,
+    ) -> Result<(), DBError> {
+        match self.index.insert(id, vector, now_ms) {
+            Ok(()) => Ok(()),
+            Err(SparseError::EmptyVector { .. }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Inserts the sparse vectors of many documents.
+    ///
+    /// On error the documents inserted so far stay in the index; callers roll
+    /// back per document.
+    pub fn insert_batch(
+        &self,
+        items: &[(DocumentId, SparseVector)],
+        now_ms: u64,
+    ) -> Result<(), DBError> {
+        for (id, vector) in items {
+            self.insert(*id, vector, now_ms)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the sparse vector indexed for `id`.
+    pub fn remove(&self, id: DocumentId, vector: &SparseVector, now_ms: u64) -> bool {
+        self.index.remove(id, vector, now_ms)
+    }
+
+    /// Erases `ids` from the index without needing their vectors; see
+    /// [`SparseIndex::purge_ids`].
+    ///
+    /// Returns the number of ids that were actually present in the index.
+    pub fn purge_ids(&self, ids: &BTreeSet<DocumentId>, now_ms: u64) -> usize {
+        self.index.purge_ids(ids, now_ms)
+    }
+
+    /// Searches the index and returns `(document_id, score)` pairs ranked by
+    /// dot product with `query`.
+    pub fn search(&self, query: &SparseVector, top_k: usize) -> Vec<(u64, f32)> {
+        self.index.search(query, top_k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Ft;
+    use crate::storage::StorageConfig;
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn test_flush_and_bootstrap_roundtrip() -> Result<(), DBError> {
+        let storage = Storage::connect(
+            "sparse_roundtrip".to_string(),
+            Arc::new(InMemory::new()),
+            StorageConfig::default(),
+        )
+        .await?;
+        let field = FieldEntry::new("terms".to_string(), Ft::SparseVector)?;
+        let sparse = Sparse::new(&field, storage.clone(), 1).await?;
+        let doc1 = SparseVector::from([("rust".to_string(), 1.5), ("db".to_string(), 0.5)]);
+        let doc2 = SparseVector::from([("db".to_string(), 2.0)]);
+        sparse.insert(1, &doc1, 2)?;
+        sparse.insert(2, &doc2, 2)?;
+        sparse.insert(3, &SparseVector::new(), 2)?;
+        assert!(sparse.flush(3).await?);
+        assert!(!sparse.has_pending_flush());
+
+        let query = SparseVector::from([("db".to_string(), 1.0)]);
+        let reopened = Sparse::bootstrap("terms".to_string(), storage.clone()).await?;
+        assert_eq!(reopened.search(&query, 10), vec![(2, 2.0), (1, 0.5)]);
+
+        assert!(reopened.remove(2, &doc2, 4));
+        assert!(reopened.flush(5).await?);
+        let reopened = Sparse::bootstrap("terms".to_string(), storage).await?;
+        assert_eq!(reopened.search(&query, 10), vec![(1, 0.5)]);
+        Ok(())
+    }
+}
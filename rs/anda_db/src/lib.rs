@@ -29,15 +29,20 @@
 //! Tantivy-backed tokenization (including Jieba) is **not** optional: this
 //! crate always depends on `anda_db_tfs` with its `full` feature, because
 //! [`index::bm25`] re-exports `default_tokenizer`/`jieba_tokenizer`
-//! unconditionally and every [`collection::Collection`] installs
-//! `default_tokenizer()` when it opens. The `tantivy` and `tantivy-jieba`
-//! feature flags that used to be listed here gated nothing — they toggled a
-//! second, unused copy of the dependency — and were removed; enabling them is
-//! now a manifest error rather than a silent no-op.
+//! unconditionally and every [`collection::Collection`] builds its recorded
+//! tokenizer definition, or `default_tokenizer()`, when it opens. The
+//! `tantivy` and `tantivy-jieba` feature flags that used to be listed here
+//! gated nothing — they toggled a second, unused copy of the dependency — and
+//! were removed; enabling them is now a manifest error rather than a silent
+//! no-op.
 //!
 //! See also the technical guide in `docs/anda_db.md` for architecture,
 //! lifecycle, indexing, and operational guidance.
 
+/// Multi-collection atomic write batches.
+pub mod batch;
+/// Bulk loading with deferred index building.
+pub mod bulk;
 /// Collection-level document storage, indexing, and query execution.
 pub mod collection;
 /// Database-level lifecycle and collection management.
@@ -50,8 +55,32 @@ pub mod index;
 pub mod query;
 /// Schema types re-exported from `anda_db_schema`.
 pub mod schema;
+/// Log-structured segment storage for collection documents.
+pub mod segment;
+/// Point-in-time database snapshots, backup, and restore.
+pub mod snapshot;
 /// Object-store-backed persistence, compression, and cached I/O.
 pub mod storage;
+/// Streaming document export and import.
+pub mod transfer;
+
+/// Runs CPU-bound index work, such as a multi-threaded batch insert, from
+/// async code without stalling the runtime.
+///
+/// On a multi-threaded Tokio runtime the current worker hands its other
+/// tasks off via [`tokio::task::block_in_place`] while `f` runs. Elsewhere
+/// (a current-thread runtime, or no runtime) `f` runs inline, as
+/// `block_in_place` is not available there.
+pub(crate) fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    use tokio::runtime::{Handle, RuntimeFlavor};
+
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
 
 /// Returns the current Unix timestamp in milliseconds.
 ///
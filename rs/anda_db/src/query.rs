@@ -1,9 +1,14 @@
+use async_trait::async_trait;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::index::BTree;
+use crate::schema::{BoxError, Document};
 
 pub use anda_db_btree::RangeQuery;
-pub use anda_db_schema::{Fv, bf16};
-pub use anda_db_tfs::BM25Params;
+pub use anda_db_schema::{Fv, SparseVector, bf16};
+pub use anda_db_tfs::{BM25Params, HighlightOptions, Snippet};
 
 const MAX_FILTER_DEPTH: usize = 64;
 const MAX_FILTER_NODES: usize = 4_096;
@@ -32,6 +37,24 @@ pub struct Query {
     ///
     /// Defaults to 10 if not specified.
     pub limit: Option<usize>,
+
+    /// Field projection.
+    ///
+    /// When specified, returned documents only carry the listed fields plus
+    /// `_id`. Unknown field names are rejected.
+    pub fields: Option<Vec<String>>,
+
+    /// Result ordering.
+    ///
+    /// When specified, results are ordered by the values of a single-field
+    /// B-Tree index instead of by relevance or id.
+    pub order_by: Option<OrderBy>,
+
+    /// Opaque continuation cursor returned by the previous page of an
+    /// ordered query (see `Collection::search_page`).
+    ///
+    /// Only valid together with the `order_by` it was issued for.
+    pub cursor: Option<String>,
 }
 
 impl Query {
@@ -44,6 +67,78 @@ impl Query {
     }
 }
 
+/// Ordering of query results by an indexed field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderBy {
+    /// The field to order by.
+    ///
+    /// The field must have a single-field B-Tree index built. Documents
+    /// without a value for the field are not returned.
+    pub field: String,
+
+    /// Whether to order from the largest value down.
+    ///
+    /// Documents with equal values are ordered by `_id`, in the same
+    /// direction. Defaults to false (ascending) when omitted.
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// Position of the last result of an ordered page.
+///
+/// Travels as the opaque `Query::cursor` string; callers pass back the cursor
+/// of [`SearchPage`] unchanged rather than build one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderCursor {
+    /// The `OrderBy::field` the cursor was issued for.
+    pub field: String,
+
+    /// The `OrderBy::descending` the cursor was issued for.
+    pub descending: bool,
+
+    /// The order key of the last result.
+    pub key: Fv,
+
+    /// The id of the last result.
+    pub id: u64,
+}
+
+impl OrderCursor {
+    /// Encodes the cursor as base64url deterministic CBOR.
+    pub fn encode(&self) -> Option<String> {
+        BTree::to_cursor(self)
+    }
+
+    /// Decodes a cursor and checks that it was issued for `order_by`.
+    pub fn decode_for(cursor: &str, order_by: &OrderBy) -> Result<Self, String> {
+        let cursor: Self = BTree::from_cursor(&Some(cursor.to_string()))
+            .map_err(|err| format!("invalid query cursor: {err}"))?
+            .ok_or_else(|| "invalid query cursor".to_string())?;
+        if cursor.field != order_by.field || cursor.descending != order_by.descending {
+            return Err("query cursor was issued for a different order_by".to_string());
+        }
+        Ok(cursor)
+    }
+}
+
+/// One page of query results.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchPage<T> {
+    /// The results of this page.
+    pub items: Vec<T>,
+
+    /// Cursor for the next page, set when `Query::order_by` is specified and
+    /// more results follow.
+    pub cursor: Option<String>,
+
+    /// Whether a search ordered by `Query::order_by` hit the cap of
+    /// [`Collection::MAX_ORDERED_SEARCH_HITS`](crate::collection::Collection::MAX_ORDERED_SEARCH_HITS)
+    /// relevance hits in some index, so that less relevant matches may be
+    /// missing from the ordered pages.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
 /// Configuration for full-text and vector search operations.
 ///
 /// Supports both text-based and vector-based search with optional reranking.
@@ -62,11 +157,37 @@ pub struct Search {
     /// fails if no index matches the query dimension.
     pub vector: Option<Vec<f32>>,
 
+    /// A multi-vector query for late-interaction (ColBERT-style MaxSim)
+    /// search.
+    ///
+    /// Each candidate document is scored by summing, over these vectors, the
+    /// distance to its nearest indexed vector, so it pairs with multi-vector
+    /// HNSW indexes (an `Array` of vectors field). All vectors must have the
+    /// same dimension, which selects the HNSW indexes like `vector` does.
+    /// Mutually exclusive with `vector`.
+    pub vectors: Option<Vec<Vec<f32>>>,
+
+    /// A sparse query vector (term → weight) for dot-product search.
+    ///
+    /// Every sparse index of the collection is searched, each contributing
+    /// one ranked list that is fused with the text and vector results. The
+    /// search fails if the collection has no sparse index.
+    pub sparse_vector: Option<SparseVector>,
+
     /// Parameters for the BM25 ranking algorithm.
     ///
     /// Customizes the behavior of the full-text search ranking.
     pub bm25_params: Option<BM25Params>,
 
+    /// Per-field boosts of BM25F text indexes, by field name.
+    ///
+    /// Overrides, for this query, the boosts configured with
+    /// `BM25Config::field_boosts` when the index was created; fields without
+    /// an entry keep their configured boost. Every field must belong to a
+    /// BM25 index with field boosts, and every boost must be finite and
+    /// non-negative.
+    pub field_boosts: Option<BTreeMap<String, f32>>,
+
     /// Configuration for reranking search results.
     ///
     /// When specified, applies the Reciprocal Rank Fusion algorithm
@@ -74,14 +195,189 @@ pub struct Search {
     /// Defaults to `RRFReranker` with k=60 if not specified.
     pub reranker: Option<RRFReranker>,
 
+    /// Strategy for fusing the ranked lists of the text, vector and sparse
+    /// searches into one ranking.
+    ///
+    /// [`Fusion::Linear`] and [`Fusion::Distribution`] fuse the normalized
+    /// scores with per-source weights instead of the ranks. Cannot be
+    /// combined with [`Search::reranker`]; when both are omitted, RRF with
+    /// k=60 is used.
+    pub fusion: Option<Fusion>,
+
+    /// Per-source score thresholds.
+    ///
+    /// Hits that miss the threshold of their source are dropped before
+    /// fusion, whatever the reranker.
+    pub score_cutoffs: Option<ScoreCutoffs>,
+
+    /// Second-stage reranking with a [`DocumentReranker`] registered on the
+    /// collection.
+    ///
+    /// The fused results are cut to the candidate pool instead of
+    /// `Query::limit`, scored by the reranker, and the best `limit` of them
+    /// are returned. Cannot be combined with `Query::order_by`.
+    pub rerank: Option<RerankOptions>,
+
+    /// Maximal marginal relevance (MMR) diversification of the vector
+    /// results.
+    ///
+    /// Each HNSW index is searched for an oversized candidate pool, from
+    /// which `Query::limit` documents are re-selected to balance relevance
+    /// to the query against similarity to the documents already picked.
+    /// Similarities are computed from the vectors stored in the index, so
+    /// no document is fetched.
+    pub mmr: Option<MmrOptions>,
+
+    /// Highlighted snippets of the text fields matching [`Search::text`].
+    ///
+    /// Only returned by `Collection::search_hits`, in
+    /// [`SearchHit::highlight`]; other searches ignore it.
+    pub highlight: Option<Highlight>,
+
     /// Whether to use logical search operators.
     ///
-    /// When true, the search text can include logical operators (AND, OR, NOT).
-    /// Defaults to false when omitted.
+    /// When true, the search text can include logical operators (AND, OR, NOT),
+    /// quoted phrases and `NEAR/n` proximity; phrase and proximity matching
+    /// needs a BM25 index with positions. Defaults to false when omitted.
     #[serde(default)]
     pub logical_search: bool,
 }
 
+impl Search {
+    /// Checks that every boost of [`Search::field_boosts`] is finite and
+    /// non-negative.
+    pub fn validate_field_boosts(&self) -> Result<(), String> {
+        for (field, boost) in self.field_boosts.iter().flatten() {
+            if !boost.is_finite() || *boost < 0.0 {
+                return Err(format!(
+                    "field boost of {field:?} must be finite and non-negative, got {boost}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the validated fusion strategy: [`Search::fusion`], or RRF
+    /// with [`Search::reranker`] or its default k.
+    pub fn resolve_fusion(&self) -> Result<Fusion, String> {
+        let fusion = match (&self.reranker, &self.fusion) {
+            (Some(_), Some(_)) => {
+                return Err("reranker and fusion cannot be combined".to_string());
+            }
+            (Some(reranker), None) => Fusion::Rrf(reranker.clone()),
+            (None, fusion) => fusion.clone().unwrap_or_default(),
+        };
+        fusion.validate()?;
+        Ok(fusion)
+    }
+}
+
+/// Selects the fields highlighted for [`Search::highlight`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Highlight {
+    /// Fields to highlight, each of which must belong to a BM25 index.
+    ///
+    /// Defaults to every field of the collection's BM25 indexes.
+    #[serde(default)]
+    pub fields: Vec<String>,
+
+    /// Size and number of the fragments extracted from each field.
+    #[serde(flatten)]
+    pub options: HighlightOptions,
+}
+
+/// Highlighted snippets of a document, by field name. Fields without a
+/// match are omitted.
+pub type Highlights = BTreeMap<String, Vec<Snippet>>;
+
+/// One result of `Collection::search_hits`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit<T> {
+    /// The matching document.
+    pub doc: T,
+
+    /// The fused relevance score, as returned by
+    /// `Collection::search_with_scores`.
+    pub score: f32,
+
+    /// Highlighted snippets, set when [`Search::highlight`] is requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<Highlights>,
+}
+
+/// Selects a registered [`DocumentReranker`] for [`Search::rerank`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RerankOptions {
+    /// The name the reranker was registered under with
+    /// `Collection::set_reranker`.
+    pub name: String,
+
+    /// Number of fused candidates handed to the reranker.
+    ///
+    /// Defaults to 50; it is raised to `Query::limit` when smaller and
+    /// clamped to `Collection::MAX_SEARCH_LIMIT`.
+    pub candidates: Option<usize>,
+
+    /// The query text given to the reranker.
+    ///
+    /// Defaults to `Search::text`; required when the search has no text.
+    pub query: Option<String>,
+}
+
+/// Options of [`Search::mmr`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MmrOptions {
+    /// Trade-off between relevance and diversity, in `[0, 1]`.
+    ///
+    /// 1 ranks by relevance alone and 0 by diversity alone. Defaults to 0.5.
+    pub lambda: f32,
+
+    /// Number of nearest documents each HNSW index is searched for.
+    ///
+    /// Defaults to ten times `Query::limit`; it is raised to `Query::limit`
+    /// when smaller and clamped to `Collection::MAX_SEARCH_LIMIT`.
+    pub candidates: Option<usize>,
+}
+
+impl Default for MmrOptions {
+    fn default() -> Self {
+        Self {
+            lambda: 0.5,
+            candidates: None,
+        }
+    }
+}
+
+impl MmrOptions {
+    /// Checks that `lambda` is within `[0, 1]`.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.lambda) {
+            return Err(format!(
+                "mmr lambda must be within [0, 1], got {}",
+                self.lambda
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A second-stage reranker, such as a local cross-encoder model, that scores
+/// search candidates against the query text.
+///
+/// Implementations are registered on a collection by name with
+/// `Collection::set_reranker`, like `IndexHooks`, and selected per query
+/// through [`Search::rerank`]. Registrations are not persisted: install them
+/// every time the collection is opened.
+#[async_trait]
+pub trait DocumentReranker: Send + Sync {
+    /// Scores `docs` against `query`.
+    ///
+    /// Returns one finite score per document, in the order of `docs`; higher
+    /// is more relevant.
+    async fn rerank(&self, query: &str, docs: &[Document]) -> Result<Vec<f32>, BoxError>;
+}
+
 /// Filter conditions for query results.
 ///
 /// Provides a flexible way to define complex filtering logic
@@ -118,6 +414,87 @@ impl Filter {
     }
 }
 
+/// An aggregation over the keys of a B-Tree index.
+///
+/// Aggregations are answered from the index alone: each key contributes the
+/// size of its posting list, and no document is loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Aggregate {
+    /// The B-Tree index to aggregate over.
+    pub field: String,
+
+    /// What to compute besides the count and the min/max keys.
+    pub aggregation: Aggregation,
+
+    /// Restricts the aggregation to the documents matching this filter.
+    pub filter: Option<Filter>,
+
+    /// Maximum number of buckets to return.
+    ///
+    /// Defaults to and is clamped to `Collection::MAX_SEARCH_LIMIT`.
+    pub limit: Option<usize>,
+}
+
+impl Aggregate {
+    /// Validates query-side structural complexity before recursive execution.
+    pub fn validate_complexity(&self) -> Result<(), String> {
+        if let Some(filter) = &self.filter {
+            filter.validate_complexity()?;
+        }
+        Ok(())
+    }
+}
+
+/// The buckets an [`Aggregate`] computes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aggregation {
+    /// Only the count and the min/max keys, no buckets.
+    Stats,
+
+    /// One bucket per distinct key, in ascending key order.
+    Terms,
+
+    /// One bucket per `interval`-wide range of an integer key, in ascending
+    /// order. A bucket key is the lower bound of its range, and empty ranges
+    /// are omitted.
+    Histogram {
+        /// The bucket width. Must be positive.
+        interval: u64,
+    },
+}
+
+/// The result of an [`Aggregate`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AggregateResult {
+    /// Number of matching (key, document) pairs. For array and map fields a
+    /// document counts once per indexed element.
+    pub count: u64,
+
+    /// The smallest matching key.
+    pub min: Option<Fv>,
+
+    /// The largest matching key.
+    pub max: Option<Fv>,
+
+    /// The buckets, empty for [`Aggregation::Stats`].
+    pub buckets: Vec<AggregateBucket>,
+
+    /// Whether buckets past `Aggregate::limit` were dropped. `count`, `min`
+    /// and `max` always cover every match.
+    pub truncated: bool,
+}
+
+/// One bucket of an [`AggregateResult`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateBucket {
+    /// The bucket key: the distinct key for [`Aggregation::Terms`], the lower
+    /// bound of the range for [`Aggregation::Histogram`].
+    pub key: Fv,
+
+    /// Number of matching documents in the bucket.
+    pub count: u64,
+}
+
 #[derive(Default)]
 struct ComplexityStats {
     nodes: usize,
@@ -219,7 +596,7 @@ fn validate_range_complexity(
 /// This algorithm combines multiple ranked lists (e.g., from text and vector searches)
 /// into a single, unified ranking by considering the position of each document
 /// across all lists.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RRFReranker {
     /// The constant factor in the RRF formula.
     ///
@@ -273,19 +650,325 @@ impl RRFReranker {
             }
         }
 
-        let mut results: Vec<(u64, f32)> = scores.into_iter().collect();
-        // Tie-break equal scores by ascending document id so the ordering is
-        // deterministic (hash-map iteration order would otherwise leak into
-        // the result and destabilize pagination).
-        results.sort_unstable_by(|a, b| {
-            b.1.partial_cmp(&a.1)
-                .unwrap_or(std::cmp::Ordering::Equal)
-                .then_with(|| a.0.cmp(&b.0))
-        });
-        results
+        sort_fused(scores)
+    }
+}
+
+/// Sorts fused scores descending.
+///
+/// Equal scores are tie-broken by ascending document id so the ordering is
+/// deterministic (hash-map iteration order would otherwise leak into the
+/// result and destabilize pagination).
+fn sort_fused(scores: FxHashMap<u64, f32>) -> Vec<(u64, f32)> {
+    let mut results: Vec<(u64, f32)> = scores.into_iter().collect();
+    results.sort_unstable_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    results
+}
+
+/// The retrieval path a ranked list of a hybrid search comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RankSource {
+    /// BM25 full-text search; scores are BM25 scores (higher is better).
+    Text,
+    /// HNSW vector search; scores are distances (lower is better).
+    Vector,
+    /// Sparse vector search; scores are dot products (higher is better).
+    Sparse,
+}
+
+/// One ranked list of a hybrid search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedList {
+    /// Where the list comes from, which decides how its scores read.
+    pub source: RankSource,
+
+    /// `(document_id, score)` pairs in ranking order, with the raw score of
+    /// the source.
+    pub hits: Vec<(u64, f32)>,
+}
+
+impl RankedList {
+    /// Returns the scores of the hits oriented so that higher is better:
+    /// vector distances are negated.
+    fn relevances(&self) -> Vec<f32> {
+        self.hits
+            .iter()
+            .map(|(_, score)| match self.source {
+                RankSource::Vector => -score,
+                RankSource::Text | RankSource::Sparse => *score,
+            })
+            .collect()
+    }
+}
+
+/// Per-source score thresholds applied to the ranked lists before fusion.
+///
+/// Thresholds compare the raw scores of each source: BM25 scores and sparse
+/// dot products are lower bounds, vector distances an upper bound.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScoreCutoffs {
+    /// Minimum BM25 score of a text hit.
+    pub min_text_score: Option<f32>,
+
+    /// Maximum distance of a vector hit.
+    pub max_vector_distance: Option<f32>,
+
+    /// Minimum dot product of a sparse vector hit.
+    pub min_sparse_score: Option<f32>,
+}
+
+impl ScoreCutoffs {
+    /// Checks that every threshold is a finite number.
+    pub fn validate(&self) -> Result<(), String> {
+        for threshold in [
+            self.min_text_score,
+            self.max_vector_distance,
+            self.min_sparse_score,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !threshold.is_finite() {
+                return Err(format!(
+                    "score cutoffs must be finite numbers, got {threshold}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the hits of `list` that miss the threshold of its source.
+    pub fn retain(&self, list: &mut RankedList) {
+        match list.source {
+            RankSource::Text => {
+                if let Some(min) = self.min_text_score {
+                    list.hits.retain(|(_, score)| *score >= min);
+                }
+            }
+            RankSource::Vector => {
+                if let Some(max) = self.max_vector_distance {
+                    list.hits.retain(|(_, distance)| *distance <= max);
+                }
+            }
+            RankSource::Sparse => {
+                if let Some(min) = self.min_sparse_score {
+                    list.hits.retain(|(_, score)| *score >= min);
+                }
+            }
+        }
+    }
+}
+
+/// Per-source weights of a score-based fusion.
+///
+/// Each defaults to 1.0; a weight of 0 ignores the source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FusionWeights {
+    /// Weight of the BM25 text lists.
+    pub text: f32,
+
+    /// Weight of the HNSW vector lists.
+    pub vector: f32,
+
+    /// Weight of the sparse vector lists.
+    pub sparse: f32,
+}
+
+impl Default for FusionWeights {
+    fn default() -> Self {
+        Self {
+            text: 1.0,
+            vector: 1.0,
+            sparse: 1.0,
+        }
     }
 }
 
+impl FusionWeights {
+    fn weight(&self, source: RankSource) -> f32 {
+        match source {
+            RankSource::Text => self.text,
+            RankSource::Vector => self.vector,
+            RankSource::Sparse => self.sparse,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for weight in [self.text, self.vector, self.sparse] {
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(format!(
+                    "fusion weights must be finite and non-negative, got {weight}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How [`LinearFusion`] maps the scores of a list onto a common scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScoreNormalization {
+    /// Scales scores to `[0, 1]` by the minimum and maximum of the list. A
+    /// list whose scores are all equal maps to 1.
+    #[default]
+    MinMax,
+
+    /// Standardizes scores by the mean and standard deviation of the list.
+    /// A list whose scores are all equal maps to 0.
+    ZScore,
+}
+
+/// Normalized linear fusion: the weighted sum, over the lists a document
+/// appears in, of its normalized score.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinearFusion {
+    /// Normalization applied to each list.
+    pub normalization: ScoreNormalization,
+
+    /// Per-source weights.
+    pub weights: FusionWeights,
+}
+
+/// Distribution-based score fusion (DBSF).
+///
+/// Each list is scaled to `[0, 1]` between its mean minus and plus three
+/// standard deviations, which keeps one outlier from compressing every other
+/// score the way min-max scaling does; the weighted scaled scores are summed.
+/// A list whose scores are all equal maps to 1.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DistributionFusion {
+    /// Per-source weights.
+    pub weights: FusionWeights,
+}
+
+/// Fuses the ranked lists of a hybrid search into one ranking.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Fusion {
+    /// Reciprocal Rank Fusion over the ranks; scores are ignored.
+    Rrf(RRFReranker),
+
+    /// Weighted sum of min-max or z-score normalized scores.
+    Linear(LinearFusion),
+
+    /// Weighted sum of distribution-scaled scores.
+    Distribution(DistributionFusion),
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::Rrf(RRFReranker::default())
+    }
+}
+
+impl From<RRFReranker> for Fusion {
+    fn from(reranker: RRFReranker) -> Self {
+        Fusion::Rrf(reranker)
+    }
+}
+
+impl Fusion {
+    /// Checks the reranker parameters: a positive RRF `k` and finite,
+    /// non-negative fusion weights.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Fusion::Rrf(rrf) => {
+                if !rrf.k.is_finite() || rrf.k <= 0.0 {
+                    return Err(format!("RRF k must be positive, got {}", rrf.k));
+                }
+                Ok(())
+            }
+            Fusion::Linear(fusion) => fusion.weights.validate(),
+            Fusion::Distribution(fusion) => fusion.weights.validate(),
+        }
+    }
+
+    /// Fuses `lists` and returns `(document_id, fused_score)` pairs sorted by
+    /// descending score.
+    pub fn fuse(&self, lists: &[RankedList]) -> Vec<(u64, f32)> {
+        match self {
+            Fusion::Rrf(rrf) => {
+                let ranked: Vec<Vec<u64>> = lists
+                    .iter()
+                    .map(|list| list.hits.iter().map(|(id, _)| *id).collect())
+                    .collect();
+                rrf.rerank(&ranked)
+            }
+            Fusion::Linear(fusion) => fuse_scores(lists, &fusion.weights, |values| {
+                match fusion.normalization {
+                    ScoreNormalization::MinMax => min_max_normalize(values),
+                    ScoreNormalization::ZScore => z_score_normalize(values),
+                }
+            }),
+            Fusion::Distribution(fusion) => {
+                fuse_scores(lists, &fusion.weights, distribution_normalize)
+            }
+        }
+    }
+}
+
+fn fuse_scores(
+    lists: &[RankedList],
+    weights: &FusionWeights,
+    normalize: impl Fn(&[f32]) -> Vec<f32>,
+) -> Vec<(u64, f32)> {
+    let mut scores: FxHashMap<u64, f32> = FxHashMap::default();
+    for list in lists {
+        let weight = weights.weight(list.source);
+        let normalized = normalize(&list.relevances());
+        for ((id, _), score) in list.hits.iter().zip(normalized) {
+            *scores.entry(*id).or_insert(0.0) += weight * score;
+        }
+    }
+    sort_fused(scores)
+}
+
+fn min_max_normalize(values: &[f32]) -> Vec<f32> {
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+    values
+        .iter()
+        .map(|v| if range > 0.0 { (v - min) / range } else { 1.0 })
+        .collect()
+}
+
+fn mean_and_std(values: &[f32]) -> (f32, f32) {
+    let n = values.len().max(1) as f32;
+    let mean = values.iter().sum::<f32>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n;
+    (mean, variance.sqrt())
+}
+
+fn z_score_normalize(values: &[f32]) -> Vec<f32> {
+    let (mean, std) = mean_and_std(values);
+    values
+        .iter()
+        .map(|v| if std > 0.0 { (v - mean) / std } else { 0.0 })
+        .collect()
+}
+
+fn distribution_normalize(values: &[f32]) -> Vec<f32> {
+    let (mean, std) = mean_and_std(values);
+    let low = mean - 3.0 * std;
+    values
+        .iter()
+        .map(|v| {
+            if std > 0.0 {
+                ((v - low) / (6.0 * std)).clamp(0.0, 1.0)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -309,4 +992,136 @@ mod tests {
 
         assert!(filter.validate_complexity().is_err());
     }
+
+    #[test]
+    fn fusion_round_trips_and_excludes_the_rrf_reranker() {
+        let linear = Fusion::Linear(LinearFusion {
+            normalization: ScoreNormalization::ZScore,
+            weights: FusionWeights {
+                text: 0.3,
+                ..Default::default()
+            },
+        });
+        let json = serde_json::to_string(&linear).unwrap();
+        assert_eq!(serde_json::from_str::<Fusion>(&json).unwrap(), linear);
+        let partial: Fusion =
+            serde_json::from_str(r#"{"Distribution": {"weights": {"vector": 2}}}"#).unwrap();
+        assert_eq!(
+            partial,
+            Fusion::Distribution(DistributionFusion {
+                weights: FusionWeights {
+                    vector: 2.0,
+                    ..Default::default()
+                },
+            })
+        );
+
+        let search: Search = serde_json::from_str(r#"{"reranker": {"k": 10}}"#).unwrap();
+        assert_eq!(
+            search.resolve_fusion(),
+            Ok(Fusion::Rrf(RRFReranker::new(10.0)))
+        );
+        let search = Search {
+            fusion: Some(linear.clone()),
+            ..Default::default()
+        };
+        assert_eq!(search.resolve_fusion(), Ok(linear.clone()));
+        let search = Search {
+            reranker: Some(RRFReranker::default()),
+            fusion: Some(linear),
+            ..Default::default()
+        };
+        assert!(search.resolve_fusion().is_err());
+        assert_eq!(Search::default().resolve_fusion(), Ok(Fusion::default()));
+    }
+
+    #[test]
+    fn score_fusion_weights_normalized_scores() {
+        let lists = [
+            RankedList {
+                source: RankSource::Text,
+                hits: vec![(1, 12.0), (2, 6.0), (3, 0.0)],
+            },
+            RankedList {
+                source: RankSource::Vector,
+                hits: vec![(3, 0.1), (2, 0.5), (1, 0.9)],
+            },
+        ];
+
+        // Min-max: text maps 1 -> 1.0, 2 -> 0.5, 3 -> 0.0; distances map
+        // 3 -> 1.0, 2 -> 0.5, 1 -> 0.0.
+        let fused = Fusion::Linear(LinearFusion {
+            weights: FusionWeights {
+                text: 1.0,
+                vector: 3.0,
+                sparse: 1.0,
+            },
+            ..Default::default()
+        })
+        .fuse(&lists);
+        assert_eq!(fused, vec![(3, 3.0), (2, 2.0), (1, 1.0)]);
+
+        let fused = Fusion::Linear(LinearFusion {
+            weights: FusionWeights {
+                vector: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .fuse(&lists);
+        assert_eq!(
+            fused.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        // Z-scores of a symmetric list are centered on the middle hit.
+        let fused = Fusion::Linear(LinearFusion {
+            normalization: ScoreNormalization::ZScore,
+            ..Default::default()
+        })
+        .fuse(&lists[..1]);
+        assert_eq!(fused[1], (2, 0.0));
+        assert!((fused[0].1 + fused[2].1).abs() < 1e-6);
+
+        // DBSF scales into [0, 1] around the mean.
+        let fused = Fusion::Distribution(DistributionFusion::default()).fuse(&lists[..1]);
+        assert_eq!(fused[1], (2, 0.5));
+        assert!(fused.iter().all(|(_, score)| (0.0..=1.0).contains(score)));
+    }
+
+    #[test]
+    fn score_cutoffs_filter_each_source() {
+        let cutoffs = ScoreCutoffs {
+            min_text_score: Some(5.0),
+            max_vector_distance: Some(0.5),
+            min_sparse_score: None,
+        };
+        let mut text = RankedList {
+            source: RankSource::Text,
+            hits: vec![(1, 12.0), (2, 6.0), (3, 0.0)],
+        };
+        let mut vector = RankedList {
+            source: RankSource::Vector,
+            hits: vec![(3, 0.1), (2, 0.5), (1, 0.9)],
+        };
+        let mut sparse = RankedList {
+            source: RankSource::Sparse,
+            hits: vec![(1, 0.1)],
+        };
+        cutoffs.retain(&mut text);
+        cutoffs.retain(&mut vector);
+        cutoffs.retain(&mut sparse);
+        assert_eq!(text.hits, vec![(1, 12.0), (2, 6.0)]);
+        assert_eq!(vector.hits, vec![(3, 0.1), (2, 0.5)]);
+        assert_eq!(sparse.hits, vec![(1, 0.1)]);
+
+        assert!(
+            ScoreCutoffs {
+                min_text_score: Some(f32::NAN),
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
 }
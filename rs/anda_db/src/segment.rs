@@ -0,0 +1,1906 @@
+//! Log-structured document storage.
+//!
+//! In [`DocumentStorage::Segments`] mode a collection does not write one
+//! object per document and per mutation intent. Every document image,
+//! intent and removal is appended as a record to an immutable, size-bounded
+//! segment object (`segments/{sequence}.seg`), and concurrent writers share
+//! one PUT per group commit. An in-memory `key -> (segment, offset)` map
+//! locates the current record of every key. Each collection flush persists
+//! the entries changed since the previous flush as a manifest delta
+//! (`segments/deltas/{n}.cbor`); once the deltas add up to a sizable share
+//! of the map, the whole map is rewritten as the segment manifest
+//! (`segments/manifest.cbor`) and the deltas are dropped. On open the
+//! manifest and its deltas are loaded and the segments written after them
+//! are replayed.
+//!
+//! Compaction runs in the background ([`AndaDB::auto_flush`]), not on the
+//! flush path: checkpointed segments whose live bytes fall below
+//! [`SegmentConfig::compaction_threshold`] have their live records copied
+//! into a fresh segment and are deleted once the persisted manifest and
+//! deltas no longer reference them.
+//!
+//! [`AndaDB::auto_flush`]: crate::database::AndaDB::auto_flush
+use bytes::{BufMut, Bytes, BytesMut};
+use cbor2::{from_reader, to_writer};
+use futures::StreamExt;
+use moka::future::Cache;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    error::DBError,
+    schema::DocumentId,
+    storage::{ObjectVersion, PutMode, Storage},
+};
+
+/// How a collection persists its documents and mutation intents.
+///
+/// Fixed when the collection is created; it is stored in the collection
+/// metadata and reused on every open.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum DocumentStorage {
+    /// One object per document (`data/{id}.cbor`) and per mutation intent
+    /// (`mutation_intents/{sequence}.cbor`); every mutation is its own PUT.
+    #[default]
+    Objects,
+    /// Documents and mutation intents are appended to size-bounded segment
+    /// objects, and concurrent mutations share one PUT.
+    Segments(SegmentConfig),
+}
+
+/// Configuration of [`DocumentStorage::Segments`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SegmentConfig {
+    /// Target maximum size of one segment object in bytes. A group commit
+    /// larger than this is split across several segments; a single record
+    /// larger than this gets a segment of its own. Clamped to the storage's
+    /// `max_small_object_size`.
+    pub max_segment_size: usize,
+    /// How long a group-commit leader waits for more mutations to join its
+    /// PUT, in milliseconds. With `0` a mutation is written immediately;
+    /// mutations arriving while a PUT is in flight still share the next one.
+    pub group_commit_delay_ms: u64,
+    /// A checkpointed segment whose live bytes fall below this fraction of
+    /// its size is rewritten by compaction.
+    pub compaction_threshold: f64,
+    /// Maximum bytes of segment data kept in memory to serve reads.
+    /// 0 disables the cache.
+    pub cache_max_bytes: u64,
+}
+
+impl Default for SegmentConfig {
+    fn default() -> Self {
+        Self {
+            max_segment_size: 1024 * 1024,     // 1 MiB
+            group_commit_delay_ms: 0,          // No added latency
+            compaction_threshold: 0.5,         // Rewrite half-dead segments
+            cache_max_bytes: 64 * 1024 * 1024, // 64 MiB
+        }
+    }
+}
+
+/// Space accounting of a segment-backed collection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentStats {
+    /// Number of segment objects the current state references.
+    pub segments: usize,
+    /// Total size of those segments in bytes (uncompressed).
+    pub total_bytes: u64,
+    /// Bytes of records that are still current.
+    pub live_bytes: u64,
+    /// Segments copied and deleted by compaction so far.
+    pub compacted_segments: u64,
+}
+
+/// Identifies a record in a collection's document storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum RecordKey {
+    /// The stored image of a document.
+    Document(DocumentId),
+    /// A durable mutation intent, keyed by its sequence.
+    Intent(u64),
+}
+
+/// The two key spaces of [`RecordKey`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RecordKind {
+    Document,
+    Intent,
+}
+
+impl RecordKind {
+    const fn tag(self) -> u8 {
+        match self {
+            RecordKind::Document => 0,
+            RecordKind::Intent => 1,
+        }
+    }
+
+    /// Object prefix used by [`DocumentStorage::Objects`].
+    const fn prefix(self) -> &'static str {
+        match self {
+            RecordKind::Document => "data/",
+            RecordKind::Intent => "mutation_intents/",
+        }
+    }
+
+    fn key(self, raw: u64) -> RecordKey {
+        match self {
+            RecordKind::Document => RecordKey::Document(raw),
+            RecordKind::Intent => RecordKey::Intent(raw),
+        }
+    }
+}
+
+impl RecordKey {
+    fn kind(self) -> RecordKind {
+        match self {
+            RecordKey::Document(_) => RecordKind::Document,
+            RecordKey::Intent(_) => RecordKind::Intent,
+        }
+    }
+
+    fn raw(self) -> u64 {
+        match self {
+            RecordKey::Document(id) => id,
+            RecordKey::Intent(sequence) => sequence,
+        }
+    }
+
+    /// Object path of this key in [`DocumentStorage::Objects`] mode.
+    pub(crate) fn path(self) -> String {
+        match self {
+            RecordKey::Document(id) => format!("{}{id}.cbor", RecordKind::Document.prefix()),
+            RecordKey::Intent(sequence) => {
+                format!("{}{sequence:020}.cbor", RecordKind::Intent.prefix())
+            }
+        }
+    }
+}
+
+/// Where the current record of a key lives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Location {
+    segment: u64,
+    /// Byte offset of the record payload within the segment.
+    offset: u32,
+    len: u32,
+}
+
+impl Location {
+    /// The version handed out for conditional writes. Records are immutable,
+    /// so the location identifies one write exactly.
+    fn version(self) -> ObjectVersion {
+        ObjectVersion {
+            e_tag: Some(format!("{}:{}", self.segment, self.offset)),
+            version: None,
+        }
+    }
+
+    fn matches(self, version: &ObjectVersion) -> bool {
+        version.e_tag.as_deref() == Some(format!("{}:{}", self.segment, self.offset).as_str())
+    }
+}
+
+/// Persisted `key -> location` map. Rows are `(key, segment, offset, len)`
+/// tuples to keep large manifests compact.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SegmentManifest {
+    /// Highest segment sequence whose records are reflected below.
+    checkpoint: u64,
+    /// Size of every segment the rows may point into.
+    segments: BTreeMap<u64, u64>,
+    documents: Vec<(DocumentId, u64, u32, u32)>,
+    intents: Vec<(u64, u64, u32, u32)>,
+    compacted_segments: u64,
+    /// Number of the last [`SegmentManifestDelta`] folded into the rows
+    /// above; only later deltas apply on top.
+    #[serde(default)]
+    delta: u64,
+}
+
+/// A persisted `(segment, offset, len)` location.
+type LocationRow = (u64, u32, u32);
+
+/// The entries of the `key -> location` map changed since the previous
+/// manifest or delta. `None` removes a key or a segment.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SegmentManifestDelta {
+    /// Highest segment sequence whose records are reflected below.
+    checkpoint: u64,
+    segments: Vec<(u64, Option<u64>)>,
+    documents: Vec<(DocumentId, Option<LocationRow>)>,
+    intents: Vec<(u64, Option<LocationRow>)>,
+    compacted_segments: u64,
+}
+
+impl SegmentManifestDelta {
+    fn rows(&self) -> usize {
+        self.segments.len() + self.documents.len() + self.intents.len()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SegmentUsage {
+    size: u64,
+    live: u64,
+}
+
+#[derive(Default)]
+struct SegmentState {
+    documents: BTreeMap<DocumentId, Location>,
+    intents: BTreeMap<u64, Location>,
+    segments: BTreeMap<u64, SegmentUsage>,
+    /// Segments no current location points into that may still be
+    /// referenced by the persisted manifest; deleted after the next one.
+    retired: BTreeSet<u64>,
+    next_segment: u64,
+    checkpoint: u64,
+    /// Whether the state changed since the manifest was persisted.
+    dirty: bool,
+    compacted_segments: u64,
+    /// Keys and segment sizes changed since the manifest or the last delta
+    /// was persisted: the content of the next delta.
+    changed_keys: BTreeSet<RecordKey>,
+    changed_segments: BTreeSet<u64>,
+    /// Numbers of the manifest deltas in storage, including stale ones
+    /// already folded into the manifest.
+    deltas: BTreeSet<u64>,
+    /// Number of the last delta folded into the persisted manifest.
+    manifest_delta: u64,
+    /// Rows persisted in deltas since the manifest was last rewritten.
+    delta_rows: usize,
+}
+
+impl SegmentState {
+    fn map(&self, kind: RecordKind) -> &BTreeMap<u64, Location> {
+        match kind {
+            RecordKind::Document => &self.documents,
+            RecordKind::Intent => &self.intents,
+        }
+    }
+
+    fn map_mut(&mut self, kind: RecordKind) -> &mut BTreeMap<u64, Location> {
+        match kind {
+            RecordKind::Document => &mut self.documents,
+            RecordKind::Intent => &mut self.intents,
+        }
+    }
+
+    fn get(&self, key: RecordKey) -> Option<Location> {
+        self.map(key.kind()).get(&key.raw()).copied()
+    }
+
+    fn set_segment_size(&mut self, sequence: u64, size: u64) {
+        self.segments.entry(sequence).or_default().size = size;
+        self.changed_segments.insert(sequence);
+        self.dirty = true;
+    }
+
+    fn remove_segment(&mut self, sequence: u64) {
+        self.segments.remove(&sequence);
+        self.changed_segments.insert(sequence);
+        self.dirty = true;
+    }
+
+    /// Applies a persisted delta on top of the loaded manifest.
+    fn apply_delta(&mut self, delta: SegmentManifestDelta) {
+        for (sequence, size) in delta.segments {
+            match size {
+                Some(size) => self.set_segment_size(sequence, size),
+                None => self.remove_segment(sequence),
+            }
+        }
+        for (kind, rows) in [
+            (RecordKind::Document, delta.documents),
+            (RecordKind::Intent, delta.intents),
+        ] {
+            for (raw, location) in rows {
+                let location = location.map(|(segment, offset, len)| Location {
+                    segment,
+                    offset,
+                    len,
+                });
+                self.apply(kind.key(raw), location);
+            }
+        }
+        self.checkpoint = delta.checkpoint;
+        self.compacted_segments = delta.compacted_segments;
+    }
+
+    /// Collects the changes since the last persisted manifest or delta.
+    fn delta(&self, checkpoint: u64) -> SegmentManifestDelta {
+        let mut delta = SegmentManifestDelta {
+            checkpoint,
+            segments: self
+                .changed_segments
+                .iter()
+                .map(|sequence| {
+                    let size = self.segments.get(sequence).map(|usage| usage.size);
+                    (*sequence, size)
+                })
+                .collect(),
+            compacted_segments: self.compacted_segments,
+            ..Default::default()
+        };
+        for key in &self.changed_keys {
+            let location = self.get(*key).map(|l| (l.segment, l.offset, l.len));
+            match key {
+                RecordKey::Document(id) => delta.documents.push((*id, location)),
+                RecordKey::Intent(sequence) => delta.intents.push((*sequence, location)),
+            }
+        }
+        delta
+    }
+
+    /// Returns the whole map as a manifest that folds in every delta.
+    fn manifest(&self, checkpoint: u64) -> SegmentManifest {
+        let rows = |map: &BTreeMap<u64, Location>| {
+            map.iter()
+                .map(|(raw, l)| (*raw, l.segment, l.offset, l.len))
+                .collect::<Vec<_>>()
+        };
+        SegmentManifest {
+            checkpoint,
+            segments: self
+                .segments
+                .iter()
+                .map(|(sequence, usage)| (*sequence, usage.size))
+                .collect(),
+            documents: rows(&self.documents),
+            intents: rows(&self.intents),
+            compacted_segments: self.compacted_segments,
+            delta: self.last_delta(),
+        }
+    }
+
+    fn last_delta(&self) -> u64 {
+        self.deltas
+            .last()
+            .copied()
+            .unwrap_or_default()
+            .max(self.manifest_delta)
+    }
+
+    /// Points `key` at `location` (or removes it), keeping the live byte
+    /// accounting of both segments in step.
+    fn apply(&mut self, key: RecordKey, location: Option<Location>) {
+        let previous = match location {
+            Some(location) => self.map_mut(key.kind()).insert(key.raw(), location),
+            None => self.map_mut(key.kind()).remove(&key.raw()),
+        };
+        if let Some(previous) = previous
+            && let Some(usage) = self.segments.get_mut(&previous.segment)
+        {
+            usage.live = usage.live.saturating_sub(previous.len as u64);
+        }
+        if let Some(location) = location {
+            self.segments.entry(location.segment).or_default().live += location.len as u64;
+        }
+        self.changed_keys.insert(key);
+        self.dirty = true;
+    }
+}
+
+/// What a write expects of the key's current state.
+#[derive(Clone, Debug)]
+pub(crate) enum Condition {
+    /// Unconditional.
+    None,
+    /// The key must not exist (`PutMode::Create`).
+    Absent,
+    /// The key's current record must have this version (`PutMode::Update`).
+    Version(ObjectVersion),
+}
+
+/// One record write submitted to a group commit. `value: None` removes the
+/// key.
+#[derive(Clone, Debug)]
+pub(crate) struct WriteOp {
+    key: RecordKey,
+    value: Option<Bytes>,
+    condition: Condition,
+}
+
+struct Submission {
+    ticket: u64,
+    ops: Vec<WriteOp>,
+    reply: tokio::sync::oneshot::Sender<CommitResult>,
+}
+
+type CommitResult = Result<Vec<Option<Location>>, DBError>;
+
+#[derive(Default)]
+struct CommitQueue {
+    next_ticket: u64,
+    pending: Vec<Submission>,
+    /// Whether a leader task is running; it drains `pending` until empty.
+    leading: bool,
+}
+
+/// Segment header; the trailing byte is the format version.
+const SEGMENT_MAGIC: &[u8; 8] = b"ADBSEG\x00\x01";
+/// kind (1) + tombstone flag (1) + key (8) + payload length (4).
+const RECORD_HEADER_LEN: usize = 14;
+
+/// A segment under construction by a group-commit leader.
+struct SegmentBuilder {
+    sequence: u64,
+    buf: BytesMut,
+    /// `(key, location)` of every record in write order.
+    records: Vec<(RecordKey, Option<Location>)>,
+    tickets: Vec<u64>,
+}
+
+impl SegmentBuilder {
+    fn new(sequence: u64) -> Self {
+        let mut buf = BytesMut::with_capacity(4096);
+        buf.put_slice(SEGMENT_MAGIC);
+        Self {
+            sequence,
+            buf,
+            records: Vec::new(),
+            tickets: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn append(&mut self, key: RecordKey, value: Option<&[u8]>) -> Option<Location> {
+        self.buf.put_u8(key.kind().tag());
+        self.buf.put_u8(u8::from(value.is_none()));
+        self.buf.put_u64_le(key.raw());
+        let payload = value.unwrap_or_default();
+        self.buf.put_u32_le(payload.len() as u32);
+        let offset = self.buf.len() as u32;
+        self.buf.put_slice(payload);
+        let location = value.map(|_| Location {
+            segment: self.sequence,
+            offset,
+            len: payload.len() as u32,
+        });
+        self.records.push((key, location));
+        location
+    }
+}
+
+/// Decodes a segment into `(key, location)` records in write order.
+fn parse_segment(sequence: u64, data: &[u8]) -> Result<Vec<(RecordKey, Option<Location>)>, String> {
+    if !data.starts_with(SEGMENT_MAGIC) {
+        return Err("invalid segment header".to_string());
+    }
+    let mut records = Vec::new();
+    let mut pos = SEGMENT_MAGIC.len();
+    while pos < data.len() {
+        if data.len() - pos < RECORD_HEADER_LEN {
+            return Err(format!("truncated record header at offset {pos}"));
+        }
+        let kind = match data[pos] {
+            0 => RecordKind::Document,
+            1 => RecordKind::Intent,
+            tag => return Err(format!("unknown record kind {tag} at offset {pos}")),
+        };
+        let tombstone = data[pos + 1] != 0;
+        let raw = u64::from_le_bytes(data[pos + 2..pos + 10].try_into().expect("8 bytes"));
+        let len = u32::from_le_bytes(data[pos + 10..pos + 14].try_into().expect("4 bytes"));
+        let offset = pos + RECORD_HEADER_LEN;
+        if data.len() - offset < len as usize {
+            return Err(format!("truncated record payload at offset {offset}"));
+        }
+        let location = (!tombstone).then_some(Location {
+            segment: sequence,
+            offset: offset as u32,
+            len,
+        });
+        records.push((kind.key(raw), location));
+        pos = offset + len as usize;
+    }
+    Ok(records)
+}
+
+/// Append-only segment log backing [`DocumentStorage::Segments`].
+///
+/// Writers enqueue their records and wait for their outcome. The first
+/// writer of an idle log spawns a leader task; after the group commit delay
+/// it takes the commit lock, drains the whole queue, checks every write's
+/// condition against the current map, writes the accepted records as one
+/// new segment (more if the group exceeds the size bound) and replies to
+/// each queued writer. It keeps leading until the queue is empty.
+///
+/// Conditions are evaluated against the in-memory map, so a segment log
+/// assumes a single writing process, like the collection's other in-memory
+/// state.
+pub(crate) struct SegmentStore {
+    storage: Storage,
+    config: SegmentConfig,
+    state: parking_lot::RwLock<SegmentState>,
+    queue: parking_lot::Mutex<CommitQueue>,
+    commit_lock: tokio::sync::Mutex<()>,
+    cache: Option<Cache<u64, Bytes>>,
+    /// Set when a segment PUT failed and its object could not be removed:
+    /// the log may hold records no writer saw succeed, so only a reopen
+    /// (which replays the log) can tell what is durable.
+    failed: AtomicBool,
+}
+
+impl SegmentStore {
+    const SEGMENT_PREFIX: &'static str = "segments/";
+    const MANIFEST_PATH: &'static str = "segments/manifest.cbor";
+    const DELTA_PREFIX: &'static str = "segments/deltas/";
+    /// Deltas persisted before the manifest is rewritten, bounding the
+    /// objects an open has to load.
+    const MAX_MANIFEST_DELTAS: usize = 64;
+    /// Delta rows always tolerated before the manifest is rewritten; above
+    /// that, the deltas may grow to half the map.
+    const MIN_DELTA_ROWS: usize = 4096;
+
+    fn segment_path(sequence: u64) -> String {
+        format!("{}{sequence:020}.seg", Self::SEGMENT_PREFIX)
+    }
+
+    fn delta_path(number: u64) -> String {
+        format!("{}{number:020}.cbor", Self::DELTA_PREFIX)
+    }
+
+    /// Reads a CBOR object through the streaming reader, which is not bound
+    /// by `max_small_object_size`.
+    async fn load_object<T: DeserializeOwned>(
+        storage: &Storage,
+        path: &str,
+    ) -> Result<Option<T>, DBError> {
+        let mut reader = match storage.stream_reader(path).await {
+            Ok(reader) => reader,
+            Err(DBError::NotFound { .. }) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .await
+            .map_err(|err| DBError::Storage {
+                name: path.to_string(),
+                source: err.into(),
+            })?;
+        from_reader(&data[..])
+            .map(Some)
+            .map_err(|err| DBError::Serialization {
+                name: path.to_string(),
+                source: err.into(),
+            })
+    }
+
+    async fn store_object<T: Serialize>(&self, path: &str, value: &T) -> Result<(), DBError> {
+        let mut data = Vec::new();
+        to_writer(value, &mut data).map_err(|err| DBError::Serialization {
+            name: path.to_string(),
+            source: err.into(),
+        })?;
+        let mut writer = self.storage.stream_writer(path);
+        let written = async {
+            writer.write_all(&data).await?;
+            writer.shutdown().await
+        }
+        .await;
+        written.map_err(|err| DBError::Storage {
+            name: path.to_string(),
+            source: err.into(),
+        })
+    }
+
+    /// Loads the manifest and its deltas and replays every segment written
+    /// after them.
+    pub(crate) async fn open(storage: Storage, mut config: SegmentConfig) -> Result<Self, DBError> {
+        let max_object_size = storage.metadata().config.max_small_object_size;
+        config.max_segment_size = config.max_segment_size.clamp(4096, max_object_size);
+
+        let manifest: SegmentManifest = Self::load_object(&storage, Self::MANIFEST_PATH)
+            .await?
+            .unwrap_or_default();
+
+        let mut state = SegmentState {
+            checkpoint: manifest.checkpoint,
+            compacted_segments: manifest.compacted_segments,
+            manifest_delta: manifest.delta,
+            ..Default::default()
+        };
+        for (sequence, size) in &manifest.segments {
+            state.segments.insert(
+                *sequence,
+                SegmentUsage {
+                    size: *size,
+                    live: 0,
+                },
+            );
+        }
+        for (kind, rows) in [
+            (RecordKind::Document, &manifest.documents),
+            (RecordKind::Intent, &manifest.intents),
+        ] {
+            for (raw, segment, offset, len) in rows {
+                let location = Location {
+                    segment: *segment,
+                    offset: *offset,
+                    len: *len,
+                };
+                state.apply(kind.key(*raw), Some(location));
+            }
+        }
+
+        let mut stream = storage.list_meta(Some(Self::DELTA_PREFIX), None);
+        while let Some(meta) = stream.next().await {
+            let meta = meta?;
+            if let Some(number) = meta
+                .location
+                .filename()
+                .and_then(|name| name.strip_suffix(".cbor"))
+                .and_then(|raw| raw.parse::<u64>().ok())
+            {
+                state.deltas.insert(number);
+            }
+        }
+        let numbers: Vec<u64> = state
+            .deltas
+            .range(manifest.delta.saturating_add(1)..)
+            .copied()
+            .collect();
+        for number in numbers {
+            let path = Self::delta_path(number);
+            let delta: SegmentManifestDelta = Self::load_object(&storage, &path)
+                .await?
+                .ok_or_else(|| DBError::NotFound {
+                    name: storage.base_path().to_string(),
+                    path,
+                    source: "manifest delta disappeared while opening".into(),
+                    _id: 0,
+                })?;
+            state.delta_rows += delta.rows();
+            state.apply_delta(delta);
+        }
+        // Everything loaded so far is persisted; only the replayed segments
+        // below make up the next delta.
+        state.changed_keys.clear();
+        state.changed_segments.clear();
+        let checkpoint = state.checkpoint;
+
+        let mut sequences = BTreeSet::new();
+        let mut stream = storage.list_meta(Some(Self::SEGMENT_PREFIX), None);
+        while let Some(meta) = stream.next().await {
+            let meta = meta?;
+            if let Some(sequence) = meta
+                .location
+                .filename()
+                .and_then(|name| name.strip_suffix(".seg"))
+                .and_then(|raw| raw.parse::<u64>().ok())
+            {
+                sequences.insert(sequence);
+            }
+        }
+
+        let store = Self {
+            cache: (config.cache_max_bytes > 0).then(|| {
+                Cache::builder()
+                    .max_capacity(config.cache_max_bytes)
+                    .weigher(|_key: &u64, value: &Bytes| {
+                        value.len().clamp(1, u32::MAX as usize) as u32
+                    })
+                    .build()
+            }),
+            storage,
+            config,
+            state: parking_lot::RwLock::new(SegmentState::default()),
+            queue: parking_lot::Mutex::new(CommitQueue::default()),
+            commit_lock: tokio::sync::Mutex::new(()),
+            failed: AtomicBool::new(false),
+        };
+
+        for sequence in &sequences {
+            if *sequence <= checkpoint {
+                // Left behind by a compaction whose retirement was
+                // interrupted; the manifest no longer points into it.
+                if !state.segments.contains_key(sequence) {
+                    state.retired.insert(*sequence);
+                }
+                continue;
+            }
+            let data = store.read_segment(*sequence).await?;
+            match parse_segment(*sequence, &data) {
+                Ok(records) => {
+                    state.set_segment_size(*sequence, data.len() as u64);
+                    for (key, location) in records {
+                        state.apply(key, location);
+                    }
+                }
+                // Segments are written by a single PUT, so a malformed one
+                // was damaged outside this crate. Its records cannot be
+                // trusted; skip it like any other unusable recovery input.
+                Err(err) => {
+                    log::warn!(
+                        action = "SegmentStore::open",
+                        path = Self::segment_path(*sequence);
+                        "Skipping undecodable segment: {err}",
+                    );
+                }
+            }
+        }
+        state.next_segment = sequences
+            .last()
+            .copied()
+            .unwrap_or_default()
+            .max(checkpoint)
+            + 1;
+        state.dirty = sequences.iter().any(|sequence| *sequence > checkpoint);
+        *store.state.write() = state;
+        Ok(store)
+    }
+
+    /// Returns the space accounting of the log.
+    pub(crate) fn stats(&self) -> SegmentStats {
+        let state = self.state.read();
+        SegmentStats {
+            segments: state.segments.len(),
+            total_bytes: state.segments.values().map(|usage| usage.size).sum(),
+            live_bytes: state.segments.values().map(|usage| usage.live).sum(),
+            compacted_segments: state.compacted_segments,
+        }
+    }
+
+    /// Lists the current keys of one kind in ascending order.
+    pub(crate) fn keys(&self, kind: RecordKind) -> Vec<RecordKey> {
+        let state = self.state.read();
+        state.map(kind).keys().map(|raw| kind.key(*raw)).collect()
+    }
+
+    /// Reads the current payload of `key` and its version.
+    pub(crate) async fn read(&self, key: RecordKey) -> Result<(Bytes, ObjectVersion), DBError> {
+        let Some(location) = self.state.read().get(key) else {
+            return Err(DBError::NotFound {
+                name: self.storage.base_path().to_string(),
+                path: key.path(),
+                source: "record not found in segment log".into(),
+                _id: match key {
+                    RecordKey::Document(id) => id,
+                    RecordKey::Intent(_) => 0,
+                },
+            });
+        };
+        let data = self.read_segment(location.segment).await?;
+        Ok((Self::record(&data, location)?, location.version()))
+    }
+
+    /// Slices the payload at `location` out of its segment's `data`, failing
+    /// if a truncated or corrupt segment does not hold it.
+    fn record(data: &Bytes, location: Location) -> Result<Bytes, DBError> {
+        let start = location.offset as usize;
+        match start.checked_add(location.len as usize) {
+            Some(end) if end <= data.len() => Ok(data.slice(start..end)),
+            _ => Err(DBError::Storage {
+                name: Self::segment_path(location.segment),
+                source: format!("record at offset {start} exceeds segment size").into(),
+            }),
+        }
+    }
+
+    async fn read_segment(&self, sequence: u64) -> Result<Bytes, DBError> {
+        if let Some(cache) = &self.cache
+            && let Some(data) = cache.get(&sequence).await
+        {
+            return Ok(data);
+        }
+        let (data, _) = self
+            .storage
+            .fetch_bytes(&Self::segment_path(sequence))
+            .await?;
+        if let Some(cache) = &self.cache {
+            cache.insert(sequence, data.clone()).await;
+        }
+        Ok(data)
+    }
+
+    /// Commits `ops` atomically through the group commit and returns the
+    /// version of every written record (`None` for removals).
+    ///
+    /// The group is committed by a spawned leader task, not by one of the
+    /// writers, so a writer whose future is dropped (a cancelled request)
+    /// neither abandons the submissions queued with its own nor holds the
+    /// commit lock while the group gathers; its write still completes.
+    pub(crate) async fn write(
+        self: &Arc<Self>,
+        ops: Vec<WriteOp>,
+    ) -> Result<Vec<Option<ObjectVersion>>, DBError> {
+        let (reply, outcome) = tokio::sync::oneshot::channel();
+        {
+            let mut queue = self.queue.lock();
+            let ticket = queue.next_ticket;
+            queue.next_ticket += 1;
+            queue.pending.push(Submission { ticket, ops, reply });
+            if !queue.leading {
+                queue.leading = true;
+                tokio::spawn(self.clone().lead());
+            }
+        }
+
+        let rt = outcome.await.map_err(|_| DBError::Storage {
+            name: self.storage.base_path().to_string(),
+            source: "group commit leader stopped before publishing the outcome".into(),
+        })?;
+        rt.map(|locations| {
+            locations
+                .into_iter()
+                .map(|l| l.map(Location::version))
+                .collect()
+        })
+    }
+
+    /// Commits the queued submissions group by group until the queue is
+    /// empty, replying to every writer.
+    async fn lead(self: Arc<Self>) {
+        loop {
+            if self.config.group_commit_delay_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(self.config.group_commit_delay_ms)).await;
+            }
+            let _commit = self.commit_lock.lock().await;
+            let submissions = {
+                let mut queue = self.queue.lock();
+                if queue.pending.is_empty() {
+                    queue.leading = false;
+                    return;
+                }
+                std::mem::take(&mut queue.pending)
+            };
+
+            let (ops, replies): (Vec<_>, Vec<_>) = submissions
+                .into_iter()
+                .map(|submission| ((submission.ticket, submission.ops), submission.reply))
+                .unzip();
+            let tickets: Vec<u64> = ops.iter().map(|(ticket, _)| *ticket).collect();
+            let mut results = self.commit(ops).await;
+            for (ticket, reply) in tickets.into_iter().zip(replies) {
+                let rt = results
+                    .remove(&ticket)
+                    .unwrap_or_else(|| Err(self.group_error("submission has no outcome")));
+                // The writer may be gone; its write is committed regardless.
+                let _ = reply.send(rt);
+            }
+        }
+    }
+
+    /// Writes one group of `(ticket, ops)` submissions. Called by the leader
+    /// while holding the commit lock.
+    async fn commit(&self, submissions: Vec<(u64, Vec<WriteOp>)>) -> FxHashMap<u64, CommitResult> {
+        let mut results = FxHashMap::default();
+        if self.failed.load(Ordering::Acquire) {
+            for (ticket, _) in submissions {
+                results.insert(ticket, Err(self.failed_error()));
+            }
+            return results;
+        }
+
+        // Lay the accepted submissions out into segments. `overlay` tracks
+        // keys written earlier in this group so later conditions observe them.
+        let mut overlay: FxHashMap<RecordKey, Option<Location>> = FxHashMap::default();
+        let mut builders: Vec<SegmentBuilder> = Vec::new();
+        let mut accepted: FxHashMap<u64, Vec<Option<Location>>> = FxHashMap::default();
+        for (ticket, ops) in submissions {
+            if let Err(err) = self.check_conditions(&ops, &overlay) {
+                results.insert(ticket, Err(err));
+                continue;
+            }
+            let size: usize = ops
+                .iter()
+                .map(|op| RECORD_HEADER_LEN + op.value.as_ref().map_or(0, |v| v.len()))
+                .sum();
+            let needs_new = match builders.last() {
+                None => true,
+                Some(builder) => {
+                    !builder.is_empty() && builder.buf.len() + size > self.config.max_segment_size
+                }
+            };
+            if needs_new {
+                let sequence = {
+                    let mut state = self.state.write();
+                    let sequence = state.next_segment;
+                    state.next_segment += 1;
+                    sequence
+                };
+                builders.push(SegmentBuilder::new(sequence));
+            }
+            let builder = builders.last_mut().expect("a builder was pushed above");
+            let mut locations = Vec::with_capacity(ops.len());
+            for op in &ops {
+                let location = builder.append(op.key, op.value.as_deref());
+                overlay.insert(op.key, location);
+                locations.push(location);
+            }
+            builder.tickets.push(ticket);
+            accepted.insert(ticket, locations);
+        }
+
+        let mut failure: Option<String> = None;
+        for builder in builders {
+            if let Some(reason) = &failure {
+                for ticket in builder.tickets {
+                    results.insert(ticket, Err(self.group_error(reason)));
+                }
+                continue;
+            }
+
+            let path = Self::segment_path(builder.sequence);
+            let data = builder.buf.freeze();
+            match self
+                .storage
+                .put_bytes(&path, data.clone(), PutMode::Create)
+                .await
+            {
+                Ok(_) => {
+                    {
+                        let mut state = self.state.write();
+                        state.set_segment_size(builder.sequence, data.len() as u64);
+                        for (key, location) in builder.records {
+                            state.apply(key, location);
+                        }
+                    }
+                    if let Some(cache) = &self.cache {
+                        cache.insert(builder.sequence, data).await;
+                    }
+                    for ticket in builder.tickets {
+                        let locations = accepted.remove(&ticket).unwrap_or_default();
+                        results.insert(ticket, Ok(locations));
+                    }
+                }
+                Err(err) => {
+                    // The PUT outcome is unknown. Remove the object so its
+                    // records cannot surface on the next open; if that fails
+                    // too, the log no longer knows what is durable.
+                    match self.storage.delete(&path).await {
+                        Ok(()) | Err(DBError::NotFound { .. }) => {}
+                        Err(delete_err) => {
+                            log::error!(
+                                action = "SegmentStore::commit",
+                                path = path;
+                                "Failed to remove segment after failed write: {delete_err:?}",
+                            );
+                            self.failed.store(true, Ordering::Release);
+                        }
+                    }
+                    failure = Some(format!("{err:?}"));
+                    let mut tickets = builder.tickets.into_iter();
+                    if let Some(first) = tickets.next() {
+                        results.insert(first, Err(err));
+                    }
+                    for ticket in tickets {
+                        results.insert(
+                            ticket,
+                            Err(self.group_error(failure.as_deref().unwrap_or_default())),
+                        );
+                    }
+                }
+            }
+        }
+        results
+    }
+
+    fn check_conditions(
+        &self,
+        ops: &[WriteOp],
+        overlay: &FxHashMap<RecordKey, Option<Location>>,
+    ) -> Result<(), DBError> {
+        let state = self.state.read();
+        for op in ops {
+            let current = match overlay.get(&op.key) {
+                Some(location) => *location,
+                None => state.get(op.key),
+            };
+            match (&op.condition, current) {
+                (Condition::None, _) => {}
+                (Condition::Absent, None) => {}
+                (Condition::Absent, Some(_)) => {
+                    return Err(DBError::AlreadyExists {
+                        name: self.storage.base_path().to_string(),
+                        path: op.key.path(),
+                        source: "record already exists in segment log".into(),
+                        _id: 0,
+                    });
+                }
+                (Condition::Version(version), Some(location)) if location.matches(version) => {}
+                (Condition::Version(_), _) => {
+                    return Err(DBError::Precondition {
+                        path: op.key.path(),
+                        source: "record version changed in segment log".into(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn group_error(&self, reason: &str) -> DBError {
+        DBError::Storage {
+            name: self.storage.base_path().to_string(),
+            source: format!("group commit failed: {reason}").into(),
+        }
+    }
+
+    fn failed_error(&self) -> DBError {
+        DBError::Storage {
+            name: self.storage.base_path().to_string(),
+            source: "segment log has an unknown write outcome; reopen the collection".into(),
+        }
+    }
+
+    /// Persists the changes of the `key -> location` map as a manifest
+    /// delta, or the whole map once the deltas grow large, and deletes
+    /// segments that no longer back any record.
+    pub(crate) async fn checkpoint(&self) -> Result<(), DBError> {
+        let _commit = self.commit_lock.lock().await;
+        self.checkpoint_locked().await
+    }
+
+    async fn checkpoint_locked(&self) -> Result<(), DBError> {
+        if self.state.read().dirty {
+            self.persist_map().await?;
+        }
+
+        let retired = self.state.read().retired.clone();
+        for sequence in retired {
+            match self.storage.delete(&Self::segment_path(sequence)).await {
+                Ok(()) | Err(DBError::NotFound { .. }) => {
+                    self.state.write().retired.remove(&sequence);
+                    if let Some(cache) = &self.cache {
+                        cache.remove(&sequence).await;
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the next manifest delta, or rewrites the manifest when the
+    /// deltas since the last rewrite would exceed half the map (at least
+    /// [`Self::MIN_DELTA_ROWS`] rows) or [`Self::MAX_MANIFEST_DELTAS`]
+    /// objects. Each flush thus writes what changed, and the occasional
+    /// rewrite costs O(1) amortized per change.
+    async fn persist_map(&self) -> Result<(), DBError> {
+        enum Update {
+            Manifest(SegmentManifest, Vec<u64>),
+            Delta(u64, SegmentManifestDelta),
+        }
+
+        let update = {
+            let state = self.state.read();
+            let checkpoint = state.next_segment.saturating_sub(1);
+            let changed = state.changed_keys.len() + state.changed_segments.len();
+            let live = state.documents.len() + state.intents.len();
+            let pending = state.deltas.range(state.manifest_delta.saturating_add(1)..);
+            if pending.count() >= Self::MAX_MANIFEST_DELTAS
+                || state.delta_rows + changed > (live / 2).max(Self::MIN_DELTA_ROWS)
+            {
+                let stale = state.deltas.iter().copied().collect();
+                Update::Manifest(state.manifest(checkpoint), stale)
+            } else {
+                Update::Delta(state.last_delta() + 1, state.delta(checkpoint))
+            }
+        };
+
+        match update {
+            Update::Manifest(manifest, stale) => {
+                self.store_object(Self::MANIFEST_PATH, &manifest).await?;
+                {
+                    let mut state = self.state.write();
+                    state.checkpoint = manifest.checkpoint;
+                    state.manifest_delta = manifest.delta;
+                    state.delta_rows = 0;
+                    state.changed_keys.clear();
+                    state.changed_segments.clear();
+                    state.dirty = false;
+                }
+                // The manifest no longer depends on them; a failed delete
+                // leaves a delta the next open skips.
+                for number in stale {
+                    match self.storage.delete(&Self::delta_path(number)).await {
+                        Ok(()) | Err(DBError::NotFound { .. }) => {
+                            self.state.write().deltas.remove(&number);
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+            Update::Delta(number, delta) => {
+                self.store_object(&Self::delta_path(number), &delta).await?;
+                let mut state = self.state.write();
+                state.checkpoint = delta.checkpoint;
+                state.deltas.insert(number);
+                state.delta_rows += delta.rows();
+                state.changed_keys.clear();
+                state.changed_segments.clear();
+                state.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the live records of sparse checkpointed segments into fresh
+    /// segments, then persists the manifest and deletes the old segments.
+    ///
+    /// Runs under the commit lock, so the copies are the current records
+    /// when they are written: if the process stops before the manifest is
+    /// persisted, replaying the new segment reproduces exactly the same map.
+    /// Returns the number of segments reclaimed.
+    pub(crate) async fn compact(&self) -> Result<usize, DBError> {
+        let _commit = self.commit_lock.lock().await;
+        if self.failed.load(Ordering::Acquire) {
+            return Err(self.failed_error());
+        }
+
+        // Oldest first; bound the copied bytes per run to one segment so a
+        // compaction pass never stalls writers for long. Fully dead segments
+        // cost nothing to reclaim and are always taken.
+        let candidates: Vec<u64> = {
+            let state = self.state.read();
+            let mut budget = self.config.max_segment_size as u64;
+            let mut candidates = Vec::new();
+            for (sequence, usage) in state.segments.range(..=state.checkpoint) {
+                if usage.live as f64 >= usage.size as f64 * self.config.compaction_threshold {
+                    continue;
+                }
+                if usage.live > 0 {
+                    if usage.live > budget {
+                        continue;
+                    }
+                    budget -= usage.live;
+                }
+                candidates.push(*sequence);
+            }
+            candidates
+        };
+        if candidates.is_empty() {
+            return Ok(0);
+        }
+
+        let live: Vec<(RecordKey, Location)> = {
+            let state = self.state.read();
+            let candidates: BTreeSet<u64> = candidates.iter().copied().collect();
+            [RecordKind::Document, RecordKind::Intent]
+                .into_iter()
+                .flat_map(|kind| {
+                    state
+                        .map(kind)
+                        .iter()
+                        .filter(|(_, l)| candidates.contains(&l.segment))
+                        .map(move |(raw, l)| (kind.key(*raw), *l))
+                })
+                .collect()
+        };
+
+        if !live.is_empty() {
+            let sequence = {
+                let mut state = self.state.write();
+                let sequence = state.next_segment;
+                state.next_segment += 1;
+                sequence
+            };
+            let mut builder = SegmentBuilder::new(sequence);
+            for (key, location) in &live {
+                let data = self.read_segment(location.segment).await?;
+                builder.append(*key, Some(&Self::record(&data, *location)?));
+            }
+            let data = builder.buf.freeze();
+            // A failed PUT changes nothing in memory. Should it have landed,
+            // replaying it later only re-installs the current records.
+            self.storage
+                .put_bytes(&Self::segment_path(sequence), data.clone(), PutMode::Create)
+                .await?;
+            let mut state = self.state.write();
+            state.set_segment_size(sequence, data.len() as u64);
+            for (key, location) in builder.records {
+                state.apply(key, location);
+            }
+        }
+
+        {
+            let mut state = self.state.write();
+            for sequence in &candidates {
+                state.remove_segment(*sequence);
+                state.retired.insert(*sequence);
+            }
+            state.compacted_segments += candidates.len() as u64;
+            state.dirty = true;
+        }
+        self.checkpoint_locked().await?;
+        Ok(candidates.len())
+    }
+}
+
+/// A collection's document and mutation-intent storage, in either
+/// [`DocumentStorage`] mode. The API mirrors the subset of [`Storage`] the
+/// collection uses for these records.
+#[derive(Clone)]
+pub(crate) enum DocumentStore {
+    Objects(Storage),
+    Segments(Arc<SegmentStore>),
+}
+
+impl DocumentStore {
+    pub(crate) async fn open(storage: Storage, mode: &DocumentStorage) -> Result<Self, DBError> {
+        match mode {
+            DocumentStorage::Objects => Ok(Self::Objects(storage)),
+            DocumentStorage::Segments(config) => Ok(Self::Segments(Arc::new(
+                SegmentStore::open(storage, config.clone()).await?,
+            ))),
+        }
+    }
+
+    /// Returns the segment accounting, or `None` in objects mode.
+    pub(crate) fn segment_stats(&self) -> Option<SegmentStats> {
+        match self {
+            Self::Objects(_) => None,
+            Self::Segments(store) => Some(store.stats()),
+        }
+    }
+
+    fn encode<T: Serialize>(&self, key: RecordKey, value: &T) -> Result<Bytes, DBError> {
+        let mut buf: Vec<u8> = Vec::new();
+        to_writer(value, &mut buf).map_err(|err| DBError::Serialization {
+            name: key.path(),
+            source: err.into(),
+        })?;
+        if let Self::Segments(store) = self {
+            let limit = store.storage.metadata().config.max_small_object_size;
+            if buf.len() > limit {
+                return Err(DBError::PayloadTooLarge {
+                    path: key.path(),
+                    size: buf.len(),
+                    limit,
+                });
+            }
+        }
+        Ok(buf.into())
+    }
+
+    fn decode<T: DeserializeOwned>(key: RecordKey, data: &[u8]) -> Result<T, DBError> {
+        from_reader(data).map_err(|err| DBError::Serialization {
+            name: key.path(),
+            source: err.into(),
+        })
+    }
+
+    async fn write_one(&self, op: WriteOp) -> Result<ObjectVersion, DBError> {
+        let Self::Segments(store) = self else {
+            unreachable!("only segment stores take write ops");
+        };
+        let versions = store.write(vec![op]).await?;
+        Ok(versions
+            .into_iter()
+            .next()
+            .flatten()
+            .unwrap_or(ObjectVersion {
+                e_tag: None,
+                version: None,
+            }))
+    }
+
+    /// Creates `key`; fails with `AlreadyExists` if it exists.
+    pub(crate) async fn create<T: Serialize>(
+        &self,
+        key: RecordKey,
+        value: &T,
+    ) -> Result<ObjectVersion, DBError> {
+        match self {
+            Self::Objects(storage) => storage.create(&key.path(), value).await,
+            Self::Segments(_) => {
+                let value = self.encode(key, value)?;
+                self.write_one(WriteOp {
+                    key,
+                    value: Some(value),
+                    condition: Condition::Absent,
+                })
+                .await
+            }
+        }
+    }
+
+    /// Writes `key`, conditionally on `version` when given.
+    pub(crate) async fn put<T: Serialize>(
+        &self,
+        key: RecordKey,
+        value: &T,
+        version: Option<ObjectVersion>,
+    ) -> Result<ObjectVersion, DBError> {
+        match self {
+            Self::Objects(storage) => storage.put(&key.path(), value, version).await,
+            Self::Segments(_) => {
+                let value = self.encode(key, value)?;
+                self.write_one(WriteOp {
+                    key,
+                    value: Some(value),
+                    condition: version.map_or(Condition::None, Condition::Version),
+                })
+                .await
+            }
+        }
+    }
+
+    /// Reads `key` through the storage cache.
+    pub(crate) async fn get<T: DeserializeOwned>(
+        &self,
+        key: RecordKey,
+    ) -> Result<(T, ObjectVersion), DBError> {
+        match self {
+            Self::Objects(storage) => storage.get(&key.path()).await,
+            Self::Segments(store) => {
+                let (data, version) = store.read(key).await?;
+                Ok((Self::decode(key, &data)?, version))
+            }
+        }
+    }
+
+    /// Reads `key`, bypassing the object cache in objects mode. Segments
+    /// are immutable, so segment mode serves both from its cache.
+    pub(crate) async fn fetch<T: DeserializeOwned>(
+        &self,
+        key: RecordKey,
+    ) -> Result<(T, ObjectVersion), DBError> {
+        match self {
+            Self::Objects(storage) => storage.fetch(&key.path()).await,
+            Self::Segments(store) => {
+                let (data, version) = store.read(key).await?;
+                Ok((Self::decode(key, &data)?, version))
+            }
+        }
+    }
+
+    /// Removes `key`. Removing an absent key is not an error in segment
+    /// mode; in objects mode it is whatever the object store reports.
+    pub(crate) async fn delete(&self, key: RecordKey) -> Result<(), DBError> {
+        match self {
+            Self::Objects(storage) => storage.delete(&key.path()).await,
+            Self::Segments(_) => self.delete_many(&[key]).await,
+        }
+    }
+
+    /// Removes every key in `keys`, skipping absent ones; segment mode
+    /// commits them in one group.
+    pub(crate) async fn delete_many(&self, keys: &[RecordKey]) -> Result<(), DBError> {
+        match self {
+            Self::Objects(storage) => {
+                for key in keys {
+                    match storage.delete(&key.path()).await {
+                        Ok(()) | Err(DBError::NotFound { .. }) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                Ok(())
+            }
+            Self::Segments(store) => {
+                let ops: Vec<WriteOp> = {
+                    let state = store.state.read();
+                    keys.iter()
+                        .filter(|key| state.get(**key).is_some())
+                        .map(|key| WriteOp {
+                            key: *key,
+                            value: None,
+                            condition: Condition::None,
+                        })
+                        .collect()
+                };
+                if ops.is_empty() {
+                    return Ok(());
+                }
+                store.write(ops).await.map(|_| ())
+            }
+        }
+    }
+
+    /// Lists the existing keys of `kind` in ascending order.
+    pub(crate) async fn list(&self, kind: RecordKind) -> Result<Vec<RecordKey>, DBError> {
+        match self {
+            Self::Objects(storage) => {
+                let mut keys = BTreeSet::new();
+                let mut stream = storage.list_meta(Some(kind.prefix()), None);
+                while let Some(meta) = stream.next().await {
+                    let meta = meta?;
+                    if let Some(raw) = meta
+                        .location
+                        .filename()
+                        .and_then(|name| name.strip_suffix(".cbor"))
+                        .and_then(|raw| raw.parse::<u64>().ok())
+                    {
+                        keys.insert(kind.key(raw));
+                    }
+                }
+                Ok(keys.into_iter().collect())
+            }
+            Self::Segments(store) => Ok(store.keys(kind)),
+        }
+    }
+
+    /// Persists the changes of the segment map. A no-op in objects mode.
+    pub(crate) async fn checkpoint(&self) -> Result<(), DBError> {
+        match self {
+            Self::Objects(_) => Ok(()),
+            Self::Segments(store) => store.checkpoint().await,
+        }
+    }
+
+    /// Runs one compaction pass over sparse segments and returns the number
+    /// of segments reclaimed; 0 in objects mode.
+    pub(crate) async fn compact(&self) -> Result<usize, DBError> {
+        match self {
+            Self::Objects(_) => Ok(0),
+            Self::Segments(store) => store.compact().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageConfig;
+    use async_trait::async_trait;
+    use futures::{future::join_all, stream::BoxStream};
+    use object_store::{
+        CopyOptions, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+        PutMultipartOptions, PutOptions, PutPayload, PutResult, Result as ObjectStoreResult,
+        memory::InMemory, path::Path,
+    };
+    use std::fmt;
+
+    /// An object store whose segment PUTs take a while, so a test can drop a
+    /// writer while its group is being written.
+    #[derive(Debug)]
+    struct SlowSegmentPutStore {
+        inner: InMemory,
+    }
+
+    impl fmt::Display for SlowSegmentPutStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("SlowSegmentPutStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for SlowSegmentPutStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> ObjectStoreResult<PutResult> {
+            if location.as_ref().ends_with(".seg") {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> ObjectStoreResult<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        fn delete_stream(
+            &self,
+            locations: BoxStream<'static, ObjectStoreResult<Path>>,
+        ) -> BoxStream<'static, ObjectStoreResult<Path>> {
+            self.inner.delete_stream(locations)
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        fn list_with_offset(
+            &self,
+            prefix: Option<&Path>,
+            offset: &Path,
+        ) -> BoxStream<'static, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list_with_offset(prefix, offset)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> ObjectStoreResult<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy_opts(
+            &self,
+            from: &Path,
+            to: &Path,
+            options: CopyOptions,
+        ) -> ObjectStoreResult<()> {
+            self.inner.copy_opts(from, to, options).await
+        }
+    }
+
+    async fn connect(object_store: Arc<dyn ObjectStore>) -> Storage {
+        Storage::connect(
+            "segments_test".to_string(),
+            object_store,
+            StorageConfig {
+                compress_level: 0,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    fn put_op(key: RecordKey, value: &str, condition: Condition) -> WriteOp {
+        let mut buf = Vec::new();
+        to_writer(&value, &mut buf).unwrap();
+        WriteOp {
+            key,
+            value: Some(buf.into()),
+            condition,
+        }
+    }
+
+    async fn read_text(store: &SegmentStore, key: RecordKey) -> Result<String, DBError> {
+        let (data, _) = store.read(key).await?;
+        Ok(from_reader(&data[..]).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_group_commit_shares_one_put() {
+        let storage = connect(Arc::new(InMemory::new())).await;
+        let store = Arc::new(
+            SegmentStore::open(
+                storage.clone(),
+                SegmentConfig {
+                    group_commit_delay_ms: 20,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap(),
+        );
+
+        let puts_before = storage.stats().total_put_count;
+        let writes = (1..=16u64).map(|id| {
+            store.write(vec![put_op(
+                RecordKey::Document(id),
+                &format!("doc {id}"),
+                Condition::Absent,
+            )])
+        });
+        for rt in join_all(writes).await {
+            assert_eq!(rt.unwrap().len(), 1);
+        }
+
+        assert_eq!(storage.stats().total_put_count - puts_before, 1);
+        assert_eq!(store.stats().segments, 1);
+        for id in 1..=16u64 {
+            assert_eq!(
+                read_text(&store, RecordKey::Document(id)).await.unwrap(),
+                format!("doc {id}")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_writer_does_not_abandon_its_group() {
+        let storage = connect(Arc::new(SlowSegmentPutStore {
+            inner: InMemory::new(),
+        }))
+        .await;
+        let store = Arc::new(
+            SegmentStore::open(storage, SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+
+        // The first writer is dropped while its segment is being written.
+        let first = tokio::time::timeout(
+            Duration::from_millis(10),
+            store.write(vec![put_op(
+                RecordKey::Document(1),
+                "first",
+                Condition::Absent,
+            )]),
+        )
+        .await;
+        assert!(first.is_err());
+
+        store
+            .write(vec![put_op(
+                RecordKey::Document(2),
+                "second",
+                Condition::Absent,
+            )])
+            .await
+            .unwrap();
+        assert_eq!(
+            read_text(&store, RecordKey::Document(1)).await.unwrap(),
+            "first"
+        );
+        assert_eq!(
+            read_text(&store, RecordKey::Document(2)).await.unwrap(),
+            "second"
+        );
+        store
+            .write(vec![put_op(
+                RecordKey::Document(3),
+                "third",
+                Condition::Absent,
+            )])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_conditions_are_checked_at_commit() {
+        let storage = connect(Arc::new(InMemory::new())).await;
+        let store = Arc::new(
+            SegmentStore::open(storage, SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+        let key = RecordKey::Document(1);
+
+        let versions = store
+            .write(vec![put_op(key, "v1", Condition::Absent)])
+            .await
+            .unwrap();
+        let v1 = versions[0].clone().unwrap();
+        let err = store
+            .write(vec![put_op(key, "again", Condition::Absent)])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::AlreadyExists { .. }), "{err:?}");
+
+        store
+            .write(vec![put_op(key, "v2", Condition::Version(v1.clone()))])
+            .await
+            .unwrap();
+        let err = store
+            .write(vec![put_op(key, "stale", Condition::Version(v1))])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::Precondition { .. }), "{err:?}");
+        assert_eq!(read_text(&store, key).await.unwrap(), "v2");
+    }
+
+    #[tokio::test]
+    async fn test_reopen_replays_segments_written_after_the_manifest() {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let store = Arc::new(
+            SegmentStore::open(
+                connect(object_store.clone()).await,
+                SegmentConfig::default(),
+            )
+            .await
+            .unwrap(),
+        );
+        for id in 1..=3u64 {
+            store
+                .write(vec![put_op(
+                    RecordKey::Document(id),
+                    "checkpointed",
+                    Condition::None,
+                )])
+                .await
+                .unwrap();
+        }
+        store.checkpoint().await.unwrap();
+
+        store
+            .write(vec![
+                put_op(RecordKey::Document(1), "updated", Condition::None),
+                put_op(RecordKey::Intent(7), "intent", Condition::Absent),
+            ])
+            .await
+            .unwrap();
+        store
+            .write(vec![WriteOp {
+                key: RecordKey::Document(2),
+                value: None,
+                condition: Condition::None,
+            }])
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = Arc::new(
+            SegmentStore::open(connect(object_store).await, SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+        assert_eq!(
+            read_text(&store, RecordKey::Document(1)).await.unwrap(),
+            "updated"
+        );
+        assert!(matches!(
+            store.read(RecordKey::Document(2)).await,
+            Err(DBError::NotFound { .. })
+        ));
+        assert_eq!(
+            read_text(&store, RecordKey::Document(3)).await.unwrap(),
+            "checkpointed"
+        );
+        assert_eq!(store.keys(RecordKind::Intent), vec![RecordKey::Intent(7)]);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_persists_deltas_until_the_manifest_is_rewritten() {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let storage = connect(object_store.clone()).await;
+        let store = Arc::new(
+            SegmentStore::open(storage.clone(), SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+        let count = |prefix: &'static str| {
+            let storage = storage.clone();
+            async move { storage.list_meta(Some(prefix), None).count().await }
+        };
+
+        // Every checkpoint writes only what changed since the previous one.
+        for id in 1..=3u64 {
+            store
+                .write(vec![put_op(RecordKey::Document(id), "v1", Condition::None)])
+                .await
+                .unwrap();
+            store.checkpoint().await.unwrap();
+        }
+        store
+            .write(vec![WriteOp {
+                key: RecordKey::Document(2),
+                value: None,
+                condition: Condition::None,
+            }])
+            .await
+            .unwrap();
+        store.checkpoint().await.unwrap();
+        assert_eq!(count(SegmentStore::DELTA_PREFIX).await, 4);
+        assert!(matches!(
+            storage.fetch_bytes(SegmentStore::MANIFEST_PATH).await,
+            Err(DBError::NotFound { .. })
+        ));
+        let delta: SegmentManifestDelta =
+            SegmentStore::load_object(&storage, &SegmentStore::delta_path(4))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(delta.documents, vec![(2, None)]);
+
+        let reopened = Arc::new(
+            SegmentStore::open(storage.clone(), SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+        assert_eq!(
+            reopened.keys(RecordKind::Document),
+            vec![RecordKey::Document(1), RecordKey::Document(3)]
+        );
+        assert_eq!(reopened.stats(), store.stats());
+
+        // Too many deltas fold into a rewritten manifest and are deleted.
+        for _ in 0..SegmentStore::MAX_MANIFEST_DELTAS {
+            store
+                .write(vec![put_op(RecordKey::Document(1), "v2", Condition::None)])
+                .await
+                .unwrap();
+            store.checkpoint().await.unwrap();
+        }
+        assert!(count(SegmentStore::DELTA_PREFIX).await < SegmentStore::MAX_MANIFEST_DELTAS);
+        let manifest: SegmentManifest =
+            SegmentStore::load_object(&storage, SegmentStore::MANIFEST_PATH)
+                .await
+                .unwrap()
+                .unwrap();
+        assert!(manifest.delta > 0);
+
+        let reopened = Arc::new(
+            SegmentStore::open(connect(object_store).await, SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+        assert_eq!(
+            read_text(&reopened, RecordKey::Document(1)).await.unwrap(),
+            "v2"
+        );
+        assert_eq!(
+            read_text(&reopened, RecordKey::Document(3)).await.unwrap(),
+            "v1"
+        );
+        assert_eq!(reopened.stats(), store.stats());
+    }
+
+    #[test]
+    fn test_record_outside_the_segment_is_an_error() {
+        let data = Bytes::from_static(b"ADBSEG\x00\x01record");
+        let record = |offset, len| {
+            SegmentStore::record(
+                &data,
+                Location {
+                    segment: 1,
+                    offset,
+                    len,
+                },
+            )
+        };
+        assert_eq!(record(8, 6).unwrap(), Bytes::from_static(b"record"));
+        assert!(matches!(record(8, 7), Err(DBError::Storage { .. })));
+        assert!(matches!(
+            record(u32::MAX, u32::MAX),
+            Err(DBError::Storage { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_compaction_reclaims_overwritten_records() {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let storage = connect(object_store.clone()).await;
+        let store = Arc::new(
+            SegmentStore::open(storage.clone(), SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+        for round in 0..5 {
+            for id in 1..=4u64 {
+                store
+                    .write(vec![put_op(
+                        RecordKey::Document(id),
+                        &format!("doc {id} round {round}"),
+                        Condition::None,
+                    )])
+                    .await
+                    .unwrap();
+            }
+        }
+        store.checkpoint().await.unwrap();
+        assert_eq!(store.stats().segments, 20);
+
+        // Each one-record segment is mostly header, so every segment is
+        // sparse and the four live records are merged into one segment.
+        let compacted = store.compact().await.unwrap();
+        assert_eq!(compacted, 20);
+        let stats = store.stats();
+        assert_eq!(stats.segments, 1);
+        assert_eq!(stats.compacted_segments, 20);
+        assert_eq!(stats.live_bytes, stats.total_bytes - 8 - 4 * 14);
+
+        let mut listed = 0;
+        let mut stream = storage.list_meta(Some(SegmentStore::SEGMENT_PREFIX), None);
+        while let Some(meta) = stream.next().await {
+            if meta.unwrap().location.as_ref().ends_with(".seg") {
+                listed += 1;
+            }
+        }
+        assert_eq!(listed, 1);
+
+        let store = Arc::new(
+            SegmentStore::open(connect(object_store).await, SegmentConfig::default())
+                .await
+                .unwrap(),
+        );
+        for id in 1..=4u64 {
+            assert_eq!(
+                read_text(&store, RecordKey::Document(id)).await.unwrap(),
+                format!("doc {id} round 4")
+            );
+        }
+    }
+}
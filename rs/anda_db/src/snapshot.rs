@@ -0,0 +1,370 @@
+//! Point-in-time database snapshots.
+//!
+//! [`AndaDB::snapshot`](crate::database::AndaDB::snapshot) checkpoints every
+//! open collection while all of them are frozen, records the
+//! [`ObjectVersion`] of each object under the database prefix (database
+//! metadata, write-batch manifests, and every collection's metadata, index
+//! buckets, documents and segments) and copies their stored bytes before
+//! the collections resume. Later flushes therefore cannot invalidate a
+//! snapshot, whether or not the backend keeps object versions.
+//!
+//! A [`DBSnapshot`] is materialized either into another object store
+//! ([`AndaDB::restore_from`](crate::database::AndaDB::restore_from)) or into a
+//! self-contained archive ([`DBSnapshot::export`]) that
+//! [`AndaDB::restore_archive`](crate::database::AndaDB::restore_archive)
+//! reads back. A snapshot rebuilt from a persisted manifest
+//! ([`DBSnapshot::from_manifest`]) holds no bytes: it reads every object with
+//! its pinned version as a precondition, so objects overwritten since fail
+//! with [`DBError::Precondition`] instead of producing a torn copy.
+//!
+//! A restore that fails partway deletes the objects it already created, so
+//! the target never holds a partial database.
+//!
+//! Archive layout:
+//!
+//! ```text
+//! "ANDASNAP" | format: u32 LE | manifest_len: u64 LE | manifest (CBOR)
+//! then for every manifest object, in order: len: u64 LE | stored bytes
+//! ```
+//!
+//! Object bytes are copied as stored (already compressed when the database
+//! uses compression), so restoring never re-encodes anything.
+use bytes::Bytes;
+use cbor2::{from_reader, to_writer};
+use object_store::{
+    GetOptions, ObjectStore, ObjectStoreExt, PutMode, PutOptions, PutPayload, path::Path,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{error::DBError, storage::ObjectVersion};
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"ANDASNAP";
+const ARCHIVE_FORMAT: u32 = 1;
+
+/// Largest manifest or object an archive may hold. Entries are read into
+/// memory whole, so a corrupt length must fail instead of being trusted.
+const MAX_ARCHIVE_ENTRY_SIZE: u64 = 1 << 32;
+
+/// Most bytes reserved up front for an archive entry; a larger entry grows as
+/// its bytes actually arrive, so a truncated archive cannot claim memory it
+/// does not contain.
+const ARCHIVE_READ_RESERVE: u64 = 1 << 20;
+
+/// The pinned object set of a database snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    /// Database name; objects live under this prefix in the object store.
+    pub database: String,
+    /// Snapshot timestamp in milliseconds.
+    pub created_at: u64,
+    /// Every object of the snapshot, sorted by path.
+    pub objects: Vec<SnapshotObject>,
+}
+
+/// An object pinned by a [`SnapshotManifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotObject {
+    /// Path relative to the database prefix.
+    pub path: String,
+    /// Stored size in bytes.
+    pub size: u64,
+    /// Version captured when the snapshot was taken.
+    pub version: ObjectVersion,
+}
+
+impl SnapshotManifest {
+    /// Total stored size of all pinned objects in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.objects.iter().map(|obj| obj.size).sum()
+    }
+
+    fn full_path(&self, path: &str) -> Path {
+        Path::from(format!("{}/{path}", self.database))
+    }
+}
+
+/// A consistent, point-in-time view of an [`AndaDB`](crate::database::AndaDB).
+///
+/// Holds the source object store, the pinned [`SnapshotManifest`] and, when
+/// taken by [`AndaDB::snapshot`](crate::database::AndaDB::snapshot), the
+/// stored bytes of every object; it does not keep the database frozen. A
+/// snapshot can be persisted by keeping its manifest and rebuilt with
+/// [`DBSnapshot::from_manifest`].
+#[derive(Clone)]
+pub struct DBSnapshot {
+    source: Arc<dyn ObjectStore>,
+    manifest: SnapshotManifest,
+    /// Bytes of `manifest.objects`, in the same order.
+    data: Option<Arc<[Bytes]>>,
+}
+
+impl std::fmt::Debug for DBSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DBSnapshot")
+            .field("manifest", &self.manifest)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DBSnapshot {
+    /// Rebuilds a snapshot from a previously captured manifest.
+    ///
+    /// The objects are read from `source` at their pinned versions when the
+    /// snapshot is materialized.
+    pub fn from_manifest(source: Arc<dyn ObjectStore>, manifest: SnapshotManifest) -> Self {
+        Self {
+            source,
+            manifest,
+            data: None,
+        }
+    }
+
+    /// Builds a snapshot from the bytes copied for every manifest object.
+    pub(crate) fn from_captured(
+        source: Arc<dyn ObjectStore>,
+        manifest: SnapshotManifest,
+        data: Vec<Bytes>,
+    ) -> Self {
+        debug_assert_eq!(manifest.objects.len(), data.len());
+        Self {
+            source,
+            manifest,
+            data: Some(data.into()),
+        }
+    }
+
+    /// Returns the pinned object set.
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// Reads a pinned object as stored.
+    ///
+    /// Returns the copied bytes when the snapshot holds them. Otherwise fails
+    /// with [`DBError::Precondition`] (or [`DBError::NotFound`]) when the
+    /// pinned version is no longer available.
+    pub async fn read_object(&self, object: &SnapshotObject) -> Result<Bytes, DBError> {
+        if let Some(data) = &self.data
+            && let Some(pos) = self
+                .manifest
+                .objects
+                .binary_search_by(|obj| obj.path.cmp(&object.path))
+                .ok()
+                .filter(|&pos| self.manifest.objects[pos] == *object)
+        {
+            return Ok(data[pos].clone());
+        }
+        read_pinned(
+            self.source.as_ref(),
+            &self.manifest.full_path(&object.path),
+            object,
+        )
+        .await
+    }
+
+    /// Writes the snapshot as a self-contained archive.
+    ///
+    /// Returns the number of object bytes written.
+    pub async fn export<W>(&self, writer: &mut W) -> Result<u64, DBError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut manifest = Vec::new();
+        to_writer(&self.manifest, &mut manifest).map_err(|err| DBError::Serialization {
+            name: "snapshot manifest".to_string(),
+            source: err.into(),
+        })?;
+
+        let mut header = Vec::with_capacity(ARCHIVE_MAGIC.len() + 12 + manifest.len());
+        header.extend_from_slice(ARCHIVE_MAGIC);
+        header.extend_from_slice(&ARCHIVE_FORMAT.to_le_bytes());
+        header.extend_from_slice(&(manifest.len() as u64).to_le_bytes());
+        header.extend_from_slice(&manifest);
+        writer.write_all(&header).await.map_err(archive_error)?;
+
+        let mut written = 0u64;
+        for object in &self.manifest.objects {
+            let data = self.read_object(object).await?;
+            writer
+                .write_all(&(data.len() as u64).to_le_bytes())
+                .await
+                .map_err(archive_error)?;
+            writer.write_all(&data).await.map_err(archive_error)?;
+            written += data.len() as u64;
+        }
+        writer.flush().await.map_err(archive_error)?;
+        Ok(written)
+    }
+
+    /// Copies every pinned object into `target` under the same paths.
+    ///
+    /// Objects are created, never overwritten: restoring into a store that
+    /// already holds the database fails with [`DBError::AlreadyExists`].
+    pub(crate) async fn restore(&self, target: &dyn ObjectStore) -> Result<(), DBError> {
+        let mut created = Vec::with_capacity(self.manifest.objects.len());
+        let rt = async {
+            for object in &self.manifest.objects {
+                let data = self.read_object(object).await?;
+                let location = self.manifest.full_path(&object.path);
+                put_new(target, location.clone(), data).await?;
+                created.push(location);
+            }
+            Ok(())
+        }
+        .await;
+        if rt.is_err() {
+            remove_created(target, &created).await;
+        }
+        rt
+    }
+}
+
+/// Restores an archive written by [`DBSnapshot::export`] into `target`.
+pub(crate) async fn restore_archive<R>(
+    reader: &mut R,
+    target: &dyn ObjectStore,
+) -> Result<SnapshotManifest, DBError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).await.map_err(archive_error)?;
+    if &magic != ARCHIVE_MAGIC {
+        return Err(DBError::Serialization {
+            name: "snapshot archive".to_string(),
+            source: "not a snapshot archive".into(),
+        });
+    }
+    let format = reader.read_u32_le().await.map_err(archive_error)?;
+    if format != ARCHIVE_FORMAT {
+        return Err(DBError::Serialization {
+            name: "snapshot archive".to_string(),
+            source: format!("unsupported snapshot archive format {format}").into(),
+        });
+    }
+
+    let manifest_len = reader.read_u64_le().await.map_err(archive_error)?;
+    let manifest = read_entry(reader, "manifest", manifest_len).await?;
+    let manifest: SnapshotManifest =
+        from_reader(&manifest[..]).map_err(|err| DBError::Serialization {
+            name: "snapshot manifest".to_string(),
+            source: err.into(),
+        })?;
+
+    let mut created = Vec::with_capacity(manifest.objects.len());
+    let rt = async {
+        for object in &manifest.objects {
+            let len = reader.read_u64_le().await.map_err(archive_error)?;
+            if len != object.size {
+                return Err(DBError::Serialization {
+                    name: "snapshot archive".to_string(),
+                    source: format!(
+                        "object {} has {len} bytes, manifest records {}",
+                        object.path, object.size
+                    )
+                    .into(),
+                });
+            }
+            let data = read_entry(reader, &object.path, len).await?;
+            let location = manifest.full_path(&object.path);
+            put_new(target, location.clone(), data.into()).await?;
+            created.push(location);
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(err) = rt {
+        remove_created(target, &created).await;
+        return Err(err);
+    }
+    Ok(manifest)
+}
+
+/// Reads the `len` bytes of archive entry `name`.
+async fn read_entry<R>(reader: &mut R, name: &str, len: u64) -> Result<Vec<u8>, DBError>
+where
+    R: AsyncRead + Unpin,
+{
+    if len > MAX_ARCHIVE_ENTRY_SIZE {
+        return Err(DBError::Serialization {
+            name: "snapshot archive".to_string(),
+            source: format!(
+                "entry {name} has {len} bytes, more than the {MAX_ARCHIVE_ENTRY_SIZE} byte limit"
+            )
+            .into(),
+        });
+    }
+    let mut data = Vec::with_capacity(len.min(ARCHIVE_READ_RESERVE) as usize);
+    (&mut *reader)
+        .take(len)
+        .read_to_end(&mut data)
+        .await
+        .map_err(archive_error)?;
+    if data.len() as u64 != len {
+        return Err(archive_error(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(data)
+}
+
+/// Reads `object` from `source` at its pinned version.
+pub(crate) async fn read_pinned(
+    source: &dyn ObjectStore,
+    location: &Path,
+    object: &SnapshotObject,
+) -> Result<Bytes, DBError> {
+    let options = GetOptions {
+        if_match: object.version.e_tag.clone(),
+        version: object.version.version.clone(),
+        ..Default::default()
+    };
+    let data = source.get_opts(location, options).await?.bytes().await?;
+    if data.len() as u64 != object.size {
+        return Err(DBError::Precondition {
+            path: location.to_string(),
+            source: format!(
+                "snapshot object size changed: expected {}, got {}",
+                object.size,
+                data.len()
+            )
+            .into(),
+        });
+    }
+    Ok(data)
+}
+
+/// Deletes the objects a failed restore created, newest first. Failures are
+/// logged: the restore error is what the caller needs to see.
+async fn remove_created(target: &dyn ObjectStore, created: &[Path]) {
+    for location in created.iter().rev() {
+        if let Err(err) = target.delete(location).await {
+            log::error!(
+                action = "snapshot::restore",
+                path = location.as_ref();
+                "Failed to remove a partially restored object: {err:?}",
+            );
+        }
+    }
+}
+
+async fn put_new(target: &dyn ObjectStore, location: Path, data: Bytes) -> Result<(), DBError> {
+    target
+        .put_opts(
+            &location,
+            PutPayload::from_bytes(data),
+            PutOptions {
+                mode: PutMode::Create,
+                ..Default::default()
+            },
+        )
+        .await?;
+    Ok(())
+}
+
+fn archive_error(err: std::io::Error) -> DBError {
+    DBError::Storage {
+        name: "snapshot archive".to_string(),
+        source: err.into(),
+    }
+}
@@ -0,0 +1,207 @@
+//! Streaming document export and import.
+//!
+//! [`Collection::export_stream`](crate::collection::Collection::export_stream)
+//! encodes a collection as a sequence of frames: an [`ExportHeader`] carrying
+//! the collection schema, followed by one frame per document in ascending id
+//! order. [`Collection::import_stream`](crate::collection::Collection::import_stream)
+//! reads the same frames back into any collection whose schema shares the
+//! exported field names.
+//!
+//! Two framings are supported (see [`ExportFormat`]):
+//!
+//! - NDJSON: one JSON value per line, human-readable and tool-friendly.
+//! - CBOR: `len: u32 LE | CBOR value` per frame, compact and lossless.
+//!
+//! Documents are written in their stored form (`{"f": {idx: value}}`); the
+//! importer maps field indexes through the header schema by field name, so
+//! the target collection may have been created with a redesigned schema.
+use bytes::Bytes;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+
+use crate::{
+    error::DBError,
+    schema::{DocumentId, Schema},
+};
+
+/// Version of the export frame layout written by this crate.
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Frame encoding of an export stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// Newline-delimited JSON.
+    #[default]
+    Ndjson,
+    /// Length-prefixed CBOR.
+    Cbor,
+}
+
+/// First frame of every export stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportHeader {
+    /// Frame layout version, [`EXPORT_FORMAT_VERSION`] when written.
+    pub version: u32,
+    /// Name of the exported collection.
+    pub collection: String,
+    /// Schema the exported documents were stored with.
+    pub schema: Schema,
+    /// Number of documents in the collection when the export started.
+    /// Documents removed while exporting are skipped, so the stream may
+    /// contain fewer.
+    pub count: u64,
+}
+
+/// How [`Collection::import_stream`](crate::collection::Collection::import_stream)
+/// assigns document ids.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportIds {
+    /// Ignores the exported `_id` and allocates a fresh id per document.
+    #[default]
+    Remap,
+    /// Keeps the exported `_id`. Ids must be ascending and greater than
+    /// every id the target collection has allocated, which holds when
+    /// importing an export into an empty collection.
+    Preserve,
+}
+
+/// Options for [`Collection::import_stream`](crate::collection::Collection::import_stream).
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Id assignment policy.
+    pub ids: ImportIds,
+    /// Documents written and indexed per batch.
+    pub batch_size: usize,
+    /// Drops values of fields the target schema does not declare instead of
+    /// failing the import.
+    pub skip_unknown_fields: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            ids: ImportIds::Remap,
+            batch_size: 1000,
+            skip_unknown_fields: false,
+        }
+    }
+}
+
+/// Outcome of a completed import.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Number of imported documents.
+    pub imported: u64,
+    /// `(exported id, imported id)` for every document, in stream order.
+    pub ids: Vec<(DocumentId, DocumentId)>,
+}
+
+/// Encodes one frame of an export stream.
+pub(crate) fn encode_frame<T: Serialize>(
+    format: ExportFormat,
+    value: &T,
+) -> Result<Bytes, DBError> {
+    let mut buf = Vec::with_capacity(256);
+    match format {
+        ExportFormat::Ndjson => {
+            serde_json::to_writer(&mut buf, value).map_err(|err| DBError::Serialization {
+                name: "export frame".to_string(),
+                source: err.into(),
+            })?;
+            buf.push(b'\n');
+        }
+        ExportFormat::Cbor => {
+            buf.extend_from_slice(&[0u8; 4]);
+            cbor2::to_writer(value, &mut buf).map_err(|err| DBError::Serialization {
+                name: "export frame".to_string(),
+                source: err.into(),
+            })?;
+            let len = u32::try_from(buf.len() - 4).map_err(|_| DBError::Serialization {
+                name: "export frame".to_string(),
+                source: format!("frame of {} bytes exceeds u32::MAX", buf.len() - 4).into(),
+            })?;
+            buf[..4].copy_from_slice(&len.to_le_bytes());
+        }
+    }
+    Ok(buf.into())
+}
+
+/// Reads the frames of an export stream.
+pub(crate) struct FrameReader<R> {
+    reader: BufReader<R>,
+    format: ExportFormat,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub(crate) fn new(reader: R, format: ExportFormat) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            format,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns the next frame, or `None` at a clean end of stream.
+    pub(crate) async fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, DBError> {
+        self.buf.clear();
+        match self.format {
+            ExportFormat::Ndjson => loop {
+                if self
+                    .reader
+                    .read_until(b'\n', &mut self.buf)
+                    .await
+                    .map_err(read_error)?
+                    == 0
+                {
+                    return Ok(None);
+                }
+                if !self.buf.trim_ascii().is_empty() {
+                    break;
+                }
+                self.buf.clear();
+            },
+            ExportFormat::Cbor => {
+                // Only a stream that ends exactly at a frame boundary is a
+                // clean end; a torn length prefix is an error.
+                if self.reader.fill_buf().await.map_err(read_error)?.is_empty() {
+                    return Ok(None);
+                }
+                let mut len = [0u8; 4];
+                self.reader.read_exact(&mut len).await.map_err(read_error)?;
+                let len = u32::from_le_bytes(len) as u64;
+                (&mut self.reader)
+                    .take(len)
+                    .read_to_end(&mut self.buf)
+                    .await
+                    .map_err(read_error)?;
+                if self.buf.len() as u64 != len {
+                    return Err(read_error(std::io::ErrorKind::UnexpectedEof.into()));
+                }
+            }
+        }
+
+        let value = match self.format {
+            ExportFormat::Ndjson => {
+                serde_json::from_slice(&self.buf).map_err(|err| DBError::Serialization {
+                    name: "import frame".to_string(),
+                    source: err.into(),
+                })?
+            }
+            ExportFormat::Cbor => {
+                cbor2::from_reader(&self.buf[..]).map_err(|err| DBError::Serialization {
+                    name: "import frame".to_string(),
+                    source: err.into(),
+                })?
+            }
+        };
+        Ok(Some(value))
+    }
+}
+
+fn read_error(err: std::io::Error) -> DBError {
+    DBError::Storage {
+        name: "import stream".to_string(),
+        source: err.into(),
+    }
+}
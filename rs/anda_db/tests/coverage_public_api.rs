@@ -68,6 +68,7 @@ fn collection_config(name: &str) -> CollectionConfig {
     CollectionConfig {
         name: name.to_string(),
         description: "coverage collection".to_string(),
+        ..Default::default()
     }
 }
 
@@ -555,6 +556,7 @@ async fn collection_public_error_paths_and_id_filters() -> Result<(), DBError> {
             search: None,
             filter: None,
             limit: Some(2),
+            ..Default::default()
         })
         .await?;
     assert!(search_none.is_empty());
@@ -572,6 +574,7 @@ async fn collection_public_error_paths_and_id_filters() -> Result<(), DBError> {
                 }),
                 filter: Some(id_filter(RangeQuery::Lt(Fv::U64(5)))),
                 limit: Some(2),
+                ..Default::default()
             })
             .await?,
         vec![1, 2]
@@ -653,6 +656,7 @@ async fn collection_search_update_remove_and_extensions() -> Result<(), DBError>
                 RangeQuery::Ge(Fv::U64(20)),
             ))),
             limit: Some(2),
+            ..Default::default()
         })
         .await?;
     assert!(filtered_candidates.iter().all(|id| *id != alpha_id));
@@ -16,12 +16,13 @@
 //! - the database accepts new writes after recovery.
 
 use anda_db::{
+    batch::WriteBatch,
     collection::{Collection, CollectionConfig},
     database::{AndaDB, DBConfig},
     error::DBError,
     index::HnswConfig,
     query::{Filter, Query, RangeQuery},
-    schema::{AndaDBSchema, Fv, Vector, bf16},
+    schema::{AndaDBSchema, Document, Fv, Vector, bf16},
     storage::StorageConfig,
     unix_ms,
 };
@@ -70,6 +71,7 @@ async fn open_docs_collection(db: &AndaDB) -> Result<Arc<Collection>, DBError> {
         CollectionConfig {
             name: "docs".to_string(),
             description: "crash recovery docs".to_string(),
+            ..Default::default()
         },
         async |collection| {
             collection.create_btree_index_nx(&["age"]).await?;
@@ -425,3 +427,182 @@ async fn corrupted_objects_error_not_panic() {
         Err(DBError::Serialization { .. })
     ));
 }
+
+async fn open_threads_collection(db: &AndaDB) -> Result<Arc<Collection>, DBError> {
+    db.open_or_create_collection(
+        CrashDoc::schema()?,
+        CollectionConfig {
+            name: "threads".to_string(),
+            description: "crash recovery threads".to_string(),
+            ..Default::default()
+        },
+        async |collection| {
+            collection.create_btree_index_nx(&["age"]).await?;
+            Ok(())
+        },
+    )
+    .await
+}
+
+/// Seeds one document per collection, flushes, then commits a batch that
+/// adds `beta` to docs, removes `alpha` from docs and updates the thread.
+/// `batch_started` is set once the seed state is durable.
+async fn run_batch_workload(
+    store: Arc<dyn ObjectStore>,
+    batch_started: &mut bool,
+) -> Result<Vec<u64>, DBError> {
+    let db = AndaDB::connect(store, db_config()).await?;
+    let docs = open_docs_collection(&db).await?;
+    let threads = open_threads_collection(&db).await?;
+    docs.add_from(&crash_doc("alpha", 10)).await?;
+    threads.add_from(&crash_doc("thread", 100)).await?;
+    db.flush().await?;
+    *batch_started = true;
+
+    let mut batch = WriteBatch::new();
+    batch
+        .add(
+            "docs",
+            Document::try_from(docs.schema(), &crash_doc("beta", 20))?,
+        )
+        .remove("docs", 1)
+        .update(
+            "threads",
+            1,
+            BTreeMap::from([("age".to_string(), Fv::U64(101))]),
+        );
+    db.write_batch(batch).await
+}
+
+/// Checks that the batch of [`run_batch_workload`] is either fully visible,
+/// through documents and indexes, or not at all. Returns whether it was.
+async fn verify_batch_atomic(db: &AndaDB, crash_point: u64) -> Result<bool, DBError> {
+    let docs = open_docs_collection(db).await?;
+    let threads = open_threads_collection(db).await?;
+
+    let committed = match docs.get_as::<CrashDoc>(2).await {
+        Ok(beta) => {
+            assert_eq!(beta.name, "beta", "crash point {crash_point}");
+            true
+        }
+        Err(DBError::NotFound { .. }) => false,
+        Err(err) => panic!("crash point {crash_point}: beta unreadable: {err:?}"),
+    };
+    assert_eq!(
+        matches!(docs.get(1).await, Err(DBError::NotFound { .. })),
+        committed,
+        "crash point {crash_point}: alpha removal is not atomic with the batch"
+    );
+    let thread: CrashDoc = threads.get_as(1).await?;
+    assert_eq!(
+        thread.age == 101,
+        committed,
+        "crash point {crash_point}: thread update is not atomic with the batch"
+    );
+
+    let (added, removed) = if committed {
+        (vec![2], vec![])
+    } else {
+        (vec![], vec![1])
+    };
+    assert_eq!(
+        ids_with_age(&docs, 20).await?,
+        added,
+        "crash point {crash_point}"
+    );
+    assert_eq!(
+        ids_with_age(&docs, 10).await?,
+        removed,
+        "crash point {crash_point}"
+    );
+    assert_eq!(
+        ids_with_age(&threads, 101).await?.len(),
+        usize::from(committed),
+        "crash point {crash_point}"
+    );
+    assert_eq!(
+        ids_with_age(&threads, 100).await?.len(),
+        usize::from(!committed),
+        "crash point {crash_point}"
+    );
+    assert_eq!(docs.len(), 1, "crash point {crash_point}");
+    Ok(committed)
+}
+
+#[tokio::test]
+async fn write_batch_is_atomic_at_every_crash_point() {
+    let (store, handle) = FaultStore::wrap(InMemory::new());
+    let store = Arc::new(store);
+    let mut batch_started = false;
+    let ids = run_batch_workload(store.clone(), &mut batch_started)
+        .await
+        .expect("clean batch workload failed");
+    assert_eq!(ids, vec![2, 1, 1]);
+    let total = handle.mutation_count();
+
+    let (mut committed, mut aborted) = (0, 0);
+    for crash_point in 0..total {
+        let (store, handle) = FaultStore::wrap(InMemory::new());
+        let store = Arc::new(store);
+        handle.crash_after_mutations(crash_point);
+        let mut batch_started = false;
+        let _ = run_batch_workload(store.clone(), &mut batch_started).await;
+        if !batch_started {
+            // The crash hit the seed phase, which the other crash test covers.
+            continue;
+        }
+
+        handle.reset();
+        let db = AndaDB::connect(store, db_config()).await.unwrap();
+        let visible = verify_batch_atomic(&db, crash_point)
+            .await
+            .unwrap_or_else(|err| panic!("crash point {crash_point}: reopen failed: {err:?}"));
+        if visible {
+            committed += 1;
+        } else {
+            aborted += 1;
+        }
+        db.close().await.unwrap();
+    }
+    assert!(
+        committed > 0 && aborted > 0,
+        "{committed} committed, {aborted} aborted"
+    );
+}
+
+/// A document write failing after the commit point poisons the touched
+/// collections; reopening one of them rolls the batch forward first.
+#[tokio::test]
+async fn write_batch_apply_failure_is_rolled_forward_on_collection_reopen() {
+    let (store, handle) = FaultStore::wrap(InMemory::new());
+    let store = Arc::new(store);
+    let db = AndaDB::connect(store.clone(), db_config()).await.unwrap();
+    let docs = open_docs_collection(&db).await.unwrap();
+    let threads = open_threads_collection(&db).await.unwrap();
+    docs.add_from(&crash_doc("alpha", 10)).await.unwrap();
+    threads.add_from(&crash_doc("thread", 100)).await.unwrap();
+    db.flush().await.unwrap();
+
+    handle.push_rule(FaultRule::fail_once(FaultOp::Put, "threads/data/"));
+    let mut batch = WriteBatch::new();
+    batch
+        .add(
+            "docs",
+            Document::try_from(docs.schema(), &crash_doc("beta", 20)).unwrap(),
+        )
+        .remove("docs", 1)
+        .update(
+            "threads",
+            1,
+            BTreeMap::from([("age".to_string(), Fv::U64(101))]),
+        );
+    assert!(db.write_batch(batch).await.is_err());
+    assert!(docs.is_poisoned());
+    assert!(threads.is_poisoned());
+
+    assert!(verify_batch_atomic(&db, 0).await.unwrap());
+    db.close().await.unwrap();
+
+    let db = AndaDB::connect(store, db_config()).await.unwrap();
+    assert!(verify_batch_atomic(&db, 0).await.unwrap());
+}
@@ -109,6 +109,7 @@ async fn open_docs_collection(db: &AndaDB) -> Result<Arc<Collection>, DBError> {
         CollectionConfig {
             name: "docs".to_string(),
             description: "format compatibility docs".to_string(),
+            ..Default::default()
         },
         async |collection| {
             collection.create_btree_index_nx(&["age"]).await?;
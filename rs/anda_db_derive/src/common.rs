@@ -556,6 +556,7 @@ pub fn find_field_type_attr(
 /// type        := primitive | array | option | map
 /// primitive   := "Bytes" | "Text" | "U64" | "I64"
 ///              | "F64"   | "F32"  | "Bool" | "Json" | "Vector"
+///              | "SparseVector"
 /// array       := "Array<" type ">"
 /// option      := "Option<" type ">"
 /// map         := "Map<" map_key "," type ">"
@@ -585,6 +586,7 @@ pub fn parse_field_type_str(
         "Bool" => Ok(quote! { #root::FieldType::Bool }),
         "Json" => Ok(quote! { #root::FieldType::Json }),
         "Vector" => Ok(quote! { #root::FieldType::Vector }),
+        "SparseVector" => Ok(quote! { #root::FieldType::SparseVector }),
 
         // Compound wrappers: Array<T>, Option<T>.
         s if s.starts_with("Array<") && s.ends_with('>') => {
@@ -654,7 +656,7 @@ pub fn parse_field_type_str(
         _ => Err(syn::Error::new(
             span,
             format!(
-                "Unsupported field type: '{type_str}'. Supported types: Bytes, Text, U64, I64, F64, F32, Bool, Json, Vector, Array<T>, Option<T>, Map<String, T>, Map<Text, T>, Map<I64, T>, Map<Bytes, T>"
+                "Unsupported field type: '{type_str}'. Supported types: Bytes, Text, U64, I64, F64, F32, Bool, Json, Vector, SparseVector, Array<T>, Option<T>, Map<String, T>, Map<Text, T>, Map<I64, T>, Map<Bytes, T>"
             ),
         )),
     }
@@ -792,6 +794,7 @@ pub fn determine_field_type(
                 }
                 "Json" => Ok(quote! { #root::FieldType::Json }),
                 "Vector" => Ok(quote! { #root::FieldType::Vector }),
+                "SparseVector" => Ok(quote! { #root::FieldType::SparseVector }),
                 "bf16" => unsupported_scalar_bf16(ty),
                 "HashMap" | "BTreeMap" | "Map" => {
                     // Handle HashMap / BTreeMap / serde_json::Map. Extra
@@ -1273,6 +1276,10 @@ mod tests {
             ("Bool", ":: anda_db_schema :: FieldType :: Bool"),
             ("Json", ":: anda_db_schema :: FieldType :: Json"),
             ("Vector", ":: anda_db_schema :: FieldType :: Vector"),
+            (
+                "SparseVector",
+                ":: anda_db_schema :: FieldType :: SparseVector",
+            ),
         ] {
             assert_eq!(tokens(parse_ft(input).unwrap()), expected);
         }
@@ -0,0 +1,54 @@
+use anda_db_hnsw::{DistanceMetric, half::bf16};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const DIMENSIONS: [usize; 3] = [128, 384, 1536];
+
+const METRICS: [DistanceMetric; 4] = [
+    DistanceMetric::Euclidean,
+    DistanceMetric::Cosine,
+    DistanceMetric::InnerProduct,
+    DistanceMetric::Manhattan,
+];
+
+/// Deterministic values in [-1, 1); keeps the inputs stable across runs.
+fn vector(dims: usize, seed: u32) -> Vec<f32> {
+    (0..dims as u32)
+        .map(|i| {
+            let x = i
+                .wrapping_mul(2_654_435_761)
+                .wrapping_add(seed.wrapping_mul(40_503));
+            (x >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+        })
+        .collect()
+}
+
+// cargo bench -p anda_db_hnsw --bench distance -- --save-baseline initial
+// cargo bench -p anda_db_hnsw --bench distance -- --baseline initial
+// cargo bench -p anda_db_hnsw --bench distance
+pub fn criterion_benchmark(c: &mut Criterion) {
+    for metric in METRICS {
+        let mut group = c.benchmark_group(format!("distance/{metric:?}"));
+        for dims in DIMENSIONS {
+            let a = vector(dims, 1);
+            let b = vector(dims, 2);
+            let a_bf16: Vec<bf16> = a.iter().copied().map(bf16::from_f32).collect();
+            let b_bf16: Vec<bf16> = b.iter().copied().map(bf16::from_f32).collect();
+            group.throughput(Throughput::Elements(dims as u64));
+
+            group.bench_with_input(BenchmarkId::new("bf16_bf16", dims), &dims, |bench, _| {
+                bench.iter(|| metric.compute(black_box(&a_bf16), black_box(&b_bf16)))
+            });
+            group.bench_with_input(BenchmarkId::new("f32_bf16", dims), &dims, |bench, _| {
+                bench.iter(|| metric.compute_mixed(black_box(&a), black_box(&b_bf16)))
+            });
+            group.bench_with_input(BenchmarkId::new("f32_f32", dims), &dims, |bench, _| {
+                bench.iter(|| metric.compute_f32(black_box(&a), black_box(&b)))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
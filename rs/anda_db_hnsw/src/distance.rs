@@ -2,7 +2,10 @@ use half::bf16;
 use rand::{distr::Uniform, prelude::*, rng};
 use serde::{Deserialize, Serialize};
 
-use crate::error::HnswError;
+use crate::{
+    error::HnswError,
+    simd::{self, AsF32, Backend},
+};
 
 /// Distance metric used for similarity computation.
 ///
@@ -26,7 +29,9 @@ impl DistanceMetric {
     /// Computes the distance between two `bf16` vectors.
     ///
     /// Internally promotes each element to `f32` before accumulating; the
-    /// result is returned as `f32`.
+    /// result is returned as `f32`. Like the other `compute*` methods, this
+    /// runs the widest SIMD kernel the CPU supports (AVX-512, AVX2 + FMA or
+    /// NEON), detected once at runtime, and falls back to a scalar loop.
     ///
     /// # Errors
     /// Returns [`HnswError::DimensionMismatch`] if `a.len() != b.len()`.
@@ -62,11 +67,24 @@ impl DistanceMetric {
 
     #[inline]
     fn dispatch<A: AsF32, B: AsF32>(&self, a: &[A], b: &[B]) -> f32 {
+        self.dispatch_with(simd::backend(), a, b)
+    }
+
+    #[inline]
+    fn dispatch_with<A: AsF32, B: AsF32>(&self, backend: Backend, a: &[A], b: &[B]) -> f32 {
         match self {
-            DistanceMetric::Euclidean => euclidean_distance(a, b),
-            DistanceMetric::Cosine => cosine_distance(a, b),
-            DistanceMetric::InnerProduct => inner_product(a, b),
-            DistanceMetric::Manhattan => manhattan_distance(a, b),
+            DistanceMetric::Euclidean => simd::sum_squared_diff(backend, a, b).sqrt(),
+            DistanceMetric::Cosine => {
+                let (dot, norm_a2, norm_b2) = simd::dot_and_norms(backend, a, b);
+                let norm_a = norm_a2.sqrt();
+                let norm_b = norm_b2.sqrt();
+                if norm_a < f32::EPSILON || norm_b < f32::EPSILON {
+                    return 1.0;
+                }
+                1.0 - (dot / (norm_a * norm_b)).clamp(-1.0, 1.0)
+            }
+            DistanceMetric::InnerProduct => -simd::dot(backend, a, b),
+            DistanceMetric::Manhattan => simd::sum_abs_diff(backend, a, b),
         }
     }
 }
@@ -167,121 +185,10 @@ impl LayerGen {
     }
 }
 
-/// Element types that promote losslessly to `f32` for distance computation.
-trait AsF32: Copy {
-    fn as_f32(self) -> f32;
-}
-
-impl AsF32 for f32 {
-    #[inline(always)]
-    fn as_f32(self) -> f32 {
-        self
-    }
-}
-
-impl AsF32 for bf16 {
-    #[inline(always)]
-    fn as_f32(self) -> f32 {
-        self.to_f32()
-    }
-}
-
-/// Unroll width for the distance kernels below. Eight independent `f32`
-/// accumulators break the floating-point add dependency chain so the loop can
-/// be pipelined / auto-vectorized; the slight change in summation order is
-/// well within `bf16` quantization noise.
-const LANES: usize = 8;
-
-#[inline]
-fn euclidean_distance<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
-    let mut acc = [0.0f32; LANES];
-    let mut chunks_a = a.chunks_exact(LANES);
-    let mut chunks_b = b.chunks_exact(LANES);
-    for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
-        for i in 0..LANES {
-            let d = ca[i].as_f32() - cb[i].as_f32();
-            acc[i] += d * d;
-        }
-    }
-    let mut sum: f32 = acc.iter().sum();
-    for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
-        let d = x.as_f32() - y.as_f32();
-        sum += d * d;
-    }
-    sum.sqrt()
-}
-
-#[inline]
-fn cosine_distance<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
-    let mut dot = [0.0f32; LANES];
-    let mut norm_a2 = [0.0f32; LANES];
-    let mut norm_b2 = [0.0f32; LANES];
-    let mut chunks_a = a.chunks_exact(LANES);
-    let mut chunks_b = b.chunks_exact(LANES);
-    for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
-        for i in 0..LANES {
-            let x = ca[i].as_f32();
-            let y = cb[i].as_f32();
-            dot[i] += x * y;
-            norm_a2[i] += x * x;
-            norm_b2[i] += y * y;
-        }
-    }
-    let mut dot_sum: f32 = dot.iter().sum();
-    let mut norm_a2_sum: f32 = norm_a2.iter().sum();
-    let mut norm_b2_sum: f32 = norm_b2.iter().sum();
-    for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
-        let x = x.as_f32();
-        let y = y.as_f32();
-        dot_sum += x * y;
-        norm_a2_sum += x * x;
-        norm_b2_sum += y * y;
-    }
-    let norm_a = norm_a2_sum.sqrt();
-    let norm_b = norm_b2_sum.sqrt();
-    if norm_a < f32::EPSILON || norm_b < f32::EPSILON {
-        return 1.0;
-    }
-    1.0 - (dot_sum / (norm_a * norm_b)).clamp(-1.0, 1.0)
-}
-
-#[inline]
-fn inner_product<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
-    let mut acc = [0.0f32; LANES];
-    let mut chunks_a = a.chunks_exact(LANES);
-    let mut chunks_b = b.chunks_exact(LANES);
-    for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
-        for i in 0..LANES {
-            acc[i] += ca[i].as_f32() * cb[i].as_f32();
-        }
-    }
-    let mut dot: f32 = acc.iter().sum();
-    for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
-        dot += x.as_f32() * y.as_f32();
-    }
-    -dot
-}
-
-#[inline]
-fn manhattan_distance<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
-    let mut acc = [0.0f32; LANES];
-    let mut chunks_a = a.chunks_exact(LANES);
-    let mut chunks_b = b.chunks_exact(LANES);
-    for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
-        for i in 0..LANES {
-            acc[i] += (ca[i].as_f32() - cb[i].as_f32()).abs();
-        }
-    }
-    let mut sum: f32 = acc.iter().sum();
-    for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
-        sum += (x.as_f32() - y.as_f32()).abs();
-    }
-    sum
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_layer_distribution() {
@@ -349,37 +256,41 @@ mod tests {
             v2.push(rng.random::<f32>());
         }
 
-        // Euclidean.
-        let impl_euclidean = euclidean_distance(&v1, &v2);
-        let scalar_euclidean = euclidean_distance_scalar(&v1, &v2);
-        assert!(
-            (impl_euclidean - scalar_euclidean).abs() < 1e-4,
-            "euclidean: impl={impl_euclidean}, scalar={scalar_euclidean}"
-        );
-
-        // Cosine.
-        let impl_cosine = cosine_distance(&v1, &v2);
-        let scalar_cosine = cosine_distance_scalar(&v1, &v2);
-        assert!(
-            (impl_cosine - scalar_cosine).abs() < 1e-4,
-            "cosine: impl={impl_cosine}, scalar={scalar_cosine}"
-        );
-
-        // Inner product.
-        let impl_inner = inner_product(&v1, &v2);
-        let scalar_inner = inner_product_scalar(&v1, &v2);
-        assert!(
-            (impl_inner - scalar_inner).abs() < 1e-4,
-            "inner: impl={impl_inner}, scalar={scalar_inner}"
-        );
-
-        // Manhattan.
-        let impl_manhattan = manhattan_distance(&v1, &v2);
-        let scalar_manhattan = manhattan_distance_scalar(&v1, &v2);
-        assert!(
-            (impl_manhattan - scalar_manhattan).abs() < 1e-4,
-            "manhattan: impl={impl_manhattan}, scalar={scalar_manhattan}"
-        );
+        for backend in simd::available_backends() {
+            let metric_of = |metric: DistanceMetric| metric.dispatch_with(backend, &v1, &v2);
+
+            // Euclidean.
+            let impl_euclidean = metric_of(DistanceMetric::Euclidean);
+            let scalar_euclidean = euclidean_distance_scalar(&v1, &v2);
+            assert!(
+                (impl_euclidean - scalar_euclidean).abs() < 1e-4,
+                "{backend:?} euclidean: impl={impl_euclidean}, scalar={scalar_euclidean}"
+            );
+
+            // Cosine.
+            let impl_cosine = metric_of(DistanceMetric::Cosine);
+            let scalar_cosine = cosine_distance_scalar(&v1, &v2);
+            assert!(
+                (impl_cosine - scalar_cosine).abs() < 1e-4,
+                "{backend:?} cosine: impl={impl_cosine}, scalar={scalar_cosine}"
+            );
+
+            // Inner product.
+            let impl_inner = metric_of(DistanceMetric::InnerProduct);
+            let scalar_inner = inner_product_scalar(&v1, &v2);
+            assert!(
+                (impl_inner - scalar_inner).abs() < 1e-4,
+                "{backend:?} inner: impl={impl_inner}, scalar={scalar_inner}"
+            );
+
+            // Manhattan.
+            let impl_manhattan = metric_of(DistanceMetric::Manhattan);
+            let scalar_manhattan = manhattan_distance_scalar(&v1, &v2);
+            assert!(
+                (impl_manhattan - scalar_manhattan).abs() < 1e-4,
+                "{backend:?} manhattan: impl={impl_manhattan}, scalar={scalar_manhattan}"
+            );
+        }
     }
 
     #[test]
@@ -481,4 +392,95 @@ mod tests {
             1.0
         );
     }
+
+    const METRICS: [DistanceMetric; 4] = [
+        DistanceMetric::Euclidean,
+        DistanceMetric::Cosine,
+        DistanceMetric::InnerProduct,
+        DistanceMetric::Manhattan,
+    ];
+
+    /// `f64` reference for the property tests below.
+    fn reference(metric: DistanceMetric, a: &[f32], b: &[f32]) -> f64 {
+        let pairs = a.iter().zip(b).map(|(&x, &y)| (x as f64, y as f64));
+        match metric {
+            DistanceMetric::Euclidean => pairs.map(|(x, y)| (x - y) * (x - y)).sum::<f64>().sqrt(),
+            DistanceMetric::Cosine => {
+                let (mut dot, mut na, mut nb) = (0.0, 0.0, 0.0);
+                for (x, y) in pairs {
+                    dot += x * y;
+                    na += x * x;
+                    nb += y * y;
+                }
+                let (na, nb) = (na.sqrt(), nb.sqrt());
+                if na < f32::EPSILON as f64 || nb < f32::EPSILON as f64 {
+                    1.0
+                } else {
+                    1.0 - (dot / (na * nb)).clamp(-1.0, 1.0)
+                }
+            }
+            DistanceMetric::InnerProduct => -pairs.map(|(x, y)| x * y).sum::<f64>(),
+            DistanceMetric::Manhattan => pairs.map(|(x, y)| (x - y).abs()).sum(),
+        }
+    }
+
+    /// Asserts that every backend agrees with the `f64` reference within a
+    /// tolerance proportional to the magnitude of the summed terms.
+    fn assert_backends_agree<A: AsF32, B: AsF32>(a: &[A], b: &[B]) {
+        let fa: Vec<f32> = a.iter().map(|v| v.as_f32()).collect();
+        let fb: Vec<f32> = b.iter().map(|v| v.as_f32()).collect();
+        let scale: f64 = fa
+            .iter()
+            .zip(&fb)
+            .map(|(&x, &y)| (x.abs() + y.abs()) as f64)
+            .map(|m| m * m.max(1.0))
+            .sum::<f64>()
+            .max(1.0);
+        for metric in METRICS {
+            let want = reference(metric, &fa, &fb);
+            for backend in simd::available_backends() {
+                let got = metric.dispatch_with(backend, a, b) as f64;
+                let tolerance = match metric {
+                    // Near-zero norms are compared against `EPSILON`; a
+                    // rounding difference can flip the `1.0` fallback.
+                    DistanceMetric::Cosine => 1e-3,
+                    _ => 1e-5 * scale,
+                };
+                assert!(
+                    (got - want).abs() <= tolerance,
+                    "{backend:?} {metric:?} dims {}: got {got}, want {want}",
+                    a.len()
+                );
+            }
+        }
+    }
+
+    fn vector_pair(max_dims: usize) -> impl Strategy<Value = (Vec<f32>, Vec<f32>)> {
+        (0..=max_dims).prop_flat_map(|dims| {
+            (
+                prop::collection::vec(-4.0f32..4.0, dims),
+                prop::collection::vec(-4.0f32..4.0, dims),
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_f32_kernels_match_reference((a, b) in vector_pair(300)) {
+            assert_backends_agree(&a, &b);
+        }
+
+        #[test]
+        fn prop_mixed_kernels_match_reference((a, b) in vector_pair(300)) {
+            let b: Vec<bf16> = b.into_iter().map(bf16::from_f32).collect();
+            assert_backends_agree(&a, &b);
+        }
+
+        #[test]
+        fn prop_bf16_kernels_match_reference((a, b) in vector_pair(300)) {
+            let a: Vec<bf16> = a.into_iter().map(bf16::from_f32).collect();
+            let b: Vec<bf16> = b.into_iter().map(bf16::from_f32).collect();
+            assert_backends_agree(&a, &b);
+        }
+    }
 }
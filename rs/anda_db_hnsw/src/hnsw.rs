@@ -29,7 +29,10 @@ use std::{
     collections::{BTreeSet, BinaryHeap, hash_map::Entry},
     future::Future,
     io::{Read, Write},
-    sync::atomic::{AtomicU64, Ordering},
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
 };
 
 pub use half;
@@ -37,6 +40,7 @@ pub use half;
 use crate::{
     DistanceMetric, LayerGen,
     error::{BoxError, HnswError},
+    quantization::{Quantization, Quantizer},
 };
 
 /// Concurrent, persistable HNSW index for approximate nearest-neighbor search.
@@ -107,6 +111,16 @@ pub struct HnswIndex {
     /// Roaring-bitmap index of live node ids. Kept in sync with `nodes`.
     ids: RwLock<Treemap>,
 
+    /// Quantizer trained once [`HnswConfig::quantization_training_size`]
+    /// vectors were inserted; unset while vectors are stored as `bf16`.
+    quantizer: OnceLock<Quantizer>,
+
+    /// Whether stored vectors are encoded with [`Self::quantizer`]. Set only
+    /// once the metadata carrying the quantizer is durable: node blobs are
+    /// persisted before the metadata, so encoding earlier could leave
+    /// encoded nodes on disk without their codebook after a crash.
+    quantized: AtomicBool,
+
     /// Live node ids grouped by their highest layer. Kept in sync with
     /// `nodes` (all mutations happen under `structural_lock`; the loaders
     /// rebuild it) so that removing the entry point or a top-layer node can
@@ -119,6 +133,10 @@ pub struct HnswIndex {
     /// no-op calls to [`Self::store_metadata`] and to make flushes idempotent
     /// under concurrent writers.
     last_saved_version: AtomicU64,
+
+    /// Beam width of searches without an override: `config.ef_search`, or
+    /// the value set by [`Self::set_ef_search`].
+    ef_search: AtomicUsize,
 }
 
 /// Tunable HNSW parameters. Defaults are suitable for 384–768-dim sentence
@@ -163,7 +181,8 @@ pub struct HnswConfig {
     ///   survivors' connectivity: under delete-heavy workloads recall can
     ///   degrade and a cluster reachable only through deleted nodes can
     ///   become unreachable. Compensate with periodic rebuilds (or
-    ///   re-inserts of affected regions) when deletions dominate.
+    ///   re-inserts of affected regions) when deletions dominate;
+    ///   [`HnswIndex::graph_health`] tells when one is due.
     /// * `true` — after pruning the reverse edges, the deleted node's
     ///   remaining neighbors are merged into each affected neighbor's
     ///   candidate set and the configured [`SelectNeighborsStrategy`]
@@ -182,6 +201,28 @@ pub struct HnswConfig {
     /// come from a release that never repaired on delete.
     #[serde(default)]
     pub reconnect_on_delete: bool,
+
+    /// Storage encoding of the vectors. Default [`Quantization::None`]
+    /// (`bf16`).
+    #[serde(default)]
+    pub quantization: Quantization,
+
+    /// Number of vectors the index must hold before a quantizer is trained
+    /// on them. Default `1024`.
+    #[serde(default = "default_quantization_training_size")]
+    pub quantization_training_size: usize,
+
+    /// Whether callers that keep the original vectors should re-rank the
+    /// final candidates of a quantized index by their exact distance.
+    /// `anda_db` collections do this from the indexed document field. The
+    /// index itself always returns distances to the quantized vectors.
+    /// Default `false`.
+    #[serde(default)]
+    pub exact_rerank: bool,
+}
+
+fn default_quantization_training_size() -> usize {
+    HnswConfig::DEFAULT_QUANTIZATION_TRAINING_SIZE
 }
 
 impl HnswConfig {
@@ -205,6 +246,10 @@ impl HnswConfig {
 
     /// Maximum search candidate-list breadth.
     pub const MAX_EF_SEARCH: usize = 4_096;
+    /// Default number of vectors a quantizer is trained on.
+    pub const DEFAULT_QUANTIZATION_TRAINING_SIZE: usize = 1_024;
+    /// Maximum number of vectors a quantizer is trained on.
+    pub const MAX_QUANTIZATION_TRAINING_SIZE: usize = 65_536;
 
     /// Creates a layer generator based on the configuration.
     ///
@@ -239,6 +284,12 @@ impl HnswConfig {
         {
             self.scale_factor = None;
         }
+        if self.quantization.validate(self.dimension).is_err() {
+            self.quantization = Quantization::None;
+        }
+        self.quantization_training_size = self
+            .quantization_training_size
+            .clamp(1, Self::MAX_QUANTIZATION_TRAINING_SIZE);
         self
     }
 
@@ -315,6 +366,20 @@ impl HnswConfig {
                 "scale_factor must be finite and greater than 0",
             ));
         }
+        if let Err(err) = self.quantization.validate(self.dimension) {
+            return Err(Self::invalid_config(name, err));
+        }
+        if self.quantization_training_size == 0
+            || self.quantization_training_size > Self::MAX_QUANTIZATION_TRAINING_SIZE
+        {
+            return Err(Self::invalid_config(
+                name,
+                format!(
+                    "quantization_training_size must be between 1 and {}",
+                    Self::MAX_QUANTIZATION_TRAINING_SIZE
+                ),
+            ));
+        }
         Ok(())
     }
 
@@ -338,6 +403,9 @@ impl Default for HnswConfig {
             scale_factor: None,
             select_neighbors_strategy: SelectNeighborsStrategy::Heuristic,
             reconnect_on_delete: false,
+            quantization: Quantization::None,
+            quantization_training_size: Self::DEFAULT_QUANTIZATION_TRAINING_SIZE,
+            exact_rerank: false,
         }
     }
 }
@@ -361,6 +429,8 @@ pub enum SelectNeighborsStrategy {
 ///
 /// A node records its highest layer, its stored vector and, for every layer
 /// from 0 up to [`HnswNode::layer`], the list of outgoing edges `(id, dist)`.
+/// The vector is stored either as `bf16` in [`HnswNode::vector`] or, once the
+/// index trained its [`Quantizer`], encoded in [`HnswNode::codes`].
 /// Distances are cached in `bf16` purely to shrink the persisted form; all
 /// computation is in `f32`.
 ///
@@ -375,9 +445,12 @@ pub struct HnswNode {
     #[serde(rename = "l")]
     pub layer: u8,
 
-    /// Stored vector in `bf16` format.
-    #[serde(rename = "vec")]
+    /// Stored vector in `bf16` format; empty when the vector is quantized.
+    #[serde(rename = "vec", default)]
     pub vector: Vec<bf16>,
+    /// Quantized vector; empty when the vector is stored as `bf16`.
+    #[serde(rename = "q", default, skip_serializing_if = "Vec::is_empty")]
+    pub codes: Vec<u8>,
 
     /// Adjacency lists indexed by layer (`neighbors[l]` = edges at layer `l`).
     #[serde(rename = "n")]
@@ -390,6 +463,27 @@ pub struct HnswNode {
     pub version: u64,
 }
 
+/// A node whose neighbors have been searched but that is not linked into the
+/// graph yet; see [`HnswIndex::insert_batch`].
+struct InsertPlan {
+    id: u64,
+    layer: u8,
+    vector: Vec<bf16>,
+    /// Forward edges per layer.
+    neighbors: Vec<SmallVec<[(u64, bf16); 64]>>,
+    /// Reverse edges to add: `neighbor_id -> [(layer, (id, dist))]`.
+    #[allow(clippy::type_complexity)]
+    reverse_edges: FxHashMap<u64, SmallVec<[(u8, (u64, bf16)); 8]>>,
+}
+
+/// The filter of a layer-0 traversal; see [`HnswIndex::search_filtered`].
+struct LayerFilter<'a> {
+    /// Ids allowed in the results.
+    allowed: &'a Treemap,
+    /// Number of visited nodes at which the traversal stops.
+    max_visits: usize,
+}
+
 /// Serializes a node to CBOR. Used by [`HnswIndex::store_dirty_nodes`] and by
 /// external tools that snapshot individual nodes.
 ///
@@ -416,6 +510,11 @@ pub struct HnswMetadata {
 
     /// Index statistics.
     pub stats: HnswStats,
+
+    /// `ef_search` set by [`HnswIndex::set_ef_search`], e.g. by
+    /// [`HnswIndex::tune_ef_search`]. Overrides `config.ef_search`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ef_search: Option<usize>,
 }
 
 /// Runtime statistics exported alongside the metadata.
@@ -448,6 +547,110 @@ pub struct HnswStats {
 
     /// Highest layer currently present in the graph.
     pub max_layer: u8,
+
+    /// Connectivity of the graph as of the last
+    /// [`HnswIndex::graph_health`] call, if any.
+    #[serde(default)]
+    pub graph_health: Option<HnswGraphHealth>,
+}
+
+/// Connectivity estimate of the layer-0 graph, see
+/// [`HnswIndex::graph_health`].
+///
+/// Deletions without [`HnswConfig::reconnect_on_delete`] erode the graph:
+/// growing `orphans` and `unreachable` counts and a falling `mean_degree`
+/// mean searches miss live nodes, and the index should be rebuilt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct HnswGraphHealth {
+    /// Timestamp (unix ms) of the measurement.
+    pub measured_at: u64,
+
+    /// Number of live nodes measured.
+    pub num_nodes: u64,
+
+    /// Live nodes that no other live node links to on layer 0.
+    pub orphans: u64,
+
+    /// Live nodes that a layer-0 walk from the entry point does not reach.
+    pub unreachable: u64,
+
+    /// Mean number of layer-0 links per live node.
+    pub mean_degree: f32,
+}
+
+/// Options of [`HnswIndex::evaluate_recall`] and
+/// [`HnswIndex::tune_ef_search`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecallOptions {
+    /// Query vectors to evaluate. When empty, `sample_size` stored vectors
+    /// are sampled as queries instead.
+    pub queries: Vec<Vec<f32>>,
+
+    /// Number of stored vectors sampled when `queries` is empty.
+    pub sample_size: usize,
+
+    /// Number of neighbors each query retrieves.
+    pub top_k: usize,
+
+    /// `ef_search` values to measure. When empty, powers of two from
+    /// `top_k` up to 1024 are measured.
+    pub ef_search: Vec<usize>,
+}
+
+impl Default for RecallOptions {
+    fn default() -> Self {
+        Self {
+            queries: Vec::new(),
+            sample_size: 100,
+            top_k: 10,
+            ef_search: Vec::new(),
+        }
+    }
+}
+
+/// Result of [`HnswIndex::evaluate_recall`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecallReport {
+    /// Number of neighbors each query retrieved.
+    pub top_k: usize,
+
+    /// Number of queries evaluated.
+    pub num_queries: usize,
+
+    /// One measurement per `ef_search` value, in ascending `ef_search` order.
+    pub measurements: Vec<RecallMeasurement>,
+}
+
+impl RecallReport {
+    /// Returns the smallest measured `ef_search` whose recall reaches
+    /// `target_recall`, or the one with the best recall if none does.
+    pub fn ef_search_for(&self, target_recall: f32) -> Option<usize> {
+        self.measurements
+            .iter()
+            .find(|m| m.recall >= target_recall)
+            .or_else(|| {
+                self.measurements
+                    .iter()
+                    .max_by(|a, b| a.recall.total_cmp(&b.recall))
+            })
+            .map(|m| m.ef_search)
+    }
+}
+
+/// Recall and latency of the searches with one `ef_search` value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecallMeasurement {
+    /// Layer-0 beam width of the searches.
+    pub ef_search: usize,
+
+    /// Mean recall@k over the queries, in `[0, 1]`.
+    pub recall: f32,
+
+    /// Mean search latency in microseconds.
+    pub mean_latency_us: f64,
+
+    /// 95th percentile search latency in microseconds.
+    pub p95_latency_us: f64,
 }
 
 /// Serializable HNSW index structure (owned version).
@@ -462,6 +665,9 @@ struct HnswIndexOwned {
     /// written by older versions, hence the default.
     #[serde(default)]
     pub removed_nodes: Vec<u64>,
+    /// Trained quantizer, if any.
+    #[serde(default)]
+    pub quantizer: Option<Quantizer>,
 }
 
 /// Serializable HNSW index structure (reference version).
@@ -470,12 +676,15 @@ struct HnswIndexRef<'a> {
     entry_point: (u64, u8),
     metadata: &'a HnswMetadata,
     removed_nodes: Vec<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quantizer: Option<&'a Quantizer>,
 }
 
 /// Immutable, single-generation persistence image captured while structural
 /// mutations are excluded.
 struct HnswFlushSnapshot {
     version: u64,
+    with_quantizer: bool,
     last_saved: u64,
     dirty_ids: Vec<u64>,
     nodes: Vec<(u64, Vec<u8>)>,
@@ -497,6 +706,10 @@ impl HnswIndex {
     /// [`Self::purge_removed_nodes`] should be called.
     pub const REMOVED_NODES_WARN_THRESHOLD: usize = 10_000;
 
+    /// Multiple of the expected number of visited nodes a filtered traversal
+    /// may visit before it stops; see [`Self::search_filtered`].
+    pub const FILTER_VISIT_SLACK: u64 = 4;
+
     /// Creates a new HNSW index.
     ///
     /// # Arguments
@@ -534,14 +747,18 @@ impl HnswIndex {
             entry_point: RwLock::new((0, 0)),
             metadata: RwLock::new(HnswMetadata {
                 name,
-                config,
+                config: config.clone(),
                 stats,
+                ef_search: None,
             }),
             dirty_nodes: RwLock::new(BTreeSet::new()),
             removed_nodes: RwLock::new(BTreeSet::new()),
             ids: RwLock::new(Treemap::new()),
+            quantizer: OnceLock::new(),
+            quantized: AtomicBool::new(false),
             search_count: AtomicU64::new(0),
             last_saved_version: AtomicU64::new(0),
+            ef_search: AtomicUsize::new(config.ef_search),
         }
     }
 
@@ -584,9 +801,24 @@ impl HnswIndex {
                 source: err.into(),
             })?;
         index.metadata.config = index.metadata.config.normalized();
+        let quantizer = OnceLock::new();
+        if let Some(q) = index.quantizer {
+            q.validate(index.metadata.config.dimension)
+                .map_err(|err| HnswError::Generic {
+                    name: index.metadata.name.clone(),
+                    source: err.into(),
+                })?;
+            let _ = quantizer.set(q);
+        }
         let layer_gen = index.metadata.config.layer_gen();
         let search_count = AtomicU64::new(index.metadata.stats.search_count);
         let last_saved_version = AtomicU64::new(index.metadata.stats.version);
+        let ef_search = index
+            .metadata
+            .ef_search
+            .map_or(index.metadata.config.ef_search, |ef| {
+                ef.clamp(1, HnswConfig::MAX_EF_SEARCH)
+            });
         let entry_point = (
             index.entry_point.0,
             index
@@ -606,8 +838,12 @@ impl HnswIndex {
             dirty_nodes: RwLock::new(BTreeSet::new()),
             removed_nodes: RwLock::new(index.removed_nodes.into_iter().collect()),
             ids: RwLock::new(Treemap::new()),
+            // Activated by `load_nodes`, which encodes leftover `bf16` nodes.
+            quantized: AtomicBool::new(false),
+            quantizer,
             search_count,
             last_saved_version,
+            ef_search: AtomicUsize::new(ef_search),
         })
     }
 
@@ -661,6 +897,7 @@ impl HnswIndex {
 
         let ids: Vec<u64> = self.ids.read().iter().collect();
         if ids.is_empty() {
+            self.encode_stored_vectors();
             return Ok(());
         }
 
@@ -672,6 +909,7 @@ impl HnswIndex {
         let name = &self.name;
         let dimension = self.config.dimension;
         let max_layers = self.config.max_layers;
+        let code_len = self.quantizer.get().map(Quantizer::code_len);
         let f_ref = &f;
         let mut stream = stream::iter(ids)
             .map(|id| async move {
@@ -683,7 +921,9 @@ impl HnswIndex {
                                 source: err.into(),
                             }
                         })?;
-                        Self::validate_loaded_node(name, id, dimension, max_layers, &node)?;
+                        Self::validate_loaded_node(
+                            name, id, dimension, code_len, max_layers, &node,
+                        )?;
                         Ok::<_, HnswError>(LoadedNode::Loaded(id, node))
                     }
                     Ok(None) => Ok(LoadedNode::Missing(id)),
@@ -749,6 +989,8 @@ impl HnswIndex {
                 });
             }
         }
+        // Nodes flushed before the quantizer was persisted are still `bf16`.
+        self.encode_stored_vectors();
         Ok(())
     }
 
@@ -756,6 +998,7 @@ impl HnswIndex {
         name: &str,
         expected_id: u64,
         dimension: usize,
+        code_len: Option<usize>,
         max_layers: u8,
         node: &HnswNode,
     ) -> Result<(), HnswError> {
@@ -769,11 +1012,22 @@ impl HnswIndex {
                 .into(),
             });
         }
-        if node.vector.len() != dimension {
-            return Err(HnswError::DimensionMismatch {
+        if node.codes.is_empty() {
+            if node.vector.len() != dimension {
+                return Err(HnswError::DimensionMismatch {
+                    name: name.to_string(),
+                    expected: dimension,
+                    got: node.vector.len(),
+                });
+            }
+        } else if !node.vector.is_empty() || Some(node.codes.len()) != code_len {
+            return Err(HnswError::Generic {
                 name: name.to_string(),
-                expected: dimension,
-                got: node.vector.len(),
+                source: format!(
+                    "Loaded node {expected_id} has {} quantized codes, expected {code_len:?}",
+                    node.codes.len()
+                )
+                .into(),
             });
         }
         if node.layer >= max_layers {
@@ -850,6 +1104,11 @@ impl HnswIndex {
         self.config.dimension
     }
 
+    /// Returns the normalized index configuration.
+    pub fn config(&self) -> &HnswConfig {
+        &self.config
+    }
+
     /// Returns the index metadata
     pub fn metadata(&self) -> HnswMetadata {
         let mut metadata = { self.metadata.read().clone() };
@@ -875,6 +1134,263 @@ impl HnswIndex {
         stats.search_count = self.search_count.load(Ordering::Relaxed);
     }
 
+    /// Measures the connectivity of the layer-0 graph and records it as
+    /// [`HnswStats::graph_health`].
+    ///
+    /// Walks every live node and its layer-0 links once, in `O(N·M)`, without
+    /// blocking inserts, removals or searches. Mutations running concurrently
+    /// may be partially observed, so the result is an estimate.
+    pub fn graph_health(&self, now_ms: u64) -> HnswGraphHealth {
+        let nodes = self.nodes.pin();
+        let mut links: u64 = 0;
+        let mut linked = FxHashSet::default();
+        for (_, node) in nodes.iter() {
+            for (neighbor, _) in node.neighbors.first().into_iter().flatten() {
+                if *neighbor != node.id && nodes.contains_key(neighbor) {
+                    links += 1;
+                    linked.insert(*neighbor);
+                }
+            }
+        }
+
+        let entry_point = self.entry_point.read().0;
+        let mut reached = FxHashSet::default();
+        let mut queue = std::collections::VecDeque::new();
+        if nodes.contains_key(&entry_point) {
+            reached.insert(entry_point);
+            queue.push_back(entry_point);
+        }
+        while let Some(id) = queue.pop_front() {
+            let Some(node) = nodes.get(&id) else {
+                continue;
+            };
+            for (neighbor, _) in node.neighbors.first().into_iter().flatten() {
+                if nodes.contains_key(neighbor) && reached.insert(*neighbor) {
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+
+        let num_nodes = nodes.len() as u64;
+        // The entry point needs no inbound link.
+        let orphans = nodes
+            .keys()
+            .filter(|id| **id != entry_point && !linked.contains(*id))
+            .count() as u64;
+        let health = HnswGraphHealth {
+            measured_at: now_ms,
+            num_nodes,
+            orphans,
+            unreachable: num_nodes.saturating_sub(reached.len() as u64),
+            mean_degree: if num_nodes == 0 {
+                0.0
+            } else {
+                links as f32 / num_nodes as f32
+            },
+        };
+        self.metadata.write().stats.graph_health = Some(health.clone());
+        health
+    }
+
+    /// Returns the layer-0 beam width of searches without an override.
+    pub fn ef_search(&self) -> usize {
+        self.ef_search.load(Ordering::Relaxed)
+    }
+
+    /// Sets the layer-0 beam width of searches without an override, and
+    /// records it in [`HnswMetadata::ef_search`] for the next flush.
+    ///
+    /// `config.ef_search` itself is left unchanged; `None` restores it.
+    ///
+    /// # Errors
+    /// [`HnswError::Generic`] if `ef_search` is 0 or above
+    /// [`HnswConfig::MAX_EF_SEARCH`].
+    pub fn set_ef_search(&self, ef_search: Option<usize>) -> Result<(), HnswError> {
+        if let Some(ef_search) = ef_search {
+            self.check_ef_search(ef_search)?;
+        }
+        self.ef_search.store(
+            ef_search.unwrap_or(self.config.ef_search),
+            Ordering::Relaxed,
+        );
+        self.update_metadata(|m| {
+            if m.ef_search != ef_search {
+                m.ef_search = ef_search;
+                m.stats.version += 1;
+            }
+        });
+        Ok(())
+    }
+
+    /// Measures the recall@k and latency that searches achieve with each
+    /// `ef_search` value of `options`.
+    ///
+    /// The ground truth is an exact scan of every stored vector with the
+    /// index metric (against the decoded vectors for a quantized index), so
+    /// evaluating costs `O(Q·N)` distance computations for `Q` queries over
+    /// `N` nodes. A result counts as a hit when its distance is within that
+    /// of the true k-th neighbor, so ties are not penalized. Sampled queries
+    /// are stored vectors, evenly spread over the id space; each one finds
+    /// itself.
+    ///
+    /// # Errors
+    /// * [`HnswError::Generic`] if the index is empty, `top_k` or
+    ///   `sample_size` is 0, or an `ef_search` value is out of range.
+    /// * [`HnswError::DimensionMismatch`] if a query has the wrong dimension.
+    pub fn evaluate_recall(&self, options: &RecallOptions) -> Result<RecallReport, HnswError> {
+        let invalid = |msg: &str| HnswError::Generic {
+            name: self.name.clone(),
+            source: msg.to_string().into(),
+        };
+        if options.top_k == 0 {
+            return Err(invalid("top_k must be greater than 0"));
+        }
+        let mut ef_values = if options.ef_search.is_empty() {
+            std::iter::successors(Some(options.top_k.next_power_of_two()), |ef| Some(ef * 2))
+                .take_while(|ef| *ef <= 1024)
+                .collect()
+        } else {
+            for ef in &options.ef_search {
+                self.check_ef_search(*ef)?;
+            }
+            options.ef_search.clone()
+        };
+        // Searches never use a beam narrower than `top_k`.
+        let min_ef = options.top_k.min(HnswConfig::MAX_EF_SEARCH);
+        for ef in &mut ef_values {
+            *ef = (*ef).max(min_ef);
+        }
+        if ef_values.is_empty() {
+            ef_values.push(min_ef);
+        }
+        ef_values.sort_unstable();
+        ef_values.dedup();
+
+        let queries = if options.queries.is_empty() {
+            if options.sample_size == 0 {
+                return Err(invalid("sample_size must be greater than 0"));
+            }
+            self.sample_queries(options.sample_size)?
+        } else {
+            for query in &options.queries {
+                self.check_query(query)?;
+            }
+            options.queries.clone()
+        };
+        if queries.is_empty() || self.nodes.is_empty() {
+            return Err(invalid("cannot evaluate the recall of an empty index"));
+        }
+
+        // Distance of the true k-th neighbor of every query.
+        let kth_distances = queries
+            .iter()
+            .map(|query| self.kth_distance(query, options.top_k))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut measurements = Vec::with_capacity(ef_values.len());
+        for ef_search in ef_values {
+            let mut hits = 0;
+            let mut expected = 0;
+            let mut latencies = Vec::with_capacity(queries.len());
+            for (query, kth) in queries.iter().zip(&kth_distances) {
+                let Some((kth, count)) = kth else {
+                    continue;
+                };
+                let start = std::time::Instant::now();
+                let results = self.search_inner(query, options.top_k, None, ef_search)?;
+                latencies.push(start.elapsed().as_secs_f64() * 1e6);
+                hits += results
+                    .iter()
+                    .filter(|(_, dist)| dist <= kth)
+                    .count()
+                    .min(*count);
+                expected += count;
+            }
+            latencies.sort_unstable_by(f64::total_cmp);
+            measurements.push(RecallMeasurement {
+                ef_search,
+                recall: if expected == 0 {
+                    1.0
+                } else {
+                    hits as f32 / expected as f32
+                },
+                mean_latency_us: latencies.iter().sum::<f64>() / latencies.len().max(1) as f64,
+                p95_latency_us: latencies
+                    .get((latencies.len() * 95).div_ceil(100).saturating_sub(1))
+                    .copied()
+                    .unwrap_or_default(),
+            });
+        }
+
+        Ok(RecallReport {
+            top_k: options.top_k,
+            num_queries: queries.len(),
+            measurements,
+        })
+    }
+
+    /// Sets the smallest `ef_search` of `options` whose measured recall
+    /// reaches `target_recall` as the index-wide beam width, see
+    /// [`Self::evaluate_recall`] and [`Self::set_ef_search`].
+    ///
+    /// If no value reaches the target, the one with the best recall is set.
+    /// Returns the chosen value together with the measurements.
+    ///
+    /// # Errors
+    /// The errors of [`Self::evaluate_recall`], and [`HnswError::Generic`]
+    /// if `target_recall` is not within `(0, 1]`.
+    pub fn tune_ef_search(
+        &self,
+        target_recall: f32,
+        options: &RecallOptions,
+    ) -> Result<(usize, RecallReport), HnswError> {
+        if !(target_recall > 0.0 && target_recall <= 1.0) {
+            return Err(HnswError::Generic {
+                name: self.name.clone(),
+                source: format!("target recall must be within (0, 1], got {target_recall}").into(),
+            });
+        }
+
+        let report = self.evaluate_recall(options)?;
+        let chosen = report
+            .ef_search_for(target_recall)
+            .unwrap_or(self.config.ef_search);
+        self.set_ef_search(Some(chosen))?;
+        Ok((chosen, report))
+    }
+
+    /// Picks up to `n` stored vectors, evenly spread over the live ids.
+    fn sample_queries(&self, n: usize) -> Result<Vec<Vec<f32>>, HnswError> {
+        let ids: Vec<u64> = self.ids.read().iter().collect();
+        let step = ids.len().div_ceil(n).max(1);
+        let nodes = self.nodes.pin();
+        let mut queries = Vec::with_capacity(n.min(ids.len()));
+        for id in ids.into_iter().step_by(step) {
+            if let Some(node) = nodes.get(&id) {
+                queries.push(if node.codes.is_empty() {
+                    node.vector.iter().map(|v| v.to_f32()).collect()
+                } else {
+                    self.decode_node(node)?
+                });
+            }
+        }
+        Ok(queries)
+    }
+
+    /// Returns the exact distance of the `top_k`-th nearest stored vector to
+    /// `query`, and how many vectors are that near (at most `top_k`).
+    fn kth_distance(&self, query: &[f32], top_k: usize) -> Result<Option<(f32, usize)>, HnswError> {
+        let nodes = self.nodes.pin();
+        let mut nearest: BinaryHeap<OrderedFloat<f32>> = BinaryHeap::with_capacity(top_k + 1);
+        for (_, node) in nodes.iter() {
+            nearest.push(OrderedFloat(self.query_distance(query, node)?));
+            if nearest.len() > top_k {
+                nearest.pop();
+            }
+        }
+        Ok(nearest.peek().map(|kth| (kth.0, nearest.len())))
+    }
+
     /// Gets all node IDs in the index.
     pub fn node_ids(&self) -> Vec<u64> {
         self.ids.read().iter().collect()
@@ -895,6 +1411,46 @@ impl HnswIndex {
             })
     }
 
+    /// Computes the distance between an `f32` query and the stored vector of
+    /// node `id`, with the metric of this index.
+    ///
+    /// The stored vector is the one searches score: the decoded codes once
+    /// the index is quantized, the `bf16` vector otherwise.
+    ///
+    /// # Errors
+    /// * [`HnswError::DimensionMismatch`] on dimension mismatch.
+    /// * [`HnswError::NotFound`] if `id` is not indexed.
+    pub fn distance(&self, query: &[f32], id: u64) -> Result<f32, HnswError> {
+        self.check_query(query)?;
+        self.nodes
+            .pin()
+            .get(&id)
+            .ok_or_else(|| HnswError::NotFound {
+                name: self.name.clone(),
+                id,
+            })
+            .and_then(|node| self.query_distance(query, node))
+    }
+
+    /// Computes the distance between the stored vectors of nodes `a` and
+    /// `b`, with the metric of this index.
+    ///
+    /// Like [`HnswIndex::distance`], a quantized node is compared by its
+    /// decoded codes, so no original vector has to be fetched.
+    ///
+    /// # Errors
+    /// * [`HnswError::NotFound`] if either id is not indexed.
+    pub fn distance_between(&self, a: u64, b: u64) -> Result<f32, HnswError> {
+        let nodes = self.nodes.pin();
+        let node = |id| {
+            nodes.get(&id).ok_or_else(|| HnswError::NotFound {
+                name: self.name.clone(),
+                id,
+            })
+        };
+        self.node_distance(node(a)?, node(b)?)
+    }
+
     /// Inserts a vector.
     ///
     /// Complexity: O(log N) expected; the exact cost is dominated by
@@ -916,6 +1472,287 @@ impl HnswIndex {
     /// * [`HnswError::Generic`] if the vector contains `NaN` / `±∞`.
     /// * [`HnswError::AlreadyExists`] if `id` is already present.
     pub fn insert(&self, id: u64, vector: Vec<bf16>, now_ms: u64) -> Result<(), HnswError> {
+        self.check_vector(&vector)?;
+
+        let _structural_guard = self.structural_lock.lock();
+        // Check if ID already exists.
+        if self.nodes.pin().contains_key(&id) {
+            return Err(HnswError::AlreadyExists {
+                name: self.name.clone(),
+                id,
+            });
+        }
+
+        let entry_point = self.live_entry_point();
+        // Randomly determine the node's layer
+        let layer = self.layer_gen.generate(self.max_layer(entry_point));
+        let Some(entry_point) = entry_point else {
+            self.insert_first(id, vector, layer, now_ms);
+            self.maybe_train_quantizer();
+            return Ok(());
+        };
+
+        let plan = self.plan_insert(id, vector, layer, entry_point)?;
+        self.apply_insert(plan, now_ms);
+        self.maybe_train_quantizer();
+        Ok(())
+    }
+
+    /// Inserts many vectors, searching their neighbors in parallel.
+    ///
+    /// Every vector is validated up front, so a dimension mismatch, a
+    /// non-finite value or a duplicate id (in the index or within `items`)
+    /// fails the call before anything is inserted. The graph is then grown
+    /// in rounds: the neighbor searches of a round run on scoped threads
+    /// against the graph as of the round start, and the round's nodes are
+    /// linked one by one once every search of the round has succeeded.
+    /// Nodes of the same round therefore do not pick each other as initial
+    /// neighbors; they are connected through the reverse edges of later
+    /// nodes, as with a regular concurrent build. Small graphs are grown
+    /// sequentially first so the parallel rounds always search a
+    /// well-connected graph.
+    ///
+    /// If a search still fails, the nodes linked by earlier rounds are
+    /// unlinked again before the error is returned, so no node of `items`
+    /// stays in the index. The rollback records no removals: it leaves no
+    /// tombstones, does not count as deletes, and restores the tombstones of
+    /// re-inserted ids.
+    ///
+    /// Holds the structural lock for the whole batch.
+    pub fn insert_batch(&self, items: Vec<(u64, Vec<bf16>)>, now_ms: u64) -> Result<(), HnswError> {
+        let mut seen = FxHashSet::default();
+        for (id, vector) in &items {
+            self.check_vector(vector)?;
+            if !seen.insert(*id) {
+                return Err(HnswError::AlreadyExists {
+                    name: self.name.clone(),
+                    id: *id,
+                });
+            }
+        }
+
+        let _structural_guard = self.structural_lock.lock();
+        {
+            let nodes = self.nodes.pin();
+            if let Some((id, _)) = items.iter().find(|(id, _)| nodes.contains_key(id)) {
+                return Err(HnswError::AlreadyExists {
+                    name: self.name.clone(),
+                    id: *id,
+                });
+            }
+        }
+
+        // Inserting clears a pending tombstone of a re-inserted id.
+        let tombstones: Vec<u64> = {
+            let removed_nodes = self.removed_nodes.read();
+            items
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| removed_nodes.contains(id))
+                .collect()
+        };
+        let mut inserted = Vec::with_capacity(items.len());
+        if let Err(err) = self.insert_rounds(items, now_ms, &mut inserted) {
+            // Newest first, so the entry point falls back to older nodes.
+            for id in inserted.into_iter().rev() {
+                self.unlink_locked(id, now_ms, false);
+            }
+            self.removed_nodes.write().extend(tombstones);
+            return Err(err);
+        }
+        self.maybe_train_quantizer();
+        Ok(())
+    }
+
+    /// The rounds of [`Self::insert_batch`], recording every linked id in
+    /// `inserted`. The caller holds the structural lock.
+    fn insert_rounds(
+        &self,
+        items: Vec<(u64, Vec<bf16>)>,
+        now_ms: u64,
+        inserted: &mut Vec<u64>,
+    ) -> Result<(), HnswError> {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(Self::MAX_BATCH_THREADS);
+        let round_size = threads * Self::BATCH_ROUND_PER_THREAD;
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            let entry_point = self.live_entry_point();
+            let max_layer = self.max_layer(entry_point);
+            let Some(entry) = entry_point else {
+                let (id, vector) = items.next().expect("peeked");
+                let layer = self.layer_gen.generate(max_layer);
+                self.insert_first(id, vector, layer, now_ms);
+                inserted.push(id);
+                continue;
+            };
+
+            // Sequential warm-up: a round adds at most a quarter of the
+            // nodes the graph it searches already has.
+            let len = self.nodes.len();
+            let round = if threads < 2 {
+                1
+            } else {
+                (len / 4).clamp(1, round_size)
+            };
+            let round: Vec<(u64, Vec<bf16>, u8)> = items
+                .by_ref()
+                .take(round)
+                .map(|(id, vector)| (id, vector, self.layer_gen.generate(max_layer)))
+                .collect();
+
+            let plans: Vec<Result<InsertPlan, HnswError>> = if round.len() == 1 || threads < 2 {
+                round
+                    .into_iter()
+                    .map(|(id, vector, layer)| self.plan_insert(id, vector, layer, entry))
+                    .collect()
+            } else {
+                let chunk = round.len().div_ceil(threads);
+                let mut chunks = Vec::with_capacity(threads);
+                let mut round = round.into_iter();
+                loop {
+                    let part: Vec<_> = round.by_ref().take(chunk).collect();
+                    if part.is_empty() {
+                        break;
+                    }
+                    chunks.push(part);
+                }
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = chunks
+                        .into_iter()
+                        .map(|part| {
+                            scope.spawn(move || {
+                                part.into_iter()
+                                    .map(|(id, vector, layer)| {
+                                        self.plan_insert(id, vector, layer, entry)
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .flat_map(|handle| {
+                            handle
+                                .join()
+                                .unwrap_or_else(|err| std::panic::resume_unwind(err))
+                        })
+                        .collect()
+                })
+            };
+
+            // Link nothing of the round unless every search succeeded.
+            let plans = plans.into_iter().collect::<Result<Vec<_>, _>>()?;
+            for plan in plans {
+                inserted.push(plan.id);
+                self.apply_insert(plan, now_ms);
+            }
+        }
+        Ok(())
+    }
+
+    /// Upper bound on the planning threads of [`Self::insert_batch`].
+    const MAX_BATCH_THREADS: usize = 8;
+    /// Nodes planned per thread and round in [`Self::insert_batch`].
+    const BATCH_ROUND_PER_THREAD: usize = 16;
+
+    /// Returns the trained quantizer, if any.
+    pub fn quantizer(&self) -> Option<&Quantizer> {
+        self.quantizer.get()
+    }
+
+    /// Returns `true` if stored vectors are quantized.
+    ///
+    /// A quantizer trained after [`HnswConfig::quantization_training_size`]
+    /// inserts takes effect once the metadata carrying it was persisted
+    /// (the next flush); vectors are stored as `bf16` until then.
+    pub fn is_quantized(&self) -> bool {
+        self.quantized.load(Ordering::Acquire)
+    }
+
+    /// Returns the storage form of a new vector: `bf16`, or its codes once
+    /// quantization is active.
+    fn stored_vector(&self, vector: Vec<bf16>) -> (Vec<bf16>, Vec<u8>) {
+        match self.quantizer.get() {
+            Some(quantizer) if self.is_quantized() => (Vec::new(), quantizer.encode(&vector)),
+            _ => (vector, Vec::new()),
+        }
+    }
+
+    /// Trains the quantizer once the index holds
+    /// [`HnswConfig::quantization_training_size`] vectors. Must run under the
+    /// structural lock.
+    ///
+    /// Only the metadata changes here, so the next flush persists the
+    /// quantizer; the stored vectors are encoded after that flush committed
+    /// (see [`Self::encode_stored_vectors`]).
+    fn maybe_train_quantizer(&self) {
+        if self.config.quantization == Quantization::None
+            || self.quantizer.get().is_some()
+            || self.nodes.len() < self.config.quantization_training_size
+        {
+            return;
+        }
+
+        let samples: Vec<Vec<f32>> = self
+            .nodes
+            .pin()
+            .iter()
+            .take(self.config.quantization_training_size)
+            .map(|(_, node)| node.vector.iter().map(|v| v.to_f32()).collect())
+            .collect();
+        match Quantizer::train(self.config.quantization, self.config.dimension, &samples) {
+            Ok(Some(quantizer)) => {
+                let _ = self.quantizer.set(quantizer);
+                self.update_metadata(|m| m.stats.version += 1);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                log::warn!(
+                    action = "train_quantizer",
+                    index = self.name.as_str();
+                    "Failed to train the HNSW quantizer: {err:?}",
+                );
+            }
+        }
+    }
+
+    /// Activates quantization and encodes every vector still stored as
+    /// `bf16`. Called once the metadata carrying the quantizer is durable;
+    /// must run under the structural lock (or on an unshared index).
+    fn encode_stored_vectors(&self) {
+        let Some(quantizer) = self.quantizer.get() else {
+            return;
+        };
+        if self.quantized.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let nodes = self.nodes.pin();
+        let ids: Vec<u64> = nodes
+            .iter()
+            .filter(|(_, node)| node.codes.is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+        let mut dirty_nodes = BTreeSet::new();
+        for id in ids {
+            if let Some(node) = nodes.get(&id) {
+                let mut node = node.clone();
+                node.codes = quantizer.encode(&node.vector);
+                node.vector = Vec::new();
+                node.version += 1;
+                nodes.insert(id, node);
+                dirty_nodes.insert(id);
+            }
+        }
+        if !dirty_nodes.is_empty() {
+            self.dirty_nodes.write().append(&mut dirty_nodes);
+        }
+    }
+
+    fn check_vector(&self, vector: &[bf16]) -> Result<(), HnswError> {
         if vector.len() != self.config.dimension {
             return Err(HnswError::DimensionMismatch {
                 name: self.name.clone(),
@@ -930,66 +1767,78 @@ impl HnswIndex {
                 source: "Vector contains invalid values (NaN or infinity)".into(),
             });
         }
+        Ok(())
+    }
 
-        let _structural_guard = self.structural_lock.lock();
+    /// Returns the `(entry point, max layer)` of a non-empty graph. Must run
+    /// under the structural lock.
+    ///
+    /// Self-heals a stale entry point (e.g. left behind by interrupted
+    /// bootstrap or external state corruption). Without this, every insert
+    /// and search would keep failing with `NotFound`.
+    fn live_entry_point(&self) -> Option<(u64, u8)> {
         let nodes = self.nodes.pin();
-        // Check if ID already exists.
-        if nodes.contains_key(&id) {
-            return Err(HnswError::AlreadyExists {
-                name: self.name.clone(),
-                id,
-            });
+        if nodes.is_empty() {
+            return None;
+        }
+        let entry_point = { *self.entry_point.read() };
+        if !nodes.contains_key(&entry_point.0) {
+            self.repair_entry_point();
+            return Some(*self.entry_point.read());
         }
+        Some(entry_point)
+    }
 
-        let (initial_entry_point_node, current_max_layer) = { *self.entry_point.read() };
-        // Self-heal a stale entry point (e.g. left behind by interrupted
-        // bootstrap or external state corruption). Without this, every insert
-        // and search would keep failing with `NotFound`. Safe here because the
-        // structural lock is held.
-        let (initial_entry_point_node, current_max_layer) =
-            if !nodes.is_empty() && !nodes.contains_key(&initial_entry_point_node) {
-                self.repair_entry_point();
-                *self.entry_point.read()
-            } else {
-                (initial_entry_point_node, current_max_layer)
-            };
-        // Randomly determine the node's layer
-        let layer = self.layer_gen.generate(current_max_layer);
+    fn max_layer(&self, entry_point: Option<(u64, u8)>) -> u8 {
+        entry_point.map_or_else(|| self.entry_point.read().1, |(_, layer)| layer)
+    }
+
+    /// Inserts the first node of an empty graph as its entry point.
+    fn insert_first(&self, id: u64, vector: Vec<bf16>, layer: u8, now_ms: u64) {
+        let (vector, codes) = self.stored_vector(vector);
+        self.nodes.pin().insert(
+            id,
+            HnswNode {
+                id,
+                layer,
+                vector,
+                codes,
+                neighbors: vec![SmallVec::new(); layer as usize + 1],
+                version: 1,
+            },
+        );
+        self.ids.write().add(id);
+        *self.entry_point.write() = (id, layer);
+        self.dirty_nodes.write().insert(id); // Mark the node as dirty for persistence
+        // A re-inserted id must not have its (new) blob purged by a
+        // pending tombstone from an earlier remove().
+        self.removed_nodes.write().remove(&id);
+
+        self.update_metadata(|m| {
+            m.stats.version += 1;
+            m.stats.last_inserted = now_ms;
+            m.stats.max_layer = layer;
+            m.stats.insert_count += 1;
+        });
+    }
+
+    /// Phase 1 of [`Self::insert`]: searches the graph for the neighbors of
+    /// a node that is not linked yet. Read-only, so several plans can be
+    /// computed concurrently against the same graph (see
+    /// [`Self::insert_batch`]).
+    fn plan_insert(
+        &self,
+        id: u64,
+        vector: Vec<bf16>,
+        layer: u8,
+        (initial_entry_point_node, current_max_layer): (u64, u8),
+    ) -> Result<InsertPlan, HnswError> {
         let mut node_neighbors: Vec<SmallVec<[(u64, bf16); 64]>> =
             vec![
                 SmallVec::with_capacity(self.config.max_connections as usize * 2);
                 layer as usize + 1
             ];
 
-        // If this is the first node, set it as the entry point
-        if nodes.is_empty() {
-            nodes.insert(
-                id,
-                HnswNode {
-                    id,
-                    layer,
-                    vector,
-                    neighbors: node_neighbors,
-                    version: 1,
-                },
-            );
-            self.ids.write().add(id);
-            *self.entry_point.write() = (id, layer);
-            self.dirty_nodes.write().insert(id); // Mark the node as dirty for persistence
-            // A re-inserted id must not have its (new) blob purged by a
-            // pending tombstone from an earlier remove().
-            self.removed_nodes.write().remove(&id);
-
-            self.update_metadata(|m| {
-                m.stats.version += 1;
-                m.stats.last_inserted = now_ms;
-                m.stats.max_layer = layer;
-                m.stats.insert_count += 1;
-            });
-
-            return Ok(());
-        }
-
         // --- Phase 1: descend the layers to gather search state ---
         // The new vector is exactly representable in f32, so searching with the
         // f32 copy yields bit-identical distances while skipping the per-element
@@ -1009,6 +1858,7 @@ impl HnswIndex {
                 current_layer_search,
                 1, // Only need the closest one for entry point search
                 &mut distance_cache,
+                None,
             )?;
             if let Some(&(nearest_id, nearest_dist, nearest_layer)) = nearest.first()
                 && nearest_dist < entry_point_dist
@@ -1050,6 +1900,7 @@ impl HnswIndex {
                 current_layer_build,
                 self.config.ef_construction,
                 &mut distance_cache,
+                None,
             )?;
 
             let selected_neighbors = self.select_neighbors(
@@ -1107,11 +1958,35 @@ impl HnswIndex {
             }
         }
 
+        Ok(InsertPlan {
+            id,
+            layer,
+            vector,
+            neighbors: node_neighbors,
+            reverse_edges: neighbor_updates_required,
+        })
+    }
+
+    /// Phases 2-4 of [`Self::insert`]: links a planned node into the graph.
+    /// Must run under the structural lock.
+    fn apply_insert(&self, plan: InsertPlan, now_ms: u64) {
+        let InsertPlan {
+            id,
+            layer,
+            vector,
+            neighbors: node_neighbors,
+            reverse_edges: neighbor_updates_required,
+        } = plan;
+        let nodes = self.nodes.pin();
+        let mut multi_distance_cache: FxHashMap<(u64, u64), f32> = FxHashMap::default();
+
         // --- Phase 2: publish the new node ---
+        let (vector, codes) = self.stored_vector(vector);
         let new_node = HnswNode {
             id,
             layer,
             vector,
+            codes,
             neighbors: node_neighbors,
             version: 1,
         };
@@ -1204,8 +2079,6 @@ impl HnswIndex {
 
         // --- Phase 4: commit the dirty set ---
         self.dirty_nodes.write().append(&mut local_dirty_nodes);
-
-        Ok(())
     }
 
     /// Inserts a vector with f32 values into the index
@@ -1255,13 +2128,25 @@ impl HnswIndex {
     /// [`HnswConfig::reconnect_on_delete`] (or rebuild periodically) when
     /// recall stability under delete-heavy workloads matters more than
     /// deletion throughput; see the config field's documentation for the
-    /// trade-offs.
+    /// trade-offs. [`HnswIndex::graph_health`] measures the erosion.
     ///
     /// # Returns
     /// * `true` if a node with `id` existed and was removed.
     /// * `false` otherwise.
     pub fn remove(&self, id: u64, now_ms: u64) -> bool {
         let _structural_guard = self.structural_lock.lock();
+        self.remove_locked(id, now_ms)
+    }
+
+    /// [`Self::remove`] for a caller that holds the structural lock.
+    fn remove_locked(&self, id: u64, now_ms: u64) -> bool {
+        self.unlink_locked(id, now_ms, true)
+    }
+
+    /// Unlinks `id` from the graph. With `record`, the removal is recorded
+    /// as a tombstone and a delete; without it, it undoes an insert that was
+    /// never visible, so it only takes back the insert count.
+    fn unlink_locked(&self, id: u64, now_ms: u64, record: bool) -> bool {
         let nodes = self.nodes.pin();
         let Some(node) = nodes.get(&id).cloned() else {
             return false;
@@ -1292,10 +2177,12 @@ impl HnswIndex {
         // the tombstone instead so `purge_removed_nodes` can delete the
         // persisted blob.
         self.dirty_nodes.write().remove(&id);
-        let pending_tombstones = {
+        let pending_tombstones = if record {
             let mut removed_nodes = self.removed_nodes.write();
             removed_nodes.insert(id);
             removed_nodes.len()
+        } else {
+            0
         };
         // Tombstones are only drained by `purge_removed_nodes`; remind the
         // caller periodically so the set (and the persisted metadata that
@@ -1325,8 +2212,12 @@ impl HnswIndex {
         };
         self.update_metadata(|m| {
             m.stats.version += 1;
-            m.stats.last_deleted = now_ms;
-            m.stats.delete_count += 1;
+            if record {
+                m.stats.last_deleted = now_ms;
+                m.stats.delete_count += 1;
+            } else {
+                m.stats.insert_count = m.stats.insert_count.saturating_sub(1);
+            }
             if let Some(max_layer) = recalculated_max_layer {
                 m.stats.max_layer = max_layer;
             }
@@ -1403,11 +2294,7 @@ impl HnswIndex {
                         let dist = match pair_distance_cache.entry(cache_key) {
                             Entry::Occupied(entry) => *entry.get(),
                             Entry::Vacant(entry) => {
-                                match self
-                                    .config
-                                    .distance_metric
-                                    .compute(&n.vector, &peer_node.vector)
-                                {
+                                match self.node_distance(n, peer_node) {
                                     Ok(dist) => {
                                         entry.insert(dist);
                                         dist
@@ -1497,7 +2384,9 @@ impl HnswIndex {
         }
 
         let query_f32: Vec<f32> = query.iter().map(|v| v.to_f32()).collect();
-        self.search_inner(&query_f32, top_k)
+        let results = self.search_inner(&query_f32, top_k, None, self.ef_search())?;
+        self.search_count.fetch_add(1, Ordering::Relaxed);
+        Ok(results)
     }
 
     /// Searches for nearest neighbors using an `f32` query vector.
@@ -1514,10 +2403,120 @@ impl HnswIndex {
     ///
     /// * `Result<Vec<(u64, f32)>, HnswError>` - Vector of (id, distance) pairs sorted by ascending distance
     pub fn search_f32(&self, query: &[f32], top_k: usize) -> Result<Vec<(u64, f32)>, HnswError> {
+        self.search_f32_with_ef(query, top_k, self.ef_search())
+    }
+
+    /// Like [`Self::search_f32`], with the layer-0 beam width `ef_search`
+    /// instead of the index-wide one, to trade recall for latency per query.
+    ///
+    /// # Errors
+    /// Besides the errors of [`Self::search_f32`], [`HnswError::Generic`] if
+    /// `ef_search` is 0 or above [`HnswConfig::MAX_EF_SEARCH`].
+    pub fn search_f32_with_ef(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        ef_search: usize,
+    ) -> Result<Vec<(u64, f32)>, HnswError> {
+        self.check_ef_search(ef_search)?;
+        if top_k == 0 {
+            return Ok(Vec::new());
+        }
+
+        self.check_query(query)?;
+        let results = self.search_inner(query, top_k, None, ef_search)?;
+        self.search_count.fetch_add(1, Ordering::Relaxed);
+        Ok(results)
+    }
+
+    /// Searches for the nearest neighbors among the ids in `allowed`.
+    ///
+    /// The filter is honoured during the layer-0 traversal instead of being
+    /// applied to the results afterwards: nodes outside `allowed` are still
+    /// expanded to reach the rest of the graph, but only allowed nodes enter
+    /// the result set, so a selective filter still returns up to `top_k`
+    /// hits instead of whatever survived from an unfiltered beam.
+    ///
+    /// The traversal needs about `ef / selectivity` visits to collect `ef`
+    /// allowed nodes, where the selectivity is the share of indexed ids that
+    /// are allowed. When that is no fewer than the allowed ids themselves,
+    /// their vectors are scored directly instead, which is both exact and
+    /// cheaper. Otherwise the traversal stops after
+    /// [`Self::FILTER_VISIT_SLACK`] times the expected visits (and never
+    /// visits more nodes than are allowed), and the exhaustive scan is the
+    /// fallback when it did not find enough allowed nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - Query vector as f32 values
+    /// * `top_k` - Number of nearest neighbors to return
+    /// * `allowed` - Ids that may appear in the result; ids that are not
+    ///   indexed are ignored
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<(u64, f32)>, HnswError>` - Vector of (id, distance) pairs sorted by ascending distance
+    pub fn search_filtered(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        allowed: &Treemap,
+    ) -> Result<Vec<(u64, f32)>, HnswError> {
         if top_k == 0 {
             return Ok(Vec::new());
         }
 
+        self.check_query(query)?;
+        let (allowed, indexed) = {
+            let ids = self.ids.read();
+            (ids.and(allowed), ids.cardinality())
+        };
+        let candidates = allowed.cardinality();
+        if candidates == 0 {
+            self.search_count.fetch_add(1, Ordering::Relaxed);
+            return Ok(Vec::new());
+        }
+
+        // The layer-0 beam width of `search_attempt`.
+        let ef = self.ef_search().max(top_k.min(HnswConfig::MAX_EF_SEARCH));
+        let expected_visits = (ef as u64).saturating_mul(indexed).div_ceil(candidates);
+        if expected_visits < candidates {
+            let filter = LayerFilter {
+                allowed: &allowed,
+                max_visits: expected_visits
+                    .saturating_mul(Self::FILTER_VISIT_SLACK)
+                    .min(candidates) as usize,
+            };
+            let results = self.search_inner(query, top_k, Some(&filter), self.ef_search())?;
+            self.search_count.fetch_add(1, Ordering::Relaxed);
+            if results.len() as u64 >= candidates.min(top_k as u64) {
+                return Ok(results);
+            }
+            // The allowed nodes the traversal could not reach are scored
+            // directly; the search itself was already counted.
+            return self.search_exhaustive(query, top_k, &allowed);
+        }
+
+        let results = self.search_exhaustive(query, top_k, &allowed)?;
+        self.search_count.fetch_add(1, Ordering::Relaxed);
+        Ok(results)
+    }
+
+    fn check_ef_search(&self, ef_search: usize) -> Result<(), HnswError> {
+        if ef_search == 0 || ef_search > HnswConfig::MAX_EF_SEARCH {
+            return Err(HnswError::Generic {
+                name: self.name.clone(),
+                source: format!(
+                    "ef_search must be between 1 and {}, got {ef_search}",
+                    HnswConfig::MAX_EF_SEARCH
+                )
+                .into(),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_query(&self, query: &[f32]) -> Result<(), HnswError> {
         if query.iter().any(|v| !v.is_finite()) {
             return Err(HnswError::Generic {
                 name: self.name.clone(),
@@ -1532,8 +2531,35 @@ impl HnswIndex {
                 got: query.len(),
             });
         }
+        Ok(())
+    }
+
+    /// Scores every id in `allowed` and returns the `top_k` nearest, sorted
+    /// by ascending distance. Ids removed concurrently are skipped.
+    fn search_exhaustive(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        allowed: &Treemap,
+    ) -> Result<Vec<(u64, f32)>, HnswError> {
+        let nodes = self.nodes.pin();
+        let mut results: BinaryHeap<(OrderedFloat<f32>, u64)> =
+            BinaryHeap::with_capacity(top_k.min(allowed.cardinality() as usize) + 1);
+        for id in allowed.iter() {
+            if let Some(node) = nodes.get(&id) {
+                let dist = self.query_distance(query, node)?;
+                results.push((OrderedFloat(dist), id));
+                if results.len() > top_k {
+                    results.pop();
+                }
+            }
+        }
 
-        self.search_inner(query, top_k)
+        Ok(results
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(d, id)| (id, d.0))
+            .collect())
     }
 
     /// Runs the two-phase search, retrying when a concurrently removed node
@@ -1542,7 +2568,16 @@ impl HnswIndex {
     /// `remove` repairs the entry point under the structural lock before it
     /// returns, so re-reading the entry point on the next attempt resolves the
     /// race; the retry bound only guards against persistent corruption.
-    fn search_inner(&self, query: &[f32], top_k: usize) -> Result<Vec<(u64, f32)>, HnswError> {
+    ///
+    /// The public searches count themselves in [`HnswStats::search_count`];
+    /// the searches of [`Self::evaluate_recall`] are not counted.
+    fn search_inner(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        filter: Option<&LayerFilter<'_>>,
+        ef_search: usize,
+    ) -> Result<Vec<(u64, f32)>, HnswError> {
         let mut attempt = 0;
         loop {
             attempt += 1;
@@ -1550,18 +2585,21 @@ impl HnswIndex {
                 return Ok(Vec::new());
             }
 
-            match self.search_attempt(query, top_k) {
-                Ok(results) => {
-                    self.search_count.fetch_add(1, Ordering::Relaxed);
-                    return Ok(results);
-                }
+            match self.search_attempt(query, top_k, filter, ef_search) {
+                Ok(results) => return Ok(results),
                 Err(HnswError::NotFound { .. }) if attempt < Self::SEARCH_MAX_ATTEMPTS => continue,
                 Err(err) => return Err(err),
             }
         }
     }
 
-    fn search_attempt(&self, query: &[f32], top_k: usize) -> Result<Vec<(u64, f32)>, HnswError> {
+    fn search_attempt(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        filter: Option<&LayerFilter<'_>>,
+        ef_search: usize,
+    ) -> Result<Vec<(u64, f32)>, HnswError> {
         let mut distance_cache = FxHashMap::default();
         let mut current_dist = f32::MAX;
         let (mut current_node, mut current_node_layer) = { *self.entry_point.read() };
@@ -1574,6 +2612,7 @@ impl HnswIndex {
                 current_layer,
                 1,
                 &mut distance_cache,
+                None,
             )?;
             if let Some(node) = nearest.first()
                 && node.1 < current_dist
@@ -1588,10 +2627,7 @@ impl HnswIndex {
         // caller-supplied `top_k` is capped at `MAX_EF_SEARCH` so it cannot
         // force an arbitrarily expensive beam; in that case fewer than
         // `top_k` results may be returned.
-        let ef = self
-            .config
-            .ef_search
-            .max(top_k.min(HnswConfig::MAX_EF_SEARCH));
+        let ef = ef_search.max(top_k.min(HnswConfig::MAX_EF_SEARCH));
         let mut results = self.search_layer(
             query,
             current_node,
@@ -1599,6 +2635,7 @@ impl HnswIndex {
             0,
             ef,
             &mut distance_cache,
+            filter,
         )?;
         results.truncate(top_k);
 
@@ -1621,10 +2658,14 @@ impl HnswIndex {
     /// * `layer` - Layer to search in
     /// * `ef` - Expansion factor (number of candidates to consider)
     /// * `distance_cache` - Cache of previously computed distances
+    /// * `filter` - Optional set of ids allowed in the results, and the
+    ///   number of visited nodes at which the search stops; other nodes are
+    ///   traversed but never returned
     ///
     /// # Returns
     ///
     /// * `Result<Vec<(u64, f32, u8)>, HnswError>` - Vector of (id, distance, node layer) pairs sorted by ascending distance
+    #[allow(clippy::too_many_arguments)]
     fn search_layer(
         &self,
         query: &[f32],
@@ -1633,6 +2674,7 @@ impl HnswIndex {
         layer: u8,
         ef: usize,
         distance_cache: &mut FxHashMap<u64, f32>,
+        filter: Option<&LayerFilter<'_>>,
     ) -> Result<Vec<(u64, f32, u8)>, HnswError> {
         let ef = ef.max(1);
         let heap_capacity = ef.saturating_mul(2);
@@ -1662,13 +2704,23 @@ impl HnswIndex {
             entry_point,
             entry_point_layer,
         ));
-        results.push((OrderedFloat(entry_dist), entry_point, entry_point_layer));
+        let allowed = |id: u64| filter.is_none_or(|f| f.allowed.contains(id));
+        let max_visits = filter.map_or(usize::MAX, |f| f.max_visits);
+        if allowed(entry_point) {
+            results.push((OrderedFloat(entry_dist), entry_point, entry_point_layer));
+        }
 
         // Get nearest candidates
         while let Some((Reverse(OrderedFloat(dist)), point, _)) = candidates.pop() {
-            if let Some((OrderedFloat(max_dist), _, _)) = results.peek()
+            // A filter keeps every reachable node a candidate while results
+            // are short, so without a budget a filter whose allowed nodes are
+            // out of reach would walk the whole graph.
+            if visited.len() >= max_visits {
+                break;
+            }
+            if results.len() >= ef
+                && let Some((OrderedFloat(max_dist), _, _)) = results.peek()
                 && &dist > max_dist
-                && results.len() >= ef
             {
                 break;
             };
@@ -1683,25 +2735,30 @@ impl HnswIndex {
                     {
                         match self.get_distance_with_cache(distance_cache, query, neighbor_node) {
                             Ok(dist) => {
-                                // results always has ≥1 element (the entry point),
-                                // so peek() always returns Some here.
-                                if let Some((OrderedFloat(max_dist), _, _)) = results.peek()
-                                    && (&dist < max_dist || results.len() < ef)
+                                // With a filter the result set may still be
+                                // short of `ef` (or empty), in which case every
+                                // reachable node stays a candidate.
+                                if results.len() < ef
+                                    || results.peek().is_some_and(
+                                        |(OrderedFloat(max_dist), _, _)| &dist < max_dist,
+                                    )
                                 {
                                     candidates.push((
                                         Reverse(OrderedFloat(dist)),
                                         neighbor,
                                         neighbor_node.layer,
                                     ));
-                                    results.push((
-                                        OrderedFloat(dist),
-                                        neighbor,
-                                        neighbor_node.layer,
-                                    ));
-
-                                    // Prune distant results
-                                    if results.len() > ef {
-                                        results.pop();
+                                    if allowed(neighbor) {
+                                        results.push((
+                                            OrderedFloat(dist),
+                                            neighbor,
+                                            neighbor_node.layer,
+                                        ));
+
+                                        // Prune distant results
+                                        if results.len() > ef {
+                                            results.pop();
+                                        }
                                     }
                                 }
                             }
@@ -1810,10 +2867,7 @@ impl HnswIndex {
                                 if let (Some(cand_node), Some(sel_node)) =
                                     (nodes.get(&cand_id), nodes.get(&sel_id))
                                 {
-                                    let dist = self
-                                        .config
-                                        .distance_metric
-                                        .compute(&cand_node.vector, &sel_node.vector)?;
+                                    let dist = self.node_distance(cand_node, sel_node)?;
                                     entry.insert(dist);
                                     dist
                                 } else {
@@ -1873,16 +2927,48 @@ impl HnswIndex {
         match cache.entry(neighbor.id) {
             Entry::Occupied(entry) => Ok(*entry.get()),
             Entry::Vacant(entry) => {
-                let dist = self
-                    .config
-                    .distance_metric
-                    .compute_mixed(query, &neighbor.vector)?;
+                let dist = self.query_distance(query, neighbor)?;
                 entry.insert(dist);
                 Ok(dist)
             }
         }
     }
 
+    /// Distance between an `f32` query and the stored vector of `node`.
+    fn query_distance(&self, query: &[f32], node: &HnswNode) -> Result<f32, HnswError> {
+        if node.codes.is_empty() {
+            self.config
+                .distance_metric
+                .compute_mixed(query, &node.vector)
+        } else {
+            let vector = self.decode_node(node)?;
+            self.config.distance_metric.compute_f32(query, &vector)
+        }
+    }
+
+    /// Distance between the stored vectors of two nodes.
+    fn node_distance(&self, a: &HnswNode, b: &HnswNode) -> Result<f32, HnswError> {
+        match (a.codes.is_empty(), b.codes.is_empty()) {
+            (true, true) => self.config.distance_metric.compute(&a.vector, &b.vector),
+            (_, false) => self.query_distance(&self.decode_node(b)?, a),
+            (false, true) => self.query_distance(&self.decode_node(a)?, b),
+        }
+    }
+
+    fn decode_node(&self, node: &HnswNode) -> Result<Vec<f32>, HnswError> {
+        match self.quantizer.get() {
+            Some(quantizer) => Ok(quantizer.decode(&node.codes)),
+            None => Err(HnswError::Generic {
+                name: self.name.clone(),
+                source: format!(
+                    "Node {} is quantized but the index has no quantizer",
+                    node.id
+                )
+                .into(),
+            }),
+        }
+    }
+
     /// Captures metadata, ids, and dirty nodes from one structural generation.
     ///
     /// The synchronous structural lock is intentionally released before any
@@ -1909,6 +2995,7 @@ impl HnswIndex {
                 entry_point: *self.entry_point.read(),
                 metadata: &metadata,
                 removed_nodes: self.removed_nodes.read().iter().copied().collect(),
+                quantizer: self.quantizer.get(),
             },
             &mut metadata_buf,
         )
@@ -1948,6 +3035,7 @@ impl HnswIndex {
 
         Ok(Some(HnswFlushSnapshot {
             version,
+            with_quantizer: self.quantizer.get().is_some(),
             last_saved,
             dirty_ids,
             nodes: node_bufs,
@@ -1976,6 +3064,9 @@ impl HnswIndex {
         self.update_metadata(|metadata| {
             metadata.stats.last_saved = snapshot.last_saved.max(metadata.stats.last_saved);
         });
+        if snapshot.with_quantizer {
+            self.encode_stored_vectors();
+        }
     }
 
     /// Persists one coherent graph generation through async callbacks.
@@ -2230,11 +3321,13 @@ impl HnswIndex {
         }
 
         meta.stats.last_saved = now_ms.max(meta.stats.last_saved);
+        let quantizer = self.quantizer.get();
         if let Err(err) = cbor2::to_writer(
             &HnswIndexRef {
                 entry_point: *self.entry_point.read(),
                 metadata: &meta,
                 removed_nodes: self.removed_nodes.read().iter().copied().collect(),
+                quantizer,
             },
             w,
         ) {
@@ -2255,6 +3348,10 @@ impl HnswIndex {
         self.update_metadata(|m| {
             m.stats.last_saved = meta.stats.last_saved.max(m.stats.last_saved);
         });
+        if quantizer.is_some() {
+            let _structural_guard = self.structural_lock.lock();
+            self.encode_stored_vectors();
+        }
 
         Ok(true)
     }
@@ -2277,7 +3374,7 @@ impl HnswIndex {
     where
         F: AsyncFnOnce(&[u8]) -> Result<(), BoxError>,
     {
-        let (version, last_saved, buf) = {
+        let (version, last_saved, with_quantizer, buf) = {
             // Keep entry-point, tombstones and metadata in one structural
             // generation while building the immutable payload. The guard is
             // released before the async callback.
@@ -2290,12 +3387,14 @@ impl HnswIndex {
             meta.stats.last_saved = now_ms.max(meta.stats.last_saved);
             let version = meta.stats.version;
             let last_saved = meta.stats.last_saved;
+            let with_quantizer = self.quantizer.get().is_some();
             let mut buf = Vec::with_capacity(256);
             cbor2::to_writer(
                 &HnswIndexRef {
                     entry_point: *self.entry_point.read(),
                     metadata: &meta,
                     removed_nodes: self.removed_nodes.read().iter().copied().collect(),
+                    quantizer: self.quantizer.get(),
                 },
                 &mut buf,
             )
@@ -2303,7 +3402,7 @@ impl HnswIndex {
                 name: self.name.clone(),
                 source: err.into(),
             })?;
-            (version, last_saved, buf)
+            (version, last_saved, with_quantizer, buf)
         };
 
         if let Err(err) = f(&buf).await {
@@ -2320,6 +3419,10 @@ impl HnswIndex {
         self.update_metadata(|m| {
             m.stats.last_saved = last_saved.max(m.stats.last_saved);
         });
+        if with_quantizer {
+            let _structural_guard = self.structural_lock.lock();
+            self.encode_stored_vectors();
+        }
 
         Ok(true)
     }
@@ -2545,6 +3648,7 @@ mod tests {
             id,
             layer: 0,
             vector: vec![bf16::from_f32(id as f32), bf16::from_f32(id as f32 + 0.5)],
+            codes: Vec::new(),
             neighbors: vec![SmallVec::new()],
             version: 1,
         }
@@ -2557,6 +3661,7 @@ mod tests {
                 entry_point,
                 metadata,
                 removed_nodes: Vec::new(),
+                quantizer: None,
             },
             &mut buf,
         )
@@ -3497,6 +4602,156 @@ mod tests {
         assert_eq!(index.stats().search_count, 0);
     }
 
+    #[test]
+    fn test_search_filtered_honours_allowed_ids() {
+        let config = HnswConfig {
+            dimension: 2,
+            max_connections: 8,
+            ef_construction: 32,
+            ..Default::default()
+        };
+        let index = HnswIndex::new("anda_db_hnsw".to_string(), Some(config));
+        let items: Vec<(u64, Vec<bf16>)> = (0..2200u64)
+            .map(|i| {
+                let v = [(i % 50) as f32, (i / 50) as f32];
+                (i, v.iter().map(|x| bf16::from_f32(*x)).collect())
+            })
+            .collect();
+        index.insert_batch(items, 0).unwrap();
+        let query = [25.2, 20.3];
+        let exact = |allowed: &Treemap, k: usize| {
+            let mut dists: Vec<f32> = allowed
+                .iter()
+                .filter(|id| *id < 2200)
+                .map(|id| {
+                    let (x, y) = ((id % 50) as f32, (id / 50) as f32);
+                    ((x - query[0]).powi(2) + (y - query[1]).powi(2)).sqrt()
+                })
+                .collect();
+            dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            dists.truncate(k);
+            dists
+        };
+
+        // A broad filter is traversed: the graph traversal walks through the
+        // excluded columns around the query but still fills `top_k`.
+        let allowed: Treemap = (0..2200u64)
+            .filter(|i| !(24..=26).contains(&(i % 50)))
+            .collect();
+        let results = index.search_filtered(&query, 10, &allowed).unwrap();
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|(id, _)| allowed.contains(*id)));
+        for (got, want) in results.iter().map(|r| r.1).zip(exact(&allowed, 10)) {
+            assert!((got - want).abs() < 1e-3, "{got} != {want}");
+        }
+
+        // A small allowed set is scored exhaustively; ids that are not
+        // indexed are ignored.
+        let few: Treemap = [7u64, 2199, 9999].into_iter().collect();
+        let results = index.search_filtered(&query, 10, &few).unwrap();
+        assert_eq!(
+            results.iter().map(|r| r.0).collect::<Vec<_>>(),
+            vec![7, 2199]
+        );
+
+        assert!(
+            index
+                .search_filtered(&query, 10, &Treemap::new())
+                .unwrap()
+                .is_empty()
+        );
+        assert!(index.search_filtered(&query, 0, &few).unwrap().is_empty());
+        assert!(matches!(
+            index.search_filtered(&[1.0], 10, &few),
+            Err(HnswError::DimensionMismatch { .. })
+        ));
+
+        // The traversal stops at its visit budget even when no allowed node
+        // is reachable, instead of walking the whole graph.
+        let unreachable: Treemap = [9999u64].into_iter().collect();
+        let mut cache = FxHashMap::default();
+        let (entry, layer) = *index.entry_point.read();
+        let filter = LayerFilter {
+            allowed: &unreachable,
+            max_visits: 100,
+        };
+        let results = index
+            .search_layer(&query, entry, layer, 0, 10, &mut cache, Some(&filter))
+            .unwrap();
+        assert!(results.is_empty());
+        let max_neighbors = 2 * index.config.max_connections as usize;
+        assert!(cache.len() <= 100 + max_neighbors, "{}", cache.len());
+
+        // A selective filter (one column in 50) is scored exhaustively, so
+        // it is exact.
+        let column: Treemap = (0..2200u64).filter(|i| i % 50 == 3).collect();
+        let results = index.search_filtered(&query, 5, &column).unwrap();
+        for (got, want) in results.iter().map(|r| r.1).zip(exact(&column, 5)) {
+            assert!((got - want).abs() < 1e-3, "{got} != {want}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quantization_activates_after_flush_and_survives_reload() {
+        let config = HnswConfig {
+            dimension: 4,
+            quantization: Quantization::Int8,
+            quantization_training_size: 64,
+            ..Default::default()
+        };
+        let index = HnswIndex::new("quantized".to_string(), Some(config));
+        let vector = |i: u64| {
+            let x = i as f32;
+            vec![x, x * 0.5, 100.0 - x, (x * 0.1).sin()]
+        };
+        for i in 0..100u64 {
+            index.insert_f32(i, vector(i), 0).unwrap();
+        }
+        // Trained at the 64th insert, but vectors stay bf16 until the
+        // metadata carrying the quantizer is persisted.
+        assert!(index.quantizer().is_some());
+        assert!(!index.is_quantized());
+        assert!(index.nodes.pin().iter().all(|(_, n)| n.codes.is_empty()));
+
+        let mut metadata = Vec::new();
+        let mut ids = Vec::new();
+        let mut blobs: HashMap<u64, Vec<u8>> = HashMap::new();
+        index
+            .flush(&mut metadata, &mut ids, 1, async |id, data| {
+                blobs.insert(id, data.to_vec());
+                Ok(true)
+            })
+            .await
+            .unwrap();
+        assert!(index.is_quantized());
+        assert!(index.nodes.pin().iter().all(|(_, n)| n.vector.is_empty()));
+        assert_eq!(index.dirty_nodes.read().len(), 100);
+
+        // Inserts after activation are encoded directly.
+        index.insert_f32(100, vector(100), 2).unwrap();
+        assert_eq!(index.nodes.pin().get(&100).unwrap().codes.len(), 4);
+        let results = index.search_f32(&vector(42), 1).unwrap();
+        assert_eq!(results[0].0, 42);
+
+        let mut metadata = Vec::new();
+        let mut ids = Vec::new();
+        index
+            .flush(&mut metadata, &mut ids, 3, async |id, data| {
+                blobs.insert(id, data.to_vec());
+                Ok(true)
+            })
+            .await
+            .unwrap();
+        let reloaded = HnswIndex::load_all(metadata.as_slice(), ids.as_slice(), async |id| {
+            Ok(blobs.get(&id).cloned())
+        })
+        .await
+        .unwrap();
+        assert!(reloaded.is_quantized());
+        assert_eq!(reloaded.len(), 101);
+        assert_eq!(reloaded.search_f32(&vector(77), 1).unwrap()[0].0, 77);
+    }
+
     #[test]
     fn test_search_rejects_non_finite_query_values() {
         let config = HnswConfig {
@@ -3585,6 +4840,7 @@ mod tests {
                 id: 1,
                 layer: 3,
                 vector: vec![bf16::from_f32(1.0), bf16::from_f32(1.0)],
+                codes: Vec::new(),
                 neighbors: vec![
                     SmallVec::new(),
                     SmallVec::new(),
@@ -3600,6 +4856,7 @@ mod tests {
                 id: 2,
                 layer: 1,
                 vector: vec![bf16::from_f32(2.0), bf16::from_f32(2.0)],
+                codes: Vec::new(),
                 neighbors: vec![SmallVec::new(), SmallVec::new()],
                 version: 1,
             },
@@ -3743,6 +5000,27 @@ mod tests {
         assert!((results[0].1 - 0.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_distance_between_nodes() {
+        let config = HnswConfig {
+            dimension: 2,
+            distance_metric: DistanceMetric::Euclidean,
+            ..Default::default()
+        };
+        let index = HnswIndex::new("anda_db_hnsw".to_string(), Some(config));
+        index.insert_f32(1, vec![1.0, 0.0], 0).unwrap();
+        index.insert_f32(2, vec![0.0, 1.0], 0).unwrap();
+
+        let distance = index.distance_between(1, 2).unwrap();
+        assert!((distance - std::f32::consts::SQRT_2).abs() < 1e-6);
+        assert_eq!(index.distance_between(2, 1).unwrap(), distance);
+        assert_eq!(index.distance_between(1, 1).unwrap(), 0.0);
+        assert!(matches!(
+            index.distance_between(1, 3),
+            Err(HnswError::NotFound { id: 3, .. })
+        ));
+    }
+
     #[test]
     fn test_manhattan_distance() {
         let v1 = vec![1.0, 0.0];
@@ -3813,6 +5091,74 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_insert_batch_validates_before_inserting() {
+        let config = HnswConfig {
+            dimension: 2,
+            ..Default::default()
+        };
+        let index = HnswIndex::new("anda_db_hnsw".to_string(), Some(config));
+        let v = |x: f32| vec![bf16::from_f32(x), bf16::from_f32(x + 1.0)];
+        index.insert_batch(Vec::new(), 0).unwrap();
+        index.insert(1, v(1.0), 0).unwrap();
+
+        for items in [
+            vec![(2, v(2.0)), (2, v(3.0))],
+            vec![(2, v(2.0)), (1, v(3.0))],
+            vec![(2, v(2.0)), (3, vec![bf16::from_f32(1.0)])],
+            vec![(2, v(2.0)), (3, vec![bf16::NAN, bf16::ONE])],
+        ] {
+            assert!(index.insert_batch(items, 0).is_err());
+            assert_eq!(index.len(), 1);
+        }
+
+        index
+            .insert_batch((2..=64).map(|id| (id, v(id as f32))).collect(), 0)
+            .unwrap();
+        assert_eq!(index.len(), 64);
+        let results = index.search_f32(&[10.0, 11.0], 1).unwrap();
+        assert_eq!(results[0].0, 10);
+
+        // Rolling a batch node back is not a removal: no tombstone, no
+        // delete, and a tombstone of the re-inserted id comes back.
+        assert!(index.remove(64, 0));
+        let stats = index.stats();
+        index.insert(64, v(64.0), 0).unwrap();
+        assert!(!index.has_removed_nodes());
+        assert!(index.unlink_locked(64, 0, false));
+        index.removed_nodes.write().extend([64]);
+        let rolled_back = index.stats();
+        assert_eq!(rolled_back.insert_count, stats.insert_count);
+        assert_eq!(rolled_back.delete_count, stats.delete_count);
+        assert_eq!(*index.removed_nodes.read(), [64].into_iter().collect());
+        index.insert(64, v(64.0), 0).unwrap();
+        assert_eq!(index.len(), 64);
+
+        // A search failing after validation (here on a corrupt entry point)
+        // leaves none of the batch in the index.
+        let corrupt = 1000;
+        let nodes = index.nodes.pin();
+        nodes.insert(
+            corrupt,
+            HnswNode {
+                id: corrupt,
+                layer: 16,
+                vector: vec![bf16::ONE],
+                codes: Vec::new(),
+                neighbors: vec![SmallVec::new(); 17],
+                version: 0,
+            },
+        );
+        *index.entry_point.write() = (corrupt, 16);
+        let items: Vec<_> = (100..200).map(|id| (id, v(id as f32))).collect();
+        assert!(matches!(
+            index.insert_batch(items, 0),
+            Err(HnswError::DimensionMismatch { .. })
+        ));
+        assert_eq!(index.len(), 65);
+        assert!((100..200).all(|id| !nodes.contains_key(&id)));
+    }
+
     #[test]
     fn test_remove() {
         let config = HnswConfig {
@@ -3899,7 +5245,7 @@ mod tests {
 
         let mut layer_cache = FxHashMap::default();
         assert!(matches!(
-            index.search_layer(&[0.0, 0.0], u64::MAX, 0, 0, 0, &mut layer_cache),
+            index.search_layer(&[0.0, 0.0], u64::MAX, 0, 0, 0, &mut layer_cache, None),
             Err(HnswError::NotFound { id: u64::MAX, .. })
         ));
 
@@ -4368,6 +5714,7 @@ mod tests {
                 search_count: 4,
                 ..Default::default()
             },
+            ef_search: None,
         };
         let bytes = metadata_bytes(&metadata, (99, 9));
         let mut loaded = HnswIndex::load_metadata(&bytes[..]).unwrap();
@@ -4563,4 +5910,148 @@ mod tests {
             .unwrap();
         assert!(!stale_dirty.has_dirty_nodes());
     }
+
+    #[test]
+    fn test_distance_matches_search_scores() {
+        let index = HnswIndex::new("distance".to_string(), Some(test_config()));
+        index.insert_f32(1, vec![1.0, 1.0], 1).unwrap();
+        index.insert_f32(2, vec![3.0, 4.0], 1).unwrap();
+
+        let hits = index.search_f32(&[0.0, 0.0], 2).unwrap();
+        for (id, distance) in hits {
+            assert_eq!(index.distance(&[0.0, 0.0], id).unwrap(), distance);
+        }
+        assert!(matches!(
+            index.distance(&[0.0, 0.0], 3),
+            Err(HnswError::NotFound { id: 3, .. })
+        ));
+        assert!(matches!(
+            index.distance(&[0.0], 1),
+            Err(HnswError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_graph_health_counts_orphans_and_unreachable_nodes() {
+        let index = HnswIndex::new("health".to_string(), Some(test_config()));
+        assert_eq!(
+            index.graph_health(1),
+            HnswGraphHealth {
+                measured_at: 1,
+                ..Default::default()
+            }
+        );
+
+        for id in 1..=20 {
+            index.insert_f32(id, vec![id as f32, 0.0], 1).unwrap();
+        }
+        let health = index.graph_health(2);
+        assert_eq!(health.num_nodes, 20);
+        assert_eq!(health.orphans, 0);
+        assert_eq!(health.unreachable, 0);
+        assert!(health.mean_degree > 0.0);
+        assert_eq!(index.stats().graph_health, Some(health));
+
+        // Cut every inbound layer-0 link of node 10 by hand.
+        let nodes = index.nodes.pin();
+        for (_, node) in nodes.iter() {
+            if node.neighbors[0].iter().any(|(id, _)| *id == 10) {
+                let mut node = node.clone();
+                node.neighbors[0].retain(|(id, _)| *id != 10);
+                nodes.insert(node.id, node);
+            }
+        }
+        drop(nodes);
+        let entry_point = index.entry_point.read().0;
+        let health = index.graph_health(3);
+        if entry_point != 10 {
+            assert_eq!(health.orphans, 1);
+            assert_eq!(health.unreachable, 1);
+        }
+        assert_eq!(index.stats().graph_health.unwrap().measured_at, 3);
+    }
+
+    #[tokio::test]
+    async fn test_recall_evaluation_and_ef_search_tuning() {
+        let index = HnswIndex::new(
+            "recall".to_string(),
+            Some(HnswConfig {
+                dimension: 3,
+                max_connections: 8,
+                ef_construction: 32,
+                ef_search: 200,
+                ..Default::default()
+            }),
+        );
+        for i in 0..300u64 {
+            let v = [(i % 7) as f32, ((i * 13) % 11) as f32, (i / 30) as f32];
+            index.insert_f32(i, v.to_vec(), 1).unwrap();
+        }
+
+        let report = index
+            .evaluate_recall(&RecallOptions {
+                sample_size: 30,
+                top_k: 5,
+                ef_search: vec![1, 100],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(report.num_queries, 30);
+        // A beam is never narrower than `top_k`.
+        let efs: Vec<usize> = report.measurements.iter().map(|m| m.ef_search).collect();
+        assert_eq!(efs, vec![5, 100]);
+        assert_eq!(report.measurements[1].recall, 1.0);
+        assert!(report.measurements.iter().all(|m| m.mean_latency_us >= 0.0));
+
+        let queries = RecallOptions {
+            queries: vec![vec![3.0, 3.0, 3.0], vec![0.5, 9.0, 1.0]],
+            top_k: 5,
+            ..Default::default()
+        };
+        let (ef_search, report) = index.tune_ef_search(0.99, &queries).unwrap();
+        assert_eq!(report.num_queries, 2);
+        assert_eq!(report.measurements[0].ef_search, 8);
+        let smallest = report
+            .measurements
+            .iter()
+            .find(|m| m.recall >= 0.99)
+            .unwrap();
+        assert_eq!(ef_search, smallest.ef_search);
+        assert_eq!(index.ef_search(), ef_search);
+        assert_eq!(index.metadata().ef_search, Some(ef_search));
+        assert_eq!(index.config().ef_search, 200);
+        assert_eq!(index.stats().search_count, 0);
+
+        // Per-query override and validation.
+        assert_eq!(
+            index.search_f32_with_ef(&[3.0, 3.0, 3.0], 5, 300).unwrap(),
+            index.search_f32_with_ef(&[3.0, 3.0, 3.0], 5, 400).unwrap(),
+        );
+        assert!(index.search_f32_with_ef(&[3.0, 3.0, 3.0], 5, 0).is_err());
+        assert!(index.set_ef_search(Some(0)).is_err());
+        assert!(index.tune_ef_search(1.5, &queries).is_err());
+        assert!(
+            index
+                .evaluate_recall(&RecallOptions {
+                    queries: vec![vec![1.0]],
+                    ..Default::default()
+                })
+                .is_err()
+        );
+        let empty = HnswIndex::new("empty".to_string(), Some(test_config()));
+        assert!(empty.evaluate_recall(&RecallOptions::default()).is_err());
+
+        // The tuned value survives a reload; `None` restores the config.
+        let mut metadata = Vec::new();
+        let mut ids = Vec::new();
+        index
+            .flush(&mut metadata, &mut ids, 2, async |_, _| Ok(true))
+            .await
+            .unwrap();
+        let loaded = HnswIndex::load_metadata(&metadata[..]).unwrap();
+        assert_eq!(loaded.ef_search(), ef_search);
+        loaded.set_ef_search(None).unwrap();
+        assert_eq!(loaded.ef_search(), 200);
+        assert_eq!(loaded.metadata().ef_search, None);
+    }
 }
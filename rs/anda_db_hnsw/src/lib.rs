@@ -13,13 +13,17 @@
 //! - Configurable index parameters;
 //! - Thread-safe implementation with concurrent read/write operations;
 //! - Serialization and deserialization support;
-//! - Support for bf16 (brain floating point) vector storage for memory efficiency.
+//! - Support for bf16 (brain floating point) vector storage for memory efficiency;
+//! - Optional int8 scalar and product quantization of the stored vectors.
 //!
 
 mod distance;
 mod error;
 mod hnsw;
+mod quantization;
+mod simd;
 
 pub use distance::*;
 pub use error::*;
 pub use hnsw::*;
+pub use quantization::*;
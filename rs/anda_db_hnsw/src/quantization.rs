@@ -0,0 +1,390 @@
+use half::bf16;
+use serde::{Deserialize, Serialize};
+
+use crate::error::HnswError;
+
+/// Storage encoding of the vectors held by an [`crate::HnswIndex`].
+///
+/// Quantized encodings need a calibration (int8) or a codebook (product
+/// quantization) learned from the data. Until the index holds
+/// [`crate::HnswConfig::quantization_training_size`] vectors they are kept
+/// in `bf16`; the [`Quantizer`] is then trained on those vectors and
+/// persisted with the index metadata. Once that metadata is durable, every
+/// node is re-encoded and later inserts are encoded directly.
+///
+/// Distances to a quantized node are computed against its decoded
+/// approximation, so rankings carry the quantization error. Keep the
+/// original vectors elsewhere and re-rank the final candidates when exact
+/// order matters (see [`crate::HnswConfig::exact_rerank`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quantization {
+    /// Vectors are stored as `bf16`, 2 bytes per dimension.
+    #[default]
+    None,
+    /// Scalar quantization to one byte per dimension, calibrated per
+    /// dimension on the training vectors. Values outside the calibrated range
+    /// are clamped.
+    Int8,
+    /// Product quantization: the vector is split into `subspaces` equal
+    /// slices and each slice is stored as the one-byte id of its nearest
+    /// centroid (up to 256 per subspace, learned by k-means), so a vector
+    /// takes `subspaces` bytes. `subspaces` must divide the dimension.
+    Product { subspaces: u16 },
+}
+
+impl Quantization {
+    /// Validates the encoding against the vector dimension.
+    pub fn validate(&self, dimension: usize) -> Result<(), String> {
+        match *self {
+            Quantization::None | Quantization::Int8 => Ok(()),
+            Quantization::Product { subspaces } => {
+                let subspaces = subspaces as usize;
+                if subspaces == 0 || subspaces > dimension || !dimension.is_multiple_of(subspaces) {
+                    return Err(format!(
+                        "product quantization subspaces must divide the dimension {dimension}, got {subspaces}"
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A trained vector quantizer, persisted alongside the index metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Quantizer {
+    /// Per-dimension affine codes: `x ≈ offset[d] + scale[d] * code`.
+    Int8 { offset: Vec<f32>, scale: Vec<f32> },
+    /// Product quantization codebook. `centroids` holds, per subspace,
+    /// `centroids_per_subspace` centroids of `dimension / subspaces` values.
+    Product {
+        dimension: usize,
+        subspaces: usize,
+        centroids_per_subspace: usize,
+        centroids: Vec<f32>,
+    },
+}
+
+impl Quantizer {
+    /// Maximum centroids per product quantization subspace; codes are bytes.
+    pub const MAX_CENTROIDS: usize = 256;
+
+    /// Lloyd iterations used to train a product quantization codebook.
+    const KMEANS_ITERATIONS: usize = 10;
+
+    /// Trains a quantizer for `quantization` on `samples`.
+    ///
+    /// Returns `None` for [`Quantization::None`] or when there are no
+    /// samples.
+    pub fn train(
+        quantization: Quantization,
+        dimension: usize,
+        samples: &[Vec<f32>],
+    ) -> Result<Option<Self>, HnswError> {
+        if samples.is_empty() {
+            return Ok(None);
+        }
+        if let Some(sample) = samples.iter().find(|s| s.len() != dimension) {
+            return Err(HnswError::DimensionMismatch {
+                name: "quantizer".to_string(),
+                expected: dimension,
+                got: sample.len(),
+            });
+        }
+
+        match quantization {
+            Quantization::None => Ok(None),
+            Quantization::Int8 => {
+                let mut min = vec![f32::MAX; dimension];
+                let mut max = vec![f32::MIN; dimension];
+                for sample in samples {
+                    for (d, &v) in sample.iter().enumerate() {
+                        min[d] = min[d].min(v);
+                        max[d] = max[d].max(v);
+                    }
+                }
+                let scale = min
+                    .iter()
+                    .zip(&max)
+                    .map(|(lo, hi)| (hi - lo) / 255.0)
+                    .collect();
+                Ok(Some(Quantizer::Int8 { offset: min, scale }))
+            }
+            Quantization::Product { subspaces } => {
+                quantization
+                    .validate(dimension)
+                    .map_err(|err| HnswError::Generic {
+                        name: "quantizer".to_string(),
+                        source: err.into(),
+                    })?;
+                let subspaces = subspaces as usize;
+                let sub_dim = dimension / subspaces;
+                let k = samples.len().min(Self::MAX_CENTROIDS);
+                let mut centroids = Vec::with_capacity(subspaces * k * sub_dim);
+                for s in 0..subspaces {
+                    let range = s * sub_dim..(s + 1) * sub_dim;
+                    let points: Vec<&[f32]> = samples.iter().map(|v| &v[range.clone()]).collect();
+                    centroids.extend(kmeans(&points, k, Self::KMEANS_ITERATIONS));
+                }
+                Ok(Some(Quantizer::Product {
+                    dimension,
+                    subspaces,
+                    centroids_per_subspace: k,
+                    centroids,
+                }))
+            }
+        }
+    }
+
+    /// Dimension of the vectors this quantizer encodes.
+    pub fn dimension(&self) -> usize {
+        match self {
+            Quantizer::Int8 { offset, .. } => offset.len(),
+            Quantizer::Product { dimension, .. } => *dimension,
+        }
+    }
+
+    /// Length in bytes of an encoded vector.
+    pub fn code_len(&self) -> usize {
+        match self {
+            Quantizer::Int8 { offset, .. } => offset.len(),
+            Quantizer::Product { subspaces, .. } => *subspaces,
+        }
+    }
+
+    /// Checks the internal consistency of a deserialized quantizer.
+    pub fn validate(&self, dimension: usize) -> Result<(), String> {
+        if self.dimension() != dimension {
+            return Err(format!(
+                "quantizer dimension {} does not match the index dimension {dimension}",
+                self.dimension()
+            ));
+        }
+        match self {
+            Quantizer::Int8 { offset, scale } => {
+                if scale.len() != offset.len() || offset.iter().chain(scale).any(|v| !v.is_finite())
+                {
+                    return Err("invalid int8 quantizer calibration".to_string());
+                }
+            }
+            Quantizer::Product {
+                dimension,
+                subspaces,
+                centroids_per_subspace,
+                centroids,
+            } => {
+                if *subspaces == 0
+                    || dimension % subspaces != 0
+                    || *centroids_per_subspace == 0
+                    || *centroids_per_subspace > Self::MAX_CENTROIDS
+                    || centroids.len() != dimension * centroids_per_subspace
+                    || centroids.iter().any(|v| !v.is_finite())
+                {
+                    return Err("invalid product quantization codebook".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes a vector.
+    pub fn encode(&self, vector: &[bf16]) -> Vec<u8> {
+        match self {
+            Quantizer::Int8 { offset, scale } => vector
+                .iter()
+                .zip(offset.iter().zip(scale))
+                .map(|(v, (lo, scale))| {
+                    if *scale > 0.0 {
+                        ((v.to_f32() - lo) / scale).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        0
+                    }
+                })
+                .collect(),
+            Quantizer::Product {
+                dimension,
+                subspaces,
+                centroids_per_subspace,
+                centroids,
+            } => {
+                let sub_dim = dimension / subspaces;
+                let vector: Vec<f32> = vector.iter().map(|v| v.to_f32()).collect();
+                (0..*subspaces)
+                    .map(|s| {
+                        let slice = &vector[s * sub_dim..(s + 1) * sub_dim];
+                        let book = &centroids[s * centroids_per_subspace * sub_dim
+                            ..(s + 1) * centroids_per_subspace * sub_dim];
+                        nearest_centroid(slice, book, sub_dim) as u8
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Decodes an encoded vector into its `f32` approximation.
+    pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        match self {
+            Quantizer::Int8 { offset, scale } => codes
+                .iter()
+                .zip(offset.iter().zip(scale))
+                .map(|(&code, (lo, scale))| lo + scale * code as f32)
+                .collect(),
+            Quantizer::Product {
+                dimension,
+                subspaces,
+                centroids_per_subspace,
+                centroids,
+            } => {
+                let sub_dim = dimension / subspaces;
+                let mut vector = Vec::with_capacity(*dimension);
+                for (s, &code) in codes.iter().enumerate() {
+                    let start = (s * centroids_per_subspace + code as usize) * sub_dim;
+                    vector.extend_from_slice(&centroids[start..start + sub_dim]);
+                }
+                vector
+            }
+        }
+    }
+}
+
+/// Squared Euclidean distance between two slices of equal length.
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// Returns the index of the centroid in `book` (`sub_dim` values each)
+/// nearest to `point`.
+fn nearest_centroid(point: &[f32], book: &[f32], sub_dim: usize) -> usize {
+    let mut best = (0, f32::MAX);
+    for (i, centroid) in book.chunks_exact(sub_dim).enumerate() {
+        let dist = squared_distance(point, centroid);
+        if dist < best.1 {
+            best = (i, dist);
+        }
+    }
+    best.0
+}
+
+/// Lloyd's k-means over `points`, seeded with evenly spaced points so the
+/// codebook is deterministic. Returns `k` centroids, flattened.
+fn kmeans(points: &[&[f32]], k: usize, iterations: usize) -> Vec<f32> {
+    let sub_dim = points[0].len();
+    let step = points.len() / k;
+    let mut centroids: Vec<f32> = (0..k)
+        .flat_map(|i| points[i * step].iter().copied())
+        .collect();
+
+    let mut assignments = vec![usize::MAX; points.len()];
+    for _ in 0..iterations {
+        let mut changed = false;
+        for (point, assigned) in points.iter().zip(assignments.iter_mut()) {
+            let nearest = nearest_centroid(point, &centroids, sub_dim);
+            if *assigned != nearest {
+                *assigned = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![0.0f32; k * sub_dim];
+        let mut counts = vec![0usize; k];
+        for (point, &assigned) in points.iter().zip(&assignments) {
+            counts[assigned] += 1;
+            for (sum, v) in sums[assigned * sub_dim..(assigned + 1) * sub_dim]
+                .iter_mut()
+                .zip(point.iter())
+            {
+                *sum += v;
+            }
+        }
+        for (c, &count) in counts.iter().enumerate() {
+            // An empty cluster keeps its previous centroid.
+            if count > 0 {
+                for d in 0..sub_dim {
+                    centroids[c * sub_dim + d] = sums[c * sub_dim + d] / count as f32;
+                }
+            }
+        }
+    }
+    centroids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_bf16(v: &[f32]) -> Vec<bf16> {
+        v.iter().map(|x| bf16::from_f32(*x)).collect()
+    }
+
+    #[test]
+    fn test_quantization_validate() {
+        assert!(Quantization::None.validate(3).is_ok());
+        assert!(Quantization::Int8.validate(3).is_ok());
+        assert!(Quantization::Product { subspaces: 4 }.validate(8).is_ok());
+        assert!(Quantization::Product { subspaces: 3 }.validate(8).is_err());
+        assert!(Quantization::Product { subspaces: 0 }.validate(8).is_err());
+        assert!(Quantization::Product { subspaces: 16 }.validate(8).is_err());
+    }
+
+    #[test]
+    fn test_int8_round_trip() {
+        let samples: Vec<Vec<f32>> = (0..20)
+            .map(|i| vec![i as f32 / 10.0, -(i as f32), 5.0])
+            .collect();
+        let quantizer = Quantizer::train(Quantization::Int8, 3, &samples)
+            .unwrap()
+            .unwrap();
+        assert_eq!(quantizer.code_len(), 3);
+        assert!(quantizer.validate(3).is_ok());
+        assert!(quantizer.validate(4).is_err());
+
+        for sample in &samples {
+            let codes = quantizer.encode(&to_bf16(sample));
+            let decoded = quantizer.decode(&codes);
+            assert!((decoded[0] - sample[0]).abs() <= 1.9 / 255.0 + 1e-2);
+            assert!((decoded[1] - sample[1]).abs() <= 19.0 / 255.0 + 1e-1);
+            // A constant dimension decodes to its calibrated value.
+            assert_eq!(decoded[2], 5.0);
+        }
+
+        // Out-of-range values are clamped.
+        let decoded = quantizer.decode(&quantizer.encode(&to_bf16(&[10.0, 10.0, 0.0])));
+        assert!((decoded[0] - 1.9).abs() < 1e-2);
+        assert!(decoded[1].abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_product_quantization_round_trip() {
+        // Two clusters per subspace: the codebook reproduces them exactly.
+        let samples: Vec<Vec<f32>> = (0..40)
+            .map(|i| {
+                if i % 2 == 0 {
+                    vec![0.0, 0.0, 1.0, 1.0]
+                } else {
+                    vec![1.0, 1.0, 0.0, 0.0]
+                }
+            })
+            .collect();
+        let quantizer = Quantizer::train(Quantization::Product { subspaces: 2 }, 4, &samples)
+            .unwrap()
+            .unwrap();
+        assert_eq!(quantizer.code_len(), 2);
+        assert!(quantizer.validate(4).is_ok());
+
+        for sample in &samples {
+            let codes = quantizer.encode(&to_bf16(sample));
+            assert_eq!(codes.len(), 2);
+            assert_eq!(&quantizer.decode(&codes), sample);
+        }
+
+        assert!(Quantizer::train(Quantization::Product { subspaces: 3 }, 4, &samples).is_err());
+        assert!(
+            Quantizer::train(Quantization::Int8, 4, &[])
+                .unwrap()
+                .is_none()
+        );
+    }
+}
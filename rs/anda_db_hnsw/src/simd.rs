@@ -0,0 +1,726 @@
+//! SIMD reductions behind [`crate::DistanceMetric`].
+//!
+//! Every metric reduces to one of four sums over the element pairs
+//! (`Σ (aᵢ − bᵢ)²`, `Σ aᵢ bᵢ`, `Σ aᵢ bᵢ` with both squared norms, and
+//! `Σ |aᵢ − bᵢ|`). Each sum has an AVX-512, AVX2 + FMA (x86_64) and NEON
+//! (aarch64) kernel, generic over the `f32` and `bf16` element types, plus a
+//! portable scalar kernel. [`backend`] picks the widest one the running CPU
+//! supports, once per process.
+//!
+//! `bf16` elements are widened in registers: a `bf16` is the upper half of
+//! an `f32`, so zero-extending to 32 bits and shifting left by 16 is exact.
+//! The kernels sum in a different order than the scalar loop, so results
+//! differ from it by float rounding only.
+
+use half::bf16;
+use std::sync::OnceLock;
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Distance kernel family selected for the running CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Backend {
+    /// Portable unrolled loops.
+    Scalar,
+    /// 256-bit AVX2 with fused multiply-add.
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    /// 512-bit AVX-512F.
+    #[cfg(target_arch = "x86_64")]
+    Avx512,
+    /// 128-bit NEON.
+    #[cfg(target_arch = "aarch64")]
+    Neon,
+}
+
+/// Returns the widest kernel family the running CPU supports.
+pub(crate) fn backend() -> Backend {
+    static BACKEND: OnceLock<Backend> = OnceLock::new();
+    *BACKEND.get_or_init(|| {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if std::arch::is_x86_feature_detected!("avx512f") {
+                return Backend::Avx512;
+            }
+            if std::arch::is_x86_feature_detected!("avx2")
+                && std::arch::is_x86_feature_detected!("fma")
+            {
+                return Backend::Avx2;
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                return Backend::Neon;
+            }
+        }
+        Backend::Scalar
+    })
+}
+
+/// Returns every kernel family the running CPU supports, scalar first.
+#[cfg(test)]
+pub(crate) fn available_backends() -> Vec<Backend> {
+    let mut backends = vec![Backend::Scalar];
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::arch::is_x86_feature_detected!("avx2") && std::arch::is_x86_feature_detected!("fma")
+        {
+            backends.push(Backend::Avx2);
+        }
+        if std::arch::is_x86_feature_detected!("avx512f") {
+            backends.push(Backend::Avx512);
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            backends.push(Backend::Neon);
+        }
+    }
+    backends
+}
+
+/// Element types that promote losslessly to `f32` for distance computation.
+///
+/// The load methods read a full register of consecutive elements starting at
+/// `ptr` and widen them to `f32` lanes. They are only called from kernels
+/// compiled for the matching target features, with at least one register of
+/// elements left.
+pub(crate) trait AsF32: Copy {
+    fn as_f32(self) -> f32;
+
+    /// Loads 8 elements.
+    #[cfg(target_arch = "x86_64")]
+    unsafe fn load_avx2(ptr: *const Self) -> __m256;
+
+    /// Loads 16 elements.
+    #[cfg(target_arch = "x86_64")]
+    unsafe fn load_avx512(ptr: *const Self) -> __m512;
+
+    /// Loads 4 elements.
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn load_neon(ptr: *const Self) -> float32x4_t;
+}
+
+impl AsF32 for f32 {
+    #[inline(always)]
+    fn as_f32(self) -> f32 {
+        self
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load_avx2(ptr: *const Self) -> __m256 {
+        unsafe { _mm256_loadu_ps(ptr) }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn load_avx512(ptr: *const Self) -> __m512 {
+        unsafe { _mm512_loadu_ps(ptr) }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn load_neon(ptr: *const Self) -> float32x4_t {
+        unsafe { vld1q_f32(ptr) }
+    }
+}
+
+impl AsF32 for bf16 {
+    #[inline(always)]
+    fn as_f32(self) -> f32 {
+        self.to_f32()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load_avx2(ptr: *const Self) -> __m256 {
+        let bits = unsafe { _mm_loadu_si128(ptr as *const __m128i) };
+        _mm256_castsi256_ps(_mm256_slli_epi32::<16>(_mm256_cvtepu16_epi32(bits)))
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn load_avx512(ptr: *const Self) -> __m512 {
+        let bits = unsafe { _mm256_loadu_si256(ptr as *const __m256i) };
+        _mm512_castsi512_ps(_mm512_slli_epi32::<16>(_mm512_cvtepu16_epi32(bits)))
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn load_neon(ptr: *const Self) -> float32x4_t {
+        let bits = unsafe { vld1_u16(ptr as *const u16) };
+        vreinterpretq_f32_u32(vshlq_n_u32::<16>(vmovl_u16(bits)))
+    }
+}
+
+/// `Σ (aᵢ − bᵢ)²` over the common prefix of `a` and `b`.
+#[inline]
+pub(crate) fn sum_squared_diff<A: AsF32, B: AsF32>(backend: Backend, a: &[A], b: &[B]) -> f32 {
+    match backend {
+        Backend::Scalar => scalar::sum_squared_diff(a, b),
+        // SAFETY: `backend()` only selects a family the CPU supports.
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { avx2::sum_squared_diff(a, b) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512 => unsafe { avx512::sum_squared_diff(a, b) },
+        #[cfg(target_arch = "aarch64")]
+        Backend::Neon => unsafe { neon::sum_squared_diff(a, b) },
+    }
+}
+
+/// `Σ aᵢ bᵢ` over the common prefix of `a` and `b`.
+#[inline]
+pub(crate) fn dot<A: AsF32, B: AsF32>(backend: Backend, a: &[A], b: &[B]) -> f32 {
+    match backend {
+        Backend::Scalar => scalar::dot(a, b),
+        // SAFETY: `backend()` only selects a family the CPU supports.
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { avx2::dot(a, b) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512 => unsafe { avx512::dot(a, b) },
+        #[cfg(target_arch = "aarch64")]
+        Backend::Neon => unsafe { neon::dot(a, b) },
+    }
+}
+
+/// `(Σ aᵢ bᵢ, Σ aᵢ², Σ bᵢ²)` over the common prefix of `a` and `b`.
+#[inline]
+pub(crate) fn dot_and_norms<A: AsF32, B: AsF32>(
+    backend: Backend,
+    a: &[A],
+    b: &[B],
+) -> (f32, f32, f32) {
+    match backend {
+        Backend::Scalar => scalar::dot_and_norms(a, b),
+        // SAFETY: `backend()` only selects a family the CPU supports.
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { avx2::dot_and_norms(a, b) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512 => unsafe { avx512::dot_and_norms(a, b) },
+        #[cfg(target_arch = "aarch64")]
+        Backend::Neon => unsafe { neon::dot_and_norms(a, b) },
+    }
+}
+
+/// `Σ |aᵢ − bᵢ|` over the common prefix of `a` and `b`.
+#[inline]
+pub(crate) fn sum_abs_diff<A: AsF32, B: AsF32>(backend: Backend, a: &[A], b: &[B]) -> f32 {
+    match backend {
+        Backend::Scalar => scalar::sum_abs_diff(a, b),
+        // SAFETY: `backend()` only selects a family the CPU supports.
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => unsafe { avx2::sum_abs_diff(a, b) },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512 => unsafe { avx512::sum_abs_diff(a, b) },
+        #[cfg(target_arch = "aarch64")]
+        Backend::Neon => unsafe { neon::sum_abs_diff(a, b) },
+    }
+}
+
+mod scalar {
+    use super::AsF32;
+
+    /// Unroll width. Eight independent `f32` accumulators break the
+    /// floating-point add dependency chain so the loop can be pipelined /
+    /// auto-vectorized; the slight change in summation order is well within
+    /// `bf16` quantization noise.
+    const LANES: usize = 8;
+
+    #[inline]
+    pub(super) fn sum_squared_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let mut acc = [0.0f32; LANES];
+        let mut chunks_a = a.chunks_exact(LANES);
+        let mut chunks_b = b.chunks_exact(LANES);
+        for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
+            for i in 0..LANES {
+                let d = ca[i].as_f32() - cb[i].as_f32();
+                acc[i] += d * d;
+            }
+        }
+        let mut sum: f32 = acc.iter().sum();
+        for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
+            let d = x.as_f32() - y.as_f32();
+            sum += d * d;
+        }
+        sum
+    }
+
+    #[inline]
+    pub(super) fn dot<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let mut acc = [0.0f32; LANES];
+        let mut chunks_a = a.chunks_exact(LANES);
+        let mut chunks_b = b.chunks_exact(LANES);
+        for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
+            for i in 0..LANES {
+                acc[i] += ca[i].as_f32() * cb[i].as_f32();
+            }
+        }
+        let mut sum: f32 = acc.iter().sum();
+        for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
+            sum += x.as_f32() * y.as_f32();
+        }
+        sum
+    }
+
+    #[inline]
+    pub(super) fn dot_and_norms<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> (f32, f32, f32) {
+        let mut dot = [0.0f32; LANES];
+        let mut norm_a2 = [0.0f32; LANES];
+        let mut norm_b2 = [0.0f32; LANES];
+        let mut chunks_a = a.chunks_exact(LANES);
+        let mut chunks_b = b.chunks_exact(LANES);
+        for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
+            for i in 0..LANES {
+                let x = ca[i].as_f32();
+                let y = cb[i].as_f32();
+                dot[i] += x * y;
+                norm_a2[i] += x * x;
+                norm_b2[i] += y * y;
+            }
+        }
+        let mut dot_sum: f32 = dot.iter().sum();
+        let mut norm_a2_sum: f32 = norm_a2.iter().sum();
+        let mut norm_b2_sum: f32 = norm_b2.iter().sum();
+        for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
+            let x = x.as_f32();
+            let y = y.as_f32();
+            dot_sum += x * y;
+            norm_a2_sum += x * x;
+            norm_b2_sum += y * y;
+        }
+        (dot_sum, norm_a2_sum, norm_b2_sum)
+    }
+
+    #[inline]
+    pub(super) fn sum_abs_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let mut acc = [0.0f32; LANES];
+        let mut chunks_a = a.chunks_exact(LANES);
+        let mut chunks_b = b.chunks_exact(LANES);
+        for (ca, cb) in (&mut chunks_a).zip(&mut chunks_b) {
+            for i in 0..LANES {
+                acc[i] += (ca[i].as_f32() - cb[i].as_f32()).abs();
+            }
+        }
+        let mut sum: f32 = acc.iter().sum();
+        for (&x, &y) in chunks_a.remainder().iter().zip(chunks_b.remainder()) {
+            sum += (x.as_f32() - y.as_f32()).abs();
+        }
+        sum
+    }
+}
+
+/// Kernels over two 8-lane accumulators (16 elements per iteration), a
+/// single 8-lane step and a scalar tail.
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use super::*;
+
+    const LANES: usize = 8;
+
+    #[inline]
+    #[target_feature(enable = "avx2,fma")]
+    fn hsum(v: __m256) -> f32 {
+        let sum = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps::<1>(v));
+        let shuf = _mm_movehdup_ps(sum);
+        let sum = _mm_add_ps(sum, shuf);
+        let shuf = _mm_movehl_ps(shuf, sum);
+        _mm_cvtss_f32(_mm_add_ss(sum, shuf))
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn sum_squared_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = _mm256_setzero_ps();
+        let mut acc1 = _mm256_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + 2 * LANES <= n {
+                let d0 = _mm256_sub_ps(A::load_avx2(pa.add(i)), B::load_avx2(pb.add(i)));
+                let d1 = _mm256_sub_ps(
+                    A::load_avx2(pa.add(i + LANES)),
+                    B::load_avx2(pb.add(i + LANES)),
+                );
+                acc0 = _mm256_fmadd_ps(d0, d0, acc0);
+                acc1 = _mm256_fmadd_ps(d1, d1, acc1);
+                i += 2 * LANES;
+            }
+            if i + LANES <= n {
+                let d = _mm256_sub_ps(A::load_avx2(pa.add(i)), B::load_avx2(pb.add(i)));
+                acc0 = _mm256_fmadd_ps(d, d, acc0);
+                i += LANES;
+            }
+        }
+        let mut sum = hsum(_mm256_add_ps(acc0, acc1));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            let d = x.as_f32() - y.as_f32();
+            sum += d * d;
+        }
+        sum
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn dot<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = _mm256_setzero_ps();
+        let mut acc1 = _mm256_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + 2 * LANES <= n {
+                acc0 = _mm256_fmadd_ps(A::load_avx2(pa.add(i)), B::load_avx2(pb.add(i)), acc0);
+                acc1 = _mm256_fmadd_ps(
+                    A::load_avx2(pa.add(i + LANES)),
+                    B::load_avx2(pb.add(i + LANES)),
+                    acc1,
+                );
+                i += 2 * LANES;
+            }
+            if i + LANES <= n {
+                acc0 = _mm256_fmadd_ps(A::load_avx2(pa.add(i)), B::load_avx2(pb.add(i)), acc0);
+                i += LANES;
+            }
+        }
+        let mut sum = hsum(_mm256_add_ps(acc0, acc1));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            sum += x.as_f32() * y.as_f32();
+        }
+        sum
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn dot_and_norms<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> (f32, f32, f32) {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut dot = _mm256_setzero_ps();
+        let mut norm_a2 = _mm256_setzero_ps();
+        let mut norm_b2 = _mm256_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + LANES <= n {
+                let x = A::load_avx2(pa.add(i));
+                let y = B::load_avx2(pb.add(i));
+                dot = _mm256_fmadd_ps(x, y, dot);
+                norm_a2 = _mm256_fmadd_ps(x, x, norm_a2);
+                norm_b2 = _mm256_fmadd_ps(y, y, norm_b2);
+                i += LANES;
+            }
+        }
+        let (mut dot, mut norm_a2, mut norm_b2) = (hsum(dot), hsum(norm_a2), hsum(norm_b2));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            let (x, y) = (x.as_f32(), y.as_f32());
+            dot += x * y;
+            norm_a2 += x * x;
+            norm_b2 += y * y;
+        }
+        (dot, norm_a2, norm_b2)
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn sum_abs_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        // Clearing the sign bit is `abs` for every finite value.
+        let sign = _mm256_set1_ps(-0.0);
+        let mut acc0 = _mm256_setzero_ps();
+        let mut acc1 = _mm256_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + 2 * LANES <= n {
+                let d0 = _mm256_sub_ps(A::load_avx2(pa.add(i)), B::load_avx2(pb.add(i)));
+                let d1 = _mm256_sub_ps(
+                    A::load_avx2(pa.add(i + LANES)),
+                    B::load_avx2(pb.add(i + LANES)),
+                );
+                acc0 = _mm256_add_ps(acc0, _mm256_andnot_ps(sign, d0));
+                acc1 = _mm256_add_ps(acc1, _mm256_andnot_ps(sign, d1));
+                i += 2 * LANES;
+            }
+            if i + LANES <= n {
+                let d = _mm256_sub_ps(A::load_avx2(pa.add(i)), B::load_avx2(pb.add(i)));
+                acc0 = _mm256_add_ps(acc0, _mm256_andnot_ps(sign, d));
+                i += LANES;
+            }
+        }
+        let mut sum = hsum(_mm256_add_ps(acc0, acc1));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            sum += (x.as_f32() - y.as_f32()).abs();
+        }
+        sum
+    }
+}
+
+/// Kernels over a 16-lane accumulator. The tail is widened to `f32` into a
+/// zero-filled scalar buffer and loaded as one full vector, so the padding
+/// lanes contribute nothing to any of the sums.
+#[cfg(target_arch = "x86_64")]
+mod avx512 {
+    use super::*;
+
+    const LANES: usize = 16;
+
+    /// Loads the last `len < LANES` elements of each side, zero-filled.
+    #[inline]
+    #[target_feature(enable = "avx512f")]
+    fn load_tail<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> (__m512, __m512) {
+        let mut xa = [0.0f32; LANES];
+        let mut xb = [0.0f32; LANES];
+        for (i, (&x, &y)) in a.iter().zip(b).enumerate() {
+            xa[i] = x.as_f32();
+            xb[i] = y.as_f32();
+        }
+        // SAFETY: both buffers hold `LANES` floats.
+        unsafe { (_mm512_loadu_ps(xa.as_ptr()), _mm512_loadu_ps(xb.as_ptr())) }
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn sum_squared_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc = _mm512_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + LANES <= n {
+                let d = _mm512_sub_ps(A::load_avx512(pa.add(i)), B::load_avx512(pb.add(i)));
+                acc = _mm512_fmadd_ps(d, d, acc);
+                i += LANES;
+            }
+        }
+        if i < n {
+            let (x, y) = load_tail(&a[i..n], &b[i..n]);
+            let d = _mm512_sub_ps(x, y);
+            acc = _mm512_fmadd_ps(d, d, acc);
+        }
+        _mm512_reduce_add_ps(acc)
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn dot<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc = _mm512_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + LANES <= n {
+                acc = _mm512_fmadd_ps(A::load_avx512(pa.add(i)), B::load_avx512(pb.add(i)), acc);
+                i += LANES;
+            }
+        }
+        if i < n {
+            let (x, y) = load_tail(&a[i..n], &b[i..n]);
+            acc = _mm512_fmadd_ps(x, y, acc);
+        }
+        _mm512_reduce_add_ps(acc)
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn dot_and_norms<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> (f32, f32, f32) {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut dot = _mm512_setzero_ps();
+        let mut norm_a2 = _mm512_setzero_ps();
+        let mut norm_b2 = _mm512_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + LANES <= n {
+                let x = A::load_avx512(pa.add(i));
+                let y = B::load_avx512(pb.add(i));
+                dot = _mm512_fmadd_ps(x, y, dot);
+                norm_a2 = _mm512_fmadd_ps(x, x, norm_a2);
+                norm_b2 = _mm512_fmadd_ps(y, y, norm_b2);
+                i += LANES;
+            }
+        }
+        if i < n {
+            let (x, y) = load_tail(&a[i..n], &b[i..n]);
+            dot = _mm512_fmadd_ps(x, y, dot);
+            norm_a2 = _mm512_fmadd_ps(x, x, norm_a2);
+            norm_b2 = _mm512_fmadd_ps(y, y, norm_b2);
+        }
+        (
+            _mm512_reduce_add_ps(dot),
+            _mm512_reduce_add_ps(norm_a2),
+            _mm512_reduce_add_ps(norm_b2),
+        )
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn sum_abs_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc = _mm512_setzero_ps();
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + LANES <= n {
+                let d = _mm512_sub_ps(A::load_avx512(pa.add(i)), B::load_avx512(pb.add(i)));
+                acc = _mm512_add_ps(acc, _mm512_abs_ps(d));
+                i += LANES;
+            }
+        }
+        if i < n {
+            let (x, y) = load_tail(&a[i..n], &b[i..n]);
+            acc = _mm512_add_ps(acc, _mm512_abs_ps(_mm512_sub_ps(x, y)));
+        }
+        _mm512_reduce_add_ps(acc)
+    }
+}
+
+/// Kernels over two 4-lane accumulators (8 elements per iteration), a
+/// single 4-lane step and a scalar tail.
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::*;
+
+    const LANES: usize = 4;
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn sum_squared_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = vdupq_n_f32(0.0);
+        let mut acc1 = vdupq_n_f32(0.0);
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + 2 * LANES <= n {
+                let d0 = vsubq_f32(A::load_neon(pa.add(i)), B::load_neon(pb.add(i)));
+                let d1 = vsubq_f32(
+                    A::load_neon(pa.add(i + LANES)),
+                    B::load_neon(pb.add(i + LANES)),
+                );
+                acc0 = vfmaq_f32(acc0, d0, d0);
+                acc1 = vfmaq_f32(acc1, d1, d1);
+                i += 2 * LANES;
+            }
+            if i + LANES <= n {
+                let d = vsubq_f32(A::load_neon(pa.add(i)), B::load_neon(pb.add(i)));
+                acc0 = vfmaq_f32(acc0, d, d);
+                i += LANES;
+            }
+        }
+        let mut sum = vaddvq_f32(vaddq_f32(acc0, acc1));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            let d = x.as_f32() - y.as_f32();
+            sum += d * d;
+        }
+        sum
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn dot<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = vdupq_n_f32(0.0);
+        let mut acc1 = vdupq_n_f32(0.0);
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + 2 * LANES <= n {
+                acc0 = vfmaq_f32(acc0, A::load_neon(pa.add(i)), B::load_neon(pb.add(i)));
+                acc1 = vfmaq_f32(
+                    acc1,
+                    A::load_neon(pa.add(i + LANES)),
+                    B::load_neon(pb.add(i + LANES)),
+                );
+                i += 2 * LANES;
+            }
+            if i + LANES <= n {
+                acc0 = vfmaq_f32(acc0, A::load_neon(pa.add(i)), B::load_neon(pb.add(i)));
+                i += LANES;
+            }
+        }
+        let mut sum = vaddvq_f32(vaddq_f32(acc0, acc1));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            sum += x.as_f32() * y.as_f32();
+        }
+        sum
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn dot_and_norms<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> (f32, f32, f32) {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut dot = vdupq_n_f32(0.0);
+        let mut norm_a2 = vdupq_n_f32(0.0);
+        let mut norm_b2 = vdupq_n_f32(0.0);
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + LANES <= n {
+                let x = A::load_neon(pa.add(i));
+                let y = B::load_neon(pb.add(i));
+                dot = vfmaq_f32(dot, x, y);
+                norm_a2 = vfmaq_f32(norm_a2, x, x);
+                norm_b2 = vfmaq_f32(norm_b2, y, y);
+                i += LANES;
+            }
+        }
+        let (mut dot, mut norm_a2, mut norm_b2) =
+            (vaddvq_f32(dot), vaddvq_f32(norm_a2), vaddvq_f32(norm_b2));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            let (x, y) = (x.as_f32(), y.as_f32());
+            dot += x * y;
+            norm_a2 += x * x;
+            norm_b2 += y * y;
+        }
+        (dot, norm_a2, norm_b2)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn sum_abs_diff<A: AsF32, B: AsF32>(a: &[A], b: &[B]) -> f32 {
+        let n = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = vdupq_n_f32(0.0);
+        let mut acc1 = vdupq_n_f32(0.0);
+        let mut i = 0;
+        // SAFETY: every load reads `LANES` elements below `n`.
+        unsafe {
+            while i + 2 * LANES <= n {
+                acc0 = vaddq_f32(
+                    acc0,
+                    vabdq_f32(A::load_neon(pa.add(i)), B::load_neon(pb.add(i))),
+                );
+                acc1 = vaddq_f32(
+                    acc1,
+                    vabdq_f32(
+                        A::load_neon(pa.add(i + LANES)),
+                        B::load_neon(pb.add(i + LANES)),
+                    ),
+                );
+                i += 2 * LANES;
+            }
+            if i + LANES <= n {
+                acc0 = vaddq_f32(
+                    acc0,
+                    vabdq_f32(A::load_neon(pa.add(i)), B::load_neon(pb.add(i))),
+                );
+                i += LANES;
+            }
+        }
+        let mut sum = vaddvq_f32(vaddq_f32(acc0, acc1));
+        for (&x, &y) in a[i..n].iter().zip(&b[i..n]) {
+            sum += (x.as_f32() - y.as_f32()).abs();
+        }
+        sum
+    }
+}
@@ -12,7 +12,7 @@
 //! within a tiny factor of the k-th ground-truth distance, so exact ties at
 //! the boundary (common with bf16 rounding) are not punished.
 
-use anda_db_hnsw::{DistanceMetric, HnswConfig, HnswIndex, half::bf16};
+use anda_db_hnsw::{DistanceMetric, HnswConfig, HnswIndex, Quantization, half::bf16};
 use std::collections::BTreeMap;
 
 /// Deterministic SplitMix64; keeps the test independent of `rand` versions.
@@ -372,3 +372,99 @@ async fn recall_survives_persistence_round_trip() {
         "reload changed retrieval quality: before {avg_before:.4}, after {avg_after:.4}"
     );
 }
+
+/// A graph grown through `insert_batch` (parallel neighbor search in rounds)
+/// must meet the same floor as one grown by sequential inserts.
+#[test]
+fn batch_insert_recall_meets_floor() {
+    let config = HnswConfig {
+        dimension: 32,
+        distance_metric: DistanceMetric::Euclidean,
+        ..Default::default()
+    };
+    let index = HnswIndex::new("recall".to_string(), Some(config));
+    let mut rng = SplitMix64(42);
+    let mut data = BTreeMap::new();
+    let mut items = Vec::new();
+    for id in 1..=1000u64 {
+        let v = rng.next_vector(32);
+        items.push((id, v.iter().map(|x| bf16::from_f32(*x)).collect()));
+        data.insert(id, v);
+    }
+    // Two batches: the second one grows a non-empty graph.
+    let rest = items.split_off(300);
+    index.insert_batch(items, 1).expect("insert_batch failed");
+    index.insert_batch(rest, 2).expect("insert_batch failed");
+    assert_eq!(index.len(), 1000);
+
+    let bench = Bench {
+        index,
+        data,
+        queries: (0..50).map(|_| rng.next_vector(32)).collect(),
+        metric: DistanceMetric::Euclidean,
+        k: 10,
+    };
+    let (avg, min) = bench.measure(&bench.index);
+    println!("batch euclidean: avg recall@10 = {avg:.4}, min = {min:.4}");
+    assert!(avg >= 0.95, "average recall@10 too low: {avg:.4}");
+    assert!(min >= 0.60, "worst-case recall@10 too low: {min:.4}");
+}
+
+/// Flushes into in-memory buffers, which activates a trained quantizer.
+async fn flush_in_memory(index: &HnswIndex) {
+    let mut metadata = Vec::new();
+    let mut ids = Vec::new();
+    index
+        .flush(&mut metadata, &mut ids, 5_000, async |_, _| Ok(true))
+        .await
+        .expect("flush failed");
+}
+
+/// Quantized distances are approximate: recall is measured against the exact
+/// ground truth and must stay within a bounded loss of the bf16 index.
+#[tokio::test]
+async fn int8_quantized_recall_meets_floor() {
+    let bench = Bench::build_with(
+        HnswConfig {
+            dimension: 32,
+            distance_metric: DistanceMetric::Euclidean,
+            quantization: Quantization::Int8,
+            quantization_training_size: 500,
+            ..Default::default()
+        },
+        1000,
+        50,
+        42,
+    );
+    assert!(!bench.index.is_quantized());
+    flush_in_memory(&bench.index).await;
+    assert!(bench.index.is_quantized());
+
+    let (avg, min) = bench.measure(&bench.index);
+    println!("int8 euclidean: avg recall@10 = {avg:.4}, min = {min:.4}");
+    assert!(avg >= 0.95, "average recall@10 too low: {avg:.4}");
+    assert!(min >= 0.60, "worst-case recall@10 too low: {min:.4}");
+}
+
+#[tokio::test]
+async fn product_quantized_recall_meets_floor() {
+    let bench = Bench::build_with(
+        HnswConfig {
+            dimension: 32,
+            distance_metric: DistanceMetric::Euclidean,
+            quantization: Quantization::Product { subspaces: 16 },
+            quantization_training_size: 1000,
+            ..Default::default()
+        },
+        1000,
+        50,
+        42,
+    );
+    flush_in_memory(&bench.index).await;
+    assert!(bench.index.is_quantized());
+
+    let (avg, min) = bench.measure(&bench.index);
+    println!("pq euclidean: avg recall@10 = {avg:.4}, min = {min:.4}");
+    assert!(avg >= 0.80, "average recall@10 too low: {avg:.4}");
+    assert!(min >= 0.40, "worst-case recall@10 too low: {min:.4}");
+}
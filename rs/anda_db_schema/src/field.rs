@@ -5,8 +5,8 @@
 //!
 //! - [`FieldType`] / [`Ft`] — the *declared* type of a field. It is a closed
 //!   enum covering primitives (`Bool`, `I64`, `U64`, `F64`, `F32`, `Bytes`,
-//!   `Text`, `Json`, `Vector`) plus three composites: `Array`, `Map` and
-//!   `Option`.
+//!   `Text`, `Json`, `Vector`, `SparseVector`) plus three composites:
+//!   `Array`, `Map` and `Option`.
 //! - [`FieldValue`] / [`Fv`] — the *runtime* value of a field. Each variant
 //!   matches a `FieldType`, plus a dedicated [`FieldValue::Null`] representing
 //!   the absence of a value for [`FieldType::Option`].
@@ -38,6 +38,12 @@ pub use ic_auth_types::{ByteArrayB64, ByteBufB64};
 /// Type alias for `Vec<bf16>`
 pub type Vector = Vec<bf16>;
 
+/// Type alias for a sparse vector: term → weight.
+///
+/// The value of a [`FieldType::SparseVector`] field, stored as a
+/// [`FieldValue::Map`] of `Text` keys to `F32` weights.
+pub type SparseVector = BTreeMap<String, f32>;
+
 /// Type alias for FieldType
 pub type Ft = FieldType;
 
@@ -146,6 +152,11 @@ pub enum FieldType {
     /// `Vec<bf16>`, bf16: 16-bit floating point type implementing the bfloat16 format.
     /// Detail: <https://docs.rs/half/latest/half/struct.bf16.html>
     Vector,
+    /// Sparse vector, a map of terms to finite `f32` weights, such as the
+    /// output of a learned sparse (SPLADE-style) encoder. Its value is a
+    /// [`FieldValue::Map`] of `Text` keys to `F32` values, see
+    /// [`SparseVector`].
+    SparseVector,
     /// Array of field types
     Array(Vec<FieldType>),
     /// Map with typed field keys and field type values
@@ -167,6 +178,7 @@ impl fmt::Debug for FieldType {
             FieldType::Text => write!(f, "Text"),
             FieldType::Json => write!(f, "Json"),
             FieldType::Vector => write!(f, "Vector"),
+            FieldType::SparseVector => write!(f, "SparseVector"),
             FieldType::Array(v) => write!(f, "Array({v:?})"),
             FieldType::Map(v) => write!(f, "Map({v:?})"),
             FieldType::Option(v) => write!(f, "Option({v:?})"),
@@ -216,6 +228,7 @@ impl FieldType {
             FieldType::Text => FieldValue::text_from(value),
             FieldType::Json => FieldValue::json_from(value),
             FieldType::Vector => FieldValue::vector_from(value),
+            FieldType::SparseVector => FieldValue::sparse_vector_from(value),
             FieldType::Array(types) => FieldValue::array_from_at(value, types, depth),
             FieldType::Map(types) => FieldValue::map_from_at(value, types, depth),
             FieldType::Option(ft) => {
@@ -241,7 +254,8 @@ impl FieldType {
     ///   range,
     /// - `F32` accepts an [`F64`](FieldValue::F64) that a stored `f32` can
     ///   produce when read back through CBOR (exact widening) or JSON
-    ///   (shortest-decimal round trip); see `is_f32_read_back`.
+    ///   (shortest-decimal round trip); see `is_f32_read_back`. So do the
+    ///   weights of a `SparseVector`.
     ///
     /// Use [`FieldType::normalize`] to fold accepted read-back shapes into
     /// the canonical variant.
@@ -301,6 +315,22 @@ impl FieldType {
                     "expected Vector, got {values:?}"
                 )))
             }
+            (FieldType::SparseVector, FieldValue::Map(values)) => {
+                for (key, weight) in values {
+                    let valid_key = matches!(key, FieldKey::Text(term) if !term.is_empty());
+                    let valid_weight = match weight {
+                        FieldValue::F32(w) => w.is_finite(),
+                        FieldValue::F64(w) => w.is_finite() && is_f32_read_back(*w),
+                        _ => false,
+                    };
+                    if !valid_key || !valid_weight {
+                        return Err(SchemaError::FieldValue(format!(
+                            "expected SparseVector of non-empty terms to finite F32 weights, got {key:?}: {weight:?}"
+                        )));
+                    }
+                }
+                Ok(())
+            }
             (FieldType::Array(types), FieldValue::Array(values)) => match types.len() {
                 0 => Ok(()),
                 1 => {
@@ -353,6 +383,7 @@ impl FieldType {
     ///   (see `is_f32_read_back`) becomes [`FieldValue::F32`],
     /// - a `Vector` field observed as an array of U64 bf16 bit patterns becomes
     ///   [`FieldValue::Vector`],
+    /// - the `F64` read-back weights of a `SparseVector` become `F32`,
     /// - a `Json` field observed as the `Map` / `Array` / primitive shape of
     ///   its payload becomes [`FieldValue::Json`] again.
     ///
@@ -405,6 +436,13 @@ impl FieldType {
                     }
                 }
             }
+            FieldType::SparseVector => {
+                if let FieldValue::Map(values) = value {
+                    for weight in values.values_mut() {
+                        FieldType::F32.normalize_at(weight, depth + 1);
+                    }
+                }
+            }
             FieldType::Array(types) => {
                 if let FieldValue::Array(values) = value {
                     match types.len() {
@@ -1667,6 +1705,67 @@ impl FieldValue {
         }
     }
 
+    /// Create a SparseVector FieldValue from a CBOR value
+    ///
+    /// Accepts a CBOR map of non-empty text terms to finite numbers, which
+    /// become `F32` weights.
+    ///
+    /// # Arguments
+    /// * `value` - The CBOR value to convert
+    ///
+    /// # Returns
+    /// * `Result<Self, SchemaError>` - The converted FieldValue or an error message
+    pub fn sparse_vector_from(value: Cbor) -> Result<Self, SchemaError> {
+        match value {
+            Cbor::Map(entries) => {
+                let mut vals = BTreeMap::new();
+                for (k, v) in entries {
+                    let term = match k {
+                        Cbor::Text(term) if !term.is_empty() => term,
+                        k => {
+                            return Err(SchemaError::FieldValue(format!(
+                                "expected non-empty SparseVector term, got {k:?}"
+                            )));
+                        }
+                    };
+                    let weight = match v {
+                        Cbor::Integer(i) => i128::from(i) as f32,
+                        v => match Self::f32_from(v)? {
+                            FieldValue::F32(w) => w,
+                            _ => unreachable!("f32_from returns F32"),
+                        },
+                    };
+                    if !weight.is_finite() {
+                        return Err(SchemaError::FieldValue(format!(
+                            "expected finite SparseVector weight for {term:?}, got {weight:?}"
+                        )));
+                    }
+                    vals.insert(FieldKey::Text(term), FieldValue::F32(weight));
+                }
+                Ok(FieldValue::Map(vals))
+            }
+            v => Err(SchemaError::FieldValue(format!(
+                "expected SparseVector, got {v:?}"
+            ))),
+        }
+    }
+
+    /// Returns the terms and weights of a valid
+    /// [`FieldType::SparseVector`] value, or `None` for any other value.
+    pub fn sparse_vector(&self) -> Option<SparseVector> {
+        let FieldValue::Map(values) = self else {
+            return None;
+        };
+        values
+            .iter()
+            .map(|(key, weight)| match (key, weight) {
+                (FieldKey::Text(term), FieldValue::F32(w)) => Some((term.clone(), *w)),
+                (FieldKey::Text(term), FieldValue::F64(w)) => Some((term.clone(), *w as f32)),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Create a bf16 from a CBOR value
     ///
     /// # Arguments
@@ -2880,6 +2979,70 @@ mod tests {
         assert!(err.to_string().contains("duplicate map key"));
     }
 
+    #[test]
+    fn test_sparse_vector_field() {
+        let ft = FieldType::SparseVector;
+        assert_eq!(format!("{ft:?}"), "SparseVector");
+
+        // Integer and float weights both extract to F32.
+        let v = ft
+            .extract(Cbor::Map(vec![
+                (Cbor::Text("apple".to_string()), Cbor::Float(0.5)),
+                (Cbor::Text("pie".to_string()), Cbor::Integer(2.into())),
+            ]))
+            .unwrap();
+        assert_eq!(
+            v,
+            Fv::Map(BTreeMap::from([
+                ("apple".into(), Fv::F32(0.5)),
+                ("pie".into(), Fv::F32(2.0)),
+            ]))
+        );
+        assert!(ft.validate(&v).is_ok());
+        assert_eq!(
+            v.sparse_vector(),
+            Some(SparseVector::from([
+                ("apple".to_string(), 0.5),
+                ("pie".to_string(), 2.0)
+            ]))
+        );
+
+        // Empty terms, non-text terms and non-finite weights are rejected.
+        assert!(
+            ft.extract(Cbor::Map(vec![(
+                Cbor::Text("".to_string()),
+                Cbor::Float(1.0)
+            )]))
+            .is_err()
+        );
+        assert!(
+            ft.extract(Cbor::Map(vec![(Cbor::Integer(1.into()), Cbor::Float(1.0))]))
+                .is_err()
+        );
+        assert!(
+            ft.extract(Cbor::Map(vec![(
+                Cbor::Text("a".to_string()),
+                Cbor::Float(f64::INFINITY)
+            )]))
+            .is_err()
+        );
+        assert!(ft.extract(Cbor::Array(vec![])).is_err());
+        assert!(
+            ft.validate(&Fv::Map(BTreeMap::from([("a".into(), Fv::U64(1))])))
+                .is_err()
+        );
+        assert!(
+            ft.validate(&Fv::Map(BTreeMap::from([("a".into(), Fv::F32(f32::NAN))])))
+                .is_err()
+        );
+
+        // F64 read-back weights validate and normalize back to F32.
+        let mut v = Fv::Map(BTreeMap::from([("a".into(), Fv::F64(0.1f32 as f64))]));
+        assert!(ft.validate(&v).is_ok());
+        ft.normalize(&mut v);
+        assert_eq!(v, Fv::Map(BTreeMap::from([("a".into(), Fv::F32(0.1))])));
+    }
+
     #[test]
     fn wildcard_maps_enforce_the_declared_key_variant() {
         // Regression: only the *value* type used to be checked, so a
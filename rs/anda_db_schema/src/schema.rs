@@ -241,6 +241,32 @@ impl Schema {
             .ok_or_else(|| SchemaError::Validation(format!("field {name:?} not found in schema")))
     }
 
+    /// Returns a schema with only the named fields, plus `_id`.
+    ///
+    /// Field indexes, the version and the allocation watermark are kept, so a
+    /// stored document decodes against the projection with
+    /// [`Document::try_from_doc`](crate::Document::try_from_doc), which drops
+    /// the values of every other field.
+    ///
+    /// # Errors
+    /// Returns an error if a named field does not exist.
+    pub fn project<S: AsRef<str>>(&self, names: &[S]) -> Result<Schema, SchemaError> {
+        let mut fields = BTreeMap::new();
+        if let Some(id) = self.fields.get(Self::ID_KEY) {
+            fields.insert(Self::ID_KEY.to_string(), id.clone());
+        }
+        for name in names {
+            let field = self.get_field_or_err(name.as_ref())?;
+            fields.insert(field.name().to_string(), field.clone());
+        }
+        Ok(Schema {
+            idx: fields.values().map(|f| f.idx()).collect(),
+            fields,
+            version: self.version,
+            next_idx: self.allocated_idx_end(),
+        })
+    }
+
     /// Returns an iterator over all fields in the schema.
     ///
     /// # Returns
@@ -1118,4 +1144,28 @@ mod tests {
 
         assert!(new_schema.upgrade_with(&old).is_err());
     }
+
+    #[test]
+    fn test_project_keeps_id_and_named_fields() {
+        let mut builder = SchemaBuilder::new();
+        builder
+            .add_field(Fe::new("name".to_string(), Ft::Text).unwrap())
+            .unwrap();
+        builder
+            .add_field(Fe::new("age".to_string(), Ft::U64).unwrap())
+            .unwrap();
+        let schema = builder.build().unwrap();
+
+        let projected = schema.project(&["age"]).unwrap();
+        assert!(projected.get_field(Schema::ID_KEY).is_some());
+        assert!(projected.get_field("age").is_some());
+        assert!(projected.get_field("name").is_none());
+        assert_eq!(projected.allocated_idx_end(), schema.allocated_idx_end());
+        assert_eq!(
+            projected.get_field("age").unwrap().idx(),
+            schema.get_field("age").unwrap().idx()
+        );
+
+        assert!(schema.project(&["missing"]).is_err());
+    }
 }
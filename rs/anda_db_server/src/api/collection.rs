@@ -12,7 +12,10 @@ use anda_db::{
     collection::{Collection, CollectionConfig, CollectionMetadata, CollectionStats},
     database::AndaDB,
     error::DBError,
-    index::HnswConfig,
+    index::{
+        BM25Config, FIELD_PATH_SEPARATOR, HnswConfig, field_path_type, is_multi_vector_field,
+        root_field,
+    },
     schema::{FieldType, Fv, Schema, as_wildcard_map, validate_field_name},
 };
 use serde::Deserialize;
@@ -45,9 +48,17 @@ pub struct CreateCollectionParams {
     /// Fields of the BM25 full-text index (at most one per collection).
     #[serde(default)]
     pub bm25_indexes: Vec<String>,
+    /// BM25 index configuration; set `positions` to enable phrase and
+    /// `NEAR/n` queries, and `tokenizer` to record a tokenizer other than
+    /// the collection's. Only applied when the index is created.
+    #[serde(default)]
+    pub bm25_config: Option<BM25Config>,
     /// HNSW vector index definitions.
     #[serde(default)]
     pub hnsw_indexes: Vec<HnswIndexParams>,
+    /// Fields of sparse vector (dot-product) indexes, one index per field.
+    #[serde(default)]
+    pub sparse_indexes: Vec<String>,
 }
 
 /// An HNSW vector index on one field.
@@ -167,6 +178,30 @@ fn btree_type_is_supported(field_type: &FieldType) -> bool {
     )
 }
 
+/// Rejects tokenizer definitions the database cannot build, or that differ
+/// from its registered definition of the same name.
+fn validate_tokenizers(db: &AndaDB, params: &CreateCollectionParams) -> Result<(), ApiError> {
+    let tokenizers = db.tokenizers();
+    let definitions = [
+        ("config.tokenizer", params.config.tokenizer.as_ref()),
+        (
+            "bm25_config.tokenizer",
+            params
+                .bm25_config
+                .as_ref()
+                .and_then(|config| config.tokenizer.as_ref()),
+        ),
+    ];
+    for (field, definition) in definitions {
+        if let Some(definition) = definition {
+            tokenizers
+                .resolve(definition)
+                .map_err(|err| ApiError::invalid_input(format!("invalid {field}: {err}")))?;
+        }
+    }
+    Ok(())
+}
+
 fn validate_definition(params: &CreateCollectionParams) -> Result<(), ApiError> {
     validate_field_name(&params.config.name)
         .map_err(|err| ApiError::invalid_input(format!("invalid collection name: {err}")))?;
@@ -177,12 +212,17 @@ fn validate_definition(params: &CreateCollectionParams) -> Result<(), ApiError>
                 "B-Tree index requires at least one field",
             ));
         }
+        let mut field_types = Vec::with_capacity(fields.len());
         for name in fields {
-            if params.schema.get_field(name).is_none() {
+            if params.schema.get_field(root_field(name)).is_none() {
                 return Err(ApiError::invalid_input(format!(
                     "B-Tree index field {name:?} is not declared in the schema"
                 )));
             }
+            let field_type = field_path_type(&params.schema, name).map_err(|err| {
+                ApiError::invalid_input(format!("invalid B-Tree index field {name:?}: {err}"))
+            })?;
+            field_types.push(field_type);
         }
         // The engine rejects a single-field `_id` index: the primary key is
         // answered from the always-present id bitmap, so such an index could
@@ -194,18 +234,35 @@ fn validate_definition(params: &CreateCollectionParams) -> Result<(), ApiError>
                 Schema::ID_KEY
             )));
         }
-        if fields.len() == 1 {
-            let field = params
-                .schema
-                .get_field(&fields[0])
-                .expect("field presence checked above");
-            if !btree_type_is_supported(field.r#type()) {
-                return Err(ApiError::invalid_input(format!(
-                    "field {:?} has type {:?}, which cannot be used by a B-Tree index",
-                    fields[0],
-                    field.r#type()
-                )));
-            }
+        // A path into a `Json` field resolves to `Json` and is indexed by
+        // its JSON scalars; a whole `Json` field is not indexable.
+        let json_path =
+            fields[0].contains(FIELD_PATH_SEPARATOR) && matches!(field_types[0], FieldType::Json);
+        if fields.len() == 1 && !json_path && !btree_type_is_supported(&field_types[0]) {
+            return Err(ApiError::invalid_input(format!(
+                "field {:?} has type {:?}, which cannot be used by a B-Tree index",
+                fields[0], field_types[0]
+            )));
+        }
+    }
+
+    if let Some(ttl) = &params.config.ttl {
+        let indexed = params
+            .btree_indexes
+            .iter()
+            .any(|fields| fields.len() == 1 && fields[0] == ttl.field);
+        let integer = params.schema.get_field(&ttl.field).is_some_and(|field| {
+            let field_type = match field.r#type() {
+                FieldType::Option(inner) => inner.as_ref(),
+                other => other,
+            };
+            matches!(field_type, FieldType::U64 | FieldType::I64)
+        });
+        if !indexed || !integer {
+            return Err(ApiError::invalid_input(format!(
+                "TTL field {:?} requires a single-field B-Tree index of type U64 or I64",
+                ttl.field
+            )));
         }
     }
 
@@ -217,6 +274,27 @@ fn validate_definition(params: &CreateCollectionParams) -> Result<(), ApiError>
         }
     }
 
+    if let Some(config) = &params.bm25_config
+        && !config.field_boosts.is_empty()
+    {
+        if config.field_boosts.len() != params.bm25_indexes.len() {
+            return Err(ApiError::invalid_input(format!(
+                "bm25_config.field_boosts has {} boosts for {} BM25 index fields",
+                config.field_boosts.len(),
+                params.bm25_indexes.len()
+            )));
+        }
+        if let Some(boost) = config
+            .field_boosts
+            .iter()
+            .find(|boost| !boost.is_finite() || **boost < 0.0)
+        {
+            return Err(ApiError::invalid_input(format!(
+                "bm25_config.field_boosts must be finite and non-negative, got {boost}"
+            )));
+        }
+    }
+
     for index in &params.hnsw_indexes {
         let field = params.schema.get_field(&index.field).ok_or_else(|| {
             ApiError::invalid_input(format!(
@@ -224,9 +302,9 @@ fn validate_definition(params: &CreateCollectionParams) -> Result<(), ApiError>
                 index.field
             ))
         })?;
-        if field.r#type() != &FieldType::Vector {
+        if field.r#type() != &FieldType::Vector && !is_multi_vector_field(field.r#type()) {
             return Err(ApiError::invalid_input(format!(
-                "HNSW index field {:?} must have type Vector",
+                "HNSW index field {:?} must have type Vector or Array of Vector",
                 index.field
             )));
         }
@@ -236,6 +314,24 @@ fn validate_definition(params: &CreateCollectionParams) -> Result<(), ApiError>
             .map_err(|err| ApiError::invalid_input(err.to_string()))?;
     }
 
+    for name in &params.sparse_indexes {
+        let field = params.schema.get_field(name).ok_or_else(|| {
+            ApiError::invalid_input(format!(
+                "sparse index field {name:?} is not declared in the schema"
+            ))
+        })?;
+        let is_sparse = match field.r#type() {
+            FieldType::SparseVector => true,
+            FieldType::Option(ft) => ft.as_ref() == &FieldType::SparseVector,
+            _ => false,
+        };
+        if !is_sparse {
+            return Err(ApiError::invalid_input(format!(
+                "sparse index field {name:?} must have type SparseVector"
+            )));
+        }
+    }
+
     Ok(())
 }
 
@@ -267,6 +363,7 @@ pub async fn create(
     params: CreateCollectionParams,
 ) -> Result<CollectionMetadata, ApiError> {
     validate_definition(&params)?;
+    validate_tokenizers(db, &params)?;
     ensure_db_writable(db)?;
     if db.metadata().collections.contains(&params.config.name) {
         return Err(ApiError::already_exists(format!(
@@ -279,12 +376,22 @@ pub async fn create(
         schema,
         btree_indexes,
         bm25_indexes,
+        bm25_config,
         hnsw_indexes,
+        sparse_indexes,
     } = params;
     let collection_name = config.name.clone();
     let collection = match db
         .create_collection(schema, config, async |collection| {
-            ensure_indexes(collection, &btree_indexes, &bm25_indexes, &hnsw_indexes).await
+            ensure_indexes(
+                collection,
+                &btree_indexes,
+                &bm25_indexes,
+                bm25_config.as_ref(),
+                &hnsw_indexes,
+                &sparse_indexes,
+            )
+            .await
         })
         .await
     {
@@ -324,13 +431,16 @@ pub async fn ensure(
     params: CreateCollectionParams,
 ) -> Result<CollectionMetadata, ApiError> {
     validate_definition(&params)?;
+    validate_tokenizers(db, &params)?;
     ensure_db_writable(db)?;
     let CreateCollectionParams {
         config,
         schema,
         btree_indexes,
         bm25_indexes,
+        bm25_config,
         hnsw_indexes,
+        sparse_indexes,
     } = params;
     let collection_name = config.name.clone();
     let hnsw_conflict: Mutex<Option<String>> = Mutex::new(None);
@@ -349,7 +459,15 @@ pub async fn ensure(
                     source: "HNSW index configuration conflict".into(),
                 });
             }
-            ensure_indexes(collection, &btree_indexes, &bm25_indexes, &hnsw_indexes).await
+            ensure_indexes(
+                collection,
+                &btree_indexes,
+                &bm25_indexes,
+                bm25_config.as_ref(),
+                &hnsw_indexes,
+                &sparse_indexes,
+            )
+            .await
         })
         .await;
     match result {
@@ -406,7 +524,9 @@ async fn ensure_indexes(
     collection: &mut Collection,
     btree_indexes: &[Vec<String>],
     bm25_indexes: &[String],
+    bm25_config: Option<&BM25Config>,
     hnsw_indexes: &[HnswIndexParams],
+    sparse_indexes: &[String],
 ) -> Result<(), DBError> {
     for fields in btree_indexes {
         let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
@@ -414,13 +534,19 @@ async fn ensure_indexes(
     }
     if !bm25_indexes.is_empty() {
         let fields: Vec<&str> = bm25_indexes.iter().map(String::as_str).collect();
-        collection.create_bm25_index_nx(&fields).await?;
+        let config = bm25_config.cloned().unwrap_or_default();
+        collection
+            .create_bm25_index_with_nx(&fields, config)
+            .await?;
     }
     for index in hnsw_indexes {
         collection
             .create_hnsw_index_nx(&index.field, index.config.clone())
             .await?;
     }
+    for field in sparse_indexes {
+        collection.create_sparse_index_nx(field).await?;
+    }
     Ok(())
 }
 
@@ -522,11 +648,14 @@ mod tests {
             config: CollectionConfig {
                 name: name.to_string(),
                 description: String::new(),
+                ..Default::default()
             },
             schema: Schema::builder().build().unwrap(),
             btree_indexes: Vec::new(),
             bm25_indexes: Vec::new(),
+            bm25_config: None,
             hnsw_indexes: Vec::new(),
+            sparse_indexes: Vec::new(),
         }
     }
 
@@ -9,8 +9,11 @@ use anda_db::{
     collection::{Collection, CollectionMetadata},
     database::AndaDB,
     error::DBError,
-    index::{from_virtual_field_name, virtual_field_value},
-    query::{Filter, Query, RangeQuery},
+    index::{JsonKey, from_virtual_field_name, virtual_field_value},
+    query::{
+        Aggregate, AggregateResult, Aggregation, Filter, Highlights, OrderBy, OrderCursor, Query,
+        RangeQuery, SearchPage,
+    },
     schema::{Document, DocumentId, FieldType, Fv, Schema, as_wildcard_map, bf16},
 };
 use anda_db_tfs::QueryType;
@@ -73,10 +76,33 @@ pub struct UpdateParams {
 pub struct SearchParams {
     /// Target collection name.
     pub collection: String,
-    /// Full-text/vector search, filter, and limit settings.
+    /// Full-text/vector search, filter, projection, ordering, and limit
+    /// settings.
     pub query: Query,
 }
 
+/// One result of `doc.search_scored`.
+#[derive(Debug, Serialize)]
+pub struct ScoredDocument {
+    /// Fused relevance score assigned by the query's reranker.
+    pub score: f32,
+    /// The matching document.
+    pub doc: Fv,
+    /// Highlighted snippets by field, set when `search.highlight` is
+    /// requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<Highlights>,
+}
+
+/// Parameters for B-Tree index aggregations.
+#[derive(Debug, Deserialize)]
+pub struct AggregateParams {
+    /// Target collection name.
+    pub collection: String,
+    /// Index, aggregation kind, filter, and bucket limit.
+    pub aggregate: Aggregate,
+}
+
 /// Parameters for filtered document ID queries.
 #[derive(Debug, Deserialize)]
 pub struct QueryIdsParams {
@@ -97,9 +123,10 @@ pub struct AddResult {
 }
 
 /// Converts numeric arrays into engine `Vector` values for `Vector`-typed
-/// fields. Floats are interpreted as numeric values; integers are bf16 bit
-/// patterns, matching the engine's wire convention so that documents read
-/// from the server can be written back unchanged.
+/// fields and the elements of `Array` of `Vector` fields. Floats are
+/// interpreted as numeric values; integers are bf16 bit patterns, matching
+/// the engine's wire convention so that documents read from the server can
+/// be written back unchanged.
 fn coerce_vector_fields(schema: &Schema, doc: &mut BTreeMap<String, Fv>) -> Result<(), ApiError> {
     fn is_vector(field_type: &FieldType) -> bool {
         match field_type {
@@ -119,11 +146,16 @@ fn coerce_vector_fields(schema: &Schema, doc: &mut BTreeMap<String, Fv>) -> Resu
         }
     }
 
-    for (name, value) in doc.iter_mut() {
-        if let Fv::Array(elements) = value
-            && let Some(field) = schema.get_field(name)
-            && is_vector(field.r#type())
-        {
+    fn is_vector_array(field_type: &FieldType) -> bool {
+        match field_type {
+            FieldType::Array(types) => types.as_slice() == [FieldType::Vector],
+            FieldType::Option(inner) => is_vector_array(inner),
+            _ => false,
+        }
+    }
+
+    fn to_vector(name: &str, value: &mut Fv) -> Result<(), ApiError> {
+        if let Fv::Array(elements) = value {
             let vector = elements
                 .iter()
                 .map(to_bf16)
@@ -135,6 +167,22 @@ fn coerce_vector_fields(schema: &Schema, doc: &mut BTreeMap<String, Fv>) -> Resu
                 })?;
             *value = Fv::Vector(vector);
         }
+        Ok(())
+    }
+
+    for (name, value) in doc.iter_mut() {
+        let Some(field) = schema.get_field(name) else {
+            continue;
+        };
+        if is_vector(field.r#type()) {
+            to_vector(name, value)?;
+        } else if is_vector_array(field.r#type())
+            && let Fv::Array(vectors) = value
+        {
+            for vector in vectors {
+                to_vector(name, vector)?;
+            }
+        }
     }
     Ok(())
 }
@@ -287,6 +335,8 @@ fn range_matches_field_type(field_type: &FieldType, query: &RangeQuery<Fv>) -> O
         FieldType::U64 => Some(RangeQuery::<u64>::try_convert_from(query.clone()).is_ok()),
         FieldType::Text => Some(RangeQuery::<String>::try_convert_from(query.clone()).is_ok()),
         FieldType::Bytes => Some(RangeQuery::<Vec<u8>>::try_convert_from(query.clone()).is_ok()),
+        // The type a path into a `Json` field resolves to.
+        FieldType::Json => Some(RangeQuery::<JsonKey>::try_convert_from(query.clone()).is_ok()),
         FieldType::Option(inner) => range_matches_field_type(inner, query),
         FieldType::Array(inner) if inner.len() == 1 => range_matches_field_type(&inner[0], query),
         // Only a *wildcard* map indexes its keys. A one-entry map declared by
@@ -343,6 +393,9 @@ fn validate_filter(metadata: &CollectionMetadata, filter: &Filter) -> Result<(),
     }
 }
 
+/// Maximum number of vectors in a multi-vector (`search.vectors`) query.
+const MAX_QUERY_VECTORS: usize = 256;
+
 fn validate_search_query(collection: &Collection, query: &Query) -> Result<(), ApiError> {
     query
         .validate_complexity()
@@ -350,6 +403,34 @@ fn validate_search_query(collection: &Collection, query: &Query) -> Result<(), A
     let metadata = collection.metadata();
 
     if let Some(search) = &query.search {
+        search.resolve_fusion().map_err(ApiError::invalid_query)?;
+        if let Some(cutoffs) = &search.score_cutoffs {
+            cutoffs.validate().map_err(ApiError::invalid_query)?;
+        }
+        if let Some(rerank) = &search.rerank {
+            // The server registers no rerankers of its own; embedders that
+            // install some on their collections can still select them.
+            if !collection.has_reranker(&rerank.name) {
+                return Err(ApiError::invalid_query(format!(
+                    "reranker {:?} is not registered",
+                    rerank.name
+                )));
+            }
+            if query.order_by.is_some() {
+                return Err(ApiError::invalid_query(
+                    "search rerank cannot be combined with order_by",
+                ));
+            }
+            if rerank.query.is_none() && search.text.is_none() {
+                return Err(ApiError::invalid_query(
+                    "search rerank requires a query text",
+                ));
+            }
+        }
+        collection
+            .validate_field_boosts(search)
+            .and_then(|_| collection.validate_highlight(search))
+            .map_err(ApiError::invalid_query)?;
         if let Some(text) = &search.text {
             if metadata.bm25_indexes.is_empty() {
                 return Err(ApiError::invalid_query(
@@ -361,20 +442,70 @@ fn validate_search_query(collection: &Collection, query: &Query) -> Result<(), A
             }
         }
 
-        if let Some(vector) = &search.vector {
-            validate_query_vector(vector)?;
+        let queries: &[Vec<f32>] = match (&search.vector, &search.vectors) {
+            (Some(_), Some(_)) => {
+                return Err(ApiError::invalid_query(
+                    "search vector and vectors are mutually exclusive",
+                ));
+            }
+            (Some(vector), None) => std::slice::from_ref(vector),
+            (None, Some(vectors)) => {
+                if vectors.is_empty() || vectors.len() > MAX_QUERY_VECTORS {
+                    return Err(ApiError::invalid_query(format!(
+                        "search vectors must hold between 1 and {MAX_QUERY_VECTORS} vectors"
+                    )));
+                }
+                vectors
+            }
+            (None, None) => &[],
+        };
+        if let Some(mmr) = &search.mmr {
+            mmr.validate().map_err(ApiError::invalid_query)?;
+            if queries.is_empty() {
+                return Err(ApiError::invalid_query("mmr requires a vector query"));
+            }
+            if query.order_by.is_some() {
+                return Err(ApiError::invalid_query(
+                    "mmr cannot be combined with order_by",
+                ));
+            }
+        }
+        if let Some(sparse_vector) = &search.sparse_vector {
+            if metadata.sparse_indexes.is_empty() {
+                return Err(ApiError::invalid_query(
+                    "sparse vector search requires a sparse index, but none exists",
+                ));
+            }
+            if sparse_vector
+                .iter()
+                .any(|(term, weight)| term.is_empty() || !weight.is_finite())
+            {
+                return Err(ApiError::invalid_query(
+                    "sparse vector terms must be non-empty with finite weights",
+                ));
+            }
+        }
+
+        if let Some(dimension) = queries.first().map(Vec::len) {
+            for vector in queries {
+                validate_query_vector(vector)?;
+                if vector.len() != dimension {
+                    return Err(ApiError::invalid_query(
+                        "search vectors must have the same dimension",
+                    ));
+                }
+            }
             let mut compatible = false;
             for field in metadata.hnsw_indexes.keys() {
                 // Metadata says the index exists. Failure to find its live
                 // wrapper is therefore internal and goes through the
                 // conservative engine-error mapping.
                 let index = collection.get_hnsw_index(field)?;
-                compatible |= index.dimension() == vector.len();
+                compatible |= index.dimension() == dimension;
             }
-            if !compatible && search.text.is_none() {
+            if !compatible && search.text.is_none() && search.sparse_vector.is_none() {
                 return Err(ApiError::invalid_query(format!(
-                    "no HNSW index matches query vector dimension {}",
-                    vector.len()
+                    "no HNSW index matches query vector dimension {dimension}"
                 )));
             }
         }
@@ -383,6 +514,95 @@ fn validate_search_query(collection: &Collection, query: &Query) -> Result<(), A
     if let Some(filter) = &query.filter {
         validate_filter(&metadata, filter)?;
     }
+
+    if let Some(fields) = &query.fields {
+        let schema = collection.schema();
+        if let Some(name) = fields.iter().find(|name| schema.get_field(name).is_none()) {
+            return Err(ApiError::invalid_query(format!(
+                "projection field {name:?} does not exist"
+            )));
+        }
+    }
+
+    match &query.order_by {
+        Some(order_by) => validate_order_by(&metadata, order_by, query.cursor.as_deref())?,
+        None if query.cursor.is_some() => {
+            return Err(ApiError::invalid_query("query cursor requires order_by"));
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+fn validate_order_by(
+    metadata: &CollectionMetadata,
+    order_by: &OrderBy,
+    cursor: Option<&str>,
+) -> Result<(), ApiError> {
+    let name = &order_by.field;
+    let field_type = metadata
+        .btree_indexes
+        .get(name)
+        .filter(|_| from_virtual_field_name(name).len() == 1)
+        .ok_or_else(|| {
+            ApiError::invalid_query(format!(
+                "order_by requires a single-field B-Tree index {name:?}, but it does not exist"
+            ))
+        })?
+        .r#type();
+    let scalar = match field_type {
+        FieldType::Option(inner) => inner.as_ref(),
+        other => other,
+    };
+    if matches!(scalar, FieldType::Array(_) | FieldType::Map(_)) {
+        return Err(ApiError::invalid_query(format!(
+            "order_by field {name:?} must be a scalar field"
+        )));
+    }
+
+    if let Some(cursor) = cursor {
+        let cursor = OrderCursor::decode_for(cursor, order_by).map_err(ApiError::invalid_query)?;
+        if range_matches_field_type(field_type, &RangeQuery::Eq(cursor.key)) != Some(true) {
+            return Err(ApiError::invalid_query("invalid query cursor"));
+        }
+    }
+    Ok(())
+}
+
+fn validate_aggregate(collection: &Collection, aggregate: &Aggregate) -> Result<(), ApiError> {
+    aggregate
+        .validate_complexity()
+        .map_err(ApiError::invalid_query)?;
+    let metadata = collection.metadata();
+    let name = &aggregate.field;
+    let field_type = metadata
+        .btree_indexes
+        .get(name)
+        .ok_or_else(|| {
+            ApiError::invalid_query(format!(
+                "aggregation requires B-Tree index {name:?}, but it does not exist"
+            ))
+        })?
+        .r#type();
+    if let Aggregation::Histogram { interval } = aggregate.aggregation {
+        if interval == 0 {
+            return Err(ApiError::invalid_query(
+                "histogram interval must be positive",
+            ));
+        }
+        let integer = !matches!(field_type, FieldType::Json)
+            && [Fv::U64(0), Fv::I64(0)].into_iter().any(|key| {
+                range_matches_field_type(field_type, &RangeQuery::Eq(key)) == Some(true)
+            });
+        if !integer {
+            return Err(ApiError::invalid_query(format!(
+                "histogram requires an integer B-Tree index, but {name:?} is not one"
+            )));
+        }
+    }
+    if let Some(filter) = &aggregate.filter {
+        validate_filter(&metadata, filter)?;
+    }
     Ok(())
 }
 
@@ -596,20 +816,70 @@ pub async fn count(db: &AndaDB, params: super::CollectionParams) -> Result<u64,
     Ok(collection.len() as u64)
 }
 
+/// Rejects `order_by` and `cursor` on the search RPCs that return a plain
+/// list, which has no room for the next-page cursor or the `truncated` flag.
+fn reject_paging(method: &str, query: &Query) -> Result<(), ApiError> {
+    if query.order_by.is_some() || query.cursor.is_some() {
+        return Err(ApiError::invalid_query(format!(
+            "{method} does not support order_by or cursor, use doc.search_page"
+        )));
+    }
+    Ok(())
+}
+
 /// `doc.search` — returns matching documents.
 pub async fn search(db: &AndaDB, params: SearchParams) -> Result<Vec<Fv>, ApiError> {
+    reject_paging("doc.search", &params.query)?;
     let collection = open(db, &params.collection).await?;
     validate_search_query(&collection, &params.query)?;
     Ok(collection.search_as(params.query).await?)
 }
 
+/// `doc.search_page` — returns one page of matching documents, with the
+/// cursor of the next page when `query.order_by` is set and more follow.
+pub async fn search_page(db: &AndaDB, params: SearchParams) -> Result<SearchPage<Fv>, ApiError> {
+    let collection = open(db, &params.collection).await?;
+    validate_search_query(&collection, &params.query)?;
+    Ok(collection.search_page_as(params.query).await?)
+}
+
+/// `doc.search_scored` — returns matching documents with their fused
+/// relevance score and, when `search.highlight` is set, highlighted snippets.
+pub async fn search_scored(
+    db: &AndaDB,
+    params: SearchParams,
+) -> Result<Vec<ScoredDocument>, ApiError> {
+    reject_paging("doc.search_scored", &params.query)?;
+    let collection = open(db, &params.collection).await?;
+    validate_search_query(&collection, &params.query)?;
+    let mut rt = Vec::new();
+    for hit in collection.search_hits(params.query).await? {
+        let doc = hit.doc.try_into().map_err(DBError::from)?;
+        rt.push(ScoredDocument {
+            score: hit.score,
+            doc,
+            highlight: hit.highlight,
+        });
+    }
+    Ok(rt)
+}
+
 /// `doc.search_ids` — returns matching document IDs only.
 pub async fn search_ids(db: &AndaDB, params: SearchParams) -> Result<Vec<DocumentId>, ApiError> {
+    reject_paging("doc.search_ids", &params.query)?;
     let collection = open(db, &params.collection).await?;
     validate_search_query(&collection, &params.query)?;
     Ok(collection.search_ids(params.query).await?)
 }
 
+/// `doc.aggregate` — returns the count, min/max keys, and buckets of a
+/// B-Tree index, computed without loading documents.
+pub async fn aggregate(db: &AndaDB, params: AggregateParams) -> Result<AggregateResult, ApiError> {
+    let collection = open(db, &params.collection).await?;
+    validate_aggregate(&collection, &params.aggregate)?;
+    Ok(collection.aggregate(params.aggregate).await?)
+}
+
 /// Maximum number of document IDs a single `doc.query_ids` call may return.
 ///
 /// An omitted `limit` used to mean "every matching ID", so a one-line request
@@ -719,6 +989,7 @@ mod tests {
             CollectionConfig {
                 name: "articles".to_string(),
                 description: String::new(),
+                ..Default::default()
             },
             async |collection| collection.create_btree_index_nx(&["slug"]).await,
         )
@@ -785,6 +1056,7 @@ mod tests {
                 CollectionConfig {
                     name: "memories".to_string(),
                     description: String::new(),
+                    ..Default::default()
                 },
                 async |_| Ok(()),
             )
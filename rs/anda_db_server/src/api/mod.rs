@@ -262,9 +262,12 @@ enum DbMethod {
     DocExists,
     DocCount,
     DocSearch,
+    DocSearchPage,
+    DocSearchScored,
     DocSearchIds,
     DocQueryIds,
     DocQueryLastIds,
+    DocAggregate,
 }
 
 impl DbMethod {
@@ -307,9 +310,12 @@ impl DbMethod {
             "doc.exists" => (Self::DocExists, Read),
             "doc.count" => (Self::DocCount, Read),
             "doc.search" => (Self::DocSearch, Read),
+            "doc.search_page" => (Self::DocSearchPage, Read),
+            "doc.search_scored" => (Self::DocSearchScored, Read),
             "doc.search_ids" => (Self::DocSearchIds, Read),
             "doc.query_ids" => (Self::DocQueryIds, Read),
             "doc.query_last_ids" => (Self::DocQueryLastIds, Read),
+            "doc.aggregate" => (Self::DocAggregate, Read),
 
             _ => return None,
         })
@@ -521,11 +527,16 @@ async fn dispatch_db(
         DbMethod::DocExists => enc.reply(&document::exists(&db, params.decode()?).await?),
         DbMethod::DocCount => enc.reply(&document::count(&db, params.decode()?).await?),
         DbMethod::DocSearch => enc.reply(&document::search(&db, params.decode()?).await?),
+        DbMethod::DocSearchPage => enc.reply(&document::search_page(&db, params.decode()?).await?),
+        DbMethod::DocSearchScored => {
+            enc.reply(&document::search_scored(&db, params.decode()?).await?)
+        }
         DbMethod::DocSearchIds => enc.reply(&document::search_ids(&db, params.decode()?).await?),
         DbMethod::DocQueryIds => enc.reply(&document::query_ids(&db, params.decode()?).await?),
         DbMethod::DocQueryLastIds => {
             enc.reply(&document::query_last_ids(&db, params.decode()?).await?)
         }
+        DbMethod::DocAggregate => enc.reply(&document::aggregate(&db, params.decode()?).await?),
     };
     Ok(resp)
 }
@@ -68,8 +68,8 @@
 //!   `collection.get_extension`, `collection.save_extension`,
 //!   `collection.remove_extension`
 //! - `doc.add`, `doc.add_many`, `doc.get`, `doc.get_many`, `doc.update`,
-//!   `doc.remove`, `doc.exists`, `doc.count`, `doc.search`, `doc.search_ids`,
-//!   `doc.query_ids`
+//!   `doc.remove`, `doc.exists`, `doc.count`, `doc.search`, `doc.search_page`,
+//!   `doc.search_scored`, `doc.search_ids`, `doc.query_ids`, `doc.aggregate`
 //!
 //! See the crate README for parameter shapes and examples.
 
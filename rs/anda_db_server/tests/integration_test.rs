@@ -673,6 +673,73 @@ async fn test_search_and_query_ids() {
     .await;
     assert_eq!(docs.as_array().unwrap().len(), 3);
 
+    // Score-based fusion returns the fused score with every document.
+    let scored = rpc_ok(
+        &app,
+        &path,
+        "doc.search_scored",
+        json!({
+            "collection": "articles",
+            "query": {
+                "search": {"text": "Anda", "fusion": {"Linear": {"normalization": "MinMax"}}},
+                "limit": 10
+            }
+        }),
+    )
+    .await;
+    let scored = scored.as_array().unwrap();
+    assert_eq!(scored.len(), 3);
+    let scores: Vec<f64> = scored
+        .iter()
+        .map(|hit| hit["score"].as_f64().unwrap())
+        .collect();
+    assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+    assert_eq!(scores[0], 1.0);
+    assert!(scored[0]["doc"]["_id"].is_u64());
+
+    let error = rpc_err(
+        &app,
+        &path,
+        "doc.search_scored",
+        json!({
+            "collection": "articles",
+            "query": {"search": {"text": "Anda", "reranker": {"k": 0.0}}}
+        }),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(error["code"], "invalid_query");
+    let error = rpc_err(
+        &app,
+        &path,
+        "doc.search_scored",
+        json!({
+            "collection": "articles",
+            "query": {"search": {
+                "text": "Anda",
+                "reranker": {"k": 60.0},
+                "fusion": {"Distribution": {}}
+            }}
+        }),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(error["code"], "invalid_query");
+
+    // MMR diversifies vector results only.
+    let error = rpc_err(
+        &app,
+        &path,
+        "doc.search",
+        json!({
+            "collection": "articles",
+            "query": {"search": {"text": "Anda", "mmr": {"lambda": 0.5}}}
+        }),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(error["code"], "invalid_query");
+
     let ids = rpc_ok(
         &app,
         &path,
@@ -699,6 +766,412 @@ async fn test_search_and_query_ids() {
     assert_eq!(ids.as_array().unwrap().len(), 1);
 }
 
+#[tokio::test]
+async fn test_phrase_search_with_bm25_positions() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+    let mut params = create_articles_params();
+    params["bm25_config"] = json!({"positions": true});
+    rpc_ok(&app, &path, "collection.create", params).await;
+
+    let phrase = add_article(&app, PRIMARY_DB, "Intro", "machine learning basics", 1).await;
+    add_article(
+        &app,
+        PRIMARY_DB,
+        "Tools",
+        "learning about the machine tools",
+        2,
+    )
+    .await;
+
+    let search_ids = async |text: &str| {
+        rpc_ok(
+            &app,
+            &path,
+            "doc.search_ids",
+            json!({
+                "collection": "articles",
+                "query": {"search": {"text": text, "logical_search": true}, "limit": 10}
+            }),
+        )
+        .await
+    };
+    assert_eq!(
+        search_ids("machine learning")
+            .await
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(search_ids("\"machine learning\"").await, json!([phrase]));
+    assert_eq!(search_ids("learning NEAR/1 machine").await, json!([phrase]));
+    // Prefix, wildcard and fuzzy terms expand over the term dictionary.
+    assert_eq!(search_ids("basi*").await, json!([phrase]));
+    assert_eq!(search_ids("t??l").await.as_array().unwrap().len(), 1);
+    assert_eq!(search_ids("machne~1 AND NOT tols~1").await, json!([phrase]));
+}
+
+#[tokio::test]
+async fn test_recorded_tokenizer() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+
+    let mut params = create_articles_params();
+    params["config"]["tokenizer"] = json!({
+        "name": "custom",
+        "version": 1,
+        "filters": ["LowerCase", {"Stemmer": "klingon"}]
+    });
+    let error = rpc_err(
+        &app,
+        &path,
+        "collection.create",
+        params,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(error["code"], "invalid_input");
+
+    // The built-in jieba definition under another version is refused.
+    let mut params = create_articles_params();
+    params["config"]["tokenizer"] = json!({"name": "jieba", "version": 2});
+    let error = rpc_err(
+        &app,
+        &path,
+        "collection.create",
+        params,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(error["code"], "invalid_input");
+
+    let mut params = create_articles_params();
+    params["config"]["tokenizer"] = json!({
+        "name": "jieba",
+        "version": 1,
+        "filters": ["JiebaMerge", {"RemoveLong": 32}, "LowerCase", {"Stemmer": "english"}]
+    });
+    let metadata = rpc_ok(&app, &path, "collection.create", params).await;
+    assert_eq!(metadata["config"]["tokenizer"]["name"], "jieba");
+
+    let id = add_article(&app, PRIMARY_DB, "笔记", "智能体需要长期记忆", 1).await;
+    let found = rpc_ok(
+        &app,
+        &path,
+        "doc.search_ids",
+        json!({"collection": "articles", "query": {"search": {"text": "记忆"}, "limit": 10}}),
+    )
+    .await;
+    assert_eq!(found, json!([id]));
+}
+
+#[tokio::test]
+async fn test_bm25f_field_boosts() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+
+    let mut params = create_articles_params();
+    params["bm25_config"] = json!({"field_boosts": [3.0]});
+    let error = rpc_err(
+        &app,
+        &path,
+        "collection.create",
+        params,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(error["code"], "invalid_input");
+
+    let mut params = create_articles_params();
+    params["bm25_config"] = json!({"field_boosts": [3.0, 1.0]});
+    rpc_ok(&app, &path, "collection.create", params).await;
+
+    let in_title = add_article(&app, PRIMARY_DB, "Rust", "notes on storage engines", 1).await;
+    let in_body = add_article(&app, PRIMARY_DB, "Storage", "rust storage engines", 2).await;
+
+    let search_ids = async |search: Value| {
+        rpc_ok(
+            &app,
+            &path,
+            "doc.search_ids",
+            json!({"collection": "articles", "query": {"search": search, "limit": 10}}),
+        )
+        .await
+    };
+    assert_eq!(
+        search_ids(json!({"text": "rust"})).await,
+        json!([in_title, in_body])
+    );
+    assert_eq!(
+        search_ids(json!({"text": "rust", "field_boosts": {"title": 0.1, "body": 5.0}})).await,
+        json!([in_body, in_title])
+    );
+
+    for search in [
+        json!({"text": "rust", "field_boosts": {"score": 1.0}}),
+        json!({"text": "rust", "field_boosts": {"title": -1.0}}),
+        json!({"field_boosts": {"title": 1.0}}),
+    ] {
+        let error = rpc_err(
+            &app,
+            &path,
+            "doc.search_ids",
+            json!({"collection": "articles", "query": {"search": search}}),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(error["code"], "invalid_query");
+    }
+}
+
+#[tokio::test]
+async fn test_search_scored_with_highlight() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+    setup_articles(&app, PRIMARY_DB).await;
+
+    let id = add_article(
+        &app,
+        PRIMARY_DB,
+        "Agent memory",
+        "Long-term memory lets agents recall earlier sessions.",
+        1,
+    )
+    .await;
+    add_article(&app, PRIMARY_DB, "Other", "Nothing relevant here", 2).await;
+
+    let search_scored = async |search: Value| {
+        rpc_ok(
+            &app,
+            &path,
+            "doc.search_scored",
+            json!({"collection": "articles", "query": {"search": search, "limit": 10}}),
+        )
+        .await
+    };
+    let hits = search_scored(json!({"text": "memory"})).await;
+    assert_eq!(hits[0]["doc"]["_id"], json!(id));
+    assert!(hits[0].get("highlight").is_none());
+
+    let hits = search_scored(json!({
+        "text": "memory",
+        "highlight": {"fields": ["body"], "fragment_size": 30}
+    }))
+    .await;
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+    let snippets = hits[0]["highlight"]["body"].as_array().unwrap();
+    assert_eq!(snippets.len(), 1);
+    let snippet = &snippets[0];
+    let text = snippet["text"].as_str().unwrap();
+    assert!(text.len() <= 30);
+    let start = snippet["highlights"][0]["start"].as_u64().unwrap() as usize;
+    let end = snippet["highlights"][0]["end"].as_u64().unwrap() as usize;
+    assert_eq!(&text[start..end], "memory");
+    assert!(hits[0]["highlight"].get("title").is_none());
+
+    for search in [
+        json!({"text": "memory", "highlight": {"fields": ["score"]}}),
+        json!({"text": "memory", "highlight": {"max_fragments": 0}}),
+        json!({"highlight": {}}),
+    ] {
+        let error = rpc_err(
+            &app,
+            &path,
+            "doc.search_scored",
+            json!({"collection": "articles", "query": {"search": search}}),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(error["code"], "invalid_query");
+    }
+}
+
+#[tokio::test]
+async fn test_search_projection_order_and_cursor() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+    setup_articles(&app, PRIMARY_DB).await;
+
+    let mut ids = Vec::new();
+    for i in 0u64..5 {
+        let id = add_article(
+            &app,
+            PRIMARY_DB,
+            &format!("Article {i}"),
+            &format!("Anda DB content number {i}"),
+            (i % 3) * 10,
+        )
+        .await;
+        ids.push(id);
+    }
+
+    // Newest-by-score first, ties broken by descending `_id`, two per page.
+    let expected = vec![ids[2], ids[4], ids[1], ids[3], ids[0]];
+    let mut query = json!({
+        "fields": ["title"],
+        "order_by": {"field": "score", "descending": true},
+        "limit": 2
+    });
+    let mut got = Vec::new();
+    loop {
+        let page = rpc_ok(
+            &app,
+            &path,
+            "doc.search_page",
+            json!({"collection": "articles", "query": query.clone()}),
+        )
+        .await;
+        for doc in page["items"].as_array().unwrap() {
+            assert!(doc.get("title").is_some());
+            assert!(doc.get("score").is_none());
+            got.push(doc["_id"].as_u64().unwrap());
+        }
+        match page["cursor"].as_str() {
+            Some(cursor) => query["cursor"] = json!(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(got, expected);
+
+    // Ascending order with another projection.
+    let page = rpc_ok(
+        &app,
+        &path,
+        "doc.search_page",
+        json!({
+            "collection": "articles",
+            "query": {
+                "fields": ["score"],
+                "order_by": {"field": "score"},
+                "limit": 3
+            }
+        }),
+    )
+    .await;
+    let docs = page["items"].as_array().unwrap();
+    let got: Vec<u64> = docs.iter().map(|d| d["_id"].as_u64().unwrap()).collect();
+    assert_eq!(got, vec![ids[0], ids[3], ids[1]]);
+    assert!(docs.iter().all(|d| d.get("title").is_none()));
+    assert_eq!(page["truncated"], false);
+
+    // The list-returning search RPCs cannot carry a cursor.
+    for (method, query) in [
+        ("doc.search", json!({"order_by": {"field": "score"}})),
+        ("doc.search_ids", json!({"order_by": {"field": "score"}})),
+        ("doc.search_scored", json!({"cursor": "AA"})),
+    ] {
+        let err = rpc_err(
+            &app,
+            &path,
+            method,
+            json!({"collection": "articles", "query": query}),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(err["code"], "invalid_query");
+        assert_eq!(
+            err["message"],
+            format!("{method} does not support order_by or cursor, use doc.search_page")
+        );
+    }
+
+    for (query, message) in [
+        (
+            json!({"order_by": {"field": "title"}}),
+            "order_by requires a single-field B-Tree index \"title\", but it does not exist",
+        ),
+        (json!({"cursor": "AA"}), "query cursor requires order_by"),
+        (
+            json!({"fields": ["missing"]}),
+            "projection field \"missing\" does not exist",
+        ),
+    ] {
+        let err = rpc_err(
+            &app,
+            &path,
+            "doc.search_page",
+            json!({"collection": "articles", "query": query}),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(err["code"], "invalid_query");
+        assert_eq!(err["message"], message);
+    }
+
+    let err = rpc_err(
+        &app,
+        &path,
+        "doc.search_page",
+        json!({
+            "collection": "articles",
+            "query": {"order_by": {"field": "score"}, "cursor": "not a cursor"}
+        }),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(err["code"], "invalid_query");
+}
+
+#[tokio::test]
+async fn test_aggregate() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+    setup_articles(&app, PRIMARY_DB).await;
+    for i in 0u64..5 {
+        add_article(&app, PRIMARY_DB, &format!("Article {i}"), "body", i * 5).await;
+    }
+
+    let rt = rpc_ok(
+        &app,
+        &path,
+        "doc.aggregate",
+        json!({
+            "collection": "articles",
+            "aggregate": {
+                "field": "score",
+                "aggregation": {"Histogram": {"interval": 10}},
+                "filter": {"Field": ["score", {"Ge": 5}]}
+            }
+        }),
+    )
+    .await;
+    assert_eq!(rt["count"], 4);
+    assert_eq!(rt["min"], 5);
+    assert_eq!(rt["max"], 20);
+    assert_eq!(
+        rt["buckets"],
+        json!([
+            {"key": 0, "count": 1},
+            {"key": 10, "count": 2},
+            {"key": 20, "count": 1}
+        ])
+    );
+    assert_eq!(rt["truncated"], false);
+
+    for (aggregate, message) in [
+        (
+            json!({"field": "title", "aggregation": "Terms"}),
+            "aggregation requires B-Tree index \"title\", but it does not exist",
+        ),
+        (
+            json!({"field": "score", "aggregation": {"Histogram": {"interval": 0}}}),
+            "histogram interval must be positive",
+        ),
+    ] {
+        let err = rpc_err(
+            &app,
+            &path,
+            "doc.aggregate",
+            json!({"collection": "articles", "aggregate": aggregate}),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(err["code"], "invalid_query");
+        assert_eq!(err["message"], message);
+    }
+}
+
 /// `doc.query_ids` with no `limit` used to return one ID per matching
 /// document — an unbounded response body from a one-line request — and
 /// `doc.get_many` accepted an unbounded ID list, each ID costing an
@@ -954,6 +1427,187 @@ async fn test_btree_index_on_a_nested_struct_field_is_rejected_at_definition() {
     assert_eq!(meta["btree_indexes"].as_object().unwrap().len(), 1);
 }
 
+/// A B-Tree index may name a dotted path into a `Map` or `Json` field; the
+/// definition is validated against the type the path resolves to.
+#[tokio::test]
+async fn test_btree_index_on_a_nested_field_path() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+
+    let schema = json!({
+        "fields": [
+            {"name": "_id", "description": "", "type": "U64", "unique": true, "index": 0},
+            {
+                "name": "meta",
+                "description": "",
+                "type": {"Map": {"owner": "Text", "size": "U64"}},
+                "unique": false,
+                "index": 1
+            },
+            {"name": "extra", "description": "", "type": "Json", "unique": false, "index": 2}
+        ]
+    });
+
+    for (fields, message) in [
+        (json!(["meta.missing"]), "invalid B-Tree index field"),
+        (json!(["meta.owner.name"]), "invalid B-Tree index field"),
+        (json!(["other.owner"]), "is not declared in the schema"),
+    ] {
+        let err = rpc_err(
+            &app,
+            &path,
+            "collection.create",
+            json!({
+                "config": {"name": "nested", "description": ""},
+                "schema": schema,
+                "btree_indexes": [fields]
+            }),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(err["code"], "invalid_input");
+        assert!(
+            err["message"].as_str().unwrap().contains(message),
+            "unexpected message: {}",
+            err["message"]
+        );
+    }
+
+    rpc_ok(
+        &app,
+        &path,
+        "collection.create",
+        json!({
+            "config": {"name": "nested", "description": ""},
+            "schema": schema,
+            "btree_indexes": [["meta.owner"], ["meta.size"], ["extra.rank"]]
+        }),
+    )
+    .await;
+    for (owner, size) in [("alice", 3), ("bob", 5), ("alice", 8)] {
+        rpc_ok(
+            &app,
+            &path,
+            "doc.add",
+            json!({"collection": "nested", "doc": {
+                "meta": {"owner": owner, "size": size},
+                "extra": {"rank": size}
+            }}),
+        )
+        .await;
+    }
+
+    let ids = rpc_ok(
+        &app,
+        &path,
+        "doc.query_ids",
+        json!({
+            "collection": "nested",
+            "filter": {"And": [
+                {"Field": ["meta.owner", {"Eq": "alice"}]},
+                {"Field": ["meta.size", {"Gt": 4}]}
+            ]}
+        }),
+    )
+    .await;
+    assert_eq!(ids, json!([3]));
+
+    // JSON numbers are indexed as numbers: ranges compare numerically and
+    // a string never equals a number.
+    let ids = rpc_ok(
+        &app,
+        &path,
+        "doc.query_ids",
+        json!({"collection": "nested", "filter": {"Field": ["extra.rank", {"Gt": 4}]}}),
+    )
+    .await;
+    assert_eq!(ids, json!([2, 3]));
+    let ids = rpc_ok(
+        &app,
+        &path,
+        "doc.query_ids",
+        json!({"collection": "nested", "filter": {"Field": ["extra.rank", {"Eq": "8"}]}}),
+    )
+    .await;
+    assert_eq!(ids, json!([]));
+}
+
+/// A collection TTL is validated at definition time and applied by the
+/// database's background flush task.
+#[tokio::test]
+async fn test_collection_ttl_expires_documents() {
+    let app = build_router(
+        AppState::connect(
+            Arc::new(InMemory::new()),
+            ServerOptions {
+                flush_interval: Duration::from_millis(20),
+                ..test_options(None)
+            },
+        )
+        .await
+        .expect("failed to connect AppState"),
+    );
+    let path = format!("/{PRIMARY_DB}");
+    let params = |btree_indexes: Value| {
+        json!({
+            "config": {
+                "name": "memories",
+                "description": "",
+                "ttl": {"field": "expires_at", "ttl_ms": 0}
+            },
+            "schema": {
+                "fields": [
+                    {"name": "_id", "description": "", "type": "U64", "unique": true, "index": 0},
+                    {"name": "expires_at", "description": "", "type": "U64", "unique": false, "index": 1}
+                ]
+            },
+            "btree_indexes": btree_indexes
+        })
+    };
+
+    let err = rpc_err(
+        &app,
+        &path,
+        "collection.create",
+        params(json!([])),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(err["code"], "invalid_input");
+
+    let meta = rpc_ok(
+        &app,
+        &path,
+        "collection.create",
+        params(json!([["expires_at"]])),
+    )
+    .await;
+    assert_eq!(meta["config"]["ttl"]["field"], "expires_at");
+
+    for expires_at in [1u64, u64::MAX / 2] {
+        rpc_ok(
+            &app,
+            &path,
+            "doc.add",
+            json!({"collection": "memories", "doc": {"expires_at": expires_at}}),
+        )
+        .await;
+    }
+
+    let query = json!({
+        "collection": "memories",
+        "filter": {"Field": ["expires_at", {"Ge": 0}]}
+    });
+    for _ in 0..100 {
+        let ids = rpc_ok(&app, &path, "doc.query_ids", query.clone()).await;
+        if ids == json!([2]) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the expired document was not removed");
+}
+
 #[tokio::test]
 async fn test_database_isolation() {
     let app = test_app().await;
@@ -1347,6 +2001,85 @@ async fn test_vector_collection_with_hnsw_index() {
     assert_eq!(docs[1]["text"], "gamma");
 }
 
+#[tokio::test]
+async fn test_multi_vector_collection_with_max_sim_search() {
+    let app = test_app().await;
+    let path = format!("/{PRIMARY_DB}");
+
+    rpc_ok(
+        &app,
+        &path,
+        "collection.create",
+        json!({
+            "config": {"name": "chunks", "description": "Chunked memories"},
+            "schema": {
+                "fields": [
+                    {"name": "_id", "description": "", "type": "U64", "unique": true, "index": 0},
+                    {"name": "text", "description": "", "type": "Text", "unique": false, "index": 1},
+                    {"name": "embeddings", "description": "", "type": {"Array": ["Vector"]}, "unique": false, "index": 2}
+                ]
+            },
+            "hnsw_indexes": [{
+                "field": "embeddings",
+                "config": {
+                    "dimension": 2,
+                    "max_layers": 4,
+                    "max_connections": 8,
+                    "ef_construction": 50,
+                    "ef_search": 20,
+                    "distance_metric": "Euclidean",
+                    "select_neighbors_strategy": "Heuristic"
+                }
+            }]
+        }),
+    )
+    .await;
+
+    for (text, embeddings) in [
+        ("one", json!([[1.0, 0.0]])),
+        ("both", json!([[0.9, 0.1], [0.1, 0.9]])),
+    ] {
+        rpc_ok(
+            &app,
+            &path,
+            "doc.add",
+            json!({"collection": "chunks", "doc": {"text": text, "embeddings": embeddings}}),
+        )
+        .await;
+    }
+
+    let docs = rpc_ok(
+        &app,
+        &path,
+        "doc.search",
+        json!({
+            "collection": "chunks",
+            "query": {"search": {"vectors": [[1.0, 0.0], [0.0, 1.0]]}}
+        }),
+    )
+    .await;
+    let docs = docs.as_array().unwrap();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0]["text"], "both");
+    assert_eq!(docs[1]["text"], "one");
+
+    for search in [
+        json!({"vector": [1.0, 0.0], "vectors": [[1.0, 0.0]]}),
+        json!({"vectors": []}),
+        json!({"vectors": [[1.0, 0.0], [1.0]]}),
+    ] {
+        let err = rpc_err(
+            &app,
+            &path,
+            "doc.search",
+            json!({"collection": "chunks", "query": {"search": search}}),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(err["code"], "invalid_query");
+    }
+}
+
 /// `collection.ensure` sends the client's HNSW configuration on every call,
 /// but the engine only compares it against the persisted one when it actually
 /// loads the collection — so a drifted configuration used to surface as an
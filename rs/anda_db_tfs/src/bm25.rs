@@ -10,18 +10,40 @@ use parking_lot::RwLock;
 use rustc_hash::{FxBuildHasher, FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
     future::Future,
     io::{Read, Write},
+    ops::Bound,
     sync::atomic::{AtomicU32, AtomicU64, Ordering},
 };
 
 use crate::error::*;
+use crate::highlight::*;
 use crate::query::*;
 use crate::tokenizer::*;
 
 const MAX_NOT_COMPLEMENT_DOCS: usize = 10_000;
 
+/// Maximum number of indexed terms a prefix, wildcard or fuzzy query
+/// expands to.
+pub const MAX_TERM_EXPANSIONS: usize = 64;
+
+/// Number of leading characters a fuzzy term shares with every indexed term
+/// it matches, so the expansion scans one slice of the term dictionary
+/// rather than all of it.
+pub const FUZZY_PREFIX_LENGTH: usize = 1;
+
+/// Maximum number of indexed terms a fuzzy query token is compared with.
+pub const MAX_FUZZY_CANDIDATES: usize = 10_000;
+
+/// Separates the texts of the fields of a document in a BM25F index, see
+/// [`BM25Config::field_boosts`].
+pub const FIELD_SEPARATOR: char = '\u{1D}';
+
+/// Positions skipped between the fields of a document, so that phrase and
+/// proximity matches do not span two fields.
+const FIELD_POSITION_GAP: u32 = 100;
+
 /// Estimates the CBOR-serialized size of `value`.
 ///
 /// The result only drives the bucket-packing heuristic
@@ -29,13 +51,217 @@ const MAX_NOT_COMPLEMENT_DOCS: usize = 10_000;
 /// which cannot happen for the plain integer/string shapes this index
 /// serializes — degrade to `0` instead of panicking on the insert/remove
 /// hot path.
-fn cbor_serialized_size<T: ?Sized + Serialize>(value: &T) -> usize {
+pub(crate) fn cbor_serialized_size<T: ?Sized + Serialize>(value: &T) -> usize {
     cbor2::serialized_size(value)
         .ok()
         .and_then(|size| usize::try_from(size).ok())
         .unwrap_or(0)
 }
 
+/// Extracts the top-k results from scored documents using partial sorting.
+/// Uses `select_nth_unstable_by` for O(n + k·log(k)) instead of O(n·log(n)).
+pub(crate) fn top_k_results(scored_docs: FxHashMap<u64, f32>, top_k: usize) -> Vec<(u64, f32)> {
+    if top_k == 0 || scored_docs.is_empty() {
+        return Vec::new();
+    }
+
+    let mut results: Vec<(u64, f32)> = scored_docs.into_iter().collect();
+    if results.len() > top_k {
+        results.select_nth_unstable_by(top_k - 1, compare_scored_docs);
+        results.truncate(top_k);
+    }
+    results.sort_unstable_by(compare_scored_docs);
+    results
+}
+
+/// Total order over scored documents: descending score, `NaN` last, ties
+/// broken by ascending document id.
+///
+/// `partial_cmp(..).unwrap_or(Equal)` is **not** a total order once a
+/// single `NaN` is present (it degrades to id-order against the `NaN` while
+/// the other pairs stay score-ordered, which produces comparison cycles and
+/// can make `sort_unstable_by` / `select_nth_unstable_by` panic). Scoring
+/// sanitizes its parameters so a `NaN` should be impossible, but the sort
+/// must not depend on that: `total_cmp` orders every `f32` bit pattern, and
+/// the explicit `NaN` bucket keeps unscorable documents at the end.
+pub(crate) fn compare_scored_docs(a: &(u64, f32), b: &(u64, f32)) -> std::cmp::Ordering {
+    match (a.1.is_nan(), b.1.is_nan()) {
+        (true, true) => a.0.cmp(&b.0),
+        (true, false) => std::cmp::Ordering::Greater,
+        (false, true) => std::cmp::Ordering::Less,
+        (false, false) => b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)),
+    }
+}
+
+/// Returns whether `text` matches `pattern`, where `*` matches any run of
+/// characters and `?` exactly one.
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The last `*` seen and the text position it is matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and retry.
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns the Levenshtein distance between `a` and `b` if it is at most
+/// `max`, giving up as soon as every alignment exceeds it.
+fn bounded_levenshtein(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        let mut row_min = curr[0];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+            row_min = row_min.min(curr[j + 1]);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    let distance = prev[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+/// The BM25 formula over one snapshot of the corpus statistics.
+struct Bm25Scorer {
+    k1: f32,
+    b: f32,
+    doc_count: f32,
+    avg_doc_tokens: f32,
+    /// `(boost, average length)` of every field of a BM25F index; empty
+    /// for other indexes.
+    fields: Vec<(f32, f32)>,
+}
+
+impl Bm25Scorer {
+    /// Returns the `(frequency, length norm)` of a match counted over the
+    /// whole document.
+    fn doc_match(&self, tf: f32, doc_len: f32) -> (f32, f32) {
+        (tf, 1.0 - self.b + self.b * doc_len / self.avg_doc_tokens)
+    }
+
+    /// Returns the `(frequency, length norm)` of a term in a BM25F index:
+    /// the frequency is the boosted sum of the term frequency in each field,
+    /// normalized by the length of that field, so the norm is `1`.
+    fn field_match(&self, field_tfs: &[u32], field_lens: &[u32]) -> (f32, f32) {
+        let tf = self
+            .fields
+            .iter()
+            .zip(field_tfs.iter().zip(field_lens))
+            .filter(|(_, (tf, _))| **tf > 0)
+            .map(|((boost, avg_len), (tf, len))| {
+                boost * *tf as f32 / (1.0 - self.b + self.b * *len as f32 / avg_len)
+            })
+            .sum();
+        (tf, 1.0)
+    }
+
+    /// Adds the scores of one query unit — a term, a phrase or a proximity
+    /// group — to `scores`. `matches` maps every matching document to its
+    /// `(frequency, length norm)`, see [`doc_match`](Self::doc_match), and
+    /// is drained.
+    fn accumulate(
+        &self,
+        matches: &mut FxHashMap<u64, (f32, f32)>,
+        scores: &mut FxHashMap<u64, f32>,
+    ) {
+        if matches.is_empty() {
+            return;
+        }
+
+        // Classic Okapi BM25: ln(1 + (N - df + 0.5)/(df + 0.5))
+        let df = matches.len() as f32;
+        let idf = ((self.doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+
+        // Compute BM25 score for each matching document. `drain` empties the
+        // map while keeping its allocation for the next query term. A term
+        // only found in fields boosted to zero matches with a zero score.
+        let k1 = self.k1;
+        for (doc_id, (tf, norm)) in matches.drain() {
+            let tf_component = if tf > 0.0 {
+                (tf * (k1 + 1.0)) / (tf + k1 * norm)
+            } else {
+                0.0
+            };
+            *scores.entry(doc_id).or_default() += idf * tf_component;
+        }
+    }
+}
+
+/// Counts the windows of a document that hold one occurrence of each of
+/// `operands` with at most `distance` other tokens between them.
+///
+/// `spans` are the `(start, end, operand)` occurrences in the document and
+/// `width` is the total length of one occurrence of every operand. The
+/// spans are swept in start order; each span that closes a window covering
+/// every operand, shrunk from the left, counts once if the window is tight
+/// enough.
+fn count_near_windows(
+    mut spans: Vec<(u32, u32, usize)>,
+    operands: usize,
+    width: i64,
+    distance: i64,
+) -> usize {
+    spans.sort_unstable();
+    let mut counts = vec![0usize; operands];
+    let mut covered = 0;
+    let mut left = 0;
+    let mut windows = 0;
+    for right in 0..spans.len() {
+        let operand = spans[right].2;
+        if counts[operand] == 0 {
+            covered += 1;
+        }
+        counts[operand] += 1;
+        if covered < operands {
+            continue;
+        }
+
+        // Drop leading spans whose operand occurs again inside the window.
+        while counts[spans[left].2] > 1 {
+            counts[spans[left].2] -= 1;
+            left += 1;
+        }
+        let end = spans[left..=right]
+            .iter()
+            .map(|(_, end, _)| *end)
+            .max()
+            .unwrap_or_default();
+        let gap = end as i64 - spans[left].0 as i64 + 1 - width;
+        if gap <= distance {
+            windows += 1;
+        }
+    }
+    windows
+}
+
 /// Identifies one durable bucket object.
 ///
 /// A bucket's content is stored in immutable, generation-suffixed objects.
@@ -62,10 +288,10 @@ pub struct FlushOutcome {
 }
 
 /// A serialized bucket captured at one flush boundary.
-struct BucketSnapshot {
-    bucket_id: u32,
-    version: u64,
-    buf: Vec<u8>,
+pub(crate) struct BucketSnapshot {
+    pub(crate) bucket_id: u32,
+    pub(crate) version: u64,
+    pub(crate) buf: Vec<u8>,
 }
 
 /// Concurrent, bucket-sharded full-text index using BM25 scoring.
@@ -117,6 +343,27 @@ pub struct BM25Index<T: Tokenizer + Clone> {
     /// Inverted index mapping tokens to (bucket id, Vec<(document_id, term_frequency)>)
     postings: DashMap<String, PostingValue>,
 
+    /// Positional postings, recorded when [`BM25Config::positions`] is set.
+    /// A token's positions live in the bucket that owns its posting.
+    positions: DashMap<String, TokenPositions>,
+
+    /// Per-field term frequencies of a BM25F index (see
+    /// [`BM25Config::field_boosts`]), stored like `positions`.
+    field_freqs: DashMap<String, TokenFieldFreqs>,
+
+    /// Maps document IDs to their token counts in each field of a BM25F index
+    doc_field_tokens: DashMap<u64, Vec<u32>>,
+
+    /// Total number of tokens indexed in each field of a BM25F index, the
+    /// per-field counterpart of `total_tokens`.
+    field_total_tokens: Vec<AtomicU64>,
+
+    /// Term dictionary: every token that has a posting list, sorted, for
+    /// prefix, wildcard and fuzzy matching. It is derived from `postings`
+    /// and never persisted; a token enters and leaves it under the shard
+    /// lock of its posting list.
+    terms: RwLock<BTreeSet<String>>,
+
     /// Index metadata.
     metadata: RwLock<BM25Metadata>,
 
@@ -149,27 +396,27 @@ pub struct BM25Index<T: Tokenizer + Clone> {
 }
 
 #[derive(Default)]
-struct Bucket {
+pub(crate) struct Bucket {
     /// Version counter incremented on each modification
-    dirty_version: u64,
+    pub(crate) dirty_version: u64,
     /// Version that was last successfully persisted
-    saved_version: u64,
+    pub(crate) saved_version: u64,
     // Current size of the bucket in bytes
-    size: usize,
+    pub(crate) size: usize,
     // List of tokens stored in this bucket
-    tokens: UniqueVec<String>,
+    pub(crate) tokens: UniqueVec<String>,
     // Set of document IDs associated with this bucket
-    doc_ids: FxHashSet<u64>,
+    pub(crate) doc_ids: FxHashSet<u64>,
 }
 
 impl Bucket {
     #[inline]
-    fn is_dirty(&self) -> bool {
+    pub(crate) fn is_dirty(&self) -> bool {
         self.dirty_version > self.saved_version
     }
 
     #[inline]
-    fn mark_dirty(&mut self) {
+    pub(crate) fn mark_dirty(&mut self) {
         self.dirty_version += 1;
     }
 }
@@ -203,13 +450,22 @@ pub struct BM25Params {
     /// `0.0` disables length normalization; `1.0` applies full normalization.
     /// The usual BM25 default is `0.75`.
     pub b: f32,
+    /// Per-field boosts overriding [`BM25Config::field_boosts`] of a BM25F
+    /// index, by field order. Fields without an entry keep their configured
+    /// boost; other indexes ignore it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field_boosts: Option<Vec<f32>>,
 }
 
 impl Default for BM25Params {
     /// Returns default BM25 parameters (`k1 = 1.2`, `b = 0.75`) which work well
     /// for most use cases.
     fn default() -> Self {
-        BM25Params { k1: 1.2, b: 0.75 }
+        BM25Params {
+            k1: 1.2,
+            b: 0.75,
+            field_boosts: None,
+        }
     }
 }
 
@@ -224,6 +480,10 @@ impl BM25Params {
     /// that can exist in memory.
     pub const MAX_K1: f32 = 1_000.0;
 
+    /// Largest field boost honored at scoring time, for the same reason as
+    /// [`BM25Params::MAX_K1`].
+    pub const MAX_FIELD_BOOST: f32 = 1_000.0;
+
     /// Returns `(k1, b)` clamped into the domain where the BM25 formula is
     /// guaranteed to stay finite: non-finite values fall back to the defaults,
     /// `k1` to `[0, MAX_K1]` and `b` to `[0, 1]`.
@@ -244,6 +504,26 @@ impl BM25Params {
         };
         (k1, b)
     }
+
+    /// Returns the boost of field `field` of a BM25F index whose configured
+    /// boost is `configured`, clamped to `[0, MAX_FIELD_BOOST]`. A non-finite
+    /// override falls back to the configured boost, and a non-finite
+    /// configured boost to `1`.
+    fn field_boost(&self, field: usize, configured: f32) -> f32 {
+        let configured = if configured.is_finite() {
+            configured.clamp(0.0, Self::MAX_FIELD_BOOST)
+        } else {
+            1.0
+        };
+        match self
+            .field_boosts
+            .as_ref()
+            .and_then(|boosts| boosts.get(field))
+        {
+            Some(boost) if boost.is_finite() => boost.clamp(0.0, Self::MAX_FIELD_BOOST),
+            _ => configured,
+        }
+    }
 }
 
 /// Top-level configuration of a [`BM25Index`].
@@ -254,7 +534,14 @@ impl BM25Params {
 ///   bucket past this limit the token is routed to a fresh bucket instead.
 ///   Smaller values produce more, smaller buckets (cheaper incremental flushes
 ///   but more I/O per full reload); larger values do the opposite.
+/// * `positions` — whether to record token positions, see
+///   [`BM25Config::positions`].
+/// * `field_boosts` — per-field boosts of a BM25F index, see
+///   [`BM25Config::field_boosts`].
+/// * `tokenizer` — the recorded tokenizer definition, see
+///   [`BM25Config::tokenizer`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BM25Config {
     /// BM25 scoring parameters used for all query scoring.
     pub bm25: BM25Params,
@@ -262,15 +549,49 @@ pub struct BM25Config {
     /// When a bucket's stored data exceeds this size,
     /// a new bucket should be created for new data
     pub bucket_overload_size: usize,
+    /// Whether to record the positions of every token (positional postings).
+    ///
+    /// Required for phrase (`"machine learning"`) and proximity (`NEAR/n`)
+    /// matching in [`BM25Index::search_advanced`]; without positions those
+    /// queries only require all of their tokens to occur. Positions roughly
+    /// double the size of the buckets. Indexes persisted before this option
+    /// existed load with it disabled.
+    pub positions: bool,
+    /// Boosts of the fields of a BM25F index, in field order.
+    ///
+    /// When non-empty, the text given to [`BM25Index::insert`] holds one
+    /// text per field, separated by [`FIELD_SEPARATOR`] (extra texts belong
+    /// to the last field). Term frequencies and lengths are then recorded per
+    /// field, and a term's frequency is the boosted sum of its frequency in
+    /// each field, normalized by the length of that field relative to the
+    /// field's average length. Phrase and proximity matches are still
+    /// counted over the whole document. Empty, the default, scores the text
+    /// as a single field; the field count cannot be changed once documents
+    /// are indexed.
+    pub field_boosts: Vec<f32>,
+    /// Definition of the tokenizer the index is built with.
+    ///
+    /// Only recorded: [`BM25Index::new`] uses the tokenizer it is given. The
+    /// record lets a loader rebuild the same tokenizer (see
+    /// [`BM25Index::load_all_with`] and [`TokenizerRegistry::resolve`])
+    /// instead of trusting whatever tokenizer the process installs. `None`,
+    /// the default and the value of indexes persisted before this option
+    /// existed, records nothing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<TokenizerConfig>,
 }
 
 impl Default for BM25Config {
-    /// Returns a default configuration with [`BM25Params::default`] and a
-    /// 512 KiB bucket size limit.
+    /// Returns a default configuration with [`BM25Params::default`], a
+    /// 512 KiB bucket size limit, no positions, a single field and no
+    /// recorded tokenizer.
     fn default() -> Self {
         BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 1024 * 512,
+            positions: false,
+            field_boosts: Vec::new(),
+            tokenizer: None,
         }
     }
 }
@@ -280,6 +601,26 @@ impl Default for BM25Config {
 /// - Vec<(document_id, token_frequency)>: List of documents and their term frequencies
 pub type PostingValue = (u32, UniqueVec<(u64, usize)>);
 
+/// Positions of one token: document id → ascending token positions.
+type TokenPositions = FxHashMap<u64, Vec<u32>>;
+
+/// Field frequencies of one token in a BM25F index: document id → term
+/// frequency in each field.
+type TokenFieldFreqs = FxHashMap<u64, Vec<u32>>;
+
+/// The tokens of one document, see [`BM25Index::tokenize_document`].
+#[derive(Default)]
+struct DocumentTokens {
+    /// Token → frequency in the document.
+    freqs: HashMap<String, usize>,
+    /// Token → ascending positions, when positions are recorded.
+    positions: HashMap<String, Vec<u32>>,
+    /// Token → frequency in each field, in a BM25F index.
+    field_freqs: HashMap<String, Vec<u32>>,
+    /// Number of tokens in each field, in a BM25F index.
+    field_tokens: Vec<u32>,
+}
+
 /// Index metadata.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BM25Metadata {
@@ -361,6 +702,19 @@ struct BucketOwned {
 
     #[serde(rename = "d")]
     doc_tokens: FxHashMap<u64, usize>,
+
+    // Positional postings; absent in buckets of indexes without positions
+    // and in buckets written before positions existed.
+    #[serde(rename = "o", default)]
+    positions: FxHashMap<String, TokenPositions>,
+
+    // Per-field term frequencies and document lengths; only present in
+    // buckets of BM25F indexes.
+    #[serde(rename = "f", default)]
+    field_freqs: FxHashMap<String, TokenFieldFreqs>,
+
+    #[serde(rename = "l", default)]
+    doc_field_tokens: FxHashMap<u64, Vec<u32>>,
 }
 
 // Reference structure for serializing bucket
@@ -371,6 +725,15 @@ struct BucketRef<'a> {
 
     #[serde(rename = "d")]
     doc_tokens: &'a FxHashMap<u64, usize>,
+
+    #[serde(rename = "o", skip_serializing_if = "FxHashMap::is_empty")]
+    positions: &'a FxHashMap<&'a String, dashmap::mapref::one::Ref<'a, String, TokenPositions>>,
+
+    #[serde(rename = "f", skip_serializing_if = "FxHashMap::is_empty")]
+    field_freqs: &'a FxHashMap<&'a String, dashmap::mapref::one::Ref<'a, String, TokenFieldFreqs>>,
+
+    #[serde(rename = "l", skip_serializing_if = "FxHashMap::is_empty")]
+    doc_field_tokens: &'a FxHashMap<u64, Vec<u32>>,
 }
 
 impl<T> BM25Index<T>
@@ -394,12 +757,22 @@ where
             version: 1,
             ..Default::default()
         };
+        let field_total_tokens = config
+            .field_boosts
+            .iter()
+            .map(|_| AtomicU64::new(0))
+            .collect();
         BM25Index {
             name: name.clone(),
             tokenizer,
             config: config.clone(),
             doc_tokens: DashMap::new(),
             postings: DashMap::new(),
+            positions: DashMap::new(),
+            field_freqs: DashMap::new(),
+            doc_field_tokens: DashMap::new(),
+            field_total_tokens,
+            terms: RwLock::new(BTreeSet::new()),
             buckets: DashMap::from_iter([(0, Bucket::default())]),
             metadata: RwLock::new(BM25Metadata {
                 name,
@@ -438,7 +811,25 @@ where
     where
         F: AsyncFnMut(BucketObject) -> Result<Option<Vec<u8>>, BoxError>,
     {
-        let mut index = Self::load_metadata(tokenizer, metadata)?;
+        Self::load_all_with(|_| Ok(tokenizer), metadata, f).await
+    }
+
+    /// Like [`load_all`](Self::load_all), with the tokenizer chosen from the
+    /// persisted configuration.
+    ///
+    /// `tokenizer` receives the loaded [`BM25Config`], typically to rebuild
+    /// the recorded [`BM25Config::tokenizer`]; its error aborts the load
+    /// before any bucket is read.
+    pub async fn load_all_with<R: Read, G, F>(
+        tokenizer: G,
+        metadata: R,
+        f: F,
+    ) -> Result<Self, BM25Error>
+    where
+        G: FnOnce(&BM25Config) -> Result<T, BM25Error>,
+        F: AsyncFnMut(BucketObject) -> Result<Option<Vec<u8>>, BoxError>,
+    {
+        let mut index = Self::load_metadata_with(tokenizer, metadata)?;
         index.load_buckets(f).await?;
         Ok(index)
     }
@@ -450,15 +841,33 @@ where
     /// [`load_buckets`](Self::load_buckets) afterwards to populate the inverted
     /// index (possibly on demand, or only for a subset of buckets).
     pub fn load_metadata<R: Read>(tokenizer: T, r: R) -> Result<Self, BM25Error> {
+        Self::load_metadata_with(|_| Ok(tokenizer), r)
+    }
+
+    /// Like [`load_metadata`](Self::load_metadata), with the tokenizer chosen
+    /// from the persisted configuration (see
+    /// [`load_all_with`](Self::load_all_with)).
+    pub fn load_metadata_with<R: Read, G>(tokenizer: G, r: R) -> Result<Self, BM25Error>
+    where
+        G: FnOnce(&BM25Config) -> Result<T, BM25Error>,
+    {
         let index: BM25IndexOwned =
             cbor2::from_reader(r).map_err(|err| BM25Error::Serialization {
                 name: "unknown".to_string(),
                 source: err.into(),
             })?;
+        let tokenizer = tokenizer(&index.metadata.config)?;
         let max_bucket_id = AtomicU32::new(index.metadata.stats.max_bucket_id);
         let max_document_id = AtomicU64::new(index.metadata.stats.max_document_id);
         let search_count = AtomicU64::new(index.metadata.stats.search_count);
         let last_saved_version = AtomicU64::new(index.metadata.stats.version);
+        let field_total_tokens = index
+            .metadata
+            .config
+            .field_boosts
+            .iter()
+            .map(|_| AtomicU64::new(0))
+            .collect();
 
         Ok(BM25Index {
             name: index.metadata.name.clone(),
@@ -466,6 +875,11 @@ where
             config: index.metadata.config.clone(),
             doc_tokens: DashMap::new(),
             postings: DashMap::new(),
+            positions: DashMap::new(),
+            field_freqs: DashMap::new(),
+            doc_field_tokens: DashMap::new(),
+            field_total_tokens,
+            terms: RwLock::new(BTreeSet::new()),
             buckets: DashMap::from_iter([(0, Bucket::default())]),
             metadata: RwLock::new(index.metadata),
             max_bucket_id,
@@ -511,6 +925,11 @@ where
             .iter()
             .map(|entry| (*entry.key(), *entry.value()))
             .collect();
+        let mut doc_field_lengths: FxHashMap<u64, Vec<u32>> = self
+            .doc_field_tokens
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
 
         let manifest = { self.metadata.read().buckets.clone() };
         let legacy = manifest.is_empty();
@@ -593,6 +1012,16 @@ where
                     }
                 }
 
+                // Like postings, the positions of a token come from the
+                // bucket that owns it; a later bucket replaces a stale copy.
+                for (token, positions) in bucket.positions {
+                    self.positions.insert(token, positions);
+                }
+                for (token, field_freqs) in bucket.field_freqs {
+                    self.field_freqs.insert(token, field_freqs);
+                }
+                doc_field_lengths.extend(bucket.doc_field_tokens);
+
                 self.buckets.insert(i, b);
             }
         }
@@ -625,6 +1054,16 @@ where
             });
 
             if !removed_entries.is_empty() {
+                if let Some(mut positions) = self.positions.get_mut(posting.key()) {
+                    for (doc_id, _) in &removed_entries {
+                        positions.remove(doc_id);
+                    }
+                }
+                if let Some(mut field_freqs) = self.field_freqs.get_mut(posting.key()) {
+                    for (doc_id, _) in &removed_entries {
+                        field_freqs.remove(doc_id);
+                    }
+                }
                 let size_decrease = if posting.1.is_empty() {
                     empty_tokens.push((bucket_id, posting.key().clone()));
                     cbor_serialized_size(&(posting.key(), (bucket_id, &removed_entries))) + 2
@@ -640,6 +1079,8 @@ where
 
         for (bucket_id, token) in empty_tokens {
             self.postings.remove(&token);
+            self.positions.remove(&token);
+            self.field_freqs.remove(&token);
             if let Some(mut bucket) = self.buckets.get_mut(&bucket_id) {
                 bucket.tokens.swap_remove_if(|k| k == &token);
             }
@@ -652,8 +1093,15 @@ where
             }
         }
 
+        self.doc_field_tokens.clear();
+        self.doc_field_tokens.extend(
+            doc_field_lengths
+                .into_iter()
+                .filter(|(doc_id, _)| loaded_doc_tokens.contains_key(doc_id)),
+        );
         self.doc_tokens.clear();
         self.doc_tokens.extend(loaded_doc_tokens);
+        *self.terms.get_mut() = self.postings.iter().map(|p| p.key().clone()).collect();
 
         let bucket_ids: Vec<u32> = self.buckets.iter().map(|b| *b.key()).collect();
         for bucket_id in bucket_ids {
@@ -669,6 +1117,14 @@ where
         let total_tokens: usize = self.doc_tokens.iter().map(|r| *r.value()).sum();
         self.total_tokens
             .store(total_tokens as u64, Ordering::Relaxed);
+        for (field, total) in self.field_total_tokens.iter().enumerate() {
+            let field_tokens: u64 = self
+                .doc_field_tokens
+                .iter()
+                .filter_map(|r| r.value().get(field).map(|v| *v as u64))
+                .sum();
+            total.store(field_tokens, Ordering::Relaxed);
+        }
 
         if legacy && !loaded_bucket_ids.is_empty() {
             // Record in memory where each loaded bucket's durable object
@@ -698,6 +1154,11 @@ where
         &self.name
     }
 
+    /// Returns the index configuration
+    pub fn config(&self) -> &BM25Config {
+        &self.config
+    }
+
     /// Returns the index metadata
     pub fn metadata(&self) -> BM25Metadata {
         let mut metadata = self.metadata.read().clone();
@@ -742,6 +1203,91 @@ where
         self.total_tokens.load(Ordering::Relaxed) as f32 / doc_count as f32
     }
 
+    /// Drops the posting list of `token` if it is empty, together with its
+    /// entry in the term dictionary. Returns whether it was dropped.
+    ///
+    /// The emptiness is re-checked under the shard lock: a concurrent insert
+    /// may have appended an entry since the caller saw the list empty.
+    fn remove_empty_posting(&self, token: &str) -> bool {
+        self.postings
+            .remove_if(token, |_, posting| {
+                let empty = posting.1.is_empty();
+                if empty {
+                    self.terms.write().remove(token);
+                }
+                empty
+            })
+            .is_some()
+    }
+
+    /// Drops the field lengths of a document of a BM25F index, keeping the
+    /// per-field token totals in step like `total_tokens`.
+    fn remove_doc_field_tokens(&self, id: u64) {
+        if let Some((_, field_tokens)) = self.doc_field_tokens.remove(&id) {
+            for (total, tokens) in self.field_total_tokens.iter().zip(field_tokens) {
+                total.fetch_sub(tokens as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Tokenizes the text of a document the way [`insert`](Self::insert)
+    /// indexes it.
+    ///
+    /// Positions are recorded when [`BM25Config::positions`] is set. In a
+    /// BM25F index the text is split at [`FIELD_SEPARATOR`] and each field is
+    /// tokenized on its own; positions continue across fields, with a gap of
+    /// [`FIELD_POSITION_GAP`] between them.
+    fn tokenize_document(&self, text: &str) -> DocumentTokens {
+        let mut tokenizer = self.tokenizer.clone();
+        let fields = self.config.field_boosts.len();
+        if fields == 0 {
+            if !self.config.positions {
+                return DocumentTokens {
+                    freqs: collect_tokens(&mut tokenizer, text, None),
+                    ..Default::default()
+                };
+            }
+            let positions = collect_token_positions(&mut tokenizer, text);
+            return DocumentTokens {
+                freqs: positions
+                    .iter()
+                    .map(|(token, positions)| (token.clone(), positions.len()))
+                    .collect(),
+                positions,
+                ..Default::default()
+            };
+        }
+
+        let mut doc = DocumentTokens {
+            field_tokens: vec![0; fields],
+            ..Default::default()
+        };
+        let mut base = 0u32;
+        for (i, field_text) in text.split(FIELD_SEPARATOR).enumerate() {
+            let field = i.min(fields - 1);
+            let mut next_base = base;
+            for (token, positions) in collect_token_positions(&mut tokenizer, field_text) {
+                let count = positions.len();
+                *doc.freqs.entry(token.clone()).or_default() += count;
+                doc.field_freqs
+                    .entry(token.clone())
+                    .or_insert_with(|| vec![0; fields])[field] += count as u32;
+                doc.field_tokens[field] += count as u32;
+                if let Some(last) = positions.last() {
+                    next_base = next_base.max(base.saturating_add(*last).saturating_add(1));
+                }
+                if self.config.positions {
+                    doc.positions
+                        .entry(token)
+                        .or_default()
+                        .extend(positions.into_iter().map(|p| base.saturating_add(p)));
+                }
+            }
+            base = next_base.saturating_add(FIELD_POSITION_GAP);
+        }
+        doc
+    }
+
     /// Inserts a document into the index.
     ///
     /// The text is tokenized with a clone of the index's tokenizer; token
@@ -751,6 +1297,9 @@ where
     /// and then applied in a second phase so that at most one bucket is marked
     /// dirty per affected bucket.
     ///
+    /// In a BM25F index the text holds the text of each field, separated by
+    /// [`FIELD_SEPARATOR`], see [`BM25Config::field_boosts`].
+    ///
     /// # Arguments
     ///
     /// * `id` — unique, caller-assigned document identifier.
@@ -770,11 +1319,13 @@ where
         // Shared with other mutations, exclusive against `compact_buckets`.
         let _mutation_guard = self.mutation_gate.read();
 
-        // Tokenize the document
-        let token_freqs = {
-            let mut tokenizer = self.tokenizer.clone();
-            collect_tokens(&mut tokenizer, text, None)
-        };
+        // Tokenize the document, recording positions and fields if configured
+        let DocumentTokens {
+            freqs: token_freqs,
+            positions: mut token_positions,
+            field_freqs: mut token_field_freqs,
+            field_tokens,
+        } = self.tokenize_document(text);
 
         // Count token frequencies
         if token_freqs.is_empty() {
@@ -807,9 +1358,36 @@ where
                 // from them at read time and needs no separate synchronization.
                 self.total_tokens
                     .fetch_add(tokens as u64, Ordering::Relaxed);
+                if !field_tokens.is_empty() {
+                    for (total, tokens) in self.field_total_tokens.iter().zip(&field_tokens) {
+                        total.fetch_add(*tokens as u64, Ordering::Relaxed);
+                    }
+                    self.doc_field_tokens.insert(id, field_tokens);
+                }
 
                 // Update inverted index
                 for (token, freq) in token_freqs {
+                    // Positions and field frequencies are stored before the
+                    // posting that makes them reachable, and without holding
+                    // a posting guard.
+                    let mut positions_size = match token_positions.remove(&token) {
+                        Some(positions) => {
+                            let size = cbor_serialized_size(&(id, &positions)) + 2;
+                            self.positions
+                                .entry(token.clone())
+                                .or_default()
+                                .insert(id, positions);
+                            size
+                        }
+                        None => 0,
+                    };
+                    if let Some(field_freqs) = token_field_freqs.remove(&token) {
+                        positions_size += cbor_serialized_size(&(id, &field_freqs)) + 2;
+                        self.field_freqs
+                            .entry(token.clone())
+                            .or_default()
+                            .insert(id, field_freqs);
+                    }
                     match self.postings.entry(token.clone()) {
                         dashmap::Entry::Occupied(mut entry) => {
                             let val = (id, freq);
@@ -820,7 +1398,7 @@ where
                             // still mark the bucket dirty below so the refreshed
                             // doc_tokens snapshot gets persisted.
                             let size_increase = if e.1.push(val) {
-                                cbor_serialized_size(&val) + 2
+                                cbor_serialized_size(&val) + 2 + positions_size
                             } else {
                                 0
                             };
@@ -831,7 +1409,10 @@ where
                             // Create new posting
                             let val = (bucket_id, vec![(id, freq)].into());
                             let size_increase =
-                                cbor_serialized_size(&(&token, (bucket_id, &[(id, freq)]))) + 2;
+                                cbor_serialized_size(&(&token, (bucket_id, &[(id, freq)])))
+                                    + 2
+                                    + positions_size;
+                            self.terms.write().insert(token.clone());
                             entry.insert(val);
                             let b = buckets_to_update.entry(bucket_id).or_default();
                             b.insert(token, size_increase);
@@ -966,11 +1547,14 @@ where
             self.total_tokens
                 .fetch_sub(removed_tokens as u64, Ordering::Relaxed);
         }
+        self.remove_doc_field_tokens(id);
 
         // Tokenize the document
-        let token_freqs = {
+        let token_freqs = if self.config.field_boosts.is_empty() {
             let mut tokenizer = self.tokenizer.clone();
             collect_tokens(&mut tokenizer, text, None)
+        } else {
+            self.tokenize_document(text).freqs
         };
 
         // buckets_to_update: FxHashMap<bucketid, FxHashMap<token, size_decrease>>
@@ -978,6 +1562,24 @@ where
         // Remove from inverted index
         let mut maybe_empty_tokens: Vec<String> = Vec::new();
         for (token, _) in token_freqs {
+            // Drop the positions first, so no posting guard is held while
+            // the positions map is locked.
+            let positions_size = self
+                .positions
+                .get_mut(&token)
+                .and_then(|mut positions| positions.remove(&id))
+                .map_or(0, |positions| cbor_serialized_size(&(id, &positions)) + 2)
+                + self
+                    .field_freqs
+                    .get_mut(&token)
+                    .and_then(|mut field_freqs| field_freqs.remove(&id))
+                    .map_or(0, |field_freqs| {
+                        cbor_serialized_size(&(id, &field_freqs)) + 2
+                    });
+            self.positions
+                .remove_if(&token, |_, positions| positions.is_empty());
+            self.field_freqs
+                .remove_if(&token, |_, field_freqs| field_freqs.is_empty());
             if let Some(mut posting) = self.postings.get_mut(&token) {
                 // Remove every entry for this document. Duplicates can exist
                 // when a previous remove() was given non-original text and the
@@ -998,7 +1600,7 @@ where
                         .iter()
                         .map(|val| cbor_serialized_size(val) + 2)
                         .sum()
-                };
+                } + positions_size;
                 let b = buckets_to_update.entry(posting.0).or_default();
                 b.insert(token, size_decrease);
             }
@@ -1010,11 +1612,7 @@ where
         let mut removed_postings: FxHashSet<String> =
             FxHashSet::with_capacity_and_hasher(maybe_empty_tokens.len(), FxBuildHasher);
         for token in maybe_empty_tokens {
-            if self
-                .postings
-                .remove_if(&token, |_, posting| posting.1.is_empty())
-                .is_some()
-            {
+            if self.remove_empty_posting(&token) {
                 removed_postings.insert(token);
             }
         }
@@ -1151,6 +1749,9 @@ where
             self.total_tokens
                 .fetch_sub(removed_tokens, Ordering::Relaxed);
         }
+        for id in ids {
+            self.remove_doc_field_tokens(*id);
+        }
 
         // Phase 2: sweep every posting list once, collecting bucket updates
         // instead of applying them, so no `postings` shard guard is held while
@@ -1172,6 +1773,22 @@ where
                 continue;
             }
 
+            let mut positions_size = 0;
+            if let Some(mut positions) = self.positions.get_mut(posting.key()) {
+                for (doc_id, _) in &removed_entries {
+                    if let Some(removed) = positions.remove(doc_id) {
+                        positions_size += cbor_serialized_size(&(doc_id, &removed)) + 2;
+                    }
+                }
+            }
+            if let Some(mut field_freqs) = self.field_freqs.get_mut(posting.key()) {
+                for (doc_id, _) in &removed_entries {
+                    if let Some(removed) = field_freqs.remove(doc_id) {
+                        positions_size += cbor_serialized_size(&(doc_id, &removed)) + 2;
+                    }
+                }
+            }
+
             // Mirror of `remove`: the whole `(token, (bucket, entries))` tuple
             // when the posting disappears — that is what `insert` charged for
             // a brand-new token — and the per-entry cost otherwise.
@@ -1183,7 +1800,7 @@ where
                     .iter()
                     .map(|entry| cbor_serialized_size(entry) + 2)
                     .sum()
-            };
+            } + positions_size;
             *bucket_size_decrease.entry(bucket_id).or_default() += size_decrease;
         }
 
@@ -1194,11 +1811,11 @@ where
         let mut removed_postings: FxHashSet<String> =
             FxHashSet::with_capacity_and_hasher(emptied_tokens.len(), FxBuildHasher);
         for (_, token) in emptied_tokens.iter() {
-            if self
-                .postings
-                .remove_if(token, |_, posting| posting.1.is_empty())
-                .is_some()
-            {
+            self.positions
+                .remove_if(token, |_, positions| positions.is_empty());
+            self.field_freqs
+                .remove_if(token, |_, field_freqs| field_freqs.is_empty());
+            if self.remove_empty_posting(token) {
                 removed_postings.insert(token.clone());
             }
         }
@@ -1290,7 +1907,7 @@ where
         // short-circuits above), matching the HNSW index's semantics.
         self.search_count.fetch_add(1, Ordering::Relaxed);
 
-        Self::top_k_results(scored_docs, top_k)
+        top_k_results(scored_docs, top_k)
     }
 
     /// Searches the index with a boolean query expression.
@@ -1355,42 +1972,59 @@ where
         // matching the HNSW index's search_count semantics.
         self.search_count.fetch_add(1, Ordering::Relaxed);
 
-        Ok(Self::top_k_results(scored_docs, top_k))
+        Ok(top_k_results(scored_docs, top_k))
     }
 
-    /// Extracts the top-k results from scored documents using partial sorting.
-    /// Uses `select_nth_unstable_by` for O(n + k·log(k)) instead of O(n·log(n)).
-    fn top_k_results(scored_docs: FxHashMap<u64, f32>, top_k: usize) -> Vec<(u64, f32)> {
-        if top_k == 0 || scored_docs.is_empty() {
-            return Vec::new();
-        }
-
-        let mut results: Vec<(u64, f32)> = scored_docs.into_iter().collect();
-        if results.len() > top_k {
-            results.select_nth_unstable_by(top_k - 1, Self::compare_scored_docs);
-            results.truncate(top_k);
-        }
-        results.sort_unstable_by(Self::compare_scored_docs);
-        results
-    }
-
-    /// Total order over scored documents: descending score, `NaN` last, ties
-    /// broken by ascending document id.
+    /// Creates a [`Highlighter`] for `query`, to extract the fragments of
+    /// stored texts that match it.
     ///
-    /// `partial_cmp(..).unwrap_or(Equal)` is **not** a total order once a
-    /// single `NaN` is present (it degrades to id-order against the `NaN` while
-    /// the other pairs stay score-ordered, which produces comparison cycles and
-    /// can make `sort_unstable_by` / `select_nth_unstable_by` panic). Scoring
-    /// sanitizes its parameters so a `NaN` should be impossible, but the sort
-    /// must not depend on that: `total_cmp` orders every `f32` bit pattern, and
-    /// the explicit `NaN` bucket keeps unscorable documents at the end.
-    fn compare_scored_docs(a: &(u64, f32), b: &(u64, f32)) -> std::cmp::Ordering {
-        match (a.1.is_nan(), b.1.is_nan()) {
-            (true, true) => a.0.cmp(&b.0),
-            (true, false) => std::cmp::Ordering::Greater,
-            (false, true) => std::cmp::Ordering::Less,
-            (false, false) => b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)),
+    /// With `logical`, the query is parsed like in
+    /// [`search_advanced`](Self::search_advanced) and only the tokens of
+    /// its non-negated terms, phrases and proximity operands, and the terms
+    /// its patterns expand to, are highlighted; otherwise every token of the
+    /// query is. Tokens are weighted by their inverse document frequency in
+    /// this index.
+    pub fn highlighter(
+        &self,
+        query: &str,
+        logical: bool,
+        options: HighlightOptions,
+    ) -> Highlighter<T> {
+        let mut tokenizer = self.tokenizer.clone();
+        let mut tokens: FxHashSet<String> = FxHashSet::default();
+        if logical {
+            for term in QueryType::parse(query).positive_terms() {
+                match term {
+                    QueryType::Term(text) | QueryType::Phrase(text) => {
+                        tokens.extend(collect_tokens(&mut tokenizer, text, None).into_keys());
+                    }
+                    QueryType::Near(operands, _) => {
+                        for text in operands {
+                            tokens.extend(collect_tokens(&mut tokenizer, text, None).into_keys());
+                        }
+                    }
+                    _ => tokens.extend(self.expand_term(term).into_iter().map(|(t, _)| t)),
+                }
+            }
+        } else {
+            tokens.extend(collect_tokens(&mut tokenizer, query, None).into_keys());
         }
+
+        // Same smoothing as scoring, floored so that a token every document
+        // contains still counts.
+        let doc_count = self.doc_tokens.len() as f32;
+        let weights = tokens
+            .into_iter()
+            .map(|token| {
+                let df = self
+                    .postings
+                    .get(&token)
+                    .map_or(0.0, |postings| postings.1.len() as f32);
+                let idf = ((doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+                (token, idf.max(0.01))
+            })
+            .collect();
+        Highlighter::new(self.tokenizer.clone(), weights, options)
     }
 
     /// Execute a query expression, returning a mapping of document IDs to scores
@@ -1402,12 +2036,121 @@ where
     ) -> FxHashMap<u64, f32> {
         match query {
             QueryType::Term(term) => self.score_term(term, params),
+            QueryType::Phrase(phrase) => self.score_phrase(phrase, params),
+            QueryType::Near(operands, distance) => self.score_near(operands, *distance, params),
+            QueryType::Prefix(_) | QueryType::Wildcard(_) | QueryType::Fuzzy(..) => {
+                self.score_expanded(query, params)
+            }
             QueryType::And(subqueries) => self.score_and(subqueries, params),
             QueryType::Or(subqueries) => self.score_or(subqueries, params),
             QueryType::Not(subquery) => self.score_not(subquery, params, negated_not),
         }
     }
 
+    /// Expands a prefix, wildcard or fuzzy query to the indexed terms it
+    /// matches, at most [`MAX_TERM_EXPANSIONS`] of them, each with its
+    /// score weight. Other queries expand to nothing.
+    ///
+    /// Prefix and wildcard matches weigh `1` and are taken in term order.
+    /// Fuzzy matches weigh `1 / (1 + distance)` and are taken closest
+    /// first; a multi-token fuzzy text matches the terms close to any of
+    /// its tokens. A fuzzy token is only compared with the terms sharing its
+    /// first [`FUZZY_PREFIX_LENGTH`] characters, at most
+    /// [`MAX_FUZZY_CANDIDATES`] of them.
+    fn expand_term(&self, query: &QueryType) -> Vec<(String, f32)> {
+        match query {
+            QueryType::Prefix(prefix) => self
+                .terms
+                .read()
+                .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                .take_while(|term| term.starts_with(prefix.as_str()))
+                .take(MAX_TERM_EXPANSIONS)
+                .map(|term| (term.clone(), 1.0))
+                .collect(),
+            QueryType::Wildcard(pattern) => {
+                // Only the terms sharing the literal head of the pattern can
+                // match, so the scan starts there.
+                let head = &pattern[..pattern.find(['*', '?']).unwrap_or(pattern.len())];
+                let pattern: Vec<char> = pattern.chars().collect();
+                self.terms
+                    .read()
+                    .range::<str, _>((Bound::Included(head), Bound::Unbounded))
+                    .take_while(|term| term.starts_with(head))
+                    .filter(|term| wildcard_match(&pattern, &term.chars().collect::<Vec<_>>()))
+                    .take(MAX_TERM_EXPANSIONS)
+                    .map(|term| (term.clone(), 1.0))
+                    .collect()
+            }
+            QueryType::Fuzzy(text, distance) => {
+                let mut tokenizer = self.tokenizer.clone();
+                let tokens: Vec<Vec<char>> = collect_tokens(&mut tokenizer, text, None)
+                    .into_keys()
+                    .map(|token| token.chars().collect())
+                    .collect();
+                if tokens.is_empty() {
+                    return Vec::new();
+                }
+
+                // The candidates are copied out of the dictionary so that
+                // inserts are not held up while the distances are computed.
+                let max = usize::from(*distance);
+                let mut candidates: BTreeSet<String> = BTreeSet::new();
+                {
+                    let terms = self.terms.read();
+                    for token in &tokens {
+                        let prefix: String = token.iter().take(FUZZY_PREFIX_LENGTH).collect();
+                        candidates.extend(
+                            terms
+                                .range::<str, _>((
+                                    Bound::Included(prefix.as_str()),
+                                    Bound::Unbounded,
+                                ))
+                                .take_while(|term| term.starts_with(prefix.as_str()))
+                                .take(MAX_FUZZY_CANDIDATES)
+                                .filter(|term| term.chars().count().abs_diff(token.len()) <= max)
+                                .cloned(),
+                        );
+                    }
+                }
+
+                let mut matches: Vec<(usize, String)> = candidates
+                    .into_iter()
+                    .filter_map(|term| {
+                        let chars: Vec<char> = term.chars().collect();
+                        tokens
+                            .iter()
+                            .filter_map(|token| bounded_levenshtein(token, &chars, max))
+                            .min()
+                            .map(|distance| (distance, term))
+                    })
+                    .collect();
+                matches.sort_unstable();
+                matches
+                    .into_iter()
+                    .take(MAX_TERM_EXPANSIONS)
+                    .map(|(distance, term)| (term, 1.0 / (1.0 + distance as f32)))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Scores a prefix, wildcard or fuzzy query: every document scores its
+    /// best-matching expanded term, scaled by the weight of that term.
+    fn score_expanded(&self, query: &QueryType, params: &BM25Params) -> FxHashMap<u64, f32> {
+        let mut scores: FxHashMap<u64, f32> = FxHashMap::default();
+        for (term, weight) in self.expand_term(query) {
+            for (doc_id, score) in self.score_tokens(std::iter::once(&term), params) {
+                let score = score * weight;
+                scores
+                    .entry(doc_id)
+                    .and_modify(|best| *best = best.max(score))
+                    .or_insert(score);
+            }
+        }
+        scores
+    }
+
     /// Scores a single term (or multi-term query text) using BM25.
     /// Accumulates scores directly without intermediate allocations.
     fn score_term(&self, term: &str, params: &BM25Params) -> FxHashMap<u64, f32> {
@@ -1415,31 +2158,36 @@ where
             return FxHashMap::default();
         }
 
-        // Be defensive against invalid params to avoid NaNs/inf in ranking.
-        let (k1, b) = params.sanitized();
-
         let mut tokenizer = self.tokenizer.clone();
         let query_terms = collect_tokens(&mut tokenizer, term, None);
-        if query_terms.is_empty() {
-            return FxHashMap::default();
-        }
+        self.score_tokens(query_terms.keys(), params)
+    }
 
-        let doc_count = self.doc_tokens.len() as f32;
-        if doc_count == 0.0 {
+    /// Scores already tokenized query terms using BM25, summing the scores
+    /// of every term a document contains.
+    fn score_tokens<'a>(
+        &self,
+        query_terms: impl IntoIterator<Item = &'a String>,
+        params: &BM25Params,
+    ) -> FxHashMap<u64, f32> {
+        let mut query_terms = query_terms.into_iter().peekable();
+        if query_terms.peek().is_none() {
             return FxHashMap::default();
         }
 
+        let Some(scorer) = self.scorer(params) else {
+            return FxHashMap::default();
+        };
         let mut scores: FxHashMap<u64, f32> =
             FxHashMap::with_capacity_and_hasher(self.doc_tokens.len().min(1000), FxBuildHasher);
-        let avg_doc_tokens = self.avg_doc_tokens().max(1.0);
 
         // Per-token dedup buffer, reused across query terms so a multi-term
         // query does not reallocate a fresh map for every term.
         let mut valid: FxHashMap<u64, (f32, f32)> = FxHashMap::default();
-        for query_token in query_terms.keys() {
+        for query_token in query_terms {
             if let Some(postings) = self.postings.get(query_token) {
-                // Single-pass: collect doc_id -> (tf, doc_len) for valid documents
-                // in one sweep over the postings.
+                // Single-pass: collect doc_id -> (tf, length norm) for valid
+                // documents in one sweep over the postings.
                 // Filter out deleted / not-loaded documents:
                 // `remove()` depends on the caller providing original text; if they don't,
                 // postings can become stale. Also, when only part of buckets are loaded,
@@ -1452,29 +2200,284 @@ where
                 valid.reserve(postings.1.len());
                 for (doc_id, token_freq) in postings.1.iter() {
                     if let Some(v) = self.doc_tokens.get(doc_id) {
-                        valid.insert(*doc_id, (*token_freq as f32, *v as f32));
+                        valid.insert(*doc_id, scorer.doc_match(*token_freq as f32, *v as f32));
                     }
                 }
+                drop(postings);
+
+                if !scorer.fields.is_empty()
+                    && let Some(field_freqs) = self.field_freqs.get(query_token)
+                {
+                    for (doc_id, m) in valid.iter_mut() {
+                        if let Some(field_tfs) = field_freqs.get(doc_id)
+                            && let Some(field_lens) = self.doc_field_tokens.get(doc_id)
+                        {
+                            *m = scorer.field_match(field_tfs, &field_lens);
+                        }
+                    }
+                }
+
+                scorer.accumulate(&mut valid, &mut scores);
+            }
+        }
+
+        scores
+    }
+
+    /// Returns the BM25 scorer for the current corpus, or `None` if the
+    /// index has no document.
+    fn scorer(&self, params: &BM25Params) -> Option<Bm25Scorer> {
+        let doc_count = self.doc_tokens.len() as f32;
+        if doc_count == 0.0 {
+            return None;
+        }
+
+        // Be defensive against invalid params to avoid NaNs/inf in ranking.
+        let (k1, b) = params.sanitized();
+        let fields = self
+            .config
+            .field_boosts
+            .iter()
+            .zip(&self.field_total_tokens)
+            .enumerate()
+            .map(|(field, (boost, total))| {
+                let avg_len = total.load(Ordering::Relaxed) as f32 / doc_count;
+                (params.field_boost(field, *boost), avg_len.max(1.0))
+            })
+            .collect();
+        Some(Bm25Scorer {
+            k1,
+            b,
+            doc_count,
+            avg_doc_tokens: self.avg_doc_tokens().max(1.0),
+            fields,
+        })
+    }
+
+    /// Scores a phrase query.
+    ///
+    /// With positional postings the phrase is scored like a single term
+    /// whose frequency in a document is the number of places the phrase
+    /// starts. Without positions every token of the phrase must occur, and
+    /// the document is scored like an `AND` of the tokens.
+    fn score_phrase(&self, phrase: &str, params: &BM25Params) -> FxHashMap<u64, f32> {
+        let tokens = self.phrase_tokens(phrase);
+        if self.config.positions {
+            self.score_phrase_tokens(&tokens, params)
+        } else {
+            self.score_all_tokens(tokens.iter().map(|(token, _)| token), params)
+        }
+    }
+
+    /// Scores a proximity query.
+    ///
+    /// With positional postings a document matches when it holds an
+    /// occurrence of every operand with at most `distance` other tokens
+    /// between them, in any order, and the number of such windows is
+    /// scored like a term frequency. Without positions every token of every
+    /// operand must occur, like an `AND`.
+    fn score_near(
+        &self,
+        operands: &[String],
+        distance: usize,
+        params: &BM25Params,
+    ) -> FxHashMap<u64, f32> {
+        let operands: Vec<Vec<(String, u32)>> = operands
+            .iter()
+            .map(|operand| self.phrase_tokens(operand))
+            .filter(|tokens| !tokens.is_empty())
+            .collect();
+        if !self.config.positions {
+            let tokens = operands.iter().flatten().map(|(token, _)| token);
+            return self.score_all_tokens(tokens, params);
+        }
+        if operands.len() <= 1 {
+            return operands
+                .first()
+                .map(|tokens| self.score_phrase_tokens(tokens, params))
+                .unwrap_or_default();
+        }
+
+        let Some(scorer) = self.scorer(params) else {
+            return FxHashMap::default();
+        };
+        // Occurrences of every operand: document → (start, end) spans.
+        let mut spans: Vec<FxHashMap<u64, Vec<(u32, u32)>>> = Vec::with_capacity(operands.len());
+        for tokens in &operands {
+            let len = tokens.last().map_or(0, |(_, offset)| *offset);
+            let starts = self.phrase_starts(tokens);
+            if starts.is_empty() {
+                return FxHashMap::default();
+            }
+            spans.push(
+                starts
+                    .into_iter()
+                    .map(|(doc_id, starts)| {
+                        let spans = starts
+                            .into_iter()
+                            .map(|start| (start, start.saturating_add(len)))
+                            .collect();
+                        (doc_id, spans)
+                    })
+                    .collect(),
+            );
+        }
+        let width: i64 = operands
+            .iter()
+            .map(|tokens| tokens.last().map_or(0, |(_, offset)| *offset as i64) + 1)
+            .sum();
+
+        let mut valid: FxHashMap<u64, (f32, f32)> = FxHashMap::default();
+        let (first, rest) = spans.split_first().unwrap_or((&spans[0], &[]));
+        for (doc_id, first_spans) in first {
+            if !rest.iter().all(|spans| spans.contains_key(doc_id)) {
+                continue;
+            }
+            let Some(doc_len) = self.doc_tokens.get(doc_id).map(|v| *v as f32) else {
+                continue;
+            };
+
+            let mut tagged: Vec<(u32, u32, usize)> = first_spans
+                .iter()
+                .map(|(start, end)| (*start, *end, 0))
+                .collect();
+            for (i, spans) in rest.iter().enumerate() {
+                tagged.extend(
+                    spans[doc_id]
+                        .iter()
+                        .map(|(start, end)| (*start, *end, i + 1)),
+                );
+            }
+            let windows = count_near_windows(tagged, spans.len(), width, distance as i64);
+            if windows > 0 {
+                valid.insert(*doc_id, scorer.doc_match(windows as f32, doc_len));
+            }
+        }
+        let mut scores = FxHashMap::default();
+        scorer.accumulate(&mut valid, &mut scores);
+        scores
+    }
+
+    /// Scores tokenized phrase, see [`phrase_tokens`](Self::phrase_tokens),
+    /// with positional postings. A single token is scored as a term.
+    fn score_phrase_tokens(
+        &self,
+        tokens: &[(String, u32)],
+        params: &BM25Params,
+    ) -> FxHashMap<u64, f32> {
+        if tokens.len() <= 1 {
+            return self.score_tokens(tokens.iter().map(|(token, _)| token), params);
+        }
+        let Some(scorer) = self.scorer(params) else {
+            return FxHashMap::default();
+        };
+        let mut valid: FxHashMap<u64, (f32, f32)> = self
+            .phrase_starts(tokens)
+            .into_iter()
+            .filter_map(|(doc_id, starts)| {
+                let doc_len = *self.doc_tokens.get(&doc_id)?;
+                Some((
+                    doc_id,
+                    scorer.doc_match(starts.len() as f32, doc_len as f32),
+                ))
+            })
+            .collect();
+        let mut scores = FxHashMap::default();
+        scorer.accumulate(&mut valid, &mut scores);
+        scores
+    }
 
-                if valid.is_empty() {
-                    continue;
+    /// Scores the documents containing every one of `tokens`, summing the
+    /// BM25 score of each token.
+    fn score_all_tokens<'a>(
+        &self,
+        tokens: impl IntoIterator<Item = &'a String>,
+        params: &BM25Params,
+    ) -> FxHashMap<u64, f32> {
+        let tokens: FxHashSet<&String> = tokens.into_iter().collect();
+        let mut result: Option<FxHashMap<u64, f32>> = None;
+        for token in tokens {
+            let scores = self.score_tokens([token], params);
+            result = Some(match result {
+                None => scores,
+                Some(mut result) => {
+                    result.retain(|doc_id, score| {
+                        if let Some(token_score) = scores.get(doc_id) {
+                            *score += *token_score;
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                    result
                 }
+            });
+            if result.as_ref().is_some_and(|result| result.is_empty()) {
+                break;
+            }
+        }
+        result.unwrap_or_default()
+    }
 
-                // Classic Okapi BM25: ln(1 + (N - df + 0.5)/(df + 0.5))
-                let df = valid.len() as f32;
-                let idf = ((doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
-
-                // Compute BM25 score for each valid document. `drain` empties the
-                // map while keeping its allocation for the next query term.
-                for (doc_id, (tf, doc_len)) in valid.drain() {
-                    let tf_component =
-                        (tf * (k1 + 1.0)) / (tf + k1 * (1.0 - b + b * doc_len / avg_doc_tokens));
-                    *scores.entry(doc_id).or_default() += idf * tf_component;
-                }
+    /// Tokenizes a phrase into `(token, offset)` pairs in text order, the
+    /// offsets being relative to the first token.
+    fn phrase_tokens(&self, phrase: &str) -> Vec<(String, u32)> {
+        let mut tokenizer = self.tokenizer.clone();
+        let mut tokens: Vec<(String, u32)> = collect_token_positions(&mut tokenizer, phrase)
+            .into_iter()
+            .flat_map(|(token, positions)| {
+                positions
+                    .into_iter()
+                    .map(move |position| (token.clone(), position))
+            })
+            .collect();
+        tokens.sort_unstable_by_key(|(_, position)| *position);
+        if let Some(first) = tokens.first().map(|(_, position)| *position) {
+            for (_, position) in tokens.iter_mut() {
+                *position -= first;
             }
         }
+        tokens
+    }
 
-        scores
+    /// Finds where a phrase occurs: maps every live document holding the
+    /// tokens at their offsets to the positions the phrase starts at.
+    ///
+    /// Only one positions guard is held at a time; the candidate starts are
+    /// narrowed token by token.
+    fn phrase_starts(&self, tokens: &[(String, u32)]) -> FxHashMap<u64, Vec<u32>> {
+        let Some(((first, _), rest)) = tokens.split_first() else {
+            return FxHashMap::default();
+        };
+        let mut starts: FxHashMap<u64, Vec<u32>> = match self.positions.get(first) {
+            Some(positions) => positions
+                .iter()
+                .filter(|(doc_id, _)| self.doc_tokens.contains_key(doc_id))
+                .map(|(doc_id, positions)| (*doc_id, positions.clone()))
+                .collect(),
+            None => return FxHashMap::default(),
+        };
+
+        for (token, offset) in rest {
+            if starts.is_empty() {
+                break;
+            }
+            let Some(positions) = self.positions.get(token) else {
+                return FxHashMap::default();
+            };
+            starts.retain(|doc_id, starts| {
+                let Some(positions) = positions.get(doc_id) else {
+                    return false;
+                };
+                starts.retain(|start| {
+                    start
+                        .checked_add(*offset)
+                        .is_some_and(|position| positions.binary_search(&position).is_ok())
+                });
+                !starts.is_empty()
+            });
+        }
+        starts
     }
 
     /// Scores an OR query
@@ -1868,7 +2871,13 @@ where
             .postings
             .iter()
             .map(|entry| {
-                let size = cbor_serialized_size(&(entry.key(), entry.value())) + 2;
+                let mut size = cbor_serialized_size(&(entry.key(), entry.value())) + 2;
+                if let Some(positions) = self.positions.get(entry.key()) {
+                    size += cbor_serialized_size(&(entry.key(), &*positions)) + 2;
+                }
+                if let Some(field_freqs) = self.field_freqs.get(entry.key()) {
+                    size += cbor_serialized_size(&(entry.key(), &*field_freqs)) + 2;
+                }
                 (entry.key().clone(), size)
             })
             .collect();
@@ -2012,11 +3021,29 @@ where
             .filter_map(|id| self.doc_tokens.get(id).map(|v| (*id, *v)))
             .collect();
 
+        let positions: FxHashMap<_, _> = postings
+            .keys()
+            .filter_map(|k| Some((*k, self.positions.get(*k)?)))
+            .collect();
+
+        let field_freqs: FxHashMap<_, _> = postings
+            .keys()
+            .filter_map(|k| Some((*k, self.field_freqs.get(*k)?)))
+            .collect();
+
+        let doc_field_tokens: FxHashMap<_, _> = referenced_doc_ids
+            .iter()
+            .filter_map(|id| Some((*id, self.doc_field_tokens.get(id)?.clone())))
+            .collect();
+
         let mut buf = Vec::with_capacity(4096);
         cbor2::to_writer(
             &BucketRef {
                 postings: &postings,
                 doc_tokens: &doc_tokens,
+                positions: &positions,
+                field_freqs: &field_freqs,
+                doc_field_tokens: &doc_field_tokens,
             },
             &mut buf,
         )
@@ -2155,6 +3182,9 @@ mod tests {
             &BucketOwned {
                 postings,
                 doc_tokens,
+                positions: FxHashMap::default(),
+                field_freqs: FxHashMap::default(),
+                doc_field_tokens: FxHashMap::default(),
             },
             &mut buf,
         )
@@ -2247,6 +3277,7 @@ mod tests {
             Some(BM25Config {
                 bm25: BM25Params::default(),
                 bucket_overload_size: 64,
+                ..Default::default()
             }),
         );
         index.insert(1, "alpha", 0).unwrap();
@@ -2340,6 +3371,7 @@ mod tests {
         let config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 64,
+            ..Default::default()
         };
         let index = BM25Index::new(
             "legacy_upgrade".to_string(),
@@ -2455,6 +3487,7 @@ mod tests {
         let config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 64,
+            ..Default::default()
         };
         let index = BM25Index::new(
             "remove_wrong_text_reload".to_string(),
@@ -2667,6 +3700,7 @@ mod tests {
         let config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 64,
+            ..Default::default()
         };
         let index = BM25Index::new(
             "manifest_migration".to_string(),
@@ -2978,6 +4012,7 @@ mod tests {
         let small_config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 50,
+            ..Default::default()
         };
         let index = BM25Index::new(
             "compact_manifest".to_string(),
@@ -3116,7 +4151,15 @@ mod tests {
 
         // 搜索相同的查询
         let default_results = default_index.search("fox", 10, None);
-        let custom_results = default_index.search("fox", 10, Some(BM25Params { k1: 1.5, b: 0.75 }));
+        let custom_results = default_index.search(
+            "fox",
+            10,
+            Some(BM25Params {
+                k1: 1.5,
+                b: 0.75,
+                ..Default::default()
+            }),
+        );
 
         // 验证结果数量相同但分数不同
         assert_eq!(default_results.len(), custom_results.len());
@@ -3154,17 +4197,28 @@ mod tests {
             BM25Params {
                 k1: f32::NAN,
                 b: f32::INFINITY,
+                ..Default::default()
             },
             BM25Params {
                 k1: f32::MAX,
                 b: 1.0,
+                ..Default::default()
             },
             BM25Params {
                 k1: f32::MAX,
                 b: f32::MAX,
+                ..Default::default()
+            },
+            BM25Params {
+                k1: 1e30,
+                b: 1.0,
+                ..Default::default()
+            },
+            BM25Params {
+                k1: -1e30,
+                b: -5.0,
+                ..Default::default()
             },
-            BM25Params { k1: 1e30, b: 1.0 },
-            BM25Params { k1: -1e30, b: -5.0 },
         ];
 
         for params in hostile {
@@ -3208,6 +4262,7 @@ mod tests {
             Some(BM25Params {
                 k1: f32::MAX,
                 b: 1.0,
+                ..Default::default()
             }),
         );
 
@@ -3245,13 +4300,13 @@ mod tests {
         for a in entries {
             for b in entries {
                 for c in entries {
-                    let ab = BM25Index::<TokenizerChain>::compare_scored_docs(&a, &b);
-                    let bc = BM25Index::<TokenizerChain>::compare_scored_docs(&b, &c);
-                    let ac = BM25Index::<TokenizerChain>::compare_scored_docs(&a, &c);
+                    let ab = compare_scored_docs(&a, &b);
+                    let bc = compare_scored_docs(&b, &c);
+                    let ac = compare_scored_docs(&a, &c);
                     // Antisymmetry.
                     assert_eq!(
                         ab.reverse(),
-                        BM25Index::<TokenizerChain>::compare_scored_docs(&b, &a),
+                        compare_scored_docs(&b, &a),
                         "not antisymmetric for {a:?} / {b:?}"
                     );
                     // Transitivity.
@@ -3268,7 +4323,7 @@ mod tests {
 
         // NaN scores rank last, never first.
         let mut entries = [n, x, y];
-        entries.sort_unstable_by(BM25Index::<TokenizerChain>::compare_scored_docs);
+        entries.sort_unstable_by(compare_scored_docs);
         assert_eq!(entries[0].0, 3);
         assert_eq!(entries[2].0, 2);
     }
@@ -3394,6 +4449,477 @@ mod tests {
         }
     }
 
+    fn create_phrase_index(positions: bool) -> BM25Index<TokenizerChain> {
+        let index = BM25Index::new(
+            "anda_db_tfs_phrase".to_string(),
+            default_tokenizer(),
+            Some(BM25Config {
+                positions,
+                ..Default::default()
+            }),
+        );
+        index
+            .insert(1, "Machine learning for agent memory", 0)
+            .unwrap();
+        index
+            .insert(2, "Learning about the machine shop", 0)
+            .unwrap();
+        index
+            .insert(3, "machine learning, machine learning", 0)
+            .unwrap();
+        index.insert(4, "memory of the agent", 0).unwrap();
+        index
+    }
+
+    #[test]
+    fn test_highlighter() {
+        let index = create_phrase_index(false);
+        let text = "Machine learning for agent memory";
+        let highlighted = |query: &str, logical: bool| {
+            let snippets = index
+                .highlighter(query, logical, HighlightOptions::default())
+                .highlight(text);
+            assert_eq!(snippets.len(), 1);
+            let words: Vec<String> = snippets[0]
+                .highlights
+                .iter()
+                .map(|range| snippets[0].text[range.clone()].to_string())
+                .collect();
+            (words, snippets[0].score)
+        };
+
+        // Rarer tokens weigh more: "machine" is in 3 of 4 documents,
+        // "agent" in 2.
+        let (words, score) = highlighted("machine agent", false);
+        assert_eq!(words, vec!["Machine", "agent"]);
+        let (_, machine) = highlighted("machine", false);
+        let (_, agent) = highlighted("agent", false);
+        assert!(agent > machine);
+        assert!((score - machine - agent).abs() < 1e-6);
+
+        // Logical queries skip negated subqueries and keep operators out.
+        let (words, _) = highlighted("machine AND NOT agent", true);
+        assert_eq!(words, vec!["Machine"]);
+        let (words, _) = highlighted("\"agent memory\" OR nothing", true);
+        assert_eq!(words, vec!["agent", "memory"]);
+
+        assert!(
+            index
+                .highlighter("NOT agent", true, HighlightOptions::default())
+                .is_empty()
+        );
+    }
+
+    fn result_ids(results: &[(u64, f32)]) -> BTreeSet<u64> {
+        results.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn test_phrase_and_near_search() {
+        let index = create_phrase_index(true);
+        let search = |query: &str| index.search_advanced(query, 10, None);
+
+        let results = search("\"machine learning\"");
+        assert_eq!(result_ids(&results), BTreeSet::from([1, 3]));
+        // Two occurrences in a shorter document rank first.
+        assert_eq!(results[0].0, 3);
+        assert_eq!(
+            result_ids(&search("\"learning machine\"")),
+            BTreeSet::from([3])
+        );
+
+        assert_eq!(
+            result_ids(&search("agent NEAR/1 memory")),
+            BTreeSet::from([1])
+        );
+        assert_eq!(
+            result_ids(&search("agent NEAR/2 memory")),
+            BTreeSet::from([1, 4])
+        );
+        assert_eq!(
+            result_ids(&search("\"machine learning\" NEAR/2 memory")),
+            BTreeSet::from([1])
+        );
+        assert!(search("\"machine learning\" NEAR/1 memory").is_empty());
+        assert_eq!(
+            result_ids(&search("\"machine learning\" AND NOT agent")),
+            BTreeSet::from([3])
+        );
+
+        // Without positions, phrases and proximity only require every token.
+        let plain = create_phrase_index(false);
+        assert_eq!(
+            result_ids(&plain.search_advanced("\"machine learning\"", 10, None)),
+            BTreeSet::from([1, 2, 3])
+        );
+        assert_eq!(
+            result_ids(&plain.search_advanced("agent NEAR/1 memory", 10, None)),
+            BTreeSet::from([1, 4])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_positions_survive_flush_remove_and_purge() {
+        let index = create_phrase_index(true);
+        let mut store = MemStore::default();
+        assert!(flush_to(&index, &mut store, 1).await.saved);
+
+        let loaded = load_from(&store).await;
+        assert!(loaded.metadata().config.positions);
+        let phrase = |index: &BM25Index<TokenizerChain>| {
+            result_ids(&index.search_advanced("\"machine learning\"", 10, None))
+        };
+        assert_eq!(phrase(&loaded), BTreeSet::from([1, 3]));
+
+        assert!(loaded.remove(3, "machine learning, machine learning", 2));
+        assert_eq!(phrase(&loaded), BTreeSet::from([1]));
+        assert!(
+            loaded
+                .positions
+                .iter()
+                .all(|positions| !positions.contains_key(&3))
+        );
+
+        assert_eq!(loaded.purge_ids(&BTreeSet::from([1]), 3), 1);
+        assert!(phrase(&loaded).is_empty());
+        assert!(
+            loaded
+                .positions
+                .iter()
+                .all(|positions| !positions.contains_key(&1) && !positions.contains_key(&3))
+        );
+
+        flush_to(&loaded, &mut store, 4).await;
+        let reloaded = load_from(&store).await;
+        assert!(phrase(&reloaded).is_empty());
+        assert_eq!(
+            result_ids(&reloaded.search_advanced("agent NEAR/2 memory", 10, None)),
+            BTreeSet::from([4])
+        );
+
+        // Buckets of an index without positions keep their previous format.
+        let plain = create_phrase_index(false);
+        let mut plain_store = MemStore::default();
+        flush_to(&plain, &mut plain_store, 1).await;
+        for data in plain_store.buckets.values() {
+            let value: cbor2::Value = cbor2::from_reader(&data[..]).unwrap();
+            let cbor2::Value::Map(entries) = value else {
+                panic!("bucket is not a map");
+            };
+            assert!(
+                entries
+                    .iter()
+                    .all(|(key, _)| *key != cbor2::Value::Text("o".to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_count_near_windows() {
+        // a@0, b@3: two tokens between them.
+        let spans = vec![(0, 0, 0), (3, 3, 1)];
+        assert_eq!(count_near_windows(spans.clone(), 2, 2, 1), 0);
+        assert_eq!(count_near_windows(spans, 2, 2, 2), 1);
+
+        // Order does not matter, and every closing span counts once.
+        let spans = vec![(5, 5, 0), (4, 4, 1), (0, 0, 0)];
+        assert_eq!(count_near_windows(spans, 2, 2, 0), 1);
+        let spans = vec![(0, 0, 0), (1, 1, 1), (2, 2, 0)];
+        assert_eq!(count_near_windows(spans, 2, 2, 0), 2);
+
+        // A missing operand never matches.
+        assert_eq!(count_near_windows(vec![(0, 0, 0)], 2, 2, 10), 0);
+    }
+
+    const FIELD_DOCS: [(u64, &str, &str); 3] = [
+        (1, "Rust ownership", "a gentle guide to memory safety"),
+        (
+            2,
+            "Memory safety",
+            "rust has ownership and borrowing rules for memory safety in systems programming",
+        ),
+        (
+            3,
+            "Rust ownership and borrowing explained with many examples",
+            "a guide",
+        ),
+    ];
+
+    fn field_doc_text(id: u64) -> String {
+        let (_, title, body) = FIELD_DOCS.iter().find(|(i, _, _)| *i == id).unwrap();
+        format!("{title}{FIELD_SEPARATOR}{body}")
+    }
+
+    fn create_fields_index(ids: &[u64], field_boosts: Vec<f32>) -> BM25Index<TokenizerChain> {
+        let index = BM25Index::new(
+            "anda_db_tfs_fields".to_string(),
+            default_tokenizer(),
+            Some(BM25Config {
+                positions: true,
+                field_boosts,
+                ..Default::default()
+            }),
+        );
+        for id in ids {
+            index.insert(*id, &field_doc_text(*id), 0).unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn test_bm25f_field_boosts_and_lengths() {
+        let index = create_fields_index(&[1, 2, 3], vec![4.0, 1.0]);
+        let ids = |results: &[(u64, f32)]| results.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+        // Title hits outrank the body hit, and the short title ranks first.
+        assert_eq!(ids(&index.search("rust", 10, None)), vec![1, 3, 2]);
+
+        // A query-time override replaces the configured boost of a field.
+        let params = BM25Params {
+            field_boosts: Some(vec![0.0]),
+            ..Default::default()
+        };
+        let results = index.search("rust", 10, Some(params));
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, 2);
+        assert!(results[0].1 > 0.0);
+        assert!(results[1..].iter().all(|(_, score)| *score == 0.0));
+
+        // Hostile boosts stay finite.
+        let params = BM25Params {
+            field_boosts: Some(vec![f32::MAX, f32::NAN]),
+            ..Default::default()
+        };
+        let results = index.search("rust", 10, Some(params));
+        assert!(results.iter().all(|(_, score)| score.is_finite()));
+
+        // Phrases do not span two fields: "safety" ends the title of 2 and
+        // "rust" starts its body.
+        assert!(
+            index
+                .search_advanced("\"safety rust\"", 10, None)
+                .is_empty()
+        );
+        assert_eq!(
+            ids(&index.search_advanced("\"memory safety\"", 10, None)),
+            vec![2, 1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bm25f_survives_flush_remove_and_purge() {
+        let index = create_fields_index(&[1, 2, 3], vec![2.0, 1.0]);
+        let queries = ["rust", "memory safety", "ownership guide"];
+        let assert_same = |a: &BM25Index<TokenizerChain>, b: &BM25Index<TokenizerChain>| {
+            for query in queries {
+                let a = a.search(query, 10, None);
+                let b = b.search(query, 10, None);
+                assert_eq!(a.len(), b.len(), "{query}");
+                for ((a_id, a_score), (b_id, b_score)) in a.iter().zip(&b) {
+                    assert_eq!(a_id, b_id, "{query}");
+                    assert!((a_score - b_score).abs() < 1e-5, "{query}");
+                }
+            }
+        };
+
+        let mut store = MemStore::default();
+        assert!(flush_to(&index, &mut store, 1).await.saved);
+        for data in store.buckets.values() {
+            let value: cbor2::Value = cbor2::from_reader(&data[..]).unwrap();
+            let cbor2::Value::Map(entries) = value else {
+                panic!("bucket is not a map");
+            };
+            for key in ["f", "l"] {
+                assert!(
+                    entries
+                        .iter()
+                        .any(|(k, _)| *k == cbor2::Value::Text(key.to_string()))
+                );
+            }
+        }
+        let loaded = load_from(&store).await;
+        assert_eq!(loaded.metadata().config.field_boosts, vec![2.0, 1.0]);
+        assert_same(&index, &loaded);
+
+        assert!(loaded.remove(2, &field_doc_text(2), 2));
+        assert_eq!(loaded.purge_ids(&BTreeSet::from([3]), 3), 1);
+        let expected = create_fields_index(&[1], vec![2.0, 1.0]);
+        assert_same(&expected, &loaded);
+        assert!(
+            loaded
+                .field_freqs
+                .iter()
+                .all(|freqs| freqs.keys().all(|id| *id == 1))
+        );
+
+        flush_to(&loaded, &mut store, 4).await;
+        let reloaded = load_from(&store).await;
+        assert_same(&expected, &reloaded);
+    }
+
+    fn create_pattern_index() -> BM25Index<TokenizerChain> {
+        let index = BM25Index::new(
+            "anda_db_tfs_patterns".to_string(),
+            default_tokenizer(),
+            None,
+        );
+        index.insert(1, "memory systems for agents", 0).unwrap();
+        index.insert(2, "memorable moments", 0).unwrap();
+        index.insert(3, "agent workflows", 0).unwrap();
+        index.insert(4, "rust memoir", 0).unwrap();
+        index
+    }
+
+    #[test]
+    fn test_wildcard_match_and_levenshtein() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        let matches = |pattern: &str, text: &str| wildcard_match(&chars(pattern), &chars(text));
+        assert!(matches("m?m*r", "memoir"));
+        assert!(matches("*", ""));
+        assert!(matches("*oir", "memoir"));
+        assert!(matches("m**r", "memoir"));
+        assert!(matches("a*b*c", "aXbYbc"));
+        assert!(!matches("m?r", "memoir"));
+        assert!(!matches("mem", "memoir"));
+        assert!(!matches("?", ""));
+
+        let distance = |a: &str, b: &str, max| bounded_levenshtein(&chars(a), &chars(b), max);
+        assert_eq!(distance("system", "system", 0), Some(0));
+        assert_eq!(distance("sytem", "system", 1), Some(1));
+        assert_eq!(distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(distance("kitten", "sitting", 2), None);
+        assert_eq!(distance("über", "uber", 1), Some(1));
+        assert_eq!(distance("a", "abcd", 2), None);
+    }
+
+    #[test]
+    fn test_prefix_wildcard_and_fuzzy_search() {
+        let index = create_pattern_index();
+        let ids = |query: &str| result_ids(&index.search_advanced(query, 10, None));
+
+        // Patterns match the stored (stemmed) terms: memori, memor, memoir.
+        assert_eq!(ids("memo*"), BTreeSet::from([1, 2, 4]));
+        assert_eq!(ids("MEMO*"), BTreeSet::from([1, 2, 4]));
+        assert_eq!(ids("mem?ir"), BTreeSet::from([4]));
+        assert_eq!(ids("m*t"), BTreeSet::from([2]));
+        assert_eq!(ids("*flow"), BTreeSet::from([3]));
+        assert!(ids("zz*").is_empty());
+
+        // A trailing `?` ends a question; it is not a wildcard.
+        assert!(!ids("rust").is_empty());
+        assert_eq!(ids("what is rust?"), ids("rust"));
+
+        // Fuzzy terms are tokenized first, then matched within the distance.
+        assert_eq!(ids("sytem~1"), BTreeSet::from([1]));
+        assert_eq!(ids("Systms~1"), BTreeSet::from([1]));
+        assert!(ids("sytem~0").is_empty());
+        assert_eq!(ids("agnet~2"), BTreeSet::from([1, 3]));
+        // Matches share the first character of the fuzzy term.
+        assert!(ids("bystem~1").is_empty());
+
+        // Patterns compose with the boolean operators.
+        assert_eq!(ids("memo* AND NOT rusty~1"), BTreeSet::from([1, 2]));
+        assert_eq!(ids("agent AND memo*"), BTreeSet::from([1]));
+
+        // Closer fuzzy matches score higher; an exact match scores like the
+        // plain term.
+        let score = |query: &str| index.search_advanced(query, 10, None)[0].1;
+        assert!((score("rust~1") - score("rust")).abs() < 1e-6);
+        assert!((score("rusty~1") - score("rust") / 2.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_term_dictionary_follows_postings() {
+        let index = create_pattern_index();
+        for id in 0..100u64 {
+            index.insert(100 + id, &format!("term{id:03}"), 0).unwrap();
+        }
+        assert_eq!(
+            index.search_advanced("term*", 1000, None).len(),
+            MAX_TERM_EXPANSIONS
+        );
+
+        assert!(index.remove(4, "rust memoir", 1));
+        assert_eq!(index.purge_ids(&BTreeSet::from([2]), 2), 1);
+        assert!(!index.terms.read().contains("memoir"));
+        assert!(!index.terms.read().contains("memor"));
+        assert_eq!(
+            result_ids(&index.search_advanced("memo*", 10, None)),
+            BTreeSet::from([1])
+        );
+
+        // The dictionary is rebuilt from the loaded postings.
+        let mut store = MemStore::default();
+        flush_to(&index, &mut store, 3).await;
+        let loaded = load_from(&store).await;
+        assert_eq!(*loaded.terms.read(), *index.terms.read());
+        assert_eq!(loaded.terms.read().len(), loaded.postings.len());
+        assert_eq!(
+            result_ids(&loaded.search_advanced("agnt~1 OR memo*", 10, None)),
+            BTreeSet::from([1, 3])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_all_with_recorded_tokenizer() {
+        let config = TokenizerConfig::jieba();
+        let mut registry = TokenizerRegistry::default();
+        let index = BM25Index::new(
+            "recorded".to_string(),
+            registry.resolve(&config).unwrap(),
+            Some(BM25Config {
+                tokenizer: Some(config.clone()),
+                ..Default::default()
+            }),
+        );
+        index.insert(1, "长期记忆 for agents", 0).unwrap();
+        let mut store = MemStore::default();
+        flush_to(&index, &mut store, 1).await;
+
+        let load = async |registry: &TokenizerRegistry| {
+            BM25Index::load_all_with(
+                |config| match &config.tokenizer {
+                    Some(tokenizer) => registry.resolve(tokenizer),
+                    None => Ok(default_tokenizer()),
+                },
+                &store.metadata[..],
+                async |object| Ok(store.buckets.get(&object).cloned()),
+            )
+            .await
+        };
+        let loaded = load(&registry).await.unwrap();
+        assert_eq!(loaded.config().tokenizer, Some(config.clone()));
+        assert_eq!(
+            result_ids(&loaded.search("记忆", 10, None)),
+            BTreeSet::from([1])
+        );
+
+        registry
+            .register(TokenizerConfig {
+                version: 2,
+                ..config
+            })
+            .unwrap();
+        assert!(matches!(
+            load(&registry).await,
+            Err(BM25Error::Tokenizer { .. })
+        ));
+    }
+
+    #[test]
+    fn test_highlighter_expands_patterns() {
+        let index = create_pattern_index();
+        let snippets = index
+            .highlighter("memo* AND NOT agent", true, HighlightOptions::default())
+            .highlight("Memories of memorable agents");
+        let words: Vec<&str> = snippets[0]
+            .highlights
+            .iter()
+            .map(|range| &snippets[0].text[range.clone()])
+            .collect();
+        assert_eq!(words, vec!["Memories", "memorable"]);
+    }
+
     #[test]
     fn test_search_not_alone() {
         let index = create_test_index();
@@ -3626,6 +5152,7 @@ mod tests {
         let config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 100, // 非常小的桶大小，强制分桶
+            ..Default::default()
         };
         let index = BM25Index::new(
             "test_bucket_serialization".to_string(),
@@ -3872,6 +5399,7 @@ mod tests {
         let config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 64,
+            ..Default::default()
         };
         let index = BM25Index::new(
             "partial_load_doc_tokens".to_string(),
@@ -3926,6 +5454,7 @@ mod tests {
         let config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 200, // small limit to trigger splits quickly
+            ..Default::default()
         };
         let index = BM25Index::new("small_bucket_test".to_string(), tokenizer, Some(config));
 
@@ -3993,6 +5522,7 @@ mod tests {
         let small_config = BM25Config {
             bm25: BM25Params::default(),
             bucket_overload_size: 50, // tiny limit to force many buckets
+            ..Default::default()
         };
         let index = BM25Index::new("compact_test".to_string(), tokenizer, Some(small_config));
 
@@ -4135,6 +5665,7 @@ mod tests {
             Some(BM25Config {
                 bm25: BM25Params::default(),
                 bucket_overload_size: 64,
+                ..Default::default()
             }),
         ));
         // Seed enough tokens that each compaction has real work to do.
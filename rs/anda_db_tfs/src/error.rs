@@ -53,4 +53,54 @@ pub enum BM25Error {
         /// Source text that produced no tokens.
         text: String,
     },
+
+    /// Error when a tokenizer definition cannot be built, or does not match
+    /// the registered definition of the same name
+    #[error("BM25 tokenizer {name:?}, error: {source:?}")]
+    Tokenizer {
+        /// Name of the tokenizer definition.
+        name: String,
+        /// Why the definition was rejected.
+        source: BoxError,
+    },
+}
+
+/// Errors that can occur when working with a sparse vector index.
+#[derive(Error, Debug)]
+pub enum SparseError {
+    /// Index-related errors.
+    #[error("Sparse index {name:?}, error: {source:?}")]
+    Generic {
+        /// Name of the sparse index that raised the error.
+        name: String,
+        /// Original error returned by the underlying operation.
+        source: BoxError,
+    },
+
+    /// CBOR serialization/deserialization errors
+    #[error("Sparse index {name:?}, CBOR serialization error: {source:?}")]
+    Serialization {
+        /// Name of the sparse index whose serialized state failed to encode or decode.
+        name: String,
+        /// Original serialization or deserialization error.
+        source: BoxError,
+    },
+
+    /// Error when trying to add a document with an ID that already exists
+    #[error("Sparse index {name:?}, document {id} already exists")]
+    AlreadyExists {
+        /// Name of the sparse index receiving the duplicate document.
+        name: String,
+        /// Document id that already exists in the index.
+        id: u64,
+    },
+
+    /// Error when a document's sparse vector has no finite, non-zero weight
+    #[error("Sparse index {name:?}, document {id} has an empty sparse vector")]
+    EmptyVector {
+        /// Name of the sparse index processing the document.
+        name: String,
+        /// Document id whose sparse vector carries no indexable term.
+        id: u64,
+    },
 }
@@ -0,0 +1,412 @@
+//! # Hit highlighting
+//!
+//! This module contains [`Highlighter`], which finds the tokens of a query
+//! in a stored text and extracts the best-scoring fragments of it as
+//! [`Snippet`]s. A highlighter is created for one query with
+//! [`BM25Index::highlighter`](crate::BM25Index::highlighter) and can then be
+//! applied to any number of texts.
+
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use crate::tokenizer::*;
+
+/// Upper bound of [`HighlightOptions::fragment_size`].
+pub const MAX_FRAGMENT_SIZE: usize = 4096;
+
+/// Upper bound of [`HighlightOptions::max_fragments`].
+pub const MAX_FRAGMENTS: usize = 32;
+
+/// Options controlling the fragments a [`Highlighter`] extracts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightOptions {
+    /// Target length of a fragment, in bytes. A fragment is padded with
+    /// the surrounding tokens up to this length; a single matched token
+    /// longer than it still makes a fragment. Defaults to 150.
+    pub fragment_size: usize,
+
+    /// Maximum number of fragments returned for one text. Defaults to 3.
+    pub max_fragments: usize,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        HighlightOptions {
+            fragment_size: 150,
+            max_fragments: 3,
+        }
+    }
+}
+
+impl HighlightOptions {
+    /// Validates the options: both must be positive and within
+    /// [`MAX_FRAGMENT_SIZE`] and [`MAX_FRAGMENTS`].
+    pub fn validate(&self) -> Result<(), String> {
+        if self.fragment_size == 0 || self.fragment_size > MAX_FRAGMENT_SIZE {
+            return Err(format!(
+                "fragment_size must be in 1..={MAX_FRAGMENT_SIZE}, got {}",
+                self.fragment_size
+            ));
+        }
+        if self.max_fragments == 0 || self.max_fragments > MAX_FRAGMENTS {
+            return Err(format!(
+                "max_fragments must be in 1..={MAX_FRAGMENTS}, got {}",
+                self.max_fragments
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A fragment of a text containing matched query tokens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    /// Byte range of the fragment in the highlighted text.
+    pub range: Range<usize>,
+
+    /// The text of the fragment.
+    pub text: String,
+
+    /// Byte ranges of the matched tokens in [`text`](Self::text), in
+    /// ascending order and never overlapping.
+    pub highlights: Vec<Range<usize>>,
+
+    /// Relevance of the fragment: the summed weight of the distinct query
+    /// tokens it contains.
+    pub score: f32,
+}
+
+/// Extracts [`Snippet`]s of texts matching one query.
+///
+/// Texts are tokenized with the tokenizer of the index that created the
+/// highlighter, keeping the byte offsets of every token, so a token matches
+/// exactly when the index would match it (after lowercasing, stemming and
+/// the like). Each query token is weighted by its inverse document
+/// frequency in the index, so fragments with rare tokens rank first.
+#[derive(Clone)]
+pub struct Highlighter<T: Tokenizer + Clone> {
+    tokenizer: T,
+    /// Weight of every query token, by token.
+    weights: FxHashMap<String, f32>,
+    options: HighlightOptions,
+}
+
+impl<T: Tokenizer + Clone> Highlighter<T> {
+    /// Creates a highlighter matching the tokens of `weights`.
+    ///
+    /// Options out of range are clamped into it.
+    pub fn new(tokenizer: T, weights: FxHashMap<String, f32>, options: HighlightOptions) -> Self {
+        Highlighter {
+            tokenizer,
+            weights,
+            options: HighlightOptions {
+                fragment_size: options.fragment_size.clamp(1, MAX_FRAGMENT_SIZE),
+                max_fragments: options.max_fragments.clamp(1, MAX_FRAGMENTS),
+            },
+        }
+    }
+
+    /// Returns true when the query has no token, so nothing can be
+    /// highlighted.
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    /// Returns the best-scoring fragments of `text`, in text order.
+    ///
+    /// Fragments never overlap. A text without any matched token has no
+    /// fragment.
+    pub fn highlight(&self, text: &str) -> Vec<Snippet> {
+        if self.weights.is_empty() || text.is_empty() {
+            return Vec::new();
+        }
+
+        let (tokens, hits, weights) = self.tokenize(text);
+        if hits.is_empty() {
+            return Vec::new();
+        }
+
+        let mut selected = self.select_windows(&tokens, &hits, &weights);
+        selected.sort_unstable_by_key(|(window, _)| window.start);
+        let mut snippets = Vec::with_capacity(selected.len());
+        for (i, (window, score)) in selected.iter().enumerate() {
+            let lower = if i == 0 { 0 } else { selected[i - 1].0.end };
+            let upper = selected
+                .get(i + 1)
+                .map_or(text.len(), |(next, _)| next.start);
+            let range = self.pad(&tokens, window, lower, upper);
+            snippets.push(Snippet {
+                text: text[range.clone()].to_string(),
+                highlights: merge_ranges(
+                    hits.iter()
+                        .map(|(hit, _)| tokens[*hit].clone())
+                        .filter(|token| range.start <= token.start && token.end <= range.end)
+                        .map(|token| token.start - range.start..token.end - range.start),
+                ),
+                range,
+                score: *score,
+            });
+        }
+        snippets
+    }
+
+    /// Tokenizes `text`, returning the byte ranges of all its tokens in
+    /// ascending order, the matched ones as `(token index, query token
+    /// index)`, and the weight of every matched query token.
+    #[allow(clippy::type_complexity)]
+    fn tokenize(&self, text: &str) -> (Vec<Range<usize>>, Vec<(usize, usize)>, Vec<f32>) {
+        let mut tokenizer = self.tokenizer.clone();
+        let mut stream = tokenizer.token_stream(text);
+        let mut tokens: Vec<Range<usize>> = Vec::new();
+        let mut hits: Vec<(usize, usize)> = Vec::new();
+        let mut matched: FxHashMap<&str, usize> = FxHashMap::default();
+        let mut weights: Vec<f32> = Vec::new();
+        while let Some(token) = stream.next() {
+            // Offsets come from the tokenizer; drop any that do not slice
+            // `text` rather than panic on them.
+            let (start, end) = (token.offset_from, token.offset_to);
+            if start >= end
+                || end > text.len()
+                || !text.is_char_boundary(start)
+                || !text.is_char_boundary(end)
+            {
+                continue;
+            }
+            // Tokens must be ordered for the window scan. A tokenizer that
+            // emits overlapping tokens (e.g. a search-mode segmenter) may
+            // go backwards; those tokens are skipped.
+            if tokens.last().is_some_and(|last| start < last.start) {
+                continue;
+            }
+
+            tokens.push(start..end);
+            // See "Token length filter" on `collect_tokens`.
+            if token.text.len() <= 1 {
+                continue;
+            }
+            if let Some((term, weight)) = self.weights.get_key_value(token.text.as_str()) {
+                let next = matched.len();
+                let term = *matched.entry(term.as_str()).or_insert_with(|| {
+                    weights.push(*weight);
+                    next
+                });
+                hits.push((tokens.len() - 1, term));
+            }
+        }
+        (tokens, hits, weights)
+    }
+
+    /// Selects up to `max_fragments` non-overlapping windows of matched
+    /// tokens, with their scores, by descending score.
+    ///
+    /// Every matched token starts a candidate window, extended over the
+    /// following matched tokens while it fits in `fragment_size`. A window
+    /// scores the summed weight of its distinct query tokens; ties go to the
+    /// window with more matches, then to the earlier one.
+    fn select_windows(
+        &self,
+        tokens: &[Range<usize>],
+        hits: &[(usize, usize)],
+        weights: &[f32],
+    ) -> Vec<(Range<usize>, f32)> {
+        let size = self.options.fragment_size;
+        let mut counts = vec![0usize; weights.len()];
+        let mut score = 0.0f32;
+        let mut end = 0;
+        // (first hit, last hit, score)
+        let mut candidates: Vec<(usize, usize, f32)> = Vec::with_capacity(hits.len());
+        for start in 0..hits.len() {
+            let from = tokens[hits[start].0].start;
+            if end <= start {
+                end = start;
+            }
+            while end < hits.len() && (end == start || tokens[hits[end].0].end - from <= size) {
+                let term = hits[end].1;
+                if counts[term] == 0 {
+                    score += weights[term];
+                }
+                counts[term] += 1;
+                end += 1;
+            }
+            candidates.push((start, end - 1, score));
+
+            let term = hits[start].1;
+            counts[term] -= 1;
+            if counts[term] == 0 {
+                score -= weights[term];
+            }
+        }
+
+        candidates.sort_unstable_by(|a, b| {
+            b.2.total_cmp(&a.2)
+                .then_with(|| (b.1 - b.0).cmp(&(a.1 - a.0)))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        let mut selected: Vec<(Range<usize>, f32)> = Vec::new();
+        for (first, last, score) in candidates {
+            let window = tokens[hits[first].0].start..tokens[hits[last].0].end;
+            if selected
+                .iter()
+                .all(|(other, _)| window.end <= other.start || other.end <= window.start)
+            {
+                selected.push((window, score));
+                if selected.len() == self.options.max_fragments {
+                    break;
+                }
+            }
+        }
+        selected
+    }
+
+    /// Pads `window` with whole surrounding tokens up to `fragment_size`
+    /// bytes, half of the spare room before it, without crossing `lower`
+    /// and `upper`.
+    fn pad(
+        &self,
+        tokens: &[Range<usize>],
+        window: &Range<usize>,
+        lower: usize,
+        upper: usize,
+    ) -> Range<usize> {
+        let spare = self
+            .options
+            .fragment_size
+            .saturating_sub(window.end - window.start);
+        let lower = lower.max(window.start.saturating_sub(spare / 2));
+        let start = tokens
+            .iter()
+            .find(|token| token.start >= lower)
+            .map_or(window.start, |token| token.start.min(window.start));
+
+        let upper = upper.min(window.end + spare - (window.start - start));
+        let end = tokens
+            .iter()
+            .rev()
+            .find(|token| token.end <= upper)
+            .map_or(window.end, |token| token.end.max(window.end));
+        start..end
+    }
+}
+
+/// Merges overlapping or adjacent ranges, which must be sorted by start.
+fn merge_ranges(ranges: impl Iterator<Item = Range<usize>>) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlighter(
+        terms: &[(&str, f32)],
+        options: HighlightOptions,
+    ) -> Highlighter<TokenizerChain> {
+        let weights = terms
+            .iter()
+            .map(|(term, weight)| (term.to_string(), *weight))
+            .collect();
+        Highlighter::new(default_tokenizer(), weights, options)
+    }
+
+    fn marked(snippet: &Snippet) -> String {
+        let mut out = String::new();
+        let mut at = 0;
+        for range in &snippet.highlights {
+            out.push_str(&snippet.text[at..range.start]);
+            out.push('[');
+            out.push_str(&snippet.text[range.clone()]);
+            out.push(']');
+            at = range.end;
+        }
+        out.push_str(&snippet.text[at..]);
+        out
+    }
+
+    #[test]
+    fn test_highlight_selects_best_fragments() {
+        let text = "Rust is a systems language. Memory safety without garbage collection \
+                    is the point of Rust. Agents need memory that lasts, and Rust \
+                    memory management helps agents.";
+        let options = HighlightOptions {
+            fragment_size: 40,
+            max_fragments: 2,
+        };
+        let hl = highlighter(&[("rust", 0.5), ("memori", 2.0), ("agent", 1.0)], options);
+        let snippets = hl.highlight(text);
+        assert_eq!(snippets.len(), 2);
+        // In text order, with the stemmed tokens matched on the raw text.
+        assert!(snippets[0].range.end <= snippets[1].range.start);
+        for snippet in &snippets {
+            assert_eq!(&text[snippet.range.clone()], snippet.text);
+            assert!(snippet.text.len() <= 40);
+            assert!(!snippet.highlights.is_empty());
+        }
+        // Both fragments hold all three query tokens; the first
+        // `Memory`, with the rarest token only, ranks below them.
+        assert_eq!(snippets[0].score, 3.5);
+        assert_eq!(snippets[1].score, 3.5);
+        assert_eq!(
+            marked(&snippets[0]),
+            "of [Rust]. [Agents] need [memory] that lasts"
+        );
+        assert_eq!(
+            marked(&snippets[1]),
+            "[Rust] [memory] management helps [agents]"
+        );
+    }
+
+    #[test]
+    fn test_highlight_edge_cases() {
+        let hl = highlighter(&[("fox", 1.0)], HighlightOptions::default());
+        assert!(hl.highlight("").is_empty());
+        assert!(hl.highlight("the lazy dog").is_empty());
+        assert!(highlighter(&[], HighlightOptions::default()).is_empty());
+
+        // A short text is returned whole.
+        let text = "The quick brown fox, the fox.";
+        let snippets = hl.highlight(text);
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].range, 0..28);
+        assert_eq!(marked(&snippets[0]), "The quick brown [fox], the [fox]");
+        assert_eq!(snippets[0].score, 1.0);
+
+        // A match longer than the fragment size is still a fragment.
+        let hl = highlighter(
+            &[("fox", 1.0)],
+            HighlightOptions {
+                fragment_size: 2,
+                max_fragments: 1,
+            },
+        );
+        let snippets = hl.highlight("foxes and dogs");
+        assert_eq!(snippets[0].text, "foxes");
+
+        // Multi-byte text is sliced on character boundaries.
+        let hl = highlighter(&[("über", 1.0)], HighlightOptions::default());
+        let snippets = hl.highlight("naïve über café");
+        assert_eq!(marked(&snippets[0]), "naïve [über] café");
+    }
+
+    #[test]
+    fn test_highlight_options() {
+        assert!(HighlightOptions::default().validate().is_ok());
+        for (fragment_size, max_fragments) in
+            [(0, 1), (MAX_FRAGMENT_SIZE + 1, 1), (10, 0), (10, 33)]
+        {
+            let options = HighlightOptions {
+                fragment_size,
+                max_fragments,
+            };
+            assert!(options.validate().is_err());
+        }
+    }
+}
@@ -11,8 +11,19 @@
 //! - **Composable tokenization**: plug any [`Tokenizer`] — including chains of
 //!   filters — into the same index. Built-in helpers cover Latin/Cyrillic/Arabic
 //!   text and Chinese via [jieba](https://github.com/messense/tantivy-jieba).
+//! - **Persisted tokenizer definitions**: a versioned [`TokenizerConfig`] is
+//!   recorded in [`BM25Config::tokenizer`] and rebuilt on load through a
+//!   [`TokenizerRegistry`], which refuses mismatched versions.
 //! - **Boolean query language** with `AND`, `OR`, `NOT`, and parentheses,
 //!   exposed through [`BM25Index::search_advanced`].
+//! - **Phrase and proximity queries** (`"machine learning"`, `NEAR/n`) over
+//!   optional positional postings, enabled by [`BM25Config::positions`].
+//! - **Prefix, wildcard and fuzzy terms** (`memo*`, `t?ol`, `agnt~1`) expanded
+//!   over a sorted term dictionary.
+//! - **BM25F field weighting**: per-field term frequencies, length
+//!   normalization and boosts, enabled by [`BM25Config::field_boosts`].
+//! - **Hit highlighting**: a [`Highlighter`] extracts the best-scoring
+//!   snippets of a stored text with the byte ranges of the matched tokens.
 //! - **Concurrent reads and writes** powered by [`dashmap`] + atomic counters,
 //!   so inserts, removes, and searches can run from multiple threads.
 //! - **Incremental persistence**: the inverted index is sharded into *buckets*
@@ -20,6 +31,9 @@
 //!   [`BM25Index::flush`].
 //! - **Bucket compaction** via [`BM25Index::compact_buckets`] to repack a
 //!   fragmented index into the minimum number of buckets.
+//! - **Sparse vectors**: [`SparseIndex`] indexes term → weight maps (e.g. from
+//!   a learned sparse encoder) in the same bucket layout and ranks documents
+//!   by dot product.
 //!
 //! ## Quick start
 //!
@@ -41,12 +55,16 @@
 
 mod bm25;
 mod error;
+mod highlight;
 mod query;
+mod sparse;
 mod tokenizer;
 
 pub use bm25::*;
 pub use error::*;
+pub use highlight::*;
 pub use query::*;
+pub use sparse::*;
 pub use tokenizer::*;
 
 #[cfg(any(test, feature = "tantivy-jieba"))]
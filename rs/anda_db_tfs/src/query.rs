@@ -1,6 +1,7 @@
 /// Represents different types of boolean queries that can be parsed from a query string.
-/// Supports Term, Or, And, and Not operations for building complex search expressions.
-/// Operator precedence: OR < AND < NOT.
+/// Supports Term, Phrase, Near, Prefix, Wildcard, Fuzzy, Or, And, and Not operations for
+/// building complex search expressions.
+/// Operator precedence: OR < AND < NOT < NEAR.
 ///
 /// # Grammar (informal)
 ///
@@ -9,11 +10,29 @@
 /// or_expr := and_expr ( " OR " and_expr )*
 /// and_expr := not_expr ( " AND " not_expr )*
 /// not_expr := "NOT " term | term
-/// term    := "(" or_expr ")" | word ( whitespace word )*
+/// term    := "(" or_expr ")" | unit ( whitespace unit )*
+/// unit    := operand ( " NEAR/" n " " operand )*
+/// operand := '"' words '"' | word | pattern
+/// pattern := word "*" | word-with-"*"-or-"?" | word "~" [ n ]
 /// ```
 ///
-/// Whitespace-separated words at the `term` level default to an implicit `OR`
-/// between them, matching the behaviour of [`BM25Index::search`].
+/// Whitespace-separated units at the `term` level default to an implicit `OR`
+/// between them, matching the behaviour of [`BM25Index::search`]. A quoted
+/// operand is a phrase, whose tokens must occur consecutively and in order;
+/// operands joined by `NEAR/n` must all occur with at most `n` other tokens
+/// between them, in any order. Operators inside quotes are plain words.
+///
+/// A word ending in `*` is a prefix, and a word with `*` (any characters) or
+/// `?` (one character) elsewhere is a wildcard pattern; both match the
+/// indexed terms as they are stored, i.e. after lowercasing and stemming.
+/// Trailing `?`s are punctuation, so `rust?` is the term `rust`. A
+/// word followed by `~n` matches the indexed terms within `n` edits of its
+/// tokens (`~` alone means `~1`, and `n` is capped at
+/// [`MAX_FUZZY_DISTANCE`]). Patterns are not recognized inside phrases and
+/// `NEAR/n` operands.
+/// Phrase and proximity matching needs an index built with
+/// [`BM25Config::positions`]; other indexes only require all of their
+/// tokens to occur.
 ///
 /// The parser is intentionally lenient: unbalanced parentheses are treated as
 /// part of the surrounding text so that user input never causes a parse error.
@@ -27,11 +46,29 @@
 /// ```
 ///
 /// [`BM25Index::search`]: crate::BM25Index::search
+/// [`BM25Config::positions`]: crate::BM25Config::positions
 #[derive(Debug, Clone, PartialEq)]
 pub enum QueryType {
     /// A simple term query that matches a single word or phrase
     Term(String),
 
+    /// A phrase query whose tokens must occur consecutively and in order
+    Phrase(String),
+
+    /// A proximity query: every operand (a word or phrase) must occur, with
+    /// at most the given number of other tokens between them
+    Near(Vec<String>, usize),
+
+    /// A prefix query matching every indexed term that starts with the text
+    Prefix(String),
+
+    /// A wildcard query where `*` matches any characters and `?` one
+    Wildcard(String),
+
+    /// A fuzzy query matching the indexed terms within the given Levenshtein
+    /// distance of the tokens of the text
+    Fuzzy(String, u8),
+
     /// A logical OR query that requires at least one subquery to match
     Or(Vec<Box<QueryType>>),
 
@@ -42,6 +79,9 @@ pub enum QueryType {
     Not(Box<QueryType>),
 }
 
+/// Upper bound of the edit distance of a [`QueryType::Fuzzy`] query.
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+
 const MAX_LOGICAL_QUERY_LEN: usize = 8 * 1024;
 const MAX_LOGICAL_QUERY_DEPTH: usize = 64;
 const MAX_LOGICAL_QUERY_NODES: usize = 1_024;
@@ -95,6 +135,14 @@ impl QueryType {
         may_materialize_not_complement(self, false)
     }
 
+    /// Returns the term, phrase, proximity and pattern queries that a
+    /// matching document may satisfy, skipping negated subqueries.
+    pub fn positive_terms(&self) -> Vec<&QueryType> {
+        let mut terms = Vec::new();
+        collect_positive_terms(self, &mut terms);
+        terms
+    }
+
     fn validate_complexity(&self) -> Result<(), String> {
         let mut stats = QueryStats::default();
         validate_ast(self, 0, &mut stats)
@@ -208,23 +256,114 @@ impl QueryType {
             }
         }
 
-        // Handle multiple terms (default to OR relationship)
-        let terms: Vec<&str> = query.split_whitespace().collect();
-        if terms.len() > 1 {
-            let subqueries: Vec<Box<QueryType>> = terms
-                .into_iter()
-                .map(|t| Box::new(QueryType::Term(t.to_lowercase())))
-                .collect();
-            return QueryType::Or(subqueries);
+        // Handle multiple units (default to OR relationship)
+        let mut units: Vec<Box<QueryType>> = Vec::new();
+        let items = Self::scan_units(query);
+        let mut i = 0;
+        while i < items.len() {
+            let UnitItem::Operand(operand, quoted) = items[i] else {
+                // A NEAR operator without a left operand is dropped.
+                i += 1;
+                continue;
+            };
+
+            let mut operands = vec![operand.to_lowercase()];
+            let mut distance = 0;
+            i += 1;
+            while let (Some(UnitItem::Near(n)), Some(UnitItem::Operand(operand, _))) =
+                (items.get(i), items.get(i + 1))
+            {
+                operands.push(operand.to_lowercase());
+                // Chained operators share the largest distance.
+                distance = distance.max(*n);
+                i += 2;
+            }
+
+            units.push(Box::new(if operands.len() > 1 {
+                QueryType::Near(operands, distance)
+            } else if quoted {
+                QueryType::Phrase(operands.remove(0))
+            } else {
+                Self::parse_word(operands.remove(0))
+            }));
         }
 
-        // Handle single term
-        if !query.is_empty() {
-            return QueryType::Term(query.to_lowercase());
+        match units.len() {
+            // Handle empty query
+            0 => QueryType::Or(vec![]),
+            // Handle single unit
+            1 => *units.remove(0),
+            _ => QueryType::Or(units),
         }
+    }
+
+    /// Parses an unquoted word into a term, or a prefix, wildcard or fuzzy
+    /// pattern. Trailing `?`s are dropped as punctuation, so natural
+    /// language questions keep matching their last word. A word made only of
+    /// wildcards stays a term, which matches nothing.
+    fn parse_word(mut word: String) -> Self {
+        const WILDCARDS: [char; 2] = ['*', '?'];
+
+        let len = word.trim_end_matches('?').len();
+        if len > 0 {
+            word.truncate(len);
+        }
+
+        if let Some((term, distance)) = word.rsplit_once('~') {
+            let distance = if distance.is_empty() {
+                Some(1)
+            } else {
+                distance.parse::<u8>().ok()
+            };
+            if let Some(distance) = distance
+                && !term.is_empty()
+                && !term.contains(WILDCARDS)
+            {
+                return QueryType::Fuzzy(term.to_string(), distance.min(MAX_FUZZY_DISTANCE));
+            }
+        }
+
+        if word.contains(WILDCARDS) && word.chars().any(|c| !WILDCARDS.contains(&c)) {
+            let prefix = word.trim_end_matches('*');
+            if !prefix.contains(WILDCARDS) {
+                return QueryType::Prefix(prefix.to_string());
+            }
+            return QueryType::Wildcard(word);
+        }
+
+        QueryType::Term(word)
+    }
+
+    /// Splits a term-level query into operands and `NEAR/n` operators.
+    ///
+    /// A quoted operand runs to the next `"`, or to the end of the input when
+    /// the quote is unbalanced; empty quotes are skipped. A `NEAR/` word
+    /// whose distance is not a number is an ordinary word.
+    fn scan_units(query: &str) -> Vec<UnitItem<'_>> {
+        let mut items = Vec::new();
+        let mut rest = query.trim_start();
+        while !rest.is_empty() {
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let (phrase, tail) = quoted.split_once('"').unwrap_or((quoted, ""));
+                let phrase = phrase.trim();
+                if !phrase.is_empty() {
+                    items.push(UnitItem::Operand(phrase, true));
+                }
+                rest = tail.trim_start();
+                continue;
+            }
 
-        // Handle empty query
-        QueryType::Or(vec![])
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '"')
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            match word.strip_prefix("NEAR/").map(str::parse::<usize>) {
+                Some(Ok(n)) => items.push(UnitItem::Near(n)),
+                _ => items.push(UnitItem::Operand(word, false)),
+            }
+            rest = rest[end..].trim_start();
+        }
+        items
     }
 
     /// Checks if parentheses in a string are balanced.
@@ -253,8 +392,10 @@ impl QueryType {
         count == 0
     }
 
-    /// Splits a string at the top level by a delimiter, ignoring delimiters inside parentheses.
-    /// Handles unbalanced parentheses by treating them as part of the text.
+    /// Splits a string at the top level by a delimiter, ignoring delimiters inside parentheses
+    /// and quotes.
+    /// Handles unbalanced parentheses by treating them as part of the text; an unbalanced
+    /// quote extends to the end of the string.
     ///
     /// This is a key function that enables proper parsing of nested expressions.
     ///
@@ -274,6 +415,7 @@ impl QueryType {
         let mut result = Vec::new();
         let mut start = 0;
         let mut paren_count: u32 = 0;
+        let mut quoted = false;
         let bytes = s.as_bytes();
         let delim_bytes = delimiter.as_bytes();
         let delim_len = delim_bytes.len();
@@ -281,6 +423,13 @@ impl QueryType {
 
         while i < bytes.len() {
             match bytes[i] {
+                b'"' => {
+                    quoted = !quoted;
+                    i += 1;
+                }
+                _ if quoted => {
+                    i += 1;
+                }
                 b'(' => {
                     paren_count += 1;
                     i += 1;
@@ -310,9 +459,34 @@ impl QueryType {
     }
 }
 
+/// An item of a term-level query, see [`QueryType::scan_units`].
+enum UnitItem<'a> {
+    /// A word, or the text of a phrase when quoted.
+    Operand(&'a str, bool),
+    /// A `NEAR/n` operator.
+    Near(usize),
+}
+
+fn collect_positive_terms<'a>(query: &'a QueryType, terms: &mut Vec<&'a QueryType>) {
+    match query {
+        QueryType::Or(subqueries) | QueryType::And(subqueries) => {
+            for query in subqueries {
+                collect_positive_terms(query, terms);
+            }
+        }
+        QueryType::Not(_) => {}
+        _ => terms.push(query),
+    }
+}
+
 fn may_materialize_not_complement(query: &QueryType, negated_not: bool) -> bool {
     match query {
-        QueryType::Term(_) => false,
+        QueryType::Term(_)
+        | QueryType::Phrase(_)
+        | QueryType::Near(..)
+        | QueryType::Prefix(_)
+        | QueryType::Wildcard(_)
+        | QueryType::Fuzzy(..) => false,
         QueryType::Not(subquery) => !negated_not || may_materialize_not_complement(subquery, false),
         QueryType::Or(subqueries) => subqueries
             .iter()
@@ -417,7 +591,20 @@ fn validate_ast(query: &QueryType, depth: usize, stats: &mut QueryStats) -> Resu
     }
 
     match query {
-        QueryType::Term(_) => Ok(()),
+        QueryType::Term(_)
+        | QueryType::Phrase(_)
+        | QueryType::Prefix(_)
+        | QueryType::Wildcard(_)
+        | QueryType::Fuzzy(..) => Ok(()),
+        QueryType::Near(operands, _) => {
+            stats.branches = stats.branches.saturating_add(operands.len());
+            if stats.branches > MAX_LOGICAL_QUERY_BRANCHES {
+                return Err(format!(
+                    "logical query AST branch count exceeds maximum {MAX_LOGICAL_QUERY_BRANCHES}"
+                ));
+            }
+            Ok(())
+        }
         QueryType::Not(query) => validate_ast(query, depth + 1, stats),
         QueryType::Or(queries) | QueryType::And(queries) => {
             stats.branches = stats.branches.saturating_add(queries.len());
@@ -590,6 +777,135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_positive_terms() {
+        let query = QueryType::parse("(rust AND NOT java) OR \"vector db\" NEAR/2 agent mem*");
+        assert_eq!(
+            query.positive_terms(),
+            vec![
+                &QueryType::Term("rust".to_string()),
+                &QueryType::Near(vec!["vector db".to_string(), "agent".to_string()], 2),
+                &QueryType::Prefix("mem".to_string()),
+            ]
+        );
+        assert!(QueryType::parse("NOT java").positive_terms().is_empty());
+    }
+
+    #[test]
+    fn test_pattern_query() {
+        let parse = QueryType::parse;
+        assert_eq!(parse("Mem*"), QueryType::Prefix("mem".to_string()));
+        assert_eq!(parse("mem**"), QueryType::Prefix("mem".to_string()));
+        assert_eq!(parse("m?m*ry"), QueryType::Wildcard("m?m*ry".to_string()));
+        assert_eq!(parse("*ory"), QueryType::Wildcard("*ory".to_string()));
+        assert_eq!(parse("memroy~"), QueryType::Fuzzy("memroy".to_string(), 1));
+        assert_eq!(parse("memroy~2"), QueryType::Fuzzy("memroy".to_string(), 2));
+        // Distances are capped; malformed suffixes and bare wildcards stay
+        // terms.
+        assert_eq!(parse("memroy~9"), QueryType::Fuzzy("memroy".to_string(), 2));
+        assert_eq!(parse("memroy~x"), QueryType::Term("memroy~x".to_string()));
+        assert_eq!(parse("~1"), QueryType::Term("~1".to_string()));
+        assert_eq!(parse("*"), QueryType::Term("*".to_string()));
+        assert_eq!(parse("m*m~1"), QueryType::Wildcard("m*m~1".to_string()));
+
+        // A trailing `?` is punctuation, not a one-character wildcard.
+        assert_eq!(parse("rust?"), QueryType::Term("rust".to_string()));
+        assert_eq!(parse("t?ol??"), QueryType::Wildcard("t?ol".to_string()));
+        assert_eq!(parse("?"), QueryType::Term("?".to_string()));
+        assert_eq!(
+            parse("what is rust?"),
+            QueryType::Or(vec![
+                Box::new(QueryType::Term("what".to_string())),
+                Box::new(QueryType::Term("is".to_string())),
+                Box::new(QueryType::Term("rust".to_string())),
+            ])
+        );
+
+        // Patterns combine with operators but not inside phrases or NEAR.
+        assert_eq!(
+            parse("agent* AND NOT java~1"),
+            QueryType::And(vec![
+                Box::new(QueryType::Prefix("agent".to_string())),
+                Box::new(QueryType::Not(Box::new(QueryType::Fuzzy(
+                    "java".to_string(),
+                    1
+                ))))
+            ])
+        );
+        assert_eq!(parse("\"mem*\""), QueryType::Phrase("mem*".to_string()));
+        assert_eq!(
+            parse("mem* NEAR/1 agent"),
+            QueryType::Near(vec!["mem*".to_string(), "agent".to_string()], 1)
+        );
+    }
+
+    /// Tests parsing phrase and proximity queries
+    #[test]
+    fn test_phrase_and_near_query() {
+        assert_eq!(
+            QueryType::parse("\"Machine Learning\""),
+            QueryType::Phrase("machine learning".to_string())
+        );
+        assert_eq!(
+            QueryType::parse("\"machine learning\" rust"),
+            QueryType::Or(vec![
+                Box::new(QueryType::Phrase("machine learning".to_string())),
+                Box::new(QueryType::Term("rust".to_string()))
+            ])
+        );
+        assert_eq!(
+            QueryType::parse("agent NEAR/3 memory"),
+            QueryType::Near(vec!["agent".to_string(), "memory".to_string()], 3)
+        );
+        // NEAR binds tighter than the implicit OR, and chains share the
+        // largest distance.
+        assert_eq!(
+            QueryType::parse("rust \"vector db\" NEAR/2 agent NEAR/5 memory"),
+            QueryType::Or(vec![
+                Box::new(QueryType::Term("rust".to_string())),
+                Box::new(QueryType::Near(
+                    vec![
+                        "vector db".to_string(),
+                        "agent".to_string(),
+                        "memory".to_string()
+                    ],
+                    5
+                ))
+            ])
+        );
+
+        // Operators inside quotes are plain words.
+        assert_eq!(
+            QueryType::parse("\"cats AND dogs\" AND NOT \"rain OR shine\""),
+            QueryType::And(vec![
+                Box::new(QueryType::Phrase("cats and dogs".to_string())),
+                Box::new(QueryType::Not(Box::new(QueryType::Phrase(
+                    "rain or shine".to_string()
+                ))))
+            ])
+        );
+
+        // Lenient forms: an unbalanced quote runs to the end, a dangling or
+        // malformed NEAR degrades, and empty quotes are skipped.
+        assert_eq!(
+            QueryType::parse("\"open phrase"),
+            QueryType::Phrase("open phrase".to_string())
+        );
+        assert_eq!(
+            QueryType::parse("NEAR/2 alpha NEAR/2"),
+            QueryType::Term("alpha".to_string())
+        );
+        assert_eq!(
+            QueryType::parse("alpha NEAR/x beta"),
+            QueryType::Or(vec![
+                Box::new(QueryType::Term("alpha".to_string())),
+                Box::new(QueryType::Term("near/x".to_string())),
+                Box::new(QueryType::Term("beta".to_string()))
+            ])
+        );
+        assert_eq!(QueryType::parse("\"\""), QueryType::Or(vec![]));
+    }
+
     #[test]
     fn try_parse_rejects_excessive_parenthesis_depth() {
         let query = format!("{}hello{}", "(".repeat(MAX_LOGICAL_QUERY_DEPTH + 1), ")");
@@ -0,0 +1,1064 @@
+//! # Sparse vector index implementation
+//!
+//! This module contains [`SparseIndex`], an inverted index over sparse
+//! vectors (term → weight maps, such as the output of a learned sparse
+//! encoder) that ranks documents by their dot product with a sparse query.
+//! It shares the bucket-sharded storage layout and the manifest commit
+//! protocol of [`BM25Index`](crate::BM25Index).
+
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use rustc_hash::{FxBuildHasher, FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    io::{Read, Write},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use crate::bm25::{Bucket, BucketSnapshot, cbor_serialized_size, top_k_results};
+use crate::{BoxError, BucketObject, FlushOutcome, SparseError};
+
+/// Concurrent, bucket-sharded inverted index scoring sparse vectors by dot
+/// product.
+///
+/// Every term of an inserted vector gets a posting list of
+/// `(document_id, weight)` entries; a query vector `q` scores a document `d`
+/// as `Σ_{t ∈ q ∩ d} q[t] · d[t]`. Only the postings of the query's terms are
+/// visited, so the cost of a search is proportional to the length of those
+/// posting lists rather than to the number of documents.
+///
+/// Persistence follows the same scheme as [`BM25Index`](crate::BM25Index):
+/// postings are packed into buckets bounded by
+/// [`SparseConfig::bucket_overload_size`], only dirty buckets are re-written
+/// on [`flush`](Self::flush), and the metadata carrying the bucket manifest is
+/// the single atomic commit point.
+///
+/// # Concurrency contract
+///
+/// Concurrent `insert`/`remove`/`search` calls are safe. Coordinating
+/// mutations against [`flush`](Self::flush)/[`flush_with`](Self::flush_with),
+/// and flushes against each other, is the **caller's** responsibility, exactly
+/// as for [`BM25Index`](crate::BM25Index).
+pub struct SparseIndex {
+    /// Index name
+    name: String,
+
+    /// Index configuration
+    config: SparseConfig,
+
+    /// Maps document IDs to the number of terms they were indexed with
+    doc_terms: DashMap<u64, usize>,
+
+    /// Buckets store information about where posting entries are stored and their current state
+    buckets: DashMap<u32, Bucket>,
+
+    /// Inverted index mapping terms to (bucket id, document_id -> weight)
+    postings: DashMap<String, SparsePostingValue>,
+
+    /// Index metadata.
+    metadata: RwLock<SparseMetadata>,
+
+    /// Maximum bucket ID currently in use
+    max_bucket_id: AtomicU32,
+
+    /// Maximum document ID currently in use
+    max_document_id: AtomicU64,
+
+    /// Number of search operations performed.
+    search_count: AtomicU64,
+
+    /// Last saved version of the index
+    last_saved_version: AtomicU64,
+}
+
+/// Sparse index configuration.
+///
+/// * `bucket_overload_size` — the soft upper bound, in bytes of the serialized
+///   CBOR payload, of a single bucket; see
+///   [`BM25Config::bucket_overload_size`](crate::BM25Config::bucket_overload_size).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SparseConfig {
+    /// Maximum size of a bucket before creating a new one
+    pub bucket_overload_size: usize,
+}
+
+impl Default for SparseConfig {
+    /// Returns a default configuration with a 512 KiB bucket size limit.
+    fn default() -> Self {
+        SparseConfig {
+            bucket_overload_size: 1024 * 512,
+        }
+    }
+}
+
+/// Type alias for posting values: (bucket id, document_id -> weight)
+/// - bucket_id: The bucket where this posting is stored
+/// - FxHashMap<document_id, weight>: Documents containing the term and their weights
+pub type SparsePostingValue = (u32, FxHashMap<u64, f32>);
+
+/// Index metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SparseMetadata {
+    /// Index name.
+    pub name: String,
+
+    /// Index configuration.
+    pub config: SparseConfig,
+
+    /// Index statistics.
+    pub stats: SparseStats,
+
+    /// Bucket manifest: `bucket_id -> generation` of the durable object that
+    /// currently holds the bucket's content.
+    #[serde(default)]
+    pub buckets: BTreeMap<u32, u64>,
+}
+
+/// Index statistics.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SparseStats {
+    /// Last insertion timestamp (unix ms).
+    pub last_inserted: u64,
+
+    /// Last deletion timestamp (unix ms).
+    pub last_deleted: u64,
+
+    /// Last saved timestamp (unix ms).
+    pub last_saved: u64,
+
+    /// Updated version for the index. It will be incremented when the index is updated.
+    pub version: u64,
+
+    /// Number of elements in the index.
+    pub num_elements: u64,
+
+    /// Number of search operations performed.
+    pub search_count: u64,
+
+    /// Number of insert operations performed.
+    pub insert_count: u64,
+
+    /// Number of delete operations performed.
+    pub delete_count: u64,
+
+    /// Maximum bucket ID currently in use
+    pub max_bucket_id: u32,
+
+    /// Maximum document ID currently in use
+    pub max_document_id: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SparseIndexOwned {
+    metadata: SparseMetadata,
+}
+
+#[derive(Clone, Serialize)]
+struct SparseIndexRef<'a> {
+    metadata: &'a SparseMetadata,
+}
+
+// Helper structure for serialization and deserialization of bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BucketOwned {
+    #[serde(rename = "p")]
+    postings: FxHashMap<String, SparsePostingValue>,
+
+    #[serde(rename = "d")]
+    doc_terms: FxHashMap<u64, usize>,
+}
+
+// Reference structure for serializing bucket
+#[derive(Serialize)]
+struct BucketRef<'a> {
+    #[serde(rename = "p")]
+    postings: &'a FxHashMap<&'a String, dashmap::mapref::one::Ref<'a, String, SparsePostingValue>>,
+
+    #[serde(rename = "d")]
+    doc_terms: &'a FxHashMap<u64, usize>,
+}
+
+/// Estimated serialized size of a brand-new posting holding one entry.
+fn new_posting_size(term: &str, bucket_id: u32, id: u64, weight: f32) -> usize {
+    cbor_serialized_size(&(term, bucket_id)) + entry_size(id, weight) + 2
+}
+
+/// Estimated serialized size of one posting entry.
+fn entry_size(id: u64, weight: f32) -> usize {
+    cbor_serialized_size(&(id, weight))
+}
+
+impl SparseIndex {
+    /// Creates a new empty sparse index with an optional config.
+    pub fn new(name: String, config: Option<SparseConfig>) -> Self {
+        let config = config.unwrap_or_default();
+        let stats = SparseStats {
+            version: 1,
+            ..Default::default()
+        };
+        SparseIndex {
+            name: name.clone(),
+            config: config.clone(),
+            doc_terms: DashMap::new(),
+            postings: DashMap::new(),
+            buckets: DashMap::from_iter([(0, Bucket::default())]),
+            metadata: RwLock::new(SparseMetadata {
+                name,
+                config,
+                stats,
+                buckets: BTreeMap::new(),
+            }),
+            max_bucket_id: AtomicU32::new(0),
+            max_document_id: AtomicU64::new(0),
+            search_count: AtomicU64::new(0),
+            last_saved_version: AtomicU64::new(0),
+        }
+    }
+
+    /// Loads a complete index (metadata and all buckets) in one call.
+    ///
+    /// `f` is invoked once per [`BucketObject`] referenced by the manifest;
+    /// return `Ok(Some(bytes))` for present buckets or `Ok(None)` to skip.
+    pub async fn load_all<R: Read, F>(metadata: R, f: F) -> Result<Self, SparseError>
+    where
+        F: AsyncFnMut(BucketObject) -> Result<Option<Vec<u8>>, BoxError>,
+    {
+        let mut index = Self::load_metadata(metadata)?;
+        index.load_buckets(f).await?;
+        Ok(index)
+    }
+
+    /// Loads only the index metadata, returning an empty shell.
+    ///
+    /// Call [`load_buckets`](Self::load_buckets) afterwards to populate the
+    /// inverted index.
+    pub fn load_metadata<R: Read>(r: R) -> Result<Self, SparseError> {
+        let index: SparseIndexOwned =
+            cbor2::from_reader(r).map_err(|err| SparseError::Serialization {
+                name: "unknown".to_string(),
+                source: err.into(),
+            })?;
+        let max_bucket_id = AtomicU32::new(index.metadata.stats.max_bucket_id);
+        let max_document_id = AtomicU64::new(index.metadata.stats.max_document_id);
+        let search_count = AtomicU64::new(index.metadata.stats.search_count);
+        let last_saved_version = AtomicU64::new(index.metadata.stats.version);
+
+        Ok(SparseIndex {
+            name: index.metadata.name.clone(),
+            config: index.metadata.config.clone(),
+            doc_terms: DashMap::new(),
+            postings: DashMap::new(),
+            buckets: DashMap::from_iter([(0, Bucket::default())]),
+            metadata: RwLock::new(index.metadata),
+            max_bucket_id,
+            max_document_id,
+            search_count,
+            last_saved_version,
+        })
+    }
+
+    /// Populates the inverted index from the buckets referenced by the
+    /// metadata's manifest.
+    ///
+    /// Returning `Ok(None)` from `f` leaves that bucket empty, which allows
+    /// read-only partial loads; a partially loaded index must not be flushed.
+    /// Posting entries that reference a document with no term count in any
+    /// loaded bucket are pruned and the affected buckets are marked dirty, so
+    /// the next [`flush`](Self::flush) persists the cleanup.
+    pub async fn load_buckets<F>(&mut self, mut f: F) -> Result<(), SparseError>
+    where
+        F: AsyncFnMut(BucketObject) -> Result<Option<Vec<u8>>, BoxError>,
+    {
+        let mut doc_term_counts: FxHashMap<u64, usize> = FxHashMap::default();
+        let manifest = { self.metadata.read().buckets.clone() };
+        for (bucket_id, generation) in manifest {
+            let object = BucketObject {
+                bucket_id,
+                generation,
+            };
+            let data = f(object).await.map_err(|err| SparseError::Generic {
+                name: self.name.clone(),
+                source: err,
+            })?;
+            let Some(data) = data else {
+                // Keep an empty placeholder so the next flush carries the
+                // manifest entry forward.
+                self.buckets.entry(bucket_id).or_default();
+                continue;
+            };
+
+            let bucket: BucketOwned =
+                cbor2::from_reader(&data[..]).map_err(|err| SparseError::Serialization {
+                    name: self.name.clone(),
+                    source: err.into(),
+                })?;
+            let mut b = Bucket {
+                size: data.len(),
+                ..Default::default()
+            };
+            doc_term_counts.extend(bucket.doc_terms);
+            for (term, mut posting) in bucket.postings {
+                posting.0 = bucket_id;
+                self.postings.insert(term.clone(), posting);
+                b.tokens.push(term);
+            }
+            self.buckets.insert(bucket_id, b);
+        }
+
+        // Buckets are self-contained, so an entry whose document has no term
+        // count anywhere is a stale leftover; drop it.
+        let mut doc_ids_by_bucket: FxHashMap<u32, FxHashSet<u64>> = FxHashMap::default();
+        let mut empty_terms: Vec<(u32, String)> = Vec::new();
+        let mut pruned_buckets: FxHashSet<u32> = FxHashSet::default();
+        for mut posting in self.postings.iter_mut() {
+            let bucket_id = posting.0;
+            let doc_ids = doc_ids_by_bucket.entry(bucket_id).or_default();
+            let before = posting.1.len();
+            posting.1.retain(|doc_id, _| {
+                let live = doc_term_counts.contains_key(doc_id);
+                if live {
+                    doc_ids.insert(*doc_id);
+                }
+                live
+            });
+            if posting.1.len() != before {
+                pruned_buckets.insert(bucket_id);
+                if posting.1.is_empty() {
+                    empty_terms.push((bucket_id, posting.key().clone()));
+                }
+            }
+        }
+
+        for (bucket_id, term) in empty_terms {
+            self.postings.remove(&term);
+            if let Some(mut bucket) = self.buckets.get_mut(&bucket_id) {
+                bucket.tokens.swap_remove_if(|k| k == &term);
+            }
+        }
+
+        let bucket_ids: Vec<u32> = self.buckets.iter().map(|b| *b.key()).collect();
+        for bucket_id in bucket_ids {
+            if let Some(mut bucket) = self.buckets.get_mut(&bucket_id) {
+                bucket.doc_ids = doc_ids_by_bucket.remove(&bucket_id).unwrap_or_default();
+                if pruned_buckets.contains(&bucket_id) {
+                    bucket.mark_dirty();
+                }
+            }
+        }
+
+        let live_docs: FxHashSet<u64> = self
+            .buckets
+            .iter()
+            .flat_map(|b| b.doc_ids.iter().copied().collect::<Vec<_>>())
+            .collect();
+        self.doc_terms.clear();
+        self.doc_terms.extend(
+            doc_term_counts
+                .into_iter()
+                .filter(|(doc_id, _)| live_docs.contains(doc_id)),
+        );
+
+        Ok(())
+    }
+
+    /// Returns the number of documents in the index
+    pub fn len(&self) -> usize {
+        self.doc_terms.len()
+    }
+
+    /// Returns whether the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.doc_terms.is_empty()
+    }
+
+    /// Returns the index name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the index metadata
+    pub fn metadata(&self) -> SparseMetadata {
+        let mut metadata = self.metadata.read().clone();
+        self.refresh_live_stats(&mut metadata.stats);
+        metadata
+    }
+
+    /// Gets current statistics about the index
+    pub fn stats(&self) -> SparseStats {
+        let mut stats = self.metadata.read().stats.clone();
+        self.refresh_live_stats(&mut stats);
+        stats
+    }
+
+    fn refresh_live_stats(&self, stats: &mut SparseStats) {
+        stats.search_count = self.search_count.load(Ordering::Relaxed);
+        stats.num_elements = self.doc_terms.len() as u64;
+        stats.max_bucket_id = self.max_bucket_id.load(Ordering::Relaxed);
+        stats.max_document_id = self.max_document_id.load(Ordering::Relaxed);
+    }
+
+    /// Inserts a document's sparse vector into the index.
+    ///
+    /// Terms with a zero or non-finite weight carry no signal for a dot
+    /// product and are skipped.
+    ///
+    /// # Errors
+    ///
+    /// * [`SparseError::EmptyVector`] if no term has a finite, non-zero weight.
+    /// * [`SparseError::AlreadyExists`] if `id` is already present.
+    pub fn insert(
+        &self,
+        id: u64,
+        vector: &BTreeMap<String, f32>,
+        now_ms: u64,
+    ) -> Result<(), SparseError> {
+        let terms: Vec<(&String, f32)> = vector
+            .iter()
+            .filter(|(_, weight)| weight.is_finite() && **weight != 0.0)
+            .map(|(term, weight)| (term, *weight))
+            .collect();
+        if terms.is_empty() {
+            return Err(SparseError::EmptyVector {
+                name: self.name.clone(),
+                id,
+            });
+        }
+
+        match self.doc_terms.entry(id) {
+            dashmap::Entry::Occupied(_) => {
+                return Err(SparseError::AlreadyExists {
+                    name: self.name.clone(),
+                    id,
+                });
+            }
+            dashmap::Entry::Vacant(v) => {
+                v.insert(terms.len());
+            }
+        }
+        let _ = self.max_document_id.fetch_max(id, Ordering::Relaxed);
+
+        // Phase 1: Update the postings collection
+        let bucket_id = self.max_bucket_id.load(Ordering::Acquire);
+        // buckets_to_update: FxHashMap<bucketid, Vec<(term, size_increase)>>
+        let mut buckets_to_update: FxHashMap<u32, Vec<(String, usize)>> = FxHashMap::default();
+        for (term, weight) in terms {
+            match self.postings.entry(term.clone()) {
+                dashmap::Entry::Occupied(mut entry) => {
+                    let posting = entry.get_mut();
+                    // A stale entry left by a remove() with a different
+                    // vector is overwritten in place, not counted again.
+                    let size_increase = match posting.1.insert(id, weight) {
+                        Some(_) => 0,
+                        None => entry_size(id, weight) + 2,
+                    };
+                    buckets_to_update
+                        .entry(posting.0)
+                        .or_default()
+                        .push((term.clone(), size_increase));
+                }
+                dashmap::Entry::Vacant(entry) => {
+                    let size_increase = new_posting_size(term, bucket_id, id, weight);
+                    let mut docs = FxHashMap::default();
+                    docs.insert(id, weight);
+                    entry.insert((bucket_id, docs));
+                    buckets_to_update
+                        .entry(bucket_id)
+                        .or_default()
+                        .push((term.clone(), size_increase));
+                }
+            }
+        }
+
+        // Phase 2: Update bucket states
+        let mut terms_to_migrate: Vec<(u32, String, usize)> = Vec::new();
+        for (bid, terms) in buckets_to_update {
+            let mut bucket = self.buckets.entry(bid).or_default();
+            bucket.mark_dirty();
+            let mut bucket_contains_doc = false;
+            for (term, size) in terms {
+                if bucket.tokens.contains(&term) {
+                    bucket.size += size;
+                    bucket_contains_doc = true;
+                } else if bucket.tokens.is_empty()
+                    || bucket.size + size < self.config.bucket_overload_size
+                {
+                    bucket.tokens.push(term);
+                    bucket.size += size;
+                    bucket_contains_doc = true;
+                } else {
+                    terms_to_migrate.push((bid, term, size));
+                }
+            }
+            if bucket_contains_doc {
+                bucket.doc_ids.insert(id);
+            }
+        }
+
+        // Phase 3: Move overflowing new terms to fresh buckets
+        if !terms_to_migrate.is_empty() {
+            let mut next_bucket_id = self.max_bucket_id.fetch_add(1, Ordering::Release) + 1;
+            for (old_bucket_id, term, size) in terms_to_migrate {
+                let fits = {
+                    let nb = self.buckets.entry(next_bucket_id).or_default();
+                    nb.tokens.is_empty() || nb.size + size < self.config.bucket_overload_size
+                };
+                if !fits {
+                    next_bucket_id = self.max_bucket_id.fetch_add(1, Ordering::Release) + 1;
+                }
+
+                if let Some(mut posting) = self.postings.get_mut(&term) {
+                    posting.0 = next_bucket_id;
+                }
+                if let Some(mut ob) = self.buckets.get_mut(&old_bucket_id)
+                    && ob.tokens.swap_remove_if(|k| &term == k).is_some()
+                {
+                    ob.size = ob.size.saturating_sub(size);
+                    ob.mark_dirty();
+                }
+
+                let mut nb = self.buckets.entry(next_bucket_id).or_default();
+                nb.mark_dirty();
+                nb.size += size;
+                nb.tokens.push(term);
+                nb.doc_ids.insert(id);
+            }
+        }
+
+        self.update_metadata(|m| {
+            m.stats.version += 1;
+            m.stats.last_inserted = now_ms;
+            m.stats.insert_count += 1;
+        });
+
+        Ok(())
+    }
+
+    /// Removes a document from the index.
+    ///
+    /// The caller must provide the *original vector* that was used on
+    /// [`insert`](Self::insert); its terms identify which posting lists
+    /// should drop the document. Entries left behind by a mismatching vector
+    /// are never scored (scoring filters by document membership) and are
+    /// pruned on the next load.
+    ///
+    /// Returns `true` if a document with the given id was found and removed.
+    pub fn remove(&self, id: u64, vector: &BTreeMap<String, f32>, now_ms: u64) -> bool {
+        let was_present = self.doc_terms.remove(&id).is_some();
+
+        // buckets_to_update: FxHashMap<bucketid, Vec<(term, size_decrease)>>
+        let mut buckets_to_update: FxHashMap<u32, Vec<(String, usize)>> = FxHashMap::default();
+        let mut maybe_empty_terms: Vec<String> = Vec::new();
+        for term in vector.keys() {
+            if let Some(mut posting) = self.postings.get_mut(term)
+                && let Some(weight) = posting.1.remove(&id)
+            {
+                let size_decrease = if posting.1.is_empty() {
+                    maybe_empty_terms.push(term.clone());
+                    new_posting_size(term, posting.0, id, weight)
+                } else {
+                    entry_size(id, weight) + 2
+                };
+                buckets_to_update
+                    .entry(posting.0)
+                    .or_default()
+                    .push((term.clone(), size_decrease));
+            }
+        }
+
+        // Drop empty postings atomically: a concurrent insert may have
+        // re-populated one, in which case it must survive.
+        let mut removed_postings: FxHashSet<String> =
+            FxHashSet::with_capacity_and_hasher(maybe_empty_terms.len(), FxBuildHasher);
+        for term in maybe_empty_terms {
+            if self
+                .postings
+                .remove_if(&term, |_, posting| posting.1.is_empty())
+                .is_some()
+            {
+                removed_postings.insert(term);
+            }
+        }
+
+        for (bucket_id, terms) in buckets_to_update {
+            if let Some(mut b) = self.buckets.get_mut(&bucket_id) {
+                b.mark_dirty();
+                for (term, size_decrease) in terms {
+                    b.size = b.size.saturating_sub(size_decrease);
+                    if removed_postings.contains(&term) {
+                        let unlist = match self.postings.get(&term) {
+                            Some(posting) => posting.0 != bucket_id,
+                            None => true,
+                        };
+                        if unlist {
+                            b.tokens.swap_remove_if(|k| &term == k);
+                        }
+                    }
+                }
+                b.doc_ids.remove(&id);
+            }
+        }
+
+        // Other buckets may still list this document; mark them dirty so the
+        // next flush drops the reference.
+        let stale_buckets: Vec<u32> = self
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.doc_ids.contains(&id))
+            .map(|bucket| *bucket.key())
+            .collect();
+        for bucket_id in stale_buckets {
+            if let Some(mut bucket) = self.buckets.get_mut(&bucket_id)
+                && bucket.doc_ids.remove(&id)
+            {
+                bucket.mark_dirty();
+            }
+        }
+
+        if was_present {
+            self.update_metadata(|m| {
+                m.stats.version += 1;
+                m.stats.last_deleted = now_ms;
+                m.stats.delete_count += 1;
+            });
+        }
+
+        was_present
+    }
+
+    /// Erases a set of document ids from the index **without their vectors**.
+    ///
+    /// Sweeps every posting list once, like
+    /// [`BM25Index::purge_ids`](crate::BM25Index::purge_ids), for repair paths
+    /// that lost the document bodies.
+    ///
+    /// Returns the number of ids that were actually present in the index.
+    pub fn purge_ids(&self, ids: &BTreeSet<u64>, now_ms: u64) -> usize {
+        if ids.is_empty() {
+            return 0;
+        }
+
+        let removed_docs = ids
+            .iter()
+            .filter(|id| self.doc_terms.remove(id).is_some())
+            .count();
+
+        let mut bucket_size_decrease: FxHashMap<u32, usize> = FxHashMap::default();
+        let mut emptied_terms: Vec<(u32, String)> = Vec::new();
+        for mut posting in self.postings.iter_mut() {
+            let bucket_id = posting.0;
+            let mut size_decrease = 0;
+            posting.1.retain(|doc_id, weight| {
+                if ids.contains(doc_id) {
+                    size_decrease += entry_size(*doc_id, *weight) + 2;
+                    false
+                } else {
+                    true
+                }
+            });
+            if size_decrease == 0 {
+                continue;
+            }
+            if posting.1.is_empty() {
+                emptied_terms.push((bucket_id, posting.key().clone()));
+            }
+            *bucket_size_decrease.entry(bucket_id).or_default() += size_decrease;
+        }
+
+        for (bucket_id, term) in emptied_terms {
+            if self
+                .postings
+                .remove_if(&term, |_, posting| posting.1.is_empty())
+                .is_some()
+                && let Some(mut bucket) = self.buckets.get_mut(&bucket_id)
+            {
+                bucket.tokens.swap_remove_if(|k| k == &term);
+            }
+        }
+
+        let mut purged_postings = !bucket_size_decrease.is_empty();
+        for (bucket_id, size_decrease) in bucket_size_decrease {
+            if let Some(mut bucket) = self.buckets.get_mut(&bucket_id) {
+                bucket.mark_dirty();
+                bucket.size = bucket.size.saturating_sub(size_decrease);
+            }
+        }
+
+        let stale_buckets: Vec<u32> = self
+            .buckets
+            .iter()
+            .filter(|bucket| ids.iter().any(|id| bucket.doc_ids.contains(id)))
+            .map(|bucket| *bucket.key())
+            .collect();
+        purged_postings |= !stale_buckets.is_empty();
+        for bucket_id in stale_buckets {
+            if let Some(mut bucket) = self.buckets.get_mut(&bucket_id) {
+                bucket.doc_ids.retain(|id| !ids.contains(id));
+                bucket.mark_dirty();
+            }
+        }
+
+        if removed_docs > 0 || purged_postings {
+            self.update_metadata(|m| {
+                m.stats.version += 1;
+                m.stats.last_deleted = now_ms;
+                m.stats.delete_count += removed_docs as u64;
+            });
+        }
+
+        removed_docs
+    }
+
+    /// Searches the index and returns the documents with the highest dot
+    /// product against `query`.
+    ///
+    /// Query terms with a zero or non-finite weight are ignored.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `(document_id, score)` pairs sorted by descending score.
+    pub fn search(&self, query: &BTreeMap<String, f32>, top_k: usize) -> Vec<(u64, f32)> {
+        if top_k == 0 {
+            return Vec::new();
+        }
+
+        let mut scores: FxHashMap<u64, f32> = FxHashMap::default();
+        for (term, query_weight) in query {
+            if !query_weight.is_finite() || *query_weight == 0.0 {
+                continue;
+            }
+            if let Some(posting) = self.postings.get(term) {
+                for (doc_id, weight) in posting.1.iter() {
+                    // Skip stale entries of removed or not-loaded documents.
+                    if self.doc_terms.contains_key(doc_id) {
+                        *scores.entry(*doc_id).or_default() += query_weight * weight;
+                    }
+                }
+            }
+        }
+        self.search_count.fetch_add(1, Ordering::Relaxed);
+
+        top_k_results(scores, top_k)
+    }
+
+    /// Persists metadata and every currently-dirty bucket.
+    ///
+    /// Convenience wrapper around [`flush_with`](Self::flush_with) that writes
+    /// the metadata blob to `metadata`.
+    pub async fn flush<W: Write, F, Fut>(
+        &self,
+        metadata: W,
+        now_ms: u64,
+        f: F,
+    ) -> Result<FlushOutcome, SparseError>
+    where
+        F: FnMut(BucketObject, Vec<u8>) -> Fut,
+        Fut: Future<Output = Result<(), BoxError>>,
+    {
+        self.flush_with(
+            now_ms,
+            move |data: Vec<u8>| {
+                let mut metadata = metadata;
+                async move {
+                    metadata.write_all(&data)?;
+                    Ok(())
+                }
+            },
+            f,
+        )
+        .await
+    }
+
+    /// Persists every dirty bucket to a new immutable object, then commits
+    /// the metadata whose manifest references them.
+    ///
+    /// Follows the manifest commit protocol of
+    /// [`BM25Index::flush_with`](crate::BM25Index::flush_with): bucket objects
+    /// are keyed by `(bucket_id, generation)`, the metadata write is the single
+    /// atomic commit point, and the objects the new manifest no longer
+    /// references are returned as [`FlushOutcome::obsolete`].
+    pub async fn flush_with<M, MFut, F, FFut>(
+        &self,
+        now_ms: u64,
+        metadata_f: M,
+        mut f: F,
+    ) -> Result<FlushOutcome, SparseError>
+    where
+        M: FnOnce(Vec<u8>) -> MFut,
+        MFut: Future<Output = Result<(), BoxError>>,
+        F: FnMut(BucketObject, Vec<u8>) -> FFut,
+        FFut: Future<Output = Result<(), BoxError>>,
+    {
+        let has_dirty = self.has_dirty_buckets();
+        if !has_dirty && !self.has_pending_metadata_flush() {
+            return Ok(FlushOutcome::default());
+        }
+        if has_dirty && !self.has_pending_metadata_flush() {
+            self.update_metadata(|m| m.stats.version += 1);
+        }
+
+        let mut dirty = Vec::new();
+        for (bucket_id, version) in self.collect_dirty_buckets() {
+            if let Some(buf) = self.serialize_bucket(bucket_id)? {
+                dirty.push(BucketSnapshot {
+                    bucket_id,
+                    version,
+                    buf,
+                });
+            }
+        }
+        dirty.sort_unstable_by_key(|snapshot| snapshot.bucket_id);
+
+        let mut meta = self.metadata();
+        meta.stats.last_saved = now_ms.max(meta.stats.last_saved);
+        let generation = meta.stats.version;
+
+        let committed = meta.buckets.clone();
+        let dirty_ids: FxHashSet<u32> = dirty.iter().map(|s| s.bucket_id).collect();
+        let mut manifest = BTreeMap::new();
+        for entry in self.buckets.iter() {
+            let id = *entry.key();
+            if dirty_ids.contains(&id) {
+                manifest.insert(id, generation);
+            } else if let Some(committed_generation) = committed.get(&id) {
+                manifest.insert(id, *committed_generation);
+            }
+        }
+        meta.buckets = manifest.clone();
+
+        let mut meta_buf = Vec::with_capacity(256);
+        cbor2::to_writer(&SparseIndexRef { metadata: &meta }, &mut meta_buf).map_err(|err| {
+            SparseError::Serialization {
+                name: self.name.clone(),
+                source: err.into(),
+            }
+        })?;
+
+        let obsolete: Vec<BucketObject> = committed
+            .iter()
+            .filter(|(id, generation)| manifest.get(id) != Some(generation))
+            .map(|(id, generation)| BucketObject {
+                bucket_id: *id,
+                generation: *generation,
+            })
+            .collect();
+
+        // Phase 1: write every dirty bucket to its new immutable object.
+        let saved_marks: Vec<(u32, u64)> = dirty
+            .iter()
+            .map(|snapshot| (snapshot.bucket_id, snapshot.version))
+            .collect();
+        for snapshot in dirty {
+            f(
+                BucketObject {
+                    bucket_id: snapshot.bucket_id,
+                    generation,
+                },
+                snapshot.buf,
+            )
+            .await
+            .map_err(|err| SparseError::Generic {
+                name: self.name.clone(),
+                source: err,
+            })?;
+        }
+
+        // Phase 2: the manifest commit — the single atomic point.
+        metadata_f(meta_buf)
+            .await
+            .map_err(|err| SparseError::Generic {
+                name: self.name.clone(),
+                source: err,
+            })?;
+
+        self.last_saved_version
+            .fetch_max(generation, Ordering::Release);
+        self.update_metadata(|m| {
+            m.stats.last_saved = meta.stats.last_saved.max(m.stats.last_saved);
+            m.buckets = manifest;
+        });
+        for (bucket_id, version) in saved_marks {
+            if let Some(mut b) = self.buckets.get_mut(&bucket_id) {
+                b.saved_version = b.saved_version.max(version);
+            }
+        }
+
+        Ok(FlushOutcome {
+            saved: true,
+            obsolete,
+        })
+    }
+
+    /// Returns whether there are dirty buckets pending persistence.
+    pub fn has_dirty_buckets(&self) -> bool {
+        self.buckets.iter().any(|b| b.is_dirty())
+    }
+
+    /// Returns whether metadata has a newer logical version than the last
+    /// serialized metadata snapshot.
+    pub fn has_pending_metadata_flush(&self) -> bool {
+        let current_version = { self.metadata.read().stats.version };
+        self.last_saved_version.load(Ordering::Acquire) < current_version
+    }
+
+    /// Gets the number of terms a document was indexed with
+    pub fn get_doc_terms(&self, id: u64) -> Option<usize> {
+        self.doc_terms.get(&id).map(|v| *v)
+    }
+
+    fn collect_dirty_buckets(&self) -> Vec<(u32, u64)> {
+        self.buckets
+            .iter()
+            .filter(|b| b.is_dirty())
+            .map(|b| (*b.key(), b.dirty_version))
+            .collect()
+    }
+
+    /// Serializes one dirty bucket, dropping every DashMap guard before
+    /// returning.
+    fn serialize_bucket(&self, bucket_id: u32) -> Result<Option<Vec<u8>>, SparseError> {
+        let bucket = match self.buckets.get(&bucket_id) {
+            Some(b) if b.is_dirty() => b,
+            _ => return Ok(None),
+        };
+
+        let mut referenced_doc_ids = FxHashSet::default();
+        let postings: FxHashMap<_, _> = bucket
+            .tokens
+            .iter()
+            .filter_map(|k| {
+                let posting = self.postings.get(k)?;
+                if posting.0 != bucket_id {
+                    return None;
+                }
+                referenced_doc_ids.extend(posting.1.keys().copied());
+                Some((k, posting))
+            })
+            .collect();
+
+        let doc_terms: FxHashMap<_, _> = referenced_doc_ids
+            .iter()
+            .filter_map(|id| self.doc_terms.get(id).map(|v| (*id, *v)))
+            .collect();
+
+        let mut buf = Vec::with_capacity(4096);
+        cbor2::to_writer(
+            &BucketRef {
+                postings: &postings,
+                doc_terms: &doc_terms,
+            },
+            &mut buf,
+        )
+        .map_err(|err| SparseError::Serialization {
+            name: self.name.clone(),
+            source: err.into(),
+        })?;
+        Ok(Some(buf))
+    }
+
+    fn update_metadata<F>(&self, f: F)
+    where
+        F: FnOnce(&mut SparseMetadata),
+    {
+        let mut metadata = self.metadata.write();
+        f(&mut metadata);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::sync::{Arc, Mutex};
+
+    fn vector(terms: &[(&str, f32)]) -> BTreeMap<String, f32> {
+        terms.iter().map(|(t, w)| (t.to_string(), *w)).collect()
+    }
+
+    #[test]
+    fn test_insert_search_and_remove() {
+        let index = SparseIndex::new("sparse".to_string(), None);
+        index
+            .insert(1, &vector(&[("apple", 1.0), ("pie", 0.5)]), 1)
+            .unwrap();
+        index
+            .insert(2, &vector(&[("apple", 0.2), ("tart", 2.0)]), 1)
+            .unwrap();
+        index.insert(3, &vector(&[("banana", 1.0)]), 1).unwrap();
+
+        let results = index.search(&vector(&[("apple", 2.0), ("tart", 1.0)]), 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, 2);
+        assert!((results[0].1 - 2.4).abs() < 1e-6);
+        assert_eq!(results[1].0, 1);
+        assert!((results[1].1 - 2.0).abs() < 1e-6);
+        assert_eq!(index.search(&vector(&[("apple", 1.0)]), 1), vec![(1, 1.0)]);
+        assert!(index.search(&vector(&[("missing", 1.0)]), 10).is_empty());
+
+        assert!(matches!(
+            index.insert(1, &vector(&[("apple", 1.0)]), 2),
+            Err(SparseError::AlreadyExists { .. })
+        ));
+        assert!(matches!(
+            index.insert(4, &vector(&[("apple", 0.0), ("nan", f32::NAN)]), 2),
+            Err(SparseError::EmptyVector { .. })
+        ));
+
+        assert!(index.remove(2, &vector(&[("apple", 0.2), ("tart", 2.0)]), 3));
+        assert!(!index.remove(2, &vector(&[("apple", 0.2), ("tart", 2.0)]), 3));
+        assert_eq!(
+            index.search(&vector(&[("apple", 2.0), ("tart", 1.0)]), 10),
+            vec![(1, 2.0)]
+        );
+        assert_eq!(index.len(), 2);
+
+        assert_eq!(index.purge_ids(&BTreeSet::from([1, 9]), 4), 1);
+        assert!(index.search(&vector(&[("apple", 1.0)]), 10).is_empty());
+        let stats = index.stats();
+        assert_eq!(stats.num_elements, 1);
+        assert_eq!(stats.delete_count, 2);
+    }
+
+    #[test]
+    fn test_flush_and_load_roundtrip() {
+        let index = SparseIndex::new(
+            "sparse".to_string(),
+            Some(SparseConfig {
+                bucket_overload_size: 64,
+            }),
+        );
+        for id in 0..50u64 {
+            let terms = vector(&[
+                (&format!("t{}", id % 7), 1.0 + id as f32),
+                (&format!("u{id}"), 0.5),
+            ]);
+            index.insert(id, &terms, 1).unwrap();
+        }
+        assert!(index.stats().max_bucket_id > 0);
+
+        let objects: Arc<Mutex<BTreeMap<BucketObject, Vec<u8>>>> = Arc::default();
+        let mut metadata = Vec::new();
+        let outcome = block_on(index.flush(&mut metadata, 2, |object, data| {
+            let objects = objects.clone();
+            async move {
+                objects.lock().unwrap().insert(object, data);
+                Ok(())
+            }
+        }))
+        .unwrap();
+        assert!(outcome.saved);
+        assert!(!index.has_dirty_buckets());
+        assert!(!index.has_pending_metadata_flush());
+
+        let loaded = block_on(SparseIndex::load_all(&metadata[..], async |object| {
+            Ok(objects.lock().unwrap().get(&object).cloned())
+        }))
+        .unwrap();
+        assert_eq!(loaded.len(), 50);
+        assert_eq!(loaded.metadata().stats.last_saved, 2);
+        let query = vector(&[("t3", 1.0), ("u10", 4.0)]);
+        assert_eq!(loaded.search(&query, 5), index.search(&query, 5));
+        assert!(!loaded.has_dirty_buckets());
+    }
+}
@@ -1,7 +1,10 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 pub use tantivy_tokenizer_api::*;
 
+use crate::error::BM25Error;
+
 /// A type-erased pipeline of a base [`Tokenizer`] followed by zero or more
 /// [`TokenFilter`]s.
 ///
@@ -113,6 +116,290 @@ pub fn default_tokenizer() -> TokenizerChain {
         .build()
 }
 
+/// A serializable definition of a tokenizer chain: a base tokenizer followed
+/// by a list of token filters.
+///
+/// Unlike a [`TokenizerChain`], a definition can be persisted with an index
+/// (see [`crate::BM25Config::tokenizer`]) and rebuilt when the index is
+/// loaded. `name` and `version` identify the definition in a
+/// [`TokenizerRegistry`]; bump the version whenever the tokens a definition
+/// produces change, so indexes built by the old version refuse to load
+/// instead of silently mismatching their queries.
+///
+/// # Example
+///
+/// ```
+/// use anda_db_tfs::{TokenFilterConfig, TokenizerConfig};
+///
+/// let config = TokenizerConfig {
+///     name: "french".to_string(),
+///     version: 1,
+///     filters: vec![
+///         TokenFilterConfig::RemoveLong(32),
+///         TokenFilterConfig::LowerCase,
+///         TokenFilterConfig::AsciiFolding,
+///         TokenFilterConfig::StopWords("french".to_string()),
+///         TokenFilterConfig::Stemmer("french".to_string()),
+///     ],
+///     ..Default::default()
+/// };
+/// # #[cfg(feature = "tantivy")]
+/// assert!(config.build().is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenizerConfig {
+    /// Name of the definition, e.g. [`TokenizerConfig::DEFAULT`].
+    pub name: String,
+
+    /// Version of the definition.
+    pub version: u32,
+
+    /// Base tokenizer splitting the text into tokens.
+    #[serde(default)]
+    pub tokenizer: BaseTokenizer,
+
+    /// Filters applied to the tokens, in order.
+    #[serde(default)]
+    pub filters: Vec<TokenFilterConfig>,
+}
+
+/// Base tokenizer of a [`TokenizerConfig`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BaseTokenizer {
+    /// Splits on every non-alphanumeric character.
+    #[default]
+    Simple,
+    /// Splits on whitespace only.
+    Whitespace,
+}
+
+/// Token filter of a [`TokenizerConfig`].
+///
+/// Languages are the names of the tantivy stemmer languages, case
+/// insensitive: `arabic`, `danish`, `dutch`, `english`, `finnish`,
+/// `french`, `german`, `greek`, `hungarian`, `italian`, `norwegian`,
+/// `portuguese`, `romanian`, `russian`, `spanish`, `swedish`, `tamil` and
+/// `turkish` (stop word lists exist for a subset of them).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenFilterConfig {
+    /// Re-tokenizes CJK tokens with Jieba (requires the `tantivy-jieba`
+    /// feature).
+    JiebaMerge,
+    /// Removes tokens of at least the given length, in bytes.
+    RemoveLong(usize),
+    /// Lowercases tokens.
+    LowerCase,
+    /// Folds characters to their ASCII equivalent, e.g. `é` to `e`.
+    AsciiFolding,
+    /// Stems tokens with the Snowball stemmer of a language.
+    Stemmer(String),
+    /// Removes the stop words of a language.
+    StopWords(String),
+}
+
+impl Default for TokenizerConfig {
+    /// Returns the definition of [`default_tokenizer`].
+    fn default() -> Self {
+        TokenizerConfig {
+            name: Self::DEFAULT.to_string(),
+            version: 1,
+            tokenizer: BaseTokenizer::Simple,
+            filters: vec![
+                TokenFilterConfig::RemoveLong(32),
+                TokenFilterConfig::LowerCase,
+                TokenFilterConfig::Stemmer("english".to_string()),
+            ],
+        }
+    }
+}
+
+impl TokenizerConfig {
+    /// Name of the built-in definition of [`default_tokenizer`].
+    pub const DEFAULT: &str = "default";
+
+    /// Name of the built-in definition of `jieba_tokenizer`.
+    pub const JIEBA: &str = "jieba";
+
+    /// Returns the definition of `jieba_tokenizer`.
+    pub fn jieba() -> Self {
+        TokenizerConfig {
+            name: Self::JIEBA.to_string(),
+            version: 1,
+            tokenizer: BaseTokenizer::Simple,
+            filters: vec![
+                TokenFilterConfig::JiebaMerge,
+                TokenFilterConfig::RemoveLong(32),
+                TokenFilterConfig::LowerCase,
+                TokenFilterConfig::Stemmer("english".to_string()),
+            ],
+        }
+    }
+
+    /// Builds the tokenizer chain of this definition.
+    ///
+    /// # Errors
+    ///
+    /// [`BM25Error::Tokenizer`] for an unknown language, a language without
+    /// stop words, or a filter whose feature is not enabled.
+    #[cfg(any(test, feature = "tantivy"))]
+    pub fn build(&self) -> Result<TokenizerChain, BM25Error> {
+        use tantivy::tokenizer::{
+            AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+            StopWordFilter, WhitespaceTokenizer,
+        };
+
+        let mut chain = match self.tokenizer {
+            BaseTokenizer::Simple => TokenizerChain::builder(SimpleTokenizer::default()).build(),
+            BaseTokenizer::Whitespace => {
+                TokenizerChain::builder(WhitespaceTokenizer::default()).build()
+            }
+        };
+        for filter in &self.filters {
+            let builder = TokenizerChain::builder(chain);
+            chain = match filter {
+                #[cfg(any(test, feature = "tantivy-jieba"))]
+                TokenFilterConfig::JiebaMerge => {
+                    builder.filter(crate::JiebaMergeFilter::new()).build()
+                }
+                #[cfg(not(any(test, feature = "tantivy-jieba")))]
+                TokenFilterConfig::JiebaMerge => {
+                    return Err(self.error("JiebaMerge requires the tantivy-jieba feature"));
+                }
+                TokenFilterConfig::RemoveLong(limit) => {
+                    builder.filter(RemoveLongFilter::limit(*limit)).build()
+                }
+                TokenFilterConfig::LowerCase => builder.filter(LowerCaser).build(),
+                TokenFilterConfig::AsciiFolding => builder.filter(AsciiFoldingFilter).build(),
+                TokenFilterConfig::Stemmer(language) => builder
+                    .filter(Stemmer::new(self.language(language)?))
+                    .build(),
+                TokenFilterConfig::StopWords(language) => {
+                    let filter =
+                        StopWordFilter::new(self.language(language)?).ok_or_else(|| {
+                            self.error(format!("no stop words for language {language:?}"))
+                        })?;
+                    builder.filter(filter).build()
+                }
+            };
+        }
+        Ok(chain)
+    }
+
+    #[cfg(any(test, feature = "tantivy"))]
+    fn language(&self, name: &str) -> Result<tantivy::tokenizer::Language, BM25Error> {
+        use tantivy::tokenizer::Language;
+
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "arabic" => Language::Arabic,
+            "danish" => Language::Danish,
+            "dutch" => Language::Dutch,
+            "english" => Language::English,
+            "finnish" => Language::Finnish,
+            "french" => Language::French,
+            "german" => Language::German,
+            "greek" => Language::Greek,
+            "hungarian" => Language::Hungarian,
+            "italian" => Language::Italian,
+            "norwegian" => Language::Norwegian,
+            "portuguese" => Language::Portuguese,
+            "romanian" => Language::Romanian,
+            "russian" => Language::Russian,
+            "spanish" => Language::Spanish,
+            "swedish" => Language::Swedish,
+            "tamil" => Language::Tamil,
+            "turkish" => Language::Turkish,
+            _ => return Err(self.error(format!("unknown language {name:?}"))),
+        })
+    }
+
+    fn error(&self, message: impl Into<String>) -> BM25Error {
+        BM25Error::Tokenizer {
+            name: self.name.clone(),
+            source: message.into().into(),
+        }
+    }
+}
+
+/// A registry of named [`TokenizerConfig`] definitions.
+///
+/// The registry is the authority on what a tokenizer name means in this
+/// process. [`TokenizerRegistry::resolve`] rebuilds a recorded definition
+/// and refuses one whose version differs from the registered definition of
+/// the same name, so an index is never read with a tokenizer other than the
+/// one that built it. Definitions under unregistered names are rebuilt as
+/// recorded. The default registry holds [`TokenizerConfig::default`] and
+/// [`TokenizerConfig::jieba`].
+#[derive(Debug, Clone)]
+pub struct TokenizerRegistry {
+    definitions: BTreeMap<String, TokenizerConfig>,
+}
+
+impl Default for TokenizerRegistry {
+    fn default() -> Self {
+        let mut definitions = BTreeMap::new();
+        for config in [TokenizerConfig::default(), TokenizerConfig::jieba()] {
+            definitions.insert(config.name.clone(), config);
+        }
+        TokenizerRegistry { definitions }
+    }
+}
+
+impl TokenizerRegistry {
+    /// Returns the registered definition of `name`.
+    pub fn get(&self, name: &str) -> Option<&TokenizerConfig> {
+        self.definitions.get(name)
+    }
+
+    /// Returns the names of the registered definitions.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.definitions.keys().map(String::as_str)
+    }
+
+    /// Registers a definition, replacing the definition of the same name.
+    ///
+    /// Replace a definition with a new version when its tokens change;
+    /// indexes recorded with the old version will then refuse to resolve.
+    ///
+    /// # Errors
+    ///
+    /// [`BM25Error::Tokenizer`] if the definition cannot be built.
+    #[cfg(any(test, feature = "tantivy"))]
+    pub fn register(&mut self, config: TokenizerConfig) -> Result<(), BM25Error> {
+        config.build()?;
+        self.definitions.insert(config.name.clone(), config);
+        Ok(())
+    }
+
+    /// Checks a recorded definition against the registered definition of
+    /// the same name.
+    ///
+    /// # Errors
+    ///
+    /// [`BM25Error::Tokenizer`] if the versions differ, or if the same
+    /// version is registered with different filters.
+    pub fn check(&self, config: &TokenizerConfig) -> Result<(), BM25Error> {
+        match self.definitions.get(&config.name) {
+            Some(registered) if registered.version != config.version => Err(config.error(format!(
+                "recorded version {} does not match registered version {}",
+                config.version, registered.version
+            ))),
+            Some(registered) if registered != config => Err(config.error(format!(
+                "recorded definition differs from registered version {}",
+                registered.version
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a recorded definition (see [`TokenizerRegistry::check`]) and
+    /// builds its tokenizer chain.
+    #[cfg(any(test, feature = "tantivy"))]
+    pub fn resolve(&self, config: &TokenizerConfig) -> Result<TokenizerChain, BM25Error> {
+        self.check(config)?;
+        config.build()
+    }
+}
+
 /// Tokenizes text and optionally filters tokens
 ///
 /// # Token length filter
@@ -158,6 +445,42 @@ pub fn collect_tokens<T: Tokenizer>(
     tokens
 }
 
+/// Tokenizes text and records where each token occurs
+///
+/// Applies the same token length filter as [`collect_tokens`]. Positions
+/// are the ones assigned by the tokenizer, so a dropped token leaves a gap
+/// instead of pulling the following tokens closer.
+///
+/// # Arguments
+///
+/// * `tokenizer` - Tokenizer to use for processing text
+/// * `text` - Text to tokenize
+///
+/// # Returns
+///
+/// A HashMap of tokens and their positions, in ascending order.
+/// The number of positions of a token is its count in [`collect_tokens`].
+pub fn collect_token_positions<T: Tokenizer>(
+    tokenizer: &mut T,
+    text: &str,
+) -> HashMap<String, Vec<u32>> {
+    let mut stream = tokenizer.token_stream(text);
+    let mut tokens: HashMap<String, Vec<u32>> = HashMap::new();
+    while let Some(token) = stream.next() {
+        // See "Token length filter" on `collect_tokens`.
+        if token.text.len() <= 1 {
+            continue;
+        }
+
+        let position = u32::try_from(token.position).unwrap_or(u32::MAX);
+        tokens
+            .entry(token.text.to_owned())
+            .or_default()
+            .push(position);
+    }
+    tokens
+}
+
 /// Performs a simple full-text search by finding matching tokens in a document
 ///
 /// # Arguments
@@ -238,4 +561,124 @@ mod tests {
         assert_eq!(tokens.get("quick"), Some(&1));
         assert_eq!(tokens.get("fox"), Some(&2));
     }
+
+    #[test]
+    fn test_collect_token_positions() {
+        let mut tokenizer = default_tokenizer();
+
+        let positions = collect_token_positions(&mut tokenizer, "the fox saw a fox, the fox ran");
+        assert_eq!(positions.get("the"), Some(&vec![0, 5]));
+        assert_eq!(positions.get("fox"), Some(&vec![1, 4, 6]));
+        // "a" is dropped by the length filter but keeps its position.
+        assert_eq!(positions.get("saw"), Some(&vec![2]));
+        assert!(!positions.contains_key("a"));
+
+        let counts = collect_tokens(&mut tokenizer, "the fox saw a fox, the fox ran", None);
+        for (token, count) in counts {
+            assert_eq!(positions[&token].len(), count);
+        }
+    }
+
+    #[test]
+    fn test_tokenizer_config_builds_builtin_chains() {
+        let text =
+            "The Quick Foxes are running 水果和蔬菜 extraordinarilylongwordthatexceedsthelimit";
+        let tokens = |mut tokenizer: TokenizerChain| {
+            let mut tokens = collect_token_positions(&mut tokenizer, text)
+                .into_iter()
+                .collect::<Vec<_>>();
+            tokens.sort();
+            tokens
+        };
+
+        let config = TokenizerConfig::default();
+        assert_eq!(config.name, TokenizerConfig::DEFAULT);
+        assert_eq!(tokens(config.build().unwrap()), tokens(default_tokenizer()));
+        assert_eq!(
+            tokens(TokenizerConfig::jieba().build().unwrap()),
+            tokens(crate::jieba_tokenizer())
+        );
+
+        // A declarative chain with stop words, round-tripped through CBOR.
+        let config = TokenizerConfig {
+            name: "english_stop".to_string(),
+            version: 1,
+            tokenizer: BaseTokenizer::Whitespace,
+            filters: vec![
+                TokenFilterConfig::LowerCase,
+                TokenFilterConfig::StopWords("English".to_string()),
+                TokenFilterConfig::Stemmer("english".to_string()),
+            ],
+        };
+        let mut data = Vec::new();
+        cbor2::to_writer(&config, &mut data).unwrap();
+        let config: TokenizerConfig = cbor2::from_reader(&data[..]).unwrap();
+        let mut tokenizer = config.build().unwrap();
+        let tokens = collect_tokens(&mut tokenizer, "The foxes are running", None);
+        assert_eq!(
+            tokens,
+            HashMap::from([("fox".to_string(), 1), ("run".to_string(), 1)])
+        );
+
+        let invalid = TokenizerConfig {
+            filters: vec![TokenFilterConfig::Stemmer("klingon".to_string())],
+            ..config.clone()
+        };
+        assert!(matches!(invalid.build(), Err(BM25Error::Tokenizer { .. })));
+    }
+
+    #[test]
+    fn test_tokenizer_registry() {
+        let mut registry = TokenizerRegistry::default();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec![TokenizerConfig::DEFAULT, TokenizerConfig::JIEBA]
+        );
+        assert!(registry.resolve(&TokenizerConfig::default()).is_ok());
+        assert!(registry.resolve(&TokenizerConfig::jieba()).is_ok());
+
+        // Unregistered definitions are rebuilt as recorded.
+        let custom = TokenizerConfig {
+            name: "folded".to_string(),
+            version: 1,
+            tokenizer: BaseTokenizer::Simple,
+            filters: vec![
+                TokenFilterConfig::LowerCase,
+                TokenFilterConfig::AsciiFolding,
+            ],
+        };
+        let mut tokenizer = registry.resolve(&custom).unwrap();
+        let tokens = collect_tokens(&mut tokenizer, "Café", None);
+        assert!(tokens.contains_key("cafe"));
+
+        // A new registered version refuses the old recorded one.
+        let upgraded = TokenizerConfig {
+            version: 2,
+            filters: vec![
+                TokenFilterConfig::LowerCase,
+                TokenFilterConfig::AsciiFolding,
+                TokenFilterConfig::Stemmer("french".to_string()),
+            ],
+            ..custom.clone()
+        };
+        registry.register(upgraded.clone()).unwrap();
+        assert_eq!(registry.get("folded"), Some(&upgraded));
+        let err = registry.check(&custom).unwrap_err().to_string();
+        assert!(registry.resolve(&custom).is_err());
+        assert!(err.contains("version 1 does not match registered version 2"));
+        assert!(registry.resolve(&upgraded).is_ok());
+
+        // So does a changed definition under the same version.
+        let changed = TokenizerConfig {
+            filters: vec![TokenFilterConfig::LowerCase],
+            ..upgraded
+        };
+        assert!(registry.check(&changed).is_err());
+
+        let invalid = TokenizerConfig {
+            filters: vec![TokenFilterConfig::StopWords("tamil".to_string())],
+            ..custom
+        };
+        assert!(registry.register(invalid).is_err());
+    }
 }